@@ -0,0 +1,213 @@
+//! Live feeds of document changes and topic messages, shared by the
+//! WebSocket API and GraphQL subscriptions
+//!
+//! Every item carries a resume token. A client that reconnects with the
+//! token of the last item it processed receives everything after that
+//! item, so items are delivered at least once across reconnects.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use lumadb_common::error::{Error, Result};
+use lumadb_common::types::{Offset, PartitionId};
+use lumadb_query::QueryEngine;
+use lumadb_storage::{ChangeKind, ChangeStream, ChangeSubscription};
+use lumadb_streaming::producer::IsolationLevel;
+use lumadb_streaming::{ConsumeRecord, StreamingEngine};
+
+/// Records read per partition when a topic feed polls
+const TOPIC_POLL_RECORDS: usize = 100;
+
+/// Wait between polls of an idle topic
+const TOPIC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A change to a document of a watched collection
+#[derive(Debug, Clone)]
+pub(crate) struct DocumentChange {
+    pub resume_token: String,
+    pub operation: ChangeKind,
+    pub collection: String,
+    pub id: String,
+    /// Document after the change, `None` for deletions
+    pub document: Option<serde_json::Value>,
+}
+
+/// Changes to the documents of one collection that match a filter
+pub(crate) struct DocumentFeed {
+    query: Arc<QueryEngine>,
+    changes: ChangeSubscription,
+    collection: String,
+    filter: Option<serde_json::Value>,
+}
+
+impl DocumentFeed {
+    /// Watch `collection` from `resume_token`, or from now on without one
+    pub fn new(
+        query: Arc<QueryEngine>,
+        changes: &Arc<ChangeStream>,
+        collection: String,
+        filter: Option<serde_json::Value>,
+        resume_token: Option<&str>,
+    ) -> Result<Self> {
+        let after = resume_token.map(str::parse).transpose()?;
+        Ok(Self {
+            query,
+            changes: changes.subscribe(after)?,
+            collection,
+            filter,
+        })
+    }
+
+    /// Wait for the next matching change
+    ///
+    /// A change matches if the document matched the filter before or after
+    /// it, so watchers also see documents leave the filtered set.
+    pub async fn next(&mut self) -> Result<DocumentChange> {
+        loop {
+            let event = self.changes.next().await?;
+            if event.collection != self.collection {
+                continue;
+            }
+            if let Some(filter) = &self.filter {
+                let matches = |doc: &Option<_>| {
+                    doc.as_ref()
+                        .is_some_and(|doc| self.query.matches_filter(doc, filter))
+                };
+                if !matches(&event.before) && !matches(&event.after) {
+                    continue;
+                }
+            }
+
+            return Ok(DocumentChange {
+                resume_token: event.position.to_string(),
+                operation: event.kind,
+                collection: event.collection.clone(),
+                id: event.id.clone(),
+                document: event.after.as_ref().map(serde_json::to_value).transpose()?,
+            });
+        }
+    }
+}
+
+/// A record of a watched topic
+#[derive(Debug, Clone)]
+pub(crate) struct TopicMessage {
+    pub resume_token: String,
+    pub record: ConsumeRecord,
+}
+
+/// Records of every partition of a topic, in offset order per partition
+pub(crate) struct TopicFeed {
+    streaming: Arc<StreamingEngine>,
+    topic: String,
+    isolation: IsolationLevel,
+    /// Next offset to read from each partition
+    read: Vec<(PartitionId, Offset)>,
+    /// Offset after the last record delivered from each partition
+    delivered: Vec<(PartitionId, Offset)>,
+    /// Records read but not delivered yet
+    pending: VecDeque<ConsumeRecord>,
+}
+
+impl TopicFeed {
+    /// Watch `topic` from `resume_token`, or from `offset` (`earliest`,
+    /// `latest` or a number) in partitions the token does not cover
+    pub fn new(
+        streaming: Arc<StreamingEngine>,
+        topic: String,
+        offset: Option<&str>,
+        resume_token: Option<&str>,
+        isolation: IsolationLevel,
+    ) -> Result<Self> {
+        let partitions = streaming
+            .partitions(&topic)
+            .ok_or_else(|| Error::Internal(format!("Topic {topic} not found")))?;
+        let resumed = resume_token
+            .map(parse_topic_token)
+            .transpose()?
+            .unwrap_or_default();
+
+        let positions = partitions
+            .iter()
+            .map(|partition| {
+                let resumed = resumed.iter().find(|(id, _)| *id == partition.id());
+                let offset = match (resumed, offset.unwrap_or("latest")) {
+                    (Some((_, offset)), _) => *offset,
+                    (None, "latest") => partition.log().log_end_offset(),
+                    (None, "earliest") => 0,
+                    (None, offset) => offset
+                        .parse()
+                        .map_err(|_| Error::Serialization(format!("invalid offset {offset}")))?,
+                };
+                Ok((partition.id(), offset))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            streaming,
+            topic,
+            isolation,
+            read: positions.clone(),
+            delivered: positions,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next record
+    pub async fn next(&mut self) -> Result<TopicMessage> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                if let Some((_, offset)) = self
+                    .delivered
+                    .iter_mut()
+                    .find(|(id, _)| *id == record.partition)
+                {
+                    *offset = record.offset + 1;
+                }
+                return Ok(TopicMessage {
+                    resume_token: topic_token(&self.delivered),
+                    record,
+                });
+            }
+
+            for (partition, offset) in &mut self.read {
+                let (records, next) = self.streaming.read_partition(
+                    &self.topic,
+                    *partition,
+                    *offset,
+                    TOPIC_POLL_RECORDS,
+                    self.isolation,
+                )?;
+                *offset = next;
+                self.pending.extend(records);
+            }
+            if self.pending.is_empty() {
+                tokio::time::sleep(TOPIC_POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Resume token of a topic feed: `partition:offset` pairs
+fn topic_token(positions: &[(PartitionId, Offset)]) -> String {
+    positions
+        .iter()
+        .map(|(partition, offset)| format!("{partition}:{offset}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_topic_token(token: &str) -> Result<Vec<(PartitionId, Offset)>> {
+    let invalid = || Error::Serialization(format!("invalid resume token {token}"));
+    token
+        .split(',')
+        .map(|position| {
+            let (partition, offset) = position.split_once(':').ok_or_else(invalid)?;
+            Ok((
+                partition.parse().map_err(|_| invalid())?,
+                offset.parse().map_err(|_| invalid())?,
+            ))
+        })
+        .collect()
+}
@@ -1,24 +1,35 @@
 //! GraphQL API implementation
+//!
+//! Subscriptions are served over WebSocket (`graphql-transport-ws` and the
+//! legacy `graphql-ws` protocol) by the WebSocket server.
 
 use std::sync::Arc;
 
-use async_graphql::{Context, EmptySubscription, Object, Schema, SimpleObject, InputObject};
+use async_graphql::{Context, Object, Schema, SimpleObject, InputObject, Subscription};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use actix_web::{web, HttpResponse};
+use futures::Stream;
 use tracing::info;
 
 use lumadb_common::config::GraphQLApiConfig;
 use lumadb_common::error::Result;
 use lumadb_common::types::TopicConfig;
 use lumadb_query::QueryEngine;
+use lumadb_storage::ChangeStream;
+use lumadb_streaming::producer::IsolationLevel;
 use lumadb_streaming::StreamingEngine;
-use lumadb_security::SecurityManager;
+use lumadb_security::{AuditCategory, AuditEvent, Credentials, SecurityManager, Session};
+
+use crate::feeds::{DocumentFeed, TopicFeed};
+
+/// Schema served by the GraphQL API
+pub type LumaSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 /// GraphQL server
 #[derive(Clone)]
 pub struct GraphQLServer {
     config: GraphQLApiConfig,
-    schema: Schema<QueryRoot, MutationRoot, EmptySubscription>,
+    schema: LumaSchema,
 }
 
 impl GraphQLServer {
@@ -28,11 +39,13 @@ impl GraphQLServer {
         query: Arc<QueryEngine>,
         streaming: Arc<StreamingEngine>,
         security: Arc<SecurityManager>,
+        changes: Arc<ChangeStream>,
     ) -> Result<Self> {
-        let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
             .data(query)
             .data(streaming)
             .data(security)
+            .data(changes)
             .finish();
 
         Ok(Self {
@@ -42,7 +55,7 @@ impl GraphQLServer {
     }
 
     /// Get the schema for embedding in Actix
-    pub fn schema(&self) -> Schema<QueryRoot, MutationRoot, EmptySubscription> {
+    pub fn schema(&self) -> LumaSchema {
         self.schema.clone()
     }
 
@@ -77,9 +90,11 @@ impl QueryRoot {
         }
     }
 
-    /// List all collections
+    /// List the collections the caller may describe
     async fn collections(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Collection>> {
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
+        let security = ctx.data::<Arc<SecurityManager>>()?;
+        let session = caller(ctx).await?;
         let collections = query_engine
             .list_collections()
             .await
@@ -87,6 +102,9 @@ impl QueryRoot {
 
         Ok(collections
             .into_iter()
+            .filter(|c| {
+                security.is_authorized(&session, &format!("collection:{}", c.name), "describe")
+            })
             .map(|c| Collection {
                 name: c.name,
                 count: c.count as i64,
@@ -95,9 +113,11 @@ impl QueryRoot {
             .collect())
     }
 
-    /// List all topics
+    /// List the topics the caller may describe
     async fn topics(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Topic>> {
         let streaming = ctx.data::<Arc<StreamingEngine>>()?;
+        let security = ctx.data::<Arc<SecurityManager>>()?;
+        let session = caller(ctx).await?;
         let topics = streaming
             .list_topics()
             .await
@@ -105,6 +125,7 @@ impl QueryRoot {
 
         Ok(topics
             .into_iter()
+            .filter(|t| security.is_authorized(&session, &format!("topic:{}", t.name), "describe"))
             .map(|t| Topic {
                 name: t.name,
                 partitions: t.partitions.len() as i32,
@@ -120,6 +141,17 @@ impl QueryRoot {
         query: String,
     ) -> async_graphql::Result<serde_json::Value> {
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
+        let security = ctx.data::<Arc<SecurityManager>>()?;
+        let session = caller(ctx).await?;
+        let accesses = query_engine
+            .accesses(&query)
+            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        for (resource, action) in &accesses {
+            security
+                .authorize(&session, resource, action)
+                .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        }
+        security.audit_statement(&session, &query, &accesses);
         let result = query_engine
             .execute(&query, &[])
             .await
@@ -141,6 +173,7 @@ impl QueryRoot {
         filter: Option<serde_json::Value>,
         limit: Option<i32>,
     ) -> async_graphql::Result<Vec<serde_json::Value>> {
+        authorize(ctx, &format!("collection:{}", collection), "read").await?;
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
         let docs = query_engine
             .find(&collection, filter.as_ref(), limit.map(|l| l as usize))
@@ -162,11 +195,13 @@ impl MutationRoot {
         ctx: &Context<'_>,
         name: String,
     ) -> async_graphql::Result<Collection> {
+        authorize(ctx, &format!("collection:{}", name), "create").await?;
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
-        let meta = query_engine
-            .create_collection(&name, None, None)
-            .await
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        let result = query_engine.create_collection(&name, None, None).await;
+        let event =
+            AuditEvent::new(AuditCategory::Ddl, "create").on(format!("collection:{}", name));
+        audit(ctx, event, &result).await?;
+        let meta = result.map_err(|e| async_graphql::Error::new(e.to_string()))?;
 
         Ok(Collection {
             name: meta.name,
@@ -182,6 +217,7 @@ impl MutationRoot {
         name: String,
         partitions: Option<i32>,
     ) -> async_graphql::Result<Topic> {
+        authorize(ctx, &format!("topic:{}", name), "create").await?;
         let streaming = ctx.data::<Arc<StreamingEngine>>()?;
 
         let mut config = TopicConfig::new(
@@ -192,10 +228,10 @@ impl MutationRoot {
         config.retention_ms = Some(7 * 24 * 60 * 60 * 1000); // 7 days
         config.segment_bytes = Some(1024 * 1024 * 1024);      // 1 GB
 
-        streaming
-            .create_topic(config)
-            .await
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        let result = streaming.create_topic(config).await;
+        let event = AuditEvent::new(AuditCategory::Ddl, "create").on(format!("topic:{}", name));
+        audit(ctx, event, &result).await?;
+        result.map_err(|e| async_graphql::Error::new(e.to_string()))?;
 
         Ok(Topic {
             name,
@@ -211,11 +247,13 @@ impl MutationRoot {
         collection: String,
         documents: Vec<serde_json::Value>,
     ) -> async_graphql::Result<InsertResult> {
+        authorize(ctx, &format!("collection:{}", collection), "write").await?;
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
-        let result = query_engine
-            .insert(&collection, &documents)
-            .await
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        let result = query_engine.insert(&collection, &documents).await;
+        let event =
+            AuditEvent::new(AuditCategory::Dml, "insert").on(format!("collection:{}", collection));
+        audit(ctx, event, &result).await?;
+        let result = result.map_err(|e| async_graphql::Error::new(e.to_string()))?;
 
         Ok(InsertResult {
             inserted_count: result.inserted_count as i64,
@@ -230,11 +268,13 @@ impl MutationRoot {
         collection: String,
         filter: serde_json::Value,
     ) -> async_graphql::Result<DeleteResult> {
+        authorize(ctx, &format!("collection:{}", collection), "write").await?;
         let query_engine = ctx.data::<Arc<QueryEngine>>()?;
-        let result = query_engine
-            .delete(&collection, &filter)
-            .await
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        let result = query_engine.delete(&collection, &filter).await;
+        let event =
+            AuditEvent::new(AuditCategory::Dml, "delete").on(format!("collection:{}", collection));
+        audit(ctx, event, &result).await?;
+        let result = result.map_err(|e| async_graphql::Error::new(e.to_string()))?;
 
         Ok(DeleteResult {
             deleted_count: result.deleted_count as i64,
@@ -242,6 +282,147 @@ impl MutationRoot {
     }
 }
 
+/// GraphQL Subscription Root
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Changes to the documents of a collection, optionally only those
+    /// matching a filter before or after the change
+    ///
+    /// Pass the `resumeToken` of the last change processed to continue
+    /// after it.
+    async fn document_changes(
+        &self,
+        ctx: &Context<'_>,
+        collection: String,
+        filter: Option<serde_json::Value>,
+        resume_token: Option<String>,
+    ) -> async_graphql::Result<impl Stream<Item = async_graphql::Result<DocumentChange>>> {
+        authorize(ctx, &format!("collection:{}", collection), "read").await?;
+        let query_engine = ctx.data::<Arc<QueryEngine>>()?.clone();
+        let changes = ctx.data::<Arc<ChangeStream>>()?;
+        let feed = DocumentFeed::new(
+            query_engine,
+            changes,
+            collection,
+            filter,
+            resume_token.as_deref(),
+        )
+        .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+
+        Ok(futures::stream::unfold(Some(feed), |feed| async move {
+            let mut feed = feed?;
+            match feed.next().await {
+                Ok(change) => Some((
+                    Ok(DocumentChange {
+                        resume_token: change.resume_token,
+                        operation: change.operation.as_str().to_string(),
+                        collection: change.collection,
+                        id: change.id,
+                        document: change.document,
+                    }),
+                    Some(feed),
+                )),
+                // The stream ends after reporting an error
+                Err(e) => Some((Err(async_graphql::Error::new(e.to_string())), None)),
+            }
+        }))
+    }
+
+    /// Records of a topic from an offset (`earliest`, `latest` or a number)
+    ///
+    /// Pass the `resumeToken` of the last record processed to continue
+    /// after it.
+    async fn topic_messages(
+        &self,
+        ctx: &Context<'_>,
+        topic: String,
+        offset: Option<String>,
+        resume_token: Option<String>,
+        read_committed: Option<bool>,
+    ) -> async_graphql::Result<impl Stream<Item = async_graphql::Result<TopicMessage>>> {
+        authorize(ctx, &format!("topic:{}", topic), "read").await?;
+        let streaming = ctx.data::<Arc<StreamingEngine>>()?.clone();
+        let isolation = if read_committed.unwrap_or(false) {
+            IsolationLevel::ReadCommitted
+        } else {
+            IsolationLevel::ReadUncommitted
+        };
+        let feed = TopicFeed::new(
+            streaming,
+            topic,
+            offset.as_deref(),
+            resume_token.as_deref(),
+            isolation,
+        )
+        .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+
+        Ok(futures::stream::unfold(Some(feed), |feed| async move {
+            let mut feed = feed?;
+            match feed.next().await {
+                Ok(message) => {
+                    let record = message.record;
+                    Some((
+                        Ok(TopicMessage {
+                            resume_token: message.resume_token,
+                            topic: record.topic,
+                            partition: record.partition,
+                            offset: record.offset,
+                            timestamp: record.timestamp,
+                            key: record.key,
+                            value: record.value,
+                            headers: serde_json::to_value(record.headers).unwrap_or_default(),
+                        }),
+                        Some(feed),
+                    ))
+                }
+                Err(e) => Some((Err(async_graphql::Error::new(e.to_string())), None)),
+            }
+        }))
+    }
+}
+
+/// Session of the caller
+///
+/// This is the session established when the request's connection was
+/// initialized, or an anonymous one.
+async fn caller(ctx: &Context<'_>) -> async_graphql::Result<Session> {
+    match ctx.data_opt::<Session>() {
+        Some(session) => Ok(session.clone()),
+        None => ctx
+            .data::<Arc<SecurityManager>>()?
+            .authenticate(&Credentials::Token(String::new()))
+            .await
+            .map_err(|e| async_graphql::Error::new(e.to_string())),
+    }
+}
+
+/// Check that the caller may perform `action` on `resource`
+async fn authorize(ctx: &Context<'_>, resource: &str, action: &str) -> async_graphql::Result<()> {
+    let security = ctx.data::<Arc<SecurityManager>>()?;
+    let session = caller(ctx).await?;
+    security
+        .authorize(&session, resource, action)
+        .map_err(|e| async_graphql::Error::new(e.to_string()))
+}
+
+/// Record `event`, taken by the caller with the given outcome, in the
+/// audit trail
+async fn audit<T>(
+    ctx: &Context<'_>,
+    event: AuditEvent,
+    outcome: &Result<T>,
+) -> async_graphql::Result<()> {
+    let session = caller(ctx).await?;
+    let event = match outcome {
+        Ok(_) => event.by(&session),
+        Err(e) => event.by(&session).failed(e.to_string()),
+    };
+    ctx.data::<Arc<SecurityManager>>()?.audit(event);
+    Ok(())
+}
+
 // ============================================================================
 // GraphQL Response Types
 // ============================================================================
@@ -276,3 +457,28 @@ struct InsertResult {
 struct DeleteResult {
     deleted_count: i64,
 }
+
+#[derive(SimpleObject)]
+struct DocumentChange {
+    /// Token to resume the subscription after this change
+    resume_token: String,
+    /// `insert`, `update` or `delete`
+    operation: String,
+    collection: String,
+    id: String,
+    /// Document after the change, null for deletions
+    document: Option<serde_json::Value>,
+}
+
+#[derive(SimpleObject)]
+struct TopicMessage {
+    /// Token to resume the subscription after this record
+    resume_token: String,
+    topic: String,
+    partition: i32,
+    offset: i64,
+    timestamp: i64,
+    key: Option<String>,
+    value: serde_json::Value,
+    headers: serde_json::Value,
+}
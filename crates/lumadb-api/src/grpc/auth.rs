@@ -0,0 +1,99 @@
+//! Authentication metadata and error mapping shared by the gRPC services
+
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+
+use lumadb_common::error::{AuthError, Error, QueryError, TransactionError};
+use lumadb_query::QueryEngine;
+use lumadb_security::{Credentials, SecurityManager, Session};
+
+/// Session of the caller named by the `authorization: Bearer <token>`
+/// entry of a request's metadata
+pub(super) async fn authenticate(
+    security: &SecurityManager,
+    metadata: &MetadataMap,
+) -> Result<Session, Status> {
+    let token = match metadata.get("authorization") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("authorization must be a bearer token"))?,
+        None if security.auth_enabled() => {
+            return Err(Status::unauthenticated("missing authorization metadata"))
+        }
+        None => "",
+    };
+
+    security
+        .authenticate(&Credentials::Token(token.to_string()))
+        .await
+        .map_err(status)
+}
+
+/// Check that `session` may perform `action` on `resource`
+pub(super) fn permit(
+    security: &SecurityManager,
+    session: &Session,
+    resource: &str,
+    action: &str,
+) -> Result<(), Status> {
+    security
+        .authorize(session, resource, action)
+        .map_err(status)
+}
+
+/// Check that `session` may run `text`, taking each action the statement
+/// takes on every resource it touches
+pub(super) fn permit_query(
+    security: &SecurityManager,
+    query: &QueryEngine,
+    session: &Session,
+    text: &str,
+) -> Result<(), Status> {
+    let accesses = query.accesses(text).map_err(status)?;
+    for (resource, action) in &accesses {
+        permit(security, session, resource, action)?;
+    }
+    security.audit_statement(session, text, &accesses);
+    Ok(())
+}
+
+/// Authenticate the caller and check that it may perform `action` on
+/// `resource`
+pub(super) async fn authorize(
+    security: &SecurityManager,
+    metadata: &MetadataMap,
+    resource: &str,
+    action: &str,
+) -> Result<Session, Status> {
+    let session = authenticate(security, metadata).await?;
+    permit(security, &session, resource, action)?;
+    Ok(session)
+}
+
+/// gRPC status for an engine error
+#[allow(clippy::needless_pass_by_value)] // by value to fit `map_err(status)`
+pub(super) fn status(e: Error) -> Status {
+    let message = e.to_string();
+    match e {
+        Error::Auth(AuthError::PermissionDenied(_)) => Status::permission_denied(message),
+        Error::Auth(_) => Status::unauthenticated(message),
+        Error::Query(QueryError::Timeout(_)) => Status::deadline_exceeded(message),
+        Error::Query(QueryError::Cancelled) => Status::cancelled(message),
+        Error::Query(_) | Error::Serialization(_) => Status::invalid_argument(message),
+        Error::Transaction(
+            TransactionError::Conflict(_) | TransactionError::SerializationFailure(_),
+        ) => Status::aborted(message),
+        Error::Storage(lumadb_common::error::StorageError::CollectionNotFound(_)) => {
+            Status::not_found(message)
+        }
+        _ => Status::internal(message),
+    }
+}
+
+/// Parse a JSON text field of a request, naming the field on failure
+pub(super) fn parse_json(field: &str, text: &str) -> Result<serde_json::Value, Status> {
+    serde_json::from_str(text)
+        .map_err(|e| Status::invalid_argument(format!("{field} is not valid JSON: {e}")))
+}
@@ -0,0 +1,74 @@
+//! Server-side deadline for unary calls
+//!
+//! `Server::timeout` covers every call, so a client stream is cut off once
+//! it has been open for the request timeout. Services are wrapped in
+//! [`UnaryDeadline`] instead, which leaves their streaming methods alone.
+//! Client deadlines (`grpc-timeout`) still apply to every call.
+
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tonic::body::BoxBody;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::NamedService;
+use tonic::Status;
+
+/// Fails the unary calls of a service with `DEADLINE_EXCEEDED` once they
+/// run longer than a timeout
+#[derive(Clone)]
+pub(super) struct UnaryDeadline<S> {
+    inner: S,
+    timeout: Duration,
+    /// Methods with a request or response stream, which have no deadline
+    streaming: &'static [&'static str],
+}
+
+impl<S> UnaryDeadline<S> {
+    pub(super) fn new(inner: S, timeout: Duration, streaming: &'static [&'static str]) -> Self {
+        Self {
+            inner,
+            timeout,
+            streaming,
+        }
+    }
+}
+
+impl<S> Service<http::Request<BoxBody>> for UnaryDeadline<S>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+        let streaming = self.streaming.contains(&method);
+        let call = self.inner.call(request);
+        if streaming {
+            return Box::pin(call);
+        }
+
+        let timeout = self.timeout;
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, call).await {
+                Ok(response) => response,
+                Err(_) => Ok(Status::deadline_exceeded(format!(
+                    "request exceeded the {}ms timeout",
+                    timeout.as_millis()
+                ))
+                .into_http()),
+            }
+        })
+    }
+}
+
+impl<S: NamedService> NamedService for UnaryDeadline<S> {
+    const NAME: &'static str = S::NAME;
+}
@@ -0,0 +1,142 @@
+//! Document service: insert, find, update and delete
+
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use lumadb_query::QueryEngine;
+use lumadb_security::{AuditCategory, AuditEvent, SecurityManager};
+
+use super::auth::{authorize, parse_json, status};
+use super::proto::document_service_server::DocumentService;
+use super::proto::{
+    DeleteRequest, DeleteResponse, FindRequest, FindResponse, InsertRequest, InsertResponse,
+    UpdateRequest, UpdateResponse,
+};
+
+/// Implementation of `lumadb.v1.DocumentService`
+pub struct DocumentApi {
+    query: Arc<QueryEngine>,
+    security: Arc<SecurityManager>,
+}
+
+impl DocumentApi {
+    pub fn new(query: Arc<QueryEngine>, security: Arc<SecurityManager>) -> Self {
+        Self { query, security }
+    }
+}
+
+/// A filter field, where empty text matches every document
+fn filter(text: &str) -> Result<serde_json::Value, Status> {
+    if text.is_empty() {
+        Ok(serde_json::json!({}))
+    } else {
+        parse_json("filter_json", text)
+    }
+}
+
+#[tonic::async_trait]
+impl DocumentService for DocumentApi {
+    async fn insert(
+        &self,
+        request: Request<InsertRequest>,
+    ) -> Result<Response<InsertResponse>, Status> {
+        let resource = format!("collection:{}", request.get_ref().collection);
+        let session = authorize(&self.security, request.metadata(), &resource, "write").await?;
+        let request = request.into_inner();
+
+        let documents = request
+            .documents_json
+            .iter()
+            .map(|doc| parse_json("documents_json", doc))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = self
+            .query
+            .insert(&request.collection, &documents)
+            .await
+            .map_err(status)?;
+        self.security.audit(
+            AuditEvent::new(AuditCategory::Dml, "insert")
+                .by(&session)
+                .on(resource)
+                .detail(format!("{} documents", result.inserted_count)),
+        );
+
+        Ok(Response::new(InsertResponse {
+            inserted_count: result.inserted_count,
+            ids: result.ids,
+        }))
+    }
+
+    async fn find(&self, request: Request<FindRequest>) -> Result<Response<FindResponse>, Status> {
+        let resource = format!("collection:{}", request.get_ref().collection);
+        authorize(&self.security, request.metadata(), &resource, "read").await?;
+        let request = request.into_inner();
+
+        let filter = filter(&request.filter_json)?;
+        let limit = (request.limit > 0).then_some(request.limit as usize);
+        let documents = self
+            .query
+            .find(&request.collection, Some(&filter), limit)
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(FindResponse {
+            documents_json: documents.iter().map(ToString::to_string).collect(),
+        }))
+    }
+
+    async fn update(
+        &self,
+        request: Request<UpdateRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let resource = format!("collection:{}", request.get_ref().collection);
+        let session = authorize(&self.security, request.metadata(), &resource, "write").await?;
+        let request = request.into_inner();
+
+        let filter = filter(&request.filter_json)?;
+        let update = parse_json("update_json", &request.update_json)?;
+        let result = self
+            .query
+            .update(&request.collection, &filter, &update)
+            .await
+            .map_err(status)?;
+        self.security.audit(
+            AuditEvent::new(AuditCategory::Dml, "update")
+                .by(&session)
+                .on(resource)
+                .detail(format!("{} documents", result.modified_count)),
+        );
+
+        Ok(Response::new(UpdateResponse {
+            matched_count: result.matched_count,
+            modified_count: result.modified_count,
+        }))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let resource = format!("collection:{}", request.get_ref().collection);
+        let session = authorize(&self.security, request.metadata(), &resource, "write").await?;
+        let request = request.into_inner();
+
+        let filter = filter(&request.filter_json)?;
+        let result = self
+            .query
+            .delete(&request.collection, &filter)
+            .await
+            .map_err(status)?;
+        self.security.audit(
+            AuditEvent::new(AuditCategory::Dml, "delete")
+                .by(&session)
+                .on(resource)
+                .detail(format!("{} documents", result.deleted_count)),
+        );
+
+        Ok(Response::new(DeleteResponse {
+            deleted_count: result.deleted_count,
+        }))
+    }
+}
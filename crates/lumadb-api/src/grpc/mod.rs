@@ -1,44 +1,178 @@
-//! gRPC API implementation (stub)
+//! gRPC API implementation
+//!
+//! Serves the services of `proto/lumadb.proto` together with the standard
+//! gRPC health and reflection services. Client deadlines (`grpc-timeout`)
+//! are honored; unary calls are also capped by `request_timeout_ms`, while
+//! streams may stay open for as long as the client keeps them.
 
+// `tonic::Status` is the error type of every handler
+#![allow(clippy::result_large_err)]
+
+mod auth;
+mod deadline;
+mod documents;
+mod query;
+mod stream;
+mod vector;
+
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use tokio::sync::Notify;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
 use tracing::info;
 
 use lumadb_common::config::GrpcApiConfig;
-use lumadb_common::error::Result;
+use lumadb_common::error::{Error, Result};
 use lumadb_query::QueryEngine;
 use lumadb_streaming::StreamingEngine;
 use lumadb_security::SecurityManager;
 
+use proto::document_service_server::DocumentServiceServer;
+use proto::query_service_server::QueryServiceServer;
+use proto::stream_service_server::StreamServiceServer;
+use proto::vector_service_server::VectorServiceServer;
+
+/// Generated protobuf messages, servers and clients
+pub mod proto {
+    #![allow(clippy::all, clippy::pedantic)]
+
+    tonic::include_proto!("lumadb.v1");
+
+    /// Encoded descriptors of the LumaDB services, for reflection
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("lumadb_descriptor");
+}
+
+use deadline::UnaryDeadline;
+
+pub use documents::DocumentApi;
+pub use query::QueryApi;
+pub use stream::StreamApi;
+pub use vector::VectorApi;
+
 /// gRPC server
 #[derive(Clone)]
 pub struct GrpcServer {
     config: GrpcApiConfig,
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+    security: Arc<SecurityManager>,
+    shutdown: Arc<Notify>,
 }
 
 impl GrpcServer {
     /// Create a new gRPC server
+    #[allow(clippy::unused_async, clippy::missing_errors_doc)] // like the other API servers
     pub async fn new(
         config: &GrpcApiConfig,
-        _query: Arc<QueryEngine>,
-        _streaming: Arc<StreamingEngine>,
-        _security: Arc<SecurityManager>,
+        query: Arc<QueryEngine>,
+        streaming: Arc<StreamingEngine>,
+        security: Arc<SecurityManager>,
     ) -> Result<Self> {
         Ok(Self {
             config: config.clone(),
+            query,
+            streaming,
+            security,
+            shutdown: Arc::new(Notify::new()),
         })
     }
 
+    /// TLS settings from the certificate and key of the security config
+    fn tls_config(&self) -> Result<ServerTlsConfig> {
+        let security = self.security.config();
+        let (Some(cert_path), Some(key_path)) = (&security.tls_cert_path, &security.tls_key_path)
+        else {
+            return Err(Error::Config(
+                "gRPC TLS needs security.tls_cert_path and security.tls_key_path".to_string(),
+            ));
+        };
+        let cert = std::fs::read(cert_path)?;
+        let key = std::fs::read(key_path)?;
+        Ok(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+    }
+
     /// Run the gRPC server
     pub async fn run(&self) -> Result<()> {
-        info!("gRPC API server listening on port {}", self.config.port);
-        // gRPC implementation would go here using Tonic
-        Ok(())
+        if !self.config.enabled {
+            info!("gRPC API disabled");
+            return Ok(());
+        }
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], self.config.port));
+        let limit = self.config.max_message_size;
+
+        let (mut health, health_service) = tonic_health::server::health_reporter();
+        health.set_serving::<QueryServiceServer<QueryApi>>().await;
+        health
+            .set_serving::<DocumentServiceServer<DocumentApi>>()
+            .await;
+        health.set_serving::<VectorServiceServer<VectorApi>>().await;
+        health.set_serving::<StreamServiceServer<StreamApi>>().await;
+
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .build_v1()
+            .map_err(|e| Error::Internal(format!("gRPC reflection: {e}")))?;
+
+        let timeout = Duration::from_millis(self.config.request_timeout_ms);
+        let mut builder = Server::builder();
+        if self.config.tls_enabled {
+            builder = builder
+                .tls_config(self.tls_config()?)
+                .map_err(|e| Error::Config(format!("gRPC TLS: {e}")))?;
+        }
+
+        let query = QueryApi::new(self.query.clone(), self.security.clone());
+        let documents = DocumentApi::new(self.query.clone(), self.security.clone());
+        let vector = VectorApi::new(self.query.clone(), self.security.clone());
+        let stream = StreamApi::new(self.streaming.clone(), self.security.clone());
+
+        info!("gRPC API server listening on {}", addr);
+
+        builder
+            .add_service(health_service)
+            .add_service(reflection)
+            .add_service(UnaryDeadline::new(
+                QueryServiceServer::new(query)
+                    .max_decoding_message_size(limit)
+                    .max_encoding_message_size(limit),
+                timeout,
+                &["ExecuteStream"],
+            ))
+            .add_service(UnaryDeadline::new(
+                DocumentServiceServer::new(documents)
+                    .max_decoding_message_size(limit)
+                    .max_encoding_message_size(limit),
+                timeout,
+                &[],
+            ))
+            .add_service(UnaryDeadline::new(
+                VectorServiceServer::new(vector)
+                    .max_decoding_message_size(limit)
+                    .max_encoding_message_size(limit),
+                timeout,
+                &[],
+            ))
+            .add_service(UnaryDeadline::new(
+                StreamServiceServer::new(stream)
+                    .max_decoding_message_size(limit)
+                    .max_encoding_message_size(limit),
+                timeout,
+                &["Produce", "Consume"],
+            ))
+            .serve_with_shutdown(addr, self.shutdown.notified())
+            .await
+            .map_err(|e| Error::Internal(format!("gRPC server: {e}")))
     }
 
     /// Shutdown the server
+    #[allow(clippy::unused_async)] // like the other API servers
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down gRPC API server");
+        self.shutdown.notify_one();
         Ok(())
     }
 }
@@ -0,0 +1,135 @@
+//! Query service: unary execution and server-streamed rows
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use lumadb_query::{QueryEngine, QueryResult};
+use lumadb_security::SecurityManager;
+
+use super::auth::{authenticate, parse_json, permit_query, status};
+use super::proto::query_service_server::QueryService;
+use super::proto::{QueryRequest, QueryResponse, Row};
+
+/// Rows fetched per page when streaming a result
+const STREAM_PAGE_SIZE: usize = 1000;
+
+/// Implementation of `lumadb.v1.QueryService`
+pub struct QueryApi {
+    query: Arc<QueryEngine>,
+    security: Arc<SecurityManager>,
+}
+
+impl QueryApi {
+    pub fn new(query: Arc<QueryEngine>, security: Arc<SecurityManager>) -> Self {
+        Self { query, security }
+    }
+}
+
+fn params(params_json: &[String]) -> Result<Vec<serde_json::Value>, Status> {
+    params_json
+        .iter()
+        .map(|param| parse_json("params_json", param))
+        .collect()
+}
+
+fn row(row: &std::collections::HashMap<String, serde_json::Value>) -> Row {
+    Row {
+        json: serde_json::to_string(row).unwrap_or_default(),
+    }
+}
+
+fn response(result: &QueryResult, next_token: Option<String>) -> QueryResponse {
+    QueryResponse {
+        columns: result.columns().to_vec(),
+        rows: result.rows().iter().map(row).collect(),
+        rows_affected: result.rows_affected(),
+        cached: result.cached,
+        execution_time_ms: result.execution_time_ms,
+        next_token: next_token.unwrap_or_default(),
+    }
+}
+
+#[tonic::async_trait]
+impl QueryService for QueryApi {
+    async fn execute(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<QueryResponse>, Status> {
+        let session = authenticate(&self.security, request.metadata()).await?;
+        let request = request.into_inner();
+        permit_query(&self.security, &self.query, &session, &request.query)?;
+        let params = params(&request.params_json)?;
+
+        let response = if request.page_size > 0 || !request.continuation_token.is_empty() {
+            let page_size = match request.page_size {
+                0 => STREAM_PAGE_SIZE,
+                n => n as usize,
+            };
+            let token = Some(request.continuation_token.as_str()).filter(|t| !t.is_empty());
+            let page = self
+                .query
+                .execute_page(&request.query, &params, page_size, token)
+                .await
+                .map_err(status)?;
+            response(&page.result, page.next_token)
+        } else {
+            let result = self
+                .query
+                .execute(&request.query, &params)
+                .await
+                .map_err(status)?;
+            response(&result, None)
+        };
+        Ok(Response::new(response))
+    }
+
+    type ExecuteStreamStream = ReceiverStream<Result<Row, Status>>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let session = authenticate(&self.security, request.metadata()).await?;
+        let request = request.into_inner();
+        permit_query(&self.security, &self.query, &session, &request.query)?;
+        let params = params(&request.params_json)?;
+        let page_size = match request.page_size {
+            0 => STREAM_PAGE_SIZE,
+            n => n as usize,
+        };
+
+        // Rows are produced a page at a time so only one page is held in
+        // memory; the stream stops early once the client goes away
+        let (tx, rx) = mpsc::channel(page_size);
+        let query = self.query.clone();
+        tokio::spawn(async move {
+            let mut token = Some(request.continuation_token).filter(|t| !t.is_empty());
+            loop {
+                let page = match query
+                    .execute_page(&request.query, &params, page_size, token.as_deref())
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        let _ = tx.send(Err(status(e))).await;
+                        return;
+                    }
+                };
+                for r in page.result.rows() {
+                    if tx.send(Ok(row(r))).await.is_err() {
+                        return;
+                    }
+                }
+                token = page.next_token;
+                if token.is_none() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
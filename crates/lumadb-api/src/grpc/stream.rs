@@ -0,0 +1,256 @@
+//! Streaming service: client-streamed produce, server-streamed consume and
+//! consumer group offset commits
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+use lumadb_common::types::Offset;
+use lumadb_security::SecurityManager;
+use lumadb_streaming::producer::IsolationLevel;
+use lumadb_streaming::{ConsumeRecord, Partition, ProduceRecord, StreamingEngine};
+
+use super::auth::{authenticate, authorize, parse_json, permit, status};
+use super::proto::stream_service_server::StreamService;
+use super::proto::{
+    CommitOffsetsRequest, CommitOffsetsResponse, ConsumeRequest, ConsumeResponse, ConsumedRecord,
+    ProduceRequest, ProduceResponse, RecordMetadata,
+};
+
+/// Records read per partition and batch when a request asks for none
+const DEFAULT_MAX_RECORDS: usize = 100;
+
+/// Wait between polls of an idle topic when a request sets none
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Implementation of `lumadb.v1.StreamService`
+pub struct StreamApi {
+    streaming: Arc<StreamingEngine>,
+    security: Arc<SecurityManager>,
+}
+
+impl StreamApi {
+    pub fn new(streaming: Arc<StreamingEngine>, security: Arc<SecurityManager>) -> Self {
+        Self {
+            streaming,
+            security,
+        }
+    }
+}
+
+fn produce_record(record: super::proto::ProduceRecord) -> Result<ProduceRecord, Status> {
+    Ok(ProduceRecord {
+        key: record.key,
+        value: parse_json("value_json", &record.value_json)?,
+        headers: (!record.headers.is_empty()).then_some(record.headers),
+        partition: record.partition,
+    })
+}
+
+fn consumed_record(record: ConsumeRecord) -> ConsumedRecord {
+    ConsumedRecord {
+        topic: record.topic,
+        partition: record.partition,
+        offset: record.offset,
+        timestamp: record.timestamp,
+        key: record.key,
+        value_json: record.value.to_string(),
+        headers: record.headers,
+    }
+}
+
+/// Offset a partition is read from when its group has committed none
+fn start_offset(offset: &str, partition: &Partition) -> Result<Offset, Status> {
+    match offset {
+        "" | "latest" => Ok(partition.log().log_end_offset()),
+        "earliest" => Ok(0),
+        offset => offset
+            .parse()
+            .map_err(|_| Status::invalid_argument(format!("invalid offset {offset}"))),
+    }
+}
+
+#[tonic::async_trait]
+impl StreamService for StreamApi {
+    async fn produce(
+        &self,
+        request: Request<Streaming<ProduceRequest>>,
+    ) -> Result<Response<ProduceResponse>, Status> {
+        let session = authenticate(&self.security, request.metadata()).await?;
+        let mut batches = request.into_inner();
+
+        let mut appended = Vec::new();
+        while let Some(batch) = batches.message().await? {
+            permit(
+                &self.security,
+                &session,
+                &format!("topic:{}", batch.topic),
+                "write",
+            )?;
+            let acks = i8::try_from(batch.acks)
+                .map_err(|_| Status::invalid_argument("acks out of range"))?;
+            let records = batch
+                .records
+                .into_iter()
+                .map(produce_record)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let metadata = self
+                .streaming
+                .produce(&batch.topic, &records, acks)
+                .await
+                .map_err(status)?;
+            appended.extend(metadata.into_iter().map(|m| RecordMetadata {
+                topic: m.topic,
+                partition: m.partition,
+                offset: m.offset,
+                timestamp: m.timestamp,
+            }));
+        }
+
+        Ok(Response::new(ProduceResponse { records: appended }))
+    }
+
+    type ConsumeStream = ReceiverStream<Result<ConsumeResponse, Status>>;
+
+    async fn consume(
+        &self,
+        request: Request<ConsumeRequest>,
+    ) -> Result<Response<Self::ConsumeStream>, Status> {
+        let resource = format!("topic:{}", request.get_ref().topic);
+        let session = authorize(&self.security, request.metadata(), &resource, "read").await?;
+        let request = request.into_inner();
+
+        let group_id = Some(request.group_id).filter(|g| !g.is_empty());
+        if let Some(group_id) = &group_id {
+            permit(
+                &self.security,
+                &session,
+                &format!("group:{group_id}"),
+                "read",
+            )?;
+        }
+        if request.auto_commit && group_id.is_none() {
+            return Err(Status::invalid_argument("auto_commit requires a group_id"));
+        }
+        let isolation = match request.isolation_level.as_str() {
+            "" => IsolationLevel::default(),
+            level => level.parse().map_err(Status::invalid_argument)?,
+        };
+        let max_records = match request.max_records {
+            0 => DEFAULT_MAX_RECORDS,
+            n => n as usize,
+        };
+        let poll_interval = match request.poll_interval_ms {
+            0 => DEFAULT_POLL_INTERVAL,
+            ms => Duration::from_millis(u64::from(ms)),
+        };
+
+        let topic = request.topic;
+        let partitions = self
+            .streaming
+            .partitions(&topic)
+            .ok_or_else(|| Status::not_found(format!("topic {topic} not found")))?;
+        let group = group_id
+            .as_deref()
+            .and_then(|g| self.streaming.find_consumer_group(g));
+        let mut positions = partitions
+            .iter()
+            .map(|partition| {
+                let committed = group
+                    .as_ref()
+                    .and_then(|g| g.get_offset(&topic, partition.id()));
+                let offset = match committed {
+                    Some(offset) => offset,
+                    None => start_offset(&request.offset, partition)?,
+                };
+                Ok((partition.id(), offset))
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        // Poll every partition from its position, send what was read, then
+        // commit the new positions for auto-committing groups
+        let (tx, rx) = mpsc::channel(16);
+        let streaming = self.streaming.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut records = Vec::new();
+                let mut advanced = Vec::new();
+                for (partition, position) in &mut positions {
+                    match streaming.read_partition(
+                        &topic,
+                        *partition,
+                        *position,
+                        max_records,
+                        isolation,
+                    ) {
+                        Ok((read, next)) => {
+                            if next != *position {
+                                *position = next;
+                                advanced.push((topic.clone(), *partition, next));
+                            }
+                            records.extend(read.into_iter().map(consumed_record));
+                        }
+                        Err(e) => {
+                            let _ = tx.send(Err(status(e))).await;
+                            return;
+                        }
+                    }
+                }
+
+                let idle = records.is_empty();
+                if !idle && tx.send(Ok(ConsumeResponse { records })).await.is_err() {
+                    return;
+                }
+                if let (true, Some(group_id)) = (request.auto_commit, &group_id) {
+                    if let Err(e) = streaming.store_offsets(group_id, &advanced) {
+                        let _ = tx.send(Err(status(e))).await;
+                        return;
+                    }
+                }
+                if idle {
+                    if tx.is_closed() {
+                        return;
+                    }
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn commit_offsets(
+        &self,
+        request: Request<CommitOffsetsRequest>,
+    ) -> Result<Response<CommitOffsetsResponse>, Status> {
+        let resource = format!("group:{}", request.get_ref().group_id);
+        let session = authorize(&self.security, request.metadata(), &resource, "read").await?;
+        let request = request.into_inner();
+        for offset in &request.offsets {
+            permit(
+                &self.security,
+                &session,
+                &format!("topic:{}", offset.topic),
+                "read",
+            )?;
+        }
+
+        if request.group_id.is_empty() {
+            return Err(Status::invalid_argument("group_id is required"));
+        }
+        let offsets: Vec<_> = request
+            .offsets
+            .into_iter()
+            .map(|o| (o.topic, o.partition, o.offset))
+            .collect();
+        self.streaming
+            .store_offsets(&request.group_id, &offsets)
+            .map_err(status)?;
+
+        Ok(Response::new(CommitOffsetsResponse {}))
+    }
+}
@@ -0,0 +1,68 @@
+//! Vector similarity search service
+
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use lumadb_query::QueryEngine;
+use lumadb_security::SecurityManager;
+
+use super::auth::{authorize, status};
+use super::proto::vector_service_server::VectorService;
+use super::proto::{VectorMatch, VectorSearchRequest, VectorSearchResponse};
+
+/// Neighbours returned when a request asks for none
+const DEFAULT_K: usize = 10;
+
+/// Implementation of `lumadb.v1.VectorService`
+pub struct VectorApi {
+    query: Arc<QueryEngine>,
+    security: Arc<SecurityManager>,
+}
+
+impl VectorApi {
+    pub fn new(query: Arc<QueryEngine>, security: Arc<SecurityManager>) -> Self {
+        Self { query, security }
+    }
+}
+
+#[tonic::async_trait]
+impl VectorService for VectorApi {
+    async fn search(
+        &self,
+        request: Request<VectorSearchRequest>,
+    ) -> Result<Response<VectorSearchResponse>, Status> {
+        let resource = format!("collection:{}", request.get_ref().collection);
+        authorize(&self.security, request.metadata(), &resource, "read").await?;
+        let request = request.into_inner();
+
+        if request.vector.is_empty() {
+            return Err(Status::invalid_argument("vector must not be empty"));
+        }
+        let k = match request.k {
+            0 => DEFAULT_K,
+            k => k as usize,
+        };
+        let filter = Some(request.filter.as_str()).filter(|f| !f.is_empty());
+
+        let results = self
+            .query
+            .vector_search(&request.collection, &request.vector, k, filter)
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(VectorSearchResponse {
+            matches: results
+                .into_iter()
+                .map(|result| VectorMatch {
+                    id: result.id,
+                    score: result.score,
+                    document_json: result
+                        .document
+                        .map(|doc| serde_json::to_string(&doc).unwrap_or_default())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        }))
+    }
+}
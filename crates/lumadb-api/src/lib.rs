@@ -4,7 +4,7 @@
 //! - REST API (Actix-Web)
 //! - GraphQL API (async-graphql)
 //! - gRPC API (Tonic)
-//! - WebSocket for real-time updates
+//! - WebSocket live queries, change feeds and GraphQL subscriptions
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
@@ -14,6 +14,9 @@ pub mod grpc;
 pub mod rest;
 pub mod websocket;
 
+mod feeds;
+
 pub use rest::RestServer;
 pub use graphql::GraphQLServer;
 pub use grpc::GrpcServer;
+pub use websocket::WebSocketServer;
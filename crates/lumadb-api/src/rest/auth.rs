@@ -0,0 +1,133 @@
+//! Authentication and authorization shared by the REST handlers
+
+use std::fmt;
+use std::sync::Arc;
+
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use futures::future::LocalBoxFuture;
+
+use lumadb_common::error::{AuthError, Error};
+use lumadb_query::QueryEngine;
+use lumadb_security::{Credentials, SecurityManager, Session};
+
+/// Session of the caller named by a request's `Authorization: Bearer
+/// <token>` header
+pub(super) struct Caller(pub Session);
+
+/// Why a request's caller could not be authenticated
+#[derive(Debug)]
+pub(super) struct Unauthenticated(String);
+
+impl fmt::Display for Unauthenticated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ResponseError for Unauthenticated {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": self.0,
+        }))
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = Unauthenticated;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let security = req.app_data::<web::Data<Arc<SecurityManager>>>().cloned();
+        let header = req
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .map(|value| value.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")));
+        let token = header.map(|token| token.map(str::to_string));
+        let host = req.peer_addr().map(|addr| addr.ip());
+
+        Box::pin(async move {
+            let security = security
+                .ok_or_else(|| Unauthenticated("security is not configured".to_string()))?;
+            let token = match token {
+                Some(Some(token)) => token,
+                Some(None) => {
+                    return Err(Unauthenticated(
+                        "authorization must be a bearer token".to_string(),
+                    ))
+                }
+                None if security.auth_enabled() => {
+                    return Err(Unauthenticated("missing authorization header".to_string()))
+                }
+                None => String::new(),
+            };
+
+            let session = security
+                .authenticate(&Credentials::Token(token))
+                .await
+                .map_err(|e| Unauthenticated(e.to_string()))?;
+            Ok(Self(match host {
+                Some(host) => session.with_host(host),
+                None => session,
+            }))
+        })
+    }
+}
+
+/// Why the caller may not take an action
+#[derive(Debug)]
+pub(super) struct Refused(Error);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ResponseError for Refused {
+    fn status_code(&self) -> StatusCode {
+        match self.0 {
+            Error::Auth(AuthError::PermissionDenied(_)) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.0.to_string(),
+        }))
+    }
+}
+
+/// Check that the caller may perform `action` on `resource`
+pub(super) fn permit(
+    security: &SecurityManager,
+    caller: &Caller,
+    resource: &str,
+    action: &str,
+) -> Result<(), Refused> {
+    security
+        .authorize(&caller.0, resource, action)
+        .map_err(Refused)
+}
+
+/// Check that the caller may run `text`, taking each action the statement
+/// takes on every resource it touches
+pub(super) fn permit_query(
+    security: &SecurityManager,
+    query: &QueryEngine,
+    caller: &Caller,
+    text: &str,
+) -> Result<(), Refused> {
+    let accesses = query.accesses(text).map_err(Refused)?;
+    for (resource, action) in &accesses {
+        permit(security, caller, resource, action)?;
+    }
+    security.audit_statement(&caller.0, text, &accesses);
+    Ok(())
+}
@@ -1,16 +1,31 @@
 //! REST API implementation
+//!
+//! Every API request carries an `Authorization: Bearer <token>` header
+//! when authentication is enabled; each handler checks that the caller may
+//! take its action on the resources it touches, answering 401 for a
+//! missing or invalid token and 403 for a denied action.
 
+mod auth;
+
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use actix_web::{web, App, HttpServer, HttpResponse, middleware};
+use actix_web::{web, App, HttpServer, HttpResponse, ResponseError, middleware};
 use actix_cors::Cors;
 use tracing::info;
 
 use lumadb_common::config::RestApiConfig;
-use lumadb_common::error::Result;
+use lumadb_common::error::{AuthError, Error, Result, TransactionError};
 use lumadb_query::QueryEngine;
+use lumadb_storage::hybrid::{Fusion, HybridQuery};
+use lumadb_streaming::producer::{IsolationLevel, ProducerIdAndEpoch};
 use lumadb_streaming::StreamingEngine;
-use lumadb_security::SecurityManager;
+use lumadb_security::authz::{AclBinding, AclBindingFilter, Operation, Permission, ResourceType, Role};
+use lumadb_security::auth::scram::MIN_ITERATIONS;
+use lumadb_security::auth::{ScramCredential, ScramMechanism};
+use lumadb_security::{AuditCategory, AuditEvent, SecurityManager};
+
+use self::auth::{permit, permit_query, Caller};
 
 /// REST API server
 #[derive(Clone)]
@@ -88,12 +103,81 @@ impl RestServer {
                 .service(
                     web::scope("/api/v1")
                         .route("/query", web::post().to(execute_query))
+                        .route("/query/transactions", web::post().to(begin_query_txn))
+                        .route("/query/transactions/{id}", web::post().to(execute_in_txn))
+                        .route(
+                            "/query/transactions/{id}/commit",
+                            web::post().to(commit_query_txn),
+                        )
+                        .route(
+                            "/query/transactions/{id}/rollback",
+                            web::post().to(rollback_query_txn),
+                        )
                         .route("/collections", web::get().to(list_collections))
                         .route("/collections", web::post().to(create_collection))
+                        .route(
+                            "/collections/{name}/quantization",
+                            web::get().to(quantization_report),
+                        )
+                        .route(
+                            "/collections/{name}/hybrid-search",
+                            web::post().to(hybrid_search),
+                        )
+                        .route(
+                            "/collections/{name}/text-indexes/{field}",
+                            web::put().to(create_text_index),
+                        )
+                        .route(
+                            "/collections/{name}/text-indexes/{field}",
+                            web::delete().to(drop_text_index),
+                        )
                         .route("/topics", web::get().to(list_topics))
                         .route("/topics", web::post().to(create_topic))
                         .route("/topics/{name}/produce", web::post().to(produce))
                         .route("/topics/{name}/consume", web::get().to(consume))
+                        .route(
+                            "/topics/{name}/partitions/{partition}/produce",
+                            web::post().to(produce_idempotent),
+                        )
+                        .route("/producers", web::post().to(init_producer))
+                        .route("/transactions/{id}", web::get().to(describe_transaction))
+                        .route(
+                            "/transactions/{id}/partitions",
+                            web::post().to(add_partitions_to_txn),
+                        )
+                        .route(
+                            "/transactions/{id}/offsets",
+                            web::post().to(add_offsets_to_txn),
+                        )
+                        .route("/transactions/{id}/end", web::post().to(end_txn))
+                        .route("/groups/{group}/members", web::post().to(join_group))
+                        .route(
+                            "/groups/{group}/members/{member}/heartbeat",
+                            web::post().to(heartbeat),
+                        )
+                        .route(
+                            "/groups/{group}/members/{member}",
+                            web::delete().to(leave_group),
+                        )
+                        .route(
+                            "/groups/{group}/offsets",
+                            web::post().to(commit_group_offsets),
+                        )
+                        .route("/acls", web::get().to(describe_acls))
+                        .route("/acls", web::post().to(create_acls))
+                        .route("/acls", web::delete().to(delete_acls))
+                        .route("/roles", web::get().to(list_roles))
+                        .route("/roles/{name}", web::put().to(put_role))
+                        .route("/roles/{name}", web::delete().to(delete_role))
+                        .route("/scram-credentials", web::get().to(list_scram_credentials))
+                        .route(
+                            "/scram-credentials/{user}/{mechanism}",
+                            web::put().to(put_scram_credential),
+                        )
+                        .route(
+                            "/scram-credentials/{user}/{mechanism}",
+                            web::delete().to(delete_scram_credential),
+                        ),
                 )
         })
         .workers(self.config.workers)
@@ -141,28 +225,177 @@ async fn metrics() -> HttpResponse {
         .body(metrics)
 }
 
+/// Page size used when a request continues a query without giving one
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
 #[derive(serde::Deserialize)]
 struct QueryRequest {
     query: String,
     #[serde(default)]
     params: Vec<serde_json::Value>,
+    /// Return at most this many rows, with a token for the next page
+    #[serde(default)]
+    page_size: Option<usize>,
+    /// `next_token` of the previous page
+    #[serde(default)]
+    continuation_token: Option<String>,
 }
 
 async fn execute_query(
     query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
     request: web::Json<QueryRequest>,
 ) -> HttpResponse {
-    match query_engine.execute(&request.query, &request.params).await {
-        Ok(result) => HttpResponse::Ok().json(result),
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+    if let Err(refused) = permit_query(&security, &query_engine, &caller, &request.query) {
+        return refused.error_response();
+    }
+    let result = if request.page_size.is_some() || request.continuation_token.is_some() {
+        query_engine
+            .execute_page(
+                &request.query,
+                &request.params,
+                request.page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+                request.continuation_token.as_deref(),
+            )
+            .await
+            .map(|page| HttpResponse::Ok().json(page))
+    } else {
+        query_engine
+            .execute(&request.query, &request.params)
+            .await
+            .map(|result| HttpResponse::Ok().json(result))
+    };
+
+    result.unwrap_or_else(|e| {
+        HttpResponse::BadRequest().json(serde_json::json!({
             "error": e.to_string(),
+        }))
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct BeginTxnRequest {
+    /// `snapshot` (default) or `serializable`
+    #[serde(default)]
+    isolation: lumadb_query::IsolationLevel,
+}
+
+/// Begin a transaction and return its handle
+async fn begin_query_txn(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    caller: Caller,
+    request: Option<web::Json<BeginTxnRequest>>,
+) -> HttpResponse {
+    let isolation = request.map(|r| r.isolation).unwrap_or_default();
+    let txn_id = query_engine.begin_for(isolation, &caller.0.user_id);
+
+    HttpResponse::Created().json(serde_json::json!({
+        "transaction_id": txn_id,
+        "isolation": isolation,
+    }))
+}
+
+/// Error response for a transaction, with conflicts as 409
+fn txn_error(e: &Error) -> HttpResponse {
+    let body = serde_json::json!({
+        "error": e.to_string(),
+    });
+    match e {
+        Error::Transaction(
+            TransactionError::Conflict(_) | TransactionError::SerializationFailure(_),
+        ) => HttpResponse::Conflict().json(body),
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+/// Response refusing a caller that did not begin the transaction, if any
+///
+/// Transaction IDs are easy to guess, so another caller's transaction is
+/// reported as not found rather than forbidden.
+fn unowned_txn(query_engine: &QueryEngine, caller: &Caller, txn_id: u64) -> Option<HttpResponse> {
+    let error = match query_engine.transaction_owner(txn_id) {
+        Ok(Some(owner)) if owner == caller.0.user_id => return None,
+        Ok(_) => format!("Transaction {txn_id} not found"),
+        Err(e) => e.to_string(),
+    };
+    Some(HttpResponse::NotFound().json(serde_json::json!({
+        "error": error,
+    })))
+}
+
+async fn execute_in_txn(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<u64>,
+    request: web::Json<QueryRequest>,
+) -> HttpResponse {
+    let txn_id = path.into_inner();
+    if let Some(response) = unowned_txn(&query_engine, &caller, txn_id) {
+        return response;
+    }
+    if let Err(refused) = permit_query(&security, &query_engine, &caller, &request.query) {
+        return refused.error_response();
+    }
+
+    match query_engine
+        .execute_in(txn_id, &request.query, &request.params)
+        .await
+    {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => txn_error(&e),
+    }
+}
+
+async fn commit_query_txn(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    caller: Caller,
+    path: web::Path<u64>,
+) -> HttpResponse {
+    let txn_id = path.into_inner();
+    if let Some(response) = unowned_txn(&query_engine, &caller, txn_id) {
+        return response;
+    }
+
+    match query_engine.commit(txn_id).await {
+        Ok(commit_ts) => HttpResponse::Ok().json(serde_json::json!({
+            "transaction_id": txn_id,
+            "commit_ts": commit_ts,
         })),
+        Err(e) => txn_error(&e),
+    }
+}
+
+async fn rollback_query_txn(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    caller: Caller,
+    path: web::Path<u64>,
+) -> HttpResponse {
+    let txn_id = path.into_inner();
+    if let Some(response) = unowned_txn(&query_engine, &caller, txn_id) {
+        return response;
+    }
+
+    match query_engine.rollback(txn_id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => txn_error(&e),
     }
 }
 
-async fn list_collections(query_engine: web::Data<Arc<QueryEngine>>) -> HttpResponse {
+/// Collections the caller may describe
+async fn list_collections(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+) -> HttpResponse {
     match query_engine.list_collections().await {
-        Ok(collections) => HttpResponse::Ok().json(collections),
+        Ok(mut collections) => {
+            collections.retain(|c| {
+                security.is_authorized(&caller.0, &format!("collection:{}", c.name), "describe")
+            });
+            HttpResponse::Ok().json(collections)
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
             "error": e.to_string(),
         })),
@@ -176,19 +409,197 @@ struct CreateCollectionRequest {
 
 async fn create_collection(
     query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
     request: web::Json<CreateCollectionRequest>,
 ) -> HttpResponse {
+    let resource = format!("collection:{}", request.name);
+    if let Err(refused) = permit(&security, &caller, &resource, "create") {
+        return refused.error_response();
+    }
+    let event = AuditEvent::new(AuditCategory::Ddl, "create").by(&caller.0).on(resource);
     match query_engine.create_collection(&request.name, None, None).await {
-        Ok(collection) => HttpResponse::Created().json(collection),
+        Ok(collection) => {
+            security.audit(event);
+            HttpResponse::Created().json(collection)
+        }
+        Err(e) => {
+            security.audit(event.failed(e.to_string()));
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string(),
+            }))
+        }
+    }
+}
+
+/// Measured recall, memory and comparison times of a quantized vector
+/// collection
+async fn quantization_report(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    if let Err(refused) = permit(
+        &security,
+        &caller,
+        &format!("collection:{name}"),
+        "describe",
+    ) {
+        return refused.error_response();
+    }
+
+    match query_engine.quantization_report(&name) {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Collection {} has no quantized vector index", name),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct HybridSearchRequest {
+    #[serde(default)]
+    vector: Option<Vec<f32>>,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default = "default_search_limit")]
+    limit: usize,
+    #[serde(default)]
+    fusion: Fusion,
+    #[serde(default)]
+    weights: Option<FusionWeights>,
+    /// Candidates each ranking contributes
+    #[serde(default)]
+    prefetch: Option<usize>,
+}
+
+#[derive(serde::Deserialize)]
+struct FusionWeights {
+    vector: f32,
+    text: f32,
+}
+
+fn default_search_limit() -> usize {
+    10
+}
+
+/// Rank a collection's documents by vector similarity and by BM25 for
+/// text, fused into one ranking; each hit explains its rank and score in
+/// both
+async fn hybrid_search(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<HybridSearchRequest>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("collection:{name}"), "read") {
+        return refused.error_response();
+    }
+    let request = request.into_inner();
+
+    let mut query = HybridQuery::new(request.limit).with_fusion(request.fusion);
+    if let Some(vector) = request.vector {
+        query = query.with_vector(vector);
+    }
+    if let Some(text) = request.text {
+        query = query.with_text(text);
+    }
+    if let Some(weights) = request.weights {
+        query = query.with_weights(weights.vector, weights.text);
+    }
+    if let Some(prefetch) = request.prefetch {
+        query = query.with_prefetch(prefetch);
+    }
+    match query_engine.hybrid_search(&name, &query).await {
+        Ok(results) => HttpResponse::Ok().json(results),
         Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
             "error": e.to_string(),
         })),
     }
 }
 
-async fn list_topics(streaming: web::Data<Arc<StreamingEngine>>) -> HttpResponse {
+/// Index the text of a document field for full-text and hybrid search
+async fn create_text_index(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (name, field) = path.into_inner();
+    let resource = format!("collection:{name}");
+    if let Err(refused) = permit(&security, &caller, &resource, "alter") {
+        return refused.error_response();
+    }
+    let event = AuditEvent::new(AuditCategory::Ddl, "alter")
+        .by(&caller.0)
+        .on(resource);
+    match query_engine.create_text_index(&name, &field).await {
+        Ok(created) => {
+            security.audit(event);
+            let response = serde_json::json!({ "field": field, "created": created });
+            if created {
+                HttpResponse::Created().json(response)
+            } else {
+                HttpResponse::Ok().json(response)
+            }
+        }
+        Err(e) => {
+            security.audit(event.failed(e.to_string()));
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string(),
+            }))
+        }
+    }
+}
+
+async fn drop_text_index(
+    query_engine: web::Data<Arc<QueryEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (name, field) = path.into_inner();
+    let resource = format!("collection:{name}");
+    if let Err(refused) = permit(&security, &caller, &resource, "alter") {
+        return refused.error_response();
+    }
+    let event = AuditEvent::new(AuditCategory::Ddl, "alter")
+        .by(&caller.0)
+        .on(resource);
+    match query_engine.drop_text_index(&name, &field).await {
+        Ok(true) => {
+            security.audit(event);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Field {} of {} has no text index", field, name),
+        })),
+        Err(e) => {
+            security.audit(event.failed(e.to_string()));
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string(),
+            }))
+        }
+    }
+}
+
+/// Topics the caller may describe
+async fn list_topics(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+) -> HttpResponse {
     match streaming.list_topics().await {
-        Ok(topics) => HttpResponse::Ok().json(topics),
+        Ok(mut topics) => {
+            topics.retain(|t| {
+                security.is_authorized(&caller.0, &format!("topic:{}", t.name), "describe")
+            });
+            HttpResponse::Ok().json(topics)
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
             "error": e.to_string(),
         })),
@@ -202,6 +613,9 @@ struct CreateTopicRequest {
     partitions: u32,
     #[serde(default = "default_replication")]
     replication_factor: u32,
+    /// Kafka-style topic configuration, e.g. `cleanup.policy`
+    #[serde(default)]
+    config: HashMap<String, String>,
 }
 
 fn default_partitions() -> u32 { 3 }
@@ -209,22 +623,38 @@ fn default_replication() -> u32 { 1 }
 
 async fn create_topic(
     streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
     request: web::Json<CreateTopicRequest>,
 ) -> HttpResponse {
-    let config = lumadb_common::types::TopicConfig::new(
+    let resource = format!("topic:{}", request.name);
+    if let Err(refused) = permit(&security, &caller, &resource, "create") {
+        return refused.error_response();
+    }
+    let mut config = lumadb_common::types::TopicConfig::new(
         &request.name,
         request.partitions,
         request.replication_factor,
     );
+    config.config.clone_from(&request.config);
 
+    let event = AuditEvent::new(AuditCategory::Ddl, "create")
+        .by(&caller.0)
+        .on(resource);
     match streaming.create_topic(config).await {
-        Ok(()) => HttpResponse::Created().json(serde_json::json!({
-            "name": request.name,
-            "partitions": request.partitions,
-        })),
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": e.to_string(),
-        })),
+        Ok(()) => {
+            security.audit(event);
+            HttpResponse::Created().json(serde_json::json!({
+                "name": request.name,
+                "partitions": request.partitions,
+            }))
+        }
+        Err(e) => {
+            security.audit(event.failed(e.to_string()));
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string(),
+            }))
+        }
     }
 }
 
@@ -237,10 +667,15 @@ struct ProduceRequest {
 
 async fn produce(
     streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
     path: web::Path<String>,
     request: web::Json<ProduceRequest>,
 ) -> HttpResponse {
     let topic = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("topic:{topic}"), "write") {
+        return refused.error_response();
+    }
 
     match streaming.produce(&topic, request.records.as_slice(), request.acks).await {
         Ok(result) => HttpResponse::Ok().json(result),
@@ -255,25 +690,640 @@ struct ConsumeParams {
     group_id: Option<String>,
     offset: Option<String>,
     max_records: Option<usize>,
+    /// `read_uncommitted` (default) or `read_committed`
+    isolation_level: Option<String>,
+    /// Member of a server-assigned group; only its partitions are read
+    member_id: Option<String>,
 }
 
 async fn consume(
     streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
     path: web::Path<String>,
     params: web::Query<ConsumeParams>,
 ) -> HttpResponse {
     let topic = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("topic:{topic}"), "read") {
+        return refused.error_response();
+    }
+    if let Some(group_id) = &params.group_id {
+        if let Err(refused) = permit(&security, &caller, &format!("group:{group_id}"), "read") {
+            return refused.error_response();
+        }
+    }
+    let isolation = match params
+        .isolation_level
+        .as_deref()
+        .map(str::parse::<IsolationLevel>)
+        .transpose()
+    {
+        Ok(isolation) => isolation.unwrap_or_default(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e,
+            }))
+        }
+    };
 
-    match streaming.consume(
-        &topic,
-        params.group_id.as_deref(),
-        params.offset.as_deref(),
-        params.max_records.unwrap_or(100),
-        5000,
-    ).await {
+    let max_records = params.max_records.unwrap_or(100);
+    let result = match (params.group_id.as_deref(), params.member_id.as_deref()) {
+        (Some(group_id), Some(member_id)) => {
+            streaming
+                .consume_assigned(
+                    &topic,
+                    group_id,
+                    member_id,
+                    params.offset.as_deref(),
+                    max_records,
+                    isolation,
+                )
+                .await
+        }
+        (group_id, _) => {
+            streaming
+                .consume_with_isolation(
+                    &topic,
+                    group_id,
+                    params.offset.as_deref(),
+                    max_records,
+                    5000,
+                    isolation,
+                )
+                .await
+        }
+    };
+    match result {
         Ok(records) => HttpResponse::Ok().json(records),
         Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
             "error": e.to_string(),
         })),
     }
 }
+
+#[derive(serde::Deserialize)]
+struct InitProducerRequest {
+    transactional_id: Option<String>,
+    #[serde(default = "default_transaction_timeout")]
+    transaction_timeout_ms: i32,
+}
+
+fn default_transaction_timeout() -> i32 {
+    60_000
+}
+
+/// Allocate a producer id; transactional producers need write on their
+/// transactional id, idempotent ones idempotent write on the cluster or
+/// write on some topic
+async fn init_producer(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    request: web::Json<InitProducerRequest>,
+) -> HttpResponse {
+    let permitted = match &request.transactional_id {
+        Some(id) => permit(
+            &security,
+            &caller,
+            &format!("transactional_id:{id}"),
+            "write",
+        ),
+        None if security.is_authorized_any(&caller.0, ResourceType::Topic, Operation::Write) => {
+            Ok(())
+        }
+        None => permit(&security, &caller, "cluster", "idempotent_write"),
+    };
+    if let Err(refused) = permitted {
+        return refused.error_response();
+    }
+    match streaming.init_producer_id(
+        request.transactional_id.as_deref(),
+        request.transaction_timeout_ms,
+        None,
+    ) {
+        Ok(producer) => HttpResponse::Ok().json(producer),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IdempotentProduceRequest {
+    producer_id: i64,
+    producer_epoch: i16,
+    /// Sequence number of the first record
+    base_sequence: i32,
+    #[serde(default)]
+    transactional: bool,
+    records: Vec<lumadb_streaming::ProduceRecord>,
+}
+
+async fn produce_idempotent(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, i32)>,
+    request: web::Json<IdempotentProduceRequest>,
+) -> HttpResponse {
+    let (topic, partition) = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("topic:{topic}"), "write") {
+        return refused.error_response();
+    }
+    let producer = lumadb_common::types::ProducerInfo {
+        producer_id: request.producer_id,
+        producer_epoch: request.producer_epoch,
+        sequence: request.base_sequence,
+        transactional: request.transactional,
+    };
+
+    match streaming.produce_to_partition(&topic, partition, &request.records, producer) {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn describe_transaction(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let transactional_id = path.into_inner();
+    let resource = format!("transactional_id:{transactional_id}");
+    if let Err(refused) = permit(&security, &caller, &resource, "describe") {
+        return refused.error_response();
+    }
+
+    match streaming.transaction(&transactional_id) {
+        Some(transaction) => HttpResponse::Ok().json(transaction),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Transactional id {} not found", transactional_id),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TxnPartition {
+    topic: String,
+    partition: i32,
+}
+
+#[derive(serde::Deserialize)]
+struct AddPartitionsRequest {
+    #[serde(flatten)]
+    producer: ProducerIdAndEpoch,
+    partitions: Vec<TxnPartition>,
+}
+
+async fn add_partitions_to_txn(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<AddPartitionsRequest>,
+) -> HttpResponse {
+    let transactional_id = path.into_inner();
+    let resource = format!("transactional_id:{transactional_id}");
+    let permitted = permit(&security, &caller, &resource, "write").and_then(|()| {
+        request
+            .partitions
+            .iter()
+            .try_for_each(|p| permit(&security, &caller, &format!("topic:{}", p.topic), "write"))
+    });
+    if let Err(refused) = permitted {
+        return refused.error_response();
+    }
+    let partitions: Vec<_> = request
+        .partitions
+        .iter()
+        .map(|p| (p.topic.clone(), p.partition))
+        .collect();
+
+    match streaming.add_partitions_to_txn(&transactional_id, request.producer, &partitions) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PartitionOffset {
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct AddOffsetsRequest {
+    #[serde(flatten)]
+    producer: ProducerIdAndEpoch,
+    group_id: String,
+    offsets: Vec<PartitionOffset>,
+}
+
+/// Commit a consumer group's offsets as part of a transaction
+async fn add_offsets_to_txn(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<AddOffsetsRequest>,
+) -> HttpResponse {
+    let transactional_id = path.into_inner();
+    let resource = format!("transactional_id:{transactional_id}");
+    let permitted = permit(&security, &caller, &resource, "write")
+        .and_then(|()| {
+            permit(
+                &security,
+                &caller,
+                &format!("group:{}", request.group_id),
+                "read",
+            )
+        })
+        .and_then(|()| {
+            request
+                .offsets
+                .iter()
+                .try_for_each(|o| permit(&security, &caller, &format!("topic:{}", o.topic), "read"))
+        });
+    if let Err(refused) = permitted {
+        return refused.error_response();
+    }
+    let offsets: Vec<_> = request
+        .offsets
+        .iter()
+        .map(|o| (o.topic.clone(), o.partition, o.offset))
+        .collect();
+
+    let result = streaming
+        .add_offsets_to_txn(&transactional_id, request.producer, &request.group_id)
+        .and_then(|()| {
+            streaming.txn_offset_commit(
+                &transactional_id,
+                &request.group_id,
+                request.producer,
+                &offsets,
+            )
+        });
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct EndTxnRequest {
+    #[serde(flatten)]
+    producer: ProducerIdAndEpoch,
+    commit: bool,
+}
+
+async fn end_txn(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<EndTxnRequest>,
+) -> HttpResponse {
+    let transactional_id = path.into_inner();
+    let resource = format!("transactional_id:{transactional_id}");
+    if let Err(refused) = permit(&security, &caller, &resource, "write") {
+        return refused.error_response();
+    }
+
+    match streaming.end_txn(&transactional_id, request.producer, request.commit) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn join_group(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<lumadb_streaming::AssignedJoinRequest>,
+) -> HttpResponse {
+    let group_id = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("group:{group_id}"), "read") {
+        return refused.error_response();
+    }
+
+    match streaming
+        .join_assigned_group(&group_id, request.into_inner())
+        .await
+    {
+        Ok(membership) => HttpResponse::Ok().json(membership),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct HeartbeatRequest {
+    generation_id: i32,
+    group_instance_id: Option<String>,
+}
+
+/// Keep a member's session alive; a rebalance in progress means the member
+/// must join again
+async fn heartbeat(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+    request: web::Json<HeartbeatRequest>,
+) -> HttpResponse {
+    let (group_id, member_id) = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("group:{group_id}"), "read") {
+        return refused.error_response();
+    }
+    let Some(group) = streaming.find_consumer_group(&group_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Group {} not found", group_id),
+        }));
+    };
+
+    match group.heartbeat(
+        &member_id,
+        request.group_instance_id.as_deref(),
+        request.generation_id,
+    ) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn leave_group(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (group_id, member_id) = path.into_inner();
+    if let Err(refused) = permit(&security, &caller, &format!("group:{group_id}"), "read") {
+        return refused.error_response();
+    }
+    let result = match streaming.find_consumer_group(&group_id) {
+        Some(group) => group.leave(&member_id).map_err(|e| e.to_string()),
+        None => Err(format!("Group {group_id} not found")),
+    };
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": e,
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CommitOffsetsRequest {
+    /// Committing member; standalone consumers leave it empty
+    #[serde(default)]
+    member_id: String,
+    group_instance_id: Option<String>,
+    #[serde(default = "default_generation")]
+    generation_id: i32,
+    offsets: Vec<PartitionOffset>,
+}
+
+fn default_generation() -> i32 {
+    -1
+}
+
+async fn commit_group_offsets(
+    streaming: web::Data<Arc<StreamingEngine>>,
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<CommitOffsetsRequest>,
+) -> HttpResponse {
+    let group_id = path.into_inner();
+    let permitted =
+        permit(&security, &caller, &format!("group:{group_id}"), "read").and_then(|()| {
+            request
+                .offsets
+                .iter()
+                .try_for_each(|o| permit(&security, &caller, &format!("topic:{}", o.topic), "read"))
+        });
+    if let Err(refused) = permitted {
+        return refused.error_response();
+    }
+    let group = streaming.consumer_group(&group_id);
+    if let Err(e) = group.validate_commit(
+        &request.member_id,
+        request.group_instance_id.as_deref(),
+        request.generation_id,
+    ) {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": e.to_string(),
+        }));
+    }
+    let offsets: Vec<_> = request
+        .offsets
+        .iter()
+        .map(|o| (o.topic.clone(), o.partition, o.offset))
+        .collect();
+
+    match streaming.store_offsets(&group_id, &offsets) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+/// ACL bindings selected by the query parameters
+async fn describe_acls(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    filter: web::Query<AclBindingFilter>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "describe") {
+        return refused.error_response();
+    }
+
+    HttpResponse::Ok().json(security.describe_acls(&filter))
+}
+
+async fn create_acls(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    bindings: web::Json<Vec<AclBinding>>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter") {
+        return refused.error_response();
+    }
+
+    match security.create_acls(&caller.0, &bindings) {
+        Ok(()) => HttpResponse::Created().json(bindings.into_inner()),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+/// Remove the ACL bindings selected by the query parameters, returning them
+async fn delete_acls(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    filter: web::Query<AclBindingFilter>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter") {
+        return refused.error_response();
+    }
+
+    match security.delete_acls(&caller.0, &filter) {
+        Ok(deleted) => HttpResponse::Ok().json(deleted),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn list_roles(security: web::Data<Arc<SecurityManager>>, caller: Caller) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "describe") {
+        return refused.error_response();
+    }
+
+    HttpResponse::Ok().json(security.roles())
+}
+
+#[derive(serde::Deserialize)]
+struct PutRoleRequest {
+    permissions: Vec<Permission>,
+}
+
+/// Define or replace a role
+async fn put_role(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+    request: web::Json<PutRoleRequest>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter") {
+        return refused.error_response();
+    }
+    let role = Role {
+        name: path.into_inner(),
+        permissions: request.into_inner().permissions,
+    };
+
+    match security.put_role(&caller.0, role.clone()) {
+        Ok(()) => HttpResponse::Ok().json(role),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn delete_role(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter") {
+        return refused.error_response();
+    }
+
+    match security.delete_role(&caller.0, &path.into_inner()) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+/// SCRAM credentials of every user, without their secrets
+async fn list_scram_credentials(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "describe_configs") {
+        return refused.error_response();
+    }
+
+    HttpResponse::Ok().json(security.scram_credentials())
+}
+
+#[derive(serde::Deserialize)]
+struct PutScramCredentialRequest {
+    password: String,
+    #[serde(default = "default_scram_iterations")]
+    iterations: u32,
+}
+
+fn default_scram_iterations() -> u32 {
+    MIN_ITERATIONS
+}
+
+/// Set a user's password for a SCRAM mechanism
+async fn put_scram_credential(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+    request: web::Json<PutScramCredentialRequest>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter_configs") {
+        return refused.error_response();
+    }
+    let (user, mechanism) = path.into_inner();
+
+    let credential = mechanism.parse::<ScramMechanism>().and_then(|mechanism| {
+        let credential = ScramCredential::new(mechanism, &request.password, request.iterations)?;
+        Ok((mechanism, credential))
+    });
+    let result = credential.and_then(|(mechanism, credential)| {
+        let iterations = credential.iterations;
+        security.upsert_scram_credential(&caller.0, &user, mechanism, credential)?;
+        Ok((mechanism, iterations))
+    });
+    match result {
+        Ok((mechanism, iterations)) => HttpResponse::Ok().json(serde_json::json!({
+            "username": user,
+            "mechanism": mechanism,
+            "iterations": iterations,
+        })),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
+
+async fn delete_scram_credential(
+    security: web::Data<Arc<SecurityManager>>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Err(refused) = permit(&security, &caller, "cluster", "alter_configs") {
+        return refused.error_response();
+    }
+    let (user, mechanism) = path.into_inner();
+
+    let result = mechanism
+        .parse::<ScramMechanism>()
+        .and_then(|mechanism| security.delete_scram_credential(&caller.0, &user, mechanism));
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(Error::Auth(AuthError::UserNotFound(_))) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("no {} credential for user {}", mechanism, user),
+            }))
+        }
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string(),
+        })),
+    }
+}
@@ -0,0 +1,392 @@
+//! JSON subscription protocol
+//!
+//! Clients send:
+//! - `{"type": "subscribe", "id": "s1", "collection": "users", "filter": {...}}`
+//!   to watch document changes, or
+//!   `{"type": "subscribe", "id": "s2", "topic": "events", "offset": "earliest"}`
+//!   to watch topic records; either may carry the `resume_token` of the
+//!   last item processed to continue after it
+//! - `{"type": "ack", "id": "s1", "resume_token": "..."}` once everything
+//!   up to an item has been processed
+//! - `{"type": "unsubscribe", "id": "s1"}`
+//!
+//! The server answers with `subscribed`, `change`, `message`,
+//! `unsubscribed` and `error` messages. Each `change` and `message` carries
+//! a resume token. A subscription stops sending once `max_unacked` of its
+//! items are unacknowledged, and every subscription of a connection waits
+//! while the connection's send queue is full, so a slow client holds back
+//! only its own feeds.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use lumadb_common::error::{Error, Result};
+use lumadb_security::Session;
+use lumadb_streaming::producer::IsolationLevel;
+
+use super::WebSocketServer;
+use crate::feeds::{DocumentFeed, TopicFeed};
+
+/// Message from a client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        id: String,
+        collection: Option<String>,
+        filter: Option<serde_json::Value>,
+        topic: Option<String>,
+        offset: Option<String>,
+        isolation_level: Option<String>,
+        resume_token: Option<String>,
+    },
+    Ack {
+        id: String,
+        resume_token: String,
+    },
+    Unsubscribe {
+        id: String,
+    },
+}
+
+/// Message to a client
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed {
+        id: String,
+    },
+    Change {
+        id: String,
+        resume_token: String,
+        operation: &'static str,
+        collection: String,
+        document_id: String,
+        document: Option<serde_json::Value>,
+    },
+    Message {
+        id: String,
+        resume_token: String,
+        topic: String,
+        partition: i32,
+        offset: i64,
+        timestamp: i64,
+        key: Option<String>,
+        value: serde_json::Value,
+        headers: HashMap<String, String>,
+    },
+    Unsubscribed {
+        id: String,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn error(id: Option<&str>, message: impl Into<String>) -> Self {
+        Self::Error {
+            id: id.map(str::to_string),
+            message: message.into(),
+        }
+    }
+
+    fn into_frame(self) -> Message {
+        Message::Text(serde_json::to_string(&self).unwrap_or_default())
+    }
+}
+
+/// Close frame with `code` and `reason`
+pub(super) fn close(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code: code.into(),
+        reason: reason.to_string().into(),
+    }))
+}
+
+/// Items a subscription has sent and not had acknowledged
+struct AckWindow {
+    /// Items that may still be sent
+    credits: Semaphore,
+    /// Resume tokens of the unacknowledged items, oldest first
+    unacked: Mutex<VecDeque<String>>,
+}
+
+impl AckWindow {
+    fn new(size: usize) -> Self {
+        Self {
+            credits: Semaphore::new(size.max(1)),
+            unacked: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Wait until another item may be sent
+    async fn reserve(&self) {
+        if let Ok(permit) = self.credits.acquire().await {
+            permit.forget();
+        }
+    }
+
+    fn sent(&self, resume_token: String) {
+        self.unacked.lock().push_back(resume_token);
+    }
+
+    /// Acknowledge every item up to the one with `resume_token`
+    fn ack(&self, resume_token: &str) -> bool {
+        let mut unacked = self.unacked.lock();
+        let Some(index) = unacked.iter().position(|token| token == resume_token) else {
+            return false;
+        };
+        unacked.drain(..=index);
+        self.credits.add_permits(index + 1);
+        true
+    }
+}
+
+/// Source of a subscription's items
+enum Feed {
+    Documents(DocumentFeed),
+    Topic(TopicFeed),
+}
+
+impl Feed {
+    /// Wait for the next item, returning its resume token and message
+    async fn next(&mut self, id: &str) -> Result<(String, ServerMessage)> {
+        Ok(match self {
+            Self::Documents(feed) => {
+                let change = feed.next().await?;
+                (
+                    change.resume_token.clone(),
+                    ServerMessage::Change {
+                        id: id.to_string(),
+                        resume_token: change.resume_token,
+                        operation: change.operation.as_str(),
+                        collection: change.collection,
+                        document_id: change.id,
+                        document: change.document,
+                    },
+                )
+            }
+            Self::Topic(feed) => {
+                let message = feed.next().await?;
+                let record = message.record;
+                (
+                    message.resume_token.clone(),
+                    ServerMessage::Message {
+                        id: id.to_string(),
+                        resume_token: message.resume_token,
+                        topic: record.topic,
+                        partition: record.partition,
+                        offset: record.offset,
+                        timestamp: record.timestamp,
+                        key: record.key,
+                        value: record.value,
+                        headers: record.headers,
+                    },
+                )
+            }
+        })
+    }
+}
+
+struct Subscription {
+    window: Arc<AckWindow>,
+    task: JoinHandle<()>,
+}
+
+/// State of one connection speaking the JSON protocol
+pub(super) struct Connection<'a> {
+    server: &'a WebSocketServer,
+    session: Session,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+impl<'a> Connection<'a> {
+    pub(super) fn new(server: &'a WebSocketServer, session: Session) -> Self {
+        Self {
+            server,
+            session,
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    /// Serve the connection until the client goes away
+    pub(super) async fn serve(mut self, ws: WebSocketStream<TcpStream>) {
+        let (mut sink, mut stream) = ws.split();
+        let (tx, mut rx) = mpsc::channel::<Message>(self.server.config.send_queue_size.max(1));
+        let writer = tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        while let Some(Ok(message)) = stream.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(message) => self.handle(message, &tx),
+                Err(e) => Some(ServerMessage::error(None, format!("invalid message: {e}"))),
+            };
+            if let Some(reply) = reply {
+                if tx.send(reply.into_frame()).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        for (_, subscription) in self.subscriptions.drain() {
+            subscription.task.abort();
+        }
+        drop(tx);
+        let _ = writer.await;
+    }
+
+    /// Apply a client message, returning the reply to send
+    fn handle(
+        &mut self,
+        message: ClientMessage,
+        tx: &mpsc::Sender<Message>,
+    ) -> Option<ServerMessage> {
+        match message {
+            ClientMessage::Subscribe {
+                id,
+                collection,
+                filter,
+                topic,
+                offset,
+                isolation_level,
+                resume_token,
+            } => {
+                if self.subscriptions.contains_key(&id) {
+                    return Some(ServerMessage::error(
+                        Some(&id),
+                        "subscription id already in use",
+                    ));
+                }
+                let feed = match (collection, topic) {
+                    (Some(collection), None) => {
+                        self.document_feed(collection, filter, resume_token.as_deref())
+                    }
+                    (None, Some(topic)) => self.topic_feed(
+                        topic,
+                        offset.as_deref(),
+                        isolation_level,
+                        resume_token.as_deref(),
+                    ),
+                    _ => Err(Error::Serialization(
+                        "subscribe needs exactly one of collection and topic".to_string(),
+                    )),
+                };
+                let feed = match feed {
+                    Ok(feed) => feed,
+                    Err(e) => return Some(ServerMessage::error(Some(&id), e.to_string())),
+                };
+
+                let window = Arc::new(AckWindow::new(self.server.config.max_unacked));
+                // Confirm before the task can send its first item
+                let subscribed = ServerMessage::Subscribed { id: id.clone() }.into_frame();
+                if tx.try_send(subscribed).is_err() {
+                    return Some(ServerMessage::error(Some(&id), "send queue is full"));
+                }
+                let task = tokio::spawn(deliver(id.clone(), feed, window.clone(), tx.clone()));
+                self.subscriptions.insert(id, Subscription { window, task });
+                None
+            }
+            ClientMessage::Ack { id, resume_token } => match self.subscriptions.get(&id) {
+                Some(subscription) if subscription.window.ack(&resume_token) => None,
+                Some(_) => Some(ServerMessage::error(
+                    Some(&id),
+                    "resume token was not sent or already acknowledged",
+                )),
+                None => Some(ServerMessage::error(Some(&id), "unknown subscription")),
+            },
+            ClientMessage::Unsubscribe { id } => match self.subscriptions.remove(&id) {
+                Some(subscription) => {
+                    subscription.task.abort();
+                    Some(ServerMessage::Unsubscribed { id })
+                }
+                None => Some(ServerMessage::error(Some(&id), "unknown subscription")),
+            },
+        }
+    }
+
+    fn document_feed(
+        &self,
+        collection: String,
+        filter: Option<serde_json::Value>,
+        resume_token: Option<&str>,
+    ) -> Result<Feed> {
+        let security = &self.server.security;
+        security.authorize(&self.session, &format!("collection:{collection}"), "read")?;
+        DocumentFeed::new(
+            self.server.query.clone(),
+            &self.server.changes,
+            collection,
+            filter,
+            resume_token,
+        )
+        .map(Feed::Documents)
+    }
+
+    fn topic_feed(
+        &self,
+        topic: String,
+        offset: Option<&str>,
+        isolation_level: Option<String>,
+        resume_token: Option<&str>,
+    ) -> Result<Feed> {
+        let security = &self.server.security;
+        security.authorize(&self.session, &format!("topic:{topic}"), "read")?;
+        let isolation = match isolation_level {
+            Some(level) => level.parse().map_err(Error::Serialization)?,
+            None => IsolationLevel::default(),
+        };
+        TopicFeed::new(
+            self.server.streaming.clone(),
+            topic,
+            offset,
+            resume_token,
+            isolation,
+        )
+        .map(Feed::Topic)
+    }
+}
+
+/// Send a subscription's items as the window and send queue allow
+///
+/// A feed error, such as an expired resume token, is reported and ends
+/// the subscription.
+async fn deliver(id: String, mut feed: Feed, window: Arc<AckWindow>, tx: mpsc::Sender<Message>) {
+    loop {
+        window.reserve().await;
+        let message = match feed.next(&id).await {
+            Ok((resume_token, message)) => {
+                window.sent(resume_token);
+                message
+            }
+            Err(e) => ServerMessage::error(Some(&id), e.to_string()),
+        };
+        let failed = matches!(message, ServerMessage::Error { .. });
+        if tx.send(message.into_frame()).await.is_err() || failed {
+            return;
+        }
+    }
+}
@@ -1,19 +1,284 @@
-//! WebSocket API for real-time updates (stub)
+//! WebSocket API for live queries and change feeds
+//!
+//! One port serves two endpoints:
+//! - `/graphql` runs GraphQL subscriptions over the `graphql-transport-ws`
+//!   and legacy `graphql-ws` subprotocols
+//! - every other path speaks the JSON protocol of [`connection`], in which
+//!   clients subscribe to document changes or topic records and
+//!   acknowledge what they processed
+//!
+//! Clients authenticate with an `Authorization: Bearer <token>` header or
+//! a `token` query parameter on the upgrade request; GraphQL clients may
+//! instead send the token in their `connection_init` payload.
 
-use lumadb_common::error::Result;
+mod connection;
 
-/// WebSocket server (stub)
+use std::sync::Arc;
+
+use async_graphql::http::{WebSocket as GraphQLWebSocket, WebSocketProtocols, WsMessage};
+use async_graphql::Data;
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Notify, Semaphore};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info, warn};
+
+use lumadb_common::config::WebSocketApiConfig;
+use lumadb_common::error::{AuthError, Error, Result};
+use lumadb_query::QueryEngine;
+use lumadb_storage::ChangeStream;
+use lumadb_streaming::StreamingEngine;
+use lumadb_security::{Credentials, SecurityManager, Session};
+
+use crate::graphql::{GraphQLServer, LumaSchema};
+
+/// Path of the GraphQL subscription endpoint
+const GRAPHQL_PATH: &str = "/graphql";
+
+/// WebSocket server
+#[derive(Clone)]
 pub struct WebSocketServer {
-    port: u16,
+    config: WebSocketApiConfig,
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+    security: Arc<SecurityManager>,
+    changes: Arc<ChangeStream>,
+    schema: LumaSchema,
+    /// Free connection slots
+    connections: Arc<Semaphore>,
+    shutdown: Arc<Notify>,
+}
+
+/// What the upgrade request of a connection asked for
+#[derive(Default)]
+struct Handshake {
+    /// GraphQL subprotocol, for connections to the GraphQL endpoint
+    graphql: Option<WebSocketProtocols>,
+    /// Bearer token from the `Authorization` header or `token` parameter
+    token: Option<String>,
 }
 
 impl WebSocketServer {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+    /// Create a new WebSocket server
+    #[allow(clippy::unused_async, clippy::missing_errors_doc)] // like the other API servers
+    pub async fn new(
+        config: &WebSocketApiConfig,
+        graphql: &GraphQLServer,
+        query: Arc<QueryEngine>,
+        streaming: Arc<StreamingEngine>,
+        security: Arc<SecurityManager>,
+        changes: Arc<ChangeStream>,
+    ) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            query,
+            streaming,
+            security,
+            changes,
+            schema: graphql.schema(),
+            connections: Arc::new(Semaphore::new(config.max_connections)),
+            shutdown: Arc::new(Notify::new()),
+        })
     }
 
+    /// Run the WebSocket server until it is shut down
     pub async fn run(&self) -> Result<()> {
-        // WebSocket implementation would go here
+        if !self.config.enabled {
+            info!("WebSocket API disabled");
+            return Ok(());
+        }
+
+        let listener = TcpListener::bind(("0.0.0.0", self.config.port)).await?;
+        info!("WebSocket API listening on port {}", self.config.port);
+
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                () = self.shutdown.notified() => break,
+            };
+            let Ok(slot) = self.connections.clone().try_acquire_owned() else {
+                warn!(
+                    "Rejecting WebSocket connection from {}: too many connections",
+                    peer
+                );
+                continue;
+            };
+
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(stream).await {
+                    debug!("WebSocket connection from {} failed: {}", peer, e);
+                }
+                drop(slot);
+            });
+        }
+        Ok(())
+    }
+
+    /// Shutdown the server
+    #[allow(clippy::unused_async, clippy::missing_errors_doc)] // like the other API servers
+    pub async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down WebSocket API server");
+        self.shutdown.notify_one();
         Ok(())
     }
+
+    // The handshake callback's error type is set by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn serve(&self, stream: TcpStream) -> Result<()> {
+        let mut handshake = Handshake::default();
+        let ws = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+            handshake = Handshake::read(request);
+            handshake.respond(request, response)
+        })
+        .await
+        .map_err(|e| Error::Internal(e.to_string()))?;
+
+        if let Some(protocol) = handshake.graphql {
+            self.serve_graphql(ws, protocol, handshake.token).await;
+            return Ok(());
+        }
+        let session = match authenticate(&self.security, handshake.token.as_deref()).await {
+            Ok(session) => session,
+            Err(e) => {
+                let mut ws = ws;
+                let _ = ws.send(connection::close(1008, &e.to_string())).await;
+                return Err(e);
+            }
+        };
+        connection::Connection::new(self, session).serve(ws).await;
+        Ok(())
+    }
+
+    /// Bridge a connection to the GraphQL schema's subscriptions
+    async fn serve_graphql(
+        &self,
+        ws: WebSocketStream<TcpStream>,
+        protocol: WebSocketProtocols,
+        token: Option<String>,
+    ) {
+        let (mut sink, stream) = ws.split();
+        let input = stream
+            .take_while(|message| futures::future::ready(message.is_ok()))
+            .filter_map(|message| {
+                futures::future::ready(match message {
+                    Ok(Message::Text(text)) => Some(text.into_bytes()),
+                    Ok(Message::Binary(bytes)) => Some(bytes),
+                    _ => None,
+                })
+            });
+
+        let security = self.security.clone();
+        let mut output = GraphQLWebSocket::new(self.schema.clone(), input, protocol)
+            .on_connection_init(move |payload| async move {
+                // A token in the init payload takes precedence over one
+                // from the upgrade request
+                let token = payload
+                    .get("authorization")
+                    .or_else(|| payload.get("Authorization"))
+                    .and_then(|v| v.as_str())
+                    .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).to_string())
+                    .or_else(|| {
+                        payload
+                            .get("token")
+                            .and_then(|v| v.as_str())
+                            .map(str::to_string)
+                    })
+                    .or(token);
+                let session = authenticate(&security, token.as_deref())
+                    .await
+                    .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+                let mut data = Data::default();
+                data.insert(session);
+                Ok(data)
+            });
+
+        while let Some(message) = output.next().await {
+            let message = match message {
+                WsMessage::Text(text) => Message::Text(text),
+                WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                    code: code.into(),
+                    reason: reason.into(),
+                })),
+            };
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Handshake {
+    fn read(request: &Request) -> Self {
+        let token = request
+            .headers()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::to_string)
+            .or_else(|| {
+                request.uri().query().and_then(|query| {
+                    query
+                        .split('&')
+                        .find_map(|param| param.strip_prefix("token="))
+                        .map(str::to_string)
+                })
+            });
+
+        let graphql = (request.uri().path() == GRAPHQL_PATH).then(|| {
+            request
+                .headers()
+                .get_all("sec-websocket-protocol")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .find_map(|protocol| protocol.trim().parse().ok())
+        });
+
+        Self {
+            // Subprotocol negotiation failures are reported by `respond`
+            graphql: graphql.flatten(),
+            token,
+        }
+    }
+
+    /// Accept the upgrade, naming the negotiated GraphQL subprotocol
+    #[allow(clippy::result_large_err)] // the callback type of `accept_hdr_async`
+    fn respond(
+        &self,
+        request: &Request,
+        mut response: Response,
+    ) -> std::result::Result<Response, ErrorResponse> {
+        if request.uri().path() != GRAPHQL_PATH {
+            return Ok(response);
+        }
+        let Some(protocol) = self.graphql else {
+            let mut error = ErrorResponse::new(Some(
+                "expected the graphql-transport-ws or graphql-ws subprotocol".to_string(),
+            ));
+            *error.status_mut() = StatusCode::BAD_REQUEST;
+            return Err(error);
+        };
+        response.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static(protocol.sec_websocket_protocol()),
+        );
+        Ok(response)
+    }
+}
+
+/// Session of the caller presenting `token`
+async fn authenticate(security: &SecurityManager, token: Option<&str>) -> Result<Session> {
+    if token.is_none() && security.auth_enabled() {
+        return Err(Error::Auth(AuthError::AuthenticationFailed(
+            "missing bearer token".to_string(),
+        )));
+    }
+    security
+        .authenticate(&Credentials::Token(token.unwrap_or_default().to_string()))
+        .await
 }
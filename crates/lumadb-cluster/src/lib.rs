@@ -12,16 +12,19 @@
 pub mod sharding;
 
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use dashmap::DashMap;
 use parking_lot::RwLock;
-use tracing::{info, debug};
+use tracing::{info, debug, warn};
 
 use lumadb_common::config::ClusterConfig;
-use lumadb_common::error::Result;
+use lumadb_common::error::{ClusterError, Result};
 use lumadb_common::types::{NodeId, NodeInfo, NodeStatus, ClusterStatus};
-use lumadb_raft::RaftEngine;
+use lumadb_raft::{
+    DocumentFilter, RaftEngine, RaftGroup, StateMachine, StateMachineFactory, StorageCommand,
+    StorageStateMachine, DEFAULT_GROUP,
+};
 
 pub use sharding::{AutoShardingEngine, ShardingConfig, ShardingStats, Shard, ShardStatus};
 
@@ -29,6 +32,7 @@ pub use sharding::{AutoShardingEngine, ShardingConfig, ShardingStats, Shard, Sha
 pub struct ClusterManager {
     config: ClusterConfig,
     raft: Arc<RaftEngine>,
+    sharding: Arc<AutoShardingEngine>,
     nodes: DashMap<NodeId, NodeInfo>,
     partitions: DashMap<String, PartitionAssignment>,
     running: Arc<RwLock<bool>>,
@@ -45,32 +49,131 @@ struct PartitionAssignment {
 impl ClusterManager {
     /// Create a new cluster manager
     pub async fn new(config: &ClusterConfig, raft: Arc<RaftEngine>) -> Result<Self> {
+        Self::with_sharding(config, raft, ShardingConfig::default()).await
+    }
+
+    /// Create a cluster manager with explicit sharding settings
+    ///
+    /// Every shard is replicated by its own Raft group, created on first
+    /// use. Collection metadata goes through the default group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Raft engine cannot be created.
+    pub async fn with_sharding(
+        config: &ClusterConfig,
+        raft: Arc<RaftEngine>,
+        sharding: ShardingConfig,
+    ) -> Result<Self> {
         info!("Initializing cluster manager: {}", config.name);
 
+        let sharding = Arc::new(AutoShardingEngine::new(sharding));
+        raft.set_state_machine_factory(shard_state_machines(&raft, &sharding));
+
         let manager = Self {
             config: config.clone(),
             raft,
+            sharding,
             nodes: DashMap::new(),
             partitions: DashMap::new(),
             running: Arc::new(RwLock::new(true)),
         };
 
-        // Register self as first node
-        let self_node = NodeInfo {
-            id: 1,
-            address: "localhost:8080".to_string(),
-            status: NodeStatus::Online,
-            is_leader: true,
-            last_heartbeat: chrono::Utc::now().timestamp_millis(),
-            metadata: HashMap::new(),
-        };
-        manager.nodes.insert(1, self_node);
+        // Register the Raft voters, starting with this node
+        let self_id = manager.raft.node_id();
+        for &id in manager.raft.default_voters() {
+            let node = NodeInfo {
+                id,
+                address: if id == self_id {
+                    "localhost:8080".to_string()
+                } else {
+                    String::new()
+                },
+                status: NodeStatus::Online,
+                is_leader: false,
+                last_heartbeat: chrono::Utc::now().timestamp_millis(),
+                metadata: HashMap::new(),
+            };
+            manager.nodes.insert(id, node);
+            manager.sharding.add_node(id);
+        }
+
+        // Metadata group; single-node clusters elect themselves immediately
+        manager.raft.get_or_create_group(DEFAULT_GROUP).await?;
 
         Ok(manager)
     }
 
+    /// Sharding engine routing keys to shards
+    #[must_use]
+    pub fn sharding(&self) -> &Arc<AutoShardingEngine> {
+        &self.sharding
+    }
+
+    /// Raft group replicating a shard, started on first use with the
+    /// shard's replicas as founding voters
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shard does not exist or its group cannot be
+    /// started.
+    pub async fn shard_group(&self, shard_id: u32) -> Result<Arc<RaftGroup>> {
+        let group_id = self
+            .sharding
+            .raft_group_for_shard(shard_id)
+            .ok_or_else(|| ClusterError::PartitionUnavailable(format!("shard {shard_id}")))?;
+        if let Some(group) = self.raft.group(group_id) {
+            return Ok(group);
+        }
+
+        let replicas = self
+            .sharding
+            .get_shard(shard_id)
+            .map(|s| s.replicas)
+            .unwrap_or_default();
+        let voters: BTreeSet<NodeId> = if replicas.is_empty() {
+            self.raft.default_voters().clone()
+        } else {
+            replicas.into_iter().collect()
+        };
+        let state_machine = shard_state_machines(&self.raft, &self.sharding)(group_id);
+        self.raft
+            .create_group(group_id, voters, state_machine)
+            .await
+    }
+
+    /// Replicate a write through the Raft group owning it: documents go to
+    /// their shard's group, collection changes to the default group
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command cannot be encoded or is not committed.
+    pub async fn replicate(&self, command: &StorageCommand) -> Result<Vec<u8>> {
+        let key = match command {
+            StorageCommand::Insert { document, .. } => Some(document.id.as_str()),
+            StorageCommand::Delete { id, .. } => Some(id.as_str()),
+            StorageCommand::CreateCollection { .. } | StorageCommand::DeleteCollection { .. } => {
+                None
+            }
+        };
+        let group = match key {
+            Some(key) => {
+                self.shard_group(self.sharding.get_shard_for_key(key))
+                    .await?
+            }
+            None => self.raft.get_or_create_group(DEFAULT_GROUP).await?,
+        };
+        group.propose(command.encode()?).await
+    }
+
     /// Get cluster status
     pub fn status(&self) -> ClusterStatus {
+        // Leadership comes from the default Raft group
+        let leader = self.raft.group(DEFAULT_GROUP).and_then(|g| g.leader());
+        for mut node in self.nodes.iter_mut() {
+            node.is_leader = Some(node.id) == leader;
+        }
+
         let nodes: Vec<NodeInfo> = self.nodes.iter().map(|e| e.value().clone()).collect();
         let healthy = nodes.iter().filter(|n| n.status == NodeStatus::Online).count();
 
@@ -98,6 +201,7 @@ impl ClusterManager {
         };
 
         self.nodes.insert(node_id, node);
+        self.sharding.add_node(node_id);
         info!("Added node {} at {}", node_id, address);
 
         Ok(node_id)
@@ -109,7 +213,8 @@ impl ClusterManager {
             node.status = NodeStatus::Leaving;
         }
 
-        // Reassign partitions
+        // Reassign partitions and shard replicas
+        self.sharding.remove_node(node_id);
         self.rebalance().await?;
 
         self.nodes.remove(&node_id);
@@ -146,9 +251,41 @@ impl ClusterManager {
             }
         }
 
+        self.reconfigure_shard_groups().await;
+
         Ok(())
     }
 
+    /// Move the Raft groups this node leads onto their shard's current
+    /// replica set
+    async fn reconfigure_shard_groups(&self) {
+        for group_id in self.raft.group_ids() {
+            let Some(shard_id) = self.sharding.shard_for_raft_group(group_id) else {
+                continue;
+            };
+            let Some(group) = self.raft.group(group_id) else {
+                continue;
+            };
+            self.sharding.set_shard_leader(shard_id, group.leader());
+            if !group.is_leader() {
+                continue;
+            }
+
+            let replicas: BTreeSet<NodeId> = self
+                .sharding
+                .get_shard(shard_id)
+                .map(|s| s.replicas.into_iter().collect())
+                .unwrap_or_default();
+            if replicas.is_empty() || group.membership().voters == replicas {
+                continue;
+            }
+            debug!("Moving shard {} to replicas {:?}", shard_id, replicas);
+            if let Err(e) = group.change_membership(replicas).await {
+                warn!("Failed to reconfigure shard {}: {}", shard_id, e);
+            }
+        }
+    }
+
     /// Get nodes list
     pub fn nodes(&self) -> Vec<NodeInfo> {
         self.nodes.iter().map(|e| e.value().clone()).collect()
@@ -162,3 +299,26 @@ impl ClusterManager {
         }
     }
 }
+
+/// State machines for groups hosted by this node: shard groups own the
+/// documents hashed to their shard, the default group owns collection
+/// metadata only
+fn shard_state_machines(
+    raft: &RaftEngine,
+    sharding: &Arc<AutoShardingEngine>,
+) -> StateMachineFactory {
+    let storage = raft.storage().clone();
+    let sharding = sharding.clone();
+    Arc::new(move |group_id| -> Arc<dyn StateMachine> {
+        let filter: DocumentFilter = match sharding.shard_for_raft_group(group_id) {
+            Some(shard_id) => {
+                let sharding = sharding.clone();
+                Arc::new(move |_collection: &str, id: &str| {
+                    sharding.get_shard_for_key(id) == shard_id
+                })
+            }
+            None => Arc::new(|_: &str, _: &str| false),
+        };
+        Arc::new(StorageStateMachine::with_filter(storage.clone(), filter))
+    })
+}
@@ -22,6 +22,9 @@ use lumadb_common::types::NodeId;
 /// Number of virtual nodes per physical node for consistent hashing
 const VIRTUAL_NODES_PER_NODE: u32 = 150;
 
+/// Raft group of shard 0; group 0 is the cluster's default group
+const FIRST_SHARD_RAFT_GROUP: u64 = 1;
+
 /// Configuration for auto-sharding
 #[derive(Debug, Clone)]
 pub struct ShardingConfig {
@@ -163,6 +166,7 @@ impl AutoShardingEngine {
 
     fn initialize_shards(&self) {
         let mut shards = self.shards.write();
+        let mut groups = self.shard_to_raft_group.write();
         let range_size = u64::MAX / u64::from(self.config.num_shards);
 
         for i in 0..self.config.num_shards {
@@ -180,6 +184,7 @@ impl AutoShardingEngine {
                 status: ShardStatus::Active,
             };
             shards.insert(i, shard);
+            groups.insert(i, FIRST_SHARD_RAFT_GROUP + u64::from(i));
         }
 
         info!("Initialized {} shards", self.config.num_shards);
@@ -210,6 +215,35 @@ impl AutoShardingEngine {
         shard_id
     }
 
+    /// Raft group replicating a shard
+    #[must_use]
+    pub fn raft_group_for_shard(&self, shard_id: u32) -> Option<u64> {
+        self.shard_to_raft_group.read().get(&shard_id).copied()
+    }
+
+    /// Shard replicated by a Raft group
+    #[must_use]
+    pub fn shard_for_raft_group(&self, group_id: u64) -> Option<u32> {
+        self.shard_to_raft_group
+            .read()
+            .iter()
+            .find(|(_, &g)| g == group_id)
+            .map(|(&s, _)| s)
+    }
+
+    /// Get a shard by ID
+    #[must_use]
+    pub fn get_shard(&self, shard_id: u32) -> Option<Shard> {
+        self.shards.read().get(&shard_id).cloned()
+    }
+
+    /// Record the leader elected by a shard's Raft group
+    pub fn set_shard_leader(&self, shard_id: u32, leader: Option<NodeId>) {
+        if let Some(shard) = self.shards.write().get_mut(&shard_id) {
+            shard.leader = leader;
+        }
+    }
+
     /// Get the node responsible for a key
     pub fn route_key(&self, key: &str) -> Option<NodeId> {
         let shard_id = self.get_shard_for_key(key);
@@ -373,6 +407,20 @@ mod tests {
         assert_eq!(stats.total_shards, 64);
     }
 
+    #[test]
+    fn test_shard_raft_groups() {
+        let engine = AutoShardingEngine::new(ShardingConfig {
+            num_shards: 8,
+            ..Default::default()
+        });
+
+        let shard = engine.get_shard_for_key("key1");
+        let group = engine.raft_group_for_shard(shard).unwrap();
+        assert_ne!(group, 0);
+        assert_eq!(engine.shard_for_raft_group(group), Some(shard));
+        assert_eq!(engine.raft_group_for_shard(8), None);
+    }
+
     #[test]
     fn test_replication_nodes() {
         let mut ring = ConsistentHashRing::new();
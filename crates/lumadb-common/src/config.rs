@@ -112,6 +112,35 @@ impl Config {
                 "Storage max_memory_bytes must be at least 64MB".to_string(),
             ));
         }
+        if !matches!(
+            self.storage.wal_sync_mode.as_str(),
+            "fsync" | "group" | "interval" | "none"
+        ) {
+            return Err(Error::Config(format!(
+                "Storage wal_sync_mode must be one of fsync, group, interval, none (got {})",
+                self.storage.wal_sync_mode
+            )));
+        }
+        if self.storage.wal_sync_mode == "interval" && self.storage.wal_sync_interval_ms == 0 {
+            return Err(Error::Config(
+                "Storage wal_sync_interval_ms must be greater than 0 in interval mode".to_string(),
+            ));
+        }
+
+        if self.storage.encryption.enabled {
+            if self.storage.encryption.key_provider != "local" {
+                return Err(Error::Config(format!(
+                    "Storage encryption key_provider must be local (got {})",
+                    self.storage.encryption.key_provider
+                )));
+            }
+            if self.storage.encryption.key_file.is_empty() {
+                return Err(Error::Config(
+                    "Storage encryption key_file is required with the local key provider"
+                        .to_string(),
+                ));
+            }
+        }
 
         // Validate streaming config
         if self.streaming.default_partitions == 0 {
@@ -160,6 +189,16 @@ impl Config {
                 "Raft heartbeat_interval_ms must be less than election_timeout_min_ms".to_string(),
             ));
         }
+        for peer in &self.raft.peers {
+            let valid = peer
+                .split_once('=')
+                .is_some_and(|(id, addr)| id.parse::<u64>().is_ok() && !addr.is_empty());
+            if !valid {
+                return Err(Error::Config(format!(
+                    "Raft peer '{peer}' must be formatted as node_id=host:port"
+                )));
+            }
+        }
 
         // Validate security config
         if self.security.auth_enabled && self.security.auth_method == "jwt" {
@@ -175,6 +214,26 @@ impl Config {
             }
         }
 
+        if self.security.audit_enabled {
+            let audit = &self.security.audit;
+            if audit.max_file_bytes < 1024 {
+                return Err(Error::Config(
+                    "Audit max_file_bytes must be at least 1KB".to_string(),
+                ));
+            }
+            if let Some(category) = audit
+                .categories
+                .iter()
+                .find(|c| !AUDIT_CATEGORIES.contains(&c.as_str()))
+            {
+                return Err(Error::Config(format!(
+                    "Audit category '{}' must be one of {}",
+                    category,
+                    AUDIT_CATEGORIES.join(", ")
+                )));
+            }
+        }
+
         if self.security.tls_enabled {
             if self.security.tls_cert_path.is_none() {
                 return Err(Error::Config(
@@ -223,14 +282,33 @@ pub struct StorageConfig {
     pub max_memory_bytes: usize,
     /// Enable write-ahead logging
     pub wal_enabled: bool,
-    /// WAL sync mode
+    /// WAL sync mode: "fsync" (every write), "group" (group commit),
+    /// "interval" (every `wal_sync_interval_ms`) or "none"
     pub wal_sync_mode: String,
+    /// WAL fsync interval in milliseconds when `wal_sync_mode` is "interval"
+    #[serde(default = "default_wal_sync_interval_ms")]
+    pub wal_sync_interval_ms: u64,
     /// Compaction interval in seconds
     pub compaction_interval_secs: u64,
     /// Enable compression
     pub compression_enabled: bool,
     /// Compression algorithm
     pub compression_algorithm: String,
+    /// Number of recent document changes kept for change feed subscribers
+    /// to resume from
+    #[serde(default = "default_change_stream_capacity")]
+    pub change_stream_capacity: usize,
+    /// Encryption of the WAL, `SSTables` and stream segments
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+}
+
+fn default_wal_sync_interval_ms() -> u64 {
+    100
+}
+
+fn default_change_stream_capacity() -> usize {
+    65_536
 }
 
 impl Default for StorageConfig {
@@ -240,9 +318,39 @@ impl Default for StorageConfig {
             max_memory_bytes: 4 * 1024 * 1024 * 1024, // 4GB
             wal_enabled: true,
             wal_sync_mode: "fsync".to_string(),
+            wal_sync_interval_ms: default_wal_sync_interval_ms(),
             compaction_interval_secs: 3600,
             compression_enabled: true,
             compression_algorithm: "lz4".to_string(),
+            change_stream_capacity: default_change_stream_capacity(),
+            encryption: EncryptionConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Encrypt data written to the WAL, `SSTables` and stream segments
+    pub enabled: bool,
+    /// Provider wrapping the data keys: "local" (master keys read from
+    /// `key_file`)
+    pub key_provider: String,
+    /// File holding the master keys of the local provider, created with a
+    /// fresh key when missing
+    pub key_file: String,
+    /// Replay WAL records written before encryption was enabled; otherwise
+    /// they are rejected, as they may have been planted to downgrade the log
+    #[serde(default)]
+    pub migrate_plaintext_wal: bool,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_provider: "local".to_string(),
+            key_file: "/var/lib/lumadb/keys/master.keys".to_string(),
+            migrate_plaintext_wal: false,
         }
     }
 }
@@ -257,12 +365,86 @@ pub struct StreamingConfig {
     pub segment_size_bytes: usize,
     /// Retention time in milliseconds
     pub retention_ms: u64,
+    /// Retention size per partition in bytes (unlimited when unset)
+    #[serde(default)]
+    pub retention_bytes: Option<u64>,
+    /// Maximum age of the active segment before it is rolled, in milliseconds
+    #[serde(default = "default_segment_ms")]
+    pub segment_ms: u64,
+    /// Bytes of log data between sparse index entries
+    #[serde(default = "default_index_interval_bytes")]
+    pub index_interval_bytes: usize,
+    /// Interval between retention checks in milliseconds
+    #[serde(default = "default_retention_check_interval_ms")]
+    pub retention_check_interval_ms: u64,
+    /// How long tombstones are kept on compacted topics, in milliseconds
+    #[serde(default = "default_delete_retention_ms")]
+    pub delete_retention_ms: u64,
+    /// Interval between log cleaner passes on compacted topics, in milliseconds
+    #[serde(default = "default_cleaner_interval_ms")]
+    pub cleaner_interval_ms: u64,
+    /// Largest transaction timeout a producer may request, in milliseconds
+    #[serde(default = "default_transaction_max_timeout_ms")]
+    pub transaction_max_timeout_ms: u64,
+    /// How long a partition keeps state for a producer that stopped
+    /// writing, in milliseconds
+    #[serde(default = "default_producer_id_expiration_ms")]
+    pub producer_id_expiration_ms: u64,
+    /// Smallest session timeout a group member may request, in milliseconds
+    #[serde(default = "default_group_min_session_timeout_ms")]
+    pub group_min_session_timeout_ms: u64,
+    /// Largest session timeout a group member may request, in milliseconds
+    #[serde(default = "default_group_max_session_timeout_ms")]
+    pub group_max_session_timeout_ms: u64,
+    /// Partitions of the internal topic holding group offsets and metadata
+    #[serde(default = "default_offsets_topic_partitions")]
+    pub offsets_topic_partitions: u32,
     /// Enable thread-per-core architecture
     pub thread_per_core: bool,
     /// Batch size for I/O operations
     pub batch_size: usize,
 }
 
+fn default_segment_ms() -> u64 {
+    7 * 24 * 60 * 60 * 1000 // 7 days
+}
+
+fn default_index_interval_bytes() -> usize {
+    4096
+}
+
+fn default_retention_check_interval_ms() -> u64 {
+    5 * 60 * 1000 // 5 minutes
+}
+
+fn default_delete_retention_ms() -> u64 {
+    24 * 60 * 60 * 1000 // 1 day
+}
+
+fn default_cleaner_interval_ms() -> u64 {
+    15 * 1000
+}
+
+fn default_transaction_max_timeout_ms() -> u64 {
+    15 * 60 * 1000 // 15 minutes
+}
+
+fn default_producer_id_expiration_ms() -> u64 {
+    24 * 60 * 60 * 1000 // 1 day
+}
+
+fn default_group_min_session_timeout_ms() -> u64 {
+    6 * 1000
+}
+
+fn default_group_max_session_timeout_ms() -> u64 {
+    30 * 60 * 1000 // 30 minutes
+}
+
+fn default_offsets_topic_partitions() -> u32 {
+    1
+}
+
 impl Default for StreamingConfig {
     fn default() -> Self {
         Self {
@@ -270,6 +452,17 @@ impl Default for StreamingConfig {
             default_replication_factor: 1,
             segment_size_bytes: 1024 * 1024 * 1024, // 1GB
             retention_ms: 7 * 24 * 60 * 60 * 1000,  // 7 days
+            retention_bytes: None,
+            segment_ms: default_segment_ms(),
+            index_interval_bytes: default_index_interval_bytes(),
+            retention_check_interval_ms: default_retention_check_interval_ms(),
+            delete_retention_ms: default_delete_retention_ms(),
+            cleaner_interval_ms: default_cleaner_interval_ms(),
+            transaction_max_timeout_ms: default_transaction_max_timeout_ms(),
+            producer_id_expiration_ms: default_producer_id_expiration_ms(),
+            group_min_session_timeout_ms: default_group_min_session_timeout_ms(),
+            group_max_session_timeout_ms: default_group_max_session_timeout_ms(),
+            offsets_topic_partitions: default_offsets_topic_partitions(),
             thread_per_core: true,
             batch_size: 16384,
         }
@@ -288,6 +481,20 @@ pub struct QueryConfig {
     pub vectorized_execution: bool,
     /// Default batch size for vectorized execution
     pub batch_size: usize,
+    /// Memory a sort may use before it spills sorted runs to disk
+    #[serde(default = "default_sort_memory_bytes")]
+    pub sort_memory_bytes: usize,
+    /// How long a transaction may go unused before it is rolled back
+    #[serde(default = "default_transaction_idle_timeout_ms")]
+    pub transaction_idle_timeout_ms: u64,
+}
+
+fn default_sort_memory_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_transaction_idle_timeout_ms() -> u64 {
+    300_000
 }
 
 impl Default for QueryConfig {
@@ -298,6 +505,8 @@ impl Default for QueryConfig {
             cache_size: 10000,
             vectorized_execution: true,
             batch_size: 8192,
+            sort_memory_bytes: default_sort_memory_bytes(),
+            transaction_idle_timeout_ms: default_transaction_idle_timeout_ms(),
         }
     }
 }
@@ -313,6 +522,9 @@ pub struct ApiConfig {
     /// gRPC API configuration
     #[serde(default)]
     pub grpc: GrpcApiConfig,
+    /// WebSocket API configuration
+    #[serde(default)]
+    pub websocket: WebSocketApiConfig,
 }
 
 impl Default for ApiConfig {
@@ -321,6 +533,7 @@ impl Default for ApiConfig {
             rest: RestApiConfig::default(),
             graphql: GraphQLApiConfig::default(),
             grpc: GrpcApiConfig::default(),
+            websocket: WebSocketApiConfig::default(),
         }
     }
 }
@@ -393,6 +606,13 @@ pub struct GrpcApiConfig {
     pub tls_enabled: bool,
     /// Maximum message size in bytes
     pub max_message_size: usize,
+    /// Deadline for calls whose client sets none, in milliseconds
+    #[serde(default = "default_grpc_timeout_ms")]
+    pub request_timeout_ms: u64,
+}
+
+fn default_grpc_timeout_ms() -> u64 {
+    30_000
 }
 
 impl Default for GrpcApiConfig {
@@ -402,6 +622,35 @@ impl Default for GrpcApiConfig {
             port: 8082,
             tls_enabled: false,
             max_message_size: 16 * 1024 * 1024, // 16MB
+            request_timeout_ms: default_grpc_timeout_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketApiConfig {
+    /// Enable WebSocket API
+    pub enabled: bool,
+    /// Port number
+    pub port: u16,
+    /// Maximum concurrent connections
+    pub max_connections: usize,
+    /// Items a subscription may have sent but not acknowledged before
+    /// delivery pauses
+    pub max_unacked: usize,
+    /// Outgoing messages queued per connection before subscriptions wait
+    /// for the client to read
+    pub send_queue_size: usize,
+}
+
+impl Default for WebSocketApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 8083,
+            max_connections: 10_000,
+            max_unacked: 1000,
+            send_queue_size: 256,
         }
     }
 }
@@ -414,6 +663,13 @@ pub struct KafkaConfig {
     pub port: u16,
     /// Advertised listeners
     pub advertised_listeners: Vec<String>,
+    /// Create unknown topics when clients request their metadata
+    #[serde(default = "default_auto_create_topics")]
+    pub auto_create_topics: bool,
+}
+
+fn default_auto_create_topics() -> bool {
+    true
 }
 
 impl Default for KafkaConfig {
@@ -422,6 +678,7 @@ impl Default for KafkaConfig {
             enabled: true,
             port: 9092,
             advertised_listeners: vec!["localhost:9092".to_string()],
+            auto_create_topics: default_auto_create_topics(),
         }
     }
 }
@@ -438,6 +695,12 @@ pub struct RaftConfig {
     pub snapshot_interval: u64,
     /// Maximum log entries before snapshot
     pub max_log_entries: u64,
+    /// Address the Raft RPC listener binds to (multi-node only)
+    #[serde(default)]
+    pub listen_addr: Option<String>,
+    /// Peer nodes as `node_id=host:port`
+    #[serde(default)]
+    pub peers: Vec<String>,
 }
 
 impl Default for RaftConfig {
@@ -448,6 +711,8 @@ impl Default for RaftConfig {
             heartbeat_interval_ms: 50,
             snapshot_interval: 10000,
             max_log_entries: 100000,
+            listen_addr: None,
+            peers: vec![],
         }
     }
 }
@@ -495,6 +760,9 @@ pub struct SecurityConfig {
     pub tls_key_path: Option<String>,
     /// Enable audit logging
     pub audit_enabled: bool,
+    /// Audit trail settings, used when `audit_enabled` is set
+    #[serde(default)]
+    pub audit: AuditConfig,
 }
 
 fn default_jwt_secret() -> String {
@@ -530,6 +798,41 @@ impl Default for SecurityConfig {
             tls_cert_path: None,
             tls_key_path: None,
             audit_enabled: false,
+            audit: AuditConfig::default(),
+        }
+    }
+}
+
+/// Audit event categories
+pub const AUDIT_CATEGORIES: &[&str] = &["authentication", "authorization", "ddl", "admin", "dml"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Directory holding the audit trail files
+    pub path: String,
+    /// Size at which the current file is closed and a new one started
+    pub max_file_bytes: u64,
+    /// Days closed files are kept; 0 keeps them forever
+    pub retention_days: u32,
+    /// Categories recorded, from `authentication`, `authorization`, `ddl`,
+    /// `admin` (user, role and ACL changes) and `dml`
+    pub categories: Vec<String>,
+    /// Internal topic every record is also produced to
+    #[serde(default)]
+    pub mirror_topic: Option<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: "/var/lib/lumadb/audit".to_string(),
+            max_file_bytes: 64 * 1024 * 1024, // 64MB
+            retention_days: 90,
+            categories: ["authentication", "authorization", "ddl", "admin"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            mirror_topic: None,
         }
     }
 }
@@ -558,6 +861,41 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_wal_sync_mode_validation() {
+        let mut config = Config::default();
+        config.storage.wal_sync_mode = "sometimes".to_string();
+        assert!(config.validate().is_err());
+
+        config.storage.wal_sync_mode = "interval".to_string();
+        config.storage.wal_sync_interval_ms = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_encryption_validation() {
+        let mut config = Config::default();
+        config.storage.encryption.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.storage.encryption.key_provider = "vault".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_audit_category_validation() {
+        let mut config = Config::default();
+        config.security.audit_enabled = true;
+        assert!(config.validate().is_ok());
+
+        config
+            .security
+            .audit
+            .categories
+            .push("everything".to_string());
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_streaming_partitions_validation() {
         let mut config = Config::default();
@@ -603,6 +941,15 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_raft_peer_validation() {
+        let mut config = Config::default();
+        config.raft.peers = vec!["2=10.0.0.2:9100".to_string()];
+        assert!(config.validate().is_ok());
+        config.raft.peers = vec!["10.0.0.2:9100".to_string()];
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_raft_heartbeat_validation() {
         let mut config = Config::default();
@@ -644,6 +991,7 @@ mod tests {
         assert_eq!(config.api.rest.port, 8080);
         assert_eq!(config.api.graphql.port, 8081);
         assert_eq!(config.api.grpc.port, 8082);
+        assert_eq!(config.api.websocket.port, 8083);
         assert_eq!(config.kafka.port, 9092);
         assert!(config.query.cache_enabled);
         assert!(config.storage.wal_enabled);
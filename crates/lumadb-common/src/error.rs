@@ -81,6 +81,12 @@ pub enum StorageError {
 
     #[error("WAL error: {0}")]
     WalError(String),
+
+    #[error("Change stream position expired: {0}")]
+    ChangeStreamExpired(String),
+
+    #[error("Encryption error: {0}")]
+    Encryption(String),
 }
 
 /// Query-related errors
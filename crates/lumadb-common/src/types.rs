@@ -44,6 +44,10 @@ pub struct Record {
     pub partition: Option<PartitionId>,
     /// Offset (assigned after produce)
     pub offset: Option<Offset>,
+    /// Idempotent or transactional producer that wrote the record
+    pub producer: Option<ProducerInfo>,
+    /// Transaction marker carried by a control record
+    pub control: Option<ControlRecord>,
 }
 
 impl Record {
@@ -55,6 +59,8 @@ impl Record {
             timestamp: chrono::Utc::now().timestamp_millis(),
             partition: None,
             offset: None,
+            producer: None,
+            control: None,
         }
     }
 
@@ -75,6 +81,43 @@ impl Record {
         self.timestamp = timestamp;
         self
     }
+
+    /// A keyed record with an empty value marks its key as deleted on
+    /// compacted topics
+    #[must_use]
+    pub fn is_tombstone(&self) -> bool {
+        self.key.is_some() && self.value.is_empty()
+    }
+
+    /// Whether the record is a transaction marker rather than user data
+    #[must_use]
+    pub fn is_control(&self) -> bool {
+        self.control.is_some()
+    }
+}
+
+/// Producer identity stamped on records written by idempotent producers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProducerInfo {
+    /// Producer id handed out by `InitProducerId`
+    pub producer_id: i64,
+    /// Producer epoch; a higher epoch fences older instances
+    pub producer_epoch: i16,
+    /// Sequence number within the producer's writes to the partition
+    /// (-1 for control records)
+    pub sequence: i32,
+    /// Whether the record was written inside a transaction
+    pub transactional: bool,
+}
+
+/// Transaction marker written to every partition a transaction touched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlRecord {
+    /// The transaction's records are visible to `read_committed` consumers
+    Commit,
+    /// The transaction's records are skipped by `read_committed` consumers
+    Abort,
 }
 
 /// Metadata about a produced record
@@ -109,6 +152,12 @@ pub struct TopicConfig {
     pub retention_bytes: Option<u64>,
     /// Segment size in bytes
     pub segment_bytes: Option<u64>,
+    /// How old records are cleaned up
+    #[serde(default)]
+    pub cleanup_policy: CleanupPolicy,
+    /// How long tombstones are kept on compacted topics, in milliseconds
+    #[serde(default)]
+    pub delete_retention_ms: Option<u64>,
     /// Additional configuration
     pub config: HashMap<String, String>,
 }
@@ -123,11 +172,78 @@ impl TopicConfig {
             retention_ms: None,
             retention_bytes: None,
             segment_bytes: None,
+            cleanup_policy: CleanupPolicy::default(),
+            delete_retention_ms: None,
             config: HashMap::new(),
         }
     }
 }
 
+/// Topic cleanup policy (Kafka `cleanup.policy`)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupPolicy {
+    /// Delete whole segments past the retention limits
+    #[default]
+    Delete,
+    /// Keep only the latest record for each key
+    Compact,
+    /// Compact, and also delete segments past the retention limits
+    CompactDelete,
+}
+
+impl CleanupPolicy {
+    /// Whether records superseded by a later record with the same key are removed
+    #[must_use]
+    pub fn compacts(self) -> bool {
+        matches!(self, Self::Compact | Self::CompactDelete)
+    }
+
+    /// Whether time- and size-based retention applies
+    #[must_use]
+    pub fn deletes(self) -> bool {
+        matches!(self, Self::Delete | Self::CompactDelete)
+    }
+
+    /// Kafka configuration value
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Compact => "compact",
+            Self::CompactDelete => "compact,delete",
+        }
+    }
+}
+
+impl std::fmt::Display for CleanupPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for CleanupPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut compact = false;
+        let mut delete = false;
+        for part in s.split(',').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "compact" => compact = true,
+                "delete" => delete = true,
+                _ => return Err(format!("invalid cleanup policy '{s}'")),
+            }
+        }
+        match (compact, delete) {
+            (true, true) => Ok(Self::CompactDelete),
+            (true, false) => Ok(Self::Compact),
+            (false, true) => Ok(Self::Delete),
+            (false, false) => Err(format!("invalid cleanup policy '{s}'")),
+        }
+    }
+}
+
 /// Topic metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TopicMetadata {
@@ -204,6 +320,15 @@ pub struct CollectionMetadata {
     pub indexes: Vec<IndexMetadata>,
     /// Schema (optional)
     pub schema: Option<serde_json::Value>,
+    /// Vector index settings, for vector collections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<VectorIndexConfig>,
+    /// Payload indexes, for vector collections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub payload_indexes: Vec<PayloadIndexSpec>,
+    /// Fields covered by the collection's full-text index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub text_fields: Vec<String>,
 }
 
 /// Collection info with vector dimensions
@@ -217,6 +342,9 @@ pub struct CollectionInfo {
     pub size_bytes: u64,
     /// Vector dimensions (if vector index exists)
     pub vector_dimensions: Option<usize>,
+    /// Vector index settings (if vector index exists)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_config: Option<VectorIndexConfig>,
 }
 
 /// Index metadata
@@ -230,6 +358,43 @@ pub struct IndexMetadata {
     pub fields: Vec<String>,
     /// Is unique
     pub unique: bool,
+    /// Conditions a document must meet, all of them, to be indexed; empty
+    /// for an index over every document
+    #[serde(default)]
+    pub filter: Vec<IndexCondition>,
+    /// Whether the index is still being backfilled
+    #[serde(default)]
+    pub building: bool,
+    /// Number of index entries
+    #[serde(default)]
+    pub entries: u64,
+    /// Number of distinct indexed values
+    #[serde(default)]
+    pub distinct_keys: u64,
+}
+
+/// A condition on a document field, used to define partial indexes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexCondition {
+    /// Dotted path of the field
+    pub field: String,
+    /// Comparison applied to the field
+    pub op: ConditionOp,
+    /// Value compared against; ignored by the NULL tests
+    pub value: serde_json::Value,
+}
+
+/// Comparison of an [`IndexCondition`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    IsNull,
+    IsNotNull,
 }
 
 /// Index types
@@ -265,16 +430,164 @@ pub struct VectorSearchResult {
 }
 
 /// Vector index configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct VectorIndexConfig {
     /// Number of dimensions
     pub dimensions: usize,
     /// Distance metric
     pub metric: DistanceMetric,
-    /// HNSW M parameter
+    /// HNSW M parameter (links per node above layer 0; twice as many at
+    /// layer 0)
     pub hnsw_m: usize,
     /// HNSW `ef_construction` parameter
     pub hnsw_ef_construction: usize,
+    /// HNSW `ef` used by searches asking for fewer results than this
+    #[serde(default = "default_hnsw_ef_search")]
+    pub hnsw_ef_search: usize,
+    /// Filtered searches expected to match fewer points than this scan the
+    /// matching points instead of traversing the graph
+    #[serde(default = "default_full_scan_threshold")]
+    pub full_scan_threshold: usize,
+    /// Compression of the vectors kept in memory; uncompressed if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<VectorQuantization>,
+}
+
+fn default_hnsw_ef_search() -> usize {
+    64
+}
+
+fn default_full_scan_threshold() -> usize {
+    10_000
+}
+
+impl VectorIndexConfig {
+    /// Cosine distance with the default HNSW parameters
+    #[must_use]
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions,
+            metric: DistanceMetric::Cosine,
+            hnsw_m: 16,
+            hnsw_ef_construction: 200,
+            hnsw_ef_search: default_hnsw_ef_search(),
+            full_scan_threshold: default_full_scan_threshold(),
+            quantization: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    #[must_use]
+    pub fn with_hnsw(mut self, m: usize, ef_construction: usize, ef_search: usize) -> Self {
+        self.hnsw_m = m;
+        self.hnsw_ef_construction = ef_construction;
+        self.hnsw_ef_search = ef_search;
+        self
+    }
+
+    #[must_use]
+    pub fn with_quantization(mut self, quantization: VectorQuantization) -> Self {
+        self.quantization = Some(quantization);
+        self
+    }
+
+    /// Check the settings describe a usable index
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.dimensions == 0 {
+            return Err("vector dimensions must be greater than 0".to_string());
+        }
+        if self.hnsw_m < 2 {
+            return Err(format!("HNSW m must be at least 2 (got {})", self.hnsw_m));
+        }
+        if self.hnsw_ef_construction == 0 || self.hnsw_ef_search == 0 {
+            return Err("HNSW ef_construction and ef_search must be greater than 0".to_string());
+        }
+        match self.quantization {
+            Some(VectorQuantization::Scalar { quantile }) if !(0.5..=1.0).contains(&quantile) => {
+                Err(format!(
+                    "Scalar quantile must be within 0.5 and 1 (got {quantile})"
+                ))
+            }
+            Some(VectorQuantization::Product { segments })
+                if segments == 0 || segments > self.dimensions =>
+            {
+                Err(format!(
+                    "Product quantization needs 1 to {} segments (got {})",
+                    self.dimensions, segments
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How a quantized vector index compresses the vectors it keeps in memory
+///
+/// Searches compare queries with the compressed codes, then rescore the
+/// best candidates against the original vectors, which persistent indexes
+/// keep on disk.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum VectorQuantization {
+    /// One byte per dimension, spread between the dimension's bounds
+    ///
+    /// The bounds leave out the `1 - quantile` most extreme values (the
+    /// dimension's minimum and maximum when `quantile` is 1).
+    Scalar {
+        #[serde(default = "default_quantile")]
+        quantile: f32,
+    },
+    /// One byte per segment: the vector is split into `segments`
+    /// subvectors, each stored as the nearest of 256 trained centroids
+    Product { segments: usize },
+    /// One bit per dimension: whether the component is above the
+    /// dimension's mean
+    Binary,
+}
+
+fn default_quantile() -> f32 {
+    1.0
+}
+
+impl VectorQuantization {
+    /// Bytes of the code of a vector with `dimensions` components
+    #[must_use]
+    pub fn code_size(&self, dimensions: usize) -> usize {
+        match self {
+            VectorQuantization::Scalar { .. } => dimensions,
+            VectorQuantization::Product { segments } => *segments,
+            VectorQuantization::Binary => dimensions.div_ceil(8),
+        }
+    }
+}
+
+/// Type of the values a payload index holds
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadFieldType {
+    /// Strings, matched exactly
+    Keyword,
+    /// Integers, matched exactly or by range
+    Integer,
+}
+
+/// An index on one payload field of a vector collection, used to plan
+/// filtered vector searches
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayloadIndexSpec {
+    /// Dotted path of the field
+    pub field: String,
+    /// Type of values indexed; values of other types are not
+    pub field_type: PayloadFieldType,
 }
 
 /// Distance metrics for vector similarity
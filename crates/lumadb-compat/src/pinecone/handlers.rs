@@ -6,6 +6,8 @@ use std::collections::HashMap;
 use actix_web::{web, HttpResponse};
 use tracing::{info, debug, error};
 
+use lumadb_common::types::{DistanceMetric, VectorIndexConfig};
+use lumadb_storage::vector::SearchOptions;
 use lumadb_storage::StorageEngine;
 
 use super::types::*;
@@ -15,6 +17,7 @@ pub struct PineconeState {
     pub storage: Arc<StorageEngine>,
     pub index_name: String,
     pub dimension: usize,
+    pub metric: DistanceMetric,
 }
 
 // ============================================================================
@@ -32,7 +35,11 @@ pub async fn upsert(
     debug!("Upserting {} vectors to {}", body.vectors.len(), collection);
 
     // Ensure collection exists
-    let _ = state.storage.create_vector_collection(&collection, state.dimension).await;
+    let config = VectorIndexConfig::new(state.dimension).with_metric(state.metric);
+    let _ = state
+        .storage
+        .create_vector_collection_with_config(&collection, config)
+        .await;
 
     let mut upserted = 0u64;
 
@@ -98,9 +105,29 @@ pub async fn query(
         }
     };
 
+    let filter = match body
+        .filter
+        .as_ref()
+        .map(MetadataFilter::to_payload_filter)
+        .transpose()
+    {
+        Ok(filter) => filter,
+        Err(e) => return HttpResponse::BadRequest().json(PineconeError::invalid_argument(&e)),
+    };
+
     debug!("Querying {} vectors from {}", body.top_k, collection);
 
-    match state.storage.vector_search(&collection, &query_vector, body.top_k).await {
+    match state
+        .storage
+        .vector_search_filtered(
+            &collection,
+            &query_vector,
+            body.top_k,
+            filter.as_ref(),
+            SearchOptions::default(),
+        )
+        .await
+    {
         Ok(results) => {
             let matches: Vec<ScoredVector> = results
                 .into_iter()
@@ -202,8 +229,20 @@ pub async fn delete(
         for id in ids {
             let _ = state.storage.delete_document(&collection, id).await;
         }
+    } else if let Some(filter) = &body.filter {
+        let filter = match filter.to_payload_filter() {
+            Ok(filter) => filter,
+            Err(e) => return HttpResponse::BadRequest().json(PineconeError::invalid_argument(&e)),
+        };
+        if let Err(e) = state
+            .storage
+            .delete_documents_matching(&collection, &filter)
+            .await
+        {
+            return HttpResponse::InternalServerError()
+                .json(PineconeError::internal(&e.to_string()));
+        }
     }
-    // TODO: Handle filter-based deletion
 
     HttpResponse::Ok().json(DeleteResponse {})
 }
@@ -8,6 +8,7 @@ use tracing::info;
 
 use lumadb_storage::StorageEngine;
 use lumadb_common::error::Result;
+use lumadb_common::types::DistanceMetric;
 
 use super::handlers::{self, PineconeState};
 
@@ -18,6 +19,7 @@ pub struct PineconeServer {
     port: u16,
     index_name: String,
     dimension: usize,
+    metric: DistanceMetric,
 }
 
 impl PineconeServer {
@@ -29,6 +31,7 @@ impl PineconeServer {
             port: 8081,
             index_name: "default".to_string(),
             dimension: 1536, // OpenAI embedding dimension
+            metric: DistanceMetric::Cosine,
         }
     }
 
@@ -59,12 +62,19 @@ impl PineconeServer {
         self
     }
 
+    /// Set the distance metric of the index's namespaces
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
     /// Run the Pinecone-compatible server
     pub async fn run(self) -> Result<()> {
         let state = web::Data::new(PineconeState {
             storage: self.storage.clone(),
             index_name: self.index_name.clone(),
             dimension: self.dimension,
+            metric: self.metric,
         });
 
         info!("Starting Pinecone-compatible API on {}:{}", self.host, self.port);
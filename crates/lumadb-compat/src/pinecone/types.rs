@@ -3,6 +3,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use lumadb_storage::vector::{NumberRange, PayloadFilter};
+
 // ============================================================================
 // Vector Types
 // ============================================================================
@@ -141,6 +143,101 @@ pub struct FilterOperators {
     pub exists: Option<bool>,
 }
 
+impl MetadataFilter {
+    /// Translate into a storage filter on the vectors' metadata
+    ///
+    /// Field conditions are `{"field": value}` (equality) or
+    /// `{"field": {"$op": operand, ..}}`, combined with `$and` and `$or`.
+    pub fn to_payload_filter(&self) -> Result<PayloadFilter, String> {
+        let value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        expression_filter(&value)
+    }
+}
+
+fn expression_filter(value: &serde_json::Value) -> Result<PayloadFilter, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| format!("Filter must be an object, got {}", value))?;
+
+    let mut all = Vec::with_capacity(object.len());
+    for (key, value) in object {
+        let filters = || -> Result<Vec<PayloadFilter>, String> {
+            value
+                .as_array()
+                .ok_or_else(|| format!("{} takes an array of filters", key))?
+                .iter()
+                .map(expression_filter)
+                .collect()
+        };
+        all.push(match key.as_str() {
+            "$and" => PayloadFilter::all(filters()?),
+            "$or" => PayloadFilter::any(filters()?),
+            _ => field_filter(key, value)?,
+        });
+    }
+    Ok(PayloadFilter::all(all))
+}
+
+fn field_filter(key: &str, condition: &serde_json::Value) -> Result<PayloadFilter, String> {
+    let operators = match condition.as_object() {
+        Some(operators) if operators.keys().all(|op| op.starts_with('$')) => operators,
+        _ => {
+            return Ok(PayloadFilter::Match {
+                key: key.to_string(),
+                values: vec![condition.clone()],
+            })
+        }
+    };
+
+    let matching = |values| PayloadFilter::Match {
+        key: key.to_string(),
+        values,
+    };
+    let list = |op: &str, operand: &serde_json::Value| {
+        operand
+            .as_array()
+            .cloned()
+            .ok_or_else(|| format!("{} on {} takes an array", op, key))
+    };
+    let mut range = NumberRange::default();
+    let mut all = Vec::new();
+    for (op, operand) in operators {
+        let number = || {
+            operand
+                .as_f64()
+                .ok_or_else(|| format!("{} on {} takes a number", op, key))
+        };
+        match op.as_str() {
+            "$eq" => all.push(matching(vec![operand.clone()])),
+            "$ne" => all.push(PayloadFilter::negate(matching(vec![operand.clone()]))),
+            "$in" => all.push(matching(list(op, operand)?)),
+            "$nin" => all.push(PayloadFilter::negate(matching(list(op, operand)?))),
+            "$gt" => range.gt = Some(number()?),
+            "$gte" => range.gte = Some(number()?),
+            "$lt" => range.lt = Some(number()?),
+            "$lte" => range.lte = Some(number()?),
+            "$exists" => {
+                let empty = PayloadFilter::IsEmpty {
+                    key: key.to_string(),
+                };
+                all.push(match operand.as_bool() {
+                    Some(true) => PayloadFilter::negate(empty),
+                    Some(false) => empty,
+                    None => return Err(format!("$exists on {} takes a boolean", key)),
+                });
+            }
+            _ => return Err(format!("Unsupported filter operator {} on {}", op, key)),
+        }
+    }
+    if range != NumberRange::default() {
+        all.push(PayloadFilter::Range {
+            key: key.to_string(),
+            range,
+        });
+    }
+    Ok(PayloadFilter::all(all))
+}
+
 /// Response from query operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResponse {
@@ -389,3 +486,35 @@ impl PineconeError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metadata_filter_translation() {
+        let filter: MetadataFilter = serde_json::from_value(json!({
+            "genre": { "$in": ["comedy", "drama"] },
+            "$or": [
+                { "year": { "$gte": 2020, "$lt": 2023 } },
+                { "rating": 5 }
+            ],
+            "draft": { "$exists": false }
+        }))
+        .unwrap();
+        let filter = filter.to_payload_filter().unwrap();
+
+        assert!(filter.matches("a", &json!({ "genre": "drama", "year": 2021 })));
+        assert!(filter.matches("a", &json!({ "genre": ["x", "comedy"], "rating": 5 })));
+        assert!(!filter.matches("a", &json!({ "genre": "drama", "year": 2023 })));
+        assert!(!filter.matches(
+            "a",
+            &json!({ "genre": "drama", "year": 2021, "draft": true })
+        ));
+        assert!(!filter.matches("a", &json!({ "genre": "horror", "year": 2021 })));
+
+        let bad: MetadataFilter = serde_json::from_value(json!({ "a": { "$like": 1 } })).unwrap();
+        assert!(bad.to_payload_filter().is_err());
+    }
+}
@@ -1,19 +1,108 @@
 //! Qdrant REST API request handlers
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
 
 use actix_web::{web, HttpResponse};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{info, debug, error};
 
-use lumadb_storage::StorageEngine;
+use lumadb_common::types::{PayloadFieldType, PayloadIndexSpec, VectorIndexConfig};
+use lumadb_storage::hybrid::{self, HybridQuery};
+use lumadb_storage::vector::PayloadFilter;
+use lumadb_storage::{DocumentWrite, StorageEngine};
 
 use super::types::*;
 use crate::{CompatError, Result};
 
+/// Metadata key prefix of collection aliases, each holding the name of the
+/// collection it points to
+const ALIAS_PREFIX: &str = "qdrant:alias:";
+
 /// Shared state for Qdrant handlers
 pub struct QdrantState {
     pub storage: Arc<StorageEngine>,
+    /// Serializes alias changes
+    alias_lock: tokio::sync::Mutex<()>,
+}
+
+impl QdrantState {
+    /// State of a server over `storage`
+    pub fn new(storage: Arc<StorageEngine>) -> Self {
+        Self {
+            storage,
+            alias_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The collection a name refers to: the one the alias of that name
+    /// points to, or else the collection of that name
+    pub fn resolve(&self, name: String) -> String {
+        match self
+            .storage
+            .get_metadata(&format!("{}{}", ALIAS_PREFIX, name))
+        {
+            Ok(Some(collection)) => String::from_utf8_lossy(&collection).into_owned(),
+            _ => name,
+        }
+    }
+
+    /// Every alias, with the collection it points to
+    pub fn aliases(&self) -> Result<BTreeMap<String, String>> {
+        Ok(self
+            .storage
+            .scan_metadata(ALIAS_PREFIX)?
+            .into_iter()
+            .map(|(key, collection)| {
+                let alias = key[ALIAS_PREFIX.len()..].to_string();
+                (alias, String::from_utf8_lossy(&collection).into_owned())
+            })
+            .collect())
+    }
+
+    /// Take alias actions, all of them or, if one cannot be taken, none
+    async fn change_aliases(&self, changes: &ChangeAliases) -> Result<()> {
+        let _lock = self.alias_lock.lock().await;
+        let collections: HashSet<String> = self
+            .storage
+            .list_collections()
+            .await?
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        let before = self.aliases()?;
+        let after = changes.apply(&before, |name| collections.contains(name))?;
+        self.save_aliases(&before, &after)
+    }
+
+    /// Drop the aliases of a deleted collection
+    async fn remove_aliases(&self, collection: &str) -> Result<()> {
+        let _lock = self.alias_lock.lock().await;
+        let before = self.aliases()?;
+        let mut after = before.clone();
+        after.retain(|_, target| target != collection);
+        self.save_aliases(&before, &after)
+    }
+
+    fn save_aliases(
+        &self,
+        before: &BTreeMap<String, String>,
+        after: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        for alias in before.keys().filter(|alias| !after.contains_key(*alias)) {
+            self.storage
+                .delete_metadata(&format!("{}{}", ALIAS_PREFIX, alias))?;
+        }
+        for (alias, collection) in after {
+            if before.get(alias) != Some(collection) {
+                let key = format!("{}{}", ALIAS_PREFIX, alias);
+                self.storage.put_metadata(&key, collection.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -46,11 +135,15 @@ pub async fn get_collection(
     path: web::Path<String>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let name = path.into_inner();
+    let name = state.resolve(path.into_inner());
 
     // Get collection metadata from storage
     match state.storage.get_collection_info(&name).await {
         Ok(Some(info)) => {
+            let vector_config = info
+                .vector_config
+                .clone()
+                .unwrap_or_else(|| VectorIndexConfig::new(128));
             let collection_info = CollectionInfo {
                 status: CollectionStatus::Green,
                 optimizer_status: OptimizerStatus::Ok,
@@ -61,8 +154,8 @@ pub async fn get_collection(
                 config: CollectionConfig {
                     params: CollectionParams {
                         vectors: VectorsConfig::Single(VectorParams {
-                            size: info.vector_dimensions.unwrap_or(128),
-                            distance: Distance::Cosine,
+                            size: vector_config.dimensions,
+                            distance: vector_config.metric.into(),
                             hnsw_config: None,
                             quantization_config: None,
                             on_disk: None,
@@ -73,9 +166,9 @@ pub async fn get_collection(
                         on_disk_payload: Some(false),
                     },
                     hnsw_config: HnswConfig {
-                        m: Some(16),
-                        ef_construct: Some(100),
-                        full_scan_threshold: Some(10000),
+                        m: Some(vector_config.hnsw_m),
+                        ef_construct: Some(vector_config.hnsw_ef_construction),
+                        full_scan_threshold: Some(vector_config.full_scan_threshold),
                         max_indexing_threads: Some(0),
                         on_disk: Some(false),
                         payload_m: None,
@@ -94,9 +187,32 @@ pub async fn get_collection(
                         wal_capacity_mb: Some(32),
                         wal_segments_ahead: Some(0),
                     },
-                    quantization_config: None,
+                    quantization_config: vector_config.quantization.map(|q| {
+                        QuantizationConfig::from_quantization(q, vector_config.dimensions)
+                    }),
+                    quantization_report: state.storage.quantization_report(&name),
                 },
-                payload_schema: std::collections::HashMap::new(),
+                payload_schema: state
+                    .storage
+                    .payload_indexes(&name)
+                    .into_iter()
+                    .map(|(spec, points)| {
+                        let info = PayloadIndexInfo {
+                            data_type: spec.field_type.into(),
+                            params: None,
+                            points: points as u64,
+                        };
+                        (spec.field, info)
+                    })
+                    .chain(state.storage.text_fields(&name).into_iter().map(|field| {
+                        let info = PayloadIndexInfo {
+                            data_type: PayloadSchemaType::Text,
+                            params: None,
+                            points: info.count as u64,
+                        };
+                        (field, info)
+                    }))
+                    .collect(),
             };
             HttpResponse::Ok().json(QdrantResponse::ok(collection_info, start.elapsed().as_secs_f64()))
         }
@@ -118,16 +234,41 @@ pub async fn create_collection(
 
     info!("Creating Qdrant-compatible collection: {}", name);
 
-    // Extract vector dimensions from config
-    let dimensions = match &body.vectors {
-        VectorsConfig::Single(params) => params.size,
-        VectorsConfig::Multi(map) => {
-            map.values().next().map(|p| p.size).unwrap_or(128)
-        }
+    // Extract vector settings from config
+    let params = match &body.vectors {
+        VectorsConfig::Single(params) => Some(params),
+        VectorsConfig::Multi(map) => map.values().next(),
     };
+    let mut config = VectorIndexConfig::new(params.map_or(128, |p| p.size));
+    if let Some(params) = params {
+        config = config.with_metric(params.distance.clone().into());
+    }
+    // Per-vector HNSW settings override the collection's
+    let hnsw = body
+        .hnsw_config
+        .as_ref()
+        .into_iter()
+        .chain(params.and_then(|p| p.hnsw_config.as_ref()));
+    for hnsw in hnsw {
+        config.hnsw_m = hnsw.m.unwrap_or(config.hnsw_m);
+        config.hnsw_ef_construction = hnsw.ef_construct.unwrap_or(config.hnsw_ef_construction);
+        config.full_scan_threshold = hnsw
+            .full_scan_threshold
+            .unwrap_or(config.full_scan_threshold);
+    }
+    // As is per-vector quantization
+    let quantization = params
+        .and_then(|p| p.quantization_config.as_ref())
+        .or(body.quantization_config.as_ref());
+    if let Some(quantization) = quantization {
+        match quantization.to_quantization(config.dimensions) {
+            Ok(quantization) => config = config.with_quantization(quantization),
+            Err(e) => return error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+        }
+    }
 
     // Create collection with vector support
-    match state.storage.create_vector_collection(&name, dimensions).await {
+    match state.storage.create_vector_collection_with_config(&name, config).await {
         Ok(_) => {
             HttpResponse::Ok().json(QdrantResponse::ok(true, start.elapsed().as_secs_f64()))
         }
@@ -143,7 +284,11 @@ pub async fn delete_collection(
     let start = Instant::now();
     let name = path.into_inner();
 
-    match state.storage.delete_collection(&name).await {
+    let deleted = async {
+        state.storage.delete_collection(&name).await?;
+        state.remove_aliases(&name).await
+    };
+    match deleted.await {
         Ok(_) => {
             HttpResponse::Ok().json(QdrantResponse::ok(true, start.elapsed().as_secs_f64()))
         }
@@ -162,7 +307,7 @@ pub async fn upsert_points(
     body: web::Json<UpsertPoints>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
+    let collection = state.resolve(path.into_inner());
 
     debug!("Upserting {} points to collection {}", body.points.len(), collection);
 
@@ -180,9 +325,9 @@ pub async fn upsert_points(
             None => continue,
         };
 
-        // Create document with vector and payload
+        // Create document with vector and payload; the ID is stored as
+        // the document's own
         let mut doc = serde_json::json!({
-            "_id": id,
             "_vector": vector,
         });
 
@@ -219,7 +364,7 @@ pub async fn get_points(
     body: web::Json<GetPoints>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
+    let collection = state.resolve(path.into_inner());
 
     let mut points = Vec::new();
 
@@ -252,7 +397,7 @@ pub async fn delete_points(
     body: web::Json<DeletePoints>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
+    let collection = state.resolve(path.into_inner());
 
     match &body.points {
         PointsSelector::Ids(ids) => {
@@ -260,8 +405,15 @@ pub async fn delete_points(
                 let _ = state.storage.delete_document(&collection, &id.to_string()).await;
             }
         }
-        PointsSelector::Filter(_filter) => {
-            // TODO: Implement filter-based deletion
+        PointsSelector::Filter(filter) => {
+            let filter = PayloadFilter::from(filter);
+            if let Err(e) = state
+                .storage
+                .delete_documents_matching(&collection, &filter)
+                .await
+            {
+                return error_response(&e.to_string(), start.elapsed().as_secs_f64());
+            }
         }
     }
 
@@ -273,6 +425,136 @@ pub async fn delete_points(
     HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
 }
 
+/// POST /collections/{name}/points/payload - Set payload keys of points
+pub async fn set_payload(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<SetPayload>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let updated = update_payloads(&state.storage, &collection, &body.target, |p| {
+        body.merge_into(p)
+    })
+    .await;
+    update_response(updated, start.elapsed().as_secs_f64())
+}
+
+/// PUT /collections/{name}/points/payload - Replace the payload of points
+pub async fn overwrite_payload(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<SetPayload>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let updated = update_payloads(&state.storage, &collection, &body.target, |p| {
+        body.overwrite(p)
+    })
+    .await;
+    update_response(updated, start.elapsed().as_secs_f64())
+}
+
+/// POST /collections/{name}/points/payload/delete - Delete payload keys of
+/// points
+pub async fn delete_payload(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<DeletePayload>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let updated = update_payloads(&state.storage, &collection, &body.target, |p| {
+        body.remove_from(p)
+    })
+    .await;
+    update_response(updated, start.elapsed().as_secs_f64())
+}
+
+/// POST /collections/{name}/points/payload/clear - Remove the whole payload
+/// of points
+pub async fn clear_payload(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<PointsTarget>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let updated = update_payloads(&state.storage, &collection, &body, |p| p.clear()).await;
+    update_response(updated, start.elapsed().as_secs_f64())
+}
+
+/// Rewrite the payloads of the points an update targets, in one commit
+///
+/// Points keep their ID and vector whatever the update does. The commit
+/// fails with a conflict if another write changes one of the points while
+/// the update runs.
+async fn update_payloads(
+    storage: &StorageEngine,
+    collection: &str,
+    target: &PointsTarget,
+    update: impl Fn(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<()> {
+    if storage.get_collection(collection).await?.is_none() {
+        return Err(CompatError::CollectionNotFound(collection.to_string()));
+    }
+    let filter = target.filter.as_ref().map(PayloadFilter::from);
+    let snapshot = storage.begin_snapshot();
+    let updated = async {
+        let documents = match target.points {
+            Some(ref ids) => {
+                let mut documents = Vec::with_capacity(ids.len());
+                for id in ids {
+                    let id = id.to_string();
+                    if let Some(doc) = storage.get_document_at(collection, &id, snapshot).await? {
+                        documents.push(doc);
+                    }
+                }
+                let filter = filter.as_ref();
+                documents.retain(|doc| filter.map_or(true, |f| f.matches(&doc.id, &doc.data)));
+                documents
+            }
+            None if filter.is_some() => {
+                storage.filter_documents(collection, filter.as_ref(), None, None)?
+            }
+            None => {
+                return Err(CompatError::InvalidRequest(
+                    "Either points or filter is required".to_string(),
+                ));
+            }
+        };
+
+        let writes = documents
+            .into_iter()
+            .map(|mut doc| {
+                if let serde_json::Value::Object(ref mut data) = doc.data {
+                    let id = data.remove("_id");
+                    let vector = data.remove("_vector");
+                    update(data);
+                    data.remove("_id");
+                    data.remove("_vector");
+                    data.extend(id.map(|id| ("_id".to_string(), id)));
+                    data.extend(vector.map(|vector| ("_vector".to_string(), vector)));
+                }
+                DocumentWrite {
+                    collection: collection.to_string(),
+                    id: doc.id.clone(),
+                    document: Some(doc),
+                }
+            })
+            .collect();
+        storage.commit(snapshot, writes, &[]).await?;
+        Ok(())
+    }
+    .await;
+    storage.release_snapshot(snapshot);
+    updated
+}
+
 // ============================================================================
 // Search Handlers
 // ============================================================================
@@ -284,47 +566,270 @@ pub async fn search_points(
     body: web::Json<SearchRequest>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
+    let collection = state.resolve(path.into_inner());
+
+    match search(&state.storage, &collection, &body).await {
+        Ok(scored_points) => {
+            let time = start.elapsed().as_secs_f64();
+            HttpResponse::Ok().json(QdrantResponse::ok(scored_points, time))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// POST /collections/{name}/points/search/batch - Run several searches
+pub async fn search_batch(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<SearchRequestBatch>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let mut results = Vec::with_capacity(body.searches.len());
+    for request in &body.searches {
+        match search(&state.storage, &collection, request).await {
+            Ok(scored_points) => results.push(scored_points),
+            Err(e) => return error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+        }
+    }
+    HttpResponse::Ok().json(QdrantResponse::ok(results, start.elapsed().as_secs_f64()))
+}
 
-    let vector = body.vector.vector();
-    let k = body.limit;
+/// Points a search finds
+async fn search(
+    storage: &StorageEngine,
+    collection: &str,
+    request: &SearchRequest,
+) -> Result<Vec<ScoredPoint>> {
+    let vector = request.vector.vector();
+    let offset = request.offset.unwrap_or(0);
+    let k = request.limit.saturating_add(offset);
+    let filter = request.filter.as_ref().map(PayloadFilter::from);
+    let options = request
+        .params
+        .as_ref()
+        .map(SearchParams::options)
+        .unwrap_or_default();
 
     debug!("Searching {} vectors in collection {}", k, collection);
 
-    match state.storage.vector_search(&collection, vector, k).await {
-        Ok(results) => {
-            let scored_points: Vec<ScoredPoint> = results
+    let results = storage
+        .vector_search_filtered(collection, vector, k, filter.as_ref(), options)
+        .await?;
+    let with_vector = matches!(request.with_vector, Some(WithVector::Bool(true)));
+    Ok(results
+        .into_iter()
+        .skip(offset)
+        .map(|r| scored_point(r.doc_id, r.score, &r.payload, with_vector))
+        .collect())
+}
+
+/// POST /collections/{name}/points/recommend - Find points like the
+/// positive examples and unlike the negative ones
+///
+/// Only the `average_vector` strategy is supported: the search runs near
+/// the mean of the positive examples, moved away from the mean of the
+/// negative ones. Examples given by ID are left out of the results.
+pub async fn recommend_points(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<RecommendRequest>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    match recommend(&state.storage, &collection, &body).await {
+        Ok(scored_points) => {
+            let time = start.elapsed().as_secs_f64();
+            HttpResponse::Ok().json(QdrantResponse::ok(scored_points, time))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// POST /collections/{name}/points/recommend/batch - Run several
+/// recommendations
+pub async fn recommend_batch(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<RecommendRequestBatch>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let mut results = Vec::with_capacity(body.searches.len());
+    for request in &body.searches {
+        match recommend(&state.storage, &collection, request).await {
+            Ok(scored_points) => results.push(scored_points),
+            Err(e) => return error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+        }
+    }
+    HttpResponse::Ok().json(QdrantResponse::ok(results, start.elapsed().as_secs_f64()))
+}
+
+/// Points a recommendation finds
+async fn recommend(
+    storage: &StorageEngine,
+    collection: &str,
+    request: &RecommendRequest,
+) -> Result<Vec<ScoredPoint>> {
+    if matches!(request.strategy, Some(strategy) if strategy != RecommendStrategy::AverageVector) {
+        return Err(CompatError::InvalidRequest(
+            "Only the average_vector recommend strategy is supported".to_string(),
+        ));
+    }
+    let index = storage
+        .vector_index(collection)
+        .ok_or_else(|| CompatError::CollectionNotFound(collection.to_string()))?;
+
+    // Stored examples are looked up, and kept out of the results
+    let mut examples = HashSet::new();
+    let mut vectors = |list: &[RecommendExample]| -> Result<Vec<Vec<f32>>> {
+        list.iter()
+            .map(|example| match example {
+                RecommendExample::Vector(vector) => Ok(vector.clone()),
+                RecommendExample::Id(id) => {
+                    let id = id.to_string();
+                    let vector = index.get(&id).ok_or_else(|| {
+                        CompatError::InvalidRequest(format!("Point {} not found", id))
+                    })?;
+                    examples.insert(id);
+                    Ok(vector)
+                }
+            })
+            .collect()
+    };
+    let positive = vectors(&request.positive)?;
+    let negative = vectors(&request.negative)?;
+    let query = RecommendRequest::average_vector(&positive, &negative)?;
+
+    let offset = request.offset.unwrap_or(0);
+    let k = request
+        .limit
+        .saturating_add(offset)
+        .saturating_add(examples.len());
+    let filter = request.filter.as_ref().map(PayloadFilter::from);
+    let options = request
+        .params
+        .as_ref()
+        .map(SearchParams::options)
+        .unwrap_or_default();
+    debug!(
+        "Recommending {} points in collection {}",
+        request.limit, collection
+    );
+
+    let results = storage
+        .vector_search_filtered(collection, &query, k, filter.as_ref(), options)
+        .await?;
+    let with_vector = matches!(request.with_vector, Some(WithVector::Bool(true)));
+    Ok(results
+        .into_iter()
+        .filter(|r| !examples.contains(&r.doc_id))
+        .skip(offset)
+        .take(request.limit)
+        .map(|r| scored_point(r.doc_id, r.score, &r.payload, with_vector))
+        .collect())
+}
+
+/// POST /collections/{name}/points/query - Query points, fusing prefetched
+/// vector and document searches
+///
+/// Documents are ranked by BM25 over the collection's text indexes. A
+/// single search scores points as `points/search` does; fused points are
+/// scored by the fusion, higher being better.
+pub async fn query_points(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<QueryRequest>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+    let with_vector = matches!(body.with_vector, Some(WithVector::Bool(true)));
+
+    match run_query(&state.storage, &collection, &body).await {
+        Ok(points) => {
+            let points = points
                 .into_iter()
-                .map(|r| {
-                    let id = PointId::Uuid(r.doc_id.clone());
-                    let payload = r.payload.as_object().cloned().map(|mut p| {
-                        p.remove("_id");
-                        p.remove("_vector");
-                        p.into_iter().collect()
-                    });
-
-                    ScoredPoint {
-                        id,
-                        version: 1,
-                        score: r.score,
-                        payload,
-                        vector: if body.with_vector.as_ref().map(|w| matches!(w, WithVector::Bool(true))).unwrap_or(false) {
-                            r.payload.get("_vector")
-                                .and_then(|v| serde_json::from_value::<Vec<f32>>(v.clone()).ok())
-                                .map(VectorInput::Dense)
-                        } else {
-                            None
-                        },
-                    }
-                })
+                .map(|(id, score, payload)| scored_point(id, score, &payload, with_vector))
                 .collect();
-
-            HttpResponse::Ok().json(QdrantResponse::ok(scored_points, start.elapsed().as_secs_f64()))
+            let result = QueryResponse { points };
+            HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
         }
         Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
     }
 }
 
+/// IDs, scores and documents of the points a query returns
+async fn run_query(
+    storage: &StorageEngine,
+    collection: &str,
+    body: &QueryRequest,
+) -> Result<Vec<(String, f32, serde_json::Value)>> {
+    let plan = body.plan()?;
+    debug!(
+        "Querying {} points in collection {}",
+        plan.limit, collection
+    );
+
+    let mut documents = HashMap::new();
+    let mut vector_ranking = Vec::new();
+    if let Some(ranking) = plan.vector {
+        let results = storage
+            .vector_search_filtered(
+                collection,
+                &ranking.query,
+                ranking.limit,
+                ranking.filter.as_ref(),
+                ranking.options,
+            )
+            .await?;
+        for result in results {
+            vector_ranking.push((result.doc_id.clone(), result.score));
+            documents.insert(result.doc_id, result.payload);
+        }
+    }
+    let text_ranking = match plan.text {
+        Some(ranking) => storage.text_search(
+            collection,
+            &ranking.query,
+            ranking.limit,
+            ranking.filter.as_ref(),
+        )?,
+        None => Vec::new(),
+    };
+
+    let ranked: Vec<(String, f32)> = match plan.fusion {
+        Some(fusion) => {
+            let query = HybridQuery::new(plan.limit).with_fusion(fusion);
+            hybrid::fuse(&query, &vector_ranking, &text_ranking)
+                .into_iter()
+                .map(|hit| (hit.doc_id, hit.score))
+                .collect()
+        }
+        None => vector_ranking.into_iter().chain(text_ranking).collect(),
+    };
+
+    let mut points = Vec::new();
+    for (id, score) in ranked
+        .into_iter()
+        .skip(plan.offset)
+        .take(plan.limit - plan.offset)
+    {
+        let payload = match documents.remove(&id) {
+            Some(payload) => payload,
+            None => storage
+                .get_document(collection, &id)
+                .await?
+                .map(|doc| doc.data)
+                .unwrap_or_default(),
+        };
+        points.push((id, score, payload));
+    }
+    Ok(points)
+}
+
 /// POST /collections/{name}/points/scroll - Scroll through points
 pub async fn scroll_points(
     state: web::Data<QdrantState>,
@@ -332,15 +837,24 @@ pub async fn scroll_points(
     body: web::Json<ScrollRequest>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
+    let collection = state.resolve(path.into_inner());
 
     let offset = body.offset.as_ref().map(|id| id.to_string());
     let limit = body.limit;
-
-    // Use scan_documents with offset as prefix filter
-    let prefix = offset.as_ref().map(|s| s.as_bytes());
-    match state.storage.scan_documents(&collection, prefix, Some(limit)).await {
-        Ok(docs) => {
+    let filter = body.filter.as_ref().map(PayloadFilter::from);
+
+    // Read one point past the page to find where the next one starts
+    let page = Some(limit.saturating_add(1));
+    match state
+        .storage
+        .filter_documents(&collection, filter.as_ref(), offset.as_deref(), page)
+    {
+        Ok(mut docs) => {
+            let next_offset = if docs.len() > limit {
+                docs.pop().map(|doc| PointId::Uuid(doc.id))
+            } else {
+                None
+            };
             let points: Vec<PointStruct> = docs
                 .into_iter()
                 .map(|doc| {
@@ -361,8 +875,6 @@ pub async fn scroll_points(
                 })
                 .collect();
 
-            let next_offset = points.last().map(|p| p.id.clone());
-
             let result = ScrollResult {
                 points,
                 next_page_offset: next_offset,
@@ -378,12 +890,19 @@ pub async fn scroll_points(
 pub async fn count_points(
     state: web::Data<QdrantState>,
     path: web::Path<String>,
-    _body: web::Json<CountRequest>,
+    body: web::Json<CountRequest>,
 ) -> HttpResponse {
     let start = Instant::now();
-    let collection = path.into_inner();
-
-    match state.storage.count_documents(&collection).await {
+    let collection = state.resolve(path.into_inner());
+
+    let count = match body.filter {
+        Some(ref filter) => state
+            .storage
+            .filter_documents(&collection, Some(&filter.into()), None, None)
+            .map(|docs| docs.len()),
+        None => state.storage.count_documents(&collection).await,
+    };
+    match count {
         Ok(count) => {
             let result = CountResult { count: count as u64 };
             HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
@@ -392,6 +911,301 @@ pub async fn count_points(
     }
 }
 
+/// PUT /collections/{name}/index - Create a payload index
+pub async fn create_field_index(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<CreateFieldIndex>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    let field_type = match body
+        .field_schema
+        .as_ref()
+        .map(PayloadFieldSchema::data_type)
+    {
+        Some(PayloadSchemaType::Keyword) => Some(PayloadFieldType::Keyword),
+        Some(PayloadSchemaType::Integer) => Some(PayloadFieldType::Integer),
+        // Text fields join the collection's full-text index
+        Some(PayloadSchemaType::Text) => None,
+        _ => {
+            return error_response(
+                "Only keyword, integer and text payload indexes are supported",
+                start.elapsed().as_secs_f64(),
+            );
+        }
+    };
+    let created = match field_type {
+        Some(field_type) => {
+            let spec = PayloadIndexSpec {
+                field: body.field_name.clone(),
+                field_type,
+            };
+            state.storage.create_payload_index(&collection, spec).await
+        }
+        None => {
+            state
+                .storage
+                .create_text_index(&collection, &body.field_name)
+                .await
+        }
+    };
+
+    match created {
+        Ok(_) => {
+            let result = UpdateResult {
+                operation_id: chrono::Utc::now().timestamp_millis() as u64,
+                status: UpdateStatus::Completed,
+            };
+            HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// DELETE /collections/{name}/index/{field_name} - Delete a payload index
+pub async fn delete_field_index(
+    state: web::Data<QdrantState>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let (collection, field) = path.into_inner();
+    let collection = state.resolve(collection);
+
+    let dropped = match state.storage.drop_payload_index(&collection, &field).await {
+        Ok(_) => state.storage.drop_text_index(&collection, &field).await,
+        Err(e) => Err(e),
+    };
+    match dropped {
+        Ok(_) => {
+            let result = UpdateResult {
+                operation_id: chrono::Utc::now().timestamp_millis() as u64,
+                status: UpdateStatus::Completed,
+            };
+            HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+// ============================================================================
+// Alias Handlers
+// ============================================================================
+
+/// POST /collections/aliases - Create, delete and rename aliases
+pub async fn update_aliases(
+    state: web::Data<QdrantState>,
+    body: web::Json<ChangeAliases>,
+) -> HttpResponse {
+    let start = Instant::now();
+
+    match state.change_aliases(&body).await {
+        Ok(()) => HttpResponse::Ok().json(QdrantResponse::ok(true, start.elapsed().as_secs_f64())),
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// GET /aliases - List every alias
+pub async fn list_aliases(state: web::Data<QdrantState>) -> HttpResponse {
+    aliases_response(&state, None, Instant::now())
+}
+
+/// GET /collections/{name}/aliases - List the aliases of a collection
+pub async fn list_collection_aliases(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let collection = path.into_inner();
+    aliases_response(&state, Some(&collection), Instant::now())
+}
+
+/// The aliases, of one collection or all of them
+fn aliases_response(state: &QdrantState, collection: Option<&str>, start: Instant) -> HttpResponse {
+    match state.aliases() {
+        Ok(aliases) => {
+            let result = CollectionsAliases {
+                aliases: aliases
+                    .into_iter()
+                    .filter(|(_, target)| collection.map_or(true, |c| c == target))
+                    .map(|(alias_name, collection_name)| AliasDescription {
+                        alias_name,
+                        collection_name,
+                    })
+                    .collect(),
+            };
+            HttpResponse::Ok().json(QdrantResponse::ok(result, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+// ============================================================================
+// Snapshot Handlers
+// ============================================================================
+
+/// POST /collections/{name}/snapshots - Snapshot a collection
+pub async fn create_snapshot(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    match state.storage.create_snapshot(&collection).await {
+        Ok(snapshot) => {
+            info!(
+                "Created snapshot {} of collection {}",
+                snapshot.name, collection
+            );
+            HttpResponse::Ok().json(QdrantResponse::ok(snapshot, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// GET /collections/{name}/snapshots - List the snapshots of a collection
+pub async fn list_snapshots(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = state.resolve(path.into_inner());
+
+    match state.storage.list_snapshots(&collection) {
+        Ok(snapshots) => {
+            HttpResponse::Ok().json(QdrantResponse::ok(snapshots, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// GET /collections/{name}/snapshots/{snapshot_name} - Download a snapshot
+pub async fn download_snapshot(
+    state: web::Data<QdrantState>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let (collection, name) = path.into_inner();
+    let collection = state.resolve(collection);
+
+    let Some(snapshot) = state.storage.snapshot_path(&collection, &name) else {
+        return error_response(
+            &format!("Snapshot {} not found", name),
+            start.elapsed().as_secs_f64(),
+        );
+    };
+    match tokio::fs::File::open(&snapshot).await {
+        Ok(file) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", name),
+            ))
+            .streaming(file_stream(file)),
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// DELETE /collections/{name}/snapshots/{snapshot_name} - Delete a snapshot
+pub async fn delete_snapshot(
+    state: web::Data<QdrantState>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let (collection, name) = path.into_inner();
+    let collection = state.resolve(collection);
+
+    match state.storage.delete_snapshot(&collection, &name) {
+        Ok(true) => {
+            HttpResponse::Ok().json(QdrantResponse::ok(true, start.elapsed().as_secs_f64()))
+        }
+        Ok(false) => error_response(
+            &format!("Snapshot {} not found", name),
+            start.elapsed().as_secs_f64(),
+        ),
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// PUT /collections/{name}/snapshots/recover - Replace a collection with a
+/// snapshot, from a local file or downloaded from a URL
+pub async fn recover_snapshot(
+    state: web::Data<QdrantState>,
+    path: web::Path<String>,
+    body: web::Json<SnapshotRecover>,
+) -> HttpResponse {
+    let start = Instant::now();
+    let collection = path.into_inner();
+
+    info!(
+        "Recovering collection {} from {}",
+        collection, body.location
+    );
+    match recover(&state.storage, &collection, &body).await {
+        Ok(points) => {
+            info!("Recovered {} points into collection {}", points, collection);
+            HttpResponse::Ok().json(QdrantResponse::ok(true, start.elapsed().as_secs_f64()))
+        }
+        Err(e) => error_response(&e.to_string(), start.elapsed().as_secs_f64()),
+    }
+}
+
+/// Restore a collection from the snapshot at a location, downloading it
+/// first if it is remote; returns the number of points restored
+async fn recover(
+    storage: &StorageEngine,
+    collection: &str,
+    request: &SnapshotRecover,
+) -> Result<usize> {
+    let location = request.location.as_str();
+    let checksum = request.checksum.as_deref();
+    if !(location.starts_with("http://") || location.starts_with("https://")) {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        return Ok(storage
+            .restore_snapshot(collection, Path::new(path), checksum)
+            .await?);
+    }
+
+    let download = storage
+        .data_dir()
+        .join("snapshots")
+        .join(format!(".download-{}.snapshot", uuid::Uuid::new_v4()));
+    let restored = match fetch_snapshot(location, request.api_key.as_deref(), &download).await {
+        Ok(()) => storage
+            .restore_snapshot(collection, &download, checksum)
+            .await
+            .map_err(CompatError::from),
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&download).await;
+    restored
+}
+
+/// Download a snapshot file to `path`
+async fn fetch_snapshot(url: &str, api_key: Option<&str>, path: &Path) -> Result<()> {
+    let network = |e: reqwest::Error| CompatError::Network(e.to_string());
+    let io = |e: std::io::Error| CompatError::Storage(e.to_string());
+
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(api_key) = api_key {
+        request = request.header("api-key", api_key);
+    }
+    let mut response = request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(network)?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io)?;
+    }
+    let mut file = tokio::fs::File::create(path).await.map_err(io)?;
+    while let Some(chunk) = response.chunk().await.map_err(network)? {
+        file.write_all(&chunk).await.map_err(io)?;
+    }
+    file.flush().await.map_err(io)
+}
+
 // ============================================================================
 // Cluster & Telemetry Handlers
 // ============================================================================
@@ -457,6 +1271,70 @@ pub async fn cluster_info() -> HttpResponse {
 // Helper Functions
 // ============================================================================
 
+/// A point found by a search, with its document's payload and, if asked
+/// for, its vector
+fn scored_point(
+    id: String,
+    score: f32,
+    document: &serde_json::Value,
+    with_vector: bool,
+) -> ScoredPoint {
+    let payload = document.as_object().cloned().map(|mut p| {
+        p.remove("_id");
+        p.remove("_vector");
+        p.into_iter().collect()
+    });
+    let vector = if with_vector {
+        document
+            .get("_vector")
+            .and_then(|v| serde_json::from_value::<Vec<f32>>(v.clone()).ok())
+            .map(VectorInput::Dense)
+    } else {
+        None
+    };
+
+    ScoredPoint {
+        id: PointId::Uuid(id),
+        version: 1,
+        score,
+        payload,
+        vector,
+    }
+}
+
+/// The chunks of a file, for a streamed response
+fn file_stream(
+    file: tokio::fs::File,
+) -> impl futures::Stream<Item = std::io::Result<bytes::Bytes>> {
+    futures::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut chunk = vec![0; 64 * 1024];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(bytes::Bytes::from(chunk)), Some(file)))
+            }
+            // The stream ends after an error
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// Response to an update: completed, or why it failed
+fn update_response(result: Result<()>, time: f64) -> HttpResponse {
+    match result {
+        Ok(()) => {
+            let result = UpdateResult {
+                operation_id: chrono::Utc::now().timestamp_millis() as u64,
+                status: UpdateStatus::Completed,
+            };
+            HttpResponse::Ok().json(QdrantResponse::ok(result, time))
+        }
+        Err(e) => error_response(&e.to_string(), time),
+    }
+}
+
 fn error_response(message: &str, time: f64) -> HttpResponse {
     HttpResponse::BadRequest().json(QdrantError {
         status: QdrantErrorStatus {
@@ -5,8 +5,13 @@
 //!
 //! Supported endpoints:
 //! - Collections: create, get, list, delete, update
-//! - Points: upsert, get, delete, search, scroll
-//! - Snapshots, cluster info, etc.
+//! - Points: upsert, get, delete, search, batch search, recommend, scroll,
+//!   count, with filters
+//! - Payloads: set, overwrite, delete keys, clear
+//! - Payload indexes: create, delete (keyword, integer and text)
+//! - Aliases: create, delete, rename, list
+//! - Snapshots: create, list, download, delete, recover from a file or URL
+//! - Cluster info, etc.
 
 mod types;
 mod handlers;
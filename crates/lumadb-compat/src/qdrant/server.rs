@@ -45,9 +45,7 @@ impl QdrantServer {
 
     /// Run the Qdrant-compatible server
     pub async fn run(self) -> Result<()> {
-        let state = web::Data::new(QdrantState {
-            storage: self.storage.clone(),
-        });
+        let state = web::Data::new(QdrantState::new(self.storage.clone()));
 
         info!("Starting Qdrant-compatible API on {}:{}", self.host, self.port);
         info!("Qdrant clients can connect to http://{}:{}", self.host, self.port);
@@ -71,6 +69,16 @@ impl QdrantServer {
                 .route("/livez", web::get().to(handlers::livez))
                 .route("/telemetry", web::get().to(handlers::telemetry))
                 .route("/cluster", web::get().to(handlers::cluster_info))
+                // Alias endpoints
+                .route("/aliases", web::get().to(handlers::list_aliases))
+                .route(
+                    "/collections/aliases",
+                    web::post().to(handlers::update_aliases),
+                )
+                .route(
+                    "/collections/{name}/aliases",
+                    web::get().to(handlers::list_collection_aliases),
+                )
                 // Collection endpoints
                 .route("/collections", web::get().to(handlers::list_collections))
                 .route("/collections/{name}", web::get().to(handlers::get_collection))
@@ -81,8 +89,68 @@ impl QdrantServer {
                 .route("/collections/{name}/points", web::post().to(handlers::get_points))
                 .route("/collections/{name}/points/delete", web::post().to(handlers::delete_points))
                 .route("/collections/{name}/points/search", web::post().to(handlers::search_points))
+                .route(
+                    "/collections/{name}/points/search/batch",
+                    web::post().to(handlers::search_batch),
+                )
+                .route(
+                    "/collections/{name}/points/recommend",
+                    web::post().to(handlers::recommend_points),
+                )
+                .route(
+                    "/collections/{name}/points/recommend/batch",
+                    web::post().to(handlers::recommend_batch),
+                )
+                .route("/collections/{name}/points/query", web::post().to(handlers::query_points))
                 .route("/collections/{name}/points/scroll", web::post().to(handlers::scroll_points))
                 .route("/collections/{name}/points/count", web::post().to(handlers::count_points))
+                // Payload endpoints
+                .route(
+                    "/collections/{name}/points/payload",
+                    web::post().to(handlers::set_payload),
+                )
+                .route(
+                    "/collections/{name}/points/payload",
+                    web::put().to(handlers::overwrite_payload),
+                )
+                .route(
+                    "/collections/{name}/points/payload/delete",
+                    web::post().to(handlers::delete_payload),
+                )
+                .route(
+                    "/collections/{name}/points/payload/clear",
+                    web::post().to(handlers::clear_payload),
+                )
+                // Payload index endpoints
+                .route(
+                    "/collections/{name}/index",
+                    web::put().to(handlers::create_field_index),
+                )
+                .route(
+                    "/collections/{name}/index/{field_name}",
+                    web::delete().to(handlers::delete_field_index),
+                )
+                // Snapshot endpoints
+                .route(
+                    "/collections/{name}/snapshots",
+                    web::post().to(handlers::create_snapshot),
+                )
+                .route(
+                    "/collections/{name}/snapshots",
+                    web::get().to(handlers::list_snapshots),
+                )
+                .route(
+                    "/collections/{name}/snapshots/recover",
+                    web::put().to(handlers::recover_snapshot),
+                )
+                .route(
+                    "/collections/{name}/snapshots/{snapshot_name}",
+                    web::get().to(handlers::download_snapshot),
+                )
+                .route(
+                    "/collections/{name}/snapshots/{snapshot_name}",
+                    web::delete().to(handlers::delete_snapshot),
+                )
         })
         .bind(format!("{}:{}", self.host, self.port))?
         .run()
@@ -1,7 +1,13 @@
 //! Qdrant API types - exact compatibility with Qdrant REST API
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use lumadb_common::types::{DistanceMetric, PayloadFieldType, VectorQuantization};
+use lumadb_storage::hybrid::Fusion;
+use lumadb_storage::vector::{self, NumberRange, PayloadFilter, QuantizationReport, SearchOptions};
+
+use crate::{CompatError, Result};
 
 // ============================================================================
 // Collection Types
@@ -58,6 +64,28 @@ pub enum Distance {
     Manhattan,
 }
 
+impl From<Distance> for DistanceMetric {
+    fn from(distance: Distance) -> Self {
+        match distance {
+            Distance::Cosine => DistanceMetric::Cosine,
+            Distance::Euclid => DistanceMetric::Euclidean,
+            Distance::Dot => DistanceMetric::DotProduct,
+            Distance::Manhattan => DistanceMetric::Manhattan,
+        }
+    }
+}
+
+impl From<DistanceMetric> for Distance {
+    fn from(metric: DistanceMetric) -> Self {
+        match metric {
+            DistanceMetric::Cosine => Distance::Cosine,
+            DistanceMetric::Euclidean => Distance::Euclid,
+            DistanceMetric::DotProduct => Distance::Dot,
+            DistanceMetric::Manhattan => Distance::Manhattan,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HnswConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -108,13 +136,83 @@ pub struct InitFrom {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[serde(untagged)]
 pub enum QuantizationConfig {
     Scalar(ScalarQuantization),
     Product(ProductQuantization),
     Binary(BinaryQuantization),
 }
 
+impl QuantizationConfig {
+    /// The quantization of an index of `dimensions`-component vectors
+    ///
+    /// Product quantization's compression ratio is that of 4-byte
+    /// components to one-byte codes per segment, so `x16` splits vectors
+    /// into a quarter as many segments as they have components.
+    pub fn to_quantization(&self, dimensions: usize) -> Result<VectorQuantization> {
+        match self {
+            QuantizationConfig::Scalar(scalar) => {
+                if scalar.config.quantization_type != "int8" {
+                    return Err(CompatError::InvalidRequest(format!(
+                        "Unsupported scalar quantization type: {}",
+                        scalar.config.quantization_type
+                    )));
+                }
+                Ok(VectorQuantization::Scalar {
+                    quantile: scalar.config.quantile.unwrap_or(1.0) as f32,
+                })
+            }
+            QuantizationConfig::Product(product) => {
+                let ratio = match product.config.compression.as_str() {
+                    "x4" => 4,
+                    "x8" => 8,
+                    "x16" => 16,
+                    "x32" => 32,
+                    "x64" => 64,
+                    other => {
+                        return Err(CompatError::InvalidRequest(format!(
+                            "Unsupported product quantization compression: {}",
+                            other
+                        )))
+                    }
+                };
+                Ok(VectorQuantization::Product {
+                    segments: (dimensions * 4 / ratio).max(1),
+                })
+            }
+            QuantizationConfig::Binary(_) => Ok(VectorQuantization::Binary),
+        }
+    }
+
+    /// The configuration describing an index's quantization
+    pub fn from_quantization(quantization: VectorQuantization, dimensions: usize) -> Self {
+        match quantization {
+            VectorQuantization::Scalar { quantile } => {
+                QuantizationConfig::Scalar(ScalarQuantization {
+                    config: ScalarQuantizationConfig {
+                        quantization_type: "int8".to_string(),
+                        quantile: Some(f64::from(quantile)),
+                        always_ram: Some(true),
+                    },
+                })
+            }
+            VectorQuantization::Product { segments } => {
+                QuantizationConfig::Product(ProductQuantization {
+                    config: ProductQuantizationConfig {
+                        compression: format!("x{}", dimensions * 4 / segments.max(1)),
+                        always_ram: Some(true),
+                    },
+                })
+            }
+            VectorQuantization::Binary => QuantizationConfig::Binary(BinaryQuantization {
+                config: BinaryQuantizationConfig {
+                    always_ram: Some(true),
+                },
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScalarQuantization {
     #[serde(rename = "scalar")]
@@ -191,6 +289,10 @@ pub struct CollectionConfig {
     pub wal_config: WalConfig,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub quantization_config: Option<QuantizationConfig>,
+    /// Measured recall, memory and comparison times of the quantization
+    /// (not part of Qdrant's API)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantization_report: Option<QuantizationReport>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -226,6 +328,15 @@ pub enum PayloadSchemaType {
     Datetime,
 }
 
+impl From<PayloadFieldType> for PayloadSchemaType {
+    fn from(field_type: PayloadFieldType) -> Self {
+        match field_type {
+            PayloadFieldType::Keyword => PayloadSchemaType::Keyword,
+            PayloadFieldType::Integer => PayloadSchemaType::Integer,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PayloadSchemaParams {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -238,6 +349,33 @@ pub struct PayloadSchemaParams {
     pub lowercase: Option<bool>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateFieldIndex {
+    pub field_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_schema: Option<PayloadFieldSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PayloadFieldSchema {
+    Type(PayloadSchemaType),
+    Params {
+        #[serde(rename = "type")]
+        data_type: PayloadSchemaType,
+    },
+}
+
+impl PayloadFieldSchema {
+    pub fn data_type(&self) -> &PayloadSchemaType {
+        match self {
+            PayloadFieldSchema::Type(data_type) | PayloadFieldSchema::Params { data_type } => {
+                data_type
+            }
+        }
+    }
+}
+
 // ============================================================================
 // Point Types
 // ============================================================================
@@ -337,6 +475,101 @@ pub enum PointsSelector {
     Filter(Filter),
 }
 
+// ============================================================================
+// Payload Types
+// ============================================================================
+
+/// Points an update applies to: those listed, those matching a filter, or
+/// those listed that match it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PointsTarget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub points: Option<Vec<PointId>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetPayload {
+    pub payload: Payload,
+    #[serde(flatten)]
+    pub target: PointsTarget,
+    /// Dotted path of the nested object the payload goes in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl SetPayload {
+    /// Set the payload's keys in a point's payload, keeping the others
+    pub fn merge_into(&self, payload: &mut serde_json::Map<String, serde_json::Value>) {
+        let target = match self.key {
+            Some(ref key) => object_at(payload, key),
+            None => payload,
+        };
+        for (key, value) in &self.payload {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+
+    /// Replace a point's payload, or the object at the key, with this one
+    pub fn overwrite(&self, payload: &mut serde_json::Map<String, serde_json::Value>) {
+        match self.key {
+            Some(ref key) => object_at(payload, key).clear(),
+            None => payload.clear(),
+        }
+        self.merge_into(payload);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletePayload {
+    pub keys: Vec<String>,
+    #[serde(flatten)]
+    pub target: PointsTarget,
+}
+
+impl DeletePayload {
+    /// Remove the keys from a point's payload; dotted keys reach into
+    /// nested objects
+    pub fn remove_from(&self, payload: &mut serde_json::Map<String, serde_json::Value>) {
+        for key in &self.keys {
+            remove_key(payload, key);
+        }
+    }
+}
+
+/// The object at a dotted path of a payload, replacing whatever else is
+/// on the path with empty objects
+fn object_at<'a>(
+    payload: &'a mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> &'a mut serde_json::Map<String, serde_json::Value> {
+    key.split('.').fold(payload, |object, part| {
+        let value = object
+            .entry(part)
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if !value.is_object() {
+            *value = serde_json::Value::Object(serde_json::Map::new());
+        }
+        match value {
+            serde_json::Value::Object(object) => object,
+            _ => unreachable!("replaced by an object above"),
+        }
+    })
+}
+
+/// Remove a key, or the key at a dotted path, from a payload
+fn remove_key(payload: &mut serde_json::Map<String, serde_json::Value>, key: &str) {
+    if payload.remove(key).is_some() {
+        return;
+    }
+    if let Some((head, rest)) = key.split_once('.') {
+        if let Some(serde_json::Value::Object(object)) = payload.get_mut(head) {
+            remove_key(object, rest);
+        }
+    }
+}
+
 // ============================================================================
 // Search Types
 // ============================================================================
@@ -387,6 +620,22 @@ pub struct SearchParams {
     pub quantization: Option<QuantizationSearchParams>,
 }
 
+impl SearchParams {
+    /// How a vector search with these parameters runs
+    pub fn options(&self) -> SearchOptions {
+        let quantization = self.quantization.as_ref();
+        SearchOptions {
+            ef: self.hnsw_ef,
+            exact: self.exact.unwrap_or(false),
+            // Candidates are always found by their codes; ignoring
+            // quantization rescores them all against the originals
+            skip_rescore: quantization
+                .is_some_and(|q| q.rescore == Some(false) && q.ignore != Some(true)),
+            oversampling: quantization.and_then(|q| q.oversampling).map(|o| o as f32),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuantizationSearchParams {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -408,6 +657,339 @@ pub struct ScoredPoint {
     pub vector: Option<VectorInput>,
 }
 
+// ============================================================================
+// Query API Types
+// ============================================================================
+
+/// Body of `POST /collections/{name}/points/query`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRequest {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefetch: Vec<Prefetch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<QueryInterface>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<SearchParams>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_payload: Option<WithPayload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_vector: Option<WithVector>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_threshold: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prefetch {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefetch: Vec<Prefetch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<QueryInterface>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<SearchParams>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QueryInterface {
+    Fusion { fusion: FusionMethod },
+    Nearest { nearest: VectorOrDocument },
+    Plain(VectorOrDocument),
+}
+
+impl QueryInterface {
+    /// What a nearest-neighbour query is near to
+    pub fn nearest(&self) -> Option<&VectorOrDocument> {
+        match self {
+            QueryInterface::Fusion { .. } => None,
+            QueryInterface::Nearest { nearest } | QueryInterface::Plain(nearest) => Some(nearest),
+        }
+    }
+}
+
+/// A dense vector, or a document whose text is matched by BM25
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VectorOrDocument {
+    Dense(Vec<f32>),
+    Document(DocumentQuery),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentQuery {
+    pub text: String,
+    /// Ignored: text is always ranked by BM25 over the collection's text
+    /// indexes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionMethod {
+    Rrf,
+    Dbsf,
+}
+
+/// Distribution-based score fusion is served by weighted fusion, which
+/// rescales each ranking by its range rather than its spread
+impl From<FusionMethod> for Fusion {
+    fn from(method: FusionMethod) -> Self {
+        match method {
+            FusionMethod::Rrf => Fusion::Rrf,
+            FusionMethod::Dbsf => Fusion::Weighted,
+        }
+    }
+}
+
+/// One ranking a query runs
+#[derive(Debug, Clone)]
+pub struct QueryRanking<T> {
+    pub query: T,
+    pub filter: Option<PayloadFilter>,
+    pub limit: usize,
+    pub options: SearchOptions,
+}
+
+/// The rankings a query runs and how they fuse
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    pub vector: Option<QueryRanking<Vec<f32>>>,
+    pub text: Option<QueryRanking<String>>,
+    /// How prefetched rankings fuse; `None` for a single ranking
+    pub fusion: Option<Fusion>,
+    /// Results wanted, including those skipped by the offset
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl QueryRequest {
+    /// The rankings this query runs
+    ///
+    /// A query is either one nearest-neighbour search, by vector or by
+    /// document text, or a fusion of prefetched searches: at most one by
+    /// vector and one by text, without nested prefetches. A prefetch's
+    /// filter applies to its own ranking, the query's filter to all.
+    pub fn plan(&self) -> Result<QueryPlan> {
+        let offset = self.offset.unwrap_or(0);
+        let limit = self.limit.unwrap_or(10).saturating_add(offset);
+        let mut plan = QueryPlan {
+            vector: None,
+            text: None,
+            fusion: None,
+            limit,
+            offset,
+        };
+        let filter = self.filter.as_ref().map(PayloadFilter::from);
+        let options = self
+            .params
+            .as_ref()
+            .map(SearchParams::options)
+            .unwrap_or_default();
+
+        match self.query {
+            Some(QueryInterface::Fusion { fusion }) => {
+                if self.prefetch.is_empty() {
+                    return Err(invalid("Fusion needs prefetch queries"));
+                }
+                plan.fusion = Some(fusion.into());
+                for prefetch in &self.prefetch {
+                    if !prefetch.prefetch.is_empty() {
+                        return Err(invalid("Nested prefetch queries are not supported"));
+                    }
+                    let nearest = prefetch
+                        .query
+                        .as_ref()
+                        .and_then(QueryInterface::nearest)
+                        .ok_or_else(|| invalid("Prefetch queries must be vectors or documents"))?;
+                    let filter = match (&filter, &prefetch.filter) {
+                        (Some(outer), Some(inner)) => Some(PayloadFilter::all(vec![
+                            outer.clone(),
+                            PayloadFilter::from(inner),
+                        ])),
+                        (outer, inner) => outer.clone().or_else(|| inner.as_ref().map(Into::into)),
+                    };
+                    let limit = prefetch.limit.unwrap_or(limit).max(limit);
+                    let options = prefetch
+                        .params
+                        .as_ref()
+                        .map_or(options, SearchParams::options);
+                    plan.add(nearest, filter, limit, options)?;
+                }
+            }
+            Some(ref query) => {
+                if !self.prefetch.is_empty() {
+                    return Err(invalid("Rescoring prefetched points is not supported"));
+                }
+                let nearest = query
+                    .nearest()
+                    .ok_or_else(|| invalid("Unsupported query"))?;
+                plan.add(nearest, filter, limit, options)?;
+            }
+            None => return Err(invalid("A query is required")),
+        }
+        Ok(plan)
+    }
+}
+
+impl QueryPlan {
+    fn add(
+        &mut self,
+        nearest: &VectorOrDocument,
+        filter: Option<PayloadFilter>,
+        limit: usize,
+        options: SearchOptions,
+    ) -> Result<()> {
+        match nearest {
+            VectorOrDocument::Dense(vector) => {
+                let ranking = QueryRanking {
+                    query: vector.clone(),
+                    filter,
+                    limit,
+                    options,
+                };
+                if self.vector.replace(ranking).is_some() {
+                    return Err(invalid("At most one vector query can be fused"));
+                }
+            }
+            VectorOrDocument::Document(document) => {
+                let ranking = QueryRanking {
+                    query: document.text.clone(),
+                    filter,
+                    limit,
+                    options,
+                };
+                if self.text.replace(ranking).is_some() {
+                    return Err(invalid("At most one document query can be fused"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> CompatError {
+    CompatError::InvalidRequest(message.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResponse {
+    pub points: Vec<ScoredPoint>,
+}
+
+// ============================================================================
+// Batch & Recommend Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchRequestBatch {
+    pub searches: Vec<SearchRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecommendRequest {
+    #[serde(default)]
+    pub positive: Vec<RecommendExample>,
+    #[serde(default)]
+    pub negative: Vec<RecommendExample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<RecommendStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    pub limit: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_payload: Option<WithPayload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_vector: Option<WithVector>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_threshold: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<SearchParams>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
+}
+
+/// A point to recommend by, or against: stored, or given as a vector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecommendExample {
+    Id(PointId),
+    Vector(Vec<f32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendStrategy {
+    AverageVector,
+    BestScore,
+    SumScores,
+}
+
+impl RecommendRequest {
+    /// The vector an average-vector recommendation searches near, given
+    /// the vectors of its examples: the mean of the positive ones, moved
+    /// away from the mean of the negative ones by as much again
+    pub fn average_vector(positive: &[Vec<f32>], negative: &[Vec<f32>]) -> Result<Vec<f32>> {
+        let positive =
+            mean(positive)?.ok_or_else(|| invalid("At least one positive example is required"))?;
+        match mean(negative)? {
+            Some(negative) if negative.len() != positive.len() => {
+                Err(CompatError::DimensionMismatch {
+                    expected: positive.len(),
+                    actual: negative.len(),
+                })
+            }
+            Some(negative) => Ok(positive
+                .iter()
+                .zip(&negative)
+                .map(|(p, n)| p + (p - n))
+                .collect()),
+            None => Ok(positive),
+        }
+    }
+}
+
+/// Component-wise mean of vectors, if there are any
+fn mean(vectors: &[Vec<f32>]) -> Result<Option<Vec<f32>>> {
+    let Some(first) = vectors.first() else {
+        return Ok(None);
+    };
+    let mut sum = vec![0.0; first.len()];
+    for vector in vectors {
+        if vector.len() != sum.len() {
+            return Err(CompatError::DimensionMismatch {
+                expected: sum.len(),
+                actual: vector.len(),
+            });
+        }
+        for (total, x) in sum.iter_mut().zip(vector) {
+            *total += x;
+        }
+    }
+    let n = vectors.len() as f32;
+    Ok(Some(sum.into_iter().map(|total| total / n).collect()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecommendRequestBatch {
+    pub searches: Vec<RecommendRequest>,
+}
+
 // ============================================================================
 // Filter Types
 // ============================================================================
@@ -519,6 +1101,122 @@ pub struct GeoRadius {
     pub radius: f64,
 }
 
+impl From<&Filter> for PayloadFilter {
+    fn from(filter: &Filter) -> Self {
+        let conditions = |conditions: &Option<Vec<Condition>>| -> Vec<PayloadFilter> {
+            conditions
+                .iter()
+                .flatten()
+                .map(PayloadFilter::from)
+                .collect()
+        };
+
+        let mut all = conditions(&filter.must);
+        let should = conditions(&filter.should);
+        if !should.is_empty() {
+            all.push(PayloadFilter::any(should));
+        }
+        let must_not = conditions(&filter.must_not);
+        if !must_not.is_empty() {
+            all.push(PayloadFilter::negate(PayloadFilter::any(must_not)));
+        }
+        PayloadFilter::all(all)
+    }
+}
+
+impl From<&Condition> for PayloadFilter {
+    fn from(condition: &Condition) -> Self {
+        match condition {
+            Condition::Field(field) => field.into(),
+            Condition::IsEmpty(c) => PayloadFilter::IsEmpty {
+                key: c.is_empty.key.clone(),
+            },
+            Condition::IsNull(c) => PayloadFilter::IsNull {
+                key: c.is_null.key.clone(),
+            },
+            Condition::HasId(c) => {
+                PayloadFilter::HasId(c.has_id.iter().map(ToString::to_string).collect())
+            }
+            Condition::Nested(c) => PayloadFilter::Nested {
+                key: c.nested.key.clone(),
+                filter: Box::new((&c.nested.filter).into()),
+            },
+            Condition::Filter(filter) => filter.into(),
+        }
+    }
+}
+
+impl From<&FieldCondition> for PayloadFilter {
+    fn from(condition: &FieldCondition) -> Self {
+        let key = condition.key.clone();
+        let mut all = Vec::new();
+        if let Some(ref value) = condition.r#match {
+            let matching = |values| PayloadFilter::Match {
+                key: key.clone(),
+                values,
+            };
+            all.push(match value {
+                MatchValue::Keyword(m) => matching(vec![m.value.clone().into()]),
+                MatchValue::Integer(m) => matching(vec![m.value.into()]),
+                MatchValue::Bool(m) => matching(vec![m.value.into()]),
+                MatchValue::Text(m) => PayloadFilter::Text {
+                    key: key.clone(),
+                    text: m.text.clone(),
+                },
+                MatchValue::Any(m) => matching(m.any.clone()),
+                MatchValue::Except(m) => PayloadFilter::negate(matching(m.except.clone())),
+            });
+        }
+        if let Some(ref range) = condition.range {
+            all.push(PayloadFilter::Range {
+                key: key.clone(),
+                range: NumberRange {
+                    gt: range.gt,
+                    gte: range.gte,
+                    lt: range.lt,
+                    lte: range.lte,
+                },
+            });
+        }
+        if let Some(ref geo) = condition.geo_radius {
+            all.push(PayloadFilter::GeoRadius {
+                key: key.clone(),
+                center: (&geo.center).into(),
+                radius: geo.radius,
+            });
+        }
+        if let Some(ref geo) = condition.geo_bounding_box {
+            all.push(PayloadFilter::GeoBoundingBox {
+                key: key.clone(),
+                top_left: (&geo.top_left).into(),
+                bottom_right: (&geo.bottom_right).into(),
+            });
+        }
+        if let Some(ref count) = condition.values_count {
+            let bound = |b: Option<usize>| b.map(|b| b as f64);
+            all.push(PayloadFilter::ValuesCount {
+                key,
+                range: NumberRange {
+                    gt: bound(count.gt),
+                    gte: bound(count.gte),
+                    lt: bound(count.lt),
+                    lte: bound(count.lte),
+                },
+            });
+        }
+        PayloadFilter::all(all)
+    }
+}
+
+impl From<&GeoPoint> for vector::GeoPoint {
+    fn from(point: &GeoPoint) -> Self {
+        vector::GeoPoint {
+            lat: point.lat,
+            lon: point.lon,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValuesCount {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -654,3 +1352,355 @@ pub struct CollectionsList {
 pub struct CollectionDescription {
     pub name: String,
 }
+
+// ============================================================================
+// Alias Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeAliases {
+    pub actions: Vec<AliasOperation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasOperation {
+    CreateAlias(CreateAlias),
+    DeleteAlias(DeleteAlias),
+    RenameAlias(RenameAlias),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAlias {
+    pub collection_name: String,
+    pub alias_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteAlias {
+    pub alias_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameAlias {
+    pub old_alias_name: String,
+    pub new_alias_name: String,
+}
+
+impl ChangeAliases {
+    /// The aliases, alias to collection, after the actions in order, or
+    /// the first action that cannot be taken
+    pub fn apply(
+        &self,
+        aliases: &BTreeMap<String, String>,
+        is_collection: impl Fn(&str) -> bool,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut aliases = aliases.clone();
+        let free = |aliases: &BTreeMap<String, String>, name: &str| {
+            if aliases.contains_key(name) {
+                Err(invalid(&format!("Alias {} already exists", name)))
+            } else if is_collection(name) {
+                Err(invalid(&format!("{} is the name of a collection", name)))
+            } else {
+                Ok(())
+            }
+        };
+        for action in &self.actions {
+            match action {
+                AliasOperation::CreateAlias(create) => {
+                    if !is_collection(&create.collection_name) {
+                        return Err(CompatError::CollectionNotFound(
+                            create.collection_name.clone(),
+                        ));
+                    }
+                    free(&aliases, &create.alias_name)?;
+                    aliases.insert(create.alias_name.clone(), create.collection_name.clone());
+                }
+                AliasOperation::DeleteAlias(delete) => {
+                    if aliases.remove(&delete.alias_name).is_none() {
+                        return Err(invalid(&format!("Alias {} not found", delete.alias_name)));
+                    }
+                }
+                AliasOperation::RenameAlias(rename) => {
+                    let collection = aliases.remove(&rename.old_alias_name).ok_or_else(|| {
+                        invalid(&format!("Alias {} not found", rename.old_alias_name))
+                    })?;
+                    free(&aliases, &rename.new_alias_name)?;
+                    aliases.insert(rename.new_alias_name.clone(), collection);
+                }
+            }
+        }
+        Ok(aliases)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasDescription {
+    pub alias_name: String,
+    pub collection_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionsAliases {
+    pub aliases: Vec<AliasDescription>,
+}
+
+// ============================================================================
+// Snapshot Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecover {
+    /// `file://`, `http://` or `https://` URL, or local path, of the
+    /// snapshot file
+    pub location: String,
+    /// SHA-256 the snapshot file must have, in hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Sent in the `api-key` header when downloading the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_translation() {
+        let filter: Filter = serde_json::from_value(json!({
+            "must": [
+                { "key": "city", "match": { "value": "London" } },
+                { "key": "price", "range": { "gte": 10.0, "lt": 20.0 } }
+            ],
+            "should": [
+                { "key": "color", "match": { "any": ["red", "blue"] } },
+                { "has_id": [7, "abc"] }
+            ],
+            "must_not": [
+                { "is_empty": { "key": "tags" } },
+                { "nested": { "key": "offers", "filter": {
+                    "must": [{ "key": "shop", "match": { "value": "x" } }]
+                } } }
+            ]
+        }))
+        .unwrap();
+        let filter = PayloadFilter::from(&filter);
+
+        let point = |color: &str, price: i64, tags: serde_json::Value, shop: &str| {
+            json!({
+                "city": "London",
+                "price": price,
+                "color": color,
+                "tags": tags,
+                "offers": [{ "shop": shop }]
+            })
+        };
+        let tags = || json!(["new"]);
+        assert!(filter.matches("1", &point("blue", 12, tags(), "y")));
+        assert!(!filter.matches("1", &point("blue", 12, json!([]), "y")));
+        // `should` is met by the ID instead of the color
+        assert!(filter.matches("7", &point("green", 12, tags(), "y")));
+        assert!(!filter.matches("1", &point("green", 12, tags(), "y")));
+        assert!(!filter.matches("1", &point("blue", 20, tags(), "y")));
+        assert!(!filter.matches("1", &point("blue", 12, tags(), "x")));
+    }
+
+    #[test]
+    fn test_quantization_config() {
+        let create: CreateCollection = serde_json::from_value(json!({
+            "vectors": { "size": 128, "distance": "Cosine" },
+            "quantization_config": {
+                "scalar": { "type": "int8", "quantile": 0.99, "always_ram": true }
+            }
+        }))
+        .unwrap();
+        let scalar = create
+            .quantization_config
+            .unwrap()
+            .to_quantization(128)
+            .unwrap();
+        assert_eq!(scalar, VectorQuantization::Scalar { quantile: 0.99 });
+
+        let product: QuantizationConfig =
+            serde_json::from_value(json!({ "product": { "compression": "x16" } })).unwrap();
+        let product = product.to_quantization(128).unwrap();
+        assert_eq!(product, VectorQuantization::Product { segments: 32 });
+        let described = serde_json::to_value(QuantizationConfig::from_quantization(product, 128));
+        assert_eq!(
+            described.unwrap(),
+            json!({ "product": { "compression": "x16", "always_ram": true } })
+        );
+
+        let binary: QuantizationConfig =
+            serde_json::from_value(json!({ "binary": { "always_ram": false } })).unwrap();
+        assert_eq!(
+            binary.to_quantization(128).unwrap(),
+            VectorQuantization::Binary
+        );
+
+        let unsupported: QuantizationConfig =
+            serde_json::from_value(json!({ "product": { "compression": "x3" } })).unwrap();
+        assert!(unsupported.to_quantization(128).is_err());
+    }
+
+    #[test]
+    fn test_query_plan() {
+        let request: QueryRequest = serde_json::from_value(json!({
+            "prefetch": [
+                { "query": [0.1, 0.2], "limit": 40 },
+                {
+                    "query": { "text": "red shoes", "model": "qdrant/bm25" },
+                    "filter": { "must": [{ "key": "lang", "match": { "value": "en" } }] }
+                }
+            ],
+            "query": { "fusion": "dbsf" },
+            "filter": { "must": [{ "key": "stock", "range": { "gt": 0 } }] },
+            "limit": 5,
+            "offset": 5
+        }))
+        .unwrap();
+        let plan = request.plan().unwrap();
+        assert!(matches!(plan.fusion, Some(Fusion::Weighted)));
+        assert_eq!((plan.limit, plan.offset), (10, 5));
+        let vector = plan.vector.unwrap();
+        assert_eq!(vector.query, [0.1, 0.2]);
+        assert_eq!(vector.limit, 40);
+        let text = plan.text.unwrap();
+        assert_eq!(text.query, "red shoes");
+        assert_eq!(text.limit, 10);
+
+        // The prefetch filter narrows only its own ranking
+        let point = json!({ "stock": 3, "lang": "de" });
+        assert!(vector.filter.unwrap().matches("1", &point));
+        assert!(!text.filter.unwrap().matches("1", &point));
+
+        let single: QueryRequest =
+            serde_json::from_value(json!({ "query": { "nearest": [1.0, 0.0] } })).unwrap();
+        let plan = single.plan().unwrap();
+        assert!(plan.fusion.is_none() && plan.text.is_none());
+        assert_eq!(plan.vector.unwrap().limit, 10);
+
+        let invalid = [
+            json!({ "query": { "fusion": "rrf" } }),
+            json!({
+                "prefetch": [{ "query": [1.0] }, { "query": [2.0] }],
+                "query": { "fusion": "rrf" }
+            }),
+            json!({ "prefetch": [{ "query": [1.0] }], "query": [1.0] }),
+            json!({ "limit": 3 }),
+        ];
+        for request in invalid {
+            let request: QueryRequest = serde_json::from_value(request).unwrap();
+            assert!(request.plan().is_err());
+        }
+    }
+
+    #[test]
+    fn test_payload_updates() {
+        let mut payload = json!({ "a": 1, "meta": { "lang": "en", "tags": ["x"] } })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        let set: SetPayload =
+            serde_json::from_value(json!({ "payload": { "b": 2 }, "points": [1, "p2"] })).unwrap();
+        assert_eq!(set.target.points.as_ref().map(Vec::len), Some(2));
+        set.merge_into(&mut payload);
+        assert_eq!(payload["a"], 1);
+        assert_eq!(payload["b"], 2);
+
+        let nested: SetPayload = serde_json::from_value(json!({
+            "payload": { "source": "web" },
+            "key": "meta.origin",
+            "filter": { "must": [{ "key": "a", "match": { "value": 1 } }] }
+        }))
+        .unwrap();
+        assert!(nested.target.filter.is_some() && nested.target.points.is_none());
+        nested.merge_into(&mut payload);
+        assert_eq!(payload["meta"]["origin"]["source"], "web");
+        assert_eq!(payload["meta"]["lang"], "en");
+
+        let delete: DeletePayload =
+            serde_json::from_value(json!({ "keys": ["b", "meta.lang", "missing.key"] })).unwrap();
+        delete.remove_from(&mut payload);
+        assert!(payload.get("b").is_none());
+        assert_eq!(
+            payload["meta"],
+            json!({ "tags": ["x"], "origin": { "source": "web" } })
+        );
+
+        nested.overwrite(&mut payload);
+        assert_eq!(payload["meta"]["origin"], json!({ "source": "web" }));
+        set.overwrite(&mut payload);
+        assert_eq!(serde_json::Value::Object(payload), json!({ "b": 2 }));
+    }
+
+    #[test]
+    fn test_recommend_vector() {
+        let request: RecommendRequest = serde_json::from_value(json!({
+            "positive": [7, "c0ffee", [1.0, 0.0]],
+            "negative": [[0.0, 1.0]],
+            "limit": 3
+        }))
+        .unwrap();
+        assert!(matches!(
+            request.positive[0],
+            RecommendExample::Id(PointId::Num(7))
+        ));
+        assert!(matches!(
+            request.positive[1],
+            RecommendExample::Id(PointId::Uuid(_))
+        ));
+        assert!(matches!(request.positive[2], RecommendExample::Vector(_)));
+
+        let positive = [vec![1.0, 0.0], vec![3.0, 0.0]];
+        let query = RecommendRequest::average_vector(&positive, &[vec![0.0, 2.0]]).unwrap();
+        assert_eq!(query, [4.0, -2.0]);
+        assert_eq!(
+            RecommendRequest::average_vector(&positive, &[]).unwrap(),
+            [2.0, 0.0]
+        );
+        assert!(RecommendRequest::average_vector(&[], &[vec![1.0, 0.0]]).is_err());
+        assert!(RecommendRequest::average_vector(&positive, &[vec![1.0]]).is_err());
+    }
+
+    #[test]
+    fn test_alias_changes() {
+        let is_collection = |name: &str| name == "docs" || name == "docs_v2";
+        let change = |actions: serde_json::Value| {
+            serde_json::from_value::<ChangeAliases>(json!({ "actions": actions })).unwrap()
+        };
+
+        let aliases = change(json!([
+            { "create_alias": { "collection_name": "docs", "alias_name": "live" } },
+            { "create_alias": { "collection_name": "docs", "alias_name": "old" } },
+            { "rename_alias": { "old_alias_name": "old", "new_alias_name": "previous" } }
+        ]))
+        .apply(&BTreeMap::new(), is_collection)
+        .unwrap();
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["previous"], "docs");
+
+        // Switching an alias to another collection in one request
+        let switched = change(json!([
+            { "delete_alias": { "alias_name": "live" } },
+            { "create_alias": { "collection_name": "docs_v2", "alias_name": "live" } }
+        ]))
+        .apply(&aliases, is_collection)
+        .unwrap();
+        assert_eq!(switched["live"], "docs_v2");
+
+        let invalid = [
+            json!([{ "create_alias": { "collection_name": "nope", "alias_name": "a" } }]),
+            json!([{ "create_alias": { "collection_name": "docs", "alias_name": "live" } }]),
+            json!([{ "create_alias": { "collection_name": "docs", "alias_name": "docs_v2" } }]),
+            json!([{ "delete_alias": { "alias_name": "missing" } }]),
+            json!([{ "rename_alias": { "old_alias_name": "live", "new_alias_name": "previous" } }]),
+        ];
+        for actions in invalid {
+            assert!(change(actions).apply(&aliases, is_collection).is_err());
+        }
+    }
+}
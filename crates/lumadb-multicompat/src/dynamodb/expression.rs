@@ -0,0 +1,886 @@
+//! DynamoDB expression language
+//!
+//! Parses and evaluates condition expressions (used by `ConditionExpression`
+//! and `FilterExpression`) and projection expressions.
+//!
+//! Conditions support the comparators `= <> < <= > >=`, `BETWEEN`, `IN`,
+//! `AND`/`OR`/`NOT`, parentheses and the functions `attribute_exists`,
+//! `attribute_not_exists`, `attribute_type`, `begins_with`, `contains` and
+//! `size`. Document paths may name nested map attributes (`a.b`) and list
+//! elements (`a[0]`), and use `#name` placeholders from
+//! `ExpressionAttributeNames`; `:value` placeholders come from
+//! `ExpressionAttributeValues`.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::core::{AdapterError, Row, Value};
+
+use super::translator::parse_dynamodb_value;
+
+/// Type names accepted by `attribute_type`
+const ATTRIBUTE_TYPES: [&str; 10] = ["S", "SS", "N", "NS", "B", "BS", "BOOL", "NULL", "L", "M"];
+
+/// Words that cannot start a document path
+const KEYWORDS: [&str; 5] = ["AND", "OR", "NOT", "BETWEEN", "IN"];
+
+/// One step of a document path
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathElement {
+    /// Attribute of an item or map
+    Attribute(String),
+    /// Element of a list
+    Index(usize),
+}
+
+/// Path to an attribute, possibly nested in maps and lists
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentPath(pub Vec<PathElement>);
+
+impl DocumentPath {
+    /// The value at this path in `item`, if there is one
+    #[must_use]
+    pub fn resolve<'a>(&self, item: &'a Row) -> Option<&'a Value> {
+        let (first, rest) = self.0.split_first()?;
+        let PathElement::Attribute(name) = first else {
+            return None;
+        };
+        rest.iter()
+            .try_fold(item.get(name)?, |value, element| match (element, value) {
+                (PathElement::Attribute(name), Value::Object(map)) => map.get(name),
+                (PathElement::Index(index), Value::Array(list)) => list.get(*index),
+                _ => None,
+            })
+    }
+}
+
+/// Comparison operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+/// Operand of a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// Attribute of the item
+    Path(DocumentPath),
+    /// Value from `ExpressionAttributeValues`
+    Value(Value),
+    /// `size(path)`
+    Size(DocumentPath),
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, item: &'a Row) -> Option<Cow<'a, Value>> {
+        match self {
+            Operand::Path(path) => path.resolve(item).map(Cow::Borrowed),
+            Operand::Value(value) => Some(Cow::Borrowed(value)),
+            Operand::Size(path) => path
+                .resolve(item)
+                .and_then(size)
+                .map(|size| Cow::Owned(Value::Integer(size))),
+        }
+    }
+}
+
+/// A parsed condition expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare(Operand, Comparator, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    AttributeExists(DocumentPath),
+    AttributeNotExists(DocumentPath),
+    AttributeType(DocumentPath, String),
+    BeginsWith(DocumentPath, Operand),
+    Contains(DocumentPath, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    /// Parse a condition expression, resolving its placeholders
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the expression is malformed or uses an undefined
+    /// placeholder.
+    pub fn parse(
+        expression: &str,
+        attr_names: Option<&Map<String, JsonValue>>,
+        attr_values: Option<&Map<String, JsonValue>>,
+    ) -> Result<Self, AdapterError> {
+        let mut parser = Parser::new(expression, attr_names, attr_values)?;
+        let condition = parser.parse_or()?;
+        parser.finish()?;
+        Ok(condition)
+    }
+
+    /// Whether `item` satisfies the condition. A missing item is an empty
+    /// row, so only `attribute_not_exists` and `<>` hold for it
+    #[must_use]
+    pub fn evaluate(&self, item: &Row) -> bool {
+        match self {
+            Condition::Compare(left, comparator, right) => {
+                match (left.resolve(item), right.resolve(item)) {
+                    (Some(left), Some(right)) => compare(&left, *comparator, &right),
+                    _ => *comparator == Comparator::NotEqual,
+                }
+            }
+            Condition::Between(operand, low, high) => {
+                match (operand.resolve(item), low.resolve(item), high.resolve(item)) {
+                    (Some(value), Some(low), Some(high)) => {
+                        compare(&value, Comparator::GreaterThanOrEqual, &low)
+                            && compare(&value, Comparator::LessThanOrEqual, &high)
+                    }
+                    _ => false,
+                }
+            }
+            Condition::In(operand, candidates) => operand.resolve(item).is_some_and(|value| {
+                candidates.iter().any(|candidate| {
+                    candidate
+                        .resolve(item)
+                        .is_some_and(|candidate| equals(&value, &candidate))
+                })
+            }),
+            Condition::AttributeExists(path) => path.resolve(item).is_some(),
+            Condition::AttributeNotExists(path) => path.resolve(item).is_none(),
+            Condition::AttributeType(path, type_name) => path
+                .resolve(item)
+                .is_some_and(|value| type_of(value) == type_name),
+            Condition::BeginsWith(path, prefix) => {
+                match (path.resolve(item), prefix.resolve(item).as_deref()) {
+                    (Some(Value::String(s)), Some(Value::String(prefix))) => s.starts_with(prefix),
+                    (Some(Value::Bytes(b)), Some(Value::Bytes(prefix))) => b.starts_with(prefix),
+                    _ => false,
+                }
+            }
+            Condition::Contains(path, operand) => {
+                match (path.resolve(item), operand.resolve(item)) {
+                    (Some(value), Some(operand)) => contains(value, &operand),
+                    _ => false,
+                }
+            }
+            Condition::And(left, right) => left.evaluate(item) && right.evaluate(item),
+            Condition::Or(left, right) => left.evaluate(item) || right.evaluate(item),
+            Condition::Not(condition) => !condition.evaluate(item),
+        }
+    }
+}
+
+/// Attributes picked out by a projection expression
+#[derive(Debug, Clone, PartialEq)]
+enum Selection {
+    /// The whole value
+    All,
+    /// Some attributes of a map, in the order first named
+    Fields(Vec<(String, Selection)>),
+    /// Some elements of a list
+    Items(BTreeMap<usize, Selection>),
+}
+
+impl Selection {
+    fn from_path(path: &[PathElement]) -> Self {
+        match path.split_first() {
+            None => Selection::All,
+            Some((PathElement::Attribute(name), rest)) => {
+                Selection::Fields(vec![(name.clone(), Selection::from_path(rest))])
+            }
+            Some((PathElement::Index(index), rest)) => {
+                Selection::Items(BTreeMap::from([(*index, Selection::from_path(rest))]))
+            }
+        }
+    }
+
+    fn add(&mut self, path: &[PathElement]) -> Result<(), AdapterError> {
+        match (self, path.split_first()) {
+            (Selection::All, _) | (_, None) => Err(AdapterError::InvalidRequest(
+                "Two document paths overlap with each other".into(),
+            )),
+            (Selection::Fields(fields), Some((PathElement::Attribute(name), rest))) => {
+                if let Some((_, selection)) = fields.iter_mut().find(|(field, _)| field == name) {
+                    selection.add(rest)
+                } else {
+                    fields.push((name.clone(), Selection::from_path(rest)));
+                    Ok(())
+                }
+            }
+            (Selection::Items(items), Some((PathElement::Index(index), rest))) => {
+                match items.entry(*index) {
+                    Entry::Occupied(entry) => entry.into_mut().add(rest),
+                    Entry::Vacant(entry) => {
+                        entry.insert(Selection::from_path(rest));
+                        Ok(())
+                    }
+                }
+            }
+            _ => Err(AdapterError::InvalidRequest(
+                "Two document paths conflict with each other".into(),
+            )),
+        }
+    }
+
+    fn pick(&self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (Selection::All, value) => Some(value.clone()),
+            (Selection::Fields(fields), Value::Object(map)) => {
+                let picked: HashMap<String, Value> = fields
+                    .iter()
+                    .filter_map(|(name, selection)| {
+                        let value = selection.pick(map.get(name)?)?;
+                        Some((name.clone(), value))
+                    })
+                    .collect();
+                (!picked.is_empty()).then_some(Value::Object(picked))
+            }
+            (Selection::Items(items), Value::Array(list)) => {
+                let picked: Vec<Value> = items
+                    .iter()
+                    .filter_map(|(index, selection)| selection.pick(list.get(*index)?))
+                    .collect();
+                (!picked.is_empty()).then_some(Value::Array(picked))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A parsed projection expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    attributes: Vec<(String, Selection)>,
+}
+
+impl Projection {
+    /// Parse a projection expression, resolving its `#name` placeholders
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the expression is malformed or uses an undefined
+    /// placeholder.
+    pub fn parse(
+        expression: &str,
+        attr_names: Option<&Map<String, JsonValue>>,
+    ) -> Result<Self, AdapterError> {
+        let mut parser = Parser::new(expression, attr_names, None)?;
+        let mut root = Selection::Fields(Vec::new());
+        loop {
+            root.add(&parser.parse_path()?.0)?;
+            if !parser.eat(&Token::Comma) {
+                break;
+            }
+        }
+        parser.finish()?;
+        let Selection::Fields(attributes) = root else {
+            unreachable!("paths start with an attribute name");
+        };
+        Ok(Self { attributes })
+    }
+
+    /// The parts of `row` named by the projection. List elements keep their
+    /// order but not their positions, as in DynamoDB
+    #[must_use]
+    pub fn apply(&self, row: &Row) -> Row {
+        self.attributes
+            .iter()
+            .filter_map(|(name, selection)| {
+                let value = selection.pick(row.get(name)?)?;
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+// ===== Evaluation =====
+
+/// DynamoDB type name of a value
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "NULL",
+        Value::Bool(_) => "BOOL",
+        Value::Integer(_) | Value::Float(_) => "N",
+        Value::String(_) => "S",
+        Value::Bytes(_) => "B",
+        Value::Array(_) => "L",
+        Value::Object(_) => "M",
+        Value::StringSet(_) => "SS",
+        Value::NumberSet(_) => "NS",
+        Value::BinarySet(_) => "BS",
+    }
+}
+
+/// `size()` of a value; numbers, booleans and nulls have none
+fn size(value: &Value) -> Option<i64> {
+    let size = match value {
+        Value::String(s) => s.chars().count(),
+        Value::Bytes(b) => b.len(),
+        Value::Array(list) => list.len(),
+        Value::Object(map) => map.len(),
+        Value::StringSet(set) => set.len(),
+        Value::NumberSet(set) => set.len(),
+        Value::BinarySet(set) => set.len(),
+        Value::Null | Value::Bool(_) | Value::Integer(_) | Value::Float(_) => return None,
+    };
+    i64::try_from(size).ok()
+}
+
+#[allow(clippy::cast_precision_loss)] // DynamoDB numbers compare as decimals
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Order of two numbers, strings or binaries; other values are unordered
+fn order(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+        _ => number(left)?.partial_cmp(&number(right)?),
+    }
+}
+
+/// Whether two values are equal; numbers compare by value and sets
+/// ignore order
+fn equals(left: &Value, right: &Value) -> bool {
+    fn same_set<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+        a.len() == b.len() && a.iter().all(|x| b.contains(x))
+    }
+
+    match (left, right) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equals(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|w| equals(v, w)))
+        }
+        (Value::StringSet(a), Value::StringSet(b)) => same_set(a, b),
+        (Value::NumberSet(a), Value::NumberSet(b)) => same_set(a, b),
+        (Value::BinarySet(a), Value::BinarySet(b)) => same_set(a, b),
+        _ => match (number(left), number(right)) {
+            (Some(_), Some(_)) => order(left, right) == Some(Ordering::Equal),
+            _ => left == right,
+        },
+    }
+}
+
+fn compare(left: &Value, comparator: Comparator, right: &Value) -> bool {
+    match comparator {
+        Comparator::Equal => equals(left, right),
+        Comparator::NotEqual => !equals(left, right),
+        Comparator::LessThan => order(left, right) == Some(Ordering::Less),
+        Comparator::LessThanOrEqual => {
+            matches!(order(left, right), Some(Ordering::Less | Ordering::Equal))
+        }
+        Comparator::GreaterThan => order(left, right) == Some(Ordering::Greater),
+        Comparator::GreaterThanOrEqual => {
+            matches!(
+                order(left, right),
+                Some(Ordering::Greater | Ordering::Equal)
+            )
+        }
+    }
+}
+
+/// `contains()`: a substring of a string, or a member of a set or list
+fn contains(value: &Value, operand: &Value) -> bool {
+    match (value, operand) {
+        (Value::String(s), Value::String(sub)) => s.contains(sub.as_str()),
+        (Value::StringSet(set), Value::String(s)) => set.contains(s),
+        (Value::NumberSet(set), operand) => number(operand).is_some_and(|n| set.contains(&n)),
+        (Value::BinarySet(set), Value::Bytes(b)) => set.contains(b),
+        (Value::Array(list), operand) => list.iter().any(|element| equals(element, operand)),
+        _ => false,
+    }
+}
+
+// ===== Parsing =====
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Attribute name, keyword or function name
+    Word(String),
+    /// `#name` placeholder
+    Name(String),
+    /// `:value` placeholder
+    Placeholder(String),
+    /// List index
+    Number(usize),
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Dot,
+    Compare(Comparator),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Word(s) | Token::Name(s) | Token::Placeholder(s) => s.clone(),
+            Token::Number(n) => n.to_string(),
+            Token::LeftParen => "(".into(),
+            Token::RightParen => ")".into(),
+            Token::LeftBracket => "[".into(),
+            Token::RightBracket => "]".into(),
+            Token::Comma => ",".into(),
+            Token::Dot => ".".into(),
+            Token::Compare(comparator) => match comparator {
+                Comparator::Equal => "=",
+                Comparator::NotEqual => "<>",
+                Comparator::LessThan => "<",
+                Comparator::LessThanOrEqual => "<=",
+                Comparator::GreaterThan => ">",
+                Comparator::GreaterThanOrEqual => ">=",
+            }
+            .into(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, AdapterError> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '=' => Token::Compare(Comparator::Equal),
+            '<' | '>' => {
+                let comparator = match (c, chars.peek().map(|&(_, next)| next)) {
+                    ('<', Some('>')) => Comparator::NotEqual,
+                    ('<', Some('=')) => Comparator::LessThanOrEqual,
+                    ('>', Some('=')) => Comparator::GreaterThanOrEqual,
+                    ('<', _) => Comparator::LessThan,
+                    _ => Comparator::GreaterThan,
+                };
+                if !matches!(comparator, Comparator::LessThan | Comparator::GreaterThan) {
+                    chars.next();
+                }
+                Token::Compare(comparator)
+            }
+            '#' | ':' | '_' | '0'..='9' | 'a'..='z' | 'A'..='Z' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if !is_word(next) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                let text = &expression[start..end];
+                match c {
+                    '#' | ':' if text.len() == 1 => return Err(syntax_error(text)),
+                    '#' => Token::Name(text.to_string()),
+                    ':' => Token::Placeholder(text.to_string()),
+                    '0'..='9' => Token::Number(text.parse().map_err(|_| syntax_error(text))?),
+                    _ => Token::Word(text.to_string()),
+                }
+            }
+            other => {
+                return Err(AdapterError::InvalidRequest(format!(
+                    "Invalid character in expression: {other}"
+                )))
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn syntax_error(token: &str) -> AdapterError {
+    AdapterError::InvalidRequest(format!("Syntax error; token: \"{token}\""))
+}
+
+/// Recursive descent parser. Precedence, from loosest: `OR`, `AND`, `NOT`,
+/// then comparisons, `BETWEEN`, `IN` and functions
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    attr_names: Option<&'a Map<String, JsonValue>>,
+    attr_values: Option<&'a Map<String, JsonValue>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(
+        expression: &str,
+        attr_names: Option<&'a Map<String, JsonValue>>,
+        attr_values: Option<&'a Map<String, JsonValue>>,
+    ) -> Result<Self, AdapterError> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            return Err(AdapterError::InvalidRequest(
+                "The expression can not be empty".into(),
+            ));
+        }
+        Ok(Self {
+            tokens,
+            pos: 0,
+            attr_names,
+            attr_values,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, AdapterError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| {
+            AdapterError::InvalidRequest("Syntax error; unexpected end of expression".into())
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), AdapterError> {
+        let token = self.next()?;
+        if token == *expected {
+            Ok(())
+        } else {
+            Err(syntax_error(&token.text()))
+        }
+    }
+
+    /// Fail if anything follows the parsed expression
+    fn finish(&self) -> Result<(), AdapterError> {
+        match self.peek() {
+            Some(token) => Err(syntax_error(&token.text())),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the next tokens are a call of the function `name`
+    fn at_function(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word == name)
+            && self.tokens.get(self.pos + 1) == Some(&Token::LeftParen)
+    }
+
+    fn parse_or(&mut self) -> Result<Condition, AdapterError> {
+        let mut condition = self.parse_and()?;
+        while self.eat_keyword("OR") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition, AdapterError> {
+        let mut condition = self.parse_not()?;
+        while self.eat_keyword("AND") {
+            condition = Condition::And(Box::new(condition), Box::new(self.parse_not()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_not(&mut self) -> Result<Condition, AdapterError> {
+        if self.eat_keyword("NOT") {
+            Ok(Condition::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Condition, AdapterError> {
+        if self.eat(&Token::LeftParen) {
+            let condition = self.parse_or()?;
+            self.expect(&Token::RightParen)?;
+            return Ok(condition);
+        }
+        for function in [
+            "attribute_exists",
+            "attribute_not_exists",
+            "attribute_type",
+            "begins_with",
+            "contains",
+        ] {
+            if self.at_function(function) {
+                return self.parse_function(function);
+            }
+        }
+
+        let operand = self.parse_operand()?;
+        match self.next()? {
+            Token::Compare(comparator) => Ok(Condition::Compare(
+                operand,
+                comparator,
+                self.parse_operand()?,
+            )),
+            token if token.is_keyword("BETWEEN") => {
+                let low = self.parse_operand()?;
+                if !self.eat_keyword("AND") {
+                    return Err(syntax_error(&self.next()?.text()));
+                }
+                Ok(Condition::Between(operand, low, self.parse_operand()?))
+            }
+            token if token.is_keyword("IN") => {
+                self.expect(&Token::LeftParen)?;
+                let mut candidates = vec![self.parse_operand()?];
+                while self.eat(&Token::Comma) {
+                    candidates.push(self.parse_operand()?);
+                }
+                self.expect(&Token::RightParen)?;
+                Ok(Condition::In(operand, candidates))
+            }
+            token => Err(syntax_error(&token.text())),
+        }
+    }
+
+    fn parse_function(&mut self, function: &str) -> Result<Condition, AdapterError> {
+        self.pos += 2;
+        let path = self.parse_path()?;
+        let condition = match function {
+            "attribute_exists" => Condition::AttributeExists(path),
+            "attribute_not_exists" => Condition::AttributeNotExists(path),
+            _ => {
+                self.expect(&Token::Comma)?;
+                let operand = self.parse_operand()?;
+                match function {
+                    "attribute_type" => match operand {
+                        Operand::Value(Value::String(type_name))
+                            if ATTRIBUTE_TYPES.contains(&type_name.as_str()) =>
+                        {
+                            Condition::AttributeType(path, type_name)
+                        }
+                        _ => {
+                            return Err(AdapterError::InvalidRequest(
+                                "Invalid attribute type name found in the expression".into(),
+                            ))
+                        }
+                    },
+                    "begins_with" => Condition::BeginsWith(path, operand),
+                    _ => Condition::Contains(path, operand),
+                }
+            }
+        };
+        self.expect(&Token::RightParen)?;
+        Ok(condition)
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, AdapterError> {
+        if self.at_function("size") {
+            self.pos += 2;
+            let path = self.parse_path()?;
+            self.expect(&Token::RightParen)?;
+            return Ok(Operand::Size(path));
+        }
+        if let Some(Token::Placeholder(placeholder)) = self.peek() {
+            let value = self
+                .attr_values
+                .and_then(|values| values.get(placeholder))
+                .ok_or_else(|| {
+                    AdapterError::InvalidRequest(format!(
+                        "An expression attribute value used in expression is not defined; \
+                         attribute value: {placeholder}"
+                    ))
+                })
+                .and_then(parse_dynamodb_value)?;
+            self.pos += 1;
+            return Ok(Operand::Value(value));
+        }
+        self.parse_path().map(Operand::Path)
+    }
+
+    fn parse_path(&mut self) -> Result<DocumentPath, AdapterError> {
+        let mut elements = vec![PathElement::Attribute(self.parse_name()?)];
+        loop {
+            if self.eat(&Token::Dot) {
+                elements.push(PathElement::Attribute(self.parse_name()?));
+            } else if self.eat(&Token::LeftBracket) {
+                match self.next()? {
+                    Token::Number(index) => elements.push(PathElement::Index(index)),
+                    token => return Err(syntax_error(&token.text())),
+                }
+                self.expect(&Token::RightBracket)?;
+            } else {
+                return Ok(DocumentPath(elements));
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, AdapterError> {
+        match self.next()? {
+            Token::Word(word) if !KEYWORDS.iter().any(|k| word.eq_ignore_ascii_case(k)) => Ok(word),
+            Token::Name(name) => self
+                .attr_names
+                .and_then(|names| names.get(&name))
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .ok_or_else(|| {
+                    AdapterError::InvalidRequest(format!(
+                        "An expression attribute name used in the document path is not \
+                         defined; attribute name: {name}"
+                    ))
+                }),
+            token => Err(syntax_error(&token.text())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item() -> Row {
+        let mut row = Row::new();
+        row.push("pk", Value::String("user#1".into()));
+        row.push("age", Value::Integer(30));
+        row.push("score", Value::Float(4.5));
+        row.push("tags", Value::StringSet(vec!["a".into(), "b".into()]));
+        row.push(
+            "address",
+            Value::Object(HashMap::from([
+                ("city".into(), Value::String("Lagos".into())),
+                ("zip".into(), Value::String("100001".into())),
+            ])),
+        );
+        row.push(
+            "orders",
+            Value::Array(vec![
+                Value::Integer(10),
+                Value::Integer(20),
+                Value::Integer(30),
+            ]),
+        );
+        row.push("a.b", Value::Bool(true));
+        row
+    }
+
+    fn check(expression: &str, values: &JsonValue) -> bool {
+        let names = json!({"#dotted": "a.b", "#addr": "address"});
+        Condition::parse(expression, names.as_object(), values.as_object())
+            .unwrap()
+            .evaluate(&item())
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let values = json!({
+            ":age": {"N": "30"}, ":low": {"N": "18"}, ":high": {"N": "65"},
+            ":score": {"N": "4"}, ":a": {"S": "x"}, ":b": {"S": "user#1"}
+        });
+        assert!(check("age = :age", &values));
+        assert!(check("age >= :low AND age <= :high", &values));
+        assert!(check("score > :score", &values));
+        assert!(check("age BETWEEN :low AND :high", &values));
+        assert!(check("pk IN (:a, :b)", &values));
+        assert!(!check("NOT age = :age", &values));
+        assert!(check(
+            "age < :low OR (age > :low AND NOT age > :high)",
+            &values
+        ));
+        assert!(check("missing <> :age", &values));
+        assert!(!check("missing = :age", &values));
+        assert!(!check("pk > :age", &values));
+    }
+
+    #[test]
+    fn test_functions_and_paths() {
+        let values = json!({
+            ":prefix": {"S": "user#"}, ":tag": {"S": "b"}, ":n": {"N": "20"},
+            ":three": {"N": "3"}, ":map": {"S": "M"}, ":city": {"S": "Lagos"}
+        });
+        assert!(check("attribute_exists(address.city)", &values));
+        assert!(check("attribute_not_exists(address.street)", &values));
+        assert!(check("attribute_exists(#dotted)", &values));
+        assert!(!check("attribute_exists(a.b)", &values));
+        assert!(check("attribute_type(#addr, :map)", &values));
+        assert!(check("begins_with(pk, :prefix)", &values));
+        assert!(check(
+            "contains(tags, :tag) AND contains(orders, :n)",
+            &values
+        ));
+        assert!(check("size(orders) = :three AND orders[1] = :n", &values));
+        assert!(check("#addr.city = :city", &values));
+        assert!(!check("attribute_exists(orders[3])", &values));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let values = json!({":v": {"N": "1"}, ":t": {"S": "X"}});
+        for expression in [
+            "age =",
+            "age = :v AND",
+            "(age = :v",
+            "age = :missing",
+            "#missing = :v",
+            "age BETWEEN :v",
+            "attribute_type(age, :t)",
+            "age = :v extra",
+        ] {
+            let result = Condition::parse(expression, None, values.as_object());
+            assert!(result.is_err(), "{expression} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_projection() {
+        let names = json!({"#addr": "address"});
+        let projection = Projection::parse(
+            "pk, #addr.city, orders[2], orders[0], missing",
+            names.as_object(),
+        )
+        .unwrap();
+        let row = projection.apply(&item());
+
+        assert_eq!(row.columns.len(), 3);
+        assert_eq!(row.get("pk"), Some(&Value::String("user#1".into())));
+        assert_eq!(
+            row.get("address"),
+            Some(&Value::Object(HashMap::from([(
+                "city".into(),
+                Value::String("Lagos".into())
+            )])))
+        );
+        assert_eq!(
+            row.get("orders"),
+            Some(&Value::Array(vec![Value::Integer(10), Value::Integer(30)]))
+        );
+
+        assert!(Projection::parse("address, address.city", None).is_err());
+        assert!(Projection::parse("orders[0], orders.x", None).is_err());
+    }
+}
@@ -3,8 +3,11 @@
 //! Full DynamoDB API compatible server using Axum.
 
 pub mod auth;
+pub mod expression;
 pub mod translator;
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -17,6 +20,7 @@ use axum::{
     Json, Router,
 };
 use serde_json::{json, Map, Value as JsonValue};
+use tokio::sync::{Mutex, MutexGuard};
 use tracing::{debug, error, info, instrument};
 
 use crate::core::{
@@ -25,6 +29,7 @@ use crate::core::{
 };
 
 use auth::{validate_signature, AuthConfig};
+use expression::{Condition, Projection};
 use translator::{
     build_query_filter, dynamodb_item_to_row, extract_key_from_item,
     parse_dynamodb_value, row_to_dynamodb_item, value_to_dynamodb,
@@ -52,12 +57,17 @@ impl Default for DynamoDBConfig {
 pub struct DynamoDBServer {
     storage: Arc<dyn StorageEngine>,
     config: DynamoDBConfig,
+    locks: ItemLocks,
 }
 
 impl DynamoDBServer {
     /// Create a new DynamoDB server
     pub fn new(storage: Arc<dyn StorageEngine>, config: DynamoDBConfig) -> Self {
-        Self { storage, config }
+        Self {
+            storage,
+            config,
+            locks: ItemLocks::new(),
+        }
     }
 
     /// Create Axum router
@@ -101,19 +111,21 @@ async fn handle_request(
 
     // Route to handler
     let response = match operation {
-        "PutItem" => handle_put_item(&state.storage, request).await?,
+        "PutItem" => handle_put_item(&state.storage, &state.locks, request).await?,
         "GetItem" => handle_get_item(&state.storage, request).await?,
-        "DeleteItem" => handle_delete_item(&state.storage, request).await?,
-        "UpdateItem" => handle_update_item(&state.storage, request).await?,
+        "DeleteItem" => handle_delete_item(&state.storage, &state.locks, request).await?,
+        "UpdateItem" => handle_update_item(&state.storage, &state.locks, request).await?,
         "Query" => handle_query(&state.storage, request).await?,
         "Scan" => handle_scan(&state.storage, request).await?,
-        "BatchWriteItem" => handle_batch_write_item(&state.storage, request).await?,
+        "BatchWriteItem" => handle_batch_write_item(&state.storage, &state.locks, request).await?,
         "BatchGetItem" => handle_batch_get_item(&state.storage, request).await?,
         "CreateTable" => handle_create_table(&state.storage, request).await?,
         "DeleteTable" => handle_delete_table(&state.storage, request).await?,
         "DescribeTable" => handle_describe_table(&state.storage, request).await?,
         "ListTables" => handle_list_tables(&state.storage, request).await?,
-        "TransactWriteItems" => handle_transact_write(&state.storage, request).await?,
+        "TransactWriteItems" => {
+            handle_transact_write(&state.storage, &state.locks, request).await?
+        }
         _ => return Err(DynamoDBError::UnknownOperation(operation.into())),
     };
 
@@ -121,22 +133,86 @@ async fn handle_request(
     Ok((StatusCode::OK, Json(response)).into_response())
 }
 
+/// Number of locks the items of all tables are spread over
+const ITEM_LOCK_STRIPES: usize = 64;
+
+/// Locks serializing the writes to each item, held from reading the item
+/// for a condition until the write lands so that the condition holds for
+/// the item the write replaces
+struct ItemLocks {
+    stripes: Vec<Mutex<()>>,
+}
+
+impl ItemLocks {
+    fn new() -> Self {
+        Self {
+            stripes: (0..ITEM_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Lock the items a request writes, taking stripes in order so that
+    /// requests writing several items cannot deadlock
+    async fn lock<'a>(
+        &self,
+        items: impl IntoIterator<Item = (&'a str, &'a Value)>,
+    ) -> Vec<MutexGuard<'_, ()>> {
+        let mut stripes: Vec<usize> = items
+            .into_iter()
+            .map(|(table, key)| {
+                let mut hasher = DefaultHasher::new();
+                table.hash(&mut hasher);
+                hash_key(key, &mut hasher);
+                #[allow(clippy::cast_possible_truncation)] // below ITEM_LOCK_STRIPES
+                let stripe = (hasher.finish() % ITEM_LOCK_STRIPES as u64) as usize;
+                stripe
+            })
+            .collect();
+        stripes.sort_unstable();
+        stripes.dedup();
+
+        let mut guards = Vec::with_capacity(stripes.len());
+        for stripe in stripes {
+            guards.push(self.stripes[stripe].lock().await);
+        }
+        guards
+    }
+}
+
+/// Hash a primary key, visiting the attributes of a composite key in name
+/// order
+fn hash_key(key: &Value, hasher: &mut impl Hasher) {
+    match key {
+        Value::Object(attributes) => {
+            let mut names: Vec<&String> = attributes.keys().collect();
+            names.sort();
+            for name in names {
+                name.hash(hasher);
+                hash_key(&attributes[name], hasher);
+            }
+        }
+        other => serde_json::to_string(other)
+            .unwrap_or_default()
+            .hash(hasher),
+    }
+}
+
 // ===== Handler Implementations =====
 
 async fn handle_put_item(
     storage: &Arc<dyn StorageEngine>,
+    locks: &ItemLocks,
     request: JsonValue,
 ) -> Result<JsonValue, DynamoDBError> {
     let table_name = get_required_string(&request, "TableName")?;
     let item = get_required_object(&request, "Item")?;
 
-    let key = extract_key_from_item(item)?;
+    let key = item_key(storage, &table_name, item).await?;
     let row = dynamodb_item_to_row(item)?;
 
-    // Check condition expression if present
-    if let Some(_condition) = request.get("ConditionExpression") {
-        // TODO: Evaluate condition against existing item
-        debug!("ConditionExpression present, skipping validation");
+    let _locked = locks.lock([(table_name.as_str(), &key)]).await;
+    if let Some(condition) = condition_field(&request, "ConditionExpression")? {
+        let existing = storage.execute_kv_get(&table_name, key.clone()).await?;
+        check_condition(&condition, existing.as_ref())?;
     }
 
     storage.execute_kv_put(&table_name, key, row).await?;
@@ -152,11 +228,12 @@ async fn handle_get_item(
     let key = get_required_object(&request, "Key")?;
 
     let key_value = extract_key_from_item(key)?;
+    let projection = projection_field(&request)?;
     let result = storage.execute_kv_get(&table_name, key_value).await?;
 
     match result {
         Some(row) => Ok(json!({
-            "Item": row_to_dynamodb_item(&row)
+            "Item": item_json(&row, projection.as_ref())
         })),
         None => Ok(json!({})),
     }
@@ -164,24 +241,32 @@ async fn handle_get_item(
 
 async fn handle_delete_item(
     storage: &Arc<dyn StorageEngine>,
+    locks: &ItemLocks,
     request: JsonValue,
 ) -> Result<JsonValue, DynamoDBError> {
     let table_name = get_required_string(&request, "TableName")?;
     let key = get_required_object(&request, "Key")?;
 
     let key_value = extract_key_from_item(key)?;
+    let condition = condition_field(&request, "ConditionExpression")?;
+
+    let _locked = locks.lock([(table_name.as_str(), &key_value)]).await;
 
-    // Get old item if ReturnValues specified
+    // Get old item if ReturnValues specified or a condition needs it
     let return_values = request.get("ReturnValues").and_then(|v| v.as_str());
-    let old_item = if return_values == Some("ALL_OLD") {
+    let old_item = if return_values == Some("ALL_OLD") || condition.is_some() {
         storage.execute_kv_get(&table_name, key_value.clone()).await?
     } else {
         None
     };
 
+    if let Some(condition) = &condition {
+        check_condition(condition, old_item.as_ref())?;
+    }
+
     storage.execute_kv_delete(&table_name, key_value).await?;
 
-    match old_item {
+    match old_item.filter(|_| return_values == Some("ALL_OLD")) {
         Some(row) => Ok(json!({
             "Attributes": row_to_dynamodb_item(&row)
         })),
@@ -191,15 +276,24 @@ async fn handle_delete_item(
 
 async fn handle_update_item(
     storage: &Arc<dyn StorageEngine>,
+    locks: &ItemLocks,
     request: JsonValue,
 ) -> Result<JsonValue, DynamoDBError> {
     let table_name = get_required_string(&request, "TableName")?;
     let key = get_required_object(&request, "Key")?;
     let key_value = extract_key_from_item(key)?;
+    let condition = condition_field(&request, "ConditionExpression")?;
 
     // Get existing item
+    let _locked = locks.lock([(table_name.as_str(), &key_value)]).await;
     let existing = storage.execute_kv_get(&table_name, key_value.clone()).await?;
-    let mut row = existing.unwrap_or_else(Row::new);
+    if let Some(condition) = &condition {
+        check_condition(condition, existing.as_ref())?;
+    }
+    let mut row = match existing {
+        Some(row) => row,
+        None => dynamodb_item_to_row(key)?,
+    };
 
     // Apply UpdateExpression
     if let Some(expr) = request.get("UpdateExpression").and_then(|v| v.as_str()) {
@@ -229,13 +323,7 @@ async fn handle_query(
     let filter = build_query_filter(&request)?;
     
     let rows = storage.execute_kv_query(&table_name, filter).await?;
-    let items: Vec<JsonValue> = rows.iter().map(row_to_dynamodb_item).collect();
-
-    Ok(json!({
-        "Items": items,
-        "Count": items.len(),
-        "ScannedCount": items.len()
-    }))
+    filtered_items(&request, &rows)
 }
 
 async fn handle_scan(
@@ -251,17 +339,12 @@ async fn handle_scan(
     };
     
     let rows = storage.execute_kv_query(&table_name, filter).await?;
-    let items: Vec<JsonValue> = rows.iter().map(row_to_dynamodb_item).collect();
-
-    Ok(json!({
-        "Items": items,
-        "Count": items.len(),
-        "ScannedCount": items.len()
-    }))
+    filtered_items(&request, &rows)
 }
 
 async fn handle_batch_write_item(
     storage: &Arc<dyn StorageEngine>,
+    locks: &ItemLocks,
     request: JsonValue,
 ) -> Result<JsonValue, DynamoDBError> {
     let request_items = request.get("RequestItems")
@@ -280,7 +363,7 @@ async fn handle_batch_write_item(
                     .and_then(|v| v.as_object())
                     .ok_or_else(|| DynamoDBError::InvalidRequest("PutRequest missing Item".into()))?;
                 
-                let key = extract_key_from_item(item)?;
+                let key = item_key(storage, table_name, item).await?;
                 let row = dynamodb_item_to_row(item)?;
                 
                 operations.push(BatchOperation::Put {
@@ -305,6 +388,7 @@ async fn handle_batch_write_item(
         }
     }
 
+    let _locked = locks.lock(operations.iter().map(operation_item)).await;
     storage.batch_write(operations).await?;
 
     Ok(json!({
@@ -326,6 +410,7 @@ async fn handle_batch_get_item(
         let keys = table_req.get("Keys")
             .and_then(|v| v.as_array())
             .ok_or_else(|| DynamoDBError::InvalidRequest("Missing Keys".into()))?;
+        let projection = projection_field(table_req)?;
 
         let mut items = Vec::new();
         for key_obj in keys {
@@ -334,7 +419,7 @@ async fn handle_batch_get_item(
             
             let key = extract_key_from_item(key_map)?;
             if let Some(row) = storage.execute_kv_get(table_name, key).await? {
-                items.push(row_to_dynamodb_item(&row));
+                items.push(item_json(&row, projection.as_ref()));
             }
         }
 
@@ -349,6 +434,7 @@ async fn handle_batch_get_item(
 
 async fn handle_transact_write(
     storage: &Arc<dyn StorageEngine>,
+    locks: &ItemLocks,
     request: JsonValue,
 ) -> Result<JsonValue, DynamoDBError> {
     let items = request.get("TransactItems")
@@ -356,52 +442,87 @@ async fn handle_transact_write(
         .ok_or_else(|| DynamoDBError::InvalidRequest("Missing TransactItems".into()))?;
 
     let mut operations = Vec::new();
+    // One condition check per transact item, so reasons line up with items
+    let mut checks = Vec::with_capacity(items.len());
 
     for item in items {
         if let Some(put) = item.get("Put") {
-            let table = put.get("TableName")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| DynamoDBError::InvalidRequest("Put missing TableName".into()))?;
-            
-            let item_obj = put.get("Item")
-                .and_then(|v| v.as_object())
-                .ok_or_else(|| DynamoDBError::InvalidRequest("Put missing Item".into()))?;
+            let table = get_required_string(put, "TableName")?;
+            let item_obj = get_required_object(put, "Item")?;
 
-            let key = extract_key_from_item(item_obj)?;
+            let key = item_key(storage, &table, item_obj).await?;
             let row = dynamodb_item_to_row(item_obj)?;
+            let condition = condition_field(put, "ConditionExpression")?;
 
+            checks.push(condition.map(|c| (table.clone(), key.clone(), c)));
             operations.push(TransactWriteItem {
                 operation: BatchOperation::Put {
-                    table: table.to_string(),
+                    table,
                     key,
                     value: row,
                 },
                 condition_expression: put.get("ConditionExpression").and_then(|v| v.as_str()).map(String::from),
                 expression_values: None,
             });
-        }
-
-        if let Some(delete) = item.get("Delete") {
-            let table = delete.get("TableName")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| DynamoDBError::InvalidRequest("Delete missing TableName".into()))?;
-            
-            let key_obj = delete.get("Key")
-                .and_then(|v| v.as_object())
-                .ok_or_else(|| DynamoDBError::InvalidRequest("Delete missing Key".into()))?;
-
-            let key = extract_key_from_item(key_obj)?;
+        } else if let Some(delete) = item.get("Delete") {
+            let table = get_required_string(delete, "TableName")?;
+            let key = extract_key_from_item(get_required_object(delete, "Key")?)?;
+            let condition = condition_field(delete, "ConditionExpression")?;
 
+            checks.push(condition.map(|c| (table.clone(), key.clone(), c)));
             operations.push(TransactWriteItem {
-                operation: BatchOperation::Delete {
-                    table: table.to_string(),
-                    key,
-                },
+                operation: BatchOperation::Delete { table, key },
                 condition_expression: delete.get("ConditionExpression").and_then(|v| v.as_str()).map(String::from),
                 expression_values: None,
             });
+        } else if let Some(check) = item.get("ConditionCheck") {
+            let table = get_required_string(check, "TableName")?;
+            let key = extract_key_from_item(get_required_object(check, "Key")?)?;
+            let condition = condition_field(check, "ConditionExpression")?.ok_or_else(|| {
+                DynamoDBError::InvalidRequest("ConditionCheck missing ConditionExpression".into())
+            })?;
+
+            checks.push(Some((table, key, condition)));
+        } else {
+            return Err(DynamoDBError::InvalidRequest(
+                "TransactItems entries must be Put, Delete or ConditionCheck".into(),
+            ));
+        }
+    }
+
+    // Check every condition before writing anything, holding every item's
+    // lock until the writes land
+    let written = operations
+        .iter()
+        .map(|item| operation_item(&item.operation));
+    let checked = checks
+        .iter()
+        .flatten()
+        .map(|(table, key, _)| (table.as_str(), key));
+    let _locked = locks.lock(written.chain(checked)).await;
+    let mut reasons = Vec::with_capacity(checks.len());
+    let mut cancelled = false;
+    for check in &checks {
+        let passed = match check {
+            Some((table, key, condition)) => {
+                let existing = storage.execute_kv_get(table, key.clone()).await?;
+                condition.evaluate(existing.as_ref().unwrap_or(&Row::new()))
+            }
+            None => true,
+        };
+        if passed {
+            reasons.push(json!({"Code": "None"}));
+        } else {
+            cancelled = true;
+            reasons.push(json!({
+                "Code": "ConditionalCheckFailed",
+                "Message": "The conditional request failed"
+            }));
         }
     }
+    if cancelled {
+        return Err(DynamoDBError::TransactionCanceled(reasons));
+    }
 
     storage.transact_write(operations).await?;
 
@@ -498,6 +619,15 @@ async fn handle_list_tables(
 
 // ===== Helper Functions =====
 
+/// Table and primary key of the item a batch operation writes
+fn operation_item(operation: &BatchOperation) -> (&str, &Value) {
+    match operation {
+        BatchOperation::Put { table, key, .. } | BatchOperation::Delete { table, key } => {
+            (table.as_str(), key)
+        }
+    }
+}
+
 fn get_required_string(request: &JsonValue, field: &str) -> Result<String, DynamoDBError> {
     request.get(field)
         .and_then(|v| v.as_str())
@@ -511,6 +641,109 @@ fn get_required_object<'a>(request: &'a JsonValue, field: &str) -> Result<&'a Ma
         .ok_or_else(|| DynamoDBError::InvalidRequest(format!("Missing required field: {}", field)))
 }
 
+/// Primary key of an item: its key attributes under the table's key schema,
+/// or the whole item for a table that does not exist yet
+async fn item_key(
+    storage: &Arc<dyn StorageEngine>,
+    table: &str,
+    item: &Map<String, JsonValue>,
+) -> Result<Value, DynamoDBError> {
+    let schema = match storage.describe_table(table).await {
+        Ok(schema) => schema,
+        Err(AdapterError::NotFound(_)) => return Ok(extract_key_from_item(item)?),
+        Err(e) => return Err(e.into()),
+    };
+    let key_names = std::iter::once(&schema.key_schema.partition_key)
+        .chain(schema.key_schema.sort_key.as_ref());
+
+    let mut key = Map::new();
+    for name in key_names {
+        let value = item.get(name).ok_or_else(|| {
+            DynamoDBError::InvalidRequest(format!("Missing the key {name} in the item"))
+        })?;
+        key.insert(name.clone(), value.clone());
+    }
+    Ok(extract_key_from_item(&key)?)
+}
+
+/// Parse a condition expression field of a request, such as
+/// `ConditionExpression` or `FilterExpression`
+fn condition_field(request: &JsonValue, field: &str) -> Result<Option<Condition>, DynamoDBError> {
+    let Some(expr) = request.get(field).and_then(|v| v.as_str()) else {
+        return Ok(None);
+    };
+    let attr_names = request
+        .get("ExpressionAttributeNames")
+        .and_then(|v| v.as_object());
+    let attr_values = request
+        .get("ExpressionAttributeValues")
+        .and_then(|v| v.as_object());
+
+    Condition::parse(expr, attr_names, attr_values)
+        .map(Some)
+        .map_err(|e| invalid_expression(field, e))
+}
+
+/// Parse the `ProjectionExpression` of a request
+fn projection_field(request: &JsonValue) -> Result<Option<Projection>, DynamoDBError> {
+    let Some(expr) = request.get("ProjectionExpression").and_then(|v| v.as_str()) else {
+        return Ok(None);
+    };
+    let attr_names = request
+        .get("ExpressionAttributeNames")
+        .and_then(|v| v.as_object());
+
+    Projection::parse(expr, attr_names)
+        .map(Some)
+        .map_err(|e| invalid_expression("ProjectionExpression", e))
+}
+
+fn invalid_expression(field: &str, e: AdapterError) -> DynamoDBError {
+    match e {
+        AdapterError::InvalidRequest(msg) => {
+            DynamoDBError::InvalidRequest(format!("Invalid {field}: {msg}"))
+        }
+        e => e.into(),
+    }
+}
+
+/// Fail unless the condition holds for the existing item
+fn check_condition(condition: &Condition, existing: Option<&Row>) -> Result<(), DynamoDBError> {
+    if condition.evaluate(existing.unwrap_or(&Row::new())) {
+        Ok(())
+    } else {
+        Err(DynamoDBError::ConditionCheckFailed(
+            "The conditional request failed".into(),
+        ))
+    }
+}
+
+fn item_json(row: &Row, projection: Option<&Projection>) -> JsonValue {
+    match projection {
+        Some(projection) => row_to_dynamodb_item(&projection.apply(row)),
+        None => row_to_dynamodb_item(row),
+    }
+}
+
+/// Query/Scan response: `FilterExpression` applies after the items are read,
+/// so `ScannedCount` counts them all and `Count` only those that match
+fn filtered_items(request: &JsonValue, rows: &[Row]) -> Result<JsonValue, DynamoDBError> {
+    let filter = condition_field(request, "FilterExpression")?;
+    let projection = projection_field(request)?;
+
+    let items: Vec<JsonValue> = rows
+        .iter()
+        .filter(|row| filter.as_ref().map_or(true, |f| f.evaluate(row)))
+        .map(|row| item_json(row, projection.as_ref()))
+        .collect();
+
+    Ok(json!({
+        "Items": items,
+        "Count": items.len(),
+        "ScannedCount": rows.len()
+    }))
+}
+
 fn apply_update_expression(
     row: &mut Row,
     expr: &str,
@@ -565,6 +798,14 @@ pub enum DynamoDBError {
     #[error("ConditionalCheckFailedException: {0}")]
     ConditionCheckFailed(String),
 
+    /// Cancellation reasons, one per transact item
+    #[error(
+        "TransactionCanceledException: Transaction cancelled, please refer cancellation \
+         reasons for specific reasons [{}]",
+        reason_codes(.0)
+    )]
+    TransactionCanceled(Vec<JsonValue>),
+
     #[error("InternalServerError: {0}")]
     InternalError(String),
 }
@@ -581,6 +822,14 @@ impl From<AdapterError> for DynamoDBError {
     }
 }
 
+fn reason_codes(reasons: &[JsonValue]) -> String {
+    reasons
+        .iter()
+        .map(|reason| reason["Code"].as_str().unwrap_or("None"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 impl IntoResponse for DynamoDBError {
     fn into_response(self) -> Response {
         let (status, error_type) = match &self {
@@ -588,14 +837,273 @@ impl IntoResponse for DynamoDBError {
             DynamoDBError::UnknownOperation(_) => (StatusCode::BAD_REQUEST, "UnknownOperationException"),
             DynamoDBError::ResourceNotFound(_) => (StatusCode::BAD_REQUEST, "ResourceNotFoundException"),
             DynamoDBError::ConditionCheckFailed(_) => (StatusCode::BAD_REQUEST, "ConditionalCheckFailedException"),
+            DynamoDBError::TransactionCanceled(_) => (StatusCode::BAD_REQUEST, "TransactionCanceledException"),
             DynamoDBError::InternalError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "InternalServerError"),
         };
 
-        let body = json!({
+        let mut body = json!({
             "__type": error_type,
             "message": self.to_string()
         });
+        if let DynamoDBError::TransactionCanceled(reasons) = self {
+            body["CancellationReasons"] = JsonValue::Array(reasons);
+        }
 
         (status, Json(body)).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::UnifiedResult;
+    use crate::storage::LumaStorage;
+    use async_trait::async_trait;
+
+    /// Storage whose item reads take a while, so that writes racing on an
+    /// item overlap between checking a condition and writing
+    struct SlowReads(LumaStorage);
+
+    #[async_trait]
+    impl StorageEngine for SlowReads {
+        async fn execute_sql(
+            &self,
+            sql: &str,
+            params: Vec<Value>,
+        ) -> Result<UnifiedResult, AdapterError> {
+            self.0.execute_sql(sql, params).await
+        }
+
+        async fn execute_kv_get(
+            &self,
+            table: &str,
+            key: Value,
+        ) -> Result<Option<Row>, AdapterError> {
+            let item = self.0.execute_kv_get(table, key).await;
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            item
+        }
+
+        async fn execute_kv_put(
+            &self,
+            table: &str,
+            key: Value,
+            value: Row,
+        ) -> Result<(), AdapterError> {
+            self.0.execute_kv_put(table, key, value).await
+        }
+
+        async fn execute_kv_delete(&self, table: &str, key: Value) -> Result<(), AdapterError> {
+            self.0.execute_kv_delete(table, key).await
+        }
+
+        async fn execute_kv_query(
+            &self,
+            table: &str,
+            filter: QueryFilter,
+        ) -> Result<Vec<Row>, AdapterError> {
+            self.0.execute_kv_query(table, filter).await
+        }
+
+        async fn batch_write(&self, operations: Vec<BatchOperation>) -> Result<(), AdapterError> {
+            self.0.batch_write(operations).await
+        }
+
+        async fn transact_write(
+            &self,
+            operations: Vec<TransactWriteItem>,
+        ) -> Result<(), AdapterError> {
+            self.0.transact_write(operations).await
+        }
+
+        async fn create_table(&self, table: &str, schema: TableSchema) -> Result<(), AdapterError> {
+            self.0.create_table(table, schema).await
+        }
+
+        async fn delete_table(&self, table: &str) -> Result<(), AdapterError> {
+            self.0.delete_table(table).await
+        }
+
+        async fn list_tables(&self) -> Result<Vec<String>, AdapterError> {
+            self.0.list_tables().await
+        }
+
+        async fn describe_table(&self, table: &str) -> Result<TableSchema, AdapterError> {
+            self.0.describe_table(table).await
+        }
+    }
+
+    async fn users_table() -> (Arc<dyn StorageEngine>, ItemLocks) {
+        users_table_in(Arc::new(LumaStorage::new())).await
+    }
+
+    async fn users_table_in(
+        storage: Arc<dyn StorageEngine>,
+    ) -> (Arc<dyn StorageEngine>, ItemLocks) {
+        let locks = ItemLocks::new();
+        handle_create_table(
+            &storage,
+            json!({
+                "TableName": "users",
+                "KeySchema": [{"AttributeName": "pk", "KeyType": "HASH"}]
+            }),
+        )
+        .await
+        .unwrap();
+        for (pk, age) in [("u1", "25"), ("u2", "40"), ("u3", "61")] {
+            handle_put_item(
+                &storage,
+                &locks,
+                json!({
+                    "TableName": "users",
+                    "Item": {
+                        "pk": {"S": pk},
+                        "age": {"N": age},
+                        "profile": {"M": {"city": {"S": "Lagos"}, "zip": {"S": "100001"}}}
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+        }
+        (storage, locks)
+    }
+
+    #[tokio::test]
+    async fn test_conditional_writes() {
+        let (storage, locks) = users_table().await;
+        let put = json!({
+            "TableName": "users",
+            "Item": {"pk": {"S": "u1"}, "age": {"N": "26"}},
+            "ConditionExpression": "attribute_not_exists(pk)"
+        });
+        let err = handle_put_item(&storage, &locks, put).await.unwrap_err();
+        assert!(matches!(err, DynamoDBError::ConditionCheckFailed(_)));
+
+        let update = json!({
+            "TableName": "users",
+            "Key": {"pk": {"S": "u1"}},
+            "UpdateExpression": "SET age = :new",
+            "ConditionExpression": "age = :old",
+            "ExpressionAttributeValues": {":old": {"N": "25"}, ":new": {"N": "26"}}
+        });
+        handle_update_item(&storage, &locks, update).await.unwrap();
+
+        let get = json!({
+            "TableName": "users",
+            "Key": {"pk": {"S": "u1"}},
+            "ProjectionExpression": "age, #p.city",
+            "ExpressionAttributeNames": {"#p": "profile"}
+        });
+        let item = handle_get_item(&storage, get).await.unwrap();
+        assert_eq!(
+            item["Item"],
+            json!({"age": {"N": "26"}, "profile": {"M": {"city": {"S": "Lagos"}}}})
+        );
+
+        let delete = json!({
+            "TableName": "users",
+            "Key": {"pk": {"S": "u2"}},
+            "ConditionExpression": "age < :max",
+            "ExpressionAttributeValues": {":max": {"N": "30"}}
+        });
+        let err = handle_delete_item(&storage, &locks, delete)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DynamoDBError::ConditionCheckFailed(_)));
+
+        let bad = json!({
+            "TableName": "users",
+            "Key": {"pk": {"S": "u2"}},
+            "ConditionExpression": "age <"
+        });
+        let err = handle_delete_item(&storage, &locks, bad).await.unwrap_err();
+        assert!(matches!(err, DynamoDBError::InvalidRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_scan_filter() {
+        let (storage, _) = users_table().await;
+        let scan = json!({
+            "TableName": "users",
+            "FilterExpression": "age BETWEEN :low AND :high AND begins_with(pk, :prefix)",
+            "ProjectionExpression": "pk",
+            "ExpressionAttributeValues": {
+                ":low": {"N": "30"}, ":high": {"N": "65"}, ":prefix": {"S": "u"}
+            }
+        });
+        let response = handle_scan(&storage, scan).await.unwrap();
+        assert_eq!(response["Count"], 2);
+        assert_eq!(response["ScannedCount"], 3);
+        for item in response["Items"].as_array().unwrap() {
+            assert_eq!(item.as_object().unwrap().len(), 1);
+            assert_ne!(item["pk"], json!({"S": "u1"}));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_cancellation_reasons() {
+        let (storage, locks) = users_table().await;
+        let transaction = json!({
+            "TransactItems": [
+                {"Put": {
+                    "TableName": "users",
+                    "Item": {"pk": {"S": "u4"}, "age": {"N": "19"}},
+                    "ConditionExpression": "attribute_not_exists(pk)"
+                }},
+                {"ConditionCheck": {
+                    "TableName": "users",
+                    "Key": {"pk": {"S": "u3"}},
+                    "ConditionExpression": "age < :max",
+                    "ExpressionAttributeValues": {":max": {"N": "60"}}
+                }}
+            ]
+        });
+        let err = handle_transact_write(&storage, &locks, transaction)
+            .await
+            .unwrap_err();
+        match err {
+            DynamoDBError::TransactionCanceled(reasons) => {
+                assert_eq!(reasons[0]["Code"], "None");
+                assert_eq!(reasons[1]["Code"], "ConditionalCheckFailed");
+            }
+            other => panic!("expected a cancelled transaction, got {other:?}"),
+        }
+
+        let get = json!({"TableName": "users", "Key": {"pk": {"S": "u4"}}});
+        let item = handle_get_item(&storage, get).await.unwrap();
+        assert!(item.get("Item").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_racing_conditional_puts() {
+        let (storage, locks) = users_table_in(Arc::new(SlowReads(LumaStorage::new()))).await;
+        let locks = Arc::new(locks);
+        let puts: Vec<_> = (0..16)
+            .map(|n| {
+                let storage = storage.clone();
+                let locks = locks.clone();
+                let put = json!({
+                    "TableName": "users",
+                    "Item": {"pk": {"S": "u9"}, "age": {"N": n.to_string()}},
+                    "ConditionExpression": "attribute_not_exists(pk)"
+                });
+                tokio::spawn(async move { handle_put_item(&storage, &locks, put).await })
+            })
+            .collect();
+
+        let mut winners = Vec::new();
+        for (n, put) in puts.into_iter().enumerate() {
+            match put.await.unwrap() {
+                Ok(_) => winners.push(n),
+                Err(DynamoDBError::ConditionCheckFailed(_)) => {}
+                Err(other) => panic!("unexpected error {other:?}"),
+            }
+        }
+        assert_eq!(winners.len(), 1);
+
+        let get = json!({"TableName": "users", "Key": {"pk": {"S": "u9"}}});
+        let item = handle_get_item(&storage, get).await.unwrap();
+        assert_eq!(item["Item"]["age"], json!({"N": winners[0].to_string()}));
+    }
+}
@@ -0,0 +1,397 @@
+//! ACL administration APIs
+//!
+//! DescribeAcls, CreateAcls and DeleteAcls manage the bindings of the
+//! security manager's authorizer in Kafka's terms. Describing needs
+//! `describe` on the cluster, creating and deleting needs `alter`. Only
+//! topic, group, transactional id and cluster bindings are visible here;
+//! collection bindings are managed through the REST API.
+
+use lumadb_common::error::Result;
+use lumadb_security::authz::{
+    AclBinding, AclBindingFilter, Operation, PatternFilter, PatternType, PermissionType,
+    ResourceType,
+};
+use lumadb_security::Session;
+
+use super::codec::{Decoder, Encoder};
+use super::error;
+use super::{Broker, RequestHeader};
+
+/// Filter code matching any resource type, pattern, operation or
+/// permission
+const ANY: i8 = 1;
+
+const RESOURCE_TOPIC: i8 = 2;
+const RESOURCE_GROUP: i8 = 3;
+const RESOURCE_CLUSTER: i8 = 4;
+const RESOURCE_TRANSACTIONAL_ID: i8 = 5;
+
+const PATTERN_MATCH: i8 = 2;
+const PATTERN_LITERAL: i8 = 3;
+const PATTERN_PREFIXED: i8 = 4;
+
+const PERMISSION_DENY: i8 = 2;
+const PERMISSION_ALLOW: i8 = 3;
+
+/// Resource types ACLs can name through the Kafka protocol
+const KAFKA_RESOURCES: &[ResourceType] = &[
+    ResourceType::Topic,
+    ResourceType::Group,
+    ResourceType::Cluster,
+    ResourceType::TransactionalId,
+];
+
+/// An ACL binding or binding filter as it travels on the wire
+struct WireAcl {
+    resource_type: i8,
+    resource_name: Option<String>,
+    pattern_type: i8,
+    principal: Option<String>,
+    host: Option<String>,
+    operation: i8,
+    permission: i8,
+}
+
+impl WireAcl {
+    /// Decode a binding, or a filter whose string fields are nullable
+    fn decode(dec: &mut Decoder<'_>, version: i16, flexible: bool, filter: bool) -> Result<Self> {
+        let string = |dec: &mut Decoder<'_>| {
+            if filter {
+                dec.nullable_string(flexible)
+            } else {
+                dec.string(flexible).map(Some)
+            }
+        };
+        let resource_type = dec.i8()?;
+        let resource_name = string(dec)?;
+        let pattern_type = if version >= 1 {
+            dec.i8()?
+        } else {
+            PATTERN_LITERAL
+        };
+        let principal = string(dec)?;
+        let host = string(dec)?;
+        Ok(Self {
+            resource_type,
+            resource_name,
+            pattern_type,
+            principal,
+            host,
+            operation: dec.i8()?,
+            permission: dec.i8()?,
+        })
+    }
+
+    /// The binding to create
+    fn binding(&self) -> std::result::Result<AclBinding, String> {
+        let pattern_type = match self.pattern_type {
+            PATTERN_LITERAL => PatternType::Literal,
+            PATTERN_PREFIXED => PatternType::Prefixed,
+            other => return Err(format!("Invalid pattern type {other}")),
+        };
+        let binding = AclBinding {
+            resource_type: resource_type(self.resource_type)?,
+            resource_name: self.resource_name.clone().unwrap_or_default(),
+            pattern_type,
+            principal: self.principal.clone().unwrap_or_default(),
+            host: self.host.clone().unwrap_or_default(),
+            operation: operation(self.operation)?,
+            permission: match self.permission {
+                PERMISSION_DENY => PermissionType::Deny,
+                PERMISSION_ALLOW => PermissionType::Allow,
+                other => return Err(format!("Invalid permission type {other}")),
+            },
+        };
+        binding.validate().map_err(|e| e.to_string())?;
+        Ok(binding)
+    }
+
+    /// The filters selecting the same bindings: one per Kafka resource
+    /// type when the filter matches any
+    fn filters(&self) -> std::result::Result<Vec<AclBindingFilter>, String> {
+        let filter = AclBindingFilter {
+            resource_type: None,
+            resource_name: self.resource_name.clone(),
+            pattern_type: match self.pattern_type {
+                ANY => PatternFilter::Any,
+                PATTERN_MATCH => PatternFilter::Match,
+                PATTERN_LITERAL => PatternFilter::Literal,
+                PATTERN_PREFIXED => PatternFilter::Prefixed,
+                other => return Err(format!("Invalid pattern type {other}")),
+            },
+            principal: self.principal.clone(),
+            host: self.host.clone(),
+            operation: match self.operation {
+                ANY => None,
+                code => Some(operation(code)?),
+            },
+            permission: match self.permission {
+                ANY => None,
+                PERMISSION_DENY => Some(PermissionType::Deny),
+                PERMISSION_ALLOW => Some(PermissionType::Allow),
+                other => return Err(format!("Invalid permission type {other}")),
+            },
+        };
+        let resource_types = match self.resource_type {
+            ANY => KAFKA_RESOURCES.to_vec(),
+            code => vec![resource_type(code)?],
+        };
+        Ok(resource_types
+            .into_iter()
+            .map(|resource_type| AclBindingFilter {
+                resource_type: Some(resource_type),
+                ..filter.clone()
+            })
+            .collect())
+    }
+}
+
+fn resource_type(code: i8) -> std::result::Result<ResourceType, String> {
+    match code {
+        RESOURCE_TOPIC => Ok(ResourceType::Topic),
+        RESOURCE_GROUP => Ok(ResourceType::Group),
+        RESOURCE_CLUSTER => Ok(ResourceType::Cluster),
+        RESOURCE_TRANSACTIONAL_ID => Ok(ResourceType::TransactionalId),
+        other => Err(format!("Invalid resource type {other}")),
+    }
+}
+
+fn resource_type_code(resource_type: ResourceType) -> i8 {
+    match resource_type {
+        ResourceType::Topic => RESOURCE_TOPIC,
+        ResourceType::Group => RESOURCE_GROUP,
+        ResourceType::Cluster => RESOURCE_CLUSTER,
+        ResourceType::TransactionalId => RESOURCE_TRANSACTIONAL_ID,
+        // Never selected by the filters above
+        ResourceType::Collection => 0,
+    }
+}
+
+fn operation(code: i8) -> std::result::Result<Operation, String> {
+    Ok(match code {
+        2 => Operation::All,
+        3 => Operation::Read,
+        4 => Operation::Write,
+        5 => Operation::Create,
+        6 => Operation::Delete,
+        7 => Operation::Alter,
+        8 => Operation::Describe,
+        9 => Operation::ClusterAction,
+        10 => Operation::DescribeConfigs,
+        11 => Operation::AlterConfigs,
+        12 => Operation::IdempotentWrite,
+        other => return Err(format!("Invalid operation {other}")),
+    })
+}
+
+fn operation_code(operation: Operation) -> i8 {
+    match operation {
+        Operation::All => 2,
+        Operation::Read => 3,
+        Operation::Write => 4,
+        Operation::Create => 5,
+        Operation::Delete => 6,
+        Operation::Alter => 7,
+        Operation::Describe => 8,
+        Operation::ClusterAction => 9,
+        Operation::DescribeConfigs => 10,
+        Operation::AlterConfigs => 11,
+        Operation::IdempotentWrite => 12,
+    }
+}
+
+fn pattern_type_code(pattern_type: PatternType) -> i8 {
+    match pattern_type {
+        PatternType::Literal => PATTERN_LITERAL,
+        PatternType::Prefixed => PATTERN_PREFIXED,
+    }
+}
+
+fn permission_code(permission: PermissionType) -> i8 {
+    match permission {
+        PermissionType::Deny => PERMISSION_DENY,
+        PermissionType::Allow => PERMISSION_ALLOW,
+    }
+}
+
+/// Whether the principal may manage ACLs with `operation`
+fn may(broker: &Broker, header: &RequestHeader, operation: Operation) -> bool {
+    broker.permits(header, ResourceType::Cluster, "", operation)
+}
+
+/// DescribeAcls: bindings matching a filter, grouped by resource pattern
+pub(super) fn describe_acls(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let filter = WireAcl::decode(dec, version, flexible, true)?;
+    dec.tagged_fields(flexible)?;
+
+    let result = if may(broker, header, Operation::Describe) {
+        filter
+            .filters()
+            .map(|filters| {
+                filters
+                    .iter()
+                    .flat_map(|filter| broker.security.describe_acls(filter))
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|message| (error::INVALID_REQUEST, Some(message)))
+    } else {
+        Err((error::CLUSTER_AUTHORIZATION_FAILED, None))
+    };
+    let (code, message, bindings) = match result {
+        Ok(bindings) => (error::NONE, None, bindings),
+        Err((code, message)) => (code, message, Vec::new()),
+    };
+
+    let mut resources: Vec<(&AclBinding, Vec<&AclBinding>)> = Vec::new();
+    for binding in &bindings {
+        let same_pattern = |(first, _): &&mut (&AclBinding, Vec<&AclBinding>)| {
+            first.resource_type == binding.resource_type
+                && first.resource_name == binding.resource_name
+                && first.pattern_type == binding.pattern_type
+        };
+        match resources.iter_mut().find(same_pattern) {
+            Some((_, acls)) => acls.push(binding),
+            None => resources.push((binding, vec![binding])),
+        }
+    }
+
+    enc.i32(0)
+        .i16(code)
+        .nullable_string(message.as_deref(), flexible);
+    enc.array_of(&resources, flexible, |enc, (resource, acls)| {
+        enc.i8(resource_type_code(resource.resource_type))
+            .string(&resource.resource_name, flexible);
+        if version >= 1 {
+            enc.i8(pattern_type_code(resource.pattern_type));
+        }
+        enc.array_of(acls, flexible, |enc, acl| {
+            enc.string(&acl.principal, flexible)
+                .string(&acl.host, flexible)
+                .i8(operation_code(acl.operation))
+                .i8(permission_code(acl.permission))
+                .tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// CreateAcls: add bindings, each succeeding or failing on its own
+pub(super) fn create_acls(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let creations = dec.array_of(flexible, |dec| {
+        let acl = WireAcl::decode(dec, version, flexible, false)?;
+        dec.tagged_fields(flexible)?;
+        Ok(acl)
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let allowed = may(broker, header, Operation::Alter);
+    let anonymous = Session::anonymous();
+    let actor = header.session.as_deref().unwrap_or(&anonymous);
+    let results: Vec<(i16, Option<String>)> = creations
+        .iter()
+        .map(|creation| {
+            if !allowed {
+                return (error::CLUSTER_AUTHORIZATION_FAILED, None);
+            }
+            let binding = match creation.binding() {
+                Ok(binding) => binding,
+                Err(message) => return (error::INVALID_REQUEST, Some(message)),
+            };
+            match broker.security.create_acls(actor, &[binding]) {
+                Ok(()) => (error::NONE, None),
+                Err(e) => (error::UNKNOWN_SERVER_ERROR, Some(e.to_string())),
+            }
+        })
+        .collect();
+
+    enc.i32(0);
+    enc.array_of(&results, flexible, |enc, (code, message)| {
+        enc.i16(*code)
+            .nullable_string(message.as_deref(), flexible)
+            .tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// DeleteAcls: remove the bindings matching each filter
+pub(super) fn delete_acls(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let filters = dec.array_of(flexible, |dec| {
+        let filter = WireAcl::decode(dec, version, flexible, true)?;
+        dec.tagged_fields(flexible)?;
+        Ok(filter)
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let allowed = may(broker, header, Operation::Alter);
+    let anonymous = Session::anonymous();
+    let actor = header.session.as_deref().unwrap_or(&anonymous);
+    let results: Vec<(i16, Option<String>, Vec<AclBinding>)> = filters
+        .iter()
+        .map(|filter| {
+            if !allowed {
+                return (error::CLUSTER_AUTHORIZATION_FAILED, None, Vec::new());
+            }
+            let filters = match filter.filters() {
+                Ok(filters) => filters,
+                Err(message) => return (error::INVALID_REQUEST, Some(message), Vec::new()),
+            };
+            let mut deleted = Vec::new();
+            for filter in &filters {
+                match broker.security.delete_acls(actor, filter) {
+                    Ok(bindings) => deleted.extend(bindings),
+                    Err(e) => return (error::UNKNOWN_SERVER_ERROR, Some(e.to_string()), deleted),
+                }
+            }
+            (error::NONE, None, deleted)
+        })
+        .collect();
+
+    enc.i32(0);
+    enc.array_of(&results, flexible, |enc, (code, message, deleted)| {
+        enc.i16(*code).nullable_string(message.as_deref(), flexible);
+        enc.array_of(deleted, flexible, |enc, acl| {
+            enc.i16(error::NONE)
+                .nullable_string(None, flexible)
+                .i8(resource_type_code(acl.resource_type))
+                .string(&acl.resource_name, flexible);
+            if version >= 1 {
+                enc.i8(pattern_type_code(acl.pattern_type));
+            }
+            enc.string(&acl.principal, flexible)
+                .string(&acl.host, flexible)
+                .i8(operation_code(acl.operation))
+                .i8(permission_code(acl.permission))
+                .tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
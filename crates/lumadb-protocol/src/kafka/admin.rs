@@ -0,0 +1,723 @@
+//! Cluster metadata and topic administration APIs
+//!
+//! Metadata only reveals topics the principal may describe. Creating a
+//! topic needs `create` on it or on the cluster, deleting it `delete`, and
+//! reading configs `describe_configs`.
+
+use std::collections::HashMap;
+
+use tracing::{info, warn};
+
+use lumadb_common::config::StreamingConfig;
+use lumadb_common::error::Result;
+use lumadb_common::types::TopicConfig;
+use lumadb_security::authz::{Operation, ResourceType};
+use lumadb_security::{AuditCategory, AuditEvent};
+use lumadb_streaming::consumer::OFFSETS_TOPIC;
+use lumadb_streaming::log::LogOptions;
+use lumadb_streaming::validate_topic_name;
+
+use super::codec::{Decoder, Encoder};
+use super::error;
+use super::{Broker, RequestHeader, APIS, CLUSTER_ID, NODE_ID};
+
+/// Authorized operations value meaning "not requested"
+const OPERATIONS_OMITTED: i32 = i32::MIN;
+
+const RESOURCE_TOPIC: i8 = 2;
+const RESOURCE_BROKER: i8 = 4;
+
+const SOURCE_DYNAMIC_TOPIC: i8 = 1;
+const SOURCE_STATIC_BROKER: i8 = 4;
+const SOURCE_DEFAULT: i8 = 5;
+
+const TYPE_BOOLEAN: i8 = 1;
+const TYPE_STRING: i8 = 2;
+const TYPE_INT: i8 = 3;
+const TYPE_LONG: i8 = 5;
+const TYPE_LIST: i8 = 7;
+
+/// ApiVersions: the version ranges of every supported API
+///
+/// An unsupported ApiVersions version is answered in the v0 format with
+/// `UNSUPPORTED_VERSION`, which tells the client to retry at a version it
+/// finds in the list.
+pub(super) fn api_versions(header: &RequestHeader, supported: bool, enc: &mut Encoder) {
+    let version = if supported { header.api_version } else { 0 };
+    let flexible = header.flexible;
+
+    enc.i16(if supported {
+        error::NONE
+    } else {
+        error::UNSUPPORTED_VERSION
+    });
+    enc.array_of(APIS, flexible, |enc, spec| {
+        enc.i16(spec.key)
+            .i16(spec.min_version)
+            .i16(spec.max_version)
+            .tagged_fields(flexible);
+    });
+    if version >= 1 {
+        enc.i32(0);
+    }
+    enc.tagged_fields(flexible);
+}
+
+/// Metadata: brokers and the partition layout of requested topics
+pub(super) async fn metadata(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+    let (requested, allow_auto_create) = decode_metadata_request(version, flexible, dec)?;
+
+    let streaming = &broker.streaming;
+    let names: Vec<Option<String>> = if let Some(names) = requested {
+        names
+    } else {
+        let mut names: Vec<String> = streaming
+            .list_topics()
+            .await?
+            .into_iter()
+            .map(|t| t.name)
+            .filter(|name| broker.allows(header, ResourceType::Topic, name, Operation::Describe))
+            .collect();
+        names.sort();
+        names.into_iter().map(Some).collect()
+    };
+
+    let topics = resolve_topics(broker, header, names, allow_auto_create).await;
+
+    if version >= 3 {
+        enc.i32(0);
+    }
+    enc.array_len(1, flexible)
+        .i32(NODE_ID)
+        .string(&broker.config.advertised_host, flexible)
+        .i32(i32::from(broker.config.advertised_port));
+    if version >= 1 {
+        enc.nullable_string(None, flexible);
+    }
+    enc.tagged_fields(flexible);
+    if version >= 2 {
+        enc.nullable_string(Some(CLUSTER_ID), flexible);
+    }
+    if version >= 1 {
+        enc.i32(NODE_ID);
+    }
+
+    enc.array_of(&topics, flexible, |enc, (name, partitions)| {
+        enc.i16(partitions.err().unwrap_or(error::NONE));
+        if version >= 12 {
+            enc.nullable_string(name.as_deref(), flexible);
+        } else {
+            enc.string(name.as_deref().unwrap_or_default(), flexible);
+        }
+        if version >= 10 {
+            enc.uuid([0; 16]);
+        }
+        if version >= 1 {
+            enc.bool(name.as_deref() == Some(OFFSETS_TOPIC)); // is_internal
+        }
+        let count = partitions.unwrap_or(0);
+        enc.array_len(count, flexible);
+        for partition in 0..count {
+            enc.i16(error::NONE)
+                .i32(i32::try_from(partition).unwrap_or(i32::MAX))
+                .i32(NODE_ID);
+            if version >= 7 {
+                enc.i32(0);
+            }
+            enc.array_of(&[NODE_ID], flexible, |enc, id| {
+                enc.i32(*id);
+            });
+            enc.array_of(&[NODE_ID], flexible, |enc, id| {
+                enc.i32(*id);
+            });
+            if version >= 5 {
+                enc.array_len(0, flexible);
+            }
+            enc.tagged_fields(flexible);
+        }
+        if version >= 8 {
+            enc.i32(OPERATIONS_OMITTED);
+        }
+        enc.tagged_fields(flexible);
+    });
+    if (8..=10).contains(&version) {
+        enc.i32(OPERATIONS_OMITTED);
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// Resolve every requested topic to its partition count or an error code,
+/// creating missing topics when allowed
+async fn resolve_topics(
+    broker: &Broker,
+    header: &RequestHeader,
+    names: Vec<Option<String>>,
+    allow_auto_create: bool,
+) -> Vec<(Option<String>, std::result::Result<usize, i16>)> {
+    let streaming = &broker.streaming;
+    let mut topics = Vec::with_capacity(names.len());
+    for name in names {
+        let Some(name) = name else {
+            // Topic ids are not supported; lookups by id always miss
+            topics.push((None, Err(error::UNKNOWN_TOPIC_ID)));
+            continue;
+        };
+        let partitions = match streaming.partitions(&name) {
+            _ if !broker.permits(header, ResourceType::Topic, &name, Operation::Describe) => {
+                Err(error::TOPIC_AUTHORIZATION_FAILED)
+            }
+            Some(partitions) => Ok(partitions.len()),
+            None if validate_topic_name(&name).is_err() => Err(error::INVALID_TOPIC_EXCEPTION),
+            None if allow_auto_create
+                && broker.config.auto_create_topics
+                && may_create_topic(broker, header, &name) =>
+            {
+                auto_create_topic(broker, &name).await
+            }
+            None => Err(error::UNKNOWN_TOPIC_OR_PARTITION),
+        };
+        topics.push((Some(name), partitions));
+    }
+    topics
+}
+
+/// Requested topic names (`None` for every topic; ids in place of names
+/// read as `None` entries) and whether missing topics may be auto-created
+fn decode_metadata_request(
+    version: i16,
+    flexible: bool,
+    dec: &mut Decoder<'_>,
+) -> Result<(Option<Vec<Option<String>>>, bool)> {
+    // A null list (or an empty one before v1) asks for every topic
+    let requested = match dec.array_len(flexible)? {
+        Some(0) if version == 0 => None,
+        Some(len) => {
+            let mut names = Vec::with_capacity(len);
+            for _ in 0..len {
+                if version >= 10 {
+                    dec.uuid()?;
+                }
+                names.push(if version >= 10 {
+                    dec.nullable_string(flexible)?
+                } else {
+                    Some(dec.string(flexible)?)
+                });
+                dec.tagged_fields(flexible)?;
+            }
+            Some(names)
+        }
+        None => None,
+    };
+    let allow_auto_create = if version >= 4 { dec.bool()? } else { true };
+    if (8..=10).contains(&version) {
+        dec.bool()?;
+    }
+    if version >= 8 {
+        dec.bool()?;
+    }
+    dec.tagged_fields(flexible)?;
+    Ok((requested, allow_auto_create))
+}
+
+/// Whether the principal may create the topic, through an ACL on it or on
+/// the cluster
+fn may_create_topic(broker: &Broker, header: &RequestHeader, name: &str) -> bool {
+    broker.allows(header, ResourceType::Cluster, "", Operation::Create)
+        || broker.permits(header, ResourceType::Topic, name, Operation::Create)
+}
+
+/// Create a topic a client asked about, with the engine's default layout
+async fn auto_create_topic(broker: &Broker, name: &str) -> std::result::Result<usize, i16> {
+    let defaults = broker.streaming.config();
+    let config = TopicConfig::new(
+        name,
+        defaults.default_partitions,
+        defaults.default_replication_factor,
+    );
+    match broker.streaming.create_topic(config).await {
+        Ok(()) => info!("Auto-created topic {} for a Kafka client", name),
+        // Lost a race with a concurrent creation
+        Err(_) if broker.streaming.topic_exists(name) => {}
+        Err(e) => {
+            warn!("Failed to auto-create topic {}: {}", name, e);
+            return Err(error::UNKNOWN_SERVER_ERROR);
+        }
+    }
+    broker
+        .streaming
+        .partitions(name)
+        .map(|partitions| partitions.len())
+        .ok_or(error::UNKNOWN_TOPIC_OR_PARTITION)
+}
+
+/// A topic to create, as sent by the client
+struct CreatableTopic {
+    name: String,
+    num_partitions: i32,
+    replication_factor: i16,
+    assignments: usize,
+    configs: HashMap<String, String>,
+}
+
+/// Outcome of creating one topic
+struct CreateTopicResult {
+    name: String,
+    error_code: i16,
+    error_message: Option<String>,
+    config: Option<TopicConfig>,
+}
+
+/// CreateTopics
+pub(super) async fn create_topics(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let requested = dec.array_of(flexible, |dec| decode_creatable_topic(dec, flexible))?;
+    dec.i32()?; // timeout_ms
+    let validate_only = if version >= 1 { dec.bool()? } else { false };
+    dec.tagged_fields(flexible)?;
+
+    let mut results = Vec::with_capacity(requested.len());
+    for topic in requested {
+        let name = topic.name.clone();
+        let outcome = if may_create_topic(broker, header, &name) {
+            let outcome = create_topic(broker, topic, validate_only).await;
+            if !validate_only {
+                let event =
+                    AuditEvent::new(AuditCategory::Ddl, "create").on(format!("topic:{name}"));
+                broker.audit(
+                    header,
+                    match &outcome {
+                        Ok(_) => event,
+                        Err((_, message)) => event.failed(message.clone()),
+                    },
+                );
+            }
+            outcome
+        } else {
+            Err((
+                error::TOPIC_AUTHORIZATION_FAILED,
+                "Authorization failed.".to_string(),
+            ))
+        };
+        let (error_code, error_message, config) = match outcome {
+            Ok(config) => (error::NONE, None, Some(config)),
+            Err((code, message)) => (code, Some(message), None),
+        };
+        results.push(CreateTopicResult {
+            name,
+            error_code,
+            error_message,
+            config,
+        });
+    }
+
+    if version >= 2 {
+        enc.i32(0);
+    }
+    let defaults = broker.streaming.config();
+    enc.array_of(&results, flexible, |enc, result| {
+        enc.string(&result.name, flexible).i16(result.error_code);
+        if version >= 1 {
+            enc.nullable_string(result.error_message.as_deref(), flexible);
+        }
+        if version >= 5 {
+            match &result.config {
+                Some(config) => {
+                    enc.i32(i32::try_from(config.partitions).unwrap_or(i32::MAX))
+                        .i16(i16::try_from(config.replication_factor).unwrap_or(i16::MAX));
+                    let entries = topic_configs(defaults, config).unwrap_or_default();
+                    enc.array_of(&entries, flexible, |enc, entry| {
+                        enc.string(&entry.name, flexible)
+                            .nullable_string(entry.value.as_deref(), flexible)
+                            .bool(false)
+                            .i8(entry.source)
+                            .bool(false)
+                            .tagged_fields(flexible);
+                    });
+                }
+                None => {
+                    enc.i32(-1).i16(-1).null_array(flexible);
+                }
+            }
+        }
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// One topic of a CreateTopics request
+fn decode_creatable_topic(dec: &mut Decoder<'_>, flexible: bool) -> Result<CreatableTopic> {
+    let name = dec.string(flexible)?;
+    let num_partitions = dec.i32()?;
+    let replication_factor = dec.i16()?;
+    let assignments = dec.array_of(flexible, |dec| {
+        dec.i32()?;
+        dec.array_of(flexible, Decoder::i32)?;
+        dec.tagged_fields(flexible)
+    })?;
+    let configs = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let value = dec.nullable_string(flexible)?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, value))
+    })?;
+    dec.tagged_fields(flexible)?;
+    Ok(CreatableTopic {
+        name,
+        num_partitions,
+        replication_factor,
+        assignments: assignments.len(),
+        configs: configs
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect(),
+    })
+}
+
+async fn create_topic(
+    broker: &Broker,
+    topic: CreatableTopic,
+    validate_only: bool,
+) -> std::result::Result<TopicConfig, (i16, String)> {
+    let streaming = &broker.streaming;
+    let defaults = streaming.config();
+
+    if validate_topic_name(&topic.name).is_err() {
+        return Err((
+            error::INVALID_TOPIC_EXCEPTION,
+            format!("Invalid topic name '{}'", topic.name),
+        ));
+    }
+    if streaming.topic_exists(&topic.name) {
+        return Err((
+            error::TOPIC_ALREADY_EXISTS,
+            format!("Topic '{}' already exists", topic.name),
+        ));
+    }
+
+    let partitions = match (topic.assignments, topic.num_partitions) {
+        (0, -1) => defaults.default_partitions,
+        (0, n) => u32::try_from(n).ok().filter(|&n| n > 0).ok_or_else(|| {
+            (
+                error::INVALID_PARTITIONS,
+                "Number of partitions must be positive".to_string(),
+            )
+        })?,
+        (n, _) => u32::try_from(n).unwrap_or(u32::MAX),
+    };
+    let replication_factor = match topic.replication_factor {
+        -1 => defaults.default_replication_factor,
+        n => u32::try_from(n).ok().filter(|&n| n > 0).ok_or_else(|| {
+            (
+                error::INVALID_REPLICATION_FACTOR,
+                "Replication factor must be positive".to_string(),
+            )
+        })?,
+    };
+
+    let mut config = TopicConfig::new(topic.name, partitions, replication_factor);
+    config.config = topic.configs;
+    LogOptions::for_topic(defaults, &config).map_err(|e| (error::INVALID_CONFIG, e.to_string()))?;
+
+    if !validate_only {
+        info!("Creating topic {} through the Kafka protocol", config.name);
+        streaming
+            .create_topic(config.clone())
+            .await
+            .map_err(|e| (error::UNKNOWN_SERVER_ERROR, e.to_string()))?;
+    }
+    Ok(config)
+}
+
+/// DeleteTopics
+pub(super) async fn delete_topics(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let names = dec.array_of(flexible, |dec| dec.string(flexible))?;
+    dec.i32()?; // timeout_ms
+    dec.tagged_fields(flexible)?;
+
+    let mut results = Vec::with_capacity(names.len());
+    for name in names {
+        let outcome = if !broker.permits(header, ResourceType::Topic, &name, Operation::Delete) {
+            (error::TOPIC_AUTHORIZATION_FAILED, None)
+        } else if broker.streaming.topic_exists(&name) {
+            info!("Deleting topic {} through the Kafka protocol", name);
+            let event = AuditEvent::new(AuditCategory::Ddl, "delete").on(format!("topic:{name}"));
+            match broker.streaming.delete_topic(&name).await {
+                Ok(()) => {
+                    broker.audit(header, event);
+                    (error::NONE, None)
+                }
+                Err(e) => {
+                    broker.audit(header, event.failed(e.to_string()));
+                    (error::UNKNOWN_SERVER_ERROR, Some(e.to_string()))
+                }
+            }
+        } else {
+            (error::UNKNOWN_TOPIC_OR_PARTITION, None)
+        };
+        results.push((name, outcome));
+    }
+
+    if version >= 1 {
+        enc.i32(0);
+    }
+    enc.array_of(&results, flexible, |enc, (name, (code, message))| {
+        enc.string(name, flexible).i16(*code);
+        if version >= 5 {
+            enc.nullable_string(message.as_deref(), flexible);
+        }
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// A configuration value as reported by DescribeConfigs
+#[derive(Clone)]
+struct ConfigEntry {
+    name: String,
+    value: Option<String>,
+    source: i8,
+    config_type: i8,
+}
+
+impl ConfigEntry {
+    fn new(name: &str, value: &(impl ToString + ?Sized), source: i8, config_type: i8) -> Self {
+        Self {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            source,
+            config_type,
+        }
+    }
+}
+
+/// Effective log settings of a topic, plus any other keys it was created
+/// with
+fn topic_configs(defaults: &StreamingConfig, topic: &TopicConfig) -> Result<Vec<ConfigEntry>> {
+    let options = LogOptions::for_topic(defaults, topic)?;
+    let source = |key: &str, typed: bool| {
+        if typed || topic.config.contains_key(key) {
+            SOURCE_DYNAMIC_TOPIC
+        } else {
+            SOURCE_DEFAULT
+        }
+    };
+    let limit = |value: Option<u64>| value.map_or(-1, |v| i64::try_from(v).unwrap_or(i64::MAX));
+
+    let mut entries = vec![
+        ConfigEntry::new(
+            "cleanup.policy",
+            options.cleanup_policy.as_str(),
+            source("cleanup.policy", false),
+            TYPE_LIST,
+        ),
+        ConfigEntry::new(
+            "retention.ms",
+            &limit(options.retention_ms),
+            source("retention.ms", topic.retention_ms.is_some()),
+            TYPE_LONG,
+        ),
+        ConfigEntry::new(
+            "retention.bytes",
+            &limit(options.retention_bytes),
+            source("retention.bytes", topic.retention_bytes.is_some()),
+            TYPE_LONG,
+        ),
+        ConfigEntry::new(
+            "segment.bytes",
+            &options.segment_bytes,
+            source("segment.bytes", topic.segment_bytes.is_some()),
+            TYPE_INT,
+        ),
+        ConfigEntry::new(
+            "segment.ms",
+            &options.segment_ms,
+            source("segment.ms", false),
+            TYPE_LONG,
+        ),
+        ConfigEntry::new(
+            "delete.retention.ms",
+            &options.delete_retention_ms,
+            source("delete.retention.ms", topic.delete_retention_ms.is_some()),
+            TYPE_LONG,
+        ),
+        ConfigEntry::new(
+            "index.interval.bytes",
+            &options.index_interval_bytes,
+            SOURCE_DEFAULT,
+            TYPE_INT,
+        ),
+    ];
+    let mut extra: Vec<_> = topic
+        .config
+        .iter()
+        .filter(|(key, _)| !entries.iter().any(|e| &e.name == *key))
+        .map(|(key, value)| ConfigEntry::new(key, value, SOURCE_DYNAMIC_TOPIC, TYPE_STRING))
+        .collect();
+    extra.sort_by(|a, b| a.name.cmp(&b.name));
+    entries.extend(extra);
+    Ok(entries)
+}
+
+/// Broker-level settings clients commonly inspect
+fn broker_configs(broker: &Broker) -> Vec<ConfigEntry> {
+    let defaults = broker.streaming.config();
+    vec![
+        ConfigEntry::new(
+            "auto.create.topics.enable",
+            &broker.config.auto_create_topics,
+            SOURCE_STATIC_BROKER,
+            TYPE_BOOLEAN,
+        ),
+        ConfigEntry::new(
+            "num.partitions",
+            &defaults.default_partitions,
+            SOURCE_STATIC_BROKER,
+            TYPE_INT,
+        ),
+        ConfigEntry::new(
+            "default.replication.factor",
+            &defaults.default_replication_factor,
+            SOURCE_STATIC_BROKER,
+            TYPE_INT,
+        ),
+        ConfigEntry::new(
+            "log.retention.ms",
+            &defaults.retention_ms,
+            SOURCE_STATIC_BROKER,
+            TYPE_LONG,
+        ),
+        ConfigEntry::new(
+            "log.segment.bytes",
+            &defaults.segment_size_bytes,
+            SOURCE_STATIC_BROKER,
+            TYPE_INT,
+        ),
+    ]
+}
+
+/// DescribeConfigs for topics and this broker
+pub(super) fn describe_configs(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let resources = dec.array_of(flexible, |dec| {
+        let resource_type = dec.i8()?;
+        let name = dec.string(flexible)?;
+        let keys = match dec.array_len(flexible)? {
+            Some(len) => Some(
+                (0..len)
+                    .map(|_| dec.string(flexible))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            None => None,
+        };
+        dec.tagged_fields(flexible)?;
+        Ok((resource_type, name, keys))
+    })?;
+    if version >= 1 {
+        dec.bool()?; // include_synonyms
+    }
+    if version >= 3 {
+        dec.bool()?; // include_documentation
+    }
+    dec.tagged_fields(flexible)?;
+
+    enc.i32(0);
+    enc.array_of(&resources, flexible, |enc, (resource_type, name, keys)| {
+        let entries = match *resource_type {
+            RESOURCE_TOPIC
+                if !broker.permits(
+                    header,
+                    ResourceType::Topic,
+                    name,
+                    Operation::DescribeConfigs,
+                ) =>
+            {
+                Err((error::TOPIC_AUTHORIZATION_FAILED, None))
+            }
+            RESOURCE_BROKER
+                if !broker.permits(
+                    header,
+                    ResourceType::Cluster,
+                    "",
+                    Operation::DescribeConfigs,
+                ) =>
+            {
+                Err((error::CLUSTER_AUTHORIZATION_FAILED, None))
+            }
+            RESOURCE_TOPIC => match broker.streaming.topic_config(name) {
+                Some(config) => topic_configs(broker.streaming.config(), &config)
+                    .map_err(|e| (error::INVALID_CONFIG, Some(e.to_string()))),
+                None => Err((error::UNKNOWN_TOPIC_OR_PARTITION, None)),
+            },
+            RESOURCE_BROKER => Ok(broker_configs(broker)),
+            other => Err((
+                error::INVALID_REQUEST,
+                Some(format!("Unsupported resource type {other}")),
+            )),
+        };
+        let (error_code, error_message, mut entries) = match entries {
+            Ok(entries) => (error::NONE, None, entries),
+            Err((code, message)) => (code, message, Vec::new()),
+        };
+        if let Some(keys) = keys {
+            entries.retain(|e| keys.contains(&e.name));
+        }
+
+        enc.i16(error_code)
+            .nullable_string(error_message.as_deref(), flexible)
+            .i8(*resource_type)
+            .string(name, flexible);
+        enc.array_of(&entries, flexible, |enc, entry| {
+            enc.string(&entry.name, flexible)
+                .nullable_string(entry.value.as_deref(), flexible)
+                .bool(false);
+            if version == 0 {
+                enc.bool(entry.source == SOURCE_DEFAULT);
+            } else {
+                enc.i8(entry.source);
+            }
+            enc.bool(false);
+            if version >= 1 {
+                enc.array_len(0, flexible);
+            }
+            if version >= 3 {
+                enc.i8(entry.config_type).nullable_string(None, flexible);
+            }
+            enc.tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
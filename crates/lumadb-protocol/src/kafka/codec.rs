@@ -0,0 +1,421 @@
+//! Kafka primitive type encoding
+//!
+//! Fixed-width integers are big-endian. Flexible versions (KIP-482) encode
+//! strings, bytes and arrays with unsigned-varint lengths offset by one (zero
+//! meaning null) and end every structure with a tagged field section; the
+//! `flexible` argument on the length-prefixed readers and writers picks the
+//! encoding.
+
+use lumadb_common::error::{Error, ProtocolError, Result};
+
+/// Reads Kafka primitive types from a request body
+///
+/// Every read fails with a protocol error on truncated or malformed input.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl<'a> Decoder<'a> {
+    #[must_use]
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Bytes not yet consumed
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Whether every byte has been consumed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Consume `len` raw bytes
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.i8()? != 0)
+    }
+
+    pub fn i8(&mut self) -> Result<i8> {
+        Ok(i8::from_be_bytes(self.array()?))
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    pub fn uuid(&mut self) -> Result<[u8; 16]> {
+        self.array()
+    }
+
+    /// Unsigned LEB128 integer
+    pub fn unsigned_varint(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.array::<1>()?[0];
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint is too long"))
+    }
+
+    /// Zigzag-encoded signed 32-bit varint
+    pub fn varint(&mut self) -> Result<i32> {
+        let raw = self.unsigned_varint()?;
+        #[allow(clippy::cast_possible_wrap)]
+        Ok((raw >> 1) as i32 ^ -((raw & 1) as i32))
+    }
+
+    /// Zigzag-encoded signed 64-bit varint
+    pub fn varlong(&mut self) -> Result<i64> {
+        let mut raw = 0u64;
+        for shift in (0..70).step_by(7) {
+            let byte = self.array::<1>()?[0];
+            raw |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                #[allow(clippy::cast_possible_wrap)]
+                return Ok((raw >> 1) as i64 ^ -((raw & 1) as i64));
+            }
+        }
+        Err(invalid("varlong is too long"))
+    }
+
+    /// Length prefix of a string, bytes or array; `None` for null
+    fn length(&mut self, flexible: bool, wide: bool) -> Result<Option<usize>> {
+        let len = if flexible {
+            i64::from(self.unsigned_varint()?) - 1
+        } else if wide {
+            i64::from(self.i32()?)
+        } else {
+            i64::from(self.i16()?)
+        };
+        if len < 0 {
+            return Ok(None);
+        }
+        let len = usize::try_from(len).map_err(|_| invalid("length out of range"))?;
+        Ok(Some(len))
+    }
+
+    pub fn nullable_string(&mut self, flexible: bool) -> Result<Option<String>> {
+        match self.length(flexible, false)? {
+            Some(len) => {
+                let bytes = self.take(len)?;
+                String::from_utf8(bytes.to_vec())
+                    .map(Some)
+                    .map_err(|_| invalid("string is not valid UTF-8"))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn string(&mut self, flexible: bool) -> Result<String> {
+        self.nullable_string(flexible)?
+            .ok_or_else(|| invalid("unexpected null string"))
+    }
+
+    pub fn nullable_bytes(&mut self, flexible: bool) -> Result<Option<&'a [u8]>> {
+        match self.length(flexible, true)? {
+            Some(len) => self.take(len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn bytes(&mut self, flexible: bool) -> Result<&'a [u8]> {
+        Ok(self.nullable_bytes(flexible)?.unwrap_or_default())
+    }
+
+    /// Element count of an array; `None` for a null array
+    pub fn array_len(&mut self, flexible: bool) -> Result<Option<usize>> {
+        let len = self.length(flexible, true)?;
+        // Every element takes at least one byte, which bounds allocations
+        // driven by a corrupt length
+        if len.is_some_and(|n| n > self.remaining()) {
+            return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+        }
+        Ok(len)
+    }
+
+    /// Decode an array, treating null as empty
+    pub fn array_of<T>(
+        &mut self,
+        flexible: bool,
+        mut element: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let len = self.array_len(flexible)?.unwrap_or(0);
+        (0..len).map(|_| element(self)).collect()
+    }
+
+    /// Skip a tagged field section; none of the tagged fields we read are
+    /// meaningful to this broker
+    pub fn tagged_fields(&mut self, flexible: bool) -> Result<()> {
+        if !flexible {
+            return Ok(());
+        }
+        let count = self.unsigned_varint()?;
+        for _ in 0..count {
+            self.unsigned_varint()?;
+            let size = self.unsigned_varint()? as usize;
+            self.take(size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes Kafka primitive types into a response body
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.i8(i8::from(value))
+    }
+
+    pub fn i8(&mut self, value: i8) -> &mut Self {
+        self.raw(&value.to_be_bytes())
+    }
+
+    pub fn i16(&mut self, value: i16) -> &mut Self {
+        self.raw(&value.to_be_bytes())
+    }
+
+    pub fn i32(&mut self, value: i32) -> &mut Self {
+        self.raw(&value.to_be_bytes())
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.raw(&value.to_be_bytes())
+    }
+
+    pub fn uuid(&mut self, value: [u8; 16]) -> &mut Self {
+        self.raw(&value)
+    }
+
+    pub fn unsigned_varint(&mut self, mut value: u32) -> &mut Self {
+        while value >= 0x80 {
+            #[allow(clippy::cast_possible_truncation)]
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        self.buf.push(value as u8);
+        self
+    }
+
+    pub fn varint(&mut self, value: i32) -> &mut Self {
+        #[allow(clippy::cast_sign_loss)]
+        self.unsigned_varint(((value << 1) ^ (value >> 31)) as u32)
+    }
+
+    pub fn varlong(&mut self, value: i64) -> &mut Self {
+        #[allow(clippy::cast_sign_loss)]
+        let mut raw = ((value << 1) ^ (value >> 63)) as u64;
+        while raw >= 0x80 {
+            #[allow(clippy::cast_possible_truncation)]
+            self.buf.push((raw as u8) | 0x80);
+            raw >>= 7;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        self.buf.push(raw as u8);
+        self
+    }
+
+    /// Length prefix of a string, bytes or array; `None` writes null
+    fn length(&mut self, len: Option<usize>, flexible: bool, wide: bool) -> &mut Self {
+        match (len, flexible) {
+            (Some(len), true) => self.unsigned_varint(u32::try_from(len + 1).unwrap_or(u32::MAX)),
+            (None, true) => self.unsigned_varint(0),
+            (Some(len), false) if wide => self.i32(i32::try_from(len).unwrap_or(i32::MAX)),
+            (Some(len), false) => self.i16(i16::try_from(len).unwrap_or(i16::MAX)),
+            (None, false) if wide => self.i32(-1),
+            (None, false) => self.i16(-1),
+        }
+    }
+
+    pub fn string(&mut self, value: &str, flexible: bool) -> &mut Self {
+        self.length(Some(value.len()), flexible, false)
+            .raw(value.as_bytes())
+    }
+
+    pub fn nullable_string(&mut self, value: Option<&str>, flexible: bool) -> &mut Self {
+        match value {
+            Some(value) => self.string(value, flexible),
+            None => self.length(None, flexible, false),
+        }
+    }
+
+    pub fn bytes(&mut self, value: &[u8], flexible: bool) -> &mut Self {
+        self.length(Some(value.len()), flexible, true).raw(value)
+    }
+
+    pub fn nullable_bytes(&mut self, value: Option<&[u8]>, flexible: bool) -> &mut Self {
+        match value {
+            Some(value) => self.bytes(value, flexible),
+            None => self.length(None, flexible, true),
+        }
+    }
+
+    pub fn array_len(&mut self, len: usize, flexible: bool) -> &mut Self {
+        self.length(Some(len), flexible, true)
+    }
+
+    pub fn null_array(&mut self, flexible: bool) -> &mut Self {
+        self.length(None, flexible, true)
+    }
+
+    /// Encode an array with one call per element
+    pub fn array_of<T>(
+        &mut self,
+        items: &[T],
+        flexible: bool,
+        mut element: impl FnMut(&mut Self, &T),
+    ) -> &mut Self {
+        self.array_len(items.len(), flexible);
+        for item in items {
+            element(self, item);
+        }
+        self
+    }
+
+    /// Write an empty tagged field section
+    pub fn tagged_fields(&mut self, flexible: bool) -> &mut Self {
+        if flexible {
+            self.unsigned_varint(0);
+        }
+        self
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> Error {
+    Error::Protocol(ProtocolError::InvalidFormat(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        let ints = [0, 1, -1, 63, -64, 64, i32::MAX, i32::MIN];
+        let longs = [0, 1, -1, 300, -300, i64::MAX, i64::MIN];
+
+        let mut enc = Encoder::new();
+        for v in ints {
+            enc.varint(v);
+        }
+        for v in longs {
+            enc.varlong(v);
+        }
+        enc.unsigned_varint(u32::MAX);
+        let bytes = enc.into_inner();
+
+        let mut dec = Decoder::new(&bytes);
+        for v in ints {
+            assert_eq!(dec.varint().unwrap(), v);
+        }
+        for v in longs {
+            assert_eq!(dec.varlong().unwrap(), v);
+        }
+        assert_eq!(dec.unsigned_varint().unwrap(), u32::MAX);
+        assert!(dec.is_empty());
+    }
+
+    #[test]
+    fn zigzag_matches_kafka_encoding() {
+        let mut enc = Encoder::new();
+        enc.varint(-1).varint(1).varint(-64).varint(64);
+        assert_eq!(enc.into_inner(), vec![0x01, 0x02, 0x7f, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn compact_and_classic_strings() {
+        let mut enc = Encoder::new();
+        enc.string("kafka", true)
+            .nullable_string(None, true)
+            .string("kafka", false)
+            .nullable_string(None, false)
+            .tagged_fields(true);
+        let bytes = enc.into_inner();
+        assert_eq!(&bytes[..6], b"\x06kafka");
+
+        let mut dec = Decoder::new(&bytes);
+        assert_eq!(dec.string(true).unwrap(), "kafka");
+        assert_eq!(dec.nullable_string(true).unwrap(), None);
+        assert_eq!(dec.string(false).unwrap(), "kafka");
+        assert_eq!(dec.nullable_string(false).unwrap(), None);
+        dec.tagged_fields(true).unwrap();
+        assert!(dec.is_empty());
+    }
+
+    #[test]
+    fn skips_unknown_tagged_fields() {
+        let mut enc = Encoder::new();
+        enc.unsigned_varint(2)
+            .unsigned_varint(0)
+            .unsigned_varint(3)
+            .raw(b"abc")
+            .unsigned_varint(7)
+            .unsigned_varint(0)
+            .i32(42);
+        let bytes = enc.into_inner();
+
+        let mut dec = Decoder::new(&bytes);
+        dec.tagged_fields(true).unwrap();
+        assert_eq!(dec.i32().unwrap(), 42);
+    }
+}
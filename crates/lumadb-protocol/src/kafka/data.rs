@@ -0,0 +1,667 @@
+//! Produce, Fetch and ListOffsets
+//!
+//! Batches carrying a producer id go through the partition's idempotent
+//! append path. Fetches and ListOffsets honour the `read_committed`
+//! isolation level by stopping at the last stable offset; fetches also list
+//! the aborted transactions the returned records overlap.
+//!
+//! Producing needs `write` on the topic (and on the transactional id, if
+//! any), fetching `read` and listing offsets `describe`.
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tracing::warn;
+
+use lumadb_common::error::{Error, ProtocolError, Result};
+use lumadb_common::types::{ControlRecord, Offset, ProducerInfo, Record};
+use lumadb_security::authz::{Operation, ResourceType};
+use lumadb_streaming::log::AbortedTransaction;
+use lumadb_streaming::producer::IsolationLevel;
+
+use super::codec::{Decoder, Encoder};
+use super::error::{self, producer_error_code};
+use super::records::{control_record, BatchRecord, Compression, RecordBatch};
+use super::{now_ms, Broker, RequestHeader};
+
+/// Outcome of producing to one partition
+struct ProducePartitionResult {
+    index: i32,
+    error_code: i16,
+    base_offset: i64,
+    log_start_offset: i64,
+    error_message: Option<String>,
+}
+
+/// Produce: append record batches to partition logs
+///
+/// Returns whether a response is due; `acks=0` producers expect none.
+pub(super) fn produce(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<bool> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let transactional_id = dec.nullable_string(flexible)?;
+    let acks = dec.i16()?;
+    dec.i32()?; // timeout_ms
+
+    let topics = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let partitions = dec.array_of(flexible, |dec| {
+            let index = dec.i32()?;
+            let records = dec.nullable_bytes(flexible)?.unwrap_or_default();
+            dec.tagged_fields(flexible)?;
+            Ok((index, records))
+        })?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, partitions))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let transaction_denied = transactional_id.as_deref().is_some_and(|id| {
+        !broker.permits(header, ResourceType::TransactionalId, id, Operation::Write)
+    });
+
+    let mut responses = Vec::with_capacity(topics.len());
+    for (name, partitions) in topics {
+        let topic_denied = !broker.permits(header, ResourceType::Topic, &name, Operation::Write);
+        let results: Vec<_> = partitions
+            .into_iter()
+            .map(|(index, records)| {
+                if transaction_denied {
+                    failed(index, error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED, None)
+                } else if topic_denied {
+                    failed(index, error::TOPIC_AUTHORIZATION_FAILED, None)
+                } else if matches!(acks, -1..=1) {
+                    produce_partition(broker, &name, index, records)
+                } else {
+                    failed(index, error::INVALID_REQUIRED_ACKS, None)
+                }
+            })
+            .collect();
+        responses.push((name, results));
+    }
+
+    if acks == 0 {
+        return Ok(false);
+    }
+
+    enc.array_of(&responses, flexible, |enc, (name, partitions)| {
+        enc.string(name, flexible);
+        enc.array_of(partitions, flexible, |enc, result| {
+            enc.i32(result.index)
+                .i16(result.error_code)
+                .i64(result.base_offset);
+            if version >= 2 {
+                enc.i64(-1); // log_append_time_ms
+            }
+            if version >= 5 {
+                enc.i64(result.log_start_offset);
+            }
+            if version >= 8 {
+                enc.array_len(0, flexible)
+                    .nullable_string(result.error_message.as_deref(), flexible);
+            }
+            enc.tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.i32(0).tagged_fields(flexible);
+    Ok(true)
+}
+
+fn failed(index: i32, error_code: i16, error_message: Option<String>) -> ProducePartitionResult {
+    ProducePartitionResult {
+        index,
+        error_code,
+        base_offset: -1,
+        log_start_offset: -1,
+        error_message,
+    }
+}
+
+fn produce_partition(
+    broker: &Broker,
+    topic: &str,
+    index: i32,
+    data: &[u8],
+) -> ProducePartitionResult {
+    let Some(partition) = broker.streaming.partition(topic, index) else {
+        return failed(index, error::UNKNOWN_TOPIC_OR_PARTITION, None);
+    };
+
+    let batches = match RecordBatch::decode_all(data) {
+        Ok(batches) => batches,
+        Err(e) => {
+            let code = match e {
+                Error::Protocol(ProtocolError::UnsupportedVersion(_)) => {
+                    error::UNSUPPORTED_FOR_MESSAGE_FORMAT
+                }
+                _ => error::CORRUPT_MESSAGE,
+            };
+            return failed(index, code, Some(e.to_string()));
+        }
+    };
+
+    let now = now_ms();
+    let mut batches: Vec<(&RecordBatch, Vec<Record>)> = batches
+        .iter()
+        .filter(|batch| !batch.is_control())
+        .map(|batch| {
+            let records = batch
+                .records
+                .iter()
+                .map(|record| {
+                    let timestamp = if batch.is_log_append_time() {
+                        now
+                    } else {
+                        batch.base_timestamp + record.timestamp_delta
+                    };
+                    to_record(record, timestamp)
+                })
+                .collect();
+            (batch, records)
+        })
+        .collect();
+    batches.retain(|(_, records)| !records.is_empty());
+    if batches.is_empty() {
+        return failed(
+            index,
+            error::INVALID_RECORD,
+            Some("Produce request without records".to_string()),
+        );
+    }
+    if partition.log().options().cleanup_policy.compacts()
+        && batches
+            .iter()
+            .flat_map(|(_, records)| records)
+            .any(|r| r.key.is_none())
+    {
+        return failed(
+            index,
+            error::INVALID_RECORD,
+            Some("Compacted topic cannot accept message without key".to_string()),
+        );
+    }
+
+    // Batches are appended one by one since each carries its own producer
+    // state; the response reports the offset of the first
+    let mut base_offset = None;
+    for (batch, records) in &batches {
+        let result = if batch.producer_id >= 0 {
+            let producer = ProducerInfo {
+                producer_id: batch.producer_id,
+                producer_epoch: batch.producer_epoch,
+                sequence: batch.base_sequence,
+                transactional: batch.is_transactional(),
+            };
+            broker
+                .streaming
+                .append_producer_records(topic, index, records, producer)
+                .map_err(|e| (producer_error_code(&e), e.to_string()))
+        } else if batch.is_transactional() {
+            Err((
+                error::INVALID_RECORD,
+                "Transactional batch without producer id".to_string(),
+            ))
+        } else {
+            broker
+                .streaming
+                .append_records(topic, index, records)
+                .map_err(|e| (error::UNKNOWN_SERVER_ERROR, e.to_string()))
+        };
+        match result {
+            Ok(offset) => {
+                base_offset.get_or_insert(offset);
+            }
+            Err((code, message)) => {
+                if code == error::UNKNOWN_SERVER_ERROR {
+                    warn!("Kafka produce to {}/{} failed: {}", topic, index, message);
+                }
+                return failed(index, code, Some(message));
+            }
+        }
+    }
+
+    ProducePartitionResult {
+        index,
+        error_code: error::NONE,
+        base_offset: base_offset.unwrap_or(-1),
+        log_start_offset: partition.log().low_watermark(),
+        error_message: None,
+    }
+}
+
+/// Convert a batch record to a stored record
+///
+/// A null value is stored empty, which the log treats as a tombstone;
+/// header values are kept as (lossy) UTF-8 strings.
+fn to_record(record: &BatchRecord, timestamp: i64) -> Record {
+    Record {
+        key: record.key.clone().map(Bytes::from),
+        value: record.value.clone().map(Bytes::from).unwrap_or_default(),
+        headers: record
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .as_deref()
+                    .map(|v| String::from_utf8_lossy(v).into_owned())
+                    .unwrap_or_default();
+                (name.clone(), value)
+            })
+            .collect(),
+        timestamp,
+        partition: None,
+        offset: None,
+        producer: None,
+        control: None,
+    }
+}
+
+/// Whether two records belong in the same batch: written by the same
+/// producer and epoch with the same transactional flag
+fn same_producer(a: Option<ProducerInfo>, b: Option<ProducerInfo>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            a.producer_id == b.producer_id
+                && a.producer_epoch == b.producer_epoch
+                && a.transactional == b.transactional
+        }
+        _ => false,
+    }
+}
+
+/// Encode fetched log entries as record batches
+///
+/// Consecutive records of the same producer share a batch; every
+/// transaction marker gets a control batch of its own.
+fn encode_batches(entries: &[(Offset, Record)]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    while let Some((_, first)) = entries.get(start) {
+        let len = if first.is_control() {
+            1
+        } else {
+            entries[start..]
+                .iter()
+                .take_while(|(_, r)| !r.is_control() && same_producer(r.producer, first.producer))
+                .count()
+        };
+        out.extend(encode_batch(&entries[start..start + len])?);
+        start += len;
+    }
+    Ok(out)
+}
+
+/// Encode log entries of one producer as a single record batch
+fn encode_batch(entries: &[(Offset, Record)]) -> Result<Vec<u8>> {
+    let Some((base_offset, first)) = entries.first() else {
+        return Ok(Vec::new());
+    };
+    let records = entries
+        .iter()
+        .map(|(offset, record)| {
+            let (key, value) = match record.control {
+                Some(control) => {
+                    let (key, value) = control_record(control == ControlRecord::Commit);
+                    (Some(key), Some(value))
+                }
+                None => (
+                    record.key.as_ref().map(|k| k.to_vec()),
+                    if record.is_tombstone() {
+                        None
+                    } else {
+                        Some(record.value.to_vec())
+                    },
+                ),
+            };
+            BatchRecord {
+                offset_delta: i32::try_from(offset - base_offset).unwrap_or(i32::MAX),
+                timestamp_delta: record.timestamp - first.timestamp,
+                key,
+                value,
+                headers: record
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Some(value.clone().into_bytes())))
+                    .collect(),
+            }
+        })
+        .collect();
+    let mut batch = RecordBatch::new(*base_offset, first.timestamp, records);
+    if let Some(producer) = first.producer {
+        batch = batch.with_producer(
+            producer.producer_id,
+            producer.producer_epoch,
+            producer.sequence,
+            producer.transactional,
+        );
+    }
+    if first.is_control() {
+        batch = batch.with_control();
+    }
+    batch.encode(Compression::None)
+}
+
+/// A partition requested by a fetch
+struct FetchPartition {
+    topic: String,
+    partition: i32,
+    fetch_offset: i64,
+    max_bytes: usize,
+    /// Whether the principal may read the topic
+    authorized: bool,
+}
+
+/// Fetch result for one partition
+struct FetchedPartition {
+    partition: i32,
+    error_code: i16,
+    high_watermark: i64,
+    last_stable_offset: i64,
+    log_start_offset: i64,
+    aborted_transactions: Vec<AbortedTransaction>,
+    records: Vec<u8>,
+}
+
+impl FetchedPartition {
+    fn failed(partition: i32, error_code: i16) -> Self {
+        Self {
+            partition,
+            error_code,
+            high_watermark: -1,
+            last_stable_offset: -1,
+            log_start_offset: -1,
+            aborted_transactions: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+}
+
+/// Fetch: read record batches, long-polling until `min_bytes` are available
+/// or `max_wait_ms` passes
+pub(super) async fn fetch(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    dec.i32()?; // replica_id
+    let max_wait_ms = dec.i32()?;
+    let min_bytes = dec.i32()?;
+    let max_bytes = dec.i32()?;
+    let isolation = IsolationLevel::from_kafka(dec.i8()?);
+    if version >= 7 {
+        dec.i32()?; // session_id
+        dec.i32()?; // session_epoch
+    }
+    let topics = dec.array_of(flexible, |dec| {
+        let topic = dec.string(flexible)?;
+        let authorized = broker.permits(header, ResourceType::Topic, &topic, Operation::Read);
+        let partitions = dec.array_of(flexible, |dec| {
+            let partition = dec.i32()?;
+            if version >= 9 {
+                dec.i32()?; // current_leader_epoch
+            }
+            let fetch_offset = dec.i64()?;
+            if version >= 12 {
+                dec.i32()?; // last_fetched_epoch
+            }
+            if version >= 5 {
+                dec.i64()?; // log_start_offset
+            }
+            let max_bytes = dec.i32()?;
+            dec.tagged_fields(flexible)?;
+            Ok(FetchPartition {
+                topic: topic.clone(),
+                partition,
+                fetch_offset,
+                max_bytes: usize::try_from(max_bytes).unwrap_or(0),
+                authorized,
+            })
+        })?;
+        dec.tagged_fields(flexible)?;
+        Ok((topic, partitions))
+    })?;
+    // Incremental fetch sessions are not supported: the response carries
+    // session id 0, so clients keep sending full fetch requests and the
+    // forgotten topic list stays empty
+    if version >= 7 {
+        dec.array_of(flexible, |dec| {
+            dec.string(flexible)?;
+            dec.array_of(flexible, Decoder::i32)?;
+            dec.tagged_fields(flexible)
+        })?;
+    }
+    if version >= 11 {
+        dec.string(flexible)?; // rack_id
+    }
+    dec.tagged_fields(flexible)?;
+
+    let deadline = Instant::now() + Duration::from_millis(u64::try_from(max_wait_ms).unwrap_or(0));
+    let min_bytes = usize::try_from(min_bytes).unwrap_or(0);
+    let max_bytes = usize::try_from(max_bytes).unwrap_or(0);
+
+    let responses = loop {
+        // Register for appends before reading so none is missed in between
+        let appended = broker.streaming.appended();
+        let (responses, bytes, failed) = read_partitions(broker, &topics, max_bytes, isolation)?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if bytes >= min_bytes || failed || remaining.is_zero() {
+            break responses;
+        }
+        let _ = tokio::time::timeout(remaining, appended).await;
+    };
+
+    enc.i32(0);
+    if version >= 7 {
+        enc.i16(error::NONE).i32(0);
+    }
+    enc.array_of(&responses, flexible, |enc, (topic, partitions)| {
+        enc.string(topic, flexible);
+        enc.array_of(partitions, flexible, |enc, p| {
+            enc.i32(p.partition)
+                .i16(p.error_code)
+                .i64(p.high_watermark)
+                .i64(p.last_stable_offset);
+            if version >= 5 {
+                enc.i64(p.log_start_offset);
+            }
+            enc.array_of(&p.aborted_transactions, flexible, |enc, aborted| {
+                enc.i64(aborted.producer_id)
+                    .i64(aborted.first_offset)
+                    .tagged_fields(flexible);
+            });
+            if version >= 11 {
+                enc.i32(-1); // preferred_read_replica
+            }
+            enc.nullable_bytes(Some(&p.records), flexible)
+                .tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// Read every requested partition within the response size limit
+///
+/// Returns the per-topic results, the number of record bytes read and
+/// whether any partition failed.
+#[allow(clippy::type_complexity)]
+fn read_partitions(
+    broker: &Broker,
+    topics: &[(String, Vec<FetchPartition>)],
+    max_bytes: usize,
+    isolation: IsolationLevel,
+) -> Result<(Vec<(String, Vec<FetchedPartition>)>, usize, bool)> {
+    let mut total = 0usize;
+    let mut failed = false;
+    let mut responses = Vec::with_capacity(topics.len());
+
+    for (topic, partitions) in topics {
+        let mut results = Vec::with_capacity(partitions.len());
+        for request in partitions {
+            if !request.authorized {
+                failed = true;
+                results.push(FetchedPartition::failed(
+                    request.partition,
+                    error::TOPIC_AUTHORIZATION_FAILED,
+                ));
+                continue;
+            }
+            let Some(partition) = broker
+                .streaming
+                .partition(&request.topic, request.partition)
+            else {
+                failed = true;
+                results.push(FetchedPartition::failed(
+                    request.partition,
+                    error::UNKNOWN_TOPIC_OR_PARTITION,
+                ));
+                continue;
+            };
+            let log = partition.log();
+            let high_watermark = log.log_end_offset();
+            let log_start_offset = log.low_watermark();
+
+            let mut result = FetchedPartition {
+                partition: request.partition,
+                error_code: error::NONE,
+                high_watermark,
+                last_stable_offset: log.last_stable_offset(),
+                log_start_offset,
+                aborted_transactions: Vec::new(),
+                records: Vec::new(),
+            };
+            if request.fetch_offset < log_start_offset || request.fetch_offset > high_watermark {
+                failed = true;
+                result.error_code = error::OFFSET_OUT_OF_RANGE;
+            } else if request.fetch_offset < high_watermark {
+                // The first partition with data may exceed the limits so
+                // that oversized records can still be consumed
+                let budget = request.max_bytes.min(max_bytes.saturating_sub(total));
+                if budget > 0 || total == 0 {
+                    let entries = log.read_isolated(
+                        request.fetch_offset,
+                        usize::MAX,
+                        budget.max(1),
+                        isolation,
+                    )?;
+                    if let (IsolationLevel::ReadCommitted, Some((first, _)), Some((last, _))) =
+                        (isolation, entries.first(), entries.last())
+                    {
+                        result.aborted_transactions = log.aborted_transactions(*first, last + 1);
+                    }
+                    result.records = encode_batches(&entries)?;
+                    total += result.records.len();
+                }
+            }
+            results.push(result);
+        }
+        responses.push((topic.clone(), results));
+    }
+    Ok((responses, total, failed))
+}
+
+/// ListOffsets: earliest, latest or first offset at a timestamp
+pub(super) fn list_offsets(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    const LATEST: i64 = -1;
+    const EARLIEST: i64 = -2;
+    // (partition, error code, timestamp, offset) per requested partition
+    type PartitionOffset = (i32, i16, i64, i64);
+
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    dec.i32()?; // replica_id
+    let isolation = if version >= 2 {
+        IsolationLevel::from_kafka(dec.i8()?)
+    } else {
+        IsolationLevel::ReadUncommitted
+    };
+    let topics = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let partitions = dec.array_of(flexible, |dec| {
+            let index = dec.i32()?;
+            if version >= 4 {
+                dec.i32()?; // current_leader_epoch
+            }
+            let timestamp = dec.i64()?;
+            dec.tagged_fields(flexible)?;
+            Ok((index, timestamp))
+        })?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, partitions))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let mut responses: Vec<(String, Vec<PartitionOffset>)> = Vec::with_capacity(topics.len());
+    for (name, partitions) in topics {
+        let authorized = broker.permits(header, ResourceType::Topic, &name, Operation::Describe);
+        let mut results = Vec::with_capacity(partitions.len());
+        for (index, timestamp) in partitions {
+            if !authorized {
+                results.push((index, error::TOPIC_AUTHORIZATION_FAILED, -1, -1));
+                continue;
+            }
+            let Some(partition) = broker.streaming.partition(&name, index) else {
+                results.push((index, error::UNKNOWN_TOPIC_OR_PARTITION, -1, -1));
+                continue;
+            };
+            let log = partition.log();
+            let (timestamp, offset) = match timestamp {
+                LATEST if isolation == IsolationLevel::ReadCommitted => {
+                    (-1, log.last_stable_offset())
+                }
+                LATEST => (-1, log.log_end_offset()),
+                EARLIEST => (-1, log.low_watermark()),
+                timestamp => match log.offset_for_timestamp(timestamp)? {
+                    Some(offset) => {
+                        let found = log.read(offset, 1, 1)?;
+                        let timestamp = found.first().map_or(-1, |(_, r)| r.timestamp);
+                        (timestamp, offset)
+                    }
+                    None => (-1, -1),
+                },
+            };
+            results.push((index, error::NONE, timestamp, offset));
+        }
+        responses.push((name, results));
+    }
+
+    if version >= 2 {
+        enc.i32(0);
+    }
+    enc.array_of(&responses, flexible, |enc, (name, partitions)| {
+        enc.string(name, flexible);
+        enc.array_of(
+            partitions,
+            flexible,
+            |enc, &(index, code, timestamp, offset)| {
+                enc.i32(index).i16(code).i64(timestamp).i64(offset);
+                if version >= 4 {
+                    enc.i32(0); // leader_epoch
+                }
+                enc.tagged_fields(flexible);
+            },
+        );
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
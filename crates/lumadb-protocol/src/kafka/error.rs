@@ -0,0 +1,75 @@
+//! Kafka protocol error codes
+
+use lumadb_streaming::consumer::GroupError;
+use lumadb_streaming::producer::ProducerError;
+
+pub const UNKNOWN_SERVER_ERROR: i16 = -1;
+pub const NONE: i16 = 0;
+pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+pub const CORRUPT_MESSAGE: i16 = 2;
+pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+pub const INVALID_TOPIC_EXCEPTION: i16 = 17;
+pub const INVALID_REQUIRED_ACKS: i16 = 21;
+pub const ILLEGAL_GENERATION: i16 = 22;
+pub const INCONSISTENT_GROUP_PROTOCOL: i16 = 23;
+pub const INVALID_GROUP_ID: i16 = 24;
+pub const UNKNOWN_MEMBER_ID: i16 = 25;
+pub const INVALID_SESSION_TIMEOUT: i16 = 26;
+pub const REBALANCE_IN_PROGRESS: i16 = 27;
+pub const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
+pub const GROUP_AUTHORIZATION_FAILED: i16 = 30;
+pub const CLUSTER_AUTHORIZATION_FAILED: i16 = 31;
+pub const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
+pub const ILLEGAL_SASL_STATE: i16 = 34;
+pub const UNSUPPORTED_VERSION: i16 = 35;
+pub const TOPIC_ALREADY_EXISTS: i16 = 36;
+pub const INVALID_PARTITIONS: i16 = 37;
+pub const INVALID_REPLICATION_FACTOR: i16 = 38;
+pub const INVALID_CONFIG: i16 = 40;
+pub const INVALID_REQUEST: i16 = 42;
+pub const UNSUPPORTED_FOR_MESSAGE_FORMAT: i16 = 43;
+pub const OUT_OF_ORDER_SEQUENCE_NUMBER: i16 = 45;
+pub const INVALID_PRODUCER_EPOCH: i16 = 47;
+pub const INVALID_TXN_STATE: i16 = 48;
+pub const INVALID_PRODUCER_ID_MAPPING: i16 = 49;
+pub const INVALID_TRANSACTION_TIMEOUT: i16 = 50;
+pub const CONCURRENT_TRANSACTIONS: i16 = 51;
+pub const TRANSACTIONAL_ID_AUTHORIZATION_FAILED: i16 = 53;
+pub const OPERATION_NOT_ATTEMPTED: i16 = 55;
+pub const SASL_AUTHENTICATION_FAILED: i16 = 58;
+pub const UNKNOWN_PRODUCER_ID: i16 = 59;
+pub const MEMBER_ID_REQUIRED: i16 = 79;
+pub const FENCED_INSTANCE_ID: i16 = 82;
+pub const INVALID_RECORD: i16 = 87;
+pub const RESOURCE_NOT_FOUND: i16 = 91;
+pub const DUPLICATE_RESOURCE: i16 = 92;
+pub const UNACCEPTABLE_CREDENTIAL: i16 = 93;
+pub const UNKNOWN_TOPIC_ID: i16 = 100;
+
+/// Kafka error code for a group protocol error
+pub fn group_error_code(error: &GroupError) -> i16 {
+    match error {
+        GroupError::UnknownMemberId => UNKNOWN_MEMBER_ID,
+        GroupError::IllegalGeneration => ILLEGAL_GENERATION,
+        GroupError::RebalanceInProgress => REBALANCE_IN_PROGRESS,
+        GroupError::InconsistentGroupProtocol => INCONSISTENT_GROUP_PROTOCOL,
+        GroupError::MemberIdRequired(_) => MEMBER_ID_REQUIRED,
+        GroupError::FencedInstanceId => FENCED_INSTANCE_ID,
+        GroupError::InvalidSessionTimeout => INVALID_SESSION_TIMEOUT,
+    }
+}
+
+/// Kafka error code for a producer or transaction error
+pub fn producer_error_code(error: &ProducerError) -> i16 {
+    match error {
+        ProducerError::OutOfOrderSequence => OUT_OF_ORDER_SEQUENCE_NUMBER,
+        ProducerError::InvalidProducerEpoch => INVALID_PRODUCER_EPOCH,
+        ProducerError::UnknownProducerId => UNKNOWN_PRODUCER_ID,
+        ProducerError::InvalidTxnState => INVALID_TXN_STATE,
+        ProducerError::InvalidProducerIdMapping => INVALID_PRODUCER_ID_MAPPING,
+        ProducerError::InvalidTransactionTimeout => INVALID_TRANSACTION_TIMEOUT,
+        ProducerError::ConcurrentTransactions => CONCURRENT_TRANSACTIONS,
+        ProducerError::UnknownTopicOrPartition => UNKNOWN_TOPIC_OR_PARTITION,
+        ProducerError::Storage(_) => UNKNOWN_SERVER_ERROR,
+    }
+}
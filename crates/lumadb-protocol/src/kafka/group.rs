@@ -0,0 +1,624 @@
+//! Group coordinator APIs
+//!
+//! This broker coordinates every group; membership and rebalances are
+//! delegated to the engine's [`ConsumerGroup`]s.
+//!
+//! Taking part in a group and committing offsets needs `read` on the group
+//! (and on the committed topics); looking up coordinators and committed
+//! offsets needs `describe`.
+
+use std::collections::BTreeMap;
+
+use tracing::warn;
+
+use lumadb_common::error::Result;
+use lumadb_security::authz::{Operation, ResourceType};
+use lumadb_streaming::consumer::{ConsumerGroup, GroupError, JoinGroupRequest};
+
+use super::codec::{Decoder, Encoder};
+use super::error::{self, group_error_code};
+use super::{Broker, Connection, RequestHeader, NODE_ID};
+
+/// Key type of a FindCoordinator request for a transactional id
+const KEY_TYPE_TRANSACTION: i8 = 1;
+
+/// Whether the principal may take part in `group_id`
+fn may_read_group(broker: &Broker, header: &RequestHeader, group_id: &str) -> bool {
+    broker.permits(header, ResourceType::Group, group_id, Operation::Read)
+}
+
+/// Resolve the member id of a leave request, mapping a static member that
+/// lost its id onto the member registered for its instance id
+fn resolve_member(
+    group: &ConsumerGroup,
+    member_id: &str,
+    group_instance_id: Option<&str>,
+) -> String {
+    match group_instance_id {
+        Some(instance) if member_id.is_empty() => {
+            group.member_for_instance(instance).unwrap_or_default()
+        }
+        _ => member_id.to_string(),
+    }
+}
+
+/// FindCoordinator: this node coordinates every group and transaction
+pub(super) fn find_coordinator(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let (key_type, keys) = if version >= 4 {
+        let key_type = dec.i8()?;
+        (
+            key_type,
+            dec.array_of(flexible, |dec| dec.string(flexible))?,
+        )
+    } else {
+        let key = dec.string(flexible)?;
+        let key_type = if version >= 1 { dec.i8()? } else { 0 };
+        (key_type, vec![key])
+    };
+    dec.tagged_fields(flexible)?;
+
+    let code = |key: &str| {
+        let (resource_type, denied) = if key_type == KEY_TYPE_TRANSACTION {
+            (
+                ResourceType::TransactionalId,
+                error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+            )
+        } else {
+            (ResourceType::Group, error::GROUP_AUTHORIZATION_FAILED)
+        };
+        if broker.permits(header, resource_type, key, Operation::Describe) {
+            error::NONE
+        } else {
+            denied
+        }
+    };
+
+    let host = &broker.config.advertised_host;
+    let port = i32::from(broker.config.advertised_port);
+
+    if version >= 1 {
+        enc.i32(0);
+    }
+    if version >= 4 {
+        enc.array_of(&keys, flexible, |enc, key| {
+            let code = code(key);
+            let (node, host, port) = if code == error::NONE {
+                (NODE_ID, host.as_str(), port)
+            } else {
+                (-1, "", -1)
+            };
+            enc.string(key, flexible)
+                .i32(node)
+                .string(host, flexible)
+                .i32(port)
+                .i16(code)
+                .nullable_string(None, flexible)
+                .tagged_fields(flexible);
+        });
+    } else {
+        let code = code(&keys[0]);
+        enc.i16(code);
+        if version >= 1 {
+            enc.nullable_string(None, flexible);
+        }
+        if code == error::NONE {
+            enc.i32(NODE_ID).string(host, flexible).i32(port);
+        } else {
+            enc.i32(-1).string("", flexible).i32(-1);
+        }
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// JoinGroup: park until the group's rebalance completes
+pub(super) async fn join_group(
+    broker: &Broker,
+    conn: &Connection,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    let session_timeout_ms = dec.i32()?;
+    let rebalance_timeout_ms = if version >= 1 {
+        dec.i32()?
+    } else {
+        session_timeout_ms
+    };
+    let member_id = dec.string(flexible)?;
+    let group_instance_id = if version >= 5 {
+        dec.nullable_string(flexible)?
+    } else {
+        None
+    };
+    let protocol_type = dec.string(flexible)?;
+    let protocols = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let metadata = dec.bytes(flexible)?.to_vec();
+        dec.tagged_fields(flexible)?;
+        Ok((name, metadata))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let result = if group_id.is_empty() {
+        Err((error::INVALID_GROUP_ID, member_id))
+    } else if !may_read_group(broker, header, &group_id) {
+        Err((error::GROUP_AUTHORIZATION_FAILED, member_id))
+    } else if protocols.is_empty() || protocol_type.is_empty() {
+        Err((error::INCONSISTENT_GROUP_PROTOCOL, member_id))
+    } else {
+        let request = JoinGroupRequest {
+            member_id: member_id.clone(),
+            group_instance_id,
+            client_id: header.client_id.clone().unwrap_or_default(),
+            client_host: format!("/{}", conn.peer.ip()),
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            protocol_type,
+            protocols,
+            require_known_member_id: version >= 4,
+        };
+        match broker.streaming.join_group(&group_id, request).await {
+            Ok(response) => Ok(response),
+            // The assigned id travels back in the error response
+            Err(GroupError::MemberIdRequired(id)) => Err((error::MEMBER_ID_REQUIRED, id)),
+            Err(e) => Err((group_error_code(&e), member_id)),
+        }
+    };
+
+    if version >= 2 {
+        enc.i32(0);
+    }
+    match result {
+        Ok(response) => {
+            enc.i16(error::NONE).i32(response.generation_id);
+            if version >= 7 {
+                enc.nullable_string(response.protocol_type.as_deref(), flexible)
+                    .nullable_string(response.protocol_name.as_deref(), flexible);
+            } else {
+                enc.string(
+                    response.protocol_name.as_deref().unwrap_or_default(),
+                    flexible,
+                );
+            }
+            enc.string(&response.leader, flexible)
+                .string(&response.member_id, flexible);
+            enc.array_of(&response.members, flexible, |enc, member| {
+                enc.string(&member.member_id, flexible);
+                if version >= 5 {
+                    enc.nullable_string(member.group_instance_id.as_deref(), flexible);
+                }
+                enc.bytes(&member.metadata, flexible)
+                    .tagged_fields(flexible);
+            });
+        }
+        Err((code, member_id)) => {
+            enc.i16(code).i32(-1);
+            if version >= 7 {
+                enc.nullable_string(None, flexible)
+                    .nullable_string(None, flexible);
+            } else {
+                enc.string("", flexible);
+            }
+            enc.string("", flexible)
+                .string(&member_id, flexible)
+                .array_len(0, flexible);
+        }
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// SyncGroup: the leader hands out assignments, followers collect theirs
+pub(super) async fn sync_group(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    let generation_id = dec.i32()?;
+    let member_id = dec.string(flexible)?;
+    let group_instance_id = if version >= 3 {
+        dec.nullable_string(flexible)?
+    } else {
+        None
+    };
+    if version >= 5 {
+        dec.nullable_string(flexible)?; // protocol_type
+        dec.nullable_string(flexible)?; // protocol_name
+    }
+    let assignments = dec.array_of(flexible, |dec| {
+        let member_id = dec.string(flexible)?;
+        let assignment = dec.bytes(flexible)?.to_vec();
+        dec.tagged_fields(flexible)?;
+        Ok((member_id, assignment))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let group = if may_read_group(broker, header, &group_id) {
+        broker
+            .streaming
+            .find_consumer_group(&group_id)
+            .ok_or(error::UNKNOWN_MEMBER_ID)
+    } else {
+        Err(error::GROUP_AUTHORIZATION_FAILED)
+    };
+    let (result, protocol_type, protocol_name) = match group {
+        Ok(group) => {
+            let result = group
+                .sync(
+                    &member_id,
+                    group_instance_id.as_deref(),
+                    generation_id,
+                    assignments,
+                )
+                .await
+                .map_err(|e| group_error_code(&e));
+            let (protocol_type, protocol_name) = group.protocol();
+            (result, protocol_type, protocol_name)
+        }
+        Err(code) => (Err(code), None, None),
+    };
+
+    if version >= 1 {
+        enc.i32(0);
+    }
+    let (code, assignment) = match result {
+        Ok(assignment) => (error::NONE, assignment),
+        Err(code) => (code, Vec::new()),
+    };
+    enc.i16(code);
+    if version >= 5 {
+        enc.nullable_string(protocol_type.as_deref(), flexible)
+            .nullable_string(protocol_name.as_deref(), flexible);
+    }
+    enc.bytes(&assignment, flexible).tagged_fields(flexible);
+    Ok(())
+}
+
+/// Heartbeat: keep a member alive and tell it when to rejoin
+pub(super) fn heartbeat(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    let generation_id = dec.i32()?;
+    let member_id = dec.string(flexible)?;
+    let group_instance_id = if version >= 3 {
+        dec.nullable_string(flexible)?
+    } else {
+        None
+    };
+    dec.tagged_fields(flexible)?;
+
+    let code = match broker.streaming.find_consumer_group(&group_id) {
+        _ if !may_read_group(broker, header, &group_id) => error::GROUP_AUTHORIZATION_FAILED,
+        Some(group) => {
+            match group.heartbeat(&member_id, group_instance_id.as_deref(), generation_id) {
+                Ok(()) => error::NONE,
+                Err(e) => group_error_code(&e),
+            }
+        }
+        None => error::UNKNOWN_MEMBER_ID,
+    };
+
+    if version >= 1 {
+        enc.i32(0);
+    }
+    enc.i16(code).tagged_fields(flexible);
+    Ok(())
+}
+
+/// LeaveGroup: remove one member (v0-2) or a batch of members (v3+)
+pub(super) fn leave_group(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    let members = if version >= 3 {
+        dec.array_of(flexible, |dec| {
+            let member_id = dec.string(flexible)?;
+            let group_instance_id = dec.nullable_string(flexible)?;
+            dec.tagged_fields(flexible)?;
+            Ok((member_id, group_instance_id))
+        })?
+    } else {
+        vec![(dec.string(flexible)?, None)]
+    };
+    dec.tagged_fields(flexible)?;
+
+    let group = if may_read_group(broker, header, &group_id) {
+        broker
+            .streaming
+            .find_consumer_group(&group_id)
+            .ok_or(error::UNKNOWN_MEMBER_ID)
+    } else {
+        Err(error::GROUP_AUTHORIZATION_FAILED)
+    };
+    let results: Vec<_> = members
+        .into_iter()
+        .map(|(member_id, group_instance_id)| {
+            let code = match &group {
+                Ok(group) => {
+                    let id = resolve_member(group, &member_id, group_instance_id.as_deref());
+                    match group.leave(&id) {
+                        Ok(()) => error::NONE,
+                        Err(e) => group_error_code(&e),
+                    }
+                }
+                Err(code) => *code,
+            };
+            (member_id, group_instance_id, code)
+        })
+        .collect();
+
+    if version >= 1 {
+        enc.i32(0);
+    }
+    if version >= 3 {
+        enc.i16(match group {
+            Err(error::GROUP_AUTHORIZATION_FAILED) => error::GROUP_AUTHORIZATION_FAILED,
+            _ => error::NONE,
+        });
+        enc.array_of(
+            &results,
+            flexible,
+            |enc, (member_id, group_instance_id, code)| {
+                enc.string(member_id, flexible)
+                    .nullable_string(group_instance_id.as_deref(), flexible)
+                    .i16(*code)
+                    .tagged_fields(flexible);
+            },
+        );
+    } else {
+        enc.i16(results.first().map_or(error::NONE, |(_, _, code)| *code));
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// OffsetCommit: store consumed positions for a group
+pub(super) fn offset_commit(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    let (generation_id, member_id) = if version >= 1 {
+        (dec.i32()?, dec.string(flexible)?)
+    } else {
+        (-1, String::new())
+    };
+    let group_instance_id = if version >= 7 {
+        dec.nullable_string(flexible)?
+    } else {
+        None
+    };
+    if (2..=4).contains(&version) {
+        dec.i64()?; // retention_time_ms
+    }
+    let topics = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let partitions = dec.array_of(flexible, |dec| {
+            let index = dec.i32()?;
+            let offset = dec.i64()?;
+            if version >= 6 {
+                dec.i32()?; // committed_leader_epoch
+            }
+            if version == 1 {
+                dec.i64()?; // commit_timestamp
+            }
+            dec.nullable_string(flexible)?; // committed_metadata
+            dec.tagged_fields(flexible)?;
+            Ok((index, offset))
+        })?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, partitions))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    // Standalone consumers commit with a negative generation into a group
+    // that may not exist yet; group members must match the live generation
+    let group = if group_id.is_empty() {
+        Err(error::INVALID_GROUP_ID)
+    } else if !may_read_group(broker, header, &group_id) {
+        Err(error::GROUP_AUTHORIZATION_FAILED)
+    } else if generation_id < 0 {
+        Ok(broker.streaming.consumer_group(&group_id))
+    } else {
+        broker
+            .streaming
+            .find_consumer_group(&group_id)
+            .ok_or(error::UNKNOWN_MEMBER_ID)
+    };
+    let group = group.and_then(|group| {
+        group
+            .validate_commit(&member_id, group_instance_id.as_deref(), generation_id)
+            .map(|()| group)
+            .map_err(|e| group_error_code(&e))
+    });
+
+    let mut responses = Vec::with_capacity(topics.len());
+    for (name, partitions) in topics {
+        let authorized = broker.permits(header, ResourceType::Topic, &name, Operation::Read);
+        let results: Vec<(i32, i16)> = partitions
+            .into_iter()
+            .map(|(index, offset)| {
+                let code = match &group {
+                    Err(code) => *code,
+                    Ok(_) if !authorized => error::TOPIC_AUTHORIZATION_FAILED,
+                    Ok(_) if broker.streaming.partition(&name, index).is_none() => {
+                        error::UNKNOWN_TOPIC_OR_PARTITION
+                    }
+                    Ok(group) => {
+                        let offsets = [(name.clone(), index, offset)];
+                        match broker.streaming.store_offsets(group.group_id(), &offsets) {
+                            Ok(()) => error::NONE,
+                            Err(e) => {
+                                warn!("Kafka offset commit for group {} failed: {}", group_id, e);
+                                error::UNKNOWN_SERVER_ERROR
+                            }
+                        }
+                    }
+                };
+                (index, code)
+            })
+            .collect();
+        responses.push((name, results));
+    }
+
+    if version >= 3 {
+        enc.i32(0);
+    }
+    enc.array_of(&responses, flexible, |enc, (name, partitions)| {
+        enc.string(name, flexible);
+        enc.array_of(partitions, flexible, |enc, &(index, code)| {
+            enc.i32(index).i16(code).tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// OffsetFetch: committed positions of a group
+pub(super) fn offset_fetch(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    // (partition, committed offset, error code) per requested partition
+    type PartitionOffset = (i32, i64, i16);
+
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let group_id = dec.string(flexible)?;
+    // A null topic list (v2+) asks for every committed offset
+    let requested = match dec.array_len(flexible)? {
+        Some(len) => {
+            let mut topics = Vec::with_capacity(len);
+            for _ in 0..len {
+                let name = dec.string(flexible)?;
+                let partitions = dec.array_of(flexible, Decoder::i32)?;
+                dec.tagged_fields(flexible)?;
+                topics.push((name, partitions));
+            }
+            Some(topics)
+        }
+        None => None,
+    };
+    if version >= 7 {
+        dec.bool()?; // require_stable
+    }
+    dec.tagged_fields(flexible)?;
+
+    let group_code = if broker.permits(header, ResourceType::Group, &group_id, Operation::Describe)
+    {
+        error::NONE
+    } else {
+        error::GROUP_AUTHORIZATION_FAILED
+    };
+    let topic_code = |name: &str| {
+        if group_code != error::NONE {
+            group_code
+        } else if broker.permits(header, ResourceType::Topic, name, Operation::Describe) {
+            error::NONE
+        } else {
+            error::TOPIC_AUTHORIZATION_FAILED
+        }
+    };
+
+    let group = broker
+        .streaming
+        .find_consumer_group(&group_id)
+        .filter(|_| group_code == error::NONE);
+    let offsets: Vec<(String, Vec<PartitionOffset>)> = if let Some(topics) = requested {
+        topics
+            .into_iter()
+            .map(|(name, partitions)| {
+                let code = topic_code(&name);
+                let offsets = partitions
+                    .into_iter()
+                    .map(|p| {
+                        let offset = group
+                            .as_ref()
+                            .filter(|_| code == error::NONE)
+                            .and_then(|g| g.get_offset(&name, p));
+                        (p, offset.unwrap_or(-1), code)
+                    })
+                    .collect();
+                (name, offsets)
+            })
+            .collect()
+    } else {
+        let mut by_topic: BTreeMap<String, Vec<PartitionOffset>> = BTreeMap::new();
+        for (topic, partition, offset) in group.map(|g| g.offsets()).unwrap_or_default() {
+            by_topic
+                .entry(topic)
+                .or_default()
+                .push((partition, offset, error::NONE));
+        }
+        by_topic
+            .into_iter()
+            .filter(|(topic, _)| topic_code(topic) == error::NONE)
+            .map(|(topic, mut partitions)| {
+                partitions.sort_unstable();
+                (topic, partitions)
+            })
+            .collect()
+    };
+
+    if version >= 3 {
+        enc.i32(0);
+    }
+    enc.array_of(&offsets, flexible, |enc, (name, partitions)| {
+        enc.string(name, flexible);
+        enc.array_of(partitions, flexible, |enc, &(index, offset, code)| {
+            enc.i32(index).i64(offset);
+            if version >= 5 {
+                enc.i32(-1); // committed_leader_epoch
+            }
+            enc.nullable_string(Some(""), flexible)
+                .i16(code)
+                .tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    if version >= 2 {
+        enc.i16(group_code);
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
@@ -1,25 +1,47 @@
 //! Kafka protocol implementation
 //!
-//! Provides 100% compatibility with Apache Kafka wire protocol.
+//! Serves the Kafka wire protocol on top of the streaming engine: produce,
+//! fetch and offset lookups against partition logs, consumer group
+//! coordination through [`lumadb_streaming::consumer::ConsumerGroup`],
+//! idempotent and transactional producers, topic administration, SASL
+//! authentication (PLAIN, SCRAM and OAUTHBEARER), and ACL and SCRAM
+//! credential administration. Every request is authorized
+//! against the authenticated principal's roles and ACLs the way a Kafka
+//! broker's authorizer would. Requests at flexible versions use
+//! compact encodings and tagged fields (see [`codec`]); record data travels
+//! as RecordBatch v2 (see [`records`]).
+
+pub mod codec;
+pub mod records;
+
+mod acls;
+mod admin;
+mod data;
+mod error;
+mod group;
+mod sasl;
+mod scram;
+mod txn;
 
-use std::sync::Arc;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
-use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::{info, debug, error};
-use bytes::{Buf, BufMut, BytesMut};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
 
-use lumadb_common::error::Result;
+use lumadb_common::error::{Error, ProtocolError, Result};
+use lumadb_security::authz::{Operation, Resource, ResourceType};
+use lumadb_security::auth::SaslExchange;
+use lumadb_security::{AuditEvent, SecurityManager, Session};
 use lumadb_streaming::StreamingEngine;
-use lumadb_security::SecurityManager;
+
+use codec::{Decoder, Encoder};
 
 /// Kafka protocol server
 #[derive(Clone)]
 pub struct KafkaServer {
-    config: KafkaConfig,
-    streaming: Arc<StreamingEngine>,
-    security: Arc<SecurityManager>,
+    broker: Arc<Broker>,
 }
 
 /// Kafka server configuration
@@ -27,6 +49,252 @@ pub struct KafkaServer {
 pub struct KafkaConfig {
     pub port: u16,
     pub host: String,
+    /// Host returned to clients in metadata responses
+    pub advertised_host: String,
+    /// Port returned to clients in metadata responses
+    pub advertised_port: u16,
+    /// Create unknown topics when clients request their metadata
+    pub auto_create_topics: bool,
+}
+
+/// State shared by every connection
+struct Broker {
+    config: KafkaConfig,
+    streaming: Arc<StreamingEngine>,
+    security: Arc<SecurityManager>,
+}
+
+/// This node's broker id; it leads every partition
+const NODE_ID: i32 = 1;
+/// Cluster id reported in metadata
+const CLUSTER_ID: &str = "lumadb";
+/// Largest request frame accepted, matching Kafka's `socket.request.max.bytes`
+const MAX_REQUEST_SIZE: usize = 100 * 1024 * 1024;
+
+pub(crate) const PRODUCE: i16 = 0;
+pub(crate) const FETCH: i16 = 1;
+pub(crate) const LIST_OFFSETS: i16 = 2;
+pub(crate) const METADATA: i16 = 3;
+pub(crate) const OFFSET_COMMIT: i16 = 8;
+pub(crate) const OFFSET_FETCH: i16 = 9;
+pub(crate) const FIND_COORDINATOR: i16 = 10;
+pub(crate) const JOIN_GROUP: i16 = 11;
+pub(crate) const HEARTBEAT: i16 = 12;
+pub(crate) const LEAVE_GROUP: i16 = 13;
+pub(crate) const SYNC_GROUP: i16 = 14;
+pub(crate) const SASL_HANDSHAKE: i16 = 17;
+pub(crate) const API_VERSIONS: i16 = 18;
+pub(crate) const CREATE_TOPICS: i16 = 19;
+pub(crate) const DELETE_TOPICS: i16 = 20;
+pub(crate) const INIT_PRODUCER_ID: i16 = 22;
+pub(crate) const ADD_PARTITIONS_TO_TXN: i16 = 24;
+pub(crate) const ADD_OFFSETS_TO_TXN: i16 = 25;
+pub(crate) const END_TXN: i16 = 26;
+pub(crate) const TXN_OFFSET_COMMIT: i16 = 28;
+pub(crate) const DESCRIBE_ACLS: i16 = 29;
+pub(crate) const CREATE_ACLS: i16 = 30;
+pub(crate) const DELETE_ACLS: i16 = 31;
+pub(crate) const DESCRIBE_CONFIGS: i16 = 32;
+pub(crate) const SASL_AUTHENTICATE: i16 = 36;
+pub(crate) const DESCRIBE_USER_SCRAM_CREDENTIALS: i16 = 50;
+pub(crate) const ALTER_USER_SCRAM_CREDENTIALS: i16 = 51;
+
+/// Supported version range of an API
+pub(crate) struct ApiSpec {
+    pub key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+    /// First version using the flexible (KIP-482) encoding
+    pub flexible_from: i16,
+}
+
+/// APIs served by this broker, as advertised through ApiVersions
+pub(crate) const APIS: &[ApiSpec] = &[
+    ApiSpec {
+        key: PRODUCE,
+        min_version: 3,
+        max_version: 9,
+        flexible_from: 9,
+    },
+    ApiSpec {
+        key: FETCH,
+        min_version: 4,
+        max_version: 12,
+        flexible_from: 12,
+    },
+    ApiSpec {
+        key: LIST_OFFSETS,
+        min_version: 1,
+        max_version: 6,
+        flexible_from: 6,
+    },
+    ApiSpec {
+        key: METADATA,
+        min_version: 0,
+        max_version: 12,
+        flexible_from: 9,
+    },
+    ApiSpec {
+        key: OFFSET_COMMIT,
+        min_version: 0,
+        max_version: 8,
+        flexible_from: 8,
+    },
+    ApiSpec {
+        key: OFFSET_FETCH,
+        min_version: 0,
+        max_version: 7,
+        flexible_from: 6,
+    },
+    ApiSpec {
+        key: FIND_COORDINATOR,
+        min_version: 0,
+        max_version: 4,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: JOIN_GROUP,
+        min_version: 0,
+        max_version: 7,
+        flexible_from: 6,
+    },
+    ApiSpec {
+        key: HEARTBEAT,
+        min_version: 0,
+        max_version: 4,
+        flexible_from: 4,
+    },
+    ApiSpec {
+        key: LEAVE_GROUP,
+        min_version: 0,
+        max_version: 4,
+        flexible_from: 4,
+    },
+    ApiSpec {
+        key: SYNC_GROUP,
+        min_version: 0,
+        max_version: 5,
+        flexible_from: 4,
+    },
+    ApiSpec {
+        key: SASL_HANDSHAKE,
+        min_version: 0,
+        max_version: 1,
+        flexible_from: i16::MAX,
+    },
+    ApiSpec {
+        key: API_VERSIONS,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: CREATE_TOPICS,
+        min_version: 0,
+        max_version: 5,
+        flexible_from: 5,
+    },
+    ApiSpec {
+        key: DELETE_TOPICS,
+        min_version: 0,
+        max_version: 5,
+        flexible_from: 4,
+    },
+    ApiSpec {
+        key: INIT_PRODUCER_ID,
+        min_version: 0,
+        max_version: 4,
+        flexible_from: 2,
+    },
+    ApiSpec {
+        key: ADD_PARTITIONS_TO_TXN,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: ADD_OFFSETS_TO_TXN,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: END_TXN,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: TXN_OFFSET_COMMIT,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 3,
+    },
+    ApiSpec {
+        key: DESCRIBE_ACLS,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 2,
+    },
+    ApiSpec {
+        key: CREATE_ACLS,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 2,
+    },
+    ApiSpec {
+        key: DELETE_ACLS,
+        min_version: 0,
+        max_version: 3,
+        flexible_from: 2,
+    },
+    ApiSpec {
+        key: DESCRIBE_CONFIGS,
+        min_version: 0,
+        max_version: 4,
+        flexible_from: 4,
+    },
+    ApiSpec {
+        key: SASL_AUTHENTICATE,
+        min_version: 0,
+        max_version: 2,
+        flexible_from: 2,
+    },
+    ApiSpec {
+        key: DESCRIBE_USER_SCRAM_CREDENTIALS,
+        min_version: 0,
+        max_version: 0,
+        flexible_from: 0,
+    },
+    ApiSpec {
+        key: ALTER_USER_SCRAM_CREDENTIALS,
+        min_version: 0,
+        max_version: 0,
+        flexible_from: 0,
+    },
+];
+
+/// Parsed request header
+pub(crate) struct RequestHeader {
+    pub api_version: i16,
+    pub client_id: Option<String>,
+    /// Whether the request body uses the flexible encoding
+    pub flexible: bool,
+    /// Principal of the connection, once authenticated
+    pub session: Option<Arc<Session>>,
+}
+
+/// Per-connection state
+pub(crate) struct Connection {
+    pub peer: SocketAddr,
+    /// Exchange of the mechanism selected by SaslHandshake
+    pub sasl: Option<SaslExchange>,
+    /// Whether the next frame is a bare SASL token (SaslHandshake v0)
+    pub raw_sasl: bool,
+    /// Authenticated principal
+    pub session: Option<Arc<Session>>,
+    /// Close the connection after the current response
+    pub close: bool,
 }
 
 impl KafkaServer {
@@ -36,19 +304,30 @@ impl KafkaServer {
         streaming: Arc<StreamingEngine>,
         security: Arc<SecurityManager>,
     ) -> Result<Self> {
+        let (advertised_host, advertised_port) = config
+            .advertised_listeners
+            .first()
+            .and_then(|listener| parse_listener(listener))
+            .unwrap_or_else(|| ("localhost".to_string(), config.port));
+
         Ok(Self {
-            config: KafkaConfig {
-                port: config.port,
-                host: "0.0.0.0".to_string(),
-            },
-            streaming,
-            security,
+            broker: Arc::new(Broker {
+                config: KafkaConfig {
+                    port: config.port,
+                    host: "0.0.0.0".to_string(),
+                    advertised_host,
+                    advertised_port,
+                    auto_create_topics: config.auto_create_topics,
+                },
+                streaming,
+                security,
+            }),
         })
     }
 
     /// Run the Kafka server
     pub async fn run(&self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.host, self.config.port);
+        let addr = format!("{}:{}", self.broker.config.host, self.broker.config.port);
         let listener = TcpListener::bind(&addr).await?;
 
         info!("Kafka protocol server listening on {}", addr);
@@ -56,11 +335,10 @@ impl KafkaServer {
         loop {
             match listener.accept().await {
                 Ok((socket, addr)) => {
-                    let streaming = self.streaming.clone();
-                    let security = self.security.clone();
+                    let broker = self.broker.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(socket, addr, streaming, security).await {
+                        if let Err(e) = handle_connection(socket, addr, broker).await {
                             error!("Connection error from {}: {}", addr, e);
                         }
                     });
@@ -79,38 +357,68 @@ impl KafkaServer {
     }
 }
 
+/// Split an advertised listener such as `PLAINTEXT://host:9092` into host and
+/// port
+fn parse_listener(listener: &str) -> Option<(String, u16)> {
+    let address = listener
+        .split_once("://")
+        .map_or(listener, |(_, rest)| rest);
+    let (host, port) = address.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
+
 async fn handle_connection(
     mut socket: TcpStream,
     addr: SocketAddr,
-    streaming: Arc<StreamingEngine>,
-    security: Arc<SecurityManager>,
+    broker: Arc<Broker>,
 ) -> Result<()> {
     debug!("New Kafka connection from {}", addr);
 
-    let mut buffer = BytesMut::with_capacity(65536);
+    let mut conn = Connection {
+        peer: addr,
+        sasl: None,
+        raw_sasl: false,
+        session: None,
+        close: false,
+    };
 
+    // Requests on a connection are answered strictly in order, as clients
+    // match responses to requests by position as well as correlation id
     loop {
-        // Read request
-        let n = socket.read_buf(&mut buffer).await?;
-        if n == 0 {
-            break;
+        let size = match socket.read_i32().await {
+            Ok(size) => size,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|&size| size <= MAX_REQUEST_SIZE)
+            .ok_or_else(|| {
+                Error::Protocol(ProtocolError::InvalidFormat(format!(
+                    "invalid request size {size}"
+                )))
+            })?;
+        let mut request = vec![0u8; size];
+        socket.read_exact(&mut request).await?;
+
+        let response = if conn.raw_sasl {
+            sasl::raw_authenticate(&broker, &mut conn, &request).await
+        } else {
+            process_request(&broker, &mut conn, &request).await?
+        };
+
+        if let Some(response) = response {
+            let mut frame = Vec::with_capacity(4 + response.len());
+            frame.extend_from_slice(
+                &u32::try_from(response.len())
+                    .unwrap_or(u32::MAX)
+                    .to_be_bytes(),
+            );
+            frame.extend_from_slice(&response);
+            socket.write_all(&frame).await?;
         }
-
-        // Parse and handle request
-        while buffer.len() >= 4 {
-            let size = (&buffer[..4]).get_i32() as usize;
-            if buffer.len() < 4 + size {
-                break;
-            }
-
-            let request_data = buffer.split_to(4 + size);
-            let response = process_request(&request_data[4..], &streaming).await?;
-
-            // Send response
-            let mut response_buf = BytesMut::new();
-            response_buf.put_i32(response.len() as i32);
-            response_buf.extend_from_slice(&response);
-            socket.write_all(&response_buf).await?;
+        if conn.close {
+            break;
         }
     }
 
@@ -118,118 +426,189 @@ async fn handle_connection(
     Ok(())
 }
 
+/// Decode a request, run its handler and encode the response
+///
+/// Returns `None` when the request expects no response (produce with
+/// `acks=0`). Malformed requests, unsupported APIs and requests sent
+/// before SASL authentication completes fail the connection, as they do on
+/// a Kafka broker.
 async fn process_request(
+    broker: &Broker,
+    conn: &mut Connection,
     data: &[u8],
-    streaming: &Arc<StreamingEngine>,
-) -> Result<Vec<u8>> {
-    if data.len() < 4 {
-        return Ok(vec![]);
-    }
-
-    let api_key = i16::from_be_bytes([data[0], data[1]]);
-    let api_version = i16::from_be_bytes([data[2], data[3]]);
+) -> Result<Option<Vec<u8>>> {
+    let mut dec = Decoder::new(data);
+    let api_key = dec.i16()?;
+    let api_version = dec.i16()?;
+    let correlation_id = dec.i32()?;
 
     debug!("Kafka request: api_key={}, version={}", api_key, api_version);
 
-    // Handle different API keys
-    match api_key {
-        0 => handle_produce(data, streaming).await,
-        1 => handle_fetch(data, streaming).await,
-        3 => handle_metadata(data, streaming).await,
-        18 => handle_api_versions(data).await,
-        _ => {
-            debug!("Unsupported API key: {}", api_key);
-            Ok(vec![])
-        }
+    let Some(spec) = APIS.iter().find(|spec| spec.key == api_key) else {
+        return Err(Error::Protocol(ProtocolError::UnsupportedOperation(
+            format!("Kafka API key {api_key}"),
+        )));
+    };
+    let supported = (spec.min_version..=spec.max_version).contains(&api_version);
+    if !supported && api_key != API_VERSIONS {
+        return Err(Error::Protocol(ProtocolError::UnsupportedVersion(format!(
+            "Kafka API key {api_key} version {api_version}"
+        ))));
     }
-}
 
-async fn handle_produce(
-    _data: &[u8],
-    _streaming: &Arc<StreamingEngine>,
-) -> Result<Vec<u8>> {
-    // Simplified produce response
-    Ok(vec![0; 8])
-}
+    let flexible = supported && api_version >= spec.flexible_from;
+    // The client id keeps its classic encoding even in flexible headers
+    let client_id = dec.nullable_string(false)?;
+    dec.tagged_fields(flexible)?;
+
+    let header = RequestHeader {
+        api_version,
+        client_id,
+        flexible,
+        session: conn.session.clone(),
+    };
+
+    if broker.security.auth_enabled()
+        && conn.session.is_none()
+        && !matches!(api_key, API_VERSIONS | SASL_HANDSHAKE | SASL_AUTHENTICATE)
+    {
+        warn!("Rejecting unauthenticated Kafka request from {}", conn.peer);
+        return Err(Error::Protocol(ProtocolError::UnsupportedOperation(
+            "request before SASL authentication".to_string(),
+        )));
+    }
 
-async fn handle_fetch(
-    _data: &[u8],
-    _streaming: &Arc<StreamingEngine>,
-) -> Result<Vec<u8>> {
-    // Simplified fetch response
-    Ok(vec![0; 8])
+    // ApiVersions responses always use header v0 so that clients can parse
+    // them before knowing which versions the broker supports
+    let mut enc = Encoder::new();
+    enc.i32(correlation_id)
+        .tagged_fields(flexible && api_key != API_VERSIONS);
+
+    // Produce with acks=0 is the one request answered with nothing
+    let respond = if api_key == PRODUCE {
+        data::produce(broker, &header, &mut dec, &mut enc)?
+    } else {
+        dispatch(
+            broker, conn, api_key, &header, supported, &mut dec, &mut enc,
+        )
+        .await?;
+        true
+    };
+
+    Ok(respond.then(|| enc.into_inner()))
 }
 
-async fn handle_metadata(
-    _data: &[u8],
-    _streaming: &Arc<StreamingEngine>,
-) -> Result<Vec<u8>> {
-    // Simplified metadata response
-    let mut response = Vec::new();
-
-    // Correlation ID placeholder
-    response.extend_from_slice(&[0, 0, 0, 0]);
-
-    // Throttle time
-    response.extend_from_slice(&[0, 0, 0, 0]);
-
-    // Brokers array (1 broker)
-    response.extend_from_slice(&[0, 0, 0, 1]);
-
-    // Broker ID
-    response.extend_from_slice(&[0, 0, 0, 1]);
-
-    // Host
-    let host = b"localhost";
-    response.extend_from_slice(&(host.len() as i16).to_be_bytes());
-    response.extend_from_slice(host);
-
-    // Port
-    response.extend_from_slice(&9092i32.to_be_bytes());
-
-    // Rack (null)
-    response.extend_from_slice(&(-1i16).to_be_bytes());
-
-    // Cluster ID (null)
-    response.extend_from_slice(&(-1i16).to_be_bytes());
-
-    // Controller ID
-    response.extend_from_slice(&[0, 0, 0, 1]);
-
-    // Topics array (empty)
-    response.extend_from_slice(&[0, 0, 0, 0]);
-
-    Ok(response)
+/// Run the handler for `api_key`, which is not Produce
+async fn dispatch(
+    broker: &Broker,
+    conn: &mut Connection,
+    api_key: i16,
+    header: &RequestHeader,
+    supported: bool,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    match api_key {
+        FETCH => data::fetch(broker, header, dec, enc).await?,
+        LIST_OFFSETS => data::list_offsets(broker, header, dec, enc)?,
+        METADATA => admin::metadata(broker, header, dec, enc).await?,
+        OFFSET_COMMIT => group::offset_commit(broker, header, dec, enc)?,
+        OFFSET_FETCH => group::offset_fetch(broker, header, dec, enc)?,
+        FIND_COORDINATOR => group::find_coordinator(broker, header, dec, enc)?,
+        JOIN_GROUP => group::join_group(broker, conn, header, dec, enc).await?,
+        HEARTBEAT => group::heartbeat(broker, header, dec, enc)?,
+        LEAVE_GROUP => group::leave_group(broker, header, dec, enc)?,
+        SYNC_GROUP => group::sync_group(broker, header, dec, enc).await?,
+        SASL_HANDSHAKE => sasl::handshake(broker, conn, header, dec, enc)?,
+        API_VERSIONS => admin::api_versions(header, supported, enc),
+        CREATE_TOPICS => admin::create_topics(broker, header, dec, enc).await?,
+        DELETE_TOPICS => admin::delete_topics(broker, header, dec, enc).await?,
+        DESCRIBE_ACLS => acls::describe_acls(broker, header, dec, enc)?,
+        CREATE_ACLS => acls::create_acls(broker, header, dec, enc)?,
+        DELETE_ACLS => acls::delete_acls(broker, header, dec, enc)?,
+        DESCRIBE_CONFIGS => admin::describe_configs(broker, header, dec, enc)?,
+        INIT_PRODUCER_ID => txn::init_producer_id(broker, header, dec, enc)?,
+        ADD_PARTITIONS_TO_TXN => txn::add_partitions_to_txn(broker, header, dec, enc)?,
+        ADD_OFFSETS_TO_TXN => txn::add_offsets_to_txn(broker, header, dec, enc)?,
+        END_TXN => txn::end_txn(broker, header, dec, enc)?,
+        TXN_OFFSET_COMMIT => txn::txn_offset_commit(broker, header, dec, enc)?,
+        SASL_AUTHENTICATE => sasl::authenticate(broker, conn, header, dec, enc).await?,
+        DESCRIBE_USER_SCRAM_CREDENTIALS => {
+            scram::describe_user_scram_credentials(broker, header, dec, enc)?;
+        }
+        ALTER_USER_SCRAM_CREDENTIALS => {
+            scram::alter_user_scram_credentials(broker, header, dec, enc)?;
+        }
+        _ => unreachable!("every advertised API has a handler"),
+    }
+    Ok(())
 }
 
-async fn handle_api_versions(_data: &[u8]) -> Result<Vec<u8>> {
-    let mut response = Vec::new();
-
-    // Correlation ID placeholder
-    response.extend_from_slice(&[0, 0, 0, 0]);
-
-    // Error code (0 = success)
-    response.extend_from_slice(&[0, 0]);
-
-    // API versions array
-    let api_versions: &[(i16, i16, i16)] = &[
-        (0, 0, 8),   // Produce
-        (1, 0, 11),  // Fetch
-        (2, 0, 6),   // ListOffsets
-        (3, 0, 9),   // Metadata
-        (18, 0, 3),  // ApiVersions
-    ];
+impl Broker {
+    /// Whether the principal of a request may perform `operation` on the
+    /// named resource; without authentication every request is allowed
+    pub(crate) fn permits(
+        &self,
+        header: &RequestHeader,
+        resource_type: ResourceType,
+        name: &str,
+        operation: Operation,
+    ) -> bool {
+        match &header.session {
+            Some(session) => {
+                self.security
+                    .permits(session, &Resource::new(resource_type, name), operation)
+            }
+            None => !self.security.auth_enabled(),
+        }
+    }
 
-    response.extend_from_slice(&(api_versions.len() as i32).to_be_bytes());
+    /// Like [`Broker::permits`], but a refusal is not audited; for
+    /// filtering listings and probing fallbacks
+    pub(crate) fn allows(
+        &self,
+        header: &RequestHeader,
+        resource_type: ResourceType,
+        name: &str,
+        operation: Operation,
+    ) -> bool {
+        match &header.session {
+            Some(session) => {
+                self.security
+                    .allows(session, &Resource::new(resource_type, name), operation)
+            }
+            None => !self.security.auth_enabled(),
+        }
+    }
 
-    for (api_key, min_version, max_version) in api_versions {
-        response.extend_from_slice(&api_key.to_be_bytes());
-        response.extend_from_slice(&min_version.to_be_bytes());
-        response.extend_from_slice(&max_version.to_be_bytes());
+    /// Record `event`, taken by the principal of a request, in the audit
+    /// trail
+    pub(crate) fn audit(&self, header: &RequestHeader, event: AuditEvent) {
+        let event = match header.session.as_deref() {
+            Some(session) => event.by(session),
+            None => event.by(&Session::anonymous()),
+        };
+        self.security.audit(event);
     }
 
-    // Throttle time
-    response.extend_from_slice(&[0, 0, 0, 0]);
+    /// Whether the principal of a request may perform `operation` on some
+    /// resource of `resource_type`
+    pub(crate) fn permits_any(
+        &self,
+        header: &RequestHeader,
+        resource_type: ResourceType,
+        operation: Operation,
+    ) -> bool {
+        match &header.session {
+            Some(session) => self
+                .security
+                .is_authorized_any(session, resource_type, operation),
+            None => !self.security.auth_enabled(),
+        }
+    }
+}
 
-    Ok(response)
+/// Current time in milliseconds
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
 }
@@ -0,0 +1,585 @@
+//! RecordBatch v2 (magic 2) encoding
+//!
+//! A batch is a fixed header followed by varint-framed records, optionally
+//! compressed as a whole. The header CRC is CRC32C (Castagnoli) over
+//! everything from the attributes field to the end of the batch.
+
+use std::io::{Read, Write};
+
+use lumadb_common::error::{Error, ProtocolError, Result};
+
+use super::codec::{invalid, Decoder, Encoder};
+
+/// Offset of the magic byte within a batch
+const MAGIC_OFFSET: usize = 16;
+/// Offset of the first CRC-covered byte (the attributes field)
+const ATTRIBUTES_OFFSET: usize = 21;
+/// Size of the batch header up to and including the record count
+const HEADER_SIZE: usize = 61;
+
+const COMPRESSION_MASK: i16 = 0x07;
+const TIMESTAMP_TYPE_FLAG: i16 = 0x08;
+const TRANSACTIONAL_FLAG: i16 = 0x10;
+const CONTROL_FLAG: i16 = 0x20;
+
+/// Stream header written by the xerial snappy framing the Java client uses
+const XERIAL_SNAPPY_MAGIC: &[u8] = b"\x82SNAPPY\x00";
+
+/// Record batch compression codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    fn from_attributes(attributes: i16) -> Result<Self> {
+        match attributes & COMPRESSION_MASK {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Gzip),
+            2 => Ok(Self::Snappy),
+            3 => Ok(Self::Lz4),
+            4 => Ok(Self::Zstd),
+            other => Err(invalid(&format!("unknown compression codec {other}"))),
+        }
+    }
+
+    fn attribute(self) -> i16 {
+        match self {
+            Self::None => 0,
+            Self::Gzip => 1,
+            Self::Snappy => 2,
+            Self::Lz4 => 3,
+            Self::Zstd => 4,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Snappy => {
+                let block = snap::raw::Encoder::new()
+                    .compress_vec(data)
+                    .map_err(|e| Error::Internal(format!("snappy compression failed: {e}")))?;
+                let mut out = Vec::with_capacity(block.len() + 20);
+                out.extend_from_slice(XERIAL_SNAPPY_MAGIC);
+                out.extend_from_slice(&1i32.to_be_bytes());
+                out.extend_from_slice(&1i32.to_be_bytes());
+                out.extend_from_slice(
+                    &u32::try_from(block.len()).unwrap_or(u32::MAX).to_be_bytes(),
+                );
+                out.extend_from_slice(&block);
+                Ok(out)
+            }
+            Self::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+                encoder
+                    .finish()
+                    .map_err(|e| Error::Internal(format!("lz4 compression failed: {e}")))
+            }
+            Self::Zstd => Ok(zstd::stream::encode_all(data, 0)?),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        let corrupt = |codec: &str, e: &dyn std::fmt::Display| {
+            invalid(&format!("corrupt {codec} record batch: {e}"))
+        };
+        let mut out = Vec::new();
+        match self {
+            Self::None => out.extend_from_slice(data),
+            Self::Gzip => {
+                flate2::read::MultiGzDecoder::new(data)
+                    .read_to_end(&mut out)
+                    .map_err(|e| corrupt("gzip", &e))?;
+            }
+            Self::Snappy => {
+                let mut decoder = snap::raw::Decoder::new();
+                if let Some(mut blocks) = data.strip_prefix(XERIAL_SNAPPY_MAGIC) {
+                    // Version and compatible version precede the blocks
+                    blocks = blocks
+                        .get(8..)
+                        .ok_or_else(|| invalid("truncated snappy header"))?;
+                    while !blocks.is_empty() {
+                        let mut dec = Decoder::new(blocks);
+                        let len =
+                            usize::try_from(dec.i32()?).map_err(|_| invalid("bad snappy block"))?;
+                        let block = dec.take(len)?;
+                        out.extend(
+                            decoder
+                                .decompress_vec(block)
+                                .map_err(|e| corrupt("snappy", &e))?,
+                        );
+                        blocks = &blocks[4 + len..];
+                    }
+                } else {
+                    out = decoder
+                        .decompress_vec(data)
+                        .map_err(|e| corrupt("snappy", &e))?;
+                }
+            }
+            Self::Lz4 => {
+                lz4_flex::frame::FrameDecoder::new(data)
+                    .read_to_end(&mut out)
+                    .map_err(|e| corrupt("lz4", &e))?;
+            }
+            Self::Zstd => {
+                out = zstd::stream::decode_all(data).map_err(|e| corrupt("zstd", &e))?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// A record within a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchRecord {
+    pub offset_delta: i32,
+    pub timestamp_delta: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<(String, Option<Vec<u8>>)>,
+}
+
+/// A v2 record batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    pub base_offset: i64,
+    pub partition_leader_epoch: i32,
+    pub attributes: i16,
+    pub last_offset_delta: i32,
+    pub base_timestamp: i64,
+    pub max_timestamp: i64,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    pub records: Vec<BatchRecord>,
+}
+
+impl RecordBatch {
+    /// A batch of plain records with no producer state
+    #[must_use]
+    pub fn new(base_offset: i64, base_timestamp: i64, records: Vec<BatchRecord>) -> Self {
+        let last_offset_delta = records.last().map_or(0, |r| r.offset_delta);
+        let max_timestamp = records
+            .iter()
+            .map(|r| base_timestamp + r.timestamp_delta)
+            .max()
+            .unwrap_or(base_timestamp);
+        Self {
+            base_offset,
+            partition_leader_epoch: 0,
+            attributes: 0,
+            last_offset_delta,
+            base_timestamp,
+            max_timestamp,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records,
+        }
+    }
+
+    /// Stamp the batch with the producer that wrote it
+    #[must_use]
+    pub fn with_producer(
+        mut self,
+        producer_id: i64,
+        producer_epoch: i16,
+        base_sequence: i32,
+        transactional: bool,
+    ) -> Self {
+        self.producer_id = producer_id;
+        self.producer_epoch = producer_epoch;
+        self.base_sequence = base_sequence;
+        if transactional {
+            self.attributes |= TRANSACTIONAL_FLAG;
+        } else {
+            self.attributes &= !TRANSACTIONAL_FLAG;
+        }
+        self
+    }
+
+    /// Mark the batch as holding a transaction marker
+    #[must_use]
+    pub fn with_control(mut self) -> Self {
+        self.attributes |= CONTROL_FLAG;
+        self
+    }
+
+    /// Compression codec of the batch's records
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attributes name an unknown codec.
+    pub fn compression(&self) -> Result<Compression> {
+        Compression::from_attributes(self.attributes)
+    }
+
+    /// Whether timestamps were assigned by the broker on append
+    #[must_use]
+    pub fn is_log_append_time(&self) -> bool {
+        self.attributes & TIMESTAMP_TYPE_FLAG != 0
+    }
+
+    #[must_use]
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_FLAG != 0
+    }
+
+    #[must_use]
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_FLAG != 0
+    }
+
+    /// Decode every batch in a records field
+    ///
+    /// A trailing partial batch, which brokers may return when a fetch hits
+    /// its size limit, is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a complete batch is malformed.
+    pub fn decode_all(mut data: &[u8]) -> Result<Vec<Self>> {
+        let mut batches = Vec::new();
+        while data.len() >= HEADER_SIZE {
+            let mut dec = Decoder::new(data);
+            dec.i64()?;
+            let length =
+                usize::try_from(dec.i32()?).map_err(|_| invalid("negative batch length"))?;
+            let Some(batch) = data.get(..12 + length) else {
+                break;
+            };
+            batches.push(Self::decode(batch)?);
+            data = &data[batch.len()..];
+        }
+        Ok(batches)
+    }
+
+    /// Decode a single batch, verifying its CRC
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch is truncated, has another magic byte,
+    /// fails its CRC or holds malformed records.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+        }
+        if data[MAGIC_OFFSET] != 2 {
+            return Err(Error::Protocol(ProtocolError::UnsupportedVersion(format!(
+                "record batch magic {}",
+                data[MAGIC_OFFSET]
+            ))));
+        }
+
+        let mut dec = Decoder::new(data);
+        let base_offset = dec.i64()?;
+        let length = usize::try_from(dec.i32()?).map_err(|_| invalid("negative batch length"))?;
+        if data.len() != 12 + length {
+            return Err(invalid("record batch length mismatch"));
+        }
+        let partition_leader_epoch = dec.i32()?;
+        dec.i8()?;
+        #[allow(clippy::cast_sign_loss)]
+        let crc = dec.i32()? as u32;
+        if crc32c(&data[ATTRIBUTES_OFFSET..]) != crc {
+            return Err(Error::Protocol(ProtocolError::CrcMismatch));
+        }
+
+        let attributes = dec.i16()?;
+        let last_offset_delta = dec.i32()?;
+        let base_timestamp = dec.i64()?;
+        let max_timestamp = dec.i64()?;
+        let producer_id = dec.i64()?;
+        let producer_epoch = dec.i16()?;
+        let base_sequence = dec.i32()?;
+        let count = usize::try_from(dec.i32()?).map_err(|_| invalid("negative record count"))?;
+
+        let compression = Compression::from_attributes(attributes)?;
+        let body = compression.decompress(dec.take(dec.remaining())?)?;
+        let mut dec = Decoder::new(&body);
+        let mut records = Vec::with_capacity(count.min(body.len()));
+        for _ in 0..count {
+            let len =
+                usize::try_from(dec.varint()?).map_err(|_| invalid("negative record length"))?;
+            records.push(decode_record(dec.take(len)?)?);
+        }
+
+        Ok(Self {
+            base_offset,
+            partition_leader_epoch,
+            attributes,
+            last_offset_delta,
+            base_timestamp,
+            max_timestamp,
+            producer_id,
+            producer_epoch,
+            base_sequence,
+            records,
+        })
+    }
+
+    /// Encode the batch, compressing its records with `compression`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the records cannot be compressed.
+    pub fn encode(&self, compression: Compression) -> Result<Vec<u8>> {
+        let mut body = Encoder::new();
+        for record in &self.records {
+            let encoded = encode_record(record);
+            body.varint(i32::try_from(encoded.len()).unwrap_or(i32::MAX))
+                .raw(&encoded);
+        }
+        let body = compression.compress(&body.into_inner())?;
+        let attributes = (self.attributes & !COMPRESSION_MASK) | compression.attribute();
+
+        let mut enc = Encoder::new();
+        enc.i64(self.base_offset)
+            .i32(0) // length, patched below
+            .i32(self.partition_leader_epoch)
+            .i8(2)
+            .i32(0) // crc, patched below
+            .i16(attributes)
+            .i32(self.last_offset_delta)
+            .i64(self.base_timestamp)
+            .i64(self.max_timestamp)
+            .i64(self.producer_id)
+            .i16(self.producer_epoch)
+            .i32(self.base_sequence)
+            .i32(i32::try_from(self.records.len()).unwrap_or(i32::MAX))
+            .raw(&body);
+
+        let mut out = enc.into_inner();
+        let length = i32::try_from(out.len() - 12).unwrap_or(i32::MAX);
+        out[8..12].copy_from_slice(&length.to_be_bytes());
+        let crc = crc32c(&out[ATTRIBUTES_OFFSET..]);
+        out[17..21].copy_from_slice(&crc.to_be_bytes());
+        Ok(out)
+    }
+}
+
+fn decode_record(data: &[u8]) -> Result<BatchRecord> {
+    let mut dec = Decoder::new(data);
+    dec.i8()?; // attributes, unused
+    let timestamp_delta = dec.varlong()?;
+    let offset_delta = dec.varint()?;
+    let key = varint_bytes(&mut dec)?;
+    let value = varint_bytes(&mut dec)?;
+    let count = usize::try_from(dec.varint()?).map_err(|_| invalid("negative header count"))?;
+    let mut headers = Vec::with_capacity(count.min(data.len()));
+    for _ in 0..count {
+        let name = varint_bytes(&mut dec)?.ok_or_else(|| invalid("null header key"))?;
+        let name = String::from_utf8(name).map_err(|_| invalid("header key is not valid UTF-8"))?;
+        headers.push((name, varint_bytes(&mut dec)?));
+    }
+    Ok(BatchRecord {
+        offset_delta,
+        timestamp_delta,
+        key,
+        value,
+        headers,
+    })
+}
+
+fn encode_record(record: &BatchRecord) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    enc.i8(0)
+        .varlong(record.timestamp_delta)
+        .varint(record.offset_delta);
+    put_varint_bytes(&mut enc, record.key.as_deref());
+    put_varint_bytes(&mut enc, record.value.as_deref());
+    enc.varint(i32::try_from(record.headers.len()).unwrap_or(i32::MAX));
+    for (name, value) in &record.headers {
+        put_varint_bytes(&mut enc, Some(name.as_bytes()));
+        put_varint_bytes(&mut enc, value.as_deref());
+    }
+    enc.into_inner()
+}
+
+fn varint_bytes(dec: &mut Decoder<'_>) -> Result<Option<Vec<u8>>> {
+    let len = dec.varint()?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let len = usize::try_from(len).map_err(|_| invalid("bad length"))?;
+    Ok(Some(dec.take(len)?.to_vec()))
+}
+
+fn put_varint_bytes(enc: &mut Encoder, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            enc.varint(i32::try_from(value.len()).unwrap_or(i32::MAX))
+                .raw(value);
+        }
+        None => {
+            enc.varint(-1);
+        }
+    }
+}
+
+/// CRC32C lookup table for the reflected Castagnoli polynomial
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0x82F6_3B78
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Key and value of a transaction marker record: a version and the marker
+/// type (0 abort, 1 commit), then a version and the coordinator epoch
+#[must_use]
+pub fn control_record(commit: bool) -> (Vec<u8>, Vec<u8>) {
+    let mut key = Encoder::new();
+    key.i16(0).i16(i16::from(commit));
+    let mut value = Encoder::new();
+    value.i16(0).i32(0);
+    (key.into_inner(), value.into_inner())
+}
+
+/// CRC32C (Castagnoli) checksum
+#[must_use]
+pub fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_batch() -> RecordBatch {
+        RecordBatch::new(
+            42,
+            1_700_000_000_000,
+            vec![
+                BatchRecord {
+                    offset_delta: 0,
+                    timestamp_delta: 0,
+                    key: Some(b"k1".to_vec()),
+                    value: Some(b"v1".to_vec()),
+                    headers: vec![("trace".to_string(), Some(b"abc".to_vec()))],
+                },
+                BatchRecord {
+                    offset_delta: 2,
+                    timestamp_delta: 5,
+                    key: Some(b"k2".to_vec()),
+                    value: None,
+                    headers: Vec::new(),
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn batch_round_trips_with_every_codec() {
+        let batch = sample_batch();
+        for compression in [
+            Compression::None,
+            Compression::Gzip,
+            Compression::Snappy,
+            Compression::Lz4,
+            Compression::Zstd,
+        ] {
+            let bytes = batch.encode(compression).unwrap();
+            let decoded = RecordBatch::decode(&bytes).unwrap();
+            assert_eq!(decoded.compression().unwrap(), compression);
+            assert_eq!(decoded.records, batch.records);
+            assert_eq!(decoded.last_offset_delta, 2);
+            assert_eq!(decoded.max_timestamp, 1_700_000_000_005);
+        }
+    }
+
+    #[test]
+    fn corrupted_batch_fails_crc() {
+        let mut bytes = sample_batch().encode(Compression::None).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(matches!(
+            RecordBatch::decode(&bytes),
+            Err(Error::Protocol(ProtocolError::CrcMismatch))
+        ));
+    }
+
+    #[test]
+    fn decode_all_skips_partial_trailing_batch() {
+        let one = sample_batch().encode(Compression::None).unwrap();
+        let mut data = one.clone();
+        data.extend_from_slice(&one);
+        data.truncate(one.len() + one.len() / 2);
+
+        let batches = RecordBatch::decode_all(&data).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].base_offset, 42);
+    }
+
+    #[test]
+    fn producer_and_control_fields_round_trip() {
+        let bytes = sample_batch()
+            .with_producer(9, 2, 17, true)
+            .encode(Compression::None)
+            .unwrap();
+        let decoded = RecordBatch::decode(&bytes).unwrap();
+        assert_eq!(
+            (
+                decoded.producer_id,
+                decoded.producer_epoch,
+                decoded.base_sequence
+            ),
+            (9, 2, 17)
+        );
+        assert!(decoded.is_transactional());
+        assert!(!decoded.is_control());
+
+        let (key, value) = control_record(true);
+        assert_eq!(key, [0, 0, 0, 1]);
+        assert_eq!(value, [0, 0, 0, 0, 0, 0]);
+        let marker = RecordBatch::new(
+            50,
+            0,
+            vec![BatchRecord {
+                offset_delta: 0,
+                timestamp_delta: 0,
+                key: Some(key),
+                value: Some(value),
+                headers: Vec::new(),
+            }],
+        )
+        .with_producer(9, 2, -1, true)
+        .with_control();
+        let decoded = RecordBatch::decode(&marker.encode(Compression::None).unwrap()).unwrap();
+        assert!(decoded.is_control() && decoded.is_transactional());
+    }
+}
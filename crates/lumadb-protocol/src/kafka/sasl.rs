@@ -0,0 +1,142 @@
+//! SASL authentication
+//!
+//! SaslHandshake selects a mechanism: PLAIN, SCRAM-SHA-256, SCRAM-SHA-512
+//! or, when a JWT secret is configured, OAUTHBEARER. From handshake v1 the
+//! exchange runs inside SaslAuthenticate requests; after a v0 handshake the
+//! client sends the bare SASL tokens as size-prefixed frames instead. SCRAM
+//! takes two round trips, the other mechanisms one.
+
+use std::sync::Arc;
+
+use tracing::{debug, warn};
+
+use lumadb_common::error::{AuthError, Error, Result};
+use lumadb_security::auth::SaslStep;
+
+use super::codec::{Decoder, Encoder};
+use super::error;
+use super::{Broker, Connection, RequestHeader};
+
+/// SaslHandshake
+pub(super) fn handshake(
+    broker: &Broker,
+    conn: &mut Connection,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let mechanism = dec.string(false)?;
+
+    let code = if conn.sasl.is_some() {
+        error::ILLEGAL_SASL_STATE
+    } else if let Some(exchange) = broker.security.sasl_exchange(&mechanism) {
+        conn.raw_sasl = header.api_version == 0;
+        conn.sasl = Some(exchange);
+        error::NONE
+    } else {
+        error::UNSUPPORTED_SASL_MECHANISM
+    };
+
+    let mechanisms = broker.security.sasl_mechanisms();
+    enc.i16(code);
+    enc.array_of(&mechanisms, false, |enc, mechanism| {
+        enc.string(mechanism.name(), false);
+    });
+    Ok(())
+}
+
+/// SaslAuthenticate
+///
+/// A failed authentication is reported and the connection closed.
+pub(super) async fn authenticate(
+    broker: &Broker,
+    conn: &mut Connection,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+    let auth_bytes = dec.bytes(flexible)?;
+    dec.tagged_fields(flexible)?;
+
+    let (code, message, reply) = match step(broker, conn, auth_bytes).await {
+        Ok(reply) => (error::NONE, None, reply),
+        Err((code, message)) => {
+            conn.close = true;
+            (code, Some(message), Vec::new())
+        }
+    };
+    enc.i16(code).nullable_string(message.as_deref(), flexible);
+    enc.bytes(&reply, flexible);
+    if header.api_version >= 1 {
+        enc.i64(0); // session_lifetime_ms: no re-authentication required
+    }
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// Take a bare token sent after a v0 handshake
+///
+/// The server's reply is sent back as a bare frame; on failure the
+/// connection is closed without a response.
+pub(super) async fn raw_authenticate(
+    broker: &Broker,
+    conn: &mut Connection,
+    token: &[u8],
+) -> Option<Vec<u8>> {
+    let reply = step(broker, conn, token).await.ok();
+    conn.close |= reply.is_none();
+    reply
+}
+
+/// Feed a client message to the connection's exchange, returning the
+/// server's reply; authenticates the connection once the exchange completes
+async fn step(
+    broker: &Broker,
+    conn: &mut Connection,
+    token: &[u8],
+) -> std::result::Result<Vec<u8>, (i16, String)> {
+    let exchange = match conn.sasl.as_mut() {
+        Some(exchange) if conn.session.is_none() && !exchange.is_complete() => exchange,
+        _ => {
+            conn.raw_sasl = false;
+            let message = "SaslAuthenticate out of sequence".to_string();
+            return Err((error::ILLEGAL_SASL_STATE, message));
+        }
+    };
+    let mechanism = exchange.mechanism().name();
+
+    match broker.security.sasl_step(exchange, token).await {
+        Ok(SaslStep::Challenge(challenge)) => Ok(challenge),
+        Ok(SaslStep::Complete(session, reply)) => {
+            debug!(
+                "Kafka client {} authenticated as {} with {}",
+                conn.peer, session.username, mechanism
+            );
+            conn.raw_sasl = false;
+            conn.session = Some(Arc::new(session.with_host(conn.peer.ip())));
+            Ok(reply)
+        }
+        Err(e) => {
+            warn!("Kafka SASL authentication from {} failed: {}", conn.peer, e);
+            conn.raw_sasl = false;
+            Err((
+                error::SASL_AUTHENTICATION_FAILED,
+                failure_message(mechanism, &e),
+            ))
+        }
+    }
+}
+
+/// What a client is told about a failed authentication: whether its
+/// message was malformed, but not whether the user exists
+fn failure_message(mechanism: &str, error: &Error) -> String {
+    match error {
+        Error::Auth(AuthError::AuthenticationFailed(reason)) => {
+            format!("Authentication failed with SASL mechanism {mechanism}: {reason}")
+        }
+        _ => format!(
+            "Authentication failed due to invalid credentials with SASL mechanism {mechanism}"
+        ),
+    }
+}
@@ -0,0 +1,308 @@
+//! SCRAM credential administration APIs
+//!
+//! DescribeUserScramCredentials and AlterUserScramCredentials manage the
+//! credentials SASL/SCRAM clients authenticate with. Describing needs
+//! `describe_configs` on the cluster, altering needs `alter_configs`.
+//! Clients send passwords already salted, so the broker never sees them.
+
+use std::collections::{BTreeMap, HashSet};
+
+use lumadb_common::error::{Error, Result};
+use lumadb_security::auth::{ScramCredential, ScramMechanism};
+use lumadb_security::authz::{Operation, ResourceType};
+use lumadb_security::Session;
+
+use super::codec::{Decoder, Encoder};
+use super::error;
+use super::{Broker, RequestHeader};
+
+/// Wire code of a SCRAM mechanism
+fn mechanism_code(mechanism: ScramMechanism) -> i8 {
+    match mechanism {
+        ScramMechanism::Sha256 => 1,
+        ScramMechanism::Sha512 => 2,
+    }
+}
+
+fn mechanism_from_code(code: i8) -> Option<ScramMechanism> {
+    match code {
+        1 => Some(ScramMechanism::Sha256),
+        2 => Some(ScramMechanism::Sha512),
+        _ => None,
+    }
+}
+
+/// A user described: name, error code and message, and each credential's
+/// mechanism and iteration count
+type UserResult<'a> = (String, i16, Option<&'static str>, &'a [(i8, i32)]);
+
+const DUPLICATE_USER: &str =
+    "Cannot describe SCRAM credentials for the same user twice in a single request";
+const UNKNOWN_USER: &str = "Attempt to describe a user credential that does not exist";
+
+/// DescribeUserScramCredentials: mechanisms and iteration counts of the
+/// named users, or of every user with a credential
+pub(super) fn describe_user_scram_credentials(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+
+    let users = match dec.array_len(flexible)? {
+        Some(len) => Some(
+            (0..len)
+                .map(|_| {
+                    let name = dec.string(flexible)?;
+                    dec.tagged_fields(flexible)?;
+                    Ok(name)
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    dec.tagged_fields(flexible)?;
+
+    enc.i32(0);
+    if !broker.permits(
+        header,
+        ResourceType::Cluster,
+        "",
+        Operation::DescribeConfigs,
+    ) {
+        enc.i16(error::CLUSTER_AUTHORIZATION_FAILED)
+            .nullable_string(None, flexible)
+            .array_len(0, flexible)
+            .tagged_fields(flexible);
+        return Ok(());
+    }
+
+    let mut credentials: BTreeMap<String, Vec<(i8, i32)>> = BTreeMap::new();
+    for info in broker.security.scram_credentials() {
+        credentials.entry(info.username).or_default().push((
+            mechanism_code(info.mechanism),
+            i32::try_from(info.iterations).unwrap_or(i32::MAX),
+        ));
+    }
+
+    let results: Vec<UserResult<'_>> = match &users {
+        None => credentials
+            .iter()
+            .map(|(user, infos)| (user.clone(), error::NONE, None, infos.as_slice()))
+            .collect(),
+        Some(users) => {
+            let mut seen = HashSet::new();
+            let duplicates: HashSet<&String> =
+                users.iter().filter(|user| !seen.insert(*user)).collect();
+            let mut reported = HashSet::new();
+            users
+                .iter()
+                .filter(|user| reported.insert(*user))
+                .map(|user| match credentials.get(user) {
+                    _ if duplicates.contains(user) => (
+                        user.clone(),
+                        error::DUPLICATE_RESOURCE,
+                        Some(DUPLICATE_USER),
+                        &[][..],
+                    ),
+                    Some(infos) => (user.clone(), error::NONE, None, infos.as_slice()),
+                    None => (
+                        user.clone(),
+                        error::RESOURCE_NOT_FOUND,
+                        Some(UNKNOWN_USER),
+                        &[][..],
+                    ),
+                })
+                .collect()
+        }
+    };
+
+    enc.i16(error::NONE).nullable_string(None, flexible);
+    enc.array_of(&results, flexible, |enc, (user, code, message, infos)| {
+        enc.string(user, flexible)
+            .i16(*code)
+            .nullable_string(*message, flexible);
+        enc.array_of(infos, flexible, |enc, (mechanism, iterations)| {
+            enc.i8(*mechanism).i32(*iterations).tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// A change AlterUserScramCredentials asks for
+enum Alteration {
+    Delete {
+        mechanism: i8,
+    },
+    Upsert {
+        mechanism: i8,
+        iterations: i32,
+        salt: Vec<u8>,
+        salted_password: Vec<u8>,
+    },
+}
+
+impl Alteration {
+    fn mechanism(&self) -> i8 {
+        match self {
+            Self::Delete { mechanism } | Self::Upsert { mechanism, .. } => *mechanism,
+        }
+    }
+}
+
+/// AlterUserScramCredentials: delete and upsert credentials
+///
+/// Each user's changes are checked together and applied only if all of
+/// them are valid; naming a user's mechanism twice is an error.
+pub(super) fn alter_user_scram_credentials(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+
+    let deletions = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let mechanism = dec.i8()?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, Alteration::Delete { mechanism }))
+    })?;
+    let upsertions = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let mechanism = dec.i8()?;
+        let iterations = dec.i32()?;
+        let salt = dec.bytes(flexible)?.to_vec();
+        let salted_password = dec.bytes(flexible)?.to_vec();
+        dec.tagged_fields(flexible)?;
+        Ok((
+            name,
+            Alteration::Upsert {
+                mechanism,
+                iterations,
+                salt,
+                salted_password,
+            },
+        ))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    // Changes grouped by user, in the order users first appear
+    let mut users: Vec<(String, Vec<Alteration>)> = Vec::new();
+    for (name, alteration) in deletions.into_iter().chain(upsertions) {
+        match users.iter_mut().find(|(user, _)| *user == name) {
+            Some((_, alterations)) => alterations.push(alteration),
+            None => users.push((name, vec![alteration])),
+        }
+    }
+
+    let allowed = broker.permits(header, ResourceType::Cluster, "", Operation::AlterConfigs);
+    let anonymous = Session::anonymous();
+    let actor = header.session.as_deref().unwrap_or(&anonymous);
+    let results: Vec<(String, i16, Option<String>)> = users
+        .into_iter()
+        .map(|(user, alterations)| {
+            let outcome = if allowed {
+                alter_user(broker, actor, &user, alterations)
+            } else {
+                Err((error::CLUSTER_AUTHORIZATION_FAILED, None))
+            };
+            match outcome {
+                Ok(()) => (user, error::NONE, None),
+                Err((code, message)) => (user, code, message),
+            }
+        })
+        .collect();
+
+    enc.i32(0);
+    enc.array_of(&results, flexible, |enc, (user, code, message)| {
+        enc.string(user, flexible)
+            .i16(*code)
+            .nullable_string(message.as_deref(), flexible)
+            .tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// Check and apply one user's changes
+fn alter_user(
+    broker: &Broker,
+    actor: &Session,
+    user: &str,
+    alterations: Vec<Alteration>,
+) -> std::result::Result<(), (i16, Option<String>)> {
+    let fail = |code: i16, message: &str| Err((code, Some(message.to_string())));
+
+    if user.is_empty() {
+        return fail(error::UNACCEPTABLE_CREDENTIAL, "Username must not be empty");
+    }
+    let mut mechanisms = HashSet::new();
+    if !alterations
+        .iter()
+        .all(|alteration| mechanisms.insert(alteration.mechanism()))
+    {
+        return fail(
+            error::DUPLICATE_RESOURCE,
+            "A user credential cannot be altered twice in the same request",
+        );
+    }
+
+    let mut changes = Vec::with_capacity(alterations.len());
+    for alteration in alterations {
+        let Some(mechanism) = mechanism_from_code(alteration.mechanism()) else {
+            return fail(error::UNSUPPORTED_SASL_MECHANISM, "Unknown SCRAM mechanism");
+        };
+        let credential = match alteration {
+            Alteration::Delete { .. } => {
+                if broker
+                    .security
+                    .scram_credentials()
+                    .iter()
+                    .all(|info| info.username != user || info.mechanism != mechanism)
+                {
+                    return fail(
+                        error::RESOURCE_NOT_FOUND,
+                        "Attempt to delete a user credential that does not exist",
+                    );
+                }
+                None
+            }
+            Alteration::Upsert {
+                iterations,
+                salt,
+                salted_password,
+                ..
+            } => {
+                let iterations = u32::try_from(iterations).unwrap_or(0);
+                let credential = ScramCredential::from_salted_password(
+                    mechanism,
+                    salt,
+                    &salted_password,
+                    iterations,
+                );
+                match credential {
+                    Ok(credential) => Some(credential),
+                    Err(e) => return fail(error::UNACCEPTABLE_CREDENTIAL, &e.to_string()),
+                }
+            }
+        };
+        changes.push((mechanism, credential));
+    }
+
+    for (mechanism, credential) in changes {
+        let applied = match credential {
+            Some(credential) => broker
+                .security
+                .upsert_scram_credential(actor, user, mechanism, credential),
+            None => broker
+                .security
+                .delete_scram_credential(actor, user, mechanism),
+        };
+        applied.map_err(|e: Error| (error::UNKNOWN_SERVER_ERROR, Some(e.to_string())))?;
+    }
+    Ok(())
+}
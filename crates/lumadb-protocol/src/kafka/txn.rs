@@ -0,0 +1,298 @@
+//! Transaction coordinator APIs
+//!
+//! Producer ids, transaction membership and markers are managed by the
+//! engine's transaction coordinator; these handlers only translate requests
+//! and map [`ProducerError`]s onto Kafka error codes.
+//!
+//! Every transactional request needs `write` on its transactional id.
+//! Idempotent producers without one need `idempotent_write` on the cluster
+//! or `write` on some topic.
+
+use lumadb_common::error::Result;
+use lumadb_security::authz::{Operation, ResourceType};
+use lumadb_streaming::producer::{ProducerError, ProducerIdAndEpoch};
+
+use super::codec::{Decoder, Encoder};
+use super::error::{self, producer_error_code};
+use super::{Broker, RequestHeader};
+
+/// Producer id and epoch fields shared by the transaction requests
+fn producer(dec: &mut Decoder<'_>) -> Result<ProducerIdAndEpoch> {
+    Ok(ProducerIdAndEpoch {
+        producer_id: dec.i64()?,
+        producer_epoch: dec.i16()?,
+    })
+}
+
+fn error_code(result: std::result::Result<(), ProducerError>) -> i16 {
+    result.map_or_else(|e| producer_error_code(&e), |()| error::NONE)
+}
+
+/// Whether the principal may use the transactional id
+fn may_write_transaction(broker: &Broker, header: &RequestHeader, transactional_id: &str) -> bool {
+    broker.permits(
+        header,
+        ResourceType::TransactionalId,
+        transactional_id,
+        Operation::Write,
+    )
+}
+
+/// Error code refusing a transaction's group, if the principal may not
+/// commit its offsets
+fn group_denied(broker: &Broker, header: &RequestHeader, group_id: &str) -> Option<i16> {
+    (!broker.permits(header, ResourceType::Group, group_id, Operation::Read))
+        .then_some(error::GROUP_AUTHORIZATION_FAILED)
+}
+
+/// InitProducerId: allocate a producer id or fence a transactional one
+pub(super) fn init_producer_id(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let transactional_id = dec.nullable_string(flexible)?;
+    let timeout_ms = dec.i32()?;
+    let current = if version >= 3 {
+        Some(producer(dec)?).filter(|p| p.producer_id >= 0)
+    } else {
+        None
+    };
+    dec.tagged_fields(flexible)?;
+
+    let denied = if let Some(id) = transactional_id.as_deref() {
+        (!may_write_transaction(broker, header, id))
+            .then_some(error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED)
+    } else {
+        let allowed = broker.allows(
+            header,
+            ResourceType::Cluster,
+            "",
+            Operation::IdempotentWrite,
+        ) || broker.permits_any(header, ResourceType::Topic, Operation::Write);
+        (!allowed).then_some(error::CLUSTER_AUTHORIZATION_FAILED)
+    };
+    let result = match denied {
+        Some(code) => Err(code),
+        None => broker
+            .streaming
+            .init_producer_id(transactional_id.as_deref(), timeout_ms, current)
+            .map_err(|e| producer_error_code(&e)),
+    };
+    let (code, producer) = match result {
+        Ok(producer) => (error::NONE, producer),
+        Err(code) => (
+            code,
+            ProducerIdAndEpoch {
+                producer_id: -1,
+                producer_epoch: -1,
+            },
+        ),
+    };
+
+    enc.i32(0)
+        .i16(code)
+        .i64(producer.producer_id)
+        .i16(producer.producer_epoch)
+        .tagged_fields(flexible);
+    Ok(())
+}
+
+/// AddPartitionsToTxn: register the partitions a transaction writes to
+///
+/// The request succeeds or fails as a whole; when some partition is
+/// unknown or not writable the others are reported as not attempted.
+pub(super) fn add_partitions_to_txn(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+
+    let transactional_id = dec.string(flexible)?;
+    let producer = producer(dec)?;
+    let topics = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let partitions = dec.array_of(flexible, Decoder::i32)?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, partitions))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    let partitions: Vec<(String, i32)> = topics
+        .iter()
+        .flat_map(|(name, partitions)| partitions.iter().map(move |p| (name.clone(), *p)))
+        .collect();
+    let rejected = |topic: &str, partition: i32| {
+        if !broker.permits(header, ResourceType::Topic, topic, Operation::Write) {
+            Some(error::TOPIC_AUTHORIZATION_FAILED)
+        } else if broker.streaming.partition(topic, partition).is_none() {
+            Some(error::UNKNOWN_TOPIC_OR_PARTITION)
+        } else {
+            None
+        }
+    };
+    let code = if !may_write_transaction(broker, header, &transactional_id) {
+        Some(error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED)
+    } else if partitions.iter().any(|(t, p)| rejected(t, *p).is_some()) {
+        None
+    } else {
+        Some(error_code(broker.streaming.add_partitions_to_txn(
+            &transactional_id,
+            producer,
+            &partitions,
+        )))
+    };
+
+    enc.i32(0);
+    enc.array_of(&topics, flexible, |enc, (name, partitions)| {
+        enc.string(name, flexible);
+        enc.array_of(partitions, flexible, |enc, &partition| {
+            let code = code
+                .or_else(|| rejected(name, partition))
+                .unwrap_or(error::OPERATION_NOT_ATTEMPTED);
+            enc.i32(partition).i16(code).tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
+
+/// AddOffsetsToTxn: make a group's offset commits part of a transaction
+pub(super) fn add_offsets_to_txn(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+
+    let transactional_id = dec.string(flexible)?;
+    let producer = producer(dec)?;
+    let group_id = dec.string(flexible)?;
+    dec.tagged_fields(flexible)?;
+
+    let code = if !may_write_transaction(broker, header, &transactional_id) {
+        error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+    } else if let Some(code) = group_denied(broker, header, &group_id) {
+        code
+    } else {
+        error_code(
+            broker
+                .streaming
+                .add_offsets_to_txn(&transactional_id, producer, &group_id),
+        )
+    };
+    enc.i32(0).i16(code).tagged_fields(flexible);
+    Ok(())
+}
+
+/// EndTxn: commit or abort a transaction
+pub(super) fn end_txn(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let flexible = header.flexible;
+
+    let transactional_id = dec.string(flexible)?;
+    let producer = producer(dec)?;
+    let commit = dec.bool()?;
+    dec.tagged_fields(flexible)?;
+
+    let code = if may_write_transaction(broker, header, &transactional_id) {
+        error_code(
+            broker
+                .streaming
+                .end_txn(&transactional_id, producer, commit),
+        )
+    } else {
+        error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+    };
+    enc.i32(0).i16(code).tagged_fields(flexible);
+    Ok(())
+}
+
+/// TxnOffsetCommit: stage group offsets that commit with a transaction
+pub(super) fn txn_offset_commit(
+    broker: &Broker,
+    header: &RequestHeader,
+    dec: &mut Decoder<'_>,
+    enc: &mut Encoder,
+) -> Result<()> {
+    let version = header.api_version;
+    let flexible = header.flexible;
+
+    let transactional_id = dec.string(flexible)?;
+    let group_id = dec.string(flexible)?;
+    let producer = producer(dec)?;
+    if version >= 3 {
+        dec.i32()?; // generation_id
+        dec.string(flexible)?; // member_id
+        dec.nullable_string(flexible)?; // group_instance_id
+    }
+    let topics = dec.array_of(flexible, |dec| {
+        let name = dec.string(flexible)?;
+        let partitions = dec.array_of(flexible, |dec| {
+            let index = dec.i32()?;
+            let offset = dec.i64()?;
+            if version >= 2 {
+                dec.i32()?; // committed_leader_epoch
+            }
+            dec.nullable_string(flexible)?; // committed_metadata
+            dec.tagged_fields(flexible)?;
+            Ok((index, offset))
+        })?;
+        dec.tagged_fields(flexible)?;
+        Ok((name, partitions))
+    })?;
+    dec.tagged_fields(flexible)?;
+
+    // Offsets of topics the principal may not read are refused; the rest
+    // are still staged
+    let readable = |name: &str| broker.permits(header, ResourceType::Topic, name, Operation::Read);
+    let offsets: Vec<_> = topics
+        .iter()
+        .filter(|(name, _)| readable(name))
+        .flat_map(|(name, partitions)| {
+            partitions
+                .iter()
+                .map(move |&(index, offset)| (name.clone(), index, offset))
+        })
+        .collect();
+    let code = if !may_write_transaction(broker, header, &transactional_id) {
+        error::TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+    } else if let Some(code) = group_denied(broker, header, &group_id) {
+        code
+    } else {
+        error_code(broker.streaming.txn_offset_commit(
+            &transactional_id,
+            &group_id,
+            producer,
+            &offsets,
+        ))
+    };
+
+    enc.i32(0);
+    enc.array_of(&topics, flexible, |enc, (name, partitions)| {
+        let code = if code == error::NONE && !readable(name) {
+            error::TOPIC_AUTHORIZATION_FAILED
+        } else {
+            code
+        };
+        enc.string(name, flexible);
+        enc.array_of(partitions, flexible, |enc, &(index, _)| {
+            enc.i32(index).i16(code).tagged_fields(flexible);
+        });
+        enc.tagged_fields(flexible);
+    });
+    enc.tagged_fields(flexible);
+    Ok(())
+}
@@ -1,22 +1,49 @@
 //! PostgreSQL wire protocol implementation
+//!
+//! With authentication enabled, clients must log in with a cleartext
+//! password, checked by the security manager. Every statement is checked
+//! against the grants of the user who logged in before it runs, whether it
+//! arrives through the simple protocol or is bound to a portal by the
+//! extended one; a portal executed with a row limit
+//! is suspended after that many rows and reads on from a continuation token
+//! when executed again. All values are sent as text.
 
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{info, debug, error};
 use bytes::{Buf, BufMut, BytesMut};
+use serde_json::Value;
 
-use lumadb_common::error::Result;
+use lumadb_common::error::{AuthError, Error, ProtocolError, Result};
+use lumadb_query::QueryEngine;
+use lumadb_security::{Credentials, SecurityManager, Session as Caller};
 
-/// PostgreSQL protocol server (stub)
+/// `AuthenticationCleartextPassword` request code
+const AUTH_CLEARTEXT_PASSWORD: u8 = 3;
+
+/// OID of the `text` type, used for every column and parameter
+const TEXT_OID: u32 = 25;
+
+/// Rows read per page by a portal executed without a row limit
+const FETCH_SIZE: usize = 1000;
+
+/// PostgreSQL protocol server
 pub struct PostgresServer {
     port: u16,
+    security: Arc<SecurityManager>,
+    query: Arc<QueryEngine>,
 }
 
 impl PostgresServer {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+    pub fn new(port: u16, security: Arc<SecurityManager>, query: Arc<QueryEngine>) -> Self {
+        Self {
+            port,
+            security,
+            query,
+        }
     }
 
     pub async fn run(&self) -> Result<()> {
@@ -27,20 +54,62 @@ impl PostgresServer {
 
         loop {
             let (socket, addr) = listener.accept().await?;
+            let security = self.security.clone();
+            let query = self.query.clone();
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(socket).await {
+                if let Err(e) = Self::handle_connection(socket, &security, &query).await {
                     error!("PostgreSQL connection error: {}", e);
                 }
             });
         }
     }
 
-    async fn handle_connection(mut socket: TcpStream) -> Result<()> {
+    async fn handle_connection(
+        mut socket: TcpStream,
+        security: &SecurityManager,
+        query: &QueryEngine,
+    ) -> Result<()> {
         let mut buffer = BytesMut::with_capacity(8192);
 
         // Read startup message
         socket.read_buf(&mut buffer).await?;
 
+        let caller = if security.auth_enabled() {
+            let username = startup_parameter(&buffer, "user").unwrap_or_default();
+            let request = [b'R', 0, 0, 0, 8, 0, 0, 0, AUTH_CLEARTEXT_PASSWORD];
+            socket.write_all(&request).await?;
+
+            // PasswordMessage: 'p', length, NUL-terminated password
+            buffer.clear();
+            socket.read_buf(&mut buffer).await?;
+            let password = match buffer.first() {
+                Some(b'p') if buffer.len() > 5 => cstring(&buffer[5..]),
+                _ => String::new(),
+            };
+            let credentials = Credentials::UsernamePassword { username, password };
+            match security.authenticate(&credentials).await {
+                Ok(caller) => caller,
+                Err(e) => {
+                    debug!("PostgreSQL authentication failed: {}", e);
+                    socket
+                        .write_all(&error_response(
+                            "FATAL",
+                            "28P01",
+                            "password authentication failed",
+                        ))
+                        .await?;
+                    return Ok(());
+                }
+            }
+        } else {
+            Caller::anonymous()
+        };
+        let caller = match socket.peer_addr() {
+            Ok(addr) => caller.with_host(addr.ip()),
+            Err(_) => caller,
+        };
+        buffer.clear();
+
         // Send authentication OK
         let auth_ok = [b'R', 0, 0, 0, 8, 0, 0, 0, 0];
         socket.write_all(&auth_ok).await?;
@@ -50,31 +119,658 @@ impl PostgresServer {
         socket.write_all(&ready).await?;
 
         // Handle queries
-        loop {
-            buffer.clear();
-            let n = socket.read_buf(&mut buffer).await?;
-            if n == 0 {
-                break;
+        let mut session = Session::new(query, security, caller);
+        while let Some((tag, body)) = read_message(&mut socket, &mut buffer).await? {
+            match tag {
+                b'Q' => {
+                    let response = session
+                        .simple_query(&cstring(&body))
+                        .await
+                        .unwrap_or_else(|e| query_error(&e));
+                    socket.write_all(&response).await?;
+                    socket.write_all(&ready).await?;
+                }
+                b'S' => {
+                    session.failed = false;
+                    socket.write_all(&ready).await?;
+                }
+                // Terminate
+                b'X' => break,
+                // Flush: responses are written as they are produced
+                b'H' => {}
+                // After an error the extended protocol skips to the next Sync
+                _ if session.failed => {}
+                _ => {
+                    let response = match session.extended(tag, body).await {
+                        Ok(response) => response,
+                        Err(e) => {
+                            session.failed = true;
+                            query_error(&e)
+                        }
+                    };
+                    socket.write_all(&response).await?;
+                }
             }
+        }
 
-            if buffer[0] == b'Q' {
-                // Query message
-                // Send empty row description
-                let row_desc = [b'T', 0, 0, 0, 6, 0, 0];
-                socket.write_all(&row_desc).await?;
+        Ok(())
+    }
+}
 
-                // Send command complete
-                let cmd_complete = [b'C', 0, 0, 0, 13, b'S', b'E', b'L', b'E', b'C', b'T', b' ', b'0', 0];
-                socket.write_all(&cmd_complete).await?;
+/// A bound statement, read a page at a time
+struct Portal {
+    query: String,
+    params: Vec<Value>,
+    /// Column names, known once the first page has been read
+    columns: Option<Vec<String>>,
+    /// Rows read but not sent yet
+    pending: VecDeque<HashMap<String, Value>>,
+    /// Token reading the page after the last one
+    token: Option<String>,
+    /// Whether every row has been read
+    exhausted: bool,
+}
 
-                // Send ready for query
-                socket.write_all(&ready).await?;
-            } else if buffer[0] == b'X' {
-                // Terminate
-                break;
+impl Portal {
+    fn new(query: String, params: Vec<Value>) -> Self {
+        Self {
+            query,
+            params,
+            columns: None,
+            pending: VecDeque::new(),
+            token: None,
+            exhausted: false,
+        }
+    }
+
+    /// Read pages until `want` rows are pending or every row has been read
+    ///
+    /// Statements other than queries run whole on the first read.
+    async fn fill(&mut self, engine: &QueryEngine, want: usize) -> Result<()> {
+        while self.pending.len() < want && !self.exhausted {
+            let (result, token) = if is_query(&self.query) {
+                let size = (want - self.pending.len()).min(FETCH_SIZE);
+                let page = engine
+                    .execute_page(&self.query, &self.params, size, self.token.as_deref())
+                    .await?;
+                (page.result, page.next_token)
+            } else {
+                (engine.execute(&self.query, &self.params).await?, None)
+            };
+            self.columns
+                .get_or_insert_with(|| result.columns().to_vec());
+            self.exhausted = token.is_none();
+            self.token = token;
+            self.pending.extend(result.into_rows());
+        }
+        Ok(())
+    }
+}
+
+/// Prepared statements and portals of a connection
+struct Session<'a> {
+    engine: &'a QueryEngine,
+    security: &'a SecurityManager,
+    /// User who logged in on the connection
+    caller: Caller,
+    statements: HashMap<String, String>,
+    portals: HashMap<String, Portal>,
+    /// Whether an extended protocol message failed since the last Sync
+    failed: bool,
+}
+
+impl<'a> Session<'a> {
+    fn new(engine: &'a QueryEngine, security: &'a SecurityManager, caller: Caller) -> Self {
+        Self {
+            engine,
+            security,
+            caller,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+        }
+    }
+
+    /// Run a Query message: every row, then CommandComplete
+    async fn simple_query(&self, query: &str) -> Result<BytesMut> {
+        let mut out = BytesMut::new();
+        if query.trim().is_empty() {
+            put_message(&mut out, b'I', &[]);
+            return Ok(out);
+        }
+        self.permit(query)?;
+        let result = self.engine.execute(query, &[]).await?;
+        let columns = result.columns().to_vec();
+        put_message(&mut out, b'T', &row_description(&columns));
+        for row in result.rows() {
+            put_message(&mut out, b'D', &data_row(&columns, row));
+        }
+        put_message(&mut out, b'C', &command_tag(query, result.rows().len()));
+        Ok(out)
+    }
+
+    /// Handle a message of the extended query protocol
+    async fn extended(&mut self, tag: u8, mut body: BytesMut) -> Result<BytesMut> {
+        let mut out = BytesMut::new();
+        match tag {
+            // Parse: statement name, query, parameter types
+            b'P' => {
+                let name = read_cstring(&mut body)?;
+                let query = read_cstring(&mut body)?;
+                self.statements.insert(name, query);
+                put_message(&mut out, b'1', &[]);
+            }
+            // Bind: portal, statement, parameter formats and values, result
+            // formats
+            b'B' => {
+                let portal = read_cstring(&mut body)?;
+                let statement = read_cstring(&mut body)?;
+                let query = self.statements.get(&statement).cloned().ok_or_else(|| {
+                    invalid(format!("prepared statement \"{statement}\" does not exist"))
+                })?;
+                self.permit(&query)?;
+                let formats = read_i16(&mut body)?;
+                for _ in 0..formats {
+                    if read_i16(&mut body)? != 0 {
+                        return Err(Error::Protocol(ProtocolError::UnsupportedOperation(
+                            "binary parameters are not supported".to_string(),
+                        )));
+                    }
+                }
+                let count = read_i16(&mut body)?;
+                let mut params = Vec::with_capacity(usize::try_from(count).unwrap_or(0));
+                for _ in 0..count {
+                    params.push(read_param(&mut body)?);
+                }
+                self.portals.insert(portal, Portal::new(query, params));
+                put_message(&mut out, b'2', &[]);
+            }
+            // Describe: 'S' for a statement, 'P' for a portal
+            b'D' => {
+                let kind = read_u8(&mut body)?;
+                let name = read_cstring(&mut body)?;
+                if kind == b'S' {
+                    // Columns are only known once a portal has read a page
+                    let query = self.statements.get(&name).ok_or_else(|| {
+                        invalid(format!("prepared statement \"{name}\" does not exist"))
+                    })?;
+                    put_message(&mut out, b't', &parameter_description(placeholders(query)));
+                    put_message(&mut out, b'n', &[]);
+                } else {
+                    let engine = self.engine;
+                    let portal = self.portal(&name)?;
+                    portal.fill(engine, 1).await?;
+                    let columns = portal.columns.clone().unwrap_or_default();
+                    put_message(&mut out, b'T', &row_description(&columns));
+                }
+            }
+            // Execute: portal and row limit, zero for no limit
+            b'E' => {
+                let name = read_cstring(&mut body)?;
+                let limit = usize::try_from(read_i32(&mut body)?).unwrap_or(0);
+                let engine = self.engine;
+                let portal = self.portal(&name)?;
+                let mut sent = 0;
+                loop {
+                    let want = if limit == 0 { FETCH_SIZE } else { limit - sent };
+                    portal.fill(engine, want).await?;
+                    let columns = portal.columns.clone().unwrap_or_default();
+                    let take = want.min(portal.pending.len());
+                    for row in portal.pending.drain(..take) {
+                        put_message(&mut out, b'D', &data_row(&columns, &row));
+                    }
+                    sent += take;
+                    if limit != 0 || portal.exhausted {
+                        break;
+                    }
+                }
+                if portal.pending.is_empty() && portal.exhausted {
+                    put_message(&mut out, b'C', &command_tag(&portal.query, sent));
+                } else {
+                    put_message(&mut out, b's', &[]);
+                }
+            }
+            // Close: 'S' for a statement, 'P' for a portal
+            b'C' => {
+                let kind = read_u8(&mut body)?;
+                let name = read_cstring(&mut body)?;
+                if kind == b'S' {
+                    self.statements.remove(&name);
+                } else {
+                    self.portals.remove(&name);
+                }
+                put_message(&mut out, b'3', &[]);
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "unexpected message type '{}'",
+                    tag as char
+                )));
             }
         }
+        Ok(out)
+    }
 
+    /// Check that the caller may run `query`, taking each action the
+    /// statement takes on every resource it touches
+    fn permit(&self, query: &str) -> Result<()> {
+        let accesses = self.engine.accesses(query)?;
+        for (resource, action) in &accesses {
+            self.security.authorize(&self.caller, resource, action)?;
+        }
+        self.security
+            .audit_statement(&self.caller, query, &accesses);
         Ok(())
     }
+
+    fn portal(&mut self, name: &str) -> Result<&mut Portal> {
+        self.portals
+            .get_mut(name)
+            .ok_or_else(|| invalid(format!("portal \"{name}\" does not exist")))
+    }
+}
+
+/// Read one frontend message: type, length counting itself, then body
+async fn read_message(
+    socket: &mut TcpStream,
+    buffer: &mut BytesMut,
+) -> Result<Option<(u8, BytesMut)>> {
+    loop {
+        if buffer.len() >= 5 {
+            let len = u32::from_be_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]) as usize;
+            if len < 4 {
+                return Err(invalid(format!("message length {len} is too short")));
+            }
+            if buffer.len() > len {
+                let tag = buffer.get_u8();
+                buffer.advance(4);
+                return Ok(Some((tag, buffer.split_to(len - 4))));
+            }
+        }
+        if socket.read_buf(buffer).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::Protocol(ProtocolError::InvalidFormat(message))
+}
+
+fn read_u8(body: &mut BytesMut) -> Result<u8> {
+    if body.remaining() < 1 {
+        return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+    }
+    Ok(body.get_u8())
+}
+
+fn read_i16(body: &mut BytesMut) -> Result<i16> {
+    if body.remaining() < 2 {
+        return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+    }
+    Ok(body.get_i16())
+}
+
+fn read_i32(body: &mut BytesMut) -> Result<i32> {
+    if body.remaining() < 4 {
+        return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+    }
+    Ok(body.get_i32())
+}
+
+fn read_cstring(body: &mut BytesMut) -> Result<String> {
+    let end = body
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(Error::Protocol(ProtocolError::IncompleteMessage))?;
+    let value = String::from_utf8_lossy(&body[..end]).into_owned();
+    body.advance(end + 1);
+    Ok(value)
+}
+
+/// A text parameter value: a number or boolean where it reads as one,
+/// else a string
+fn read_param(body: &mut BytesMut) -> Result<Value> {
+    let Ok(len) = usize::try_from(read_i32(body)?) else {
+        return Ok(Value::Null);
+    };
+    if body.remaining() < len {
+        return Err(Error::Protocol(ProtocolError::IncompleteMessage));
+    }
+    let text = String::from_utf8_lossy(&body.split_to(len)).into_owned();
+    Ok(match serde_json::from_str(&text) {
+        Ok(value @ (Value::Number(_) | Value::Bool(_))) => value,
+        _ => Value::String(text),
+    })
+}
+
+/// Whether a statement is a query, which portals read a page at a time
+fn is_query(query: &str) -> bool {
+    query
+        .trim_start()
+        .get(..6)
+        .is_some_and(|verb| verb.eq_ignore_ascii_case("select"))
+}
+
+/// Number of `$n` parameters a statement refers to
+fn placeholders(query: &str) -> usize {
+    query
+        .split('$')
+        .skip(1)
+        .filter_map(|rest| {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            rest[..digits].parse::<usize>().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Append a backend message: type, length counting itself, then body
+fn put_message(out: &mut BytesMut, tag: u8, body: &[u8]) {
+    out.put_u8(tag);
+    out.put_u32(u32::try_from(body.len() + 4).unwrap_or(u32::MAX));
+    out.put_slice(body);
+}
+
+fn put_cstring(out: &mut BytesMut, value: &str) {
+    out.put_slice(value.as_bytes());
+    out.put_u8(0);
+}
+
+/// `RowDescription` body for text columns
+fn row_description(columns: &[String]) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i16(i16::try_from(columns.len()).unwrap_or(i16::MAX));
+    for column in columns {
+        put_cstring(&mut body, column);
+        body.put_u32(0); // table OID
+        body.put_i16(0); // column number
+        body.put_u32(TEXT_OID);
+        body.put_i16(-1); // variable length
+        body.put_i32(-1); // no type modifier
+        body.put_i16(0); // text format
+    }
+    body
+}
+
+/// `ParameterDescription` body for text parameters
+fn parameter_description(count: usize) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i16(i16::try_from(count).unwrap_or(i16::MAX));
+    for _ in 0..count {
+        body.put_u32(TEXT_OID);
+    }
+    body
+}
+
+/// `DataRow` body with the text of each column, NULL as length -1
+fn data_row(columns: &[String], row: &HashMap<String, Value>) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i16(i16::try_from(columns.len()).unwrap_or(i16::MAX));
+    for column in columns {
+        let text = match row.get(column) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(value) => Some(value.to_string()),
+        };
+        match text {
+            Some(text) => {
+                body.put_i32(i32::try_from(text.len()).unwrap_or(i32::MAX));
+                body.put_slice(text.as_bytes());
+            }
+            None => body.put_i32(-1),
+        }
+    }
+    body
+}
+
+/// `CommandComplete` body: the statement's verb, with the row count for
+/// queries
+fn command_tag(query: &str, rows: usize) -> BytesMut {
+    let verb = query
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let mut body = BytesMut::new();
+    if verb == "SELECT" {
+        put_cstring(&mut body, &format!("SELECT {rows}"));
+    } else {
+        put_cstring(&mut body, &verb);
+    }
+    body
+}
+
+/// ERROR `ErrorResponse` for a failed statement
+fn query_error(error: &Error) -> BytesMut {
+    let code = match error {
+        Error::Auth(AuthError::PermissionDenied(_)) => "42501",
+        Error::Query(_) => "42000",
+        Error::Protocol(_) => "08P01",
+        _ => "XX000",
+    };
+    error_response("ERROR", code, &error.to_string())
+}
+
+/// NUL-terminated string at the start of `bytes`
+fn cstring(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Value of a parameter of a startup message: length, protocol version,
+/// then NUL-terminated name and value pairs
+fn startup_parameter(message: &[u8], name: &str) -> Option<String> {
+    let mut fields = message.get(8..)?.split(|&b| b == 0);
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        if key.is_empty() {
+            break;
+        }
+        if key == name.as_bytes() {
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+    }
+    None
+}
+
+/// `ErrorResponse` with a severity and SQLSTATE code
+fn error_response(severity: &str, code: &str, message: &str) -> BytesMut {
+    let mut fields = BytesMut::new();
+    for (kind, value) in [(b'S', severity), (b'C', code), (b'M', message)] {
+        fields.put_u8(kind);
+        fields.put_slice(value.as_bytes());
+        fields.put_u8(0);
+    }
+    fields.put_u8(0);
+
+    let mut response = BytesMut::with_capacity(fields.len() + 5);
+    response.put_u8(b'E');
+    response.put_u32(u32::try_from(fields.len() + 4).unwrap_or(u32::MAX));
+    response.put_slice(&fields);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumadb_common::config::{QueryConfig, SecurityConfig, StorageConfig};
+    use lumadb_security::authz::Role;
+    use lumadb_storage::StorageEngine;
+    use serde_json::json;
+
+    /// Connect as `admin`, giving its password if the server asks for one
+    async fn connect(dir: &std::path::Path, security: SecurityManager) -> TcpStream {
+        let storage_config = StorageConfig {
+            path: dir.to_string_lossy().to_string(),
+            ..StorageConfig::default()
+        };
+        let storage = Arc::new(StorageEngine::new(&storage_config).await.unwrap());
+        let query = Arc::new(
+            QueryEngine::new(&QueryConfig::default(), storage)
+                .await
+                .unwrap(),
+        );
+        query.create_collection("nums", None, None).await.unwrap();
+        let docs: Vec<_> = (0..5).map(|n| json!({"n": n})).collect();
+        query.insert("nums", &docs).await.unwrap();
+        let security = Arc::new(security);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            PostgresServer::handle_connection(socket, &security, &query)
+                .await
+                .unwrap();
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = BytesMut::new();
+        startup.put_u32(196_608);
+        startup.put_slice(b"user\0admin\0\0");
+        let mut message = BytesMut::new();
+        message.put_u32(u32::try_from(startup.len() + 4).unwrap());
+        message.put_slice(&startup);
+        client.write_all(&message).await.unwrap();
+
+        let mut buffer = BytesMut::new();
+        while let Some((tag, mut body)) = read_message(&mut client, &mut buffer).await.unwrap() {
+            if tag == b'R' && body.get_u32() == u32::from(AUTH_CLEARTEXT_PASSWORD) {
+                let mut password = BytesMut::new();
+                put_message(&mut password, b'p', b"admin\0");
+                client.write_all(&password).await.unwrap();
+            }
+            if tag == b'Z' {
+                break;
+            }
+        }
+        client
+    }
+
+    /// Backend messages up to and including ReadyForQuery
+    async fn responses(client: &mut TcpStream) -> Vec<(u8, BytesMut)> {
+        let mut buffer = BytesMut::new();
+        let mut messages = Vec::new();
+        while let Some((tag, body)) = read_message(client, &mut buffer).await.unwrap() {
+            messages.push((tag, body));
+            if tag == b'Z' {
+                break;
+            }
+        }
+        messages
+    }
+
+    /// SQLSTATE code of an ErrorResponse
+    fn code(body: &BytesMut) -> String {
+        let field = body[..]
+            .split(|&b| b == 0)
+            .find_map(|field| field.strip_prefix(b"C"))
+            .unwrap();
+        String::from_utf8(field.to_vec()).unwrap()
+    }
+
+    /// Text of the single column of a DataRow
+    fn value(mut body: BytesMut) -> String {
+        assert_eq!(body.get_i16(), 1);
+        let len = usize::try_from(body.get_i32()).unwrap();
+        String::from_utf8(body[..len].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn execute_with_row_limit_suspends_the_portal() {
+        let dir = tempfile::tempdir().unwrap();
+        let security = SecurityManager::new(&SecurityConfig::default())
+            .await
+            .unwrap();
+        let mut client = connect(dir.path(), security).await;
+
+        let mut out = BytesMut::new();
+        put_message(&mut out, b'P', b"\0SELECT n FROM nums WHERE n >= 1\0\0\0");
+        let mut bind = BytesMut::new();
+        bind.put_slice(b"\0\0");
+        bind.put_i16(0);
+        bind.put_i16(0);
+        bind.put_i16(0);
+        put_message(&mut out, b'B', &bind);
+        put_message(&mut out, b'D', b"P\0");
+        for _ in 0..2 {
+            put_message(&mut out, b'E', b"\0\0\0\0\x03");
+        }
+        put_message(&mut out, b'S', &[]);
+        client.write_all(&out).await.unwrap();
+
+        let messages = responses(&mut client).await;
+        let tags: Vec<u8> = messages.iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, b"12TDDDsDCZ");
+
+        let mut values: Vec<String> = messages
+            .into_iter()
+            .filter(|(tag, _)| *tag == b'D')
+            .map(|(_, body)| value(body))
+            .collect();
+        values.sort();
+        assert_eq!(values, ["1", "2", "3", "4"]);
+    }
+
+    #[tokio::test]
+    async fn simple_query_returns_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let security = SecurityManager::new(&SecurityConfig::default())
+            .await
+            .unwrap();
+        let mut client = connect(dir.path(), security).await;
+
+        let mut out = BytesMut::new();
+        put_message(&mut out, b'Q', b"SELECT COUNT(*) AS n FROM nums\0");
+        client.write_all(&out).await.unwrap();
+
+        let mut messages = responses(&mut client).await.into_iter();
+        assert_eq!(messages.next().unwrap().0, b'T');
+        let (tag, row) = messages.next().unwrap();
+        assert_eq!((tag, value(row).as_str()), (b'D', "5"));
+        let (tag, complete) = messages.next().unwrap();
+        assert_eq!((tag, &complete[..]), (b'C', &b"SELECT 1\0"[..]));
+    }
+
+    #[tokio::test]
+    async fn statements_need_the_callers_grants() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SecurityConfig {
+            auth_enabled: true,
+            auth_method: "sasl".to_string(),
+            ..SecurityConfig::default()
+        };
+        let security = SecurityManager::new(&config).await.unwrap();
+        // The built-in admin user can still log in, but holds no grants
+        let role = Role {
+            name: "admin".to_string(),
+            permissions: Vec::new(),
+        };
+        security.put_role(&Caller::anonymous(), role).unwrap();
+        let mut client = connect(dir.path(), security).await;
+
+        let mut out = BytesMut::new();
+        put_message(&mut out, b'Q', b"SELECT n FROM nums\0");
+        client.write_all(&out).await.unwrap();
+        let messages = responses(&mut client).await;
+        let tags: Vec<u8> = messages.iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, b"EZ");
+        assert_eq!(code(&messages[0].1), "42501");
+
+        let mut out = BytesMut::new();
+        put_message(&mut out, b'P', b"\0DELETE FROM nums\0\0\0");
+        let mut bind = BytesMut::new();
+        bind.put_slice(b"\0\0");
+        bind.put_i16(0);
+        bind.put_i16(0);
+        bind.put_i16(0);
+        put_message(&mut out, b'B', &bind);
+        put_message(&mut out, b'E', b"\0\0\0\0\0");
+        put_message(&mut out, b'S', &[]);
+        client.write_all(&out).await.unwrap();
+        let messages = responses(&mut client).await;
+        let tags: Vec<u8> = messages.iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, b"1EZ");
+        assert_eq!(code(&messages[1].1), "42501");
+    }
 }
@@ -21,9 +21,7 @@ impl Analyzer {
     /// Analyze an AST
     pub fn analyze(&self, ast: &Ast) -> Result<AnalyzedQuery> {
         match ast {
-            Ast::Select { columns, from, filter, order_by, limit } => {
-                self.analyze_select(columns, from, filter, order_by, limit)
-            }
+            Ast::Select(select) => self.analyze_select(select),
             Ast::Insert { table, columns, values } => {
                 self.analyze_insert(table, columns, values)
             }
@@ -42,6 +40,26 @@ impl Analyzer {
             Ast::DropTable { name } => {
                 Ok(AnalyzedQuery::DropTable { name: name.clone() })
             }
+            Ast::CreateIndex { name, table, fields, unique, filter } => {
+                Ok(AnalyzedQuery::CreateIndex {
+                    name: name.clone(),
+                    table: table.clone(),
+                    fields: fields.clone(),
+                    unique: *unique,
+                    filter: filter.clone(),
+                })
+            }
+            Ast::DropIndex { name, table } => {
+                Ok(AnalyzedQuery::DropIndex {
+                    name: name.clone(),
+                    table: table.clone(),
+                })
+            }
+            Ast::Begin { .. } | Ast::Commit | Ast::Rollback => {
+                Err(Error::Query(QueryError::SemanticError(
+                    "Transaction statements are run by the query engine".to_string(),
+                )))
+            }
             Ast::Stream { topic, filter, limit } => {
                 Ok(AnalyzedQuery::Stream {
                     topic: topic.clone(),
@@ -60,33 +78,23 @@ impl Analyzer {
             Ast::TopicDelete { name } => {
                 Ok(AnalyzedQuery::TopicDelete { name: name.clone() })
             }
-            Ast::VectorSearch { collection, vector, k } => {
+            Ast::VectorSearch { collection, vector, k, text } => {
                 Ok(AnalyzedQuery::VectorSearch {
                     collection: collection.clone(),
                     vector: vector.clone(),
                     k: *k,
+                    text: text.clone(),
                 })
             }
         }
     }
 
-    fn analyze_select(
-        &self,
-        columns: &[String],
-        from: &str,
-        filter: &Option<crate::parser::Expr>,
-        order_by: &Option<Vec<(String, bool)>>,
-        limit: &Option<usize>,
-    ) -> Result<AnalyzedQuery> {
+    // Kept fallible and on `self` for the catalog checks in the TODO below
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    fn analyze_select(&self, select: &crate::parser::Select) -> Result<AnalyzedQuery> {
         // TODO: Validate table exists and columns are valid
 
-        Ok(AnalyzedQuery::Select {
-            columns: columns.to_vec(),
-            from: from.to_string(),
-            filter: filter.clone(),
-            order_by: order_by.clone(),
-            limit: *limit,
-        })
+        Ok(AnalyzedQuery::Select(Box::new(select.clone())))
     }
 
     fn analyze_insert(
@@ -130,13 +138,7 @@ impl Analyzer {
 /// Analyzed query with type information
 #[derive(Debug, Clone)]
 pub enum AnalyzedQuery {
-    Select {
-        columns: Vec<String>,
-        from: String,
-        filter: Option<crate::parser::Expr>,
-        order_by: Option<Vec<(String, bool)>>,
-        limit: Option<usize>,
-    },
+    Select(Box<crate::parser::Select>),
     Insert {
         table: String,
         columns: Vec<String>,
@@ -158,6 +160,17 @@ pub enum AnalyzedQuery {
     DropTable {
         name: String,
     },
+    CreateIndex {
+        name: String,
+        table: String,
+        fields: Vec<String>,
+        unique: bool,
+        filter: Vec<lumadb_common::types::IndexCondition>,
+    },
+    DropIndex {
+        name: String,
+        table: Option<String>,
+    },
     Stream {
         topic: String,
         filter: Option<crate::parser::Expr>,
@@ -176,5 +189,6 @@ pub enum AnalyzedQuery {
         collection: String,
         vector: Vec<f32>,
         k: usize,
+        text: Option<crate::parser::TextMatch>,
     },
 }
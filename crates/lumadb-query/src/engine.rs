@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
 use parking_lot::RwLock;
@@ -10,13 +11,18 @@ use tracing::{info, debug};
 use lumadb_common::config::QueryConfig;
 use lumadb_common::error::{Result, Error, QueryError};
 use lumadb_common::types::{QueryPlan, Document, CollectionMetadata, VectorSearchResult};
+use lumadb_storage::hybrid::{HybridQuery, HybridSearchResult};
+use lumadb_storage::vector::QuantizationReport;
 use lumadb_storage::StorageEngine;
+use lumadb_txn::{IsolationLevel, TransactionCoordinator};
 
-use crate::parser::Parser;
+use crate::parser::{Ast, Parser};
 use crate::analyzer::Analyzer;
 use crate::optimizer::Optimizer;
 use crate::executor::Executor;
-use crate::QueryResult;
+use crate::functions::FunctionRegistry;
+use crate::pagination::{self, ContinuationToken};
+use crate::{QueryPage, QueryResult};
 
 /// Main query engine
 pub struct QueryEngine {
@@ -34,6 +40,8 @@ pub struct QueryEngine {
     optimizer: Optimizer,
     /// Executor
     executor: Executor,
+    /// Open transactions
+    transactions: TransactionCoordinator,
     /// Ready state
     ready: Arc<RwLock<bool>>,
 }
@@ -48,14 +56,19 @@ impl QueryEngine {
     pub async fn new(config: &QueryConfig, storage: Arc<StorageEngine>) -> Result<Self> {
         info!("Initializing query engine");
 
+        let functions = Arc::new(FunctionRegistry::new());
         Ok(Self {
             config: config.clone(),
             storage: storage.clone(),
             cache: DashMap::new(),
             parser: Parser::new(),
             analyzer: Analyzer::new(storage.clone()),
-            optimizer: Optimizer::new(),
-            executor: Executor::new(storage),
+            optimizer: Optimizer::new(functions.clone()).with_storage(storage.clone()),
+            transactions: TransactionCoordinator::with_idle_timeout(
+                storage.clone(),
+                Duration::from_millis(config.transaction_idle_timeout_ms),
+            ),
+            executor: Executor::new(storage, functions, config.sort_memory_bytes),
             ready: Arc::new(RwLock::new(true)),
         })
     }
@@ -91,6 +104,9 @@ impl QueryEngine {
 
         // Parse
         let ast = self.parser.parse(query)?;
+        if let Some(result) = self.run_transaction_statement(None, &ast).await? {
+            return Ok(result);
+        }
 
         // Analyze
         let analyzed = self.analyzer.analyze(&ast)?;
@@ -123,6 +139,211 @@ impl QueryEngine {
         Ok(result)
     }
 
+    /// Begin a transaction, returning its ID
+    ///
+    /// Run statements in it with [`QueryEngine::execute_in`] and end it
+    /// with [`QueryEngine::commit`] or [`QueryEngine::rollback`]. SQL
+    /// `BEGIN` does the same.
+    pub fn begin(&self, isolation: IsolationLevel) -> u64 {
+        self.transactions.begin(isolation).id
+    }
+
+    /// Begin a transaction on behalf of `owner`, returning its ID
+    pub fn begin_for(&self, isolation: IsolationLevel, owner: &str) -> u64 {
+        self.transactions
+            .begin_for(isolation, Some(owner.to_string()))
+            .id
+    }
+
+    /// Principal that began an active transaction, if it was begun for one
+    ///
+    /// # Errors
+    ///
+    /// Fails if the transaction is unknown.
+    pub fn transaction_owner(&self, txn_id: u64) -> Result<Option<String>> {
+        Ok(self.transactions.get(txn_id)?.owner.clone())
+    }
+
+    /// Execute a query inside a transaction
+    ///
+    /// `COMMIT` and `ROLLBACK` end the transaction. Results are not
+    /// cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is unknown or the query fails.
+    pub async fn execute_in(
+        &self,
+        txn_id: u64,
+        query: &str,
+        params: &[serde_json::Value],
+    ) -> Result<QueryResult> {
+        debug!("Executing query in transaction {}: {}", txn_id, query);
+        let txn = self.transactions.get(txn_id)?;
+
+        let ast = self.parser.parse(query)?;
+        if let Some(result) = self.run_transaction_statement(Some(txn_id), &ast).await? {
+            return Ok(result);
+        }
+        let analyzed = self.analyzer.analyze(&ast)?;
+        let plan = self.optimizer.optimize(&analyzed)?;
+        let result = self
+            .executor
+            .in_transaction(txn)
+            .execute(&plan, params)
+            .await?;
+
+        lumadb_common::metrics::record_query("execute", true);
+
+        Ok(result)
+    }
+
+    /// Commit a transaction, returning its commit timestamp
+    ///
+    /// Fails, rolling the transaction back, if it conflicts with a
+    /// transaction that committed first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is unknown, conflicts, or cannot be
+    /// written.
+    pub async fn commit(&self, txn_id: u64) -> Result<u64> {
+        self.transactions.commit(txn_id).await
+    }
+
+    /// Roll back a transaction, discarding its writes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is unknown.
+    pub async fn rollback(&self, txn_id: u64) -> Result<()> {
+        self.transactions.rollback(txn_id).await
+    }
+
+    /// Run `BEGIN`, `COMMIT` or `ROLLBACK` with `current` the transaction
+    /// the statement runs in; `None` for other statements
+    async fn run_transaction_statement(
+        &self,
+        current: Option<u64>,
+        ast: &Ast,
+    ) -> Result<Option<QueryResult>> {
+        let (key, value) = match (ast, current) {
+            (Ast::Begin { .. }, Some(txn_id)) => {
+                return Err(Error::Query(QueryError::ExecutionError(format!(
+                    "Transaction {txn_id} is already in progress"
+                ))));
+            }
+            (Ast::Begin { isolation }, None) => (
+                "transaction_id",
+                serde_json::Value::from(self.begin(*isolation)),
+            ),
+            (Ast::Commit | Ast::Rollback, None) => {
+                return Err(Error::Query(QueryError::ExecutionError(
+                    "No transaction in progress".to_string(),
+                )));
+            }
+            (Ast::Commit, Some(txn_id)) => (
+                "commit_ts",
+                serde_json::Value::from(self.commit(txn_id).await?),
+            ),
+            (Ast::Rollback, Some(txn_id)) => {
+                self.rollback(txn_id).await?;
+                ("rolled_back", serde_json::Value::from(txn_id))
+            }
+            _ => return Ok(None),
+        };
+
+        let mut row = HashMap::new();
+        row.insert(key.to_string(), value);
+        Ok(Some(QueryResult::new(vec![row])))
+    }
+
+    /// Execute a query and return one page of its rows
+    ///
+    /// Pass the `next_token` of a page as `token` to read the page after
+    /// it. Results of paginated queries are not cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `page_size` is zero, `token` belongs to another
+    /// query, or the query fails.
+    pub async fn execute_page(
+        &self,
+        query: &str,
+        params: &[serde_json::Value],
+        page_size: usize,
+        token: Option<&str>,
+    ) -> Result<QueryPage> {
+        debug!("Executing query page: {}", query);
+        if page_size == 0 {
+            return Err(Error::Query(QueryError::SemanticError(
+                "page size must be positive".to_string(),
+            )));
+        }
+
+        let fingerprint = self.cache_key(query, params);
+        let token = token
+            .map(|token| ContinuationToken::decode(token, fingerprint))
+            .transpose()?;
+        let returned = token.as_ref().map_or(0, |t| t.returned);
+        let after = token.and_then(|t| t.after);
+
+        let ast = self.parser.parse(query)?;
+        let analyzed = self.analyzer.analyze(&ast)?;
+        let plan = self.optimizer.optimize(&analyzed)?;
+
+        // Resume the scan where the last page stopped, or else skip the
+        // rows earlier pages returned
+        let resumable = pagination::resumable(&plan);
+        let mut cursor = match &after {
+            Some(after) if resumable => {
+                let plan = pagination::resume(&plan, returned);
+                self.executor.open(&plan, params, Some(after)).await?
+            }
+            _ => {
+                let mut cursor = self.executor.open(&plan, params, None).await?;
+                for _ in 0..returned {
+                    if cursor.next_row()?.is_none() {
+                        break;
+                    }
+                }
+                cursor
+            }
+        };
+
+        let rows = cursor.fetch(page_size)?;
+        let columns = cursor.columns();
+        let position = cursor.position();
+        let more = rows.len() == page_size && cursor.next_row()?.is_some();
+
+        let next_token = more.then(|| {
+            ContinuationToken {
+                query: fingerprint,
+                returned: returned + rows.len(),
+                after: if resumable { position } else { None },
+            }
+            .encode()
+        });
+        let result = match columns {
+            Some(columns) => QueryResult::with_columns(columns, rows),
+            None => QueryResult::new(rows),
+        };
+
+        lumadb_common::metrics::record_query("execute_page", true);
+
+        Ok(QueryPage { result, next_token })
+    }
+
+    /// Resources `query` would touch, as `(resource, action)` pairs for
+    /// the authorizer; see [`Ast::accesses`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if `query` does not parse.
+    pub fn accesses(&self, query: &str) -> Result<Vec<(String, &'static str)>> {
+        Ok(self.parser.parse(query)?.accesses())
+    }
+
     /// Explain a query plan
     pub async fn explain(&self, query: &str) -> Result<QueryPlan> {
         let ast = self.parser.parse(query)?;
@@ -152,6 +373,9 @@ impl QueryEngine {
             size_bytes: 0,
             indexes: vec![],
             schema: schema.cloned(),
+            vector: None,
+            payload_indexes: vec![],
+            text_fields: vec![],
         })
     }
 
@@ -265,7 +489,9 @@ impl QueryEngine {
         k: usize,
         _filter: Option<&str>,
     ) -> Result<Vec<VectorSearchResult>> {
-        let index = self.storage.get_or_create_vector_index(collection, vector.len());
+        let Some(index) = self.storage.vector_index(collection) else {
+            return Ok(Vec::new());
+        };
         let results = index.search(vector, k);
 
         // Convert (id, score) tuples to VectorSearchResult
@@ -284,6 +510,47 @@ impl QueryEngine {
         Ok(search_results)
     }
 
+    /// Hybrid search fusing vector similarity with BM25 over the
+    /// collection's full-text index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection has no vector or full-text index the
+    /// query needs, or the search fails.
+    pub async fn hybrid_search(
+        &self,
+        collection: &str,
+        query: &HybridQuery,
+    ) -> Result<Vec<HybridSearchResult>> {
+        self.storage.hybrid_search(collection, query).await
+    }
+
+    /// Index the text of a document field for full-text and hybrid search;
+    /// returns `false` if it already is
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection does not exist or its documents
+    /// cannot be read.
+    pub async fn create_text_index(&self, collection: &str, field: &str) -> Result<bool> {
+        self.storage.create_text_index(collection, field).await
+    }
+
+    /// Stop indexing the text of a document field; returns whether it was
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the full-text index cannot be rebuilt.
+    pub async fn drop_text_index(&self, collection: &str, field: &str) -> Result<bool> {
+        self.storage.drop_text_index(collection, field).await
+    }
+
+    /// How a quantized vector collection's codes trade recall for memory
+    /// and speed
+    pub fn quantization_report(&self, collection: &str) -> Option<QuantizationReport> {
+        self.storage.quantization_report(collection)
+    }
+
     // ========================================================================
     // Helper Methods
     // ========================================================================
@@ -306,7 +573,9 @@ impl QueryEngine {
         self.cache.retain(|_, v| now - v.timestamp < 300000); // 5 minutes
     }
 
-    fn matches_filter(&self, doc: &Document, filter: &serde_json::Value) -> bool {
+    /// Whether `doc` has every field of `filter` with an equal value, as
+    /// `find`, `update` and `delete` select documents
+    pub fn matches_filter(&self, doc: &Document, filter: &serde_json::Value) -> bool {
         if let serde_json::Value::Object(filter_map) = filter {
             for (key, expected) in filter_map {
                 if let Some(actual) = doc.get(key) {
@@ -386,3 +655,564 @@ pub struct UpdateResult {
 pub struct DeleteResult {
     pub deleted_count: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumadb_common::config::StorageConfig;
+    use serde_json::json;
+
+    async fn engine_with_data(dir: &std::path::Path) -> QueryEngine {
+        let storage_config = StorageConfig {
+            path: dir.to_string_lossy().to_string(),
+            ..StorageConfig::default()
+        };
+        let storage = Arc::new(StorageEngine::new(&storage_config).await.unwrap());
+        let config = QueryConfig {
+            cache_enabled: false,
+            ..QueryConfig::default()
+        };
+        let engine = QueryEngine::new(&config, storage).await.unwrap();
+
+        engine.create_collection("emp", None, None).await.unwrap();
+        engine
+            .insert(
+                "emp",
+                &[
+                    json!({"name": "ann", "dept": 1, "salary": 100}),
+                    json!({"name": "bob", "dept": 1, "salary": 200}),
+                    json!({"name": "cid", "dept": 2, "salary": 400}),
+                    json!({"name": "dan", "dept": null, "salary": 50}),
+                ],
+            )
+            .await
+            .unwrap();
+        engine.create_collection("dept", None, None).await.unwrap();
+        engine
+            .insert(
+                "dept",
+                &[
+                    json!({"id": 1, "title": "eng"}),
+                    json!({"id": 2, "title": "ops"}),
+                    json!({"id": 3, "title": "hr"}),
+                ],
+            )
+            .await
+            .unwrap();
+        engine
+    }
+
+    fn column(result: &QueryResult, name: &str) -> Vec<serde_json::Value> {
+        result.rows().iter().map(|r| r[name].clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn group_by_with_having_and_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+
+        let result = engine
+            .execute(
+                "SELECT dept, COUNT(*) AS n, SUM(salary) AS total, MAX(name) FROM emp \
+                 WHERE dept IS NOT NULL GROUP BY dept HAVING COUNT(*) >= 1 ORDER BY total DESC",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.columns(), ["dept", "n", "total", "max(name)"]);
+        assert_eq!(column(&result, "dept"), [json!(2), json!(1)]);
+        assert_eq!(column(&result, "n"), [json!(1), json!(2)]);
+        assert_eq!(column(&result, "max(name)"), [json!("cid"), json!("bob")]);
+
+        let result = engine
+            .execute(
+                "SELECT COUNT(dept), COUNT(DISTINCT dept), AVG(salary) FROM emp",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.rows()[0]["count(dept)"], json!(3));
+        assert_eq!(result.rows()[0]["count(DISTINCT dept)"], json!(2));
+        assert_eq!(result.rows()[0]["avg(salary)"], json!(187.5));
+
+        let err = engine
+            .execute("SELECT name, COUNT(*) FROM emp GROUP BY dept", &[])
+            .await;
+        assert!(err.is_err());
+    }
+
+    #[tokio::test]
+    async fn joins() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+
+        let result = engine
+            .execute(
+                "SELECT e.name, d.title FROM emp e JOIN dept d ON e.dept = d.id ORDER BY e.name",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            column(&result, "name"),
+            [json!("ann"), json!("bob"), json!("cid")]
+        );
+        assert_eq!(
+            column(&result, "title"),
+            [json!("eng"), json!("eng"), json!("ops")]
+        );
+
+        let result = engine
+            .execute(
+                "SELECT e.name, d.title FROM emp e LEFT JOIN dept d ON e.dept = d.id WHERE d.id IS NULL",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(column(&result, "name"), [json!("dan")]);
+
+        let result = engine
+            .execute(
+                "SELECT COUNT(*) FROM emp e FULL JOIN dept d ON e.dept = d.id",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.rows()[0]["count(*)"], json!(5));
+
+        // Non-equi conditions fall back to a nested loop
+        let result = engine
+            .execute(
+                "SELECT e.name, d.id FROM emp e, dept d WHERE e.salary > d.id * 100 AND d.title = 'ops'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.rows().len(), 1);
+
+        let plan = engine
+            .explain("SELECT * FROM emp e, dept d WHERE e.dept = d.id")
+            .await
+            .unwrap();
+        assert!(plan.nodes.iter().any(|n| n.node_type == "HashJoin"));
+    }
+
+    #[tokio::test]
+    async fn subqueries_distinct_and_expressions() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+
+        let result = engine
+            .execute(
+                "SELECT t.dept, t.total FROM (SELECT dept, SUM(salary) AS total FROM emp GROUP BY dept) t \
+                 WHERE t.total > (SELECT MIN(salary) FROM emp WHERE dept = 1) ORDER BY 2",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(column(&result, "total"), [json!(300.0), json!(400.0)]);
+
+        let result = engine
+            .execute(
+                "SELECT name FROM emp WHERE dept IN (SELECT id FROM dept WHERE title LIKE 'e%') ORDER BY name DESC",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(column(&result, "name"), [json!("bob"), json!("ann")]);
+
+        let result = engine
+            .execute(
+                "SELECT DISTINCT dept FROM emp ORDER BY dept LIMIT 2 OFFSET 1",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(column(&result, "dept"), [json!(2), json!(null)]);
+
+        let result = engine
+            .execute(
+                "SELECT upper(name) || '!' AS shout, salary * 2 + 1 AS x, \
+                 CASE WHEN salary >= 200 THEN 'high' ELSE 'low' END AS band \
+                 FROM emp WHERE name = 'bob'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.rows()[0]["shout"], json!("BOB!"));
+        assert_eq!(result.rows()[0]["x"], json!(401));
+        assert_eq!(result.rows()[0]["band"], json!("high"));
+
+        let result = engine.execute("SELECT 1 + 1 AS result", &[]).await.unwrap();
+        assert_eq!(result.rows()[0]["result"], json!(2));
+    }
+
+    #[tokio::test]
+    async fn correlated_subqueries_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+
+        for query in [
+            "SELECT d.title FROM dept d WHERE EXISTS (SELECT 1 FROM emp e WHERE e.dept = d.id)",
+            "SELECT d.title, (SELECT MAX(e.salary) FROM emp e WHERE e.dept = d.id) FROM dept d",
+        ] {
+            let err = engine.execute(query, &[]).await.unwrap_err();
+            assert!(
+                matches!(&err, Error::Query(QueryError::SemanticError(m)) if m.contains("d.id")),
+                "{query}: {err}"
+            );
+        }
+
+        // A qualifier naming no relation is an error rather than NULL
+        let err = engine
+            .execute("SELECT x.name FROM emp e", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Query(QueryError::SemanticError(_))));
+    }
+
+    #[tokio::test]
+    async fn paginated_scans_resume_past_the_old_row_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+        engine.create_collection("big", None, None).await.unwrap();
+        let docs: Vec<_> = (0..2500).map(|n| json!({"n": n})).collect();
+        engine.insert("big", &docs).await.unwrap();
+
+        let result = engine
+            .execute("SELECT COUNT(*) FROM big", &[])
+            .await
+            .unwrap();
+        assert_eq!(result.rows()[0]["count(*)"], json!(2500));
+
+        // Scans resume after the last document; LIMIT spans pages
+        let query = "SELECT n FROM big WHERE n % 2 = 0 LIMIT 700";
+        let mut token = None;
+        let mut seen = std::collections::HashSet::new();
+        let mut pages = Vec::new();
+        loop {
+            let page = engine
+                .execute_page(query, &[], 300, token.as_deref())
+                .await
+                .unwrap();
+            assert_eq!(page.result.columns(), ["n"]);
+            pages.push(page.result.rows().len());
+            for row in page.result.rows() {
+                assert!(seen.insert(row["n"].as_i64().unwrap()));
+            }
+            match page.next_token {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, [300, 300, 100]);
+        assert!(seen.iter().all(|n| n % 2 == 0));
+
+        // Sorted queries re-run and skip the rows already returned
+        let query = "SELECT n FROM big ORDER BY n DESC";
+        let first = engine.execute_page(query, &[], 1000, None).await.unwrap();
+        let token = first.next_token.unwrap();
+        let second = engine
+            .execute_page(query, &[], 1000, Some(&token))
+            .await
+            .unwrap();
+        assert_eq!(first.result.rows()[999]["n"], json!(1500));
+        assert_eq!(second.result.rows()[0]["n"], json!(1499));
+        let last = engine
+            .execute_page(query, &[], 1000, second.next_token.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(last.result.rows().len(), 500);
+        assert!(last.next_token.is_none());
+
+        let other = engine
+            .execute_page("SELECT n FROM big", &[], 10, Some(&token))
+            .await;
+        assert!(other.is_err());
+    }
+
+    /// Engine with 300 `people` of varying age, city and email
+    async fn engine_with_people(dir: &std::path::Path) -> QueryEngine {
+        let engine = engine_with_data(dir).await;
+        engine
+            .create_collection("people", None, None)
+            .await
+            .unwrap();
+        let cities = ["ams", "ber", "cph"];
+        let docs: Vec<_> = (0..300)
+            .map(|i| {
+                json!({
+                    "age": i % 50,
+                    "email": format!("p{i}@example.com"),
+                    "active": i % 2 == 0,
+                    "address": {"city": cities[i % 3]},
+                })
+            })
+            .collect();
+        engine.insert("people", &docs).await.unwrap();
+        engine
+    }
+
+    fn uses_index(plan: &QueryPlan, index: &str) -> bool {
+        plan.nodes
+            .iter()
+            .any(|n| n.node_type == "IndexScan" && n.description.contains(index))
+    }
+
+    #[tokio::test]
+    async fn secondary_indexes_drive_index_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_people(dir.path()).await;
+        let expected = |f: &dyn Fn(usize) -> bool| (0..300).filter(|&i| f(i)).count();
+
+        let query = "SELECT age FROM people WHERE age > 45 AND age <= 47";
+        assert!(!uses_index(&engine.explain(query).await.unwrap(), "by_age"));
+        engine
+            .execute("CREATE INDEX by_age ON people (age)", &[])
+            .await
+            .unwrap();
+        assert!(uses_index(&engine.explain(query).await.unwrap(), "by_age"));
+        let result = engine.execute(query, &[]).await.unwrap();
+        assert_eq!(
+            result.rows().len(),
+            expected(&|i| i % 50 == 46 || i % 50 == 47)
+        );
+        assert!(column(&result, "age")
+            .iter()
+            .all(|a| a == &json!(46) || a == &json!(47)));
+
+        // Compound index on a nested path: equality, then a range
+        engine
+            .execute(
+                "CREATE INDEX by_city_age ON people (address.city, age)",
+                &[],
+            )
+            .await
+            .unwrap();
+        let query = "SELECT p.age FROM people p WHERE p.address.city = 'ber' AND p.age < 10";
+        assert!(uses_index(
+            &engine.explain(query).await.unwrap(),
+            "by_city_age"
+        ));
+        let result = engine.execute(query, &[]).await.unwrap();
+        assert_eq!(
+            result.rows().len(),
+            expected(&|i| i % 3 == 1 && i % 50 < 10)
+        );
+
+        // Unique indexes reject duplicates, both existing and new
+        let err = engine
+            .execute("CREATE UNIQUE INDEX uniq_age ON people (age)", &[])
+            .await;
+        assert!(err.is_err());
+        engine
+            .execute("CREATE UNIQUE INDEX by_email ON people (email)", &[])
+            .await
+            .unwrap();
+        let dup = engine
+            .insert("people", &[json!({"email": "p7@example.com", "age": 1})])
+            .await;
+        assert!(dup.is_err());
+        let result = engine
+            .execute(
+                "SELECT COUNT(*) FROM people WHERE email = 'p7@example.com'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.rows()[0]["count(*)"], json!(1));
+
+        // A partial index serves only queries implying its condition
+        engine
+            .execute("DROP INDEX people.by_age", &[])
+            .await
+            .unwrap();
+        engine
+            .execute(
+                "CREATE INDEX active_age ON people (age) WHERE active = true",
+                &[],
+            )
+            .await
+            .unwrap();
+        let query = "SELECT age FROM people WHERE active = true AND age = 8";
+        assert!(uses_index(
+            &engine.explain(query).await.unwrap(),
+            "active_age"
+        ));
+        let result = engine.execute(query, &[]).await.unwrap();
+        assert_eq!(
+            result.rows().len(),
+            expected(&|i| i % 2 == 0 && i % 50 == 8)
+        );
+        let plan = engine
+            .explain("SELECT age FROM people WHERE age = 8")
+            .await
+            .unwrap();
+        assert!(plan.nodes.iter().all(|n| n.node_type != "IndexScan"));
+
+        // Deleted documents disappear from index scans
+        let victim = engine
+            .execute("SELECT _id FROM people WHERE email = 'p8@example.com'", &[])
+            .await
+            .unwrap();
+        let id = victim.rows()[0]["_id"].as_str().unwrap();
+        engine.storage.delete_document("people", id).await.unwrap();
+        let result = engine.execute(query, &[]).await.unwrap();
+        assert_eq!(
+            result.rows().len(),
+            expected(&|i| i % 2 == 0 && i % 50 == 8) - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn sql_transactions_read_a_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+        let txn_id = |result: QueryResult| result.rows()[0]["transaction_id"].as_u64().unwrap();
+        let total = "SELECT SUM(salary) AS total FROM emp";
+
+        let first = txn_id(engine.execute("BEGIN", &[]).await.unwrap());
+        let second = txn_id(engine.execute("START TRANSACTION", &[]).await.unwrap());
+        engine
+            .execute_in(first, "UPDATE emp SET salary = 300 WHERE name = 'ann'", &[])
+            .await
+            .unwrap();
+        engine
+            .execute_in(
+                first,
+                "INSERT INTO emp (name, dept, salary) VALUES ('eve', 2, -10)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+        // Only the transaction sees its writes until it commits
+        let result = engine.execute_in(first, total, &[]).await.unwrap();
+        assert_eq!(result.rows()[0]["total"], json!(940.0));
+        let result = engine.execute(total, &[]).await.unwrap();
+        assert_eq!(result.rows()[0]["total"], json!(750.0));
+        engine.execute_in(first, "COMMIT", &[]).await.unwrap();
+        assert!(engine.execute_in(first, total, &[]).await.is_err());
+
+        // The second transaction keeps its snapshot, and loses the race
+        let result = engine.execute_in(second, total, &[]).await.unwrap();
+        assert_eq!(result.rows()[0]["total"], json!(750.0));
+        engine
+            .execute_in(second, "DELETE FROM emp WHERE name = 'ann'", &[])
+            .await
+            .unwrap();
+        assert!(engine.execute_in(second, "COMMIT", &[]).await.is_err());
+
+        let third = engine.begin(IsolationLevel::Serializable);
+        engine
+            .execute_in(third, "DELETE FROM emp WHERE salary < 0", &[])
+            .await
+            .unwrap();
+        engine.execute_in(third, "ROLLBACK", &[]).await.unwrap();
+        let result = engine.execute(total, &[]).await.unwrap();
+        assert_eq!(result.rows()[0]["total"], json!(940.0));
+        assert!(engine.execute("COMMIT", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hybrid_vector_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+        engine
+            .storage
+            .create_vector_collection("notes", 2)
+            .await
+            .unwrap();
+        let notes = [
+            ("n1", "it's a vector database", [1.0, 0.0]),
+            ("n2", "streaming topics", [0.9, 0.1]),
+            ("n3", "a database for text", [0.0, 1.0]),
+        ];
+        for (id, body, vector) in notes {
+            let doc = Document::with_id(id, json!({ "body": body }));
+            engine
+                .storage
+                .insert_vector_document("notes", &doc, &vector)
+                .await
+                .unwrap();
+        }
+        assert!(engine.create_text_index("notes", "body").await.unwrap());
+
+        let result = engine
+            .execute(
+                "VECTOR SEARCH notes [1.0, 0.0] MATCH 'database' LIMIT 3",
+                &[],
+            )
+            .await
+            .unwrap();
+        // Matching the text lifts n3 above n2, which is nearer
+        assert_eq!(
+            column(&result, "id"),
+            [json!("n1"), json!("n3"), json!("n2")]
+        );
+        let top = &result.rows()[0];
+        assert_eq!(top["vector_rank"], json!(1));
+        assert!(top["text_rank"].is_number());
+        assert!(result.rows()[2]["text_rank"].is_null());
+
+        // Only the text counts with a zero vector weight
+        let result = engine
+            .execute(
+                "VECTOR SEARCH notes [1.0, 0.0] MATCH 'database text' 2 \
+                 FUSION WEIGHTED WEIGHTS 0 1",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(column(&result, "id")[0], json!("n3"));
+
+        let result = engine
+            .execute("VECTOR SEARCH notes MATCH 'it''s'", &[])
+            .await
+            .unwrap();
+        assert!(result.rows()[0]["vector_rank"].is_null());
+        assert!(engine
+            .execute("VECTOR SEARCH notes LIMIT 2", &[])
+            .await
+            .is_err());
+        assert!(engine
+            .execute("VECTOR SEARCH notes [1.0, x]", &[])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn accesses_name_every_table_a_statement_touches() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_data(dir.path()).await;
+        let accesses = |query: &str| {
+            engine
+                .accesses(query)
+                .unwrap()
+                .into_iter()
+                .map(|(resource, action)| format!("{action} {resource}"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            accesses(
+                "SELECT e.name, (SELECT MAX(id) FROM dept) FROM emp e \
+                 JOIN (SELECT * FROM audit) a ON e.name = a.name \
+                 WHERE e.dept IN (SELECT id FROM teams)"
+            ),
+            [
+                "read collection:audit",
+                "read collection:dept",
+                "read collection:emp",
+                "read collection:teams",
+            ]
+        );
+        assert_eq!(
+            accesses("DELETE FROM emp WHERE dept IN (SELECT id FROM dept)"),
+            ["write collection:emp", "read collection:dept"]
+        );
+        assert_eq!(accesses("DROP TABLE emp"), ["delete collection:emp"]);
+        assert_eq!(accesses("TOPIC LIST"), ["describe cluster"]);
+        assert!(accesses("BEGIN").is_empty());
+    }
+}
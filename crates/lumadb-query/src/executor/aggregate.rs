@@ -0,0 +1,140 @@
+//! Grouping and aggregation
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use lumadb_common::error::{Error, QueryError, Result};
+
+use super::eval::{hash_key, Evaluator, Row};
+use super::stream::{BoxedStream, RowStream};
+use crate::functions::{Accumulator, FunctionRegistry};
+use crate::optimizer::AggregateCall;
+use crate::parser::Expr;
+
+struct Group {
+    key: Vec<Value>,
+    accumulators: Vec<Box<dyn Accumulator>>,
+    /// Values already aggregated, for DISTINCT aggregates
+    seen: Vec<HashSet<String>>,
+}
+
+/// Group rows by `group_by` and compute `aggregates` per group
+///
+/// Groups come out in the order they were first seen. NULL arguments are
+/// skipped, as SQL aggregates ignore them. Without GROUP BY there is
+/// exactly one group, even for no input rows.
+fn aggregate(
+    evaluator: &Evaluator<'_>,
+    rows: &mut dyn RowStream,
+    group_by: &[Expr],
+    aggregates: &[AggregateCall],
+) -> Result<Vec<Row>> {
+    let new_group = |key: Vec<Value>| -> Result<Group> {
+        let accumulators = aggregates
+            .iter()
+            .map(|call| {
+                evaluator
+                    .functions
+                    .get_aggregate(&call.function)
+                    .map(|f| f.create_accumulator())
+                    .ok_or_else(|| Error::Query(QueryError::UnknownFunction(call.function.clone())))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Group {
+            key,
+            accumulators,
+            seen: vec![HashSet::new(); aggregates.len()],
+        })
+    };
+
+    let mut groups = Vec::new();
+    let mut index = HashMap::new();
+    while let Some(row) = rows.next_row()? {
+        let row = &row;
+        let key = group_by
+            .iter()
+            .map(|e| evaluator.evaluate(e, row))
+            .collect::<Result<Vec<_>>>()?;
+        let i = match index.entry(hash_key(&key)) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                groups.push(new_group(key)?);
+                *entry.insert(groups.len() - 1)
+            }
+        };
+
+        let group: &mut Group = &mut groups[i];
+        for (j, call) in aggregates.iter().enumerate() {
+            let value = match call.args.first() {
+                Some(arg) => evaluator.evaluate(arg, row)?,
+                None => Value::Null,
+            };
+            if value.is_null()
+                || (call.distinct && !group.seen[j].insert(hash_key(std::slice::from_ref(&value))))
+            {
+                continue;
+            }
+            group.accumulators[j].accumulate(&value);
+        }
+    }
+    if groups.is_empty() && group_by.is_empty() {
+        groups.push(new_group(Vec::new())?);
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|group| {
+            let mut row: Row = group_by
+                .iter()
+                .map(ToString::to_string)
+                .zip(group.key)
+                .collect();
+            for (call, accumulator) in aggregates.iter().zip(&group.accumulators) {
+                row.insert(call.name.clone(), accumulator.finalize());
+            }
+            row
+        })
+        .collect())
+}
+
+/// Aggregates its whole input on the first pull, then returns the groups
+pub(super) struct AggregateStream {
+    input: Option<BoxedStream>,
+    group_by: Vec<Expr>,
+    aggregates: Vec<AggregateCall>,
+    functions: Arc<FunctionRegistry>,
+    groups: std::vec::IntoIter<Row>,
+}
+
+impl AggregateStream {
+    pub(super) fn new(
+        input: BoxedStream,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateCall>,
+        functions: Arc<FunctionRegistry>,
+    ) -> Self {
+        Self {
+            input: Some(input),
+            group_by,
+            aggregates,
+            functions,
+            groups: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl RowStream for AggregateStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        if let Some(mut input) = self.input.take() {
+            let evaluator = Evaluator {
+                functions: &self.functions,
+            };
+            let groups = aggregate(&evaluator, input.as_mut(), &self.group_by, &self.aggregates)?;
+            self.groups = groups.into_iter();
+        }
+        Ok(self.groups.next())
+    }
+}
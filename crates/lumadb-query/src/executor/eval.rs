@@ -0,0 +1,434 @@
+//! Expression evaluation over rows
+//!
+//! Evaluation follows SQL semantics: comparisons and arithmetic involving
+//! NULL yield NULL, and AND/OR use three-valued logic. A predicate holds
+//! only when it evaluates to `true`.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use lumadb_common::error::{Error, QueryError, Result};
+
+use crate::functions::FunctionRegistry;
+use crate::parser::{BinaryOperator, DataType, Expr, UnaryOperator};
+
+/// A row keyed by column name
+pub(crate) type Row = HashMap<String, Value>;
+
+/// Look up a column by its exact name, or an unqualified name by the one
+/// qualified column it matches
+///
+/// A name that matches no column may be a path into a nested object, as in
+/// `t.address.city`; the longest prefix naming a column is followed into
+/// its value.
+pub(crate) fn lookup<'a>(row: &'a Row, name: &str) -> Option<&'a Value> {
+    if let Some(value) = column(row, name) {
+        return Some(value);
+    }
+    let mut end = name.len();
+    while let Some(dot) = name[..end].rfind('.') {
+        if let Some(value) = column(row, &name[..dot]) {
+            return name[dot + 1..]
+                .split('.')
+                .try_fold(value, |value, field| value.get(field));
+        }
+        end = dot;
+    }
+    None
+}
+
+fn column<'a>(row: &'a Row, name: &str) -> Option<&'a Value> {
+    if let Some(value) = row.get(name) {
+        return Some(value);
+    }
+    if name.contains('.') {
+        return None;
+    }
+    let mut matches = row.iter().filter(|(key, _)| {
+        key.rsplit_once('.')
+            .is_some_and(|(_, column)| column == name)
+    });
+    match (matches.next(), matches.next()) {
+        (Some((_, value)), None) => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether a predicate result holds
+pub(crate) fn is_true(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Null => false,
+        _ => true,
+    }
+}
+
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Bool(_) => 0,
+        Value::Number(_) => 1,
+        Value::String(_) => 2,
+        Value::Array(_) => 3,
+        Value::Object(_) => 4,
+        Value::Null => 5,
+    }
+}
+
+/// Total order over values, used for sorting and MIN/MAX
+///
+/// Values of different types are ordered by type, with NULL last.
+#[must_use]
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a
+                .as_f64()
+                .unwrap_or(0.0)
+                .partial_cmp(&b.as_f64().unwrap_or(0.0))
+                .unwrap_or(Ordering::Equal),
+        },
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            a.to_string().cmp(&b.to_string())
+        }
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+/// Equality of non-null values, comparing numbers by value
+pub(crate) fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => compare_values(a, b) == Ordering::Equal,
+        _ => a == b,
+    }
+}
+
+/// Hashable form of a list of values, equal for values that compare equal
+pub(crate) fn hash_key(values: &[Value]) -> String {
+    let mut key = String::new();
+    for value in values {
+        match value {
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => key.push_str(&i.to_string()),
+                None => key.push_str(&n.as_f64().unwrap_or(0.0).to_string()),
+            },
+            _ => key.push_str(&value.to_string()),
+        }
+        key.push('\u{1f}');
+    }
+    key
+}
+
+/// SQL comparison: NULL when either side is NULL or the types differ
+fn compare_sql(a: &Value, b: &Value) -> Option<Ordering> {
+    if a.is_null() || b.is_null() || type_rank(a) != type_rank(b) {
+        return None;
+    }
+    Some(compare_values(a, b))
+}
+
+fn number(f: f64) -> Value {
+    serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+fn arithmetic(op: BinaryOperator, a: &Value, b: &Value) -> Value {
+    let (Value::Number(x), Value::Number(y)) = (a, b) else {
+        return Value::Null;
+    };
+    if let (Some(x), Some(y)) = (x.as_i64(), y.as_i64()) {
+        let exact = match op {
+            BinaryOperator::Plus => x.checked_add(y),
+            BinaryOperator::Minus => x.checked_sub(y),
+            BinaryOperator::Mul => x.checked_mul(y),
+            BinaryOperator::Div if y != 0 && x % y == 0 => x.checked_div(y),
+            BinaryOperator::Mod if y != 0 => x.checked_rem(y),
+            _ => None,
+        };
+        if let Some(result) = exact {
+            return Value::from(result);
+        }
+    }
+
+    let (x, y) = (x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+    match op {
+        BinaryOperator::Plus => number(x + y),
+        BinaryOperator::Minus => number(x - y),
+        BinaryOperator::Mul => number(x * y),
+        BinaryOperator::Div if y != 0.0 => number(x / y),
+        BinaryOperator::Mod if y != 0.0 => number(x % y),
+        _ => Value::Null,
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Match `value` against a LIKE pattern, where `%` matches any run of
+/// characters and `_` any single character
+fn like(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // Greedy matching with backtracking to the last `%`
+    let (mut v, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '_' || (pattern[p] != '%' && pattern[p] == value[v]))
+        {
+            v += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            star = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = star {
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+// Float to integer casts truncate and saturate like SQL engines do
+#[allow(clippy::cast_possible_truncation)]
+fn cast(value: &Value, data_type: &DataType) -> Result<Value> {
+    if value.is_null() {
+        return Ok(Value::Null);
+    }
+    let converted = match data_type {
+        DataType::Int64 => match value {
+            Value::Number(n) => n
+                .as_i64()
+                .or_else(|| {
+                    n.as_f64()
+                        .filter(|f| f.is_finite())
+                        .map(|f| f.trunc() as i64)
+                })
+                .map(Value::from),
+            Value::String(s) => s.trim().parse::<i64>().ok().map(Value::from),
+            Value::Bool(b) => Some(Value::from(i64::from(*b))),
+            _ => None,
+        },
+        DataType::Float64 => match value {
+            Value::Number(n) => n.as_f64().map(number),
+            Value::String(s) => s.trim().parse::<f64>().ok().map(number),
+            Value::Bool(b) => Some(number(if *b { 1.0 } else { 0.0 })),
+            _ => None,
+        },
+        DataType::String | DataType::Timestamp => Some(Value::String(text(value))),
+        DataType::Boolean => match value {
+            Value::Bool(b) => Some(Value::Bool(*b)),
+            Value::Number(n) => n.as_f64().map(|f| Value::Bool(f != 0.0)),
+            Value::String(s) => match s.trim().to_lowercase().as_str() {
+                "true" | "t" | "1" => Some(Value::Bool(true)),
+                "false" | "f" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        },
+        DataType::Json => Some(match value {
+            Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| value.clone()),
+            other => other.clone(),
+        }),
+        DataType::Vector(_) => None,
+    };
+    converted.ok_or_else(|| {
+        Error::Query(QueryError::TypeMismatch {
+            expected: format!("{data_type:?}"),
+            actual: value.to_string(),
+        })
+    })
+}
+
+/// Evaluates expressions against rows
+pub(crate) struct Evaluator<'a> {
+    pub functions: &'a FunctionRegistry,
+}
+
+impl Evaluator<'_> {
+    /// Evaluate an expression whose subqueries have been bound
+    pub fn evaluate(&self, expr: &Expr, row: &Row) -> Result<Value> {
+        Ok(match expr {
+            Expr::Column(name) => lookup(row, name).cloned().unwrap_or(Value::Null),
+            Expr::Literal(value) => value.clone(),
+            Expr::BinaryOp { left, op, right } => self.binary(left, *op, right, row)?,
+            Expr::UnaryOp { op, expr } => match (op, self.evaluate(expr, row)?) {
+                (_, Value::Null) => Value::Null,
+                (UnaryOperator::Not, value) => Value::Bool(!is_true(&value)),
+                (UnaryOperator::Neg, value) => {
+                    arithmetic(BinaryOperator::Minus, &Value::from(0), &value)
+                }
+            },
+            Expr::Function { name, args, .. } => {
+                if self.functions.get_aggregate(name).is_some() {
+                    return Err(Error::Query(QueryError::SemanticError(format!(
+                        "aggregate {expr} is not allowed here"
+                    ))));
+                }
+                let function = self
+                    .functions
+                    .get_scalar(name)
+                    .ok_or_else(|| Error::Query(QueryError::UnknownFunction(name.clone())))?;
+                let args = args
+                    .iter()
+                    .map(|a| self.evaluate(a, row))
+                    .collect::<Result<Vec<_>>>()?;
+                function.evaluate(&args)?
+            }
+            Expr::Wildcard => Value::Bool(true),
+            Expr::IsNull { expr, negated } => {
+                Value::Bool(self.evaluate(expr, row)?.is_null() != *negated)
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => self.in_list(expr, list, *negated, row)?,
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => match (self.evaluate(expr, row)?, self.evaluate(pattern, row)?) {
+                (Value::Null, _) | (_, Value::Null) => Value::Null,
+                (value, pattern) => {
+                    let (mut value, mut pattern) = (text(&value), text(&pattern));
+                    if *case_insensitive {
+                        value = value.to_lowercase();
+                        pattern = pattern.to_lowercase();
+                    }
+                    Value::Bool(like(&value, &pattern) != *negated)
+                }
+            },
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => self.case(operand.as_deref(), branches, else_result.as_deref(), row)?,
+            Expr::Cast { expr, data_type } => cast(&self.evaluate(expr, row)?, data_type)?,
+            Expr::InSubquery { .. } | Expr::Exists { .. } | Expr::Subquery(_) => {
+                return Err(Error::Query(QueryError::ExecutionError(format!(
+                    "subquery in {expr} was not evaluated"
+                ))));
+            }
+        })
+    }
+
+    /// `expr [NOT] IN (list)` with SQL's three-valued logic
+    fn in_list(&self, expr: &Expr, list: &[Expr], negated: bool, row: &Row) -> Result<Value> {
+        let value = self.evaluate(expr, row)?;
+        if value.is_null() {
+            return Ok(Value::Null);
+        }
+        let mut saw_null = false;
+        for item in list {
+            let item = self.evaluate(item, row)?;
+            saw_null |= item.is_null();
+            if !item.is_null() && values_equal(&value, &item) {
+                return Ok(Value::Bool(!negated));
+            }
+        }
+        Ok(if saw_null {
+            Value::Null
+        } else {
+            Value::Bool(negated)
+        })
+    }
+
+    /// Simple (`CASE x WHEN ...`) and searched (`CASE WHEN ...`) forms
+    fn case(
+        &self,
+        operand: Option<&Expr>,
+        branches: &[(Expr, Expr)],
+        else_result: Option<&Expr>,
+        row: &Row,
+    ) -> Result<Value> {
+        let operand = operand.map(|o| self.evaluate(o, row)).transpose()?;
+        for (when, then) in branches {
+            let when = self.evaluate(when, row)?;
+            let matched = match &operand {
+                Some(operand) => {
+                    !operand.is_null() && !when.is_null() && values_equal(operand, &when)
+                }
+                None => is_true(&when),
+            };
+            if matched {
+                return self.evaluate(then, row);
+            }
+        }
+        else_result.map_or(Ok(Value::Null), |e| self.evaluate(e, row))
+    }
+
+    /// Evaluate a predicate
+    pub fn matches(&self, predicate: &Expr, row: &Row) -> Result<bool> {
+        Ok(is_true(&self.evaluate(predicate, row)?))
+    }
+
+    fn binary(&self, left: &Expr, op: BinaryOperator, right: &Expr, row: &Row) -> Result<Value> {
+        let left = self.evaluate(left, row)?;
+        // Short-circuit where the left side decides the result
+        match (op, &left) {
+            (BinaryOperator::And, Value::Bool(false)) => return Ok(Value::Bool(false)),
+            (BinaryOperator::Or, Value::Bool(true)) => return Ok(Value::Bool(true)),
+            _ => {}
+        }
+        let right = self.evaluate(right, row)?;
+
+        Ok(match op {
+            BinaryOperator::And | BinaryOperator::Or => {
+                let truth = |v: &Value| if v.is_null() { None } else { Some(is_true(v)) };
+                let result = match (op, truth(&left), truth(&right)) {
+                    (BinaryOperator::And, Some(true), Some(true)) => Some(true),
+                    (BinaryOperator::Or, Some(true), _) | (BinaryOperator::Or, _, Some(true)) => {
+                        Some(true)
+                    }
+                    (BinaryOperator::And, Some(false), _)
+                    | (BinaryOperator::And, _, Some(false))
+                    | (BinaryOperator::Or, Some(false), Some(false)) => Some(false),
+                    _ => None,
+                };
+                result.map_or(Value::Null, Value::Bool)
+            }
+            BinaryOperator::Eq | BinaryOperator::Ne => {
+                if left.is_null() || right.is_null() {
+                    Value::Null
+                } else {
+                    Value::Bool(values_equal(&left, &right) == (op == BinaryOperator::Eq))
+                }
+            }
+            BinaryOperator::Lt | BinaryOperator::Le | BinaryOperator::Gt | BinaryOperator::Ge => {
+                match compare_sql(&left, &right) {
+                    Some(ordering) => Value::Bool(match op {
+                        BinaryOperator::Lt => ordering == Ordering::Less,
+                        BinaryOperator::Le => ordering != Ordering::Greater,
+                        BinaryOperator::Gt => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    }),
+                    None => Value::Null,
+                }
+            }
+            BinaryOperator::Concat => {
+                if left.is_null() || right.is_null() {
+                    Value::Null
+                } else {
+                    Value::String(text(&left) + &text(&right))
+                }
+            }
+            BinaryOperator::Plus
+            | BinaryOperator::Minus
+            | BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::Mod => arithmetic(op, &left, &right),
+        })
+    }
+}
@@ -0,0 +1,151 @@
+//! Join algorithms
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use lumadb_common::error::Result;
+
+use super::eval::{hash_key, Evaluator, Row};
+use super::stream::{BoxedStream, RowStream};
+use crate::functions::FunctionRegistry;
+use crate::parser::{Expr, JoinKind};
+
+fn merge(left: &Row, right: &Row) -> Row {
+    let mut row = left.clone();
+    row.extend(right.iter().map(|(k, v)| (k.clone(), v.clone())));
+    row
+}
+
+/// Joins two inputs, pairing rows whose keys are equal and that satisfy
+/// `condition`
+///
+/// The right input is read whole and hashed on `right_keys`, then the left
+/// input streams through and probes it with `left_keys`. Rows with a NULL
+/// key never match. Without keys every pair of rows is compared, which
+/// makes this a nested loop join. Rows an outer join keeps unmatched lack
+/// the other input's columns, which read as NULL.
+pub(super) struct JoinStream {
+    left: BoxedStream,
+    /// Right input, until it has been read into `build`
+    right: Option<BoxedStream>,
+    kind: JoinKind,
+    left_keys: Vec<Expr>,
+    right_keys: Vec<Expr>,
+    condition: Option<Expr>,
+    functions: Arc<FunctionRegistry>,
+    build: Vec<Row>,
+    table: HashMap<String, Vec<usize>>,
+    matched_right: Vec<bool>,
+    /// Joined rows not yet returned
+    pending: VecDeque<Row>,
+    left_done: bool,
+}
+
+impl JoinStream {
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn new(
+        left: BoxedStream,
+        right: BoxedStream,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        condition: Option<Expr>,
+        functions: Arc<FunctionRegistry>,
+    ) -> Self {
+        Self {
+            left,
+            right: Some(right),
+            kind,
+            left_keys,
+            right_keys,
+            condition,
+            functions,
+            build: Vec::new(),
+            table: HashMap::new(),
+            matched_right: Vec::new(),
+            pending: VecDeque::new(),
+            left_done: false,
+        }
+    }
+
+    fn key_of(evaluator: &Evaluator<'_>, row: &Row, keys: &[Expr]) -> Result<Option<String>> {
+        let values = keys
+            .iter()
+            .map(|k| evaluator.evaluate(k, row))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((!values.iter().any(serde_json::Value::is_null)).then(|| hash_key(&values)))
+    }
+
+    fn build(&mut self, mut right: BoxedStream) -> Result<()> {
+        let evaluator = Evaluator {
+            functions: &self.functions,
+        };
+        while let Some(row) = right.next_row()? {
+            if let Some(key) = Self::key_of(&evaluator, &row, &self.right_keys)? {
+                self.table.entry(key).or_default().push(self.build.len());
+            }
+            self.build.push(row);
+        }
+        self.matched_right = vec![false; self.build.len()];
+        Ok(())
+    }
+
+    fn probe(&mut self, left: Row) -> Result<()> {
+        let evaluator = Evaluator {
+            functions: &self.functions,
+        };
+        let mut matched = false;
+        let key = Self::key_of(&evaluator, &left, &self.left_keys)?;
+        if let Some(candidates) = key.and_then(|key| self.table.get(&key)) {
+            for &i in candidates {
+                let row = merge(&left, &self.build[i]);
+                if self
+                    .condition
+                    .as_ref()
+                    .map_or(Ok(true), |c| evaluator.matches(c, &row))?
+                {
+                    matched = true;
+                    self.matched_right[i] = true;
+                    self.pending.push_back(row);
+                }
+            }
+        }
+        if !matched && self.kind.keeps_left() {
+            self.pending.push_back(left);
+        }
+        Ok(())
+    }
+}
+
+impl RowStream for JoinStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        if let Some(right) = self.right.take() {
+            self.build(right)?;
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            if self.left_done {
+                return Ok(None);
+            }
+            if let Some(left) = self.left.next_row()? {
+                self.probe(left)?;
+                continue;
+            }
+
+            self.left_done = true;
+            if self.kind.keeps_right() {
+                let build = std::mem::take(&mut self.build);
+                self.pending.extend(
+                    build
+                        .into_iter()
+                        .zip(&self.matched_right)
+                        .filter(|(_, matched)| !**matched)
+                        .map(|(r, _)| r),
+                );
+            }
+        }
+    }
+}
@@ -4,20 +4,170 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use lumadb_common::error::{Result, Error, QueryError};
+use lumadb_common::types::{Document, IndexCondition, IndexMetadata, IndexType};
+use lumadb_storage::hybrid::HybridQuery;
 use lumadb_storage::StorageEngine;
+use lumadb_txn::Transaction;
 
-use crate::optimizer::PhysicalPlan;
+use crate::functions::FunctionRegistry;
+use crate::optimizer::{Optimizer, PhysicalPlan, ProjectItem};
+use crate::parser::{Expr, Select, TextMatch};
 use crate::QueryResult;
 
+mod aggregate;
+mod eval;
+mod join;
+mod sort;
+mod stream;
+
+pub use eval::compare_values;
+
+use aggregate::AggregateStream;
+use eval::Evaluator;
+use join::JoinStream;
+use sort::SortStream;
+use stream::{
+    AliasStream, BoxedStream, DistinctStream, Documents, FilterStream, LimitStream, ProjectStream,
+    RowsStream, ScanPosition, ScanStream,
+};
+
 /// Query executor
 pub struct Executor {
     storage: Arc<StorageEngine>,
+    functions: Arc<FunctionRegistry>,
+    /// Plans subqueries met during execution
+    planner: Optimizer,
+    /// Memory a sort may use before spilling to disk
+    sort_memory: usize,
+    /// Transaction that statements read and write through, if any
+    txn: Option<Arc<Transaction>>,
+}
+
+/// Rows of an executing plan, produced as they are read
+pub struct Cursor {
+    stream: BoxedStream,
+    position: ScanPosition,
+}
+
+impl Cursor {
+    /// Read the next row, or `None` once all rows were read
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if evaluating the plan fails.
+    pub fn next_row(&mut self) -> Result<Option<HashMap<String, serde_json::Value>>> {
+        self.stream.next_row()
+    }
+
+    /// Read up to `n` rows
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if evaluating the plan fails.
+    pub fn fetch(&mut self, n: usize) -> Result<Vec<HashMap<String, serde_json::Value>>> {
+        let mut rows = Vec::new();
+        while rows.len() < n {
+            match self.stream.next_row()? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Names of the output columns, when the plan defines their order
+    ///
+    /// Columns expanded from wildcards are known once a row holding them
+    /// was read.
+    #[must_use]
+    pub fn columns(&self) -> Option<Vec<String>> {
+        self.stream.columns()
+    }
+
+    /// ID of the last document the plan's table scan read
+    ///
+    /// For plans that neither buffer nor reorder rows this is the document
+    /// behind the last row returned, from which [`Executor::open`] can
+    /// resume.
+    #[must_use]
+    pub fn position(&self) -> Option<String> {
+        self.position.lock().clone()
+    }
+
+    /// Read all remaining rows into a result
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if evaluating the plan fails.
+    pub fn into_result(mut self) -> Result<QueryResult> {
+        let rows = self.fetch(usize::MAX)?;
+        Ok(match self.columns() {
+            Some(columns) => QueryResult::with_columns(columns, rows),
+            None => QueryResult::new(rows),
+        })
+    }
 }
 
 impl Executor {
     /// Create a new executor
-    pub fn new(storage: Arc<StorageEngine>) -> Self {
-        Self { storage }
+    pub fn new(
+        storage: Arc<StorageEngine>,
+        functions: Arc<FunctionRegistry>,
+        sort_memory: usize,
+    ) -> Self {
+        Self {
+            planner: Optimizer::new(functions.clone()).with_storage(storage.clone()),
+            storage,
+            functions,
+            sort_memory,
+            txn: None,
+        }
+    }
+
+    /// Executor running statements inside `txn`
+    ///
+    /// Queries read the transaction's snapshot and its own writes, and
+    /// INSERT, UPDATE and DELETE are buffered in it until it commits.
+    /// Tables are read by full scans, as secondary indexes list only the
+    /// newest documents. Other statements take effect immediately.
+    #[must_use]
+    pub fn in_transaction(&self, txn: Arc<Transaction>) -> Self {
+        Self {
+            txn: Some(txn),
+            ..Self::new(
+                self.storage.clone(),
+                self.functions.clone(),
+                self.sort_memory,
+            )
+        }
+    }
+
+    /// Documents of a table as the statement sees them
+    fn documents(&self, table: &str) -> Result<Vec<Document>> {
+        match &self.txn {
+            Some(txn) => txn.scan(table)?.collect(),
+            None => self.storage.document_scan(table, None, None)?.collect(),
+        }
+    }
+
+    async fn put_document(&self, table: &str, doc: Document) -> Result<()> {
+        match &self.txn {
+            Some(txn) => txn.put(table, doc),
+            None => self.storage.insert_document(table, &doc).await,
+        }
+    }
+
+    async fn remove_document(&self, table: &str, id: &str) -> Result<()> {
+        match &self.txn {
+            Some(txn) => txn.delete(table, id),
+            None => self.storage.delete_document(table, id).await.map(|_| ()),
+        }
+    }
+
+    fn evaluator(&self) -> Evaluator<'_> {
+        Evaluator {
+            functions: &self.functions,
+        }
     }
 
     /// Execute a physical plan
@@ -27,17 +177,19 @@ impl Executor {
         params: &[serde_json::Value],
     ) -> Result<QueryResult> {
         match plan {
-            PhysicalPlan::Scan { table, columns, .. } => {
-                self.execute_scan(table, columns).await
-            }
-            PhysicalPlan::Filter { input, predicate, .. } => {
-                self.execute_filter(input, predicate, params).await
-            }
-            PhysicalPlan::Sort { input, order_by, .. } => {
-                self.execute_sort(input, order_by, params).await
-            }
-            PhysicalPlan::Limit { input, limit, .. } => {
-                self.execute_limit(input, *limit, params).await
+            PhysicalPlan::Scan { .. }
+            | PhysicalPlan::IndexScan { .. }
+            | PhysicalPlan::SingleRow
+            | PhysicalPlan::Filter { .. }
+            | PhysicalPlan::SubqueryAlias { .. }
+            | PhysicalPlan::HashJoin { .. }
+            | PhysicalPlan::NestedLoopJoin { .. }
+            | PhysicalPlan::Aggregate { .. }
+            | PhysicalPlan::Project { .. }
+            | PhysicalPlan::Distinct { .. }
+            | PhysicalPlan::Sort { .. }
+            | PhysicalPlan::Limit { .. } => {
+                self.open(plan, params, None).await?.into_result()
             }
             PhysicalPlan::Insert { table, columns, values, .. } => {
                 self.execute_insert(table, columns, values).await
@@ -54,11 +206,17 @@ impl Executor {
             PhysicalPlan::DropTable { name } => {
                 self.execute_drop_table(name).await
             }
+            PhysicalPlan::CreateIndex { name, table, fields, unique, filter } => {
+                self.execute_create_index(name, table, fields, *unique, filter).await
+            }
+            PhysicalPlan::DropIndex { name, table } => {
+                self.execute_drop_index(name, table.as_deref()).await
+            }
             PhysicalPlan::TopicList => {
                 self.execute_topic_list().await
             }
-            PhysicalPlan::VectorSearch { collection, vector, k } => {
-                self.execute_vector_search(collection, vector, *k).await
+            PhysicalPlan::VectorSearch { collection, vector, k, text } => {
+                self.execute_vector_search(collection, vector, *k, text.as_ref()).await
             }
             _ => Err(Error::Query(QueryError::ExecutionError(
                 "Unsupported operation".to_string(),
@@ -66,95 +224,265 @@ impl Executor {
         }
     }
 
-    async fn execute_scan(
+    /// Open a cursor over the rows of a physical plan
+    ///
+    /// Rows are produced as the cursor is read. With `start_after` the
+    /// plan's table scans begin after the document with that ID, which
+    /// resumes a scan from [`Cursor::position`]. Statements that do not
+    /// query rows run to completion when the cursor is opened.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plan cannot be started or a statement fails.
+    pub async fn open(
         &self,
-        table: &str,
-        columns: &[String],
-    ) -> Result<QueryResult> {
-        let docs = self.storage.scan_documents(table, None, Some(1000)).await?;
-
-        let rows: Vec<HashMap<String, serde_json::Value>> = docs
-            .into_iter()
-            .map(|doc| {
-                let mut row = HashMap::new();
-                row.insert("_id".to_string(), serde_json::Value::String(doc.id));
-
-                if let serde_json::Value::Object(map) = doc.data {
-                    for (k, v) in map {
-                        if columns.contains(&"*".to_string()) || columns.contains(&k) {
-                            row.insert(k, v);
-                        }
-                    }
-                }
-
-                row
-            })
-            .collect();
-
-        Ok(QueryResult::new(rows))
+        plan: &PhysicalPlan,
+        params: &[serde_json::Value],
+        start_after: Option<&str>,
+    ) -> Result<Cursor> {
+        let position = ScanPosition::default();
+        let stream = self.stream(plan, params, start_after, &position).await?;
+        Ok(Cursor { stream, position })
     }
 
-    async fn execute_filter(
+    async fn stream(
         &self,
-        input: &PhysicalPlan,
-        predicate: &crate::parser::Expr,
+        plan: &PhysicalPlan,
         params: &[serde_json::Value],
-    ) -> Result<QueryResult> {
-        let result = Box::pin(self.execute(input, params)).await?;
-
-        // Apply filter to rows
-        let filtered: Vec<_> = result
-            .rows()
-            .iter()
-            .filter(|row| self.evaluate_predicate(predicate, row))
-            .cloned()
-            .collect();
-
-        Ok(QueryResult::new(filtered))
+        start_after: Option<&str>,
+        position: &ScanPosition,
+    ) -> Result<BoxedStream> {
+        let functions = &self.functions;
+        Ok(match plan {
+            PhysicalPlan::Scan {
+                table,
+                alias,
+                columns,
+                ..
+            } => {
+                let docs: Documents = match &self.txn {
+                    Some(txn) => Box::new(txn.scan(table)?),
+                    None => Box::new(self.storage.document_scan(table, None, start_after)?),
+                };
+                Box::new(ScanStream::new(docs, alias, columns, position.clone()))
+            }
+            PhysicalPlan::IndexScan {
+                table,
+                alias,
+                index,
+                range,
+                ..
+            } => {
+                // Indexes list the newest documents, so a transaction scans
+                // its snapshot; the filter above repeats the index conditions
+                let docs: Documents = match &self.txn {
+                    Some(txn) => Box::new(txn.scan(table)?),
+                    None => Box::new(self.storage.index_scan(table, index, range)?),
+                };
+                let columns = ["*".to_string()];
+                Box::new(ScanStream::new(docs, alias, &columns, position.clone()))
+            }
+            PhysicalPlan::SingleRow => Box::new(RowsStream::new(vec![HashMap::new()], None)),
+            PhysicalPlan::HashJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                residual,
+                ..
+            } => {
+                let left = Box::pin(self.stream(left, params, start_after, position)).await?;
+                let right = Box::pin(self.stream(right, params, start_after, position)).await?;
+                let residual = match residual {
+                    Some(residual) => Some(self.bind_subqueries(residual, params).await?),
+                    None => None,
+                };
+                Box::new(JoinStream::new(
+                    left,
+                    right,
+                    *kind,
+                    left_keys.clone(),
+                    right_keys.clone(),
+                    residual,
+                    functions.clone(),
+                ))
+            }
+            PhysicalPlan::NestedLoopJoin {
+                left,
+                right,
+                kind,
+                condition,
+                ..
+            } => {
+                let left = Box::pin(self.stream(left, params, start_after, position)).await?;
+                let right = Box::pin(self.stream(right, params, start_after, position)).await?;
+                let condition = match condition {
+                    Some(condition) => Some(self.bind_subqueries(condition, params).await?),
+                    None => None,
+                };
+                Box::new(JoinStream::new(
+                    left,
+                    right,
+                    *kind,
+                    Vec::new(),
+                    Vec::new(),
+                    condition,
+                    functions.clone(),
+                ))
+            }
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::SubqueryAlias { input, .. }
+            | PhysicalPlan::Aggregate { input, .. }
+            | PhysicalPlan::Project { input, .. }
+            | PhysicalPlan::Distinct { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::Limit { input, .. } => {
+                let input = Box::pin(self.stream(input, params, start_after, position)).await?;
+                self.unary_stream(plan, input, params).await?
+            }
+            _ => {
+                let result = Box::pin(self.execute(plan, params)).await?;
+                let columns = result.columns().to_vec();
+                Box::new(RowsStream::new(result.into_rows(), Some(columns)))
+            }
+        })
     }
 
-    async fn execute_sort(
+    /// Apply a single-input operator to the stream of its input
+    async fn unary_stream(
         &self,
-        input: &PhysicalPlan,
-        order_by: &[(String, bool)],
+        plan: &PhysicalPlan,
+        input: BoxedStream,
         params: &[serde_json::Value],
-    ) -> Result<QueryResult> {
-        let result = Box::pin(self.execute(input, params)).await?;
-        let mut rows: Vec<_> = result.rows().to_vec();
-
-        // Sort rows
-        rows.sort_by(|a, b| {
-            for (column, asc) in order_by {
-                let va = a.get(column);
-                let vb = b.get(column);
-
-                let cmp = match (va, vb) {
-                    (Some(va), Some(vb)) => {
-                        let ord = va.to_string().cmp(&vb.to_string());
-                        if *asc { ord } else { ord.reverse() }
-                    }
-                    _ => std::cmp::Ordering::Equal,
-                };
-
-                if cmp != std::cmp::Ordering::Equal {
-                    return cmp;
+    ) -> Result<BoxedStream> {
+        let functions = self.functions.clone();
+        Ok(match plan {
+            PhysicalPlan::Filter { predicate, .. } => {
+                let predicate = self.bind_subqueries(predicate, params).await?;
+                Box::new(FilterStream::new(input, predicate, functions))
+            }
+            PhysicalPlan::SubqueryAlias { alias, .. } => Box::new(AliasStream::new(input, alias)),
+            PhysicalPlan::Aggregate {
+                group_by,
+                aggregates,
+                ..
+            } => Box::new(AggregateStream::new(
+                input,
+                group_by.clone(),
+                aggregates.clone(),
+                functions,
+            )),
+            PhysicalPlan::Project { items, .. } => {
+                let mut bound = Vec::with_capacity(items.len());
+                for item in items {
+                    bound.push(match item {
+                        ProjectItem::Expr { expr, name } => ProjectItem::Expr {
+                            expr: self.bind_subqueries(expr, params).await?,
+                            name: name.clone(),
+                        },
+                        ProjectItem::Wildcard(_) => item.clone(),
+                    });
                 }
+                Box::new(ProjectStream::new(input, bound, functions))
             }
-            std::cmp::Ordering::Equal
-        });
-
-        Ok(QueryResult::new(rows))
+            PhysicalPlan::Distinct { .. } => Box::new(DistinctStream::new(input)),
+            PhysicalPlan::Sort { order_by, .. } => Box::new(SortStream::new(
+                input,
+                order_by.clone(),
+                functions,
+                self.sort_memory,
+            )),
+            PhysicalPlan::Limit { limit, offset, .. } => {
+                Box::new(LimitStream::new(input, *limit, *offset))
+            }
+            _ => {
+                return Err(Error::Query(QueryError::ExecutionError(
+                    "plan is not a single-input operator".to_string(),
+                )))
+            }
+        })
     }
 
-    async fn execute_limit(
+    /// Run the uncorrelated subqueries of an expression and substitute
+    /// their results
+    async fn bind_subqueries(
         &self,
-        input: &PhysicalPlan,
-        limit: usize,
+        expr: &Expr,
         params: &[serde_json::Value],
-    ) -> Result<QueryResult> {
-        let result = Box::pin(self.execute(input, params)).await?;
-        let rows: Vec<_> = result.rows().iter().take(limit).cloned().collect();
-        Ok(QueryResult::new(rows))
+    ) -> Result<Expr> {
+        let mut subqueries: Vec<&Select> = Vec::new();
+        expr.visit(&mut |e| {
+            match e {
+                Expr::InSubquery { subquery, .. } | Expr::Exists { subquery, .. } => {
+                    subqueries.push(subquery);
+                }
+                Expr::Subquery(subquery) => subqueries.push(subquery),
+                _ => {}
+            }
+            true
+        });
+        if subqueries.is_empty() {
+            return Ok(expr.clone());
+        }
+
+        let mut results = Vec::with_capacity(subqueries.len());
+        for subquery in subqueries {
+            let plan = self.planner.plan_select(subquery)?;
+            results.push(Box::pin(self.execute(&plan, params)).await?);
+        }
+
+        // Replace the subqueries in the order they were visited
+        let mut results = results.into_iter();
+        let single_column = |result: QueryResult| -> Result<Vec<serde_json::Value>> {
+            let [column] = result.columns() else {
+                return Err(Error::Query(QueryError::SemanticError(
+                    "subquery must return exactly one column".to_string(),
+                )));
+            };
+            let column = column.clone();
+            Ok(result
+                .into_rows()
+                .into_iter()
+                .map(|mut row| row.remove(&column).unwrap_or(serde_json::Value::Null))
+                .collect())
+        };
+        expr.try_transform(&mut |e| {
+            Ok(match e {
+                Expr::InSubquery { expr, negated, .. } => {
+                    let values = results
+                        .next()
+                        .map(single_column)
+                        .transpose()?
+                        .unwrap_or_default();
+                    Some(Expr::InList {
+                        expr: expr.clone(),
+                        list: values.into_iter().map(Expr::Literal).collect(),
+                        negated: *negated,
+                    })
+                }
+                Expr::Exists { negated, .. } => {
+                    let found = results.next().is_some_and(|r| !r.rows().is_empty());
+                    Some(Expr::Literal(serde_json::Value::Bool(found != *negated)))
+                }
+                Expr::Subquery(_) => {
+                    let values = results
+                        .next()
+                        .map(single_column)
+                        .transpose()?
+                        .unwrap_or_default();
+                    if values.len() > 1 {
+                        return Err(Error::Query(QueryError::ExecutionError(
+                            "more than one row returned by a subquery used as an expression"
+                                .to_string(),
+                        )));
+                    }
+                    let value = values.into_iter().next().unwrap_or(serde_json::Value::Null);
+                    Some(Expr::Literal(value))
+                }
+                _ => None,
+            })
+        })
     }
 
     async fn execute_insert(
@@ -171,8 +499,8 @@ impl Executor {
                 }
             }
 
-            let doc = lumadb_common::types::Document::new(serde_json::Value::Object(data));
-            self.storage.insert_document(table, &doc).await?;
+            let doc = Document::new(serde_json::Value::Object(data));
+            self.put_document(table, doc).await?;
         }
 
         let mut result = HashMap::new();
@@ -190,7 +518,7 @@ impl Executor {
         set: &HashMap<String, serde_json::Value>,
         filter: &Option<crate::parser::Expr>,
     ) -> Result<QueryResult> {
-        let docs = self.storage.scan_documents(table, None, None).await?;
+        let docs = self.documents(table)?;
         let mut updated = 0;
 
         for doc in docs {
@@ -206,7 +534,7 @@ impl Executor {
             };
 
             if let Some(pred) = filter {
-                if !self.evaluate_predicate(pred, &row) {
+                if !self.evaluator().matches(pred, &row)? {
                     continue;
                 }
             }
@@ -219,8 +547,8 @@ impl Executor {
                 }
             }
 
-            let updated_doc = lumadb_common::types::Document::with_id(&doc.id, new_data);
-            self.storage.insert_document(table, &updated_doc).await?;
+            let updated_doc = Document::with_id(&doc.id, new_data);
+            self.put_document(table, updated_doc).await?;
             updated += 1;
         }
 
@@ -238,7 +566,7 @@ impl Executor {
         table: &str,
         filter: &Option<crate::parser::Expr>,
     ) -> Result<QueryResult> {
-        let docs = self.storage.scan_documents(table, None, None).await?;
+        let docs = self.documents(table)?;
         let mut deleted = 0;
 
         for doc in docs {
@@ -253,12 +581,12 @@ impl Executor {
             };
 
             if let Some(pred) = filter {
-                if !self.evaluate_predicate(pred, &row) {
+                if !self.evaluator().matches(pred, &row)? {
                     continue;
                 }
             }
 
-            self.storage.delete_document(table, &doc.id).await?;
+            self.remove_document(table, &doc.id).await?;
             deleted += 1;
         }
 
@@ -293,19 +621,123 @@ impl Executor {
         Ok(QueryResult::new(vec![result]))
     }
 
+    async fn execute_create_index(
+        &self,
+        name: &str,
+        table: &str,
+        fields: &[String],
+        unique: bool,
+        filter: &[IndexCondition],
+    ) -> Result<QueryResult> {
+        let metadata = IndexMetadata {
+            name: name.to_string(),
+            index_type: IndexType::BTree,
+            fields: fields.to_vec(),
+            unique,
+            filter: filter.to_vec(),
+            building: true,
+            entries: 0,
+            distinct_keys: 0,
+        };
+        self.storage.create_index(table, metadata).await?;
+
+        let mut result = HashMap::new();
+        result.insert(
+            "created".to_string(),
+            serde_json::Value::String(name.to_string()),
+        );
+
+        Ok(QueryResult::new(vec![result]))
+    }
+
+    async fn execute_drop_index(&self, name: &str, table: Option<&str>) -> Result<QueryResult> {
+        let table = if let Some(table) = table {
+            table.to_string()
+        } else {
+            let owners: Vec<String> = self
+                .storage
+                .list_collections()
+                .await?
+                .into_iter()
+                .filter(|c| c.indexes.iter().any(|i| i.name == name))
+                .map(|c| c.name)
+                .collect();
+            match <[String; 1]>::try_from(owners) {
+                Ok([table]) => table,
+                Err(owners) if owners.is_empty() => {
+                    return Err(Error::Query(QueryError::ExecutionError(format!(
+                        "Index {name} does not exist"
+                    ))))
+                }
+                Err(_) => {
+                    return Err(Error::Query(QueryError::ExecutionError(format!(
+                        "Index {name} exists on several tables; qualify it as table.{name}"
+                    ))))
+                }
+            }
+        };
+        self.storage.drop_index(&table, name).await?;
+
+        let mut result = HashMap::new();
+        result.insert(
+            "dropped".to_string(),
+            serde_json::Value::String(name.to_string()),
+        );
+
+        Ok(QueryResult::new(vec![result]))
+    }
+
     async fn execute_topic_list(&self) -> Result<QueryResult> {
         // This would integrate with the streaming engine
         Ok(QueryResult::new(vec![]))
     }
 
+    /// Run a vector search, hybrid if it has text to match
+    ///
+    /// Rows of a hybrid search hold the fused score and, for each ranking,
+    /// the document's rank and score there (null if it is not ranked).
     async fn execute_vector_search(
         &self,
         collection: &str,
         vector: &[f32],
         k: usize,
+        text: Option<&TextMatch>,
     ) -> Result<QueryResult> {
-        let index = self.storage.get_or_create_vector_index(collection, vector.len());
-        let results = index.search(vector, k);
+        if let Some(text) = text {
+            let mut query = HybridQuery::new(k)
+                .with_text(text.text.clone())
+                .with_fusion(text.fusion)
+                .with_weights(text.vector_weight, text.text_weight);
+            if !vector.is_empty() {
+                query = query.with_vector(vector.to_vec());
+            }
+            let rows = self
+                .storage
+                .hybrid_search(collection, &query)
+                .await?
+                .into_iter()
+                .map(|hit| {
+                    let mut row = HashMap::new();
+                    row.insert("id".to_string(), serde_json::Value::String(hit.doc_id));
+                    row.insert("score".to_string(), serde_json::json!(hit.score));
+                    for (name, component) in [("vector", hit.vector), ("text", hit.text)] {
+                        let (rank, score) = component
+                            .map_or((serde_json::Value::Null, serde_json::Value::Null), |c| {
+                                (serde_json::json!(c.rank), serde_json::json!(c.score))
+                            });
+                        row.insert(format!("{name}_rank"), rank);
+                        row.insert(format!("{name}_score"), score);
+                    }
+                    row
+                })
+                .collect();
+            return Ok(QueryResult::new(rows));
+        }
+
+        let results = match self.storage.vector_index(collection) {
+            Some(index) => index.search(vector, k),
+            None => Vec::new(),
+        };
 
         let rows: Vec<HashMap<String, serde_json::Value>> = results
             .into_iter()
@@ -319,91 +751,4 @@ impl Executor {
 
         Ok(QueryResult::new(rows))
     }
-
-    fn evaluate_predicate(
-        &self,
-        predicate: &crate::parser::Expr,
-        row: &HashMap<String, serde_json::Value>,
-    ) -> bool {
-        use crate::parser::{Expr, BinaryOperator};
-
-        match predicate {
-            Expr::Column(name) => {
-                row.get(name)
-                    .map(|v| !v.is_null())
-                    .unwrap_or(false)
-            }
-            Expr::Literal(value) => {
-                !value.is_null()
-            }
-            Expr::BinaryOp { left, op, right } => {
-                let left_val = self.evaluate_expr(left, row);
-                let right_val = self.evaluate_expr(right, row);
-
-                match op {
-                    BinaryOperator::Eq => left_val == right_val,
-                    BinaryOperator::Ne => left_val != right_val,
-                    BinaryOperator::Lt => {
-                        self.compare_values(&left_val, &right_val) == std::cmp::Ordering::Less
-                    }
-                    BinaryOperator::Le => {
-                        matches!(
-                            self.compare_values(&left_val, &right_val),
-                            std::cmp::Ordering::Less | std::cmp::Ordering::Equal
-                        )
-                    }
-                    BinaryOperator::Gt => {
-                        self.compare_values(&left_val, &right_val) == std::cmp::Ordering::Greater
-                    }
-                    BinaryOperator::Ge => {
-                        matches!(
-                            self.compare_values(&left_val, &right_val),
-                            std::cmp::Ordering::Greater | std::cmp::Ordering::Equal
-                        )
-                    }
-                    BinaryOperator::And => {
-                        self.evaluate_predicate(left, row) && self.evaluate_predicate(right, row)
-                    }
-                    BinaryOperator::Or => {
-                        self.evaluate_predicate(left, row) || self.evaluate_predicate(right, row)
-                    }
-                    _ => true,
-                }
-            }
-            Expr::Function { name, args } => {
-                // Handle function evaluation
-                true
-            }
-        }
-    }
-
-    fn evaluate_expr(
-        &self,
-        expr: &crate::parser::Expr,
-        row: &HashMap<String, serde_json::Value>,
-    ) -> serde_json::Value {
-        use crate::parser::Expr;
-
-        match expr {
-            Expr::Column(name) => row.get(name).cloned().unwrap_or(serde_json::Value::Null),
-            Expr::Literal(value) => value.clone(),
-            _ => serde_json::Value::Null,
-        }
-    }
-
-    fn compare_values(
-        &self,
-        a: &serde_json::Value,
-        b: &serde_json::Value,
-    ) -> std::cmp::Ordering {
-        match (a, b) {
-            (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
-                let a = a.as_f64().unwrap_or(0.0);
-                let b = b.as_f64().unwrap_or(0.0);
-                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
-            }
-            (serde_json::Value::String(a), serde_json::Value::String(b)) => a.cmp(b),
-            _ => std::cmp::Ordering::Equal,
-        }
-    }
 }
@@ -0,0 +1,245 @@
+//! Sorting with spilling to disk
+//!
+//! Rows are buffered until their estimated size passes the memory budget.
+//! The buffer is then sorted and written to a temporary file as a run, and
+//! the runs are merged once the input is exhausted. Inputs that fit the
+//! budget are sorted in memory without touching disk.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use serde_json::Value;
+use tracing::debug;
+
+use lumadb_common::error::Result;
+
+use super::eval::{compare_values, Evaluator, Row};
+use super::stream::{BoxedStream, RowStream};
+use crate::functions::FunctionRegistry;
+use crate::parser::Expr;
+
+/// A row with its evaluated sort keys
+type Keyed = (Vec<Value>, Row);
+
+type RunReader =
+    serde_json::StreamDeserializer<'static, serde_json::de::IoRead<BufReader<File>>, Keyed>;
+
+/// Rough heap footprint of a value
+fn estimated_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => 24 + s.len(),
+        Value::Array(values) => 24 + values.iter().map(estimated_size).sum::<usize>(),
+        Value::Object(map) => {
+            24 + map
+                .iter()
+                .map(|(k, v)| 24 + k.len() + estimated_size(v))
+                .sum::<usize>()
+        }
+        _ => 16,
+    }
+}
+
+fn row_size((keys, row): &Keyed) -> usize {
+    keys.iter().map(estimated_size).sum::<usize>()
+        + row
+            .iter()
+            .map(|(k, v)| 48 + k.len() + estimated_size(v))
+            .sum::<usize>()
+}
+
+/// Order rows by their keys; NULLs come last ascending and first descending
+fn compare(a: &[Value], b: &[Value], order_by: &[(Expr, bool)]) -> Ordering {
+    for ((va, vb), (_, asc)) in a.iter().zip(b).zip(order_by) {
+        let ord = compare_values(va, vb);
+        let cmp = if *asc { ord } else { ord.reverse() };
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+    Ordering::Equal
+}
+
+/// A sorted run written to disk
+struct Run {
+    reader: RunReader,
+    head: Option<Keyed>,
+}
+
+impl Run {
+    fn write(rows: Vec<Keyed>) -> Result<Self> {
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        for row in rows {
+            serde_json::to_writer(&mut file, &row)?;
+            file.write_all(b"\n")?;
+        }
+        let mut file = file
+            .into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut run = Self {
+            reader: serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter(),
+            head: None,
+        };
+        run.advance()?;
+        Ok(run)
+    }
+
+    fn advance(&mut self) -> Result<()> {
+        self.head = self.reader.next().transpose()?;
+        Ok(())
+    }
+}
+
+enum Sorted {
+    Memory(std::vec::IntoIter<Keyed>),
+    Merge(Vec<Run>),
+}
+
+/// Sorts its whole input on the first pull
+pub(super) struct SortStream {
+    input: Option<BoxedStream>,
+    order_by: Vec<(Expr, bool)>,
+    functions: Arc<FunctionRegistry>,
+    memory_budget: usize,
+    sorted: Sorted,
+    columns: Option<Vec<String>>,
+}
+
+impl SortStream {
+    pub(super) fn new(
+        input: BoxedStream,
+        order_by: Vec<(Expr, bool)>,
+        functions: Arc<FunctionRegistry>,
+        memory_budget: usize,
+    ) -> Self {
+        Self {
+            input: Some(input),
+            order_by,
+            functions,
+            memory_budget,
+            sorted: Sorted::Memory(Vec::new().into_iter()),
+            columns: None,
+        }
+    }
+
+    fn sort(&self, input: &mut BoxedStream) -> Result<Sorted> {
+        let evaluator = Evaluator {
+            functions: &self.functions,
+        };
+        let sort_run = |rows: &mut Vec<Keyed>| {
+            // Stable, so rows with equal keys keep their input order
+            rows.sort_by(|(a, _), (b, _)| compare(a, b, &self.order_by));
+        };
+
+        let mut runs = Vec::new();
+        let mut buffer = Vec::new();
+        let mut buffered = 0;
+        while let Some(row) = input.next_row()? {
+            let keys = self
+                .order_by
+                .iter()
+                .map(|(expr, _)| evaluator.evaluate(expr, &row))
+                .collect::<Result<Vec<_>>>()?;
+            let keyed = (keys, row);
+            buffered += row_size(&keyed);
+            buffer.push(keyed);
+
+            if buffered > self.memory_budget {
+                sort_run(&mut buffer);
+                debug!("Spilling sorted run of {} rows", buffer.len());
+                runs.push(Run::write(std::mem::take(&mut buffer))?);
+                buffered = 0;
+            }
+        }
+
+        sort_run(&mut buffer);
+        if runs.is_empty() {
+            return Ok(Sorted::Memory(buffer.into_iter()));
+        }
+        if !buffer.is_empty() {
+            runs.push(Run::write(buffer)?);
+        }
+        Ok(Sorted::Merge(runs))
+    }
+}
+
+impl RowStream for SortStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        if let Some(mut input) = self.input.take() {
+            self.sorted = self.sort(&mut input)?;
+            // Known once the input was read, for wildcards expanded from it
+            self.columns = input.columns();
+        }
+
+        match &mut self.sorted {
+            Sorted::Memory(rows) => Ok(rows.next().map(|(_, row)| row)),
+            Sorted::Merge(runs) => {
+                // Ties go to the earlier run, which holds earlier input rows
+                let mut next: Option<usize> = None;
+                for (i, run) in runs.iter().enumerate() {
+                    let Some((keys, _)) = &run.head else {
+                        continue;
+                    };
+                    let smaller = next.map_or(true, |j| {
+                        let (best, _) = runs[j].head.as_ref().expect("candidate run has a head");
+                        compare(keys, best, &self.order_by) == Ordering::Less
+                    });
+                    if smaller {
+                        next = Some(i);
+                    }
+                }
+
+                let Some(i) = next else {
+                    return Ok(None);
+                };
+                let (_, row) = runs[i].head.take().expect("chosen run has a head");
+                runs[i].advance()?;
+                Ok(Some(row))
+            }
+        }
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        match &self.input {
+            Some(input) => input.columns(),
+            None => self.columns.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::stream::RowsStream;
+    use serde_json::json;
+
+    #[test]
+    fn spills_runs_and_merges_them_in_order() {
+        let rows: Vec<Row> = (0..500)
+            .map(|i| {
+                let mut row = Row::new();
+                row.insert("n".to_string(), json!((i * 7919) % 500));
+                row.insert("i".to_string(), json!(i));
+                row
+            })
+            .collect();
+        let order_by = vec![(Expr::Column("n".to_string()), false)];
+
+        // A budget this small writes a run every few rows
+        let mut stream = SortStream::new(
+            Box::new(RowsStream::new(rows, None)),
+            order_by,
+            Arc::new(FunctionRegistry::new()),
+            1024,
+        );
+        let mut sorted = Vec::new();
+        while let Some(row) = stream.next_row().unwrap() {
+            sorted.push(row["n"].as_i64().unwrap());
+        }
+        assert!(matches!(&stream.sorted, Sorted::Merge(runs) if runs.len() > 10));
+        assert_eq!(sorted, (0..500).rev().collect::<Vec<_>>());
+    }
+}
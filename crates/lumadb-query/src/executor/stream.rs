@@ -0,0 +1,367 @@
+//! Pull-based row streams
+//!
+//! Every operator pulls rows from its input one at a time, so a plan only
+//! reads as much of its tables as its consumer asks for. Joins, sorts and
+//! aggregates consume their inputs before producing rows; the other
+//! operators stream.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use lumadb_common::error::Result;
+use lumadb_common::types::Document;
+
+use super::eval::{hash_key, Evaluator, Row};
+use crate::functions::FunctionRegistry;
+use crate::optimizer::ProjectItem;
+use crate::parser::Expr;
+
+/// Source of rows pulled by the operator above it
+pub(crate) trait RowStream: Send {
+    /// Pull the next row, or `None` once the stream is exhausted
+    fn next_row(&mut self) -> Result<Option<Row>>;
+
+    /// Output column names in order, for streams that define them
+    fn columns(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+pub(crate) type BoxedStream = Box<dyn RowStream>;
+
+/// ID of the last document a scan produced, shared with the cursor
+/// reading the plan
+pub(crate) type ScanPosition = Arc<Mutex<Option<String>>>;
+
+/// Rows that were produced up front
+pub(super) struct RowsStream {
+    rows: std::vec::IntoIter<Row>,
+    columns: Option<Vec<String>>,
+}
+
+impl RowsStream {
+    pub(super) fn new(rows: Vec<Row>, columns: Option<Vec<String>>) -> Self {
+        Self {
+            rows: rows.into_iter(),
+            columns,
+        }
+    }
+}
+
+impl RowStream for RowsStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        Ok(self.rows.next())
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        self.columns.clone()
+    }
+}
+
+/// Documents read by a scan, from the table or through an index
+pub(super) type Documents = Box<dyn Iterator<Item = Result<Document>> + Send>;
+
+/// Reads a table, qualifying its columns with an alias
+pub(super) struct ScanStream {
+    docs: Documents,
+    alias: String,
+    /// Columns to read, or `None` for all of them
+    columns: Option<HashSet<String>>,
+    position: ScanPosition,
+}
+
+impl ScanStream {
+    pub(super) fn new(
+        docs: Documents,
+        alias: &str,
+        columns: &[String],
+        position: ScanPosition,
+    ) -> Self {
+        let columns =
+            (!columns.iter().any(|c| c == "*")).then(|| columns.iter().cloned().collect());
+        Self {
+            docs,
+            alias: alias.to_string(),
+            columns,
+            position,
+        }
+    }
+}
+
+impl RowStream for ScanStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        let Some(doc) = self.docs.next().transpose()? else {
+            return Ok(None);
+        };
+
+        let alias = &self.alias;
+        let mut row = HashMap::new();
+        row.insert(format!("{alias}._id"), Value::String(doc.id.clone()));
+        if let Value::Object(map) = doc.data {
+            for (k, v) in map {
+                if self.columns.as_ref().map_or(true, |c| c.contains(&k)) {
+                    row.insert(format!("{alias}.{k}"), v);
+                }
+            }
+        }
+        *self.position.lock() = Some(doc.id);
+
+        Ok(Some(row))
+    }
+}
+
+/// Keeps the rows satisfying a predicate
+pub(super) struct FilterStream {
+    input: BoxedStream,
+    predicate: Expr,
+    functions: Arc<FunctionRegistry>,
+}
+
+impl FilterStream {
+    pub(super) fn new(
+        input: BoxedStream,
+        predicate: Expr,
+        functions: Arc<FunctionRegistry>,
+    ) -> Self {
+        Self {
+            input,
+            predicate,
+            functions,
+        }
+    }
+}
+
+impl RowStream for FilterStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        let evaluator = Evaluator {
+            functions: &self.functions,
+        };
+        while let Some(row) = self.input.next_row()? {
+            if evaluator.matches(&self.predicate, &row)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        self.input.columns()
+    }
+}
+
+/// Qualifies the columns of a derived table with its alias
+pub(super) struct AliasStream {
+    input: BoxedStream,
+    alias: String,
+}
+
+impl AliasStream {
+    pub(super) fn new(input: BoxedStream, alias: &str) -> Self {
+        Self {
+            input,
+            alias: alias.to_string(),
+        }
+    }
+}
+
+impl RowStream for AliasStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        let alias = &self.alias;
+        Ok(self.input.next_row()?.map(|row| {
+            row.into_iter()
+                .map(|(k, v)| (format!("{alias}.{k}"), v))
+                .collect()
+        }))
+    }
+}
+
+/// Computes the output columns of a query
+///
+/// Wildcards expand to the input columns in name order, under their
+/// unqualified names. A column whose unqualified name an earlier column
+/// already took keeps its qualified name. Since rows are schemaless, the
+/// columns a wildcard expands to grow as new ones are met.
+pub(super) struct ProjectStream {
+    input: BoxedStream,
+    items: Vec<ProjectItem>,
+    functions: Arc<FunctionRegistry>,
+    /// Input key and output name of each column a wildcard item expanded to
+    expanded: Vec<Vec<(String, String)>>,
+    names: HashSet<String>,
+}
+
+impl ProjectStream {
+    pub(super) fn new(
+        input: BoxedStream,
+        items: Vec<ProjectItem>,
+        functions: Arc<FunctionRegistry>,
+    ) -> Self {
+        let names = items
+            .iter()
+            .filter_map(|item| match item {
+                ProjectItem::Expr { name, .. } => Some(name.clone()),
+                ProjectItem::Wildcard(_) => None,
+            })
+            .collect();
+        Self {
+            expanded: vec![Vec::new(); items.len()],
+            input,
+            items,
+            functions,
+            names,
+        }
+    }
+
+    /// Expand the wildcards over any columns of `row` not seen before
+    fn expand(&mut self, row: &Row) {
+        let keys: BTreeSet<&String> = row.keys().collect();
+        for (item, expanded) in self.items.iter().zip(&mut self.expanded) {
+            let ProjectItem::Wildcard(qualifier) = item else {
+                continue;
+            };
+            for key in &keys {
+                let matches = qualifier.as_ref().map_or(true, |q| {
+                    key.strip_prefix(q.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+                });
+                if !matches || expanded.iter().any(|(k, _)| k == *key) {
+                    continue;
+                }
+                let unqualified = key.rsplit_once('.').map_or(key.as_str(), |(_, name)| name);
+                let name = if self.names.contains(unqualified) {
+                    (*key).clone()
+                } else {
+                    unqualified.to_string()
+                };
+                self.names.insert(name.clone());
+                expanded.push(((*key).clone(), name));
+            }
+        }
+    }
+}
+
+impl RowStream for ProjectStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        let Some(row) = self.input.next_row()? else {
+            return Ok(None);
+        };
+        if self
+            .items
+            .iter()
+            .any(|item| matches!(item, ProjectItem::Wildcard(_)))
+        {
+            self.expand(&row);
+        }
+
+        let evaluator = Evaluator {
+            functions: &self.functions,
+        };
+        let mut out = HashMap::new();
+        for (item, expanded) in self.items.iter().zip(&self.expanded) {
+            match item {
+                ProjectItem::Wildcard(_) => {
+                    for (key, name) in expanded {
+                        out.insert(name.clone(), row.get(key).cloned().unwrap_or(Value::Null));
+                    }
+                }
+                ProjectItem::Expr { expr, name } => {
+                    out.insert(name.clone(), evaluator.evaluate(expr, &row)?);
+                }
+            }
+        }
+        Ok(Some(out))
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        let mut columns = Vec::new();
+        for (item, expanded) in self.items.iter().zip(&self.expanded) {
+            match item {
+                ProjectItem::Wildcard(_) => {
+                    columns.extend(expanded.iter().map(|(_, name)| name.clone()));
+                }
+                ProjectItem::Expr { name, .. } => columns.push(name.clone()),
+            }
+        }
+        Some(columns)
+    }
+}
+
+/// Drops rows equal to an earlier one
+pub(super) struct DistinctStream {
+    input: BoxedStream,
+    seen: HashSet<String>,
+}
+
+impl DistinctStream {
+    pub(super) fn new(input: BoxedStream) -> Self {
+        Self {
+            input,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl RowStream for DistinctStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        while let Some(row) = self.input.next_row()? {
+            let sorted: BTreeMap<&String, &Value> = row.iter().collect();
+            let values: Vec<Value> = sorted
+                .into_iter()
+                .flat_map(|(k, v)| [Value::String(k.clone()), v.clone()])
+                .collect();
+            if self.seen.insert(hash_key(&values)) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        self.input.columns()
+    }
+}
+
+/// Skips `offset` rows, then passes at most `limit` rows
+///
+/// Stops pulling from its input once the limit is reached.
+pub(super) struct LimitStream {
+    input: BoxedStream,
+    skip: usize,
+    remaining: Option<usize>,
+}
+
+impl LimitStream {
+    pub(super) fn new(input: BoxedStream, limit: Option<usize>, offset: usize) -> Self {
+        Self {
+            input,
+            skip: offset,
+            remaining: limit,
+        }
+    }
+}
+
+impl RowStream for LimitStream {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        while self.skip > 0 {
+            if self.input.next_row()?.is_none() {
+                return Ok(None);
+            }
+            self.skip -= 1;
+        }
+        match &mut self.remaining {
+            Some(0) => Ok(None),
+            Some(remaining) => {
+                *remaining -= 1;
+                self.input.next_row()
+            }
+            None => self.input.next_row(),
+        }
+    }
+
+    fn columns(&self) -> Option<Vec<String>> {
+        self.input.columns()
+    }
+}
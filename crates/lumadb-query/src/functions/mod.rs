@@ -5,6 +5,8 @@ use std::sync::Arc;
 
 use lumadb_common::error::{Result, Error, QueryError};
 
+use crate::executor::compare_values;
+
 /// Function registry
 pub struct FunctionRegistry {
     /// Scalar functions
@@ -342,25 +344,38 @@ impl AggregateFunction for MinFunction {
     }
 }
 
+/// Keeps the smallest non-null value in the order of [`compare_values`]
 struct MinAccumulator {
-    min: Option<f64>,
+    min: Option<serde_json::Value>,
+}
+
+impl MinAccumulator {
+    fn offer(&mut self, value: &serde_json::Value) {
+        if value.is_null() {
+            return;
+        }
+        let better = self.min.as_ref().map_or(true, |current| {
+            compare_values(value, current) == std::cmp::Ordering::Less
+        });
+        if better {
+            self.min = Some(value.clone());
+        }
+    }
 }
 
 impl Accumulator for MinAccumulator {
     fn accumulate(&mut self, value: &serde_json::Value) {
-        if let Some(n) = value.as_f64() {
-            self.min = Some(self.min.map_or(n, |m| m.min(n)));
-        }
+        self.offer(value);
     }
 
     fn finalize(&self) -> serde_json::Value {
-        self.min.map_or(serde_json::Value::Null, |m| serde_json::json!(m))
+        self.min.clone().unwrap_or(serde_json::Value::Null)
     }
 
     fn merge(&mut self, other: &dyn Accumulator) {
         if let Some(min_acc) = other.as_any().downcast_ref::<MinAccumulator>() {
-            if let Some(other_min) = min_acc.min {
-                self.min = Some(self.min.map_or(other_min, |m| m.min(other_min)));
+            if let Some(other_min) = &min_acc.min {
+                self.offer(other_min);
             }
         }
     }
@@ -382,25 +397,38 @@ impl AggregateFunction for MaxFunction {
     }
 }
 
+/// Keeps the largest non-null value in the order of [`compare_values`]
 struct MaxAccumulator {
-    max: Option<f64>,
+    max: Option<serde_json::Value>,
+}
+
+impl MaxAccumulator {
+    fn offer(&mut self, value: &serde_json::Value) {
+        if value.is_null() {
+            return;
+        }
+        let better = self.max.as_ref().map_or(true, |current| {
+            compare_values(value, current) == std::cmp::Ordering::Greater
+        });
+        if better {
+            self.max = Some(value.clone());
+        }
+    }
 }
 
 impl Accumulator for MaxAccumulator {
     fn accumulate(&mut self, value: &serde_json::Value) {
-        if let Some(n) = value.as_f64() {
-            self.max = Some(self.max.map_or(n, |m| m.max(n)));
-        }
+        self.offer(value);
     }
 
     fn finalize(&self) -> serde_json::Value {
-        self.max.map_or(serde_json::Value::Null, |m| serde_json::json!(m))
+        self.max.clone().unwrap_or(serde_json::Value::Null)
     }
 
     fn merge(&mut self, other: &dyn Accumulator) {
         if let Some(max_acc) = other.as_any().downcast_ref::<MaxAccumulator>() {
-            if let Some(other_max) = max_acc.max {
-                self.max = Some(self.max.map_or(other_max, |m| m.max(other_max)));
+            if let Some(other_max) = &max_acc.max {
+                self.offer(other_max);
             }
         }
     }
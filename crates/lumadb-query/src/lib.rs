@@ -6,6 +6,7 @@
 //! - Cost-based query optimizer
 //! - Vectorized query execution
 //! - User-defined functions (UDFs)
+//! - Snapshot-isolated transactions (BEGIN / COMMIT / ROLLBACK)
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
@@ -17,8 +18,10 @@ pub mod optimizer;
 pub mod parser;
 
 mod engine;
+mod pagination;
 
 pub use engine::QueryEngine;
+pub use lumadb_txn::IsolationLevel;
 
 use serde::{Deserialize, Serialize};
 
@@ -66,11 +69,29 @@ impl QueryResult {
         }
     }
 
+    /// Create a query result with the given column order
+    #[must_use]
+    pub fn with_columns(
+        columns: Vec<String>,
+        rows: Vec<std::collections::HashMap<String, serde_json::Value>>,
+    ) -> Self {
+        Self {
+            columns,
+            ..Self::new(rows)
+        }
+    }
+
     /// Get rows
     pub fn rows(&self) -> &[std::collections::HashMap<String, serde_json::Value>] {
         &self.rows
     }
 
+    /// Take the rows
+    #[must_use]
+    pub fn into_rows(self) -> Vec<std::collections::HashMap<String, serde_json::Value>> {
+        self.rows
+    }
+
     /// Get columns
     pub fn columns(&self) -> &[String] {
         &self.columns
@@ -123,3 +144,13 @@ impl QueryResult {
         output
     }
 }
+
+/// One page of a paginated query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryPage {
+    /// Rows of this page
+    #[serde(flatten)]
+    pub result: QueryResult,
+    /// Token continuing the query after this page, absent on the last page
+    pub next_token: Option<String>,
+}
@@ -0,0 +1,169 @@
+//! Choice of secondary index scans
+//!
+//! A query on one table whose WHERE clause compares indexed fields with
+//! literals can read just the documents an index lists. Each ready index
+//! is matched against the AND-ed terms of the predicate: equalities on a
+//! leading run of its fields, then bounds on the field after them. Rows
+//! are estimated from the index's entry and distinct value counts, and the
+//! cheapest index is used when it beats scanning the whole table.
+
+use std::ops::Bound;
+
+use serde_json::Value;
+
+use lumadb_common::types::{ConditionOp, IndexCondition, IndexMetadata, IndexType};
+use lumadb_storage::index::IndexRange;
+
+use super::select::conjuncts;
+use super::{Optimizer, PhysicalPlan};
+use crate::parser::{field_condition, Expr};
+
+/// Fraction of the entries assumed to pass each bound of a range
+const RANGE_SELECTIVITY: f64 = 0.3;
+
+/// Cost of reading a document through an index, relative to reading it in
+/// a table scan
+const INDEX_LOOKUP_COST: f64 = 2.0;
+
+/// Conditions the predicate places on fields of the table read as `alias`
+fn predicate_conditions(predicate: &Expr, alias: &str) -> Vec<IndexCondition> {
+    let mut terms = Vec::new();
+    conjuncts(Some(predicate.clone()), &mut terms);
+    terms
+        .iter()
+        .filter_map(field_condition)
+        .map(|mut condition| {
+            if let Some(field) = condition
+                .field
+                .strip_prefix(alias)
+                .and_then(|rest| rest.strip_prefix('.'))
+            {
+                condition.field = field.to_string();
+            }
+            condition
+        })
+        .collect()
+}
+
+/// Range of an index the conditions select, with its estimated rows
+fn index_range(index: &IndexMetadata, conditions: &[IndexCondition]) -> Option<(IndexRange, f64)> {
+    if !matches!(index.index_type, IndexType::BTree) {
+        return None;
+    }
+    // A partial index lists only the documents meeting its conditions
+    if !index.filter.iter().all(|c| conditions.contains(c)) {
+        return None;
+    }
+
+    let mut equal = Vec::new();
+    for field in &index.fields {
+        let found = conditions
+            .iter()
+            .find(|c| c.field == *field && matches!(c.op, ConditionOp::Eq | ConditionOp::IsNull));
+        match found {
+            Some(condition) => equal.push(condition.value.clone()),
+            None => break,
+        }
+    }
+
+    let (mut lower, mut upper) = (Bound::Unbounded, Bound::Unbounded);
+    if let Some(field) = index.fields.get(equal.len()) {
+        for condition in conditions.iter().filter(|c| c.field == *field) {
+            let value = condition.value.clone();
+            match condition.op {
+                ConditionOp::Gt if lower == Bound::Unbounded => lower = Bound::Excluded(value),
+                ConditionOp::GtEq if lower == Bound::Unbounded => lower = Bound::Included(value),
+                ConditionOp::Lt if upper == Bound::Unbounded => upper = Bound::Excluded(value),
+                ConditionOp::LtEq if upper == Bound::Unbounded => upper = Bound::Included(value),
+                _ => {}
+            }
+        }
+    }
+    let bounds = i32::from(lower != Bound::Unbounded) + i32::from(upper != Bound::Unbounded);
+    if equal.is_empty() && bounds == 0 {
+        return None;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let (entries, distinct) = (index.entries as f64, index.distinct_keys.max(1) as f64);
+    #[allow(clippy::cast_precision_loss)]
+    let prefix = equal.len() as f64 / index.fields.len() as f64;
+    // Values are assumed spread evenly over the distinct keys
+    let rows = entries / distinct.powf(prefix) * RANGE_SELECTIVITY.powi(bounds);
+
+    Some((
+        IndexRange {
+            equal,
+            lower,
+            upper,
+        },
+        rows,
+    ))
+}
+
+/// Conditions of an index range, for EXPLAIN
+pub(super) fn describe_range(fields: &[String], range: &IndexRange) -> String {
+    let mut terms: Vec<String> = fields
+        .iter()
+        .zip(&range.equal)
+        .map(|(field, value)| match value {
+            Value::Null => format!("{field} IS NULL"),
+            value => format!("{field} = {value}"),
+        })
+        .collect();
+    if let Some(field) = fields.get(range.equal.len()) {
+        match &range.lower {
+            Bound::Included(value) => terms.push(format!("{field} >= {value}")),
+            Bound::Excluded(value) => terms.push(format!("{field} > {value}")),
+            Bound::Unbounded => {}
+        }
+        match &range.upper {
+            Bound::Included(value) => terms.push(format!("{field} <= {value}")),
+            Bound::Excluded(value) => terms.push(format!("{field} < {value}")),
+            Bound::Unbounded => {}
+        }
+    }
+    terms.join(" AND ")
+}
+
+impl Optimizer {
+    /// Plan reading a table through its cheapest usable index for
+    /// `predicate`, if that beats a full scan
+    pub(super) fn plan_index_scan(
+        &self,
+        table: &str,
+        alias: &str,
+        predicate: &Expr,
+    ) -> Option<PhysicalPlan> {
+        if !self.cost_based {
+            return None;
+        }
+        let statistics = self.storage.as_ref()?.collection_statistics(table).ok()??;
+        let conditions = predicate_conditions(predicate, alias);
+
+        let (index, (range, rows)) = statistics
+            .indexes
+            .iter()
+            .filter_map(|index| Some((index, index_range(index, &conditions)?)))
+            .min_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))?;
+
+        #[allow(clippy::cast_precision_loss)]
+        let scan_cost = statistics.documents as f64;
+        let cost = rows * INDEX_LOOKUP_COST + 1.0;
+        if cost >= scan_cost {
+            return None;
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let estimated_rows = rows.ceil() as u64;
+        Some(PhysicalPlan::IndexScan {
+            table: table.to_string(),
+            alias: alias.to_string(),
+            index: index.name.clone(),
+            fields: index.fields.clone(),
+            range,
+            estimated_rows,
+            estimated_cost: cost,
+        })
+    }
+}
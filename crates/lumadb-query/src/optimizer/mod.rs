@@ -1,28 +1,52 @@
 //! Cost-based query optimizer
 
+use std::sync::Arc;
+
 use lumadb_common::error::Result;
-use lumadb_common::types::QueryPlan;
+use lumadb_common::types::{IndexCondition, PlanNode, QueryPlan};
+use lumadb_storage::index::IndexRange;
+use lumadb_storage::StorageEngine;
 
 use crate::analyzer::AnalyzedQuery;
+use crate::functions::FunctionRegistry;
+use crate::parser::{Expr, JoinKind};
+
+mod index;
+mod select;
 
 /// Query optimizer
 pub struct Optimizer {
     /// Enable cost-based optimization
     cost_based: bool,
+    /// Functions, used to tell aggregates from scalar functions
+    functions: Arc<FunctionRegistry>,
+    /// Source of table statistics and indexes; without it every table is
+    /// read by a full scan
+    storage: Option<Arc<StorageEngine>>,
 }
 
 impl Optimizer {
     /// Create a new optimizer
-    pub fn new() -> Self {
-        Self { cost_based: true }
+    #[must_use]
+    pub fn new(functions: Arc<FunctionRegistry>) -> Self {
+        Self {
+            cost_based: true,
+            functions,
+            storage: None,
+        }
+    }
+
+    /// Plan with the statistics and secondary indexes of `storage`
+    #[must_use]
+    pub fn with_storage(mut self, storage: Arc<StorageEngine>) -> Self {
+        self.storage = Some(storage);
+        self
     }
 
     /// Optimize an analyzed query
     pub fn optimize(&self, query: &AnalyzedQuery) -> Result<PhysicalPlan> {
         match query {
-            AnalyzedQuery::Select { columns, from, filter, order_by, limit } => {
-                self.optimize_select(columns, from, filter, order_by, limit)
-            }
+            AnalyzedQuery::Select(select) => self.plan_select(select),
             AnalyzedQuery::Insert { table, columns, values } => {
                 Ok(PhysicalPlan::Insert {
                     table: table.clone(),
@@ -55,6 +79,21 @@ impl Optimizer {
             AnalyzedQuery::DropTable { name } => {
                 Ok(PhysicalPlan::DropTable { name: name.clone() })
             }
+            AnalyzedQuery::CreateIndex { name, table, fields, unique, filter } => {
+                Ok(PhysicalPlan::CreateIndex {
+                    name: name.clone(),
+                    table: table.clone(),
+                    fields: fields.clone(),
+                    unique: *unique,
+                    filter: filter.clone(),
+                })
+            }
+            AnalyzedQuery::DropIndex { name, table } => {
+                Ok(PhysicalPlan::DropIndex {
+                    name: name.clone(),
+                    table: table.clone(),
+                })
+            }
             AnalyzedQuery::Stream { topic, filter, limit } => {
                 Ok(PhysicalPlan::Stream {
                     topic: topic.clone(),
@@ -73,93 +112,145 @@ impl Optimizer {
             AnalyzedQuery::TopicDelete { name } => {
                 Ok(PhysicalPlan::TopicDelete { name: name.clone() })
             }
-            AnalyzedQuery::VectorSearch { collection, vector, k } => {
+            AnalyzedQuery::VectorSearch { collection, vector, k, text } => {
                 Ok(PhysicalPlan::VectorSearch {
                     collection: collection.clone(),
                     vector: vector.clone(),
                     k: *k,
+                    text: text.clone(),
                 })
             }
         }
     }
 
-    fn optimize_select(
-        &self,
-        columns: &[String],
-        from: &str,
-        filter: &Option<crate::parser::Expr>,
-        order_by: &Option<Vec<(String, bool)>>,
-        limit: &Option<usize>,
-    ) -> Result<PhysicalPlan> {
-        // Build execution plan
-        let mut plan = PhysicalPlan::Scan {
-            table: from.to_string(),
-            columns: columns.to_vec(),
-            estimated_cost: 1000.0,
-        };
-
-        // Add filter if present
-        if let Some(expr) = filter {
-            plan = PhysicalPlan::Filter {
-                input: Box::new(plan),
-                predicate: expr.clone(),
-                estimated_cost: 100.0,
-            };
-        }
-
-        // Add sort if present
-        if let Some(order) = order_by {
-            plan = PhysicalPlan::Sort {
-                input: Box::new(plan),
-                order_by: order.clone(),
-                estimated_cost: 500.0,
-            };
-        }
-
-        // Add limit if present
-        if let Some(n) = limit {
-            plan = PhysicalPlan::Limit {
-                input: Box::new(plan),
-                limit: *n,
-                estimated_cost: 1.0,
-            };
-        }
-
-        Ok(plan)
-    }
 }
 
 impl Default for Optimizer {
     fn default() -> Self {
-        Self::new()
+        Self::new(Arc::new(FunctionRegistry::new()))
     }
 }
 
+/// An aggregate function call computed by [`PhysicalPlan::Aggregate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateCall {
+    /// Aggregate function name
+    pub function: String,
+    /// Arguments; `[Expr::Wildcard]` for `COUNT(*)`
+    pub args: Vec<Expr>,
+    /// Whether only distinct argument values are aggregated
+    pub distinct: bool,
+    /// Column the result is stored under
+    pub name: String,
+}
+
+/// An output column of [`PhysicalPlan::Project`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProjectItem {
+    /// Every column, or those of one qualifier, under their unqualified
+    /// names where these are unambiguous
+    Wildcard(Option<String>),
+    Expr {
+        expr: Expr,
+        name: String,
+    },
+}
+
 /// Physical execution plan
+///
+/// Rows flowing between operators key their columns by `qualifier.column`,
+/// the qualifier being the table name or alias; projection gives them
+/// their output names.
 #[derive(Debug, Clone)]
 pub enum PhysicalPlan {
     /// Table scan
     Scan {
         table: String,
+        /// Qualifier of the scanned columns
+        alias: String,
         columns: Vec<String>,
         estimated_cost: f64,
     },
-    /// Filter (WHERE clause)
+    /// Scan of the documents a secondary index lists within a range
+    ///
+    /// Documents may fall outside the range's exclusive bounds, so the
+    /// query's predicate is still applied to the scanned rows.
+    IndexScan {
+        table: String,
+        /// Qualifier of the scanned columns
+        alias: String,
+        index: String,
+        /// Indexed fields, in key order
+        fields: Vec<String>,
+        range: IndexRange,
+        estimated_rows: u64,
+        estimated_cost: f64,
+    },
+    /// A single row without columns, the input of a query without FROM
+    SingleRow,
+    /// Filter (WHERE and HAVING clauses)
     Filter {
         input: Box<PhysicalPlan>,
-        predicate: crate::parser::Expr,
+        predicate: Expr,
+        estimated_cost: f64,
+    },
+    /// Qualify the output columns of a FROM subquery with its alias
+    SubqueryAlias {
+        input: Box<PhysicalPlan>,
+        alias: String,
+    },
+    /// Join on equality of key expressions, building a hash table over the
+    /// right input
+    HashJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        /// Remaining join condition, checked on rows with matching keys
+        residual: Option<Expr>,
+        estimated_cost: f64,
+    },
+    /// Join comparing every pair of rows
+    NestedLoopJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        kind: JoinKind,
+        condition: Option<Expr>,
+        estimated_cost: f64,
+    },
+    /// Grouping and aggregation (GROUP BY)
+    ///
+    /// Output rows hold each group key under the key expression's text and
+    /// each aggregate under [`AggregateCall::name`].
+    Aggregate {
+        input: Box<PhysicalPlan>,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateCall>,
+        estimated_cost: f64,
+    },
+    /// Projection (select list)
+    Project {
+        input: Box<PhysicalPlan>,
+        items: Vec<ProjectItem>,
+        estimated_cost: f64,
+    },
+    /// Duplicate row removal (SELECT DISTINCT)
+    Distinct {
+        input: Box<PhysicalPlan>,
         estimated_cost: f64,
     },
     /// Sort (ORDER BY)
     Sort {
         input: Box<PhysicalPlan>,
-        order_by: Vec<(String, bool)>,
+        order_by: Vec<(Expr, bool)>,
         estimated_cost: f64,
     },
-    /// Limit
+    /// Limit and offset
     Limit {
         input: Box<PhysicalPlan>,
-        limit: usize,
+        limit: Option<usize>,
+        offset: usize,
         estimated_cost: f64,
     },
     /// Insert
@@ -191,6 +282,16 @@ pub enum PhysicalPlan {
     DropTable {
         name: String,
     },
+    /// Create and backfill a secondary index
+    CreateIndex {
+        name: String,
+        table: String,
+        fields: Vec<String>,
+        unique: bool,
+        filter: Vec<IndexCondition>,
+    },
+    /// Drop a secondary index
+    DropIndex { name: String, table: Option<String> },
     /// Stream from topic
     Stream {
         topic: String,
@@ -214,6 +315,8 @@ pub enum PhysicalPlan {
         collection: String,
         vector: Vec<f32>,
         k: usize,
+        /// Text of a hybrid search
+        text: Option<crate::parser::TextMatch>,
     },
 }
 
@@ -230,53 +333,176 @@ impl PhysicalPlan {
         }
     }
 
-    fn to_plan_nodes(&self) -> Vec<lumadb_common::types::PlanNode> {
+    fn to_plan_nodes(&self) -> Vec<PlanNode> {
+        let (node_type, cost, rows) = self.node_stats();
+        let node = |children| PlanNode {
+            node_type: node_type.to_string(),
+            description: self.description(),
+            children,
+            cost,
+            rows,
+        };
+
+        // Unary operators follow the nodes of their input; joins hold the
+        // nodes of both inputs as children
         match self {
-            PhysicalPlan::Scan { table, columns, estimated_cost } => {
-                vec![lumadb_common::types::PlanNode {
-                    node_type: "Scan".to_string(),
-                    description: format!("Scan {} columns from {}", columns.len(), table),
-                    children: vec![],
-                    cost: *estimated_cost,
-                    rows: 1000,
-                }]
+            PhysicalPlan::HashJoin { left, right, .. }
+            | PhysicalPlan::NestedLoopJoin { left, right, .. } => {
+                let mut children = left.to_plan_nodes();
+                children.extend(right.to_plan_nodes());
+                vec![node(children)]
             }
-            PhysicalPlan::Filter { input, predicate, estimated_cost } => {
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::SubqueryAlias { input, .. }
+            | PhysicalPlan::Aggregate { input, .. }
+            | PhysicalPlan::Project { input, .. }
+            | PhysicalPlan::Distinct { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::Limit { input, .. } => {
                 let mut nodes = input.to_plan_nodes();
-                nodes.push(lumadb_common::types::PlanNode {
-                    node_type: "Filter".to_string(),
-                    description: format!("Filter with predicate"),
-                    children: vec![],
-                    cost: *estimated_cost,
-                    rows: 100,
-                });
+                nodes.push(node(vec![]));
                 nodes
             }
-            _ => vec![lumadb_common::types::PlanNode {
-                node_type: "Unknown".to_string(),
-                description: "Unknown operation".to_string(),
-                children: vec![],
-                cost: 0.0,
-                rows: 0,
-            }],
+            _ => vec![node(vec![])],
+        }
+    }
+
+    /// Node type, cost and estimated rows of this operator
+    fn node_stats(&self) -> (&'static str, f64, u64) {
+        match self {
+            PhysicalPlan::Scan { estimated_cost, .. } => ("Scan", *estimated_cost, 1000),
+            PhysicalPlan::IndexScan {
+                estimated_rows,
+                estimated_cost,
+                ..
+            } => ("IndexScan", *estimated_cost, *estimated_rows),
+            PhysicalPlan::SingleRow => ("SingleRow", 0.0, 1),
+            PhysicalPlan::Filter { estimated_cost, .. } => ("Filter", *estimated_cost, 100),
+            PhysicalPlan::SubqueryAlias { .. } => ("SubqueryAlias", 0.0, 1000),
+            PhysicalPlan::HashJoin { estimated_cost, .. } => ("HashJoin", *estimated_cost, 1000),
+            PhysicalPlan::NestedLoopJoin { estimated_cost, .. } => {
+                ("NestedLoopJoin", *estimated_cost, 1000)
+            }
+            PhysicalPlan::Aggregate { estimated_cost, .. } => ("Aggregate", *estimated_cost, 100),
+            PhysicalPlan::Project { estimated_cost, .. } => ("Project", *estimated_cost, 100),
+            PhysicalPlan::Distinct { estimated_cost, .. } => ("Distinct", *estimated_cost, 100),
+            PhysicalPlan::Sort { estimated_cost, .. } => ("Sort", *estimated_cost, 100),
+            PhysicalPlan::Limit {
+                limit,
+                estimated_cost,
+                ..
+            } => ("Limit", *estimated_cost, limit.map_or(100, |l| l as u64)),
+            _ => ("Unknown", 0.0, 0),
+        }
+    }
+
+    /// EXPLAIN description of this operator
+    fn description(&self) -> String {
+        match self {
+            PhysicalPlan::Scan {
+                table,
+                alias,
+                columns,
+                ..
+            } => format!("Scan {} columns from {} as {}", columns.len(), table, alias),
+            PhysicalPlan::IndexScan {
+                table,
+                alias,
+                index,
+                fields,
+                range,
+                ..
+            } => format!(
+                "Index scan of {} as {} using {} on {}",
+                table,
+                alias,
+                index,
+                index::describe_range(fields, range)
+            ),
+            PhysicalPlan::SingleRow => "Single empty row".to_string(),
+            PhysicalPlan::Filter { predicate, .. } => format!("Filter on {predicate}"),
+            PhysicalPlan::SubqueryAlias { alias, .. } => format!("Subquery as {alias}"),
+            PhysicalPlan::HashJoin {
+                kind,
+                left_keys,
+                right_keys,
+                ..
+            } => {
+                let keys: Vec<String> = left_keys
+                    .iter()
+                    .zip(right_keys)
+                    .map(|(l, r)| format!("{l} = {r}"))
+                    .collect();
+                format!("{kind:?} hash join on {}", keys.join(", "))
+            }
+            PhysicalPlan::NestedLoopJoin {
+                kind, condition, ..
+            } => match condition {
+                Some(condition) => format!("{kind:?} nested loop join on {condition}"),
+                None => format!("{kind:?} nested loop join"),
+            },
+            PhysicalPlan::Aggregate {
+                group_by,
+                aggregates,
+                ..
+            } => {
+                let keys: Vec<String> = group_by.iter().map(ToString::to_string).collect();
+                let calls: Vec<&str> = aggregates.iter().map(|a| a.name.as_str()).collect();
+                format!(
+                    "Aggregate {} grouped by [{}]",
+                    calls.join(", "),
+                    keys.join(", ")
+                )
+            }
+            PhysicalPlan::Project { items, .. } => format!("Project {} items", items.len()),
+            PhysicalPlan::Distinct { .. } => "Remove duplicate rows".to_string(),
+            PhysicalPlan::Sort { order_by, .. } => {
+                let keys: Vec<String> = order_by
+                    .iter()
+                    .map(|(e, asc)| format!("{e} {}", if *asc { "ASC" } else { "DESC" }))
+                    .collect();
+                format!("Sort by {}", keys.join(", "))
+            }
+            PhysicalPlan::Limit { limit, offset, .. } => match limit {
+                Some(limit) => format!("Limit {limit} offset {offset}"),
+                None => format!("Offset {offset}"),
+            },
+            _ => "Unknown operation".to_string(),
         }
     }
 
     fn estimated_cost(&self) -> f64 {
         match self {
-            PhysicalPlan::Scan { estimated_cost, .. } => *estimated_cost,
             PhysicalPlan::Filter { input, estimated_cost, .. } => {
                 input.estimated_cost() + estimated_cost
             }
             PhysicalPlan::Sort { input, estimated_cost, .. } => {
                 input.estimated_cost() + estimated_cost
             }
-            PhysicalPlan::Limit { input, estimated_cost, .. } => {
+            PhysicalPlan::Limit { input, estimated_cost, .. }
+            | PhysicalPlan::Aggregate { input, estimated_cost, .. }
+            | PhysicalPlan::Project { input, estimated_cost, .. }
+            | PhysicalPlan::Distinct { input, estimated_cost, .. } => {
                 input.estimated_cost() + estimated_cost
             }
-            PhysicalPlan::Insert { estimated_cost, .. } => *estimated_cost,
-            PhysicalPlan::Update { estimated_cost, .. } => *estimated_cost,
-            PhysicalPlan::Delete { estimated_cost, .. } => *estimated_cost,
+            PhysicalPlan::SubqueryAlias { input, .. } => input.estimated_cost(),
+            PhysicalPlan::HashJoin {
+                left,
+                right,
+                estimated_cost,
+                ..
+            }
+            | PhysicalPlan::NestedLoopJoin {
+                left,
+                right,
+                estimated_cost,
+                ..
+            } => left.estimated_cost() + right.estimated_cost() + estimated_cost,
+            PhysicalPlan::Scan { estimated_cost, .. }
+            | PhysicalPlan::IndexScan { estimated_cost, .. }
+            | PhysicalPlan::Insert { estimated_cost, .. }
+            | PhysicalPlan::Update { estimated_cost, .. }
+            | PhysicalPlan::Delete { estimated_cost, .. } => *estimated_cost,
             _ => 0.0,
         }
     }
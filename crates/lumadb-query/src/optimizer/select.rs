@@ -0,0 +1,620 @@
+//! Planning of SELECT queries
+//!
+//! A query is planned bottom-up as FROM (scans, subqueries and joins),
+//! WHERE, GROUP BY with its aggregates, HAVING, then the select list.
+//! Without DISTINCT rows are sorted and limited before projection, so ORDER
+//! BY may use any input column; with DISTINCT they are projected and
+//! deduplicated first and ORDER BY must refer to the select list.
+
+use lumadb_common::error::{Error, QueryError, Result};
+
+use super::{AggregateCall, Optimizer, PhysicalPlan, ProjectItem};
+use crate::parser::{BinaryOperator, Expr, JoinKind, Select, SelectItem, TableRef};
+
+fn semantic(message: impl Into<String>) -> Error {
+    Error::Query(QueryError::SemanticError(message.into()))
+}
+
+/// Column name without its qualifier
+fn unqualified(column: &str) -> &str {
+    column.rsplit_once('.').map_or(column, |(_, name)| name)
+}
+
+/// Whether two expressions denote the same value, treating an unqualified
+/// column as equal to any qualified column of that name
+fn same_expr(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Column(a), Expr::Column(b)) => {
+            a == b || ((!a.contains('.') || !b.contains('.')) && unqualified(a) == unqualified(b))
+        }
+        _ => a == b,
+    }
+}
+
+/// Split a predicate into its AND-ed terms
+pub(super) fn conjuncts(expr: Option<Expr>, out: &mut Vec<Expr>) {
+    match expr {
+        Some(Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        }) => {
+            conjuncts(Some(*left), out);
+            conjuncts(Some(*right), out);
+        }
+        Some(expr) => out.push(expr),
+        None => {}
+    }
+}
+
+/// AND terms back together
+fn conjoin(terms: Vec<Expr>) -> Option<Expr> {
+    terms.into_iter().reduce(|left, right| Expr::BinaryOp {
+        left: Box::new(left),
+        op: BinaryOperator::And,
+        right: Box::new(right),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Join input an expression reads from, if all its columns are qualified
+/// with qualifiers of that one input
+fn expr_side(expr: &Expr, left: &[String], right: &[String]) -> Option<Side> {
+    let mut side = None;
+    let mut valid = true;
+    expr.visit(&mut |e| {
+        match e {
+            Expr::Column(column) => {
+                let this = match column.rsplit_once('.') {
+                    Some((q, _)) if left.iter().any(|l| l == q) => Some(Side::Left),
+                    Some((q, _)) if right.iter().any(|r| r == q) => Some(Side::Right),
+                    _ => None,
+                };
+                if this.is_none() || (side.is_some() && side != this) {
+                    valid = false;
+                }
+                side = this;
+            }
+            Expr::Exists { .. } | Expr::Subquery(_) | Expr::InSubquery { .. } => valid = false,
+            _ => {}
+        }
+        valid
+    });
+    side.filter(|_| valid)
+}
+
+fn qualifiers(table: &TableRef) -> Vec<String> {
+    table.qualifiers().into_iter().map(str::to_string).collect()
+}
+
+/// Split equalities between the two join inputs into key pairs, leaving
+/// the remaining terms of the condition
+fn join_keys(
+    condition: Option<Expr>,
+    left: &[String],
+    right: &[String],
+) -> (Vec<(Expr, Expr)>, Vec<Expr>) {
+    let mut terms = Vec::new();
+    conjuncts(condition, &mut terms);
+
+    let mut keys = Vec::new();
+    let mut rest = Vec::new();
+    for term in terms {
+        if let Expr::BinaryOp {
+            left: a,
+            op: BinaryOperator::Eq,
+            right: b,
+        } = &term
+        {
+            match (expr_side(a, left, right), expr_side(b, left, right)) {
+                (Some(Side::Left), Some(Side::Right)) => {
+                    keys.push((a.as_ref().clone(), b.as_ref().clone()));
+                    continue;
+                }
+                (Some(Side::Right), Some(Side::Left)) => {
+                    keys.push((b.as_ref().clone(), a.as_ref().clone()));
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        rest.push(term);
+    }
+    (keys, rest)
+}
+
+/// Turn cross joins from a comma-separated FROM list into inner joins on
+/// the WHERE equalities that link their inputs
+fn push_join_conditions(table: TableRef, filter: &mut Option<Expr>) -> TableRef {
+    match table {
+        TableRef::Join {
+            left,
+            right,
+            kind: JoinKind::Cross,
+            on: None,
+        } => {
+            let left = push_join_conditions(*left, filter);
+            let (keys, rest) = join_keys(filter.take(), &qualifiers(&left), &qualifiers(&right));
+            *filter = conjoin(rest);
+            let on = conjoin(
+                keys.into_iter()
+                    .map(|(l, r)| Expr::BinaryOp {
+                        left: Box::new(l),
+                        op: BinaryOperator::Eq,
+                        right: Box::new(r),
+                    })
+                    .collect(),
+            );
+            TableRef::Join {
+                left: Box::new(left),
+                right,
+                kind: if on.is_some() {
+                    JoinKind::Inner
+                } else {
+                    JoinKind::Cross
+                },
+                on,
+            }
+        }
+        table => table,
+    }
+}
+
+/// Output name of a select list expression
+fn output_name(expr: &Expr, alias: Option<&String>) -> String {
+    match (alias, expr) {
+        (Some(alias), _) => alias.clone(),
+        (None, Expr::Column(column)) => unqualified(column).to_string(),
+        (None, expr) => expr.to_string(),
+    }
+}
+
+/// Select list items with unique output names
+fn project_items(items: &[SelectItem]) -> Vec<ProjectItem> {
+    let mut names: Vec<String> = Vec::with_capacity(items.len());
+    items
+        .iter()
+        .map(|item| match item {
+            SelectItem::Wildcard => ProjectItem::Wildcard(None),
+            SelectItem::QualifiedWildcard(qualifier) => {
+                ProjectItem::Wildcard(Some(qualifier.clone()))
+            }
+            SelectItem::Expr { expr, alias } => {
+                let mut name = output_name(expr, alias.as_ref());
+                if names.contains(&name) {
+                    // Keep a second `b.id` apart from `a.id`
+                    name = expr.to_string();
+                }
+                names.push(name.clone());
+                ProjectItem::Expr {
+                    expr: expr.clone(),
+                    name,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Sort `input` unless there are no keys
+fn sort_plan(input: PhysicalPlan, order_by: Vec<(Expr, bool)>) -> PhysicalPlan {
+    if order_by.is_empty() {
+        input
+    } else {
+        PhysicalPlan::Sort {
+            input: Box::new(input),
+            order_by,
+            estimated_cost: 500.0,
+        }
+    }
+}
+
+/// Apply LIMIT and OFFSET to `input` unless they keep every row
+fn limit_plan(input: PhysicalPlan, limit: Option<usize>, offset: usize) -> PhysicalPlan {
+    if limit.is_none() && offset == 0 {
+        input
+    } else {
+        PhysicalPlan::Limit {
+            input: Box::new(input),
+            limit,
+            offset,
+            estimated_cost: 1.0,
+        }
+    }
+}
+
+/// Expressions of a query, join conditions included, but not those of
+/// its subqueries
+fn query_exprs(select: &Select) -> Vec<&Expr> {
+    fn join_conditions<'a>(table: &'a TableRef, out: &mut Vec<&'a Expr>) {
+        if let TableRef::Join {
+            left, right, on, ..
+        } = table
+        {
+            join_conditions(left, out);
+            join_conditions(right, out);
+            out.extend(on);
+        }
+    }
+
+    let mut exprs: Vec<&Expr> = select
+        .projection
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::Expr { expr, .. } => Some(expr),
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => None,
+        })
+        .chain(&select.filter)
+        .chain(&select.group_by)
+        .chain(&select.having)
+        .chain(select.order_by.iter().map(|(expr, _)| expr))
+        .collect();
+    if let Some(from) = &select.from {
+        join_conditions(from, &mut exprs);
+    }
+    exprs
+}
+
+/// Check that every qualified column names a relation of its query
+///
+/// Subqueries run once ahead of the query that encloses them, so a column
+/// qualified with a relation of an enclosing query cannot be bound and is
+/// rejected rather than read as NULL. Unqualified names are not checked:
+/// documents need not share their fields.
+fn check_scope(select: &Select, outer: &[&str]) -> Result<()> {
+    let inner = select
+        .from
+        .as_ref()
+        .map(TableRef::qualifiers)
+        .unwrap_or_default();
+    let mut result = Ok(());
+    for expr in query_exprs(select) {
+        expr.visit(&mut |e| {
+            if result.is_err() {
+                return false;
+            }
+            match e {
+                Expr::Column(column) => match column.split_once('.') {
+                    Some((q, _)) if outer.contains(&q) && !inner.contains(&q) => {
+                        result = Err(semantic(format!(
+                            "correlated subqueries are not supported: {column} refers to the \
+                             enclosing query"
+                        )));
+                    }
+                    Some((q, _)) if !inner.contains(&q) => {
+                        result = Err(semantic(format!(
+                            "column {column} does not exist: no relation {q} in scope"
+                        )));
+                    }
+                    _ => {}
+                },
+                Expr::InSubquery { subquery, .. }
+                | Expr::Exists { subquery, .. }
+                | Expr::Subquery(subquery) => {
+                    let scope: Vec<&str> = outer.iter().chain(&inner).copied().collect();
+                    result = check_scope(subquery, &scope);
+                }
+                _ => {}
+            }
+            result.is_ok()
+        });
+    }
+    result
+}
+
+impl Optimizer {
+    /// Plan a SELECT query
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query references columns outside its scope,
+    /// misuses aggregates, or has an ORDER BY key a DISTINCT select list cannot
+    /// satisfy.
+    pub fn plan_select(&self, select: &Select) -> Result<PhysicalPlan> {
+        check_scope(select, &[])?;
+
+        let mut filter = select.filter.clone();
+        let mut plan = match &select.from {
+            Some(from) => {
+                let from = push_join_conditions(from.clone(), &mut filter);
+                let indexed = match (&from, &filter) {
+                    (TableRef::Table { name, alias }, Some(predicate)) => {
+                        self.plan_index_scan(name, alias.as_deref().unwrap_or(name), predicate)
+                    }
+                    _ => None,
+                };
+                match indexed {
+                    Some(scan) => scan,
+                    None => self.plan_table(&from)?,
+                }
+            }
+            None => PhysicalPlan::SingleRow,
+        };
+
+        if let Some(predicate) = filter {
+            self.check_no_aggregates(&predicate, "WHERE")?;
+            plan = PhysicalPlan::Filter {
+                input: Box::new(plan),
+                predicate,
+                estimated_cost: 100.0,
+            };
+        }
+
+        let mut projection = project_items(&select.projection);
+        // Sort keys must name select list columns once DISTINCT rows are
+        // projected, so resolve them before aggregates are rewritten
+        let distinct_order_by = if select.distinct {
+            Some(Self::distinct_order_by(select, &projection)?)
+        } else {
+            None
+        };
+        let mut order_by = select
+            .order_by
+            .iter()
+            .map(|(expr, asc)| Ok((Self::resolve_order_key(expr, &select.projection)?, *asc)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut having = select.having.clone();
+
+        let mut aggregates = Vec::new();
+        for expr in projection
+            .iter()
+            .filter_map(|item| match item {
+                ProjectItem::Expr { expr, .. } => Some(expr),
+                ProjectItem::Wildcard(_) => None,
+            })
+            .chain(having.iter())
+            .chain(order_by.iter().map(|(e, _)| e))
+        {
+            self.collect_aggregates(expr, &mut aggregates)?;
+        }
+
+        if !select.group_by.is_empty() || !aggregates.is_empty() || having.is_some() {
+            for expr in &select.group_by {
+                self.check_no_aggregates(expr, "GROUP BY")?;
+            }
+            let rewrite = |expr: &Expr| self.rewrite_aggregated(expr, &select.group_by);
+            for item in &mut projection {
+                match item {
+                    ProjectItem::Expr { expr, .. } => *expr = rewrite(expr)?,
+                    ProjectItem::Wildcard(_) => {
+                        return Err(semantic(
+                            "SELECT * cannot be used with GROUP BY or aggregates",
+                        ));
+                    }
+                }
+            }
+            having = having.as_ref().map(rewrite).transpose()?;
+            for (expr, _) in &mut order_by {
+                *expr = rewrite(expr)?;
+            }
+
+            plan = PhysicalPlan::Aggregate {
+                input: Box::new(plan),
+                group_by: select.group_by.clone(),
+                aggregates,
+                estimated_cost: 200.0,
+            };
+        }
+
+        if let Some(predicate) = having {
+            plan = PhysicalPlan::Filter {
+                input: Box::new(plan),
+                predicate,
+                estimated_cost: 10.0,
+            };
+        }
+
+        let project = |input: PhysicalPlan, items: Vec<ProjectItem>| PhysicalPlan::Project {
+            input: Box::new(input),
+            items,
+            estimated_cost: 10.0,
+        };
+        let limit = |input| limit_plan(input, select.limit, select.offset.unwrap_or(0));
+
+        if let Some(distinct_order_by) = distinct_order_by {
+            plan = project(plan, projection);
+            plan = PhysicalPlan::Distinct {
+                input: Box::new(plan),
+                estimated_cost: 100.0,
+            };
+            Ok(limit(sort_plan(plan, distinct_order_by)))
+        } else {
+            Ok(project(limit(sort_plan(plan, order_by)), projection))
+        }
+    }
+
+    /// Resolve DISTINCT sort keys to the select list columns they name
+    fn distinct_order_by(select: &Select, projection: &[ProjectItem]) -> Result<Vec<(Expr, bool)>> {
+        let has_wildcard = projection
+            .iter()
+            .any(|item| matches!(item, ProjectItem::Wildcard(_)));
+        select
+            .order_by
+            .iter()
+            .map(|(expr, asc)| {
+                let expr = Self::resolve_order_key(expr, &select.projection)?;
+                let selected = projection.iter().find_map(|item| match item {
+                    ProjectItem::Expr { expr: e, name } if same_expr(e, &expr) => Some(name),
+                    _ => None,
+                });
+                let key = match (selected, expr) {
+                    (Some(name), _) => Expr::Column(name.clone()),
+                    (None, Expr::Column(column)) if has_wildcard => {
+                        Expr::Column(unqualified(&column).to_string())
+                    }
+                    (None, expr) => {
+                        return Err(semantic(format!(
+                            "for SELECT DISTINCT, ORDER BY expression {expr} must appear in \
+                             select list"
+                        )))
+                    }
+                };
+                Ok((key, *asc))
+            })
+            .collect()
+    }
+
+    fn plan_table(&self, table: &TableRef) -> Result<PhysicalPlan> {
+        match table {
+            TableRef::Table { name, alias } => Ok(PhysicalPlan::Scan {
+                table: name.clone(),
+                alias: alias.clone().unwrap_or_else(|| name.clone()),
+                columns: vec!["*".to_string()],
+                estimated_cost: 1000.0,
+            }),
+            TableRef::Subquery { query, alias } => Ok(PhysicalPlan::SubqueryAlias {
+                input: Box::new(self.plan_select(query)?),
+                alias: alias.clone(),
+            }),
+            TableRef::Join {
+                left,
+                right,
+                kind,
+                on,
+            } => {
+                if let Some(on) = on {
+                    self.check_no_aggregates(on, "JOIN conditions")?;
+                }
+                let (keys, rest) = if *kind == JoinKind::Cross {
+                    (Vec::new(), Vec::new())
+                } else {
+                    join_keys(on.clone(), &qualifiers(left), &qualifiers(right))
+                };
+                let left = Box::new(self.plan_table(left)?);
+                let right = Box::new(self.plan_table(right)?);
+
+                // Without equalities between the inputs every pair is compared
+                if keys.is_empty() {
+                    return Ok(PhysicalPlan::NestedLoopJoin {
+                        left,
+                        right,
+                        kind: *kind,
+                        condition: on.clone(),
+                        estimated_cost: 10_000.0,
+                    });
+                }
+                let (left_keys, right_keys) = keys.into_iter().unzip();
+                Ok(PhysicalPlan::HashJoin {
+                    left,
+                    right,
+                    kind: *kind,
+                    left_keys,
+                    right_keys,
+                    residual: conjoin(rest),
+                    estimated_cost: 2000.0,
+                })
+            }
+        }
+    }
+
+    /// Replace a select list alias or 1-based position in ORDER BY with the
+    /// expression it names
+    fn resolve_order_key(expr: &Expr, projection: &[SelectItem]) -> Result<Expr> {
+        match expr {
+            Expr::Literal(serde_json::Value::Number(n)) => {
+                let item = n
+                    .as_u64()
+                    .and_then(|i| usize::try_from(i).ok())
+                    .and_then(|i| i.checked_sub(1))
+                    .and_then(|i| projection.get(i));
+                match item {
+                    Some(SelectItem::Expr { expr, .. }) => Ok(expr.clone()),
+                    _ => Err(semantic(format!(
+                        "ORDER BY position {n} is not in select list"
+                    ))),
+                }
+            }
+            Expr::Column(column) => Ok(projection
+                .iter()
+                .find_map(|item| match item {
+                    SelectItem::Expr {
+                        expr,
+                        alias: Some(alias),
+                    } if alias == column => Some(expr.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| expr.clone())),
+            _ => Ok(expr.clone()),
+        }
+    }
+
+    fn is_aggregate(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::Function { name, .. } if self.functions.get_aggregate(name).is_some())
+    }
+
+    fn check_no_aggregates(&self, expr: &Expr, clause: &str) -> Result<()> {
+        let mut found = None;
+        expr.visit(&mut |e| {
+            if self.is_aggregate(e) {
+                found = Some(e.to_string());
+            }
+            found.is_none()
+        });
+        match found {
+            Some(call) => Err(semantic(format!(
+                "aggregate {call} is not allowed in {clause}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the aggregate calls in `expr` that are not in `out` yet
+    fn collect_aggregates(&self, expr: &Expr, out: &mut Vec<AggregateCall>) -> Result<()> {
+        let mut result = Ok(());
+        expr.visit(&mut |e| {
+            let Expr::Function {
+                name,
+                args,
+                distinct,
+            } = e
+            else {
+                return true;
+            };
+            if !self.is_aggregate(e) || result.is_err() {
+                return result.is_ok();
+            }
+            result = args
+                .iter()
+                .try_for_each(|arg| self.check_no_aggregates(arg, "aggregate arguments"))
+                .and_then(|()| match args.as_slice() {
+                    [Expr::Wildcard] if name == "count" && !distinct => Ok(()),
+                    [arg] if *arg != Expr::Wildcard => Ok(()),
+                    _ => Err(semantic(format!("invalid arguments to aggregate {e}"))),
+                });
+            let call = e.to_string();
+            if result.is_ok() && !out.iter().any(|a| a.name == call) {
+                out.push(AggregateCall {
+                    function: name.clone(),
+                    args: args.clone(),
+                    distinct: *distinct,
+                    name: call,
+                });
+            }
+            false
+        });
+        result
+    }
+
+    /// Rewrite an expression evaluated after aggregation to read group
+    /// keys and aggregate results from the aggregated rows
+    fn rewrite_aggregated(&self, expr: &Expr, group_by: &[Expr]) -> Result<Expr> {
+        expr.try_transform(&mut |e| {
+            if self.is_aggregate(e) {
+                return Ok(Some(Expr::Column(e.to_string())));
+            }
+            if let Some(key) = group_by.iter().find(|key| same_expr(key, e)) {
+                return Ok(Some(Expr::Column(key.to_string())));
+            }
+            match e {
+                Expr::Column(column) => Err(semantic(format!(
+                    "column {column} must appear in the GROUP BY clause or be used in an aggregate function"
+                ))),
+                _ => Ok(None),
+            }
+        })
+    }
+}
@@ -0,0 +1,122 @@
+//! Conversion of INSERT, UPDATE and DELETE statements into the query AST
+
+use std::collections::HashMap;
+
+use sqlparser::ast;
+
+use lumadb_common::error::{Error, QueryError, Result};
+
+use super::index::field_of;
+use super::select::object_name;
+use super::{Ast, Expr, SqlParser, UnaryOperator};
+
+fn dml_error(message: String) -> Error {
+    Error::Query(QueryError::ParseError(message))
+}
+
+/// Name of a table written by a statement
+fn table_name(table: &ast::TableWithJoins) -> Result<String> {
+    match &table.relation {
+        ast::TableFactor::Table { name, .. } if table.joins.is_empty() => Ok(object_name(name)),
+        relation => Err(dml_error(format!("Unsupported target table {relation}"))),
+    }
+}
+
+impl SqlParser {
+    /// Value of an expression that must be a literal, such as `'a'` or `-1`
+    fn literal(&self, expr: &ast::Expr) -> Result<serde_json::Value> {
+        match self.convert_expr(expr)? {
+            Expr::Literal(value) => Ok(value),
+            Expr::UnaryOp {
+                op: UnaryOperator::Neg,
+                expr,
+            } => match *expr {
+                Expr::Literal(serde_json::Value::Number(n)) => Ok(match n.as_i64() {
+                    Some(i) => serde_json::Value::from(-i),
+                    None => serde_json::json!(-n.as_f64().unwrap_or_default()),
+                }),
+                expr => Err(dml_error(format!("Expected a literal, found -{expr}"))),
+            },
+            expr => Err(dml_error(format!("Expected a literal, found {expr}"))),
+        }
+    }
+
+    /// Convert `INSERT INTO table (columns) VALUES (...), ...`
+    pub(super) fn convert_insert(
+        &self,
+        table_name: &ast::ObjectName,
+        columns: &[ast::Ident],
+        source: Option<&ast::Query>,
+    ) -> Result<Ast> {
+        let Some(ast::SetExpr::Values(values)) = source.map(|q| q.body.as_ref()) else {
+            return Err(dml_error("INSERT supports only VALUES".to_string()));
+        };
+        if columns.is_empty() {
+            return Err(dml_error("INSERT needs a column list".to_string()));
+        }
+
+        let values = values
+            .rows
+            .iter()
+            .map(|row| {
+                if row.len() != columns.len() {
+                    return Err(dml_error(format!(
+                        "INSERT has {} columns but a row of {} values",
+                        columns.len(),
+                        row.len()
+                    )));
+                }
+                row.iter().map(|value| self.literal(value)).collect()
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Ast::Insert {
+            table: object_name(table_name),
+            columns: columns.iter().map(|c| c.value.clone()).collect(),
+            values,
+        })
+    }
+
+    /// Convert `UPDATE table SET field = literal, ... [WHERE ...]`
+    pub(super) fn convert_update(
+        &self,
+        table: &ast::TableWithJoins,
+        assignments: &[ast::Assignment],
+        selection: Option<&ast::Expr>,
+    ) -> Result<Ast> {
+        let table = table_name(table)?;
+
+        let mut set = HashMap::with_capacity(assignments.len());
+        for assignment in assignments {
+            let column = assignment
+                .id
+                .iter()
+                .map(|ident| ident.value.as_str())
+                .collect::<Vec<_>>()
+                .join(".");
+            set.insert(field_of(&column, &table), self.literal(&assignment.value)?);
+        }
+
+        Ok(Ast::Update {
+            table,
+            set,
+            filter: selection.map(|e| self.convert_expr(e)).transpose()?,
+        })
+    }
+
+    /// Convert `DELETE FROM table [WHERE ...]`
+    pub(super) fn convert_delete(
+        &self,
+        from: &[ast::TableWithJoins],
+        selection: Option<&ast::Expr>,
+    ) -> Result<Ast> {
+        let [table] = from else {
+            return Err(dml_error("DELETE takes one table".to_string()));
+        };
+
+        Ok(Ast::Delete {
+            table: table_name(table)?,
+            filter: selection.map(|e| self.convert_expr(e)).transpose()?,
+        })
+    }
+}
@@ -0,0 +1,155 @@
+//! Conversion of index statements into the query AST
+
+use sqlparser::ast;
+
+use lumadb_common::error::{Error, QueryError, Result};
+use lumadb_common::types::{ConditionOp, IndexCondition};
+
+use super::{Ast, BinaryOperator, Expr, SqlParser};
+
+/// A comparison of a column with a literal, or a NULL test of a column,
+/// as a condition on the column's field
+///
+/// The field is the column name as written, qualifier included.
+pub(crate) fn field_condition(term: &Expr) -> Option<IndexCondition> {
+    let condition = |field: &str, op, value: &serde_json::Value| IndexCondition {
+        field: field.to_string(),
+        op,
+        value: value.clone(),
+    };
+    match term {
+        Expr::IsNull { expr, negated } => match expr.as_ref() {
+            Expr::Column(column) => {
+                let op = if *negated {
+                    ConditionOp::IsNotNull
+                } else {
+                    ConditionOp::IsNull
+                };
+                Some(condition(column, op, &serde_json::Value::Null))
+            }
+            _ => None,
+        },
+        Expr::BinaryOp { left, op, right } => {
+            let (column, op, value) = match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), Expr::Literal(value)) => (column, *op, value),
+                // `5 < x` is `x > 5`
+                (Expr::Literal(value), Expr::Column(column)) => {
+                    let flipped = match op {
+                        BinaryOperator::Lt => BinaryOperator::Gt,
+                        BinaryOperator::Le => BinaryOperator::Ge,
+                        BinaryOperator::Gt => BinaryOperator::Lt,
+                        BinaryOperator::Ge => BinaryOperator::Le,
+                        op => *op,
+                    };
+                    (column, flipped, value)
+                }
+                _ => return None,
+            };
+            if value.is_null() {
+                return None;
+            }
+            let op = match op {
+                BinaryOperator::Eq => ConditionOp::Eq,
+                BinaryOperator::Ne => ConditionOp::NotEq,
+                BinaryOperator::Lt => ConditionOp::Lt,
+                BinaryOperator::Le => ConditionOp::LtEq,
+                BinaryOperator::Gt => ConditionOp::Gt,
+                BinaryOperator::Ge => ConditionOp::GtEq,
+                _ => return None,
+            };
+            Some(condition(column, op, value))
+        }
+        _ => None,
+    }
+}
+
+/// Field path of a column of `table`, without the table qualifier
+pub(super) fn field_of(column: &str, table: &str) -> String {
+    column
+        .strip_prefix(table)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .unwrap_or(column)
+        .to_string()
+}
+
+fn index_error(message: String) -> Error {
+    Error::Query(QueryError::ParseError(message))
+}
+
+impl SqlParser {
+    /// Convert `CREATE [UNIQUE] INDEX [name] ON table (fields) [WHERE ...]`
+    ///
+    /// Fields are document paths such as `address.city`. The WHERE clause
+    /// of a partial index is an AND of comparisons between a field and a
+    /// literal and of NULL tests.
+    pub(super) fn convert_create_index(
+        &self,
+        name: Option<&ast::ObjectName>,
+        table_name: &ast::ObjectName,
+        columns: &[ast::OrderByExpr],
+        unique: bool,
+        predicate: Option<&ast::Expr>,
+    ) -> Result<Ast> {
+        let table = super::select::object_name(table_name);
+
+        let fields = columns
+            .iter()
+            .map(|column| match self.convert_expr(&column.expr)? {
+                Expr::Column(column) => Ok(field_of(&column, &table)),
+                expr => Err(index_error(format!(
+                    "Unsupported index expression {expr}, expected a field"
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if fields.is_empty() {
+            return Err(index_error("An index needs at least one field".to_string()));
+        }
+
+        let mut filter = Vec::new();
+        if let Some(predicate) = predicate {
+            let mut terms = vec![self.convert_expr(predicate)?];
+            while let Some(term) = terms.pop() {
+                if let Expr::BinaryOp {
+                    left,
+                    op: BinaryOperator::And,
+                    right,
+                } = term
+                {
+                    terms.push(*right);
+                    terms.push(*left);
+                    continue;
+                }
+                let mut condition = field_condition(&term).ok_or_else(|| {
+                    index_error(format!("Unsupported condition {term} in partial index"))
+                })?;
+                condition.field = field_of(&condition.field, &table);
+                filter.push(condition);
+            }
+        }
+
+        let name = match name {
+            Some(name) => super::select::object_name(name),
+            None => format!("{}_{}_idx", table, fields.join("_").replace('.', "_")),
+        };
+        Ok(Ast::CreateIndex {
+            name,
+            table,
+            fields,
+            unique,
+            filter,
+        })
+    }
+
+    /// Convert `DROP INDEX [table.]name`
+    pub(super) fn convert_drop_index(names: &[ast::ObjectName]) -> Result<Ast> {
+        let [name] = names else {
+            return Err(index_error("DROP INDEX takes one index".to_string()));
+        };
+        let (table, name) = match name.0.as_slice() {
+            [name] => (None, name.value.clone()),
+            [table, name] => (Some(table.value.clone()), name.value.clone()),
+            _ => return Err(index_error(format!("Invalid index name {name}"))),
+        };
+        Ok(Ast::DropIndex { name, table })
+    }
+}
@@ -1,6 +1,17 @@
 //! Query parser for LQL and SQL
 
+use std::fmt;
+
 use lumadb_common::error::{Result, Error, QueryError};
+use lumadb_common::types::IndexCondition;
+use lumadb_txn::IsolationLevel;
+
+mod dml;
+mod index;
+mod select;
+mod vector;
+
+pub(crate) use index::field_condition;
 
 /// Query parser supporting LQL and SQL
 pub struct Parser {
@@ -33,6 +44,14 @@ impl Parser {
             || query.starts_with("create")
             || query.starts_with("DROP")
             || query.starts_with("drop")
+            || query.starts_with("BEGIN")
+            || query.starts_with("begin")
+            || query.starts_with("START")
+            || query.starts_with("start")
+            || query.starts_with("COMMIT")
+            || query.starts_with("commit")
+            || query.starts_with("ROLLBACK")
+            || query.starts_with("rollback")
         {
             self.sql_parser.parse(query)
         } else {
@@ -53,7 +72,7 @@ impl Parser {
         match parts[0].to_uppercase().as_str() {
             "STREAM" => self.parse_stream_query(&parts[1..]),
             "TOPIC" => self.parse_topic_query(&parts[1..]),
-            "VECTOR" => self.parse_vector_query(&parts[1..]),
+            "VECTOR" => Self::parse_vector_query(&query[parts[0].len()..]),
             _ => Err(Error::Query(QueryError::ParseError(format!(
                 "Unknown LQL command: {}",
                 parts[0]
@@ -86,15 +105,6 @@ impl Parser {
             _ => Ok(Ast::TopicList),
         }
     }
-
-    fn parse_vector_query(&self, parts: &[&str]) -> Result<Ast> {
-        // VECTOR SEARCH collection [vector] k
-        Ok(Ast::VectorSearch {
-            collection: parts.get(1).unwrap_or(&"").to_string(),
-            vector: Vec::new(),
-            k: 10,
-        })
-    }
 }
 
 impl Default for Parser {
@@ -132,48 +142,81 @@ impl SqlParser {
         use sqlparser::ast::Statement;
 
         match stmt {
-            Statement::Query(query) => Ok(Ast::Select {
-                columns: vec!["*".to_string()],
-                from: self.extract_table_name(query),
-                filter: None,
-                order_by: None,
-                limit: None,
-            }),
-            Statement::Insert { table_name, columns, .. } => Ok(Ast::Insert {
-                table: table_name.to_string(),
-                columns: columns.iter().map(|c| c.to_string()).collect(),
-                values: Vec::new(),
-            }),
-            Statement::Update { table, .. } => Ok(Ast::Update {
-                table: table.relation.to_string(),
-                set: std::collections::HashMap::new(),
-                filter: None,
-            }),
-            Statement::Delete { from, .. } => Ok(Ast::Delete {
-                table: from.first().map(|t| t.relation.to_string()).unwrap_or_default(),
-                filter: None,
-            }),
+            Statement::Query(query) => Ok(Ast::Select(Box::new(self.convert_query(query)?))),
+            Statement::Insert {
+                table_name,
+                columns,
+                source,
+                ..
+            } => self.convert_insert(table_name, columns, source.as_deref()),
+            Statement::Update {
+                table,
+                assignments,
+                from: None,
+                selection,
+                ..
+            } => self.convert_update(table, assignments, selection.as_ref()),
+            Statement::Delete {
+                from, selection, ..
+            } => self.convert_delete(from, selection.as_ref()),
             Statement::CreateTable { name, .. } => Ok(Ast::CreateTable {
                 name: name.to_string(),
                 columns: Vec::new(),
             }),
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                predicate,
+                ..
+            } => self.convert_create_index(
+                name.as_ref(),
+                table_name,
+                columns,
+                *unique,
+                predicate.as_ref(),
+            ),
+            Statement::Drop {
+                object_type: sqlparser::ast::ObjectType::Index,
+                names,
+                ..
+            } => Self::convert_drop_index(names),
             Statement::Drop { names, .. } => Ok(Ast::DropTable {
                 name: names.first().map(|n| n.to_string()).unwrap_or_default(),
             }),
+            Statement::StartTransaction { modes, .. } => Ok(Ast::Begin {
+                isolation: Self::convert_isolation(modes),
+            }),
+            Statement::Commit { chain: false } => Ok(Ast::Commit),
+            Statement::Rollback {
+                chain: false,
+                savepoint: None,
+            } => Ok(Ast::Rollback),
             _ => Err(Error::Query(QueryError::ParseError(
                 "Unsupported statement type".to_string(),
             ))),
         }
     }
 
-    fn extract_table_name(&self, query: &sqlparser::ast::Query) -> String {
-        // Simplified extraction
-        if let sqlparser::ast::SetExpr::Select(select) = query.body.as_ref() {
-            if let Some(from) = select.from.first() {
-                return from.relation.to_string();
-            }
+    /// Isolation level requested by `START TRANSACTION` modes
+    ///
+    /// Every level below serializable runs as snapshot isolation, which
+    /// is at least as strong.
+    fn convert_isolation(modes: &[sqlparser::ast::TransactionMode]) -> IsolationLevel {
+        use sqlparser::ast::{TransactionIsolationLevel, TransactionMode};
+
+        let serializable = modes.iter().any(|mode| {
+            matches!(
+                mode,
+                TransactionMode::IsolationLevel(TransactionIsolationLevel::Serializable)
+            )
+        });
+        if serializable {
+            IsolationLevel::Serializable
+        } else {
+            IsolationLevel::Snapshot
         }
-        String::new()
     }
 }
 
@@ -181,13 +224,7 @@ impl SqlParser {
 #[derive(Debug, Clone)]
 pub enum Ast {
     // SQL statements
-    Select {
-        columns: Vec<String>,
-        from: String,
-        filter: Option<Expr>,
-        order_by: Option<Vec<(String, bool)>>,
-        limit: Option<usize>,
-    },
+    Select(Box<Select>),
     Insert {
         table: String,
         columns: Vec<String>,
@@ -209,6 +246,27 @@ pub enum Ast {
     DropTable {
         name: String,
     },
+    /// A secondary index on document fields
+    CreateIndex {
+        name: String,
+        table: String,
+        /// Dotted field paths, in key order
+        fields: Vec<String>,
+        unique: bool,
+        /// Conditions of a partial index
+        filter: Vec<IndexCondition>,
+    },
+    DropIndex {
+        name: String,
+        /// Table of the index, if qualified
+        table: Option<String>,
+    },
+    /// `BEGIN` or `START TRANSACTION`
+    Begin {
+        isolation: IsolationLevel,
+    },
+    Commit,
+    Rollback,
 
     // LQL statements
     Stream {
@@ -227,14 +285,206 @@ pub enum Ast {
     },
     VectorSearch {
         collection: String,
+        /// Query vector; empty in a full-text search
         vector: Vec<f32>,
         k: usize,
+        /// Text of a hybrid search
+        text: Option<TextMatch>,
     },
 }
 
+/// The `MATCH` clause of a hybrid vector search, and how its full-text
+/// ranking fuses with the vector ranking
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    pub text: String,
+    pub fusion: lumadb_storage::hybrid::Fusion,
+    pub vector_weight: f32,
+    pub text_weight: f32,
+}
+
+impl Ast {
+    /// Resources the statement touches, as `(resource, action)` pairs for
+    /// the authorizer
+    ///
+    /// Queries read every collection they name, subqueries included; DML
+    /// writes its target; DDL creates, deletes or alters (indexes) its
+    /// collection. LQL statements act on their topic or collection, and
+    /// listing topics describes the cluster.
+    #[must_use]
+    pub fn accesses(&self) -> Vec<(String, &'static str)> {
+        let collection = |name: &str| format!("collection:{name}");
+        let mut accesses = Vec::new();
+        let mut reads = Vec::new();
+        match self {
+            Ast::Select(select) => select.tables(&mut reads),
+            Ast::Insert { table, .. } => accesses.push((collection(table), "write")),
+            Ast::Update { table, filter, .. } | Ast::Delete { table, filter } => {
+                accesses.push((collection(table), "write"));
+                if let Some(filter) = filter {
+                    filter.tables(&mut reads);
+                }
+            }
+            Ast::CreateTable { name, .. } => accesses.push((collection(name), "create")),
+            Ast::DropTable { name } => accesses.push((collection(name), "delete")),
+            Ast::CreateIndex { table, .. } => accesses.push((collection(table), "alter")),
+            Ast::DropIndex { table, .. } => accesses.push((
+                table
+                    .as_deref()
+                    .map_or_else(|| "cluster".to_string(), collection),
+                "alter",
+            )),
+            Ast::Begin { .. } | Ast::Commit | Ast::Rollback => {}
+            Ast::Stream { topic, filter, .. } => {
+                accesses.push((format!("topic:{topic}"), "read"));
+                if let Some(filter) = filter {
+                    filter.tables(&mut reads);
+                }
+            }
+            Ast::TopicCreate { name, .. } => accesses.push((format!("topic:{name}"), "create")),
+            Ast::TopicList => accesses.push(("cluster".to_string(), "describe")),
+            Ast::TopicDelete { name } => accesses.push((format!("topic:{name}"), "delete")),
+            Ast::VectorSearch {
+                collection: name, ..
+            } => accesses.push((collection(name), "read")),
+        }
+
+        reads.sort_unstable();
+        reads.dedup();
+        accesses.extend(reads.into_iter().map(|table| (collection(table), "read")));
+        accesses
+    }
+}
+
+/// A SELECT query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub distinct: bool,
+    pub projection: Vec<SelectItem>,
+    /// Tables read by the query; `None` for a query without FROM
+    pub from: Option<TableRef>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    /// Sort keys with their direction (`true` for ascending)
+    pub order_by: Vec<(Expr, bool)>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl Select {
+    /// Collect the tables the query reads, in FROM and in subqueries
+    pub fn tables<'a>(&'a self, out: &mut Vec<&'a str>) {
+        if let Some(from) = &self.from {
+            from.tables(out);
+        }
+        let items = self.projection.iter().filter_map(|item| match item {
+            SelectItem::Expr { expr, .. } => Some(expr),
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => None,
+        });
+        let exprs = items
+            .chain(&self.filter)
+            .chain(&self.group_by)
+            .chain(&self.having)
+            .chain(self.order_by.iter().map(|(expr, _)| expr));
+        for expr in exprs {
+            expr.tables(out);
+        }
+    }
+}
+
+/// An item of the select list
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`
+    Wildcard,
+    /// `alias.*`
+    QualifiedWildcard(String),
+    /// An expression with an optional `AS` alias
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// A relation in the FROM clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    /// A subquery in FROM, addressed by its alias
+    Subquery {
+        query: Box<Select>,
+        alias: String,
+    },
+    Join {
+        left: Box<TableRef>,
+        right: Box<TableRef>,
+        kind: JoinKind,
+        on: Option<Expr>,
+    },
+}
+
+impl TableRef {
+    /// Collect the tables this relation reads
+    fn tables<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            TableRef::Table { name, .. } => out.push(name),
+            TableRef::Subquery { query, .. } => query.tables(out),
+            TableRef::Join {
+                left, right, on, ..
+            } => {
+                left.tables(out);
+                right.tables(out);
+                if let Some(on) = on {
+                    on.tables(out);
+                }
+            }
+        }
+    }
+
+    /// Names that qualify the columns of this relation
+    #[must_use]
+    pub fn qualifiers(&self) -> Vec<&str> {
+        match self {
+            TableRef::Table { name, alias } => vec![alias.as_deref().unwrap_or(name)],
+            TableRef::Subquery { alias, .. } => vec![alias],
+            TableRef::Join { left, right, .. } => {
+                let mut qualifiers = left.qualifiers();
+                qualifiers.extend(right.qualifiers());
+                qualifiers
+            }
+        }
+    }
+}
+
+/// Join types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+impl JoinKind {
+    /// Whether unmatched rows of the left input are kept
+    #[must_use]
+    pub fn keeps_left(self) -> bool {
+        matches!(self, JoinKind::Left | JoinKind::Full)
+    }
+
+    /// Whether unmatched rows of the right input are kept
+    #[must_use]
+    pub fn keeps_right(self) -> bool {
+        matches!(self, JoinKind::Right | JoinKind::Full)
+    }
+}
+
 /// Expression node
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
+    /// A column, optionally qualified as `table.column`
     Column(String),
     Literal(serde_json::Value),
     BinaryOp {
@@ -242,14 +492,317 @@ pub enum Expr {
         op: BinaryOperator,
         right: Box<Expr>,
     },
+    UnaryOp {
+        op: UnaryOperator,
+        expr: Box<Expr>,
+    },
+    /// Scalar or aggregate function call
     Function {
         name: String,
         args: Vec<Expr>,
+        distinct: bool,
+    },
+    /// `*` as a function argument, as in `COUNT(*)`
+    Wildcard,
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<Select>,
+        negated: bool,
+    },
+    Exists {
+        subquery: Box<Select>,
+        negated: bool,
+    },
+    /// Subquery returning a single value
+    Subquery(Box<Select>),
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+        case_insensitive: bool,
+    },
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        else_result: Option<Box<Expr>>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
     },
 }
 
+impl Expr {
+    /// Visit this expression and its subexpressions in pre-order
+    ///
+    /// Returning `false` from `f` skips the children of that node.
+    /// Subqueries are not entered.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Expr) -> bool) {
+        if !f(self) {
+            return;
+        }
+        match self {
+            Expr::BinaryOp { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            Expr::UnaryOp { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::InSubquery { expr, .. }
+            | Expr::Cast { expr, .. } => expr.visit(f),
+            Expr::Function { args, .. } => {
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+            Expr::InList { expr, list, .. } => {
+                expr.visit(f);
+                for item in list {
+                    item.visit(f);
+                }
+            }
+            Expr::Like { expr, pattern, .. } => {
+                expr.visit(f);
+                pattern.visit(f);
+            }
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                if let Some(operand) = operand {
+                    operand.visit(f);
+                }
+                for (when, then) in branches {
+                    when.visit(f);
+                    then.visit(f);
+                }
+                if let Some(else_result) = else_result {
+                    else_result.visit(f);
+                }
+            }
+            Expr::Column(_)
+            | Expr::Literal(_)
+            | Expr::Wildcard
+            | Expr::Exists { .. }
+            | Expr::Subquery(_) => {}
+        }
+    }
+
+    /// Collect the tables read by subqueries of this expression
+    fn tables<'a>(&'a self, out: &mut Vec<&'a str>) {
+        self.visit(&mut |expr| {
+            match expr {
+                Expr::InSubquery { subquery, .. }
+                | Expr::Exists { subquery, .. }
+                | Expr::Subquery(subquery) => subquery.tables(out),
+                _ => {}
+            }
+            true
+        });
+    }
+
+    /// Rebuild this expression top-down
+    ///
+    /// Where `f` returns a replacement the node is swapped for it without
+    /// visiting its children; elsewhere the children are transformed.
+    /// Subqueries are not entered.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by `f`.
+    pub fn try_transform(&self, f: &mut impl FnMut(&Expr) -> Result<Option<Expr>>) -> Result<Expr> {
+        if let Some(replacement) = f(self)? {
+            return Ok(replacement);
+        }
+        let mut t = |e: &Expr| e.try_transform(f);
+        Ok(match self {
+            Expr::BinaryOp { left, op, right } => Expr::BinaryOp {
+                left: Box::new(t(left)?),
+                op: *op,
+                right: Box::new(t(right)?),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: *op,
+                expr: Box::new(t(expr)?),
+            },
+            Expr::Function {
+                name,
+                args,
+                distinct,
+            } => Expr::Function {
+                name: name.clone(),
+                args: args.iter().map(&mut t).collect::<Result<_>>()?,
+                distinct: *distinct,
+            },
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: Box::new(t(expr)?),
+                negated: *negated,
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: Box::new(t(expr)?),
+                list: list.iter().map(&mut t).collect::<Result<_>>()?,
+                negated: *negated,
+            },
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => Expr::InSubquery {
+                expr: Box::new(t(expr)?),
+                subquery: subquery.clone(),
+                negated: *negated,
+            },
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => Expr::Like {
+                expr: Box::new(t(expr)?),
+                pattern: Box::new(t(pattern)?),
+                negated: *negated,
+                case_insensitive: *case_insensitive,
+            },
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => Expr::Case {
+                operand: operand.as_deref().map(|e| t(e).map(Box::new)).transpose()?,
+                branches: branches
+                    .iter()
+                    .map(|(when, then)| Ok((t(when)?, t(then)?)))
+                    .collect::<Result<_>>()?,
+                else_result: else_result
+                    .as_deref()
+                    .map(|e| t(e).map(Box::new))
+                    .transpose()?,
+            },
+            Expr::Cast { expr, data_type } => Expr::Cast {
+                expr: Box::new(t(expr)?),
+                data_type: data_type.clone(),
+            },
+            Expr::Column(_)
+            | Expr::Literal(_)
+            | Expr::Wildcard
+            | Expr::Exists { .. }
+            | Expr::Subquery(_) => self.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Nested binary operations are parenthesized so names stay unambiguous
+        fn operand(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+            match expr {
+                Expr::BinaryOp { .. } => write!(f, "({expr})"),
+                _ => write!(f, "{expr}"),
+            }
+        }
+        fn list(f: &mut fmt::Formatter<'_>, exprs: &[Expr]) -> fmt::Result {
+            for (i, expr) in exprs.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{expr}")?;
+            }
+            Ok(())
+        }
+        let not = |negated: bool| if negated { "NOT " } else { "" };
+
+        match self {
+            Expr::Column(name) => f.write_str(name),
+            Expr::Literal(serde_json::Value::String(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(value) => write!(f, "{value}"),
+            Expr::BinaryOp { left, op, right } => {
+                operand(f, left)?;
+                write!(f, " {op} ")?;
+                operand(f, right)
+            }
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr,
+            } => write!(f, "NOT {expr}"),
+            Expr::UnaryOp {
+                op: UnaryOperator::Neg,
+                expr,
+            } => {
+                f.write_str("-")?;
+                operand(f, expr)
+            }
+            Expr::Function {
+                name,
+                args,
+                distinct,
+            } => {
+                write!(f, "{name}({}", if *distinct { "DISTINCT " } else { "" })?;
+                list(f, args)?;
+                f.write_str(")")
+            }
+            Expr::Wildcard => f.write_str("*"),
+            Expr::IsNull { expr, negated } => write!(f, "{expr} IS {}NULL", not(*negated)),
+            Expr::InList {
+                expr,
+                list: items,
+                negated,
+            } => {
+                write!(f, "{expr} {}IN (", not(*negated))?;
+                list(f, items)?;
+                f.write_str(")")
+            }
+            Expr::InSubquery { expr, negated, .. } => {
+                write!(f, "{expr} {}IN (subquery)", not(*negated))
+            }
+            Expr::Exists { negated, .. } => write!(f, "{}EXISTS (subquery)", not(*negated)),
+            Expr::Subquery(_) => f.write_str("(subquery)"),
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => {
+                let op = if *case_insensitive { "ILIKE" } else { "LIKE" };
+                write!(f, "{expr} {}{op} {pattern}", not(*negated))
+            }
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                f.write_str("CASE")?;
+                if let Some(operand) = operand {
+                    write!(f, " {operand}")?;
+                }
+                for (when, then) in branches {
+                    write!(f, " WHEN {when} THEN {then}")?;
+                }
+                if let Some(else_result) = else_result {
+                    write!(f, " ELSE {else_result}")?;
+                }
+                f.write_str(" END")
+            }
+            Expr::Cast { expr, data_type } => write!(f, "CAST({expr} AS {data_type:?})"),
+        }
+    }
+}
+
 /// Binary operators
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinaryOperator {
     Eq,
     Ne,
@@ -263,10 +816,40 @@ pub enum BinaryOperator {
     Minus,
     Mul,
     Div,
+    Mod,
+    Concat,
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::Ne => "<>",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::Concat => "||",
+        })
+    }
+}
+
+/// Unary operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Not,
+    Neg,
 }
 
 /// Column definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ColumnDef {
     pub name: String,
     pub data_type: DataType,
@@ -274,7 +857,7 @@ pub struct ColumnDef {
 }
 
 /// Data types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Int64,
     Float64,
@@ -0,0 +1,466 @@
+//! Conversion of SQL queries into the query AST
+
+use sqlparser::ast;
+
+use lumadb_common::error::{Error, QueryError, Result};
+
+use super::{
+    BinaryOperator, DataType, Expr, JoinKind, Select, SelectItem, SqlParser, TableRef,
+    UnaryOperator,
+};
+
+fn unsupported(what: impl std::fmt::Display) -> Error {
+    Error::Query(QueryError::ParseError(format!("Unsupported {what}")))
+}
+
+/// Dotted name from its identifiers, without quoting
+pub(super) fn object_name(name: &ast::ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|ident| ident.value.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Expr {
+    Expr::BinaryOp {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    }
+}
+
+impl SqlParser {
+    /// Convert a query; set operations and CTEs are not supported
+    pub(super) fn convert_query(&self, query: &ast::Query) -> Result<Select> {
+        if query.with.is_some() {
+            return Err(unsupported("WITH clause"));
+        }
+        let mut select = match query.body.as_ref() {
+            ast::SetExpr::Select(select) => self.convert_select(select)?,
+            ast::SetExpr::Query(query) => return self.convert_query(query),
+            _ => return Err(unsupported("set operation")),
+        };
+
+        select.order_by = query
+            .order_by
+            .iter()
+            .map(|o| Ok((self.convert_expr(&o.expr)?, o.asc.unwrap_or(true))))
+            .collect::<Result<_>>()?;
+        select.limit = query
+            .limit
+            .as_ref()
+            .map(|e| Self::convert_count(e, "LIMIT"))
+            .transpose()?;
+        select.offset = query
+            .offset
+            .as_ref()
+            .map(|o| Self::convert_count(&o.value, "OFFSET"))
+            .transpose()?;
+        Ok(select)
+    }
+
+    fn convert_select(&self, select: &ast::Select) -> Result<Select> {
+        let distinct = match &select.distinct {
+            None => false,
+            Some(ast::Distinct::Distinct) => true,
+            Some(ast::Distinct::On(_)) => return Err(unsupported("DISTINCT ON")),
+        };
+
+        let projection = select
+            .projection
+            .iter()
+            .map(|item| {
+                Ok(match item {
+                    ast::SelectItem::UnnamedExpr(expr) => SelectItem::Expr {
+                        expr: self.convert_expr(expr)?,
+                        alias: None,
+                    },
+                    ast::SelectItem::ExprWithAlias { expr, alias } => SelectItem::Expr {
+                        expr: self.convert_expr(expr)?,
+                        alias: Some(alias.value.clone()),
+                    },
+                    ast::SelectItem::QualifiedWildcard(name, _) => {
+                        SelectItem::QualifiedWildcard(object_name(name))
+                    }
+                    ast::SelectItem::Wildcard(_) => SelectItem::Wildcard,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        // A comma-separated FROM list is a chain of cross joins
+        let mut from = None;
+        for table in &select.from {
+            let relation = self.convert_table_with_joins(table)?;
+            from = Some(match from {
+                None => relation,
+                Some(left) => TableRef::Join {
+                    left: Box::new(left),
+                    right: Box::new(relation),
+                    kind: JoinKind::Cross,
+                    on: None,
+                },
+            });
+        }
+
+        let group_by = match &select.group_by {
+            ast::GroupByExpr::Expressions(exprs) => exprs
+                .iter()
+                .map(|e| self.convert_expr(e))
+                .collect::<Result<_>>()?,
+            ast::GroupByExpr::All => return Err(unsupported("GROUP BY ALL")),
+        };
+
+        Ok(Select {
+            distinct,
+            projection,
+            from,
+            filter: select
+                .selection
+                .as_ref()
+                .map(|e| self.convert_expr(e))
+                .transpose()?,
+            group_by,
+            having: select
+                .having
+                .as_ref()
+                .map(|e| self.convert_expr(e))
+                .transpose()?,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        })
+    }
+
+    fn convert_table_with_joins(&self, table: &ast::TableWithJoins) -> Result<TableRef> {
+        let mut relation = self.convert_table_factor(&table.relation)?;
+        for join in &table.joins {
+            let right = self.convert_table_factor(&join.relation)?;
+            let (kind, constraint) = match &join.join_operator {
+                ast::JoinOperator::Inner(c) => (JoinKind::Inner, Some(c)),
+                ast::JoinOperator::LeftOuter(c) => (JoinKind::Left, Some(c)),
+                ast::JoinOperator::RightOuter(c) => (JoinKind::Right, Some(c)),
+                ast::JoinOperator::FullOuter(c) => (JoinKind::Full, Some(c)),
+                ast::JoinOperator::CrossJoin => (JoinKind::Cross, None),
+                _ => return Err(unsupported("join type")),
+            };
+            let on = match constraint {
+                None | Some(ast::JoinConstraint::None) => None,
+                Some(ast::JoinConstraint::On(expr)) => Some(self.convert_expr(expr)?),
+                Some(ast::JoinConstraint::Using(columns)) => {
+                    Some(Self::using_condition(&relation, &right, columns)?)
+                }
+                Some(ast::JoinConstraint::Natural) => return Err(unsupported("NATURAL join")),
+            };
+            relation = TableRef::Join {
+                left: Box::new(relation),
+                right: Box::new(right),
+                kind,
+                on,
+            };
+        }
+        Ok(relation)
+    }
+
+    /// `USING (a, b)` compares the columns of the right relation with those
+    /// of the last relation joined before it
+    fn using_condition(left: &TableRef, right: &TableRef, columns: &[ast::Ident]) -> Result<Expr> {
+        let (Some(left), Some(right)) = (left.qualifiers().pop(), right.qualifiers().pop()) else {
+            return Err(unsupported("USING join"));
+        };
+        columns
+            .iter()
+            .map(|c| {
+                binary(
+                    Expr::Column(format!("{left}.{}", c.value)),
+                    BinaryOperator::Eq,
+                    Expr::Column(format!("{right}.{}", c.value)),
+                )
+            })
+            .reduce(|acc, eq| binary(acc, BinaryOperator::And, eq))
+            .ok_or_else(|| unsupported("empty USING list"))
+    }
+
+    fn convert_table_factor(&self, factor: &ast::TableFactor) -> Result<TableRef> {
+        match factor {
+            ast::TableFactor::Table {
+                name,
+                alias,
+                args: None,
+                ..
+            } => Ok(TableRef::Table {
+                name: object_name(name),
+                alias: alias.as_ref().map(|a| a.name.value.clone()),
+            }),
+            ast::TableFactor::Derived {
+                subquery, alias, ..
+            } => {
+                let alias = alias.as_ref().ok_or_else(|| {
+                    Error::Query(QueryError::ParseError(
+                        "Subquery in FROM must have an alias".to_string(),
+                    ))
+                })?;
+                Ok(TableRef::Subquery {
+                    query: Box::new(self.convert_query(subquery)?),
+                    alias: alias.name.value.clone(),
+                })
+            }
+            _ => Err(unsupported(format!("table expression: {factor}"))),
+        }
+    }
+
+    /// Convert a LIMIT or OFFSET value
+    fn convert_count(expr: &ast::Expr, clause: &str) -> Result<usize> {
+        match expr {
+            ast::Expr::Value(ast::Value::Number(n, _)) => n.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            Error::Query(QueryError::ParseError(format!(
+                "{clause} must be a non-negative integer"
+            )))
+        })
+    }
+
+    /// Convert an expression
+    pub(super) fn convert_expr(&self, expr: &ast::Expr) -> Result<Expr> {
+        use ast::Expr as E;
+
+        let boxed = |e: &ast::Expr| self.convert_expr(e).map(Box::new);
+        Ok(match expr {
+            E::Identifier(ident) => Expr::Column(ident.value.clone()),
+            E::CompoundIdentifier(idents) => Expr::Column(
+                idents
+                    .iter()
+                    .map(|i| i.value.as_str())
+                    .collect::<Vec<_>>()
+                    .join("."),
+            ),
+            E::Value(value) => Expr::Literal(Self::convert_value(value)?),
+            E::Nested(inner) => self.convert_expr(inner)?,
+            E::BinaryOp { left, op, right } => Expr::BinaryOp {
+                left: boxed(left)?,
+                op: Self::convert_binary_op(op)?,
+                right: boxed(right)?,
+            },
+            E::UnaryOp { op, expr } => self.convert_unary(*op, expr)?,
+            E::IsNull(inner) | E::IsNotNull(inner) => Expr::IsNull {
+                expr: boxed(inner)?,
+                negated: matches!(expr, E::IsNotNull(_)),
+            },
+            E::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: boxed(expr)?,
+                list: list
+                    .iter()
+                    .map(|e| self.convert_expr(e))
+                    .collect::<Result<_>>()?,
+                negated: *negated,
+            },
+            E::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => Expr::InSubquery {
+                expr: boxed(expr)?,
+                subquery: Box::new(self.convert_query(subquery)?),
+                negated: *negated,
+            },
+            E::Exists { subquery, negated } => Expr::Exists {
+                subquery: Box::new(self.convert_query(subquery)?),
+                negated: *negated,
+            },
+            E::Subquery(query) => Expr::Subquery(Box::new(self.convert_query(query)?)),
+            E::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => self.convert_between(expr, *negated, low, high)?,
+            E::Like {
+                negated,
+                expr: value,
+                pattern,
+                escape_char: None,
+            }
+            | E::ILike {
+                negated,
+                expr: value,
+                pattern,
+                escape_char: None,
+            } => Expr::Like {
+                expr: boxed(value)?,
+                pattern: boxed(pattern)?,
+                negated: *negated,
+                case_insensitive: matches!(expr, E::ILike { .. }),
+            },
+            E::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => Expr::Case {
+                operand: operand.as_deref().map(boxed).transpose()?,
+                branches: conditions
+                    .iter()
+                    .zip(results)
+                    .map(|(when, then)| Ok((self.convert_expr(when)?, self.convert_expr(then)?)))
+                    .collect::<Result<_>>()?,
+                else_result: else_result.as_deref().map(boxed).transpose()?,
+            },
+            E::Cast {
+                expr,
+                data_type,
+                format: None,
+            } => Expr::Cast {
+                expr: boxed(expr)?,
+                data_type: Self::convert_data_type(data_type)?,
+            },
+            E::Function(function) => self.convert_function(function)?,
+            _ => return Err(unsupported(format!("expression: {expr}"))),
+        })
+    }
+
+    fn convert_unary(&self, op: ast::UnaryOperator, expr: &ast::Expr) -> Result<Expr> {
+        let op = match op {
+            ast::UnaryOperator::Not => UnaryOperator::Not,
+            ast::UnaryOperator::Minus => UnaryOperator::Neg,
+            ast::UnaryOperator::Plus => return self.convert_expr(expr),
+            _ => return Err(unsupported(format!("operator {op}"))),
+        };
+        Ok(Expr::UnaryOp {
+            op,
+            expr: Box::new(self.convert_expr(expr)?),
+        })
+    }
+
+    /// `expr BETWEEN low AND high` as a pair of comparisons
+    fn convert_between(
+        &self,
+        expr: &ast::Expr,
+        negated: bool,
+        low: &ast::Expr,
+        high: &ast::Expr,
+    ) -> Result<Expr> {
+        let expr = self.convert_expr(expr)?;
+        let range = binary(
+            binary(expr.clone(), BinaryOperator::Ge, self.convert_expr(low)?),
+            BinaryOperator::And,
+            binary(expr, BinaryOperator::Le, self.convert_expr(high)?),
+        );
+        Ok(if negated {
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr: Box::new(range),
+            }
+        } else {
+            range
+        })
+    }
+
+    fn convert_function(&self, function: &ast::Function) -> Result<Expr> {
+        if function.over.is_some() {
+            return Err(unsupported("window function"));
+        }
+        if function.filter.is_some() || !function.order_by.is_empty() {
+            return Err(unsupported(format!("aggregate clause in {function}")));
+        }
+
+        let args = function
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(expr)) => {
+                    self.convert_expr(expr)
+                }
+                ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Wildcard) => Ok(Expr::Wildcard),
+                _ => Err(unsupported(format!("function argument {arg}"))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Expr::Function {
+            name: object_name(&function.name).to_lowercase(),
+            args,
+            distinct: function.distinct,
+        })
+    }
+
+    fn convert_value(value: &ast::Value) -> Result<serde_json::Value> {
+        match value {
+            ast::Value::Number(n, _) => n
+                .parse::<i64>()
+                .map(serde_json::Value::from)
+                .or_else(|_| n.parse::<f64>().map(|f| serde_json::json!(f)))
+                .map_err(|_| Error::Query(QueryError::ParseError(format!("Invalid number: {n}")))),
+            ast::Value::SingleQuotedString(s) | ast::Value::DoubleQuotedString(s) => {
+                Ok(serde_json::Value::String(s.clone()))
+            }
+            ast::Value::Boolean(b) => Ok(serde_json::Value::Bool(*b)),
+            ast::Value::Null => Ok(serde_json::Value::Null),
+            _ => Err(unsupported(format!("literal {value}"))),
+        }
+    }
+
+    fn convert_binary_op(op: &ast::BinaryOperator) -> Result<BinaryOperator> {
+        use ast::BinaryOperator as B;
+
+        Ok(match op {
+            B::Eq => BinaryOperator::Eq,
+            B::NotEq => BinaryOperator::Ne,
+            B::Lt => BinaryOperator::Lt,
+            B::LtEq => BinaryOperator::Le,
+            B::Gt => BinaryOperator::Gt,
+            B::GtEq => BinaryOperator::Ge,
+            B::And => BinaryOperator::And,
+            B::Or => BinaryOperator::Or,
+            B::Plus => BinaryOperator::Plus,
+            B::Minus => BinaryOperator::Minus,
+            B::Multiply => BinaryOperator::Mul,
+            B::Divide => BinaryOperator::Div,
+            B::Modulo => BinaryOperator::Mod,
+            B::StringConcat => BinaryOperator::Concat,
+            _ => return Err(unsupported(format!("operator {op}"))),
+        })
+    }
+
+    fn convert_data_type(data_type: &ast::DataType) -> Result<DataType> {
+        use ast::DataType as T;
+
+        Ok(match data_type {
+            T::TinyInt(_)
+            | T::SmallInt(_)
+            | T::Int(_)
+            | T::Int2(_)
+            | T::Int4(_)
+            | T::Int8(_)
+            | T::Int64
+            | T::Integer(_)
+            | T::BigInt(_) => DataType::Int64,
+            T::Float(_)
+            | T::Float4
+            | T::Float8
+            | T::Float64
+            | T::Real
+            | T::Double
+            | T::DoublePrecision
+            | T::Numeric(_)
+            | T::Decimal(_)
+            | T::Dec(_) => DataType::Float64,
+            T::Char(_)
+            | T::Character(_)
+            | T::Varchar(_)
+            | T::CharVarying(_)
+            | T::CharacterVarying(_)
+            | T::Nvarchar(_)
+            | T::Text
+            | T::String(_)
+            | T::Uuid => DataType::String,
+            T::Bool | T::Boolean => DataType::Boolean,
+            T::Timestamp(..) | T::Datetime(_) | T::Date => DataType::Timestamp,
+            T::JSON | T::JSONB => DataType::Json,
+            _ => return Err(unsupported(format!("type {data_type}"))),
+        })
+    }
+}
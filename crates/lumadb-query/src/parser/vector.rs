@@ -0,0 +1,159 @@
+//! Parsing of LQL vector searches
+//!
+//! ```text
+//! VECTOR SEARCH <collection> [<x>, <y>, ...] [MATCH '<text>'] [[LIMIT] <k>]
+//!     [FUSION RRF | WEIGHTED] [WEIGHTS <vector> <text>]
+//! ```
+//!
+//! With `MATCH` the search is hybrid: the collection's full-text index
+//! ranks documents by BM25 for the text, and that ranking is fused with the
+//! vector ranking, if there is a vector. Quotes inside the text are
+//! doubled, as in SQL.
+
+use lumadb_common::error::{Error, QueryError, Result};
+use lumadb_storage::hybrid::Fusion;
+
+use super::{Ast, Parser, TextMatch};
+
+/// Results of a vector search without a `LIMIT`
+const DEFAULT_K: usize = 10;
+
+fn vector_error(message: impl Into<String>) -> Error {
+    Error::Query(QueryError::ParseError(message.into()))
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(String),
+    Vector(Vec<f32>),
+    Text(String),
+}
+
+/// Split a vector search into words, bracketed vectors and quoted text
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '[' {
+            let end = input[start..]
+                .find(']')
+                .ok_or_else(|| vector_error("Unterminated vector"))?;
+            let components = input[start + 1..start + end]
+                .split(',')
+                .map(str::trim)
+                .filter(|component| !component.is_empty())
+                .map(|component| {
+                    component
+                        .parse::<f32>()
+                        .map_err(|_| vector_error(format!("Invalid vector component: {component}")))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            tokens.push(Token::Vector(components));
+            while chars.next_if(|&(i, _)| i <= start + end).is_some() {}
+        } else if c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\'')) if chars.next_if(|&(_, c)| c == '\'').is_some() => {
+                        text.push('\'');
+                    }
+                    Some((_, '\'')) => break,
+                    Some((_, c)) => text.push(c),
+                    None => return Err(vector_error("Unterminated text")),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else {
+            let mut word = String::new();
+            while let Some((_, c)) =
+                chars.next_if(|&(_, c)| !c.is_whitespace() && c != '[' && c != '\'')
+            {
+                word.push(c);
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+fn number<T: std::str::FromStr>(token: Option<Token>, what: &str) -> Result<T> {
+    match token {
+        Some(Token::Word(word)) => word
+            .parse()
+            .map_err(|_| vector_error(format!("Invalid {what}: {word}"))),
+        _ => Err(vector_error(format!("Expected {what}"))),
+    }
+}
+
+impl Parser {
+    /// Parse what follows `VECTOR` in a vector search
+    pub(super) fn parse_vector_query(query: &str) -> Result<Ast> {
+        let mut tokens = tokenize(query)?.into_iter();
+        match tokens.next() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("SEARCH") => {}
+            _ => return Err(vector_error("Expected VECTOR SEARCH")),
+        }
+        let Some(Token::Word(collection)) = tokens.next() else {
+            return Err(vector_error("Expected a collection to search"));
+        };
+
+        let mut vector = Vec::new();
+        let mut k = DEFAULT_K;
+        let mut text = None;
+        let mut fusion = Fusion::default();
+        let mut weights = (1.0, 1.0);
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Vector(components) => vector = components,
+                Token::Word(word) => match word.to_uppercase().as_str() {
+                    "MATCH" => match tokens.next() {
+                        Some(Token::Text(query)) => text = Some(query),
+                        _ => return Err(vector_error("Expected quoted text after MATCH")),
+                    },
+                    "LIMIT" => k = number(tokens.next(), "limit")?,
+                    "FUSION" => {
+                        fusion = match tokens.next() {
+                            Some(Token::Word(word)) if word.eq_ignore_ascii_case("RRF") => {
+                                Fusion::Rrf
+                            }
+                            Some(Token::Word(word)) if word.eq_ignore_ascii_case("WEIGHTED") => {
+                                Fusion::Weighted
+                            }
+                            _ => return Err(vector_error("Expected FUSION RRF or WEIGHTED")),
+                        }
+                    }
+                    "WEIGHTS" => {
+                        weights = (
+                            number(tokens.next(), "vector weight")?,
+                            number(tokens.next(), "text weight")?,
+                        );
+                    }
+                    _ => {
+                        k = word
+                            .parse()
+                            .map_err(|_| vector_error(format!("Unexpected {word}")))?;
+                    }
+                },
+                Token::Text(_) => return Err(vector_error("Unexpected text without MATCH")),
+            }
+        }
+
+        if vector.is_empty() && text.is_none() {
+            return Err(vector_error("VECTOR SEARCH needs a vector or MATCH text"));
+        }
+        Ok(Ast::VectorSearch {
+            collection,
+            vector,
+            k,
+            text: text.map(|text| TextMatch {
+                text,
+                fusion,
+                vector_weight: weights.0,
+                text_weight: weights.1,
+            }),
+        })
+    }
+}
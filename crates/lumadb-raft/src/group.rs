@@ -0,0 +1,1167 @@
+//! A single Raft consensus group
+//!
+//! Each group runs two background tasks: a ticker driving election timeouts
+//! and leader heartbeats, and an apply loop handing committed entries to the
+//! group's [`StateMachine`] in log order. Protocol state lives behind one
+//! mutex that is never held across an await point.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rand::Rng;
+use tokio::sync::{oneshot, Notify};
+use tracing::{debug, info, warn};
+
+use lumadb_common::config::RaftConfig;
+use lumadb_common::error::{ClusterError, Error, Result};
+use lumadb_common::types::{LogIndex, NodeId, Term};
+
+use crate::membership::Membership;
+use crate::message::{
+    AppendEntriesRequest, AppendEntriesResponse, EntryPayload, InstallSnapshotRequest,
+    InstallSnapshotResponse, LogEntry, RaftMessage, RequestVoteRequest, RequestVoteResponse,
+    Snapshot, SnapshotMeta,
+};
+use crate::state_machine::StateMachine;
+use crate::storage::{HardState, RaftLogStorage};
+use crate::transport::{RaftMessageHandler, RaftTransport};
+
+/// Maximum entries carried by one AppendEntries request
+const MAX_APPEND_BATCH: usize = 512;
+
+/// Maximum entries applied per state machine batch
+const MAX_APPLY_BATCH: usize = 1024;
+
+/// How long a proposal waits to be committed and applied
+const PROPOSAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Raft node role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// Leader's view of a follower
+#[derive(Debug, Clone)]
+struct Progress {
+    next_index: LogIndex,
+    match_index: LogIndex,
+    /// Set while a request is outstanding, so replication stays one
+    /// request deep per peer
+    in_flight: Option<Instant>,
+    last_contact: Instant,
+}
+
+/// A client proposal waiting for its entry to be applied
+struct Proposal {
+    term: Term,
+    tx: oneshot::Sender<Result<Vec<u8>>>,
+}
+
+/// Mutable protocol state
+struct RaftCore {
+    log: RaftLogStorage,
+    role: NodeState,
+    leader_id: Option<NodeId>,
+    commit_index: LogIndex,
+    last_applied: LogIndex,
+    membership: Membership,
+    /// Log index of the entry that set `membership`
+    membership_index: LogIndex,
+    progress: HashMap<NodeId, Progress>,
+    votes: HashSet<NodeId>,
+    election_deadline: Instant,
+    next_heartbeat: Instant,
+}
+
+impl RaftCore {
+    fn term(&self) -> Term {
+        self.log.hard_state().term
+    }
+
+    fn voted_for(&self) -> Option<NodeId> {
+        self.log.hard_state().voted_for
+    }
+
+    fn refresh_membership(&mut self) {
+        let (index, membership) = self.log.membership();
+        self.membership_index = index;
+        self.membership = membership;
+    }
+}
+
+/// A single Raft consensus group
+pub struct RaftGroup {
+    group_id: u64,
+    node_id: NodeId,
+    config: RaftConfig,
+    core: Mutex<RaftCore>,
+    transport: Arc<dyn RaftTransport>,
+    state_machine: Arc<dyn StateMachine>,
+    pending: Mutex<HashMap<LogIndex, Proposal>>,
+    apply_notify: Notify,
+    /// Signalled after every applied batch
+    applied_notify: Notify,
+    /// Held while the state machine is being written (apply or restore)
+    apply_lock: tokio::sync::Mutex<()>,
+    stopped: AtomicBool,
+}
+
+impl RaftGroup {
+    /// Start a group on this node
+    ///
+    /// `initial` is the bootstrap membership, written as the first log entry
+    /// when the log is empty. Every founding member must be started with the
+    /// same set; nodes joining later start with an empty membership and
+    /// learn it from the leader.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log storage cannot be opened or recovered.
+    pub async fn start(
+        group_id: u64,
+        node_id: NodeId,
+        config: &RaftConfig,
+        mut log: RaftLogStorage,
+        initial: Membership,
+        transport: Arc<dyn RaftTransport>,
+        state_machine: Arc<dyn StateMachine>,
+    ) -> Result<Arc<Self>> {
+        if log.last_index() == 0 && !initial.voters.is_empty() {
+            log.append(&[LogEntry {
+                term: 0,
+                index: 1,
+                payload: EntryPayload::Membership(initial),
+            }])?;
+        }
+
+        let snapshot_index = log.snapshot().meta.last_index;
+        if snapshot_index > 0 && !state_machine.is_durable() {
+            state_machine.restore(&log.snapshot().data).await?;
+        }
+
+        // The bootstrap entry is identical on every founding member, so it
+        // is committed by construction
+        let bootstrap_commit = u64::from(log.term(1) == Some(0));
+        let (membership_index, membership) = log.membership();
+        let now = Instant::now();
+
+        let group = Arc::new(Self {
+            group_id,
+            node_id,
+            config: config.clone(),
+            core: Mutex::new(RaftCore {
+                log,
+                role: NodeState::Follower,
+                leader_id: None,
+                commit_index: snapshot_index.max(bootstrap_commit),
+                last_applied: snapshot_index,
+                membership,
+                membership_index,
+                progress: HashMap::new(),
+                votes: HashSet::new(),
+                election_deadline: now,
+                next_heartbeat: now,
+            }),
+            transport,
+            state_machine,
+            pending: Mutex::new(HashMap::new()),
+            apply_notify: Notify::new(),
+            applied_notify: Notify::new(),
+            apply_lock: tokio::sync::Mutex::new(()),
+            stopped: AtomicBool::new(false),
+        });
+
+        let sole_voter = {
+            let mut core = group.core.lock();
+            core.election_deadline = now + group.election_timeout();
+            core.membership.voters.len() == 1
+                && core.membership.voters.contains(&node_id)
+                && !core.membership.is_joint()
+        };
+        // A sole voter elects itself right away instead of waiting out a timeout
+        if sole_voter {
+            group.campaign();
+        }
+
+        info!("Started Raft group {} on node {}", group_id, node_id);
+
+        tokio::spawn(group.clone().run_ticker());
+        tokio::spawn(group.clone().run_apply_loop());
+        group.apply_notify.notify_one();
+
+        Ok(group)
+    }
+
+    /// Group identifier
+    pub fn group_id(&self) -> u64 {
+        self.group_id
+    }
+
+    /// Check if this node is the leader
+    pub fn is_leader(&self) -> bool {
+        self.core.lock().role == NodeState::Leader
+    }
+
+    /// Current role of this node
+    pub fn role(&self) -> NodeState {
+        self.core.lock().role
+    }
+
+    /// Get the current leader
+    pub fn leader(&self) -> Option<NodeId> {
+        self.core.lock().leader_id
+    }
+
+    /// Get the current term
+    pub fn term(&self) -> Term {
+        self.core.lock().term()
+    }
+
+    /// Highest committed log index
+    pub fn commit_index(&self) -> LogIndex {
+        self.core.lock().commit_index
+    }
+
+    /// Highest log index applied to the state machine
+    pub fn applied_index(&self) -> LogIndex {
+        self.core.lock().last_applied
+    }
+
+    /// Index of the latest snapshot
+    pub fn snapshot_index(&self) -> LogIndex {
+        self.core.lock().log.snapshot().meta.last_index
+    }
+
+    /// Membership currently in effect
+    pub fn membership(&self) -> Membership {
+        self.core.lock().membership.clone()
+    }
+
+    // ========================================================================
+    // Client API
+    // ========================================================================
+
+    /// Replicate `data`, returning the state machine's result once applied
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this node is not the leader, or the entry is dropped
+    /// or not applied in time.
+    pub async fn propose(self: &Arc<Self>, data: Vec<u8>) -> Result<Vec<u8>> {
+        let (index, rx) = {
+            let mut core = self.core.lock();
+            Self::check_leader(&core)?;
+            let (tx, rx) = oneshot::channel();
+            let term = core.term();
+            let index = self.append_as_leader(&mut core, EntryPayload::Command(data))?;
+            self.pending.lock().insert(index, Proposal { term, tx });
+            (index, rx)
+        };
+        self.broadcast();
+
+        match tokio::time::timeout(PROPOSAL_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => {
+                Err(ClusterError::ReplicationFailed("proposal dropped".to_string()).into())
+            }
+            Err(_) => {
+                self.pending.lock().remove(&index);
+                Err(
+                    ClusterError::ReplicationFailed(format!("proposal at index {index} timed out"))
+                        .into(),
+                )
+            }
+        }
+    }
+
+    /// Change the voter set through joint consensus, returning once the
+    /// final configuration is committed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `voters` is empty, this node is not the leader,
+    /// another change is in progress, or the change is not committed.
+    pub async fn change_membership(self: &Arc<Self>, voters: BTreeSet<NodeId>) -> Result<()> {
+        if voters.is_empty() {
+            return Err(
+                ClusterError::ConsensusFailed("membership cannot be empty".to_string()).into(),
+            );
+        }
+        {
+            let mut core = self.core.lock();
+            Self::check_leader(&core)?;
+            if core.membership.is_joint() || core.membership_index > core.commit_index {
+                return Err(ClusterError::ConsensusFailed(
+                    "membership change already in progress".to_string(),
+                )
+                .into());
+            }
+            if core.membership.voters == voters {
+                return Ok(());
+            }
+            let joint = core.membership.joint(voters.clone());
+            info!(
+                "Group {}: entering joint configuration {:?} -> {:?}",
+                self.group_id, core.membership.voters, voters
+            );
+            self.append_as_leader(&mut core, EntryPayload::Membership(joint))?;
+        }
+        self.broadcast();
+
+        let deadline = Instant::now() + PROPOSAL_TIMEOUT;
+        loop {
+            let notified = self.applied_notify.notified();
+            {
+                let core = self.core.lock();
+                if !core.membership.is_joint()
+                    && core.membership.voters == voters
+                    && core.last_applied >= core.membership_index
+                {
+                    return Ok(());
+                }
+                if core.role != NodeState::Leader && core.membership.voters.contains(&self.node_id)
+                {
+                    return Self::check_leader(&core);
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || tokio::time::timeout(remaining, notified).await.is_err() {
+                return Err(ClusterError::ReplicationFailed(
+                    "membership change timed out".to_string(),
+                )
+                .into());
+            }
+        }
+    }
+
+    /// Start an election immediately
+    pub fn campaign(self: &Arc<Self>) {
+        let (request, peers) = {
+            let mut core = self.core.lock();
+            if !core.membership.is_voter(self.node_id) || core.role == NodeState::Leader {
+                return;
+            }
+            let term = core.term() + 1;
+            if let Err(e) = core.log.set_hard_state(HardState {
+                term,
+                voted_for: Some(self.node_id),
+            }) {
+                warn!("Group {}: failed to persist vote: {}", self.group_id, e);
+                return;
+            }
+            debug!(
+                "Group {}: node {} campaigning in term {}",
+                self.group_id, self.node_id, term
+            );
+            core.role = NodeState::Candidate;
+            core.leader_id = None;
+            core.votes = HashSet::from([self.node_id]);
+            core.election_deadline = Instant::now() + self.election_timeout();
+
+            let votes = core.votes.clone();
+            if core.membership.has_quorum(|n| votes.contains(&n)) {
+                let became = self.become_leader(&mut core);
+                drop(core);
+                if became {
+                    self.broadcast();
+                }
+                return;
+            }
+
+            let request = RequestVoteRequest {
+                term,
+                candidate_id: self.node_id,
+                last_log_index: core.log.last_index(),
+                last_log_term: core.log.last_term(),
+            };
+            let mut peers = core.membership.all_voters();
+            peers.remove(&self.node_id);
+            (request, peers)
+        };
+
+        for peer in peers {
+            let this = self.clone();
+            let request = request.clone();
+            tokio::spawn(async move {
+                let term = request.term;
+                let reply = this
+                    .send_with_timeout(peer, RaftMessage::RequestVote(request))
+                    .await;
+                if let Ok(RaftMessage::RequestVoteResponse(response)) = reply {
+                    this.handle_vote_response(peer, term, &response);
+                }
+            });
+        }
+    }
+
+    /// Take a snapshot of everything applied so far and compact the log
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot cannot be taken or saved.
+    pub async fn snapshot(&self) -> Result<()> {
+        let _guard = self.apply_lock.lock().await;
+        self.take_snapshot().await
+    }
+
+    /// Stop background tasks and fail outstanding proposals
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.apply_notify.notify_one();
+        for (_, proposal) in self.pending.lock().drain() {
+            let _ = proposal.tx.send(Err(ClusterError::ReplicationFailed(
+                "raft group shut down".to_string(),
+            )
+            .into()));
+        }
+    }
+
+    // ========================================================================
+    // Message handling
+    // ========================================================================
+
+    /// Handle an RPC from a peer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a request or cannot be persisted
+    /// or applied.
+    pub async fn handle_message(&self, message: RaftMessage) -> Result<RaftMessage> {
+        match message {
+            RaftMessage::RequestVote(request) => Ok(RaftMessage::RequestVoteResponse(
+                self.handle_request_vote(&request)?,
+            )),
+            RaftMessage::AppendEntries(request) => Ok(RaftMessage::AppendEntriesResponse(
+                self.handle_append_entries(request)?,
+            )),
+            RaftMessage::InstallSnapshot(request) => Ok(RaftMessage::InstallSnapshotResponse(
+                self.handle_install_snapshot(request).await?,
+            )),
+            other => Err(Error::Internal(format!(
+                "unexpected raft request: {other:?}"
+            ))),
+        }
+    }
+
+    fn handle_request_vote(&self, request: &RequestVoteRequest) -> Result<RequestVoteResponse> {
+        let mut core = self.core.lock();
+        if request.term > core.term() {
+            self.become_follower(&mut core, request.term, None)?;
+        }
+
+        let log_ok = (request.last_log_term, request.last_log_index)
+            >= (core.log.last_term(), core.log.last_index());
+        let grant = request.term == core.term()
+            && core.voted_for().map_or(true, |v| v == request.candidate_id)
+            && log_ok;
+
+        if grant {
+            let term = core.term();
+            core.log.set_hard_state(HardState {
+                term,
+                voted_for: Some(request.candidate_id),
+            })?;
+            core.election_deadline = Instant::now() + self.election_timeout();
+        }
+
+        Ok(RequestVoteResponse {
+            term: core.term(),
+            vote_granted: grant,
+        })
+    }
+
+    fn handle_append_entries(
+        &self,
+        request: AppendEntriesRequest,
+    ) -> Result<AppendEntriesResponse> {
+        let mut core = self.core.lock();
+        let reject = |term, conflict_index| AppendEntriesResponse {
+            term,
+            success: false,
+            match_index: 0,
+            conflict_index,
+        };
+
+        if request.term < core.term() {
+            return Ok(reject(core.term(), 0));
+        }
+        if request.term > core.term() || core.role != NodeState::Follower {
+            self.become_follower(&mut core, request.term, Some(request.leader_id))?;
+        }
+        core.leader_id = Some(request.leader_id);
+        core.election_deadline = Instant::now() + self.election_timeout();
+
+        let term = core.term();
+        let snapshot_index = core.log.snapshot().meta.last_index;
+        let prev = request.prev_log_index;
+
+        // Consistency check; entries covered by our snapshot are committed
+        // and therefore match
+        if prev > core.log.last_index() {
+            return Ok(reject(term, core.log.last_index() + 1));
+        }
+        if prev > snapshot_index {
+            let local_term = core.log.term(prev);
+            if local_term != Some(request.prev_log_term) {
+                // Skip back over the whole conflicting term
+                let mut conflict = prev;
+                while conflict > snapshot_index + 1 && core.log.term(conflict - 1) == local_term {
+                    conflict -= 1;
+                }
+                return Ok(reject(term, conflict));
+            }
+        }
+
+        let last_new = prev + request.entries.len() as LogIndex;
+        let mut to_append = Vec::new();
+        let mut config_changed = false;
+        for entry in request.entries {
+            if entry.index <= snapshot_index {
+                continue;
+            }
+            if to_append.is_empty() {
+                match core.log.term(entry.index) {
+                    Some(t) if t == entry.term => continue,
+                    Some(_) => {
+                        if entry.index <= core.commit_index {
+                            return Err(Error::Internal(format!(
+                                "raft group {}: leader conflicts with committed entry {}",
+                                self.group_id, entry.index
+                            )));
+                        }
+                        core.log.truncate_from(entry.index)?;
+                        self.fail_pending_from(entry.index);
+                        config_changed = true;
+                    }
+                    None => {}
+                }
+            }
+            config_changed |= matches!(entry.payload, EntryPayload::Membership(_));
+            to_append.push(entry);
+        }
+        core.log.append(&to_append)?;
+        if config_changed {
+            core.refresh_membership();
+        }
+
+        let match_index = last_new.max(snapshot_index);
+        let commit = request.leader_commit.min(match_index);
+        if commit > core.commit_index {
+            core.commit_index = commit;
+            self.apply_notify.notify_one();
+        }
+
+        Ok(AppendEntriesResponse {
+            term,
+            success: true,
+            match_index,
+            conflict_index: 0,
+        })
+    }
+
+    async fn handle_install_snapshot(
+        &self,
+        request: InstallSnapshotRequest,
+    ) -> Result<InstallSnapshotResponse> {
+        {
+            let mut core = self.core.lock();
+            if request.term < core.term() {
+                return Ok(InstallSnapshotResponse { term: core.term() });
+            }
+            if request.term > core.term() || core.role != NodeState::Follower {
+                self.become_follower(&mut core, request.term, Some(request.leader_id))?;
+            }
+            core.leader_id = Some(request.leader_id);
+            core.election_deadline = Instant::now() + self.election_timeout();
+            if request.snapshot.meta.last_index <= core.last_applied {
+                return Ok(InstallSnapshotResponse { term: core.term() });
+            }
+        }
+
+        let _guard = self.apply_lock.lock().await;
+        let index = request.snapshot.meta.last_index;
+        if index <= self.core.lock().last_applied {
+            return Ok(InstallSnapshotResponse { term: self.term() });
+        }
+
+        info!(
+            "Group {}: installing snapshot at index {}",
+            self.group_id, index
+        );
+        self.state_machine.restore(&request.snapshot.data).await?;
+
+        let mut core = self.core.lock();
+        core.log.save_snapshot(request.snapshot)?;
+        core.commit_index = core.commit_index.max(index);
+        core.last_applied = index;
+        core.refresh_membership();
+        self.apply_notify.notify_one();
+        Ok(InstallSnapshotResponse { term: core.term() })
+    }
+
+    fn handle_vote_response(
+        self: &Arc<Self>,
+        peer: NodeId,
+        term: Term,
+        response: &RequestVoteResponse,
+    ) {
+        let mut core = self.core.lock();
+        if response.term > core.term() {
+            if let Err(e) = self.become_follower(&mut core, response.term, None) {
+                warn!("Group {}: {}", self.group_id, e);
+            }
+            return;
+        }
+        if core.role != NodeState::Candidate || core.term() != term || !response.vote_granted {
+            return;
+        }
+
+        core.votes.insert(peer);
+        let votes = core.votes.clone();
+        if core.membership.has_quorum(|n| votes.contains(&n)) && self.become_leader(&mut core) {
+            drop(core);
+            self.broadcast();
+        }
+    }
+
+    // ========================================================================
+    // Replication
+    // ========================================================================
+
+    /// Send AppendEntries (or a snapshot) to every peer without a request
+    /// already in flight
+    fn broadcast(self: &Arc<Self>) {
+        let peers: Vec<NodeId> = {
+            let mut core = self.core.lock();
+            if core.role != NodeState::Leader {
+                return;
+            }
+            core.next_heartbeat =
+                Instant::now() + Duration::from_millis(self.config.heartbeat_interval_ms);
+            core.progress.keys().copied().collect()
+        };
+        for peer in peers {
+            self.replicate_to(peer);
+        }
+    }
+
+    fn replicate_to(self: &Arc<Self>, peer: NodeId) {
+        let (message, term, snapshot_index) = {
+            let mut core = self.core.lock();
+            if core.role != NodeState::Leader {
+                return;
+            }
+            let now = Instant::now();
+            let rpc_timeout = self.rpc_timeout();
+            let Some(progress) = core.progress.get_mut(&peer) else {
+                return;
+            };
+            if progress
+                .in_flight
+                .is_some_and(|sent| now.duration_since(sent) < rpc_timeout)
+            {
+                return;
+            }
+            progress.in_flight = Some(now);
+            let next = progress.next_index;
+
+            let term = core.term();
+            if next < core.log.first_index() {
+                let snapshot = core.log.snapshot().clone();
+                let index = snapshot.meta.last_index;
+                let message = RaftMessage::InstallSnapshot(InstallSnapshotRequest {
+                    term,
+                    leader_id: self.node_id,
+                    snapshot,
+                });
+                (message, term, Some(index))
+            } else {
+                let prev_log_index = next - 1;
+                let message = RaftMessage::AppendEntries(AppendEntriesRequest {
+                    term,
+                    leader_id: self.node_id,
+                    prev_log_index,
+                    prev_log_term: core.log.term(prev_log_index).unwrap_or(0),
+                    entries: core.log.entries(next, LogIndex::MAX, MAX_APPEND_BATCH),
+                    leader_commit: core.commit_index,
+                });
+                (message, term, None)
+            }
+        };
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let reply = this.send_with_timeout(peer, message).await;
+            this.handle_replication_reply(peer, term, snapshot_index, reply);
+        });
+    }
+
+    fn handle_replication_reply(
+        self: &Arc<Self>,
+        peer: NodeId,
+        term: Term,
+        snapshot_index: Option<LogIndex>,
+        reply: Result<RaftMessage>,
+    ) {
+        let (again, committed) = {
+            let mut core = self.core.lock();
+            if let Some(progress) = core.progress.get_mut(&peer) {
+                progress.in_flight = None;
+            }
+
+            let reply_term = match reply {
+                Ok(RaftMessage::AppendEntriesResponse(ref r)) => r.term,
+                Ok(RaftMessage::InstallSnapshotResponse(ref r)) => r.term,
+                Ok(_) => return,
+                Err(e) => {
+                    debug!(
+                        "Group {}: replication to node {} failed: {}",
+                        self.group_id, peer, e
+                    );
+                    return;
+                }
+            };
+            if reply_term > core.term() {
+                if let Err(e) = self.become_follower(&mut core, reply_term, None) {
+                    warn!("Group {}: {}", self.group_id, e);
+                }
+                return;
+            }
+            if core.role != NodeState::Leader || core.term() != term {
+                return;
+            }
+
+            let last_index = core.log.last_index();
+            let Some(progress) = core.progress.get_mut(&peer) else {
+                return;
+            };
+            progress.last_contact = Instant::now();
+
+            match reply {
+                Ok(RaftMessage::AppendEntriesResponse(response)) if response.success => {
+                    progress.match_index = progress.match_index.max(response.match_index);
+                    progress.next_index = progress.match_index + 1;
+                }
+                Ok(RaftMessage::AppendEntriesResponse(response)) => {
+                    progress.next_index = response
+                        .conflict_index
+                        .min(progress.next_index.saturating_sub(1))
+                        .max(progress.match_index + 1);
+                }
+                Ok(RaftMessage::InstallSnapshotResponse(_)) => {
+                    if let Some(index) = snapshot_index {
+                        progress.match_index = progress.match_index.max(index);
+                        progress.next_index = progress.match_index + 1;
+                    }
+                }
+                _ => {}
+            }
+            let again = progress.next_index <= last_index;
+            (again, self.advance_commit(&mut core))
+        };
+
+        if committed {
+            self.apply_notify.notify_one();
+        }
+        if again {
+            self.replicate_to(peer);
+        }
+    }
+
+    /// Advance the commit index to the highest entry of the current term
+    /// stored on a quorum
+    fn advance_commit(&self, core: &mut RaftCore) -> bool {
+        let term = core.term();
+        let mut index = core.log.last_index();
+        while index > core.commit_index {
+            match core.log.term(index) {
+                Some(t) if t == term => {
+                    let progress = &core.progress;
+                    let replicated = core.membership.has_quorum(|n| {
+                        n == self.node_id
+                            || progress.get(&n).is_some_and(|p| p.match_index >= index)
+                    });
+                    if replicated {
+                        core.commit_index = index;
+                        return true;
+                    }
+                }
+                // Earlier entries belong to earlier terms too
+                Some(t) if t < term => break,
+                _ => {}
+            }
+            index -= 1;
+        }
+        false
+    }
+
+    // ========================================================================
+    // Role transitions
+    // ========================================================================
+
+    fn become_follower(
+        &self,
+        core: &mut RaftCore,
+        term: Term,
+        leader: Option<NodeId>,
+    ) -> Result<()> {
+        if term > core.term() {
+            core.log.set_hard_state(HardState {
+                term,
+                voted_for: None,
+            })?;
+        }
+        if core.role == NodeState::Leader {
+            info!(
+                "Group {}: node {} stepping down in term {}",
+                self.group_id, self.node_id, term
+            );
+        }
+        core.role = NodeState::Follower;
+        core.leader_id = leader;
+        core.progress.clear();
+        core.votes.clear();
+        core.election_deadline = Instant::now() + self.election_timeout();
+        Ok(())
+    }
+
+    /// Returns whether the transition succeeded (the no-op entry could be
+    /// persisted)
+    fn become_leader(&self, core: &mut RaftCore) -> bool {
+        info!(
+            "Group {}: node {} elected leader for term {}",
+            self.group_id,
+            self.node_id,
+            core.term()
+        );
+        core.role = NodeState::Leader;
+        core.leader_id = Some(self.node_id);
+        core.votes.clear();
+        core.progress.clear();
+        self.sync_progress(core);
+
+        // Committing a no-op from the new term commits everything before it
+        if let Err(e) = self.append_as_leader(core, EntryPayload::Noop) {
+            warn!("Group {}: failed to append no-op: {}", self.group_id, e);
+            let term = core.term();
+            let _ = self.become_follower(core, term, None);
+            return false;
+        }
+        true
+    }
+
+    /// Track every voter of the current membership
+    fn sync_progress(&self, core: &mut RaftCore) {
+        let next_index = core.log.last_index() + 1;
+        let now = Instant::now();
+        for node in core.membership.all_voters() {
+            if node != self.node_id {
+                core.progress.entry(node).or_insert(Progress {
+                    next_index,
+                    match_index: 0,
+                    in_flight: None,
+                    last_contact: now,
+                });
+            }
+        }
+    }
+
+    fn append_as_leader(&self, core: &mut RaftCore, payload: EntryPayload) -> Result<LogIndex> {
+        let index = core.log.last_index() + 1;
+        let is_config = matches!(payload, EntryPayload::Membership(_));
+        let entry = LogEntry {
+            term: core.term(),
+            index,
+            payload,
+        };
+        core.log.append(&[entry])?;
+        if is_config {
+            core.refresh_membership();
+            self.sync_progress(core);
+        }
+        if self.advance_commit(core) {
+            self.apply_notify.notify_one();
+        }
+        Ok(index)
+    }
+
+    fn check_leader(core: &RaftCore) -> Result<()> {
+        if core.role == NodeState::Leader {
+            Ok(())
+        } else {
+            Err(match core.leader_id {
+                Some(leader) => ClusterError::NotLeader(leader.to_string()),
+                None => ClusterError::NoLeader,
+            }
+            .into())
+        }
+    }
+
+    // ========================================================================
+    // Background tasks
+    // ========================================================================
+
+    async fn run_ticker(self: Arc<Self>) {
+        let tick = Duration::from_millis((self.config.heartbeat_interval_ms / 2).max(1));
+        while !self.stopped.load(Ordering::SeqCst) {
+            tokio::time::sleep(tick).await;
+            self.tick();
+        }
+    }
+
+    fn tick(self: &Arc<Self>) {
+        let now = Instant::now();
+        let mut campaign = false;
+        let mut heartbeat = false;
+        {
+            let mut core = self.core.lock();
+            match core.role {
+                NodeState::Leader => {
+                    // Step down when a quorum has been unreachable for a full
+                    // election timeout, so a partitioned leader stops
+                    // accepting proposals
+                    let window = Duration::from_millis(self.config.election_timeout_max_ms);
+                    let progress = &core.progress;
+                    let in_contact = core.membership.has_quorum(|n| {
+                        n == self.node_id
+                            || progress
+                                .get(&n)
+                                .is_some_and(|p| now.duration_since(p.last_contact) < window)
+                    });
+                    if !in_contact {
+                        let term = core.term();
+                        if let Err(e) = self.become_follower(&mut core, term, None) {
+                            warn!("Group {}: {}", self.group_id, e);
+                        }
+                    } else if now >= core.next_heartbeat {
+                        heartbeat = true;
+                    }
+                }
+                NodeState::Follower | NodeState::Candidate => {
+                    campaign =
+                        now >= core.election_deadline && core.membership.is_voter(self.node_id);
+                }
+            }
+        }
+        if heartbeat {
+            self.broadcast();
+        }
+        if campaign {
+            self.campaign();
+        }
+    }
+
+    async fn run_apply_loop(self: Arc<Self>) {
+        loop {
+            self.apply_notify.notified().await;
+            if self.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            if let Err(e) = self.apply_committed().await {
+                warn!("Group {}: apply failed: {}", self.group_id, e);
+            }
+        }
+    }
+
+    async fn apply_committed(self: &Arc<Self>) -> Result<()> {
+        let _guard = self.apply_lock.lock().await;
+        loop {
+            let entries = {
+                let core = self.core.lock();
+                if core.last_applied >= core.commit_index {
+                    break;
+                }
+                core.log.entries(
+                    core.last_applied + 1,
+                    core.commit_index + 1,
+                    MAX_APPLY_BATCH,
+                )
+            };
+            if entries.is_empty() {
+                break;
+            }
+
+            for entry in entries {
+                let result = match entry.payload {
+                    EntryPayload::Command(ref data) => {
+                        self.state_machine.apply(entry.index, data).await
+                    }
+                    EntryPayload::Membership(ref membership) => {
+                        self.on_membership_applied(membership);
+                        Ok(Vec::new())
+                    }
+                    EntryPayload::Noop => Ok(Vec::new()),
+                };
+
+                if let Some(proposal) = self.pending.lock().remove(&entry.index) {
+                    let result = if proposal.term == entry.term {
+                        result
+                    } else {
+                        Err(ClusterError::ReplicationFailed(format!(
+                            "entry {} was overwritten by a newer leader",
+                            entry.index
+                        ))
+                        .into())
+                    };
+                    let _ = proposal.tx.send(result);
+                } else if let Err(e) = result {
+                    debug!(
+                        "Group {}: entry {} failed to apply: {}",
+                        self.group_id, entry.index, e
+                    );
+                }
+
+                self.core.lock().last_applied = entry.index;
+            }
+            self.applied_notify.notify_waiters();
+        }
+
+        self.maybe_snapshot().await
+    }
+
+    /// Leaders finish a joint change once it commits, and step down once a
+    /// configuration without them commits
+    fn on_membership_applied(self: &Arc<Self>, membership: &Membership) {
+        let mut broadcast = false;
+        {
+            let mut core = self.core.lock();
+            if core.role != NodeState::Leader || core.membership != *membership {
+                return;
+            }
+            if membership.is_joint() {
+                info!(
+                    "Group {}: leaving joint configuration for {:?}",
+                    self.group_id, membership.voters
+                );
+                match self.append_as_leader(
+                    &mut core,
+                    EntryPayload::Membership(membership.leave_joint()),
+                ) {
+                    Ok(_) => broadcast = true,
+                    Err(e) => warn!(
+                        "Group {}: failed to leave joint configuration: {}",
+                        self.group_id, e
+                    ),
+                }
+            } else {
+                let voters = membership.voters.clone();
+                core.progress.retain(|node, _| voters.contains(node));
+                if !voters.contains(&self.node_id) {
+                    let term = core.term();
+                    if let Err(e) = self.become_follower(&mut core, term, None) {
+                        warn!("Group {}: {}", self.group_id, e);
+                    }
+                }
+            }
+        }
+        if broadcast {
+            self.broadcast();
+        }
+    }
+
+    async fn maybe_snapshot(&self) -> Result<()> {
+        let due = {
+            let core = self.core.lock();
+            let snapshot_index = core.log.snapshot().meta.last_index;
+            let since_snapshot = core.last_applied - snapshot_index;
+            let log_len = core.log.last_index() - snapshot_index;
+            since_snapshot > 0
+                && (since_snapshot >= self.config.snapshot_interval
+                    || log_len >= self.config.max_log_entries)
+        };
+        if due {
+            self.take_snapshot().await?;
+        }
+        Ok(())
+    }
+
+    /// Caller must hold `apply_lock` so the state machine matches
+    /// `last_applied`
+    async fn take_snapshot(&self) -> Result<()> {
+        let index = {
+            let core = self.core.lock();
+            if core.last_applied <= core.log.snapshot().meta.last_index {
+                return Ok(());
+            }
+            core.last_applied
+        };
+
+        let data = self.state_machine.snapshot().await?;
+
+        let mut core = self.core.lock();
+        let term = core
+            .log
+            .term(index)
+            .ok_or_else(|| Error::Internal(format!("raft entry {index} missing for snapshot")))?;
+        let (_, membership) = core.log.membership_at(index);
+        core.log.save_snapshot(Snapshot {
+            meta: SnapshotMeta {
+                last_index: index,
+                last_term: term,
+                membership,
+            },
+            data,
+        })?;
+        debug!("Group {}: snapshot at index {}", self.group_id, index);
+        Ok(())
+    }
+
+    // ========================================================================
+    // Helpers
+    // ========================================================================
+
+    fn election_timeout(&self) -> Duration {
+        let min = self.config.election_timeout_min_ms;
+        let max = self.config.election_timeout_max_ms.max(min);
+        Duration::from_millis(rand::thread_rng().gen_range(min..=max))
+    }
+
+    fn rpc_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.election_timeout_min_ms)
+    }
+
+    async fn send_with_timeout(&self, peer: NodeId, message: RaftMessage) -> Result<RaftMessage> {
+        let send = self.transport.send(peer, self.group_id, message);
+        tokio::time::timeout(self.rpc_timeout(), send)
+            .await
+            .map_err(|_| {
+                Error::Cluster(ClusterError::ReplicationFailed(format!(
+                    "rpc to node {peer} timed out"
+                )))
+            })?
+    }
+
+    fn fail_pending_from(&self, index: LogIndex) {
+        let mut pending = self.pending.lock();
+        let truncated: Vec<LogIndex> = pending.keys().copied().filter(|&i| i >= index).collect();
+        for i in truncated {
+            if let Some(proposal) = pending.remove(&i) {
+                let _ = proposal
+                    .tx
+                    .send(Err(ClusterError::ReplicationFailed(format!(
+                        "entry {i} was overwritten by a newer leader"
+                    ))
+                    .into()));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RaftMessageHandler for RaftGroup {
+    async fn handle(&self, group_id: u64, message: RaftMessage) -> Result<RaftMessage> {
+        if group_id != self.group_id {
+            return Err(
+                ClusterError::PartitionUnavailable(format!("raft group {group_id}")).into(),
+            );
+        }
+        self.handle_message(message).await
+    }
+}
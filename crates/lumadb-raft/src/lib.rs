@@ -1,158 +1,296 @@
 //! Multi-Raft consensus implementation
 //!
-//! Provides distributed consensus for LumaDB clusters.
+//! Provides distributed consensus for LumaDB clusters. A node hosts many
+//! independent [`RaftGroup`]s (one per shard plus a default data group); each
+//! replicates a durable log, elects its own leader and applies committed
+//! entries to a [`StateMachine`].
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
+pub mod group;
+pub mod membership;
+pub mod message;
+pub mod state_machine;
+pub mod storage;
+pub mod transport;
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use dashmap::DashMap;
 use parking_lot::RwLock;
-use tokio::sync::mpsc;
-use tracing::{info, debug, warn};
+use tracing::{info, warn};
 
 use lumadb_common::config::RaftConfig;
-use lumadb_common::error::Result;
-use lumadb_common::types::{NodeId, Term, LogIndex};
+use lumadb_common::error::{Error, Result};
+use lumadb_common::types::NodeId;
 use lumadb_storage::StorageEngine;
 
+pub use group::{NodeState, RaftGroup};
+pub use membership::Membership;
+pub use message::{EntryPayload, LogEntry, RaftMessage, Snapshot, SnapshotMeta};
+pub use state_machine::{DocumentFilter, StateMachine, StorageCommand, StorageStateMachine};
+pub use storage::{HardState, RaftLogStorage};
+pub use transport::{
+    InProcessNetwork, InProcessTransport, RaftMessageHandler, RaftTransport, TcpTransport,
+};
+
+/// Group used for writes that are not routed to a shard
+pub const DEFAULT_GROUP: u64 = 0;
+
+/// Builds the state machine for a group hosted on this node
+pub type StateMachineFactory = Arc<dyn Fn(u64) -> Arc<dyn StateMachine> + Send + Sync>;
+
 /// Raft engine managing multiple Raft groups
 pub struct RaftEngine {
     config: RaftConfig,
     node_id: NodeId,
+    /// Founding voters of groups created without an explicit membership
+    voters: BTreeSet<NodeId>,
     groups: DashMap<u64, Arc<RaftGroup>>,
+    /// Serializes group creation, which awaits storage recovery
+    create_lock: tokio::sync::Mutex<()>,
     storage: Arc<StorageEngine>,
+    transport: Arc<dyn RaftTransport>,
+    /// State machines for groups created implicitly; defaults to the whole
+    /// storage engine
+    state_machine_factory: RwLock<Option<StateMachineFactory>>,
     running: Arc<RwLock<bool>>,
 }
 
 impl RaftEngine {
     /// Create a new Raft engine
-    pub async fn new(config: &RaftConfig, storage: Arc<StorageEngine>) -> Result<Self> {
-        info!("Initializing Raft engine");
+    ///
+    /// Peers listed in the configuration are reached over TCP; without peers
+    /// the node forms single-voter groups.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a configured peer address is invalid.
+    #[allow(clippy::unused_async)]
+    pub async fn new(
+        node_id: NodeId,
+        config: &RaftConfig,
+        storage: Arc<StorageEngine>,
+    ) -> Result<Self> {
+        info!("Initializing Raft engine on node {}", node_id);
+
+        let peers = parse_peers(&config.peers)?;
+        let transport: Arc<dyn RaftTransport> = if peers.is_empty() {
+            InProcessNetwork::new().transport(node_id)
+        } else {
+            Arc::new(TcpTransport::new(
+                peers.clone(),
+                Duration::from_millis(config.election_timeout_min_ms),
+            ))
+        };
+
+        let mut voters: BTreeSet<NodeId> = peers.keys().copied().collect();
+        voters.insert(node_id);
+
+        Ok(Self::with_transport(
+            node_id, voters, config, storage, transport,
+        ))
+    }
 
-        Ok(Self {
+    /// Create an engine over an explicit transport
+    pub fn with_transport(
+        node_id: NodeId,
+        voters: BTreeSet<NodeId>,
+        config: &RaftConfig,
+        storage: Arc<StorageEngine>,
+        transport: Arc<dyn RaftTransport>,
+    ) -> Self {
+        Self {
             config: config.clone(),
-            node_id: 1, // Would be configured in production
+            node_id,
+            voters,
             groups: DashMap::new(),
+            create_lock: tokio::sync::Mutex::new(()),
             storage,
+            transport,
+            state_machine_factory: RwLock::new(None),
             running: Arc::new(RwLock::new(true)),
-        })
+        }
     }
 
-    /// Get or create a Raft group
-    pub fn get_or_create_group(&self, group_id: u64) -> Arc<RaftGroup> {
-        self.groups
-            .entry(group_id)
-            .or_insert_with(|| Arc::new(RaftGroup::new(group_id, self.node_id, &self.config)))
-            .clone()
+    /// This node's identifier
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
     }
 
-    /// Propose a value to a Raft group
-    pub async fn propose(&self, group_id: u64, data: Vec<u8>) -> Result<()> {
-        let group = self.get_or_create_group(group_id);
-        group.propose(data).await
+    /// Founding voters used for groups without an explicit membership
+    pub fn default_voters(&self) -> &BTreeSet<NodeId> {
+        &self.voters
     }
 
-    /// Shutdown the Raft engine
-    pub async fn shutdown(&self) -> Result<()> {
-        info!("Shutting down Raft engine");
-        *self.running.write() = false;
-        Ok(())
+    /// Storage engine the default state machines apply to
+    pub fn storage(&self) -> &Arc<StorageEngine> {
+        &self.storage
     }
-}
 
-/// A single Raft consensus group
-pub struct RaftGroup {
-    group_id: u64,
-    node_id: NodeId,
-    state: RwLock<RaftState>,
-    log: RwLock<Vec<LogEntry>>,
-}
+    /// Serve Raft RPCs on the configured listen address, if any
+    pub fn start_listener(self: &Arc<Self>) {
+        let Some(addr) = self.config.listen_addr.clone() else {
+            return;
+        };
+        let handler: Arc<dyn RaftMessageHandler> = self.clone();
+        tokio::spawn(async move {
+            info!("Raft listener on {}", addr);
+            if let Err(e) = transport::serve(&addr, handler).await {
+                warn!("Raft listener on {} stopped: {}", addr, e);
+            }
+        });
+    }
 
-/// Raft node state
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum NodeState {
-    Follower,
-    Candidate,
-    Leader,
-}
+    /// Get a hosted group
+    pub fn group(&self, group_id: u64) -> Option<Arc<RaftGroup>> {
+        self.groups.get(&group_id).map(|g| g.clone())
+    }
 
-/// Raft state
-struct RaftState {
-    current_term: Term,
-    voted_for: Option<NodeId>,
-    state: NodeState,
-    commit_index: LogIndex,
-    last_applied: LogIndex,
-    leader_id: Option<NodeId>,
-}
+    /// Identifiers of all hosted groups
+    pub fn group_ids(&self) -> Vec<u64> {
+        self.groups.iter().map(|g| *g.key()).collect()
+    }
 
-/// Log entry
-#[derive(Debug, Clone)]
-struct LogEntry {
-    term: Term,
-    index: LogIndex,
-    data: Vec<u8>,
-}
+    /// Set how state machines are built for implicitly created groups
+    pub fn set_state_machine_factory(&self, factory: StateMachineFactory) {
+        *self.state_machine_factory.write() = Some(factory);
+    }
 
-impl RaftGroup {
-    fn new(group_id: u64, node_id: NodeId, config: &RaftConfig) -> Self {
-        Self {
-            group_id,
-            node_id,
-            state: RwLock::new(RaftState {
-                current_term: 0,
-                voted_for: None,
-                state: NodeState::Follower,
-                commit_index: 0,
-                last_applied: 0,
-                leader_id: None,
-            }),
-            log: RwLock::new(Vec::new()),
+    fn state_machine_for(&self, group_id: u64) -> Arc<dyn StateMachine> {
+        match *self.state_machine_factory.read() {
+            Some(ref factory) => factory(group_id),
+            None => Arc::new(StorageStateMachine::new(self.storage.clone())),
         }
     }
 
-    /// Propose a value
-    async fn propose(&self, data: Vec<u8>) -> Result<()> {
-        let mut state = self.state.write();
+    /// Get or create a Raft group with the default founding voters
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group cannot be started.
+    pub async fn get_or_create_group(&self, group_id: u64) -> Result<Arc<RaftGroup>> {
+        self.create_group(
+            group_id,
+            self.voters.clone(),
+            self.state_machine_for(group_id),
+        )
+        .await
+    }
 
-        // Only leader can propose
-        if state.state != NodeState::Leader {
-            // In single-node mode, become leader immediately
-            state.state = NodeState::Leader;
-            state.leader_id = Some(self.node_id);
+    /// Get or create a group with the given founding voters and state
+    /// machine. Nodes joining an existing group pass an empty voter set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group cannot be started.
+    pub async fn create_group(
+        &self,
+        group_id: u64,
+        voters: BTreeSet<NodeId>,
+        state_machine: Arc<dyn StateMachine>,
+    ) -> Result<Arc<RaftGroup>> {
+        if let Some(group) = self.group(group_id) {
+            return Ok(group);
+        }
+        if !*self.running.read() {
+            return Err(Error::Internal("raft engine is shut down".to_string()));
         }
 
-        // Append to log
-        let mut log = self.log.write();
-        let index = log.len() as LogIndex + 1;
-        log.push(LogEntry {
-            term: state.current_term,
-            index,
-            data,
-        });
+        let _guard = self.create_lock.lock().await;
+        if let Some(group) = self.group(group_id) {
+            return Ok(group);
+        }
 
-        // In single-node mode, commit immediately
-        state.commit_index = index;
-        state.last_applied = index;
+        let log = RaftLogStorage::open(&self.group_path(group_id))?;
+        let group = RaftGroup::start(
+            group_id,
+            self.node_id,
+            &self.config,
+            log,
+            Membership::new(voters),
+            self.transport.clone(),
+            state_machine,
+        )
+        .await?;
+        self.groups.insert(group_id, group.clone());
+        Ok(group)
+    }
 
-        Ok(())
+    /// Propose a value to a Raft group
+    pub async fn propose(&self, group_id: u64, data: Vec<u8>) -> Result<()> {
+        let group = self.get_or_create_group(group_id).await?;
+        group.propose(data).await.map(|_| ())
     }
 
-    /// Check if this node is the leader
-    pub fn is_leader(&self) -> bool {
-        self.state.read().state == NodeState::Leader
+    /// Replicate a storage command through a group and return its result
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group cannot be started or the command is not
+    /// committed.
+    pub async fn propose_command(
+        &self,
+        group_id: u64,
+        command: &StorageCommand,
+    ) -> Result<Vec<u8>> {
+        let group = self.get_or_create_group(group_id).await?;
+        group.propose(command.encode()?).await
     }
 
-    /// Get the current leader
-    pub fn leader(&self) -> Option<NodeId> {
-        self.state.read().leader_id
+    /// Shutdown the Raft engine
+    pub async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down Raft engine");
+        *self.running.write() = false;
+        for group in &self.groups {
+            group.shutdown();
+        }
+        Ok(())
     }
 
-    /// Get the current term
-    pub fn term(&self) -> Term {
-        self.state.read().current_term
+    fn group_path(&self, group_id: u64) -> PathBuf {
+        self.storage
+            .data_dir()
+            .join("raft")
+            .join(format!("group-{group_id}"))
     }
 }
+
+#[async_trait]
+impl RaftMessageHandler for RaftEngine {
+    async fn handle(&self, group_id: u64, message: RaftMessage) -> Result<RaftMessage> {
+        // A leader may reach this node before the group exists here (the
+        // node was just added to it); join with an empty membership and
+        // learn the configuration from the leader's log
+        let group = match self.group(group_id) {
+            Some(group) => group,
+            None => {
+                self.create_group(group_id, BTreeSet::new(), self.state_machine_for(group_id))
+                    .await?
+            }
+        };
+        group.handle_message(message).await
+    }
+}
+
+/// Parse `node_id=host:port` peer entries
+fn parse_peers(peers: &[String]) -> Result<HashMap<NodeId, String>> {
+    peers
+        .iter()
+        .map(|peer| {
+            let (id, addr) = peer
+                .split_once('=')
+                .ok_or_else(|| Error::Config(format!("invalid raft peer '{peer}'")))?;
+            let id = id
+                .trim()
+                .parse::<NodeId>()
+                .map_err(|_| Error::Config(format!("invalid raft peer id in '{peer}'")))?;
+            Ok((id, addr.trim().to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,81 @@
+//! Cluster membership with joint consensus
+//!
+//! A configuration change from `C_old` to `C_new` first commits the joint
+//! configuration `C_old,new`, during which every decision needs a majority
+//! of both voter sets, and then commits `C_new` on its own.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::types::NodeId;
+
+/// Voting configuration of a Raft group
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Membership {
+    /// Voters of the (incoming) configuration
+    pub voters: BTreeSet<NodeId>,
+    /// Voters of the outgoing configuration while a joint change is in progress
+    pub outgoing: Option<BTreeSet<NodeId>>,
+}
+
+impl Membership {
+    /// Create a simple (non-joint) configuration
+    pub fn new(voters: impl IntoIterator<Item = NodeId>) -> Self {
+        Self {
+            voters: voters.into_iter().collect(),
+            outgoing: None,
+        }
+    }
+
+    /// Joint configuration moving from `self` to `new_voters`
+    #[must_use]
+    pub fn joint(&self, new_voters: BTreeSet<NodeId>) -> Self {
+        Self {
+            voters: new_voters,
+            outgoing: Some(self.voters.clone()),
+        }
+    }
+
+    /// Final configuration once a joint change has committed
+    #[must_use]
+    pub fn leave_joint(&self) -> Self {
+        Self {
+            voters: self.voters.clone(),
+            outgoing: None,
+        }
+    }
+
+    /// Whether a joint change is in progress
+    #[must_use]
+    pub fn is_joint(&self) -> bool {
+        self.outgoing.is_some()
+    }
+
+    /// Whether `node` votes in either configuration
+    #[must_use]
+    pub fn is_voter(&self, node: NodeId) -> bool {
+        self.voters.contains(&node) || self.outgoing.as_ref().is_some_and(|o| o.contains(&node))
+    }
+
+    /// All nodes that vote in either configuration
+    #[must_use]
+    pub fn all_voters(&self) -> BTreeSet<NodeId> {
+        let mut all = self.voters.clone();
+        if let Some(ref outgoing) = self.outgoing {
+            all.extend(outgoing.iter().copied());
+        }
+        all
+    }
+
+    /// Whether the nodes accepted by `granted` form a quorum (a majority of
+    /// each active voter set)
+    pub fn has_quorum(&self, granted: impl Fn(NodeId) -> bool) -> bool {
+        let majority = |set: &BTreeSet<NodeId>| {
+            set.is_empty() || set.iter().filter(|&&n| granted(n)).count() > set.len() / 2
+        };
+        !self.voters.is_empty()
+            && majority(&self.voters)
+            && self.outgoing.as_ref().map_or(true, majority)
+    }
+}
@@ -0,0 +1,112 @@
+//! Raft log entries and RPC messages
+
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::types::{LogIndex, NodeId, Term};
+
+use crate::membership::Membership;
+
+/// Payload of a log entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryPayload {
+    /// Appended by a new leader to commit entries from earlier terms
+    Noop,
+    /// Application command passed to the state machine
+    Command(Vec<u8>),
+    /// Configuration change; takes effect as soon as it is appended
+    Membership(Membership),
+}
+
+/// Replicated log entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Term in which the entry was created
+    pub term: Term,
+    /// Position in the log (1-based)
+    pub index: LogIndex,
+    /// Entry payload
+    pub payload: EntryPayload,
+}
+
+/// Snapshot metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    /// Index of the last entry covered by the snapshot
+    pub last_index: LogIndex,
+    /// Term of the last entry covered by the snapshot
+    pub last_term: Term,
+    /// Membership as of `last_index`
+    pub membership: Membership,
+}
+
+/// State machine snapshot plus metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Snapshot metadata
+    pub meta: SnapshotMeta,
+    /// Serialized state machine
+    pub data: Vec<u8>,
+}
+
+/// RequestVote RPC arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestVoteRequest {
+    pub term: Term,
+    pub candidate_id: NodeId,
+    pub last_log_index: LogIndex,
+    pub last_log_term: Term,
+}
+
+/// RequestVote RPC result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestVoteResponse {
+    pub term: Term,
+    pub vote_granted: bool,
+}
+
+/// AppendEntries RPC arguments (also used as heartbeat)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppendEntriesRequest {
+    pub term: Term,
+    pub leader_id: NodeId,
+    pub prev_log_index: LogIndex,
+    pub prev_log_term: Term,
+    pub entries: Vec<LogEntry>,
+    pub leader_commit: LogIndex,
+}
+
+/// AppendEntries RPC result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppendEntriesResponse {
+    pub term: Term,
+    pub success: bool,
+    /// Highest index known to match the leader's log (on success)
+    pub match_index: LogIndex,
+    /// Where the leader should retry from (on failure)
+    pub conflict_index: LogIndex,
+}
+
+/// InstallSnapshot RPC arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallSnapshotRequest {
+    pub term: Term,
+    pub leader_id: NodeId,
+    pub snapshot: Snapshot,
+}
+
+/// InstallSnapshot RPC result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallSnapshotResponse {
+    pub term: Term,
+}
+
+/// Message exchanged between Raft peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RaftMessage {
+    RequestVote(RequestVoteRequest),
+    RequestVoteResponse(RequestVoteResponse),
+    AppendEntries(AppendEntriesRequest),
+    AppendEntriesResponse(AppendEntriesResponse),
+    InstallSnapshot(InstallSnapshotRequest),
+    InstallSnapshotResponse(InstallSnapshotResponse),
+}
@@ -0,0 +1,206 @@
+//! Replicated state machines
+//!
+//! Committed commands are handed to a [`StateMachine`] in log order on every
+//! replica. [`StorageStateMachine`] applies [`StorageCommand`]s to the local
+//! [`StorageEngine`].
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{Error, Result};
+use lumadb_common::types::{Document, LogIndex};
+use lumadb_storage::StorageEngine;
+
+/// State machine driven by committed Raft entries
+#[async_trait]
+pub trait StateMachine: Send + Sync {
+    /// Apply a committed command and return its result
+    async fn apply(&self, index: LogIndex, command: &[u8]) -> Result<Vec<u8>>;
+
+    /// Serialize the full state for a snapshot
+    async fn snapshot(&self) -> Result<Vec<u8>>;
+
+    /// Replace the full state from a snapshot
+    async fn restore(&self, snapshot: &[u8]) -> Result<()>;
+
+    /// Whether state survives restarts. Volatile state machines are restored
+    /// from the latest snapshot when their group starts.
+    fn is_durable(&self) -> bool {
+        false
+    }
+}
+
+/// Write command replicated through a Raft group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StorageCommand {
+    CreateCollection {
+        name: String,
+    },
+    DeleteCollection {
+        name: String,
+    },
+    Insert {
+        collection: String,
+        document: Document,
+    },
+    Delete {
+        collection: String,
+        id: String,
+    },
+}
+
+impl StorageCommand {
+    /// Encode for proposal
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command cannot be serialized.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Decode a committed command
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not an encoded command.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Serialized form of a storage snapshot
+#[derive(Serialize, Deserialize)]
+struct CollectionSnapshot {
+    name: String,
+    documents: Vec<Document>,
+}
+
+/// Decides whether a document `(collection, id)` belongs to a group
+pub type DocumentFilter = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Applies [`StorageCommand`]s to a storage engine
+///
+/// Commands are idempotent, so re-applying entries after a restart (the
+/// applied index is not persisted) converges to the same state. When several
+/// groups share one engine, each is given a [`DocumentFilter`] so snapshots
+/// only carry, and restores only replace, the group's own documents.
+pub struct StorageStateMachine {
+    storage: Arc<StorageEngine>,
+    filter: Option<DocumentFilter>,
+}
+
+impl StorageStateMachine {
+    /// Create a state machine owning every document in `storage`
+    pub fn new(storage: Arc<StorageEngine>) -> Self {
+        Self {
+            storage,
+            filter: None,
+        }
+    }
+
+    /// Create a state machine owning the documents accepted by `filter`
+    pub fn with_filter(storage: Arc<StorageEngine>, filter: DocumentFilter) -> Self {
+        Self {
+            storage,
+            filter: Some(filter),
+        }
+    }
+
+    fn owns(&self, collection: &str, id: &str) -> bool {
+        self.filter.as_ref().map_or(true, |f| f(collection, id))
+    }
+}
+
+#[async_trait]
+impl StateMachine for StorageStateMachine {
+    async fn apply(&self, _index: LogIndex, command: &[u8]) -> Result<Vec<u8>> {
+        match StorageCommand::decode(command)? {
+            StorageCommand::CreateCollection { name } => {
+                if self.storage.get_collection(&name).await?.is_none() {
+                    self.storage.create_collection(&name).await?;
+                }
+                Ok(Vec::new())
+            }
+            StorageCommand::DeleteCollection { name } => {
+                if self.storage.get_collection(&name).await?.is_some() {
+                    self.storage.delete_collection(&name).await?;
+                }
+                Ok(Vec::new())
+            }
+            StorageCommand::Insert {
+                collection,
+                document,
+            } => {
+                self.storage.insert_document(&collection, &document).await?;
+                Ok(Vec::new())
+            }
+            StorageCommand::Delete { collection, id } => {
+                let deleted = self.storage.delete_document(&collection, &id).await?;
+                Ok(vec![u8::from(deleted)])
+            }
+        }
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        let mut collections = Vec::new();
+        for metadata in self.storage.list_collections().await? {
+            let documents = self
+                .storage
+                .scan_documents(&metadata.name, None, Some(usize::MAX))
+                .await?
+                .into_iter()
+                .filter(|doc| self.owns(&metadata.name, &doc.id))
+                .collect();
+            collections.push(CollectionSnapshot {
+                name: metadata.name,
+                documents,
+            });
+        }
+        serde_json::to_vec(&collections).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    async fn restore(&self, snapshot: &[u8]) -> Result<()> {
+        let collections: Vec<CollectionSnapshot> = serde_json::from_slice(snapshot)?;
+
+        for metadata in self.storage.list_collections().await? {
+            if self.filter.is_none() {
+                self.storage.delete_collection(&metadata.name).await?;
+                continue;
+            }
+            for doc in self
+                .storage
+                .scan_documents(&metadata.name, None, Some(usize::MAX))
+                .await?
+            {
+                if self.owns(&metadata.name, &doc.id) {
+                    self.storage
+                        .delete_document(&metadata.name, &doc.id)
+                        .await?;
+                }
+            }
+        }
+        for collection in collections {
+            if self
+                .storage
+                .get_collection(&collection.name)
+                .await?
+                .is_none()
+            {
+                self.storage.create_collection(&collection.name).await?;
+            }
+            for document in &collection.documents {
+                self.storage
+                    .insert_document(&collection.name, document)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_durable(&self) -> bool {
+        true
+    }
+}
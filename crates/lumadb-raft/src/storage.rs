@@ -0,0 +1,409 @@
+//! Durable Raft log, hard state and snapshot storage
+//!
+//! Each group keeps three files in its directory:
+//! - `hardstate`: current term and vote, rewritten atomically
+//! - `log`: append-only frames `[len u32][crc32 u32][bincode LogEntry]`
+//! - `snapshot`: latest state machine snapshot, rewritten atomically
+//!
+//! Entries are also cached in memory; snapshotting bounds the cache.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use lumadb_common::error::{Error, Result};
+use lumadb_common::types::{LogIndex, NodeId, Term};
+
+use crate::membership::Membership;
+use crate::message::{EntryPayload, LogEntry, Snapshot};
+
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// State that must survive restarts before answering any RPC
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardState {
+    /// Latest term this node has seen
+    pub term: Term,
+    /// Candidate voted for in `term`
+    pub voted_for: Option<NodeId>,
+}
+
+/// Raft log storage, optionally backed by a directory
+pub struct RaftLogStorage {
+    dir: Option<PathBuf>,
+    hard_state: HardState,
+    snapshot: Snapshot,
+    entries: Vec<LogEntry>,
+    /// Byte offset of each cached entry's frame in the log file
+    offsets: Vec<u64>,
+    log_file: Option<File>,
+    log_size: u64,
+}
+
+impl RaftLogStorage {
+    /// Volatile storage, used by tests and scratch groups
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            hard_state: HardState::default(),
+            snapshot: Snapshot::default(),
+            entries: Vec::new(),
+            offsets: Vec::new(),
+            log_file: None,
+            log_size: 0,
+        }
+    }
+
+    /// Open (or create) durable storage in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or its files are
+    /// corrupt.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let hard_state = match fs::read(dir.join("hardstate")) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::Serialization(format!("raft hard state: {e}")))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HardState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let snapshot: Snapshot = match fs::read(dir.join("snapshot")) {
+            Ok(bytes) => bincode::deserialize(&bytes)
+                .map_err(|e| Error::Serialization(format!("raft snapshot: {e}")))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Snapshot::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let log_path = dir.join("log");
+        let (entries, offsets, valid_len) = Self::read_log(&log_path, snapshot.meta.last_index)?;
+
+        let log_file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&log_path)?;
+        if log_file.metadata()?.len() != valid_len {
+            warn!(
+                "Truncating torn Raft log tail at {} in {:?}",
+                valid_len, log_path
+            );
+            log_file.set_len(valid_len)?;
+            log_file.sync_all()?;
+        }
+
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            hard_state,
+            snapshot,
+            entries,
+            offsets,
+            log_file: Some(log_file),
+            log_size: valid_len,
+        })
+    }
+
+    /// Read log frames, stopping at the first torn or corrupt frame. Entries
+    /// already covered by the snapshot are skipped but keep their space in
+    /// the file until the next compaction rewrites it.
+    fn read_log(path: &Path, snapshot_index: LogIndex) -> Result<(Vec<LogEntry>, Vec<u64>, u64)> {
+        let mut entries = Vec::new();
+        let mut offsets = Vec::new();
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((entries, offsets, 0)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut offset = 0u64;
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        loop {
+            if reader.read_exact(&mut header).is_err() {
+                break;
+            }
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let mut body = vec![0u8; len];
+            if reader.read_exact(&mut body).is_err() || crc32fast::hash(&body) != crc {
+                break;
+            }
+            let Ok(entry) = bincode::deserialize::<LogEntry>(&body) else {
+                break;
+            };
+
+            let expected = entries
+                .last()
+                .map_or(snapshot_index + 1, |e: &LogEntry| e.index + 1);
+            if entry.index > snapshot_index {
+                if entry.index != expected {
+                    break;
+                }
+                entries.push(entry);
+                offsets.push(offset);
+            }
+            offset += (FRAME_HEADER_SIZE + len) as u64;
+        }
+
+        Ok((entries, offsets, offset))
+    }
+
+    /// Current hard state
+    #[must_use]
+    pub fn hard_state(&self) -> &HardState {
+        &self.hard_state
+    }
+
+    /// Persist a new hard state
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hard state cannot be written.
+    pub fn set_hard_state(&mut self, hard_state: HardState) -> Result<()> {
+        if let Some(ref dir) = self.dir {
+            let bytes =
+                serde_json::to_vec(&hard_state).map_err(|e| Error::Serialization(e.to_string()))?;
+            write_atomic(dir, "hardstate", &bytes)?;
+        }
+        self.hard_state = hard_state;
+        Ok(())
+    }
+
+    /// Index of the first entry still in the log
+    #[must_use]
+    pub fn first_index(&self) -> LogIndex {
+        self.snapshot.meta.last_index + 1
+    }
+
+    /// Index of the last entry (the snapshot index if the log is empty)
+    #[must_use]
+    pub fn last_index(&self) -> LogIndex {
+        self.entries
+            .last()
+            .map_or(self.snapshot.meta.last_index, |e| e.index)
+    }
+
+    /// Term of the last entry
+    #[must_use]
+    pub fn last_term(&self) -> Term {
+        self.entries
+            .last()
+            .map_or(self.snapshot.meta.last_term, |e| e.term)
+    }
+
+    /// Term of the entry at `index`, if it is known
+    #[must_use]
+    pub fn term(&self, index: LogIndex) -> Option<Term> {
+        if index == self.snapshot.meta.last_index {
+            return Some(self.snapshot.meta.last_term);
+        }
+        self.entry(index).map(|e| e.term)
+    }
+
+    /// Entry at `index`, if it has not been compacted away
+    #[must_use]
+    pub fn entry(&self, index: LogIndex) -> Option<&LogEntry> {
+        let first = self.first_index();
+        if index < first {
+            return None;
+        }
+        self.entries.get(usize::try_from(index - first).ok()?)
+    }
+
+    /// Entries in `[lo, hi)`, clamped to what the log holds
+    #[must_use]
+    pub fn entries(&self, lo: LogIndex, hi: LogIndex, max: usize) -> Vec<LogEntry> {
+        let first = self.first_index();
+        let lo = lo.max(first);
+        let hi = hi.min(self.last_index() + 1);
+        if lo >= hi {
+            return Vec::new();
+        }
+        let start = usize::try_from(lo - first).unwrap_or(usize::MAX);
+        let end = usize::try_from(hi - first).unwrap_or(usize::MAX);
+        self.entries[start..end].iter().take(max).cloned().collect()
+    }
+
+    /// Append entries; the first must directly follow `last_index`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entries do not follow the log or cannot be
+    /// written.
+    pub fn append(&mut self, entries: &[LogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        if entries[0].index != self.last_index() + 1 {
+            return Err(Error::Internal(format!(
+                "raft log gap: appending {} after {}",
+                entries[0].index,
+                self.last_index()
+            )));
+        }
+
+        if let Some(ref mut file) = self.log_file {
+            let mut buf = Vec::new();
+            let mut offsets = Vec::with_capacity(entries.len());
+            for entry in entries {
+                let body =
+                    bincode::serialize(entry).map_err(|e| Error::Serialization(e.to_string()))?;
+                offsets.push(self.log_size + buf.len() as u64);
+                let len = u32::try_from(body.len())
+                    .map_err(|_| Error::Internal("raft log entry too large".to_string()))?;
+                buf.extend_from_slice(&len.to_le_bytes());
+                buf.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+                buf.extend_from_slice(&body);
+            }
+            file.write_all(&buf)?;
+            file.sync_data()?;
+            self.log_size += buf.len() as u64;
+            self.offsets.extend(offsets);
+        }
+
+        self.entries.extend_from_slice(entries);
+        Ok(())
+    }
+
+    /// Remove every entry at or after `index`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be rewritten.
+    pub fn truncate_from(&mut self, index: LogIndex) -> Result<()> {
+        let first = self.first_index();
+        if index > self.last_index() {
+            return Ok(());
+        }
+        let keep = usize::try_from(index.saturating_sub(first)).unwrap_or(0);
+
+        if let Some(ref mut file) = self.log_file {
+            let new_size = self.offsets.get(keep).copied().unwrap_or(self.log_size);
+            file.set_len(new_size)?;
+            file.sync_all()?;
+            self.log_size = new_size;
+            self.offsets.truncate(keep);
+        }
+
+        self.entries.truncate(keep);
+        Ok(())
+    }
+
+    /// Latest snapshot
+    #[must_use]
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    /// Store a snapshot and discard the log prefix it covers. If the log
+    /// disagrees with the snapshot at its last index the whole log is
+    /// discarded (the case for snapshots installed from a leader).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot or the compacted log cannot be written.
+    pub fn save_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        let last_index = snapshot.meta.last_index;
+        if last_index <= self.snapshot.meta.last_index {
+            return Ok(());
+        }
+
+        if let Some(ref dir) = self.dir {
+            let bytes =
+                bincode::serialize(&snapshot).map_err(|e| Error::Serialization(e.to_string()))?;
+            write_atomic(dir, "snapshot", &bytes)?;
+        }
+
+        let retained: Vec<LogEntry> = if self.term(last_index) == Some(snapshot.meta.last_term) {
+            self.entries
+                .iter()
+                .filter(|e| e.index > last_index)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        self.snapshot = snapshot;
+        self.entries.clear();
+        self.offsets.clear();
+        self.rewrite_log(retained)
+    }
+
+    /// Replace the log file with `entries`
+    fn rewrite_log(&mut self, entries: Vec<LogEntry>) -> Result<()> {
+        let Some(dir) = self.dir.clone() else {
+            self.entries = entries;
+            return Ok(());
+        };
+
+        let tmp = dir.join("log.tmp");
+        {
+            let file = File::create(&tmp)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, dir.join("log"))?;
+        sync_dir(&dir)?;
+
+        self.log_file = Some(
+            OpenOptions::new()
+                .read(true)
+                .append(true)
+                .open(dir.join("log"))?,
+        );
+        self.log_size = 0;
+        self.append(&entries)
+    }
+
+    /// Membership in effect and the index it was set at: the latest
+    /// configuration entry in the log, falling back to the snapshot's
+    #[must_use]
+    pub fn membership(&self) -> (LogIndex, Membership) {
+        self.membership_at(LogIndex::MAX)
+    }
+
+    /// Membership as of `index`
+    #[must_use]
+    pub fn membership_at(&self, index: LogIndex) -> (LogIndex, Membership) {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.index <= index)
+            .find_map(|e| match e.payload {
+                EntryPayload::Membership(ref m) => Some((e.index, m.clone())),
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                (
+                    self.snapshot.meta.last_index,
+                    self.snapshot.meta.membership.clone(),
+                )
+            })
+    }
+}
+
+/// Write `name` in `dir` via a temporary file and rename
+fn write_atomic(dir: &Path, name: &str, bytes: &[u8]) -> Result<()> {
+    let tmp = dir.join(format!("{name}.tmp"));
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, dir.join(name))?;
+    sync_dir(dir)
+}
+
+fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
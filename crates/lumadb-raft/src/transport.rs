@@ -0,0 +1,269 @@
+//! Pluggable transport between Raft peers
+//!
+//! Raft RPCs are request/response: [`RaftTransport::send`] delivers a message
+//! to the target node's [`RaftMessageHandler`] and returns its reply.
+//! [`InProcessNetwork`] connects engines living in one process (tests, and
+//! single-node deployments); [`TcpTransport`] speaks length-prefixed bincode
+//! frames to peers served by [`serve`].
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+use lumadb_common::error::{Error, NetworkError, Result};
+use lumadb_common::types::NodeId;
+
+use crate::message::RaftMessage;
+
+/// Largest frame accepted from a peer
+const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+/// Sends Raft messages to peers
+#[async_trait]
+pub trait RaftTransport: Send + Sync {
+    /// Deliver `message` for `group_id` to `target` and wait for its reply
+    async fn send(
+        &self,
+        target: NodeId,
+        group_id: u64,
+        message: RaftMessage,
+    ) -> Result<RaftMessage>;
+}
+
+/// Receives Raft messages addressed to a node
+#[async_trait]
+pub trait RaftMessageHandler: Send + Sync {
+    /// Handle `message` for `group_id` and produce the reply
+    async fn handle(&self, group_id: u64, message: RaftMessage) -> Result<RaftMessage>;
+}
+
+// ============================================================================
+// In-process transport
+// ============================================================================
+
+/// Message router between nodes in the same process
+///
+/// Links can be cut to simulate partitions and crashed nodes.
+#[derive(Default)]
+pub struct InProcessNetwork {
+    nodes: RwLock<HashMap<NodeId, Arc<dyn RaftMessageHandler>>>,
+    /// Nodes cut off from every other node
+    isolated: RwLock<HashSet<NodeId>>,
+}
+
+impl InProcessNetwork {
+    /// Create an empty network
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Transport used by `node` to reach its peers
+    pub fn transport(self: &Arc<Self>, node: NodeId) -> Arc<InProcessTransport> {
+        Arc::new(InProcessTransport {
+            network: self.clone(),
+            node,
+        })
+    }
+
+    /// Attach `node`'s message handler
+    pub fn register(&self, node: NodeId, handler: Arc<dyn RaftMessageHandler>) {
+        self.nodes.write().insert(node, handler);
+    }
+
+    /// Detach `node`
+    pub fn unregister(&self, node: NodeId) {
+        self.nodes.write().remove(&node);
+    }
+
+    /// Drop all traffic to and from `node`
+    pub fn isolate(&self, node: NodeId) {
+        self.isolated.write().insert(node);
+    }
+
+    /// Restore traffic to and from `node`
+    pub fn heal(&self, node: NodeId) {
+        self.isolated.write().remove(&node);
+    }
+}
+
+/// [`RaftTransport`] over an [`InProcessNetwork`]
+pub struct InProcessTransport {
+    network: Arc<InProcessNetwork>,
+    node: NodeId,
+}
+
+#[async_trait]
+impl RaftTransport for InProcessTransport {
+    async fn send(
+        &self,
+        target: NodeId,
+        group_id: u64,
+        message: RaftMessage,
+    ) -> Result<RaftMessage> {
+        let handler = {
+            let isolated = self.network.isolated.read();
+            if isolated.contains(&self.node) || isolated.contains(&target) {
+                return Err(
+                    NetworkError::ConnectionFailed(format!("node {target} unreachable")).into(),
+                );
+            }
+            self.network.nodes.read().get(&target).cloned()
+        };
+        let handler = handler.ok_or_else(|| {
+            NetworkError::ConnectionFailed(format!("node {target} not registered"))
+        })?;
+        handler.handle(group_id, message).await
+    }
+}
+
+// ============================================================================
+// TCP transport
+// ============================================================================
+
+/// [`RaftTransport`] over TCP with one cached connection per peer
+pub struct TcpTransport {
+    peers: RwLock<HashMap<NodeId, String>>,
+    connections: DashMap<NodeId, Arc<tokio::sync::Mutex<Option<TcpStream>>>>,
+    timeout: Duration,
+}
+
+impl TcpTransport {
+    /// Create a transport with a peer address book
+    #[must_use]
+    pub fn new(peers: HashMap<NodeId, String>, timeout: Duration) -> Self {
+        Self {
+            peers: RwLock::new(peers),
+            connections: DashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Add or update a peer address
+    pub fn set_peer(&self, node: NodeId, addr: String) {
+        self.peers.write().insert(node, addr);
+        self.connections.remove(&node);
+    }
+
+    async fn round_trip(
+        &self,
+        slot: &mut Option<TcpStream>,
+        target: NodeId,
+        frame: &[u8],
+    ) -> Result<RaftMessage> {
+        if slot.is_none() {
+            let addr =
+                self.peers.read().get(&target).cloned().ok_or_else(|| {
+                    NetworkError::AddressResolution(format!("unknown node {target}"))
+                })?;
+            let stream = TcpStream::connect(&addr)
+                .await
+                .map_err(|e| NetworkError::ConnectionFailed(format!("{addr}: {e}")))?;
+            stream.set_nodelay(true)?;
+            *slot = Some(stream);
+        }
+        let stream = slot.as_mut().ok_or(NetworkError::ConnectionClosed)?;
+        write_frame(stream, frame).await?;
+        let reply = read_frame(stream).await?;
+        decode(&reply)
+    }
+}
+
+#[async_trait]
+impl RaftTransport for TcpTransport {
+    async fn send(
+        &self,
+        target: NodeId,
+        group_id: u64,
+        message: RaftMessage,
+    ) -> Result<RaftMessage> {
+        let frame = bincode::serialize(&(group_id, message))
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        let slot = self
+            .connections
+            .entry(target)
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(None)))
+            .clone();
+        let mut slot = slot.lock().await;
+
+        match tokio::time::timeout(self.timeout, self.round_trip(&mut slot, target, &frame)).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(e)) => {
+                *slot = None;
+                Err(e)
+            }
+            Err(_) => {
+                *slot = None;
+                Err(NetworkError::Timeout(format!("raft rpc to node {target}")).into())
+            }
+        }
+    }
+}
+
+/// Accept Raft connections on `addr` and dispatch them to `handler`
+///
+/// # Errors
+///
+/// Returns an error if `addr` cannot be bound.
+pub async fn serve(addr: &str, handler: Arc<dyn RaftMessageHandler>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            debug!("Raft connection from {}", peer);
+            loop {
+                let Ok(frame) = read_frame(&mut stream).await else {
+                    break;
+                };
+                let reply = match bincode::deserialize::<(u64, RaftMessage)>(&frame) {
+                    Ok((group_id, message)) => handler.handle(group_id, message).await,
+                    Err(e) => Err(Error::Serialization(e.to_string())),
+                };
+                let reply = match reply.and_then(|r| {
+                    bincode::serialize(&r).map_err(|e| Error::Serialization(e.to_string()))
+                }) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        warn!("Raft request from {} failed: {}", peer, e);
+                        break;
+                    }
+                };
+                if write_frame(&mut stream, &reply).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> Result<()> {
+    let len = u32::try_from(frame.len())
+        .map_err(|_| Error::Internal("raft frame too large".to_string()))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(frame).await?;
+    Ok(())
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let len = stream.read_u32().await? as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(Error::Internal(format!(
+            "raft frame of {len} bytes exceeds limit"
+        )));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+fn decode(bytes: &[u8]) -> Result<RaftMessage> {
+    bincode::deserialize(bytes).map_err(|e| Error::Serialization(e.to_string()))
+}
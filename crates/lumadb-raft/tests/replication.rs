@@ -0,0 +1,276 @@
+//! Multi-node Raft tests over the in-process transport
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use lumadb_common::config::RaftConfig;
+use lumadb_common::error::Result;
+use lumadb_common::types::{LogIndex, NodeId};
+use lumadb_raft::{InProcessNetwork, Membership, RaftGroup, RaftLogStorage, StateMachine};
+
+const GROUP: u64 = 7;
+
+/// Key-value state machine fed `key=value` commands
+#[derive(Default)]
+struct KvStateMachine {
+    data: Mutex<BTreeMap<String, String>>,
+}
+
+#[async_trait]
+impl StateMachine for KvStateMachine {
+    async fn apply(&self, _index: LogIndex, command: &[u8]) -> Result<Vec<u8>> {
+        let command = String::from_utf8_lossy(command);
+        let (key, value) = command.split_once('=').unwrap_or((&command, ""));
+        self.data.lock().insert(key.to_string(), value.to_string());
+        Ok(key.as_bytes().to_vec())
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&*self.data.lock())?)
+    }
+
+    async fn restore(&self, snapshot: &[u8]) -> Result<()> {
+        *self.data.lock() = serde_json::from_slice(snapshot)?;
+        Ok(())
+    }
+}
+
+struct Node {
+    group: Arc<RaftGroup>,
+    kv: Arc<KvStateMachine>,
+}
+
+struct Cluster {
+    network: Arc<InProcessNetwork>,
+    nodes: BTreeMap<NodeId, Node>,
+    config: RaftConfig,
+}
+
+impl Cluster {
+    async fn new(voters: &[NodeId], config: RaftConfig) -> Self {
+        let mut cluster = Self {
+            network: InProcessNetwork::new(),
+            nodes: BTreeMap::new(),
+            config,
+        };
+        for &id in voters {
+            cluster
+                .start_node(id, Membership::new(voters.iter().copied()))
+                .await;
+        }
+        cluster
+    }
+
+    async fn start_node(&mut self, id: NodeId, membership: Membership) {
+        let kv = Arc::new(KvStateMachine::default());
+        let group = RaftGroup::start(
+            GROUP,
+            id,
+            &self.config,
+            RaftLogStorage::in_memory(),
+            membership,
+            self.network.transport(id),
+            kv.clone(),
+        )
+        .await
+        .unwrap();
+        self.network.register(id, group.clone());
+        self.nodes.insert(id, Node { group, kv });
+    }
+
+    async fn wait_for_leader(&self, excluding: &[NodeId]) -> NodeId {
+        wait_until(|| {
+            self.nodes
+                .iter()
+                .filter(|(id, _)| !excluding.contains(id))
+                .find(|(_, n)| n.group.is_leader())
+                .map(|(id, _)| *id)
+        })
+        .await
+    }
+
+    async fn wait_applied(&self, nodes: &[NodeId], key: &str, value: &str) {
+        wait_until(|| {
+            nodes
+                .iter()
+                .all(|id| self.nodes[id].kv.data.lock().get(key).map(String::as_str) == Some(value))
+                .then_some(())
+        })
+        .await;
+    }
+}
+
+async fn wait_until<T>(mut check: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(value) = check() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "condition not reached in time");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_election_and_replication() {
+    let cluster = Cluster::new(&[1, 2, 3], RaftConfig::default()).await;
+    let leader = cluster.wait_for_leader(&[]).await;
+    let group = &cluster.nodes[&leader].group;
+
+    let result = group.propose(b"a=1".to_vec()).await.unwrap();
+    assert_eq!(result, b"a");
+    cluster.wait_applied(&[1, 2, 3], "a", "1").await;
+
+    // Exactly one leader per term
+    let term = group.term();
+    let leaders = cluster
+        .nodes
+        .values()
+        .filter(|n| n.group.is_leader() && n.group.term() == term)
+        .count();
+    assert_eq!(leaders, 1);
+
+    // Followers redirect proposals
+    let follower = cluster.nodes.keys().find(|&&id| id != leader).unwrap();
+    assert!(cluster.nodes[follower]
+        .group
+        .propose(b"b=2".to_vec())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_leader_failover() {
+    let cluster = Cluster::new(&[1, 2, 3], RaftConfig::default()).await;
+    let old_leader = cluster.wait_for_leader(&[]).await;
+    cluster.nodes[&old_leader]
+        .group
+        .propose(b"a=1".to_vec())
+        .await
+        .unwrap();
+
+    cluster.network.isolate(old_leader);
+    let new_leader = cluster.wait_for_leader(&[old_leader]).await;
+    assert_ne!(new_leader, old_leader);
+    cluster.nodes[&new_leader]
+        .group
+        .propose(b"a=2".to_vec())
+        .await
+        .unwrap();
+
+    // The isolated leader steps down once it loses contact with a quorum
+    wait_until(|| (!cluster.nodes[&old_leader].group.is_leader()).then_some(())).await;
+
+    cluster.network.heal(old_leader);
+    cluster.wait_applied(&[1, 2, 3], "a", "2").await;
+}
+
+#[tokio::test]
+async fn test_membership_change() {
+    let mut cluster = Cluster::new(&[1, 2, 3], RaftConfig::default()).await;
+    let leader = cluster.wait_for_leader(&[]).await;
+    cluster.nodes[&leader]
+        .group
+        .propose(b"a=1".to_vec())
+        .await
+        .unwrap();
+
+    // Node 4 joins with no configuration and learns it from the leader
+    cluster.start_node(4, Membership::default()).await;
+    let group = cluster.nodes[&leader].group.clone();
+    group
+        .change_membership(BTreeSet::from([1, 2, 3, 4]))
+        .await
+        .unwrap();
+    cluster.wait_applied(&[4], "a", "1").await;
+    assert_eq!(
+        cluster.nodes[&4].group.membership(),
+        Membership::new([1, 2, 3, 4])
+    );
+
+    // Remove the leader itself; it steps down once the change commits
+    let remaining: BTreeSet<NodeId> = [1, 2, 3, 4].into_iter().filter(|&n| n != leader).collect();
+    group.change_membership(remaining.clone()).await.unwrap();
+    wait_until(|| (!group.is_leader()).then_some(())).await;
+
+    let new_leader = cluster.wait_for_leader(&[leader]).await;
+    cluster.nodes[&new_leader]
+        .group
+        .propose(b"b=2".to_vec())
+        .await
+        .unwrap();
+    let remaining: Vec<NodeId> = remaining.into_iter().collect();
+    cluster.wait_applied(&remaining, "b", "2").await;
+}
+
+#[tokio::test]
+async fn test_snapshot_catch_up() {
+    let config = RaftConfig {
+        snapshot_interval: 10,
+        ..RaftConfig::default()
+    };
+    let cluster = Cluster::new(&[1, 2, 3], config).await;
+    let leader = cluster.wait_for_leader(&[]).await;
+    let lagging = *cluster.nodes.keys().find(|&&id| id != leader).unwrap();
+    cluster.network.isolate(lagging);
+
+    let group = &cluster.nodes[&leader].group;
+    for i in 0..50 {
+        group
+            .propose(format!("k{i}={i}").into_bytes())
+            .await
+            .unwrap();
+    }
+    wait_until(|| (group.snapshot_index() > 20).then_some(())).await;
+
+    // The lagging follower is behind the leader's log start and must be
+    // brought up to date with a snapshot
+    cluster.network.heal(lagging);
+    cluster.wait_applied(&[lagging], "k49", "49").await;
+    assert!(cluster.nodes[&lagging].group.snapshot_index() > 0);
+    assert_eq!(cluster.nodes[&lagging].kv.data.lock().len(), 50);
+}
+
+#[tokio::test]
+async fn test_durable_log_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RaftConfig::default();
+    let network = InProcessNetwork::new();
+
+    let start = || async {
+        let kv = Arc::new(KvStateMachine::default());
+        let group = RaftGroup::start(
+            GROUP,
+            1,
+            &config,
+            RaftLogStorage::open(dir.path()).unwrap(),
+            Membership::new([1]),
+            network.transport(1),
+            kv.clone(),
+        )
+        .await
+        .unwrap();
+        (group, kv)
+    };
+
+    let (group, _) = start().await;
+    assert!(group.is_leader());
+    group.propose(b"a=1".to_vec()).await.unwrap();
+    group.propose(b"b=2".to_vec()).await.unwrap();
+    group.snapshot().await.unwrap();
+    group.propose(b"c=3".to_vec()).await.unwrap();
+    let term = group.term();
+    group.shutdown();
+    drop(group);
+
+    // The snapshot restores a, b and replaying the log tail restores c
+    let (group, kv) = start().await;
+    assert!(group.term() > term);
+    wait_until(|| (kv.data.lock().len() == 3).then_some(())).await;
+    assert_eq!(kv.data.lock().get("c").map(String::as_str), Some("3"));
+    group.shutdown();
+}
@@ -0,0 +1,631 @@
+//! Audit trail
+//!
+//! Security-relevant events (authentication attempts, authorization
+//! denials, DDL, user, role and ACL changes and, optionally, DML) are
+//! appended as JSON lines to `audit.jsonl` in the audit directory. Each
+//! record carries the SHA-256 hash of the record before it, so editing,
+//! removing or reordering records breaks the chain, which [`verify`]
+//! detects. The file is closed and a new one started once it reaches the
+//! size limit; closed files are deleted after the retention period.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+use tracing::info;
+
+use lumadb_common::config::AuditConfig;
+use lumadb_common::error::{Error, Result, StorageError};
+
+use crate::manager::Session;
+
+/// File records are appended to; closed files are named
+/// `audit-<first sequence number>.jsonl`
+const CURRENT_FILE: &str = "audit.jsonl";
+
+/// `prev_hash` of the first record of a trail
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Records held for a slow mirror before it starts missing them
+const MIRROR_CAPACITY: usize = 4096;
+
+/// Kind of an audited event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCategory {
+    /// A login attempt, successful or not
+    Authentication,
+    /// A denied request
+    Authorization,
+    /// Creating, altering or dropping a collection, index or topic
+    Ddl,
+    /// A user, role or ACL change
+    Admin,
+    /// A data change; values are redacted
+    Dml,
+}
+
+impl AuditCategory {
+    /// Name of the category, as configured
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Authentication => "authentication",
+            Self::Authorization => "authorization",
+            Self::Ddl => "ddl",
+            Self::Admin => "admin",
+            Self::Dml => "dml",
+        }
+    }
+}
+
+impl FromStr for AuditCategory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "authentication" => Self::Authentication,
+            "authorization" => Self::Authorization,
+            "ddl" => Self::Ddl,
+            "admin" => Self::Admin,
+            "dml" => Self::Dml,
+            other => return Err(Error::Config(format!("unknown audit category {other}"))),
+        })
+    }
+}
+
+/// Whether an audited action succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// An event to record
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    category: AuditCategory,
+    action: String,
+    outcome: AuditOutcome,
+    principal: Option<String>,
+    host: Option<String>,
+    resource: Option<String>,
+    detail: Option<String>,
+}
+
+impl AuditEvent {
+    /// A successful `action`
+    pub fn new(category: AuditCategory, action: impl Into<String>) -> Self {
+        Self {
+            category,
+            action: action.into(),
+            outcome: AuditOutcome::Success,
+            principal: None,
+            host: None,
+            resource: None,
+            detail: None,
+        }
+    }
+
+    /// Taken by the caller of `session`
+    #[must_use]
+    pub fn by(mut self, session: &Session) -> Self {
+        self.principal = Some(session.principal());
+        self.host.clone_from(&session.host);
+        self
+    }
+
+    /// Taken by `principal`, who has no session
+    #[must_use]
+    pub fn by_principal(mut self, principal: impl Into<String>) -> Self {
+        self.principal = Some(principal.into());
+        self
+    }
+
+    /// Taken on `resource`
+    #[must_use]
+    pub fn on(mut self, resource: impl Into<String>) -> Self {
+        self.resource = Some(resource.into());
+        self
+    }
+
+    #[must_use]
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// The action failed, for `reason`
+    #[must_use]
+    pub fn failed(mut self, reason: impl Into<String>) -> Self {
+        self.outcome = AuditOutcome::Failure;
+        self.detail = Some(reason.into());
+        self
+    }
+
+    #[must_use]
+    pub fn category(&self) -> AuditCategory {
+        self.category
+    }
+}
+
+/// A record of the audit trail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the trail, from 1
+    pub seq: u64,
+    /// Milliseconds since the epoch
+    pub timestamp: i64,
+    pub category: AuditCategory,
+    pub action: String,
+    pub outcome: AuditOutcome,
+    /// `User:<name>` of the caller, if known
+    pub principal: Option<String>,
+    pub host: Option<String>,
+    pub resource: Option<String>,
+    pub detail: Option<String>,
+    /// Hash of the previous record
+    pub prev_hash: String,
+    /// Hash of this record without this field
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// Hash of the record's serialized contents, excluding its own hash
+    fn digest(&self) -> String {
+        let mut unhashed = self.clone();
+        unhashed.hash.clear();
+        let bytes = serde_json::to_vec(&unhashed).unwrap_or_default();
+        hex::encode(Sha256::digest(&bytes))
+    }
+}
+
+/// Appends records to the audit trail
+pub struct AuditLog {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    retention: Option<Duration>,
+    categories: HashSet<AuditCategory>,
+    writer: Mutex<Writer>,
+    mirror: broadcast::Sender<AuditRecord>,
+}
+
+/// The file being appended to and the end of the chain
+struct Writer {
+    file: File,
+    size: u64,
+    /// Sequence number of the file's first record, if it has one
+    first_seq: Option<u64>,
+    next_seq: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Open the trail in the configured directory, continuing its chain
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be created or its last record cannot be
+    /// read.
+    pub fn open(config: &AuditConfig) -> Result<Self> {
+        let categories = config
+            .categories
+            .iter()
+            .map(|c| c.parse())
+            .collect::<Result<HashSet<_>>>()?;
+        let dir = PathBuf::from(&config.path);
+        fs::create_dir_all(&dir)?;
+
+        let current = dir.join(CURRENT_FILE);
+        let records = recover(&current)?;
+        let last = match records.last() {
+            Some(record) => Some(record.clone()),
+            None => closed_files(&dir)?
+                .last()
+                .map(|path| read_records(path))
+                .transpose()?
+                .and_then(|records| records.last().cloned()),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)?;
+        let writer = Writer {
+            size: file.metadata()?.len(),
+            file,
+            first_seq: records.first().map(|r| r.seq),
+            next_seq: last.as_ref().map_or(1, |r| r.seq + 1),
+            last_hash: last.map_or_else(|| GENESIS_HASH.to_string(), |r| r.hash),
+        };
+        info!(
+            "Audit trail at {} continues from record {}",
+            dir.display(),
+            writer.next_seq
+        );
+
+        let log = Self {
+            dir,
+            max_file_bytes: config.max_file_bytes,
+            retention: (config.retention_days > 0)
+                .then(|| Duration::from_secs(u64::from(config.retention_days) * 86_400)),
+            categories,
+            writer: Mutex::new(writer),
+            mirror: broadcast::channel(MIRROR_CAPACITY).0,
+        };
+        log.expire()?;
+        Ok(log)
+    }
+
+    /// Directory holding the trail
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether events of `category` are recorded
+    pub fn records(&self, category: AuditCategory) -> bool {
+        self.categories.contains(&category)
+    }
+
+    /// Append `event`, unless its category is not recorded
+    ///
+    /// # Errors
+    ///
+    /// Fails if the record cannot be written or the file cannot be rotated.
+    pub fn record(&self, event: AuditEvent) -> Result<()> {
+        if !self.records(event.category) {
+            return Ok(());
+        }
+
+        let mut writer = self.writer.lock();
+        if writer.size >= self.max_file_bytes {
+            self.rotate(&mut writer)?;
+        }
+
+        let mut record = AuditRecord {
+            seq: writer.next_seq,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            category: event.category,
+            action: event.action,
+            outcome: event.outcome,
+            principal: event.principal,
+            host: event.host,
+            resource: event.resource,
+            detail: event.detail,
+            prev_hash: writer.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.digest();
+
+        let mut line =
+            serde_json::to_vec(&record).map_err(|e| Error::Serialization(e.to_string()))?;
+        line.push(b'\n');
+        writer.file.write_all(&line)?;
+        writer.size += line.len() as u64;
+        writer.first_seq.get_or_insert(record.seq);
+        writer.next_seq += 1;
+        writer.last_hash.clone_from(&record.hash);
+        drop(writer);
+
+        // Nobody listening is not an error
+        let _ = self.mirror.send(record);
+        Ok(())
+    }
+
+    /// Records as they are appended, for mirroring the trail elsewhere;
+    /// a receiver that falls too far behind misses records
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditRecord> {
+        self.mirror.subscribe()
+    }
+
+    /// Close the current file and start a new one
+    fn rotate(&self, writer: &mut Writer) -> Result<()> {
+        let Some(first_seq) = writer.first_seq else {
+            return Ok(());
+        };
+        writer.file.sync_all()?;
+        let current = self.dir.join(CURRENT_FILE);
+        fs::rename(&current, self.dir.join(closed_file_name(first_seq)))?;
+        writer.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)?;
+        writer.size = 0;
+        writer.first_seq = None;
+        self.expire()
+    }
+
+    /// Delete closed files last written before the retention period
+    fn expire(&self) -> Result<()> {
+        let Some(retention) = self.retention else {
+            return Ok(());
+        };
+        let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+            return Ok(());
+        };
+        for path in closed_files(&self.dir)? {
+            if fs::metadata(&path)?.modified()? < cutoff {
+                info!("Removing expired audit file {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of verifying a trail
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuditVerification {
+    pub files: usize,
+    pub records: u64,
+    pub first_seq: Option<u64>,
+    pub last_seq: Option<u64>,
+}
+
+/// Check that the trail in `dir` is intact: every record's hash matches
+/// its contents and links to the record before it, with no gaps in the
+/// sequence
+///
+/// Files removed by retention are not gaps; the oldest remaining record
+/// starts the chain.
+///
+/// # Errors
+///
+/// Fails if a file cannot be read, or names the first record that is edited,
+/// out of sequence or unlinked.
+pub fn verify(dir: &Path) -> Result<AuditVerification> {
+    let mut files = closed_files(dir)?;
+    let current = dir.join(CURRENT_FILE);
+    if current.exists() {
+        files.push(current);
+    }
+
+    let mut verification = AuditVerification {
+        files: files.len(),
+        ..AuditVerification::default()
+    };
+    let mut prev: Option<AuditRecord> = None;
+    for path in &files {
+        let reader = BufReader::new(File::open(path)?);
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let tampered = |reason: String| {
+                Error::Storage(StorageError::CorruptData(format!(
+                    "audit trail {} line {}: {}",
+                    path.display(),
+                    index + 1,
+                    reason
+                )))
+            };
+            let record: AuditRecord = serde_json::from_str(&line)
+                .map_err(|e| tampered(format!("unreadable record: {e}")))?;
+
+            match &prev {
+                Some(prev) if record.seq != prev.seq + 1 => {
+                    return Err(tampered(format!(
+                        "record {} follows record {}",
+                        record.seq, prev.seq
+                    )))
+                }
+                Some(prev) if record.prev_hash != prev.hash => {
+                    return Err(tampered(format!(
+                        "record {} does not chain to record {}",
+                        record.seq, prev.seq
+                    )))
+                }
+                None if record.seq == 1 && record.prev_hash != GENESIS_HASH => {
+                    return Err(tampered(
+                        "first record does not start the chain".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+            if record.digest() != record.hash {
+                return Err(tampered(format!(
+                    "record {} does not match its hash",
+                    record.seq
+                )));
+            }
+
+            verification.first_seq.get_or_insert(record.seq);
+            verification.last_seq = Some(record.seq);
+            verification.records += 1;
+            prev = Some(record);
+        }
+    }
+    Ok(verification)
+}
+
+/// `statement` with its string and number literals replaced by `?`
+#[must_use]
+pub fn redact(statement: &str) -> String {
+    let mut redacted = String::with_capacity(statement.len());
+    let mut chars = statement.chars().peekable();
+    let mut in_word = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // '' inside a string is an escaped quote
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                        }
+                        Some('\'') | None => break,
+                        Some(_) => {}
+                    }
+                }
+                redacted.push('?');
+                in_word = false;
+            }
+            c if c.is_ascii_digit() && !in_word => {
+                while chars
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.')
+                {
+                    chars.next();
+                }
+                redacted.push('?');
+            }
+            c => {
+                in_word = c.is_alphanumeric() || c == '_' || c == '$';
+                redacted.push(c);
+            }
+        }
+    }
+    redacted
+}
+
+fn closed_file_name(first_seq: u64) -> String {
+    format!("audit-{first_seq:020}.jsonl")
+}
+
+/// Closed files of the trail in `dir`, oldest first
+fn closed_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let closed = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                name.starts_with("audit-")
+                    && std::path::Path::new(name)
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl"))
+            });
+        if closed {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_records(path: &Path) -> Result<Vec<AuditRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let record = serde_json::from_str(&line?).map_err(|e| {
+            Error::Storage(StorageError::CorruptData(format!(
+                "audit trail {}: {}",
+                path.display(),
+                e
+            )))
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Records of the current file, after dropping a record left partly
+/// written by a crash
+fn recover(path: &Path) -> Result<Vec<AuditRecord>> {
+    let Ok(contents) = fs::read(path) else {
+        return Ok(Vec::new());
+    };
+    let complete = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    if complete < contents.len() {
+        tracing::warn!("Dropping a partly written record from {}", path.display());
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    read_records(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path, max_file_bytes: u64) -> AuditConfig {
+        AuditConfig {
+            path: dir.to_string_lossy().to_string(),
+            max_file_bytes,
+            retention_days: 0,
+            categories: vec!["authentication".to_string(), "ddl".to_string()],
+            mirror_topic: None,
+        }
+    }
+
+    fn event(n: usize) -> AuditEvent {
+        AuditEvent::new(AuditCategory::Ddl, "create")
+            .by_principal("User:alice")
+            .on(format!("collection:c{n}"))
+    }
+
+    #[test]
+    fn chain_spans_rotations_and_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(&config(dir.path(), 1024)).unwrap();
+        for n in 0..20 {
+            log.record(event(n)).unwrap();
+        }
+        // Not a selected category
+        log.record(AuditEvent::new(AuditCategory::Dml, "insert"))
+            .unwrap();
+        drop(log);
+
+        let log = AuditLog::open(&config(dir.path(), 1024)).unwrap();
+        log.record(event(20)).unwrap();
+
+        let verification = verify(dir.path()).unwrap();
+        assert!(verification.files > 1);
+        assert_eq!(verification.records, 21);
+        assert_eq!(verification.first_seq, Some(1));
+        assert_eq!(verification.last_seq, Some(21));
+    }
+
+    #[test]
+    fn verify_detects_edits_and_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(&config(dir.path(), 1 << 20)).unwrap();
+        for n in 0..3 {
+            log.record(event(n)).unwrap();
+        }
+        drop(log);
+        let path = dir.path().join(CURRENT_FILE);
+        let original = fs::read_to_string(&path).unwrap();
+
+        fs::write(&path, original.replace("collection:c1", "collection:cx")).unwrap();
+        assert!(verify(dir.path()).is_err());
+
+        let without_second: String = original.lines().enumerate().filter(|(i, _)| *i != 1).fold(
+            String::new(),
+            |mut out, (_, line)| {
+                out.push_str(line);
+                out.push('\n');
+                out
+            },
+        );
+        fs::write(&path, without_second).unwrap();
+        assert!(verify(dir.path()).is_err());
+
+        fs::write(&path, original).unwrap();
+        assert_eq!(verify(dir.path()).unwrap().records, 3);
+    }
+
+    #[test]
+    fn redact_replaces_literals() {
+        assert_eq!(
+            redact("INSERT INTO users (name, age) VALUES ('o''brien', 42) -- t2"),
+            "INSERT INTO users (name, age) VALUES (?, ?) -- t2"
+        );
+        assert_eq!(
+            redact("UPDATE t1 SET x = $1 WHERE y > 3.5"),
+            "UPDATE t1 SET x = $1 WHERE y > ?"
+        );
+    }
+}
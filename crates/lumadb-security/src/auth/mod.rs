@@ -10,10 +10,13 @@ use crate::manager::{AuthenticatedUser, Credentials};
 
 pub mod jwt;
 pub mod sasl;
+pub mod scram;
 pub mod mtls;
 
 pub use jwt::JwtAuth;
-pub use sasl::SaslAuth;
+pub use sasl::{SaslAuth, SaslExchange, SaslMechanism, SaslStep};
+pub use scram::{ScramCredential, ScramCredentialInfo, ScramCredentials, ScramMechanism};
+pub use mtls::MtlsAuth;
 
 /// Authenticator trait
 #[async_trait]
@@ -1,4 +1,8 @@
-//! mTLS authentication (stub)
+//! mTLS authentication
+//!
+//! The TLS layer verifies the client certificate chain; this
+//! authenticator only maps a verified certificate to an identity, the
+//! common name of its subject.
 
 use async_trait::async_trait;
 
@@ -7,6 +11,9 @@ use lumadb_common::error::{Result, Error, AuthError};
 use super::Authenticator;
 use crate::manager::{AuthenticatedUser, Credentials};
 
+/// DER encoding of the `commonName` attribute type, 2.5.4.3
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+
 /// mTLS authenticator
 pub struct MtlsAuth;
 
@@ -27,10 +34,14 @@ impl Authenticator for MtlsAuth {
     async fn authenticate(&self, credentials: &Credentials) -> Result<AuthenticatedUser> {
         match credentials {
             Credentials::Certificate(cert) => {
-                // In production, validate certificate and extract identity
+                let name = subject_common_name(cert).ok_or_else(|| {
+                    Error::Auth(AuthError::AuthenticationFailed(
+                        "client certificate has no subject common name".to_string(),
+                    ))
+                })?;
                 Ok(AuthenticatedUser {
-                    id: "cert-user".to_string(),
-                    username: "cert-user".to_string(),
+                    id: name.clone(),
+                    username: name,
                     roles: vec!["user".to_string()],
                 })
             }
@@ -38,3 +49,67 @@ impl Authenticator for MtlsAuth {
         }
     }
 }
+
+/// Split a DER element off the front of `input`: its tag, contents and
+/// the bytes after it
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let len = rest[..octets]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        (len, &rest[octets..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// Common name of the subject of a DER certificate
+fn subject_common_name(cert: &[u8]) -> Option<String> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (SEQUENCE, certificate, _) = der_element(cert)? else {
+        return None;
+    };
+    let (SEQUENCE, tbs, _) = der_element(certificate)? else {
+        return None;
+    };
+    // version (optional), serialNumber, signature, issuer, validity, subject
+    let mut rest = tbs;
+    let (tag, _, after) = der_element(rest)?;
+    if tag == VERSION {
+        rest = after;
+    }
+    for _ in 0..4 {
+        rest = der_element(rest)?.2;
+    }
+    let (SEQUENCE, mut rdns, _) = der_element(rest)? else {
+        return None;
+    };
+
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value }
+    while !rdns.is_empty() {
+        let (_, mut attributes, next) = der_element(rdns)?;
+        while !attributes.is_empty() {
+            let (_, attribute, next) = der_element(attributes)?;
+            let (_, oid, value) = der_element(attribute)?;
+            if oid == COMMON_NAME_OID {
+                let (_, value, _) = der_element(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+            attributes = next;
+        }
+        rdns = next;
+    }
+    None
+}
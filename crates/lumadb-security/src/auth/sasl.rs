@@ -1,12 +1,15 @@
 //! SASL authentication
 
+use std::str::FromStr;
+
 use async_trait::async_trait;
 use dashmap::DashMap;
 
 use lumadb_common::error::{Result, Error, AuthError};
 
+use super::scram::{ScramMechanism, ScramServer};
 use super::Authenticator;
-use crate::manager::{AuthenticatedUser, Credentials};
+use crate::manager::{AuthenticatedUser, Credentials, Session};
 
 /// SASL authenticator
 pub struct SaslAuth {
@@ -101,3 +104,148 @@ impl Authenticator for SaslAuth {
 fn hex_encode(data: impl AsRef<[u8]>) -> String {
     data.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
 }
+
+/// A SASL mechanism the server can offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaslMechanism {
+    /// Username and password in the clear, checked by the authenticator
+    Plain,
+    /// Salted challenge-response against stored SCRAM credentials
+    Scram(ScramMechanism),
+    /// A bearer JWT (RFC 7628)
+    OAuthBearer,
+}
+
+impl SaslMechanism {
+    /// SASL mechanism name
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+            Self::Scram(mechanism) => mechanism.name(),
+            Self::OAuthBearer => "OAUTHBEARER",
+        }
+    }
+}
+
+impl FromStr for SaslMechanism {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "PLAIN" => Ok(Self::Plain),
+            "OAUTHBEARER" => Ok(Self::OAuthBearer),
+            _ => match ScramMechanism::ALL.into_iter().find(|m| m.name() == s) {
+                Some(mechanism) => Ok(Self::Scram(mechanism)),
+                None => Err(Error::Auth(AuthError::AuthenticationFailed(format!(
+                    "unsupported SASL mechanism {s}"
+                )))),
+            },
+        }
+    }
+}
+
+/// Server side of one SASL exchange, stepped by
+/// [`SecurityManager::sasl_step`](crate::SecurityManager::sasl_step)
+pub struct SaslExchange {
+    pub(crate) mechanism: SaslMechanism,
+    pub(crate) scram: Option<ScramServer>,
+    pub(crate) complete: bool,
+}
+
+impl SaslExchange {
+    #[must_use]
+    pub fn new(mechanism: SaslMechanism) -> Self {
+        Self {
+            mechanism,
+            scram: match mechanism {
+                SaslMechanism::Scram(mechanism) => Some(ScramServer::new(mechanism)),
+                _ => None,
+            },
+            complete: false,
+        }
+    }
+
+    #[must_use]
+    pub fn mechanism(&self) -> SaslMechanism {
+        self.mechanism
+    }
+
+    /// Whether the exchange has finished, successfully or not
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Outcome of one step of an exchange
+pub enum SaslStep {
+    /// Send this challenge and wait for the client's next message
+    Challenge(Vec<u8>),
+    /// The client authenticated; send the final message, which may be empty
+    Complete(Session, Vec<u8>),
+}
+
+/// Parse a PLAIN message, `[authzid] NUL authcid NUL passwd`, into the
+/// username and password
+///
+/// # Errors
+///
+/// Fails if the message is not three NUL-separated UTF-8 fields, or its
+/// authorization id is not the username.
+pub fn parse_plain(message: &[u8]) -> Result<(String, String)> {
+    let malformed = || {
+        Error::Auth(AuthError::AuthenticationFailed(
+            "invalid SASL/PLAIN message".to_string(),
+        ))
+    };
+    let message = std::str::from_utf8(message).map_err(|_| malformed())?;
+    let mut parts = message.split('\0');
+    let (Some(authzid), Some(username), Some(password), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(malformed());
+    };
+    if !authzid.is_empty() && authzid != username {
+        return Err(Error::Auth(AuthError::AuthenticationFailed(
+            "authorization id must match the username".to_string(),
+        )));
+    }
+    Ok((username.to_string(), password.to_string()))
+}
+
+/// Parse an OAUTHBEARER initial response, `n,[a=authzid],^Aauth=Bearer
+/// <token>^A^A`, into the authorization id and the token
+///
+/// # Errors
+///
+/// Fails if the message is not a GS2 header followed by an `auth` bearer field.
+pub fn parse_oauthbearer(message: &[u8]) -> Result<(Option<String>, String)> {
+    let malformed = || {
+        Error::Auth(AuthError::AuthenticationFailed(
+            "invalid SASL/OAUTHBEARER message".to_string(),
+        ))
+    };
+    let message = std::str::from_utf8(message).map_err(|_| malformed())?;
+    let mut parts = message.split('\u{1}');
+    let gs2_header = parts.next().ok_or_else(malformed)?;
+    let authzid = match gs2_header
+        .strip_prefix("n,")
+        .or_else(|| gs2_header.strip_prefix("y,"))
+    {
+        Some("," | "") => None,
+        Some(rest) => Some(
+            rest.strip_prefix("a=")
+                .and_then(|a| a.strip_suffix(','))
+                .ok_or_else(malformed)?
+                .to_string(),
+        ),
+        None => return Err(malformed()),
+    };
+    let token = parts
+        .filter_map(|pair| pair.strip_prefix("auth="))
+        .find_map(|auth| auth.strip_prefix("Bearer "))
+        .filter(|token| !token.is_empty())
+        .ok_or_else(malformed)?;
+    Ok((authzid, token.to_string()))
+}
@@ -0,0 +1,815 @@
+//! SASL/SCRAM authentication (RFC 5802, RFC 7677)
+//!
+//! Credentials keep only the salt, iteration count, stored key and server
+//! key of each user, never the password. An exchange takes two client
+//! messages:
+//! - client-first `n,,n=<user>,r=<client nonce>` is answered with
+//!   server-first `r=<client nonce><server nonce>,s=<salt>,i=<iterations>`
+//! - client-final `c=<gs2 header>,r=<nonce>,p=<proof>` is answered with
+//!   server-final `v=<server signature>` once the proof checks out
+//!
+//! Channel binding is not supported: the client must send the `n` or `y`
+//! flag, and the final message must echo its GS2 header and the combined
+//! nonce exactly. An authorization id, if sent, must name the user.
+//!
+//! Unknown users are answered with a salt and iteration count derived from
+//! the username and a server secret, as RFC 5802 section 5.1 suggests, so
+//! client-first does not reveal which users exist. Their exchanges fail at
+//! client-final like a wrong password.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use lumadb_common::error::{AuthError, Error, Result};
+use lumadb_storage::StorageEngine;
+
+/// Fewest PBKDF2 iterations a credential may use
+pub const MIN_ITERATIONS: u32 = 4096;
+/// Most PBKDF2 iterations a credential may use
+pub const MAX_ITERATIONS: u32 = 16384;
+
+/// Metadata key prefix of persisted credentials
+const CREDENTIAL_PREFIX: &str = "security:scram:";
+/// Metadata key of the secret unknown users' salts are derived from
+const MOCK_SECRET_KEY: &str = "security:scram-mock-secret";
+
+/// Iterations offered to unknown users, Kafka's default
+const MOCK_ITERATIONS: u32 = 4096;
+
+/// Random bytes in a generated salt
+const SALT_LEN: usize = 32;
+/// Random bytes in the server's part of a nonce
+const NONCE_LEN: usize = 24;
+
+/// SCRAM hash function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScramMechanism {
+    #[serde(rename = "SCRAM-SHA-256")]
+    Sha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    Sha512,
+}
+
+impl ScramMechanism {
+    pub const ALL: [Self; 2] = [Self::Sha256, Self::Sha512];
+
+    /// SASL mechanism name
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SCRAM-SHA-256",
+            Self::Sha512 => "SCRAM-SHA-512",
+        }
+    }
+
+    fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Self::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes any key size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn salted_password(self, password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+        match self {
+            Self::Sha256 => {
+                let mut out = [0u8; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut out);
+                out.to_vec()
+            }
+            Self::Sha512 => {
+                let mut out = [0u8; 64];
+                pbkdf2::pbkdf2_hmac::<Sha512>(password.as_bytes(), salt, iterations, &mut out);
+                out.to_vec()
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScramMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScramMechanism {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mechanism| mechanism.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::Config(format!("unknown SCRAM mechanism {s}")))
+    }
+}
+
+/// What the server keeps of a user's password for one mechanism
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScramCredential {
+    #[serde(with = "base64_bytes")]
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    #[serde(with = "base64_bytes")]
+    pub stored_key: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub server_key: Vec<u8>,
+}
+
+impl ScramCredential {
+    /// Derive a credential from a password, with a fresh random salt
+    ///
+    /// # Errors
+    ///
+    /// Fails if the iteration count is out of range or no random salt can be
+    /// drawn.
+    pub fn new(mechanism: ScramMechanism, password: &str, iterations: u32) -> Result<Self> {
+        check_iterations(iterations)?;
+        let salt = random_bytes(SALT_LEN)?;
+        let salted_password = mechanism.salted_password(password, &salt, iterations);
+        Self::from_salted_password(mechanism, salt, &salted_password, iterations)
+    }
+
+    /// Build a credential from a password a client already salted, as
+    /// Kafka's AlterUserScramCredentials sends it
+    ///
+    /// # Errors
+    ///
+    /// Fails if the iteration count is out of range, or the salt or password is
+    /// empty.
+    pub fn from_salted_password(
+        mechanism: ScramMechanism,
+        salt: Vec<u8>,
+        salted_password: &[u8],
+        iterations: u32,
+    ) -> Result<Self> {
+        check_iterations(iterations)?;
+        if salt.is_empty() || salted_password.is_empty() {
+            return Err(Error::Config(
+                "SCRAM salt and password must not be empty".to_string(),
+            ));
+        }
+        let client_key = mechanism.hmac(salted_password, b"Client Key");
+        Ok(Self {
+            salt,
+            iterations,
+            stored_key: mechanism.hash(&client_key),
+            server_key: mechanism.hmac(salted_password, b"Server Key"),
+        })
+    }
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Internal("failed to generate random bytes".to_string()))?;
+    Ok(bytes)
+}
+
+fn check_iterations(iterations: u32) -> Result<()> {
+    if (MIN_ITERATIONS..=MAX_ITERATIONS).contains(&iterations) {
+        Ok(())
+    } else {
+        Err(Error::Config(format!(
+            "SCRAM iterations must be between {MIN_ITERATIONS} and {MAX_ITERATIONS}"
+        )))
+    }
+}
+
+/// A user's credential for one mechanism, without its secrets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScramCredentialInfo {
+    pub username: String,
+    pub mechanism: ScramMechanism,
+    pub iterations: u32,
+}
+
+/// Persisted form of a credential
+#[derive(Serialize, Deserialize)]
+struct StoredCredential {
+    username: String,
+    mechanism: ScramMechanism,
+    #[serde(flatten)]
+    credential: ScramCredential,
+}
+
+/// SCRAM credentials of every user
+///
+/// Kept in the storage engine's metadata when there is one, so they
+/// survive restarts.
+pub struct ScramCredentials {
+    credentials: DashMap<(String, ScramMechanism), ScramCredential>,
+    /// Secret the credentials offered to unknown users are derived from
+    mock_secret: Vec<u8>,
+    storage: Option<Arc<StorageEngine>>,
+}
+
+impl ScramCredentials {
+    /// Create an empty in-memory credential store
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system's random source fails.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            credentials: DashMap::new(),
+            mock_secret: random_bytes(SALT_LEN).expect("failed to generate a SCRAM secret"),
+            storage: None,
+        }
+    }
+
+    /// Create a credential store kept in `storage`, loading what an
+    /// earlier run persisted
+    ///
+    /// # Errors
+    ///
+    /// Fails if the persisted credentials cannot be read or decoded.
+    pub fn with_storage(storage: Arc<StorageEngine>) -> Result<Self> {
+        let credentials = DashMap::new();
+        for (_, value) in storage.scan_metadata(CREDENTIAL_PREFIX)? {
+            let stored: StoredCredential = serde_json::from_slice(&value)?;
+            credentials.insert((stored.username, stored.mechanism), stored.credential);
+        }
+        // Kept so an unknown user is offered the same salt after a restart
+        let mock_secret = if let Some(secret) = storage.get_metadata(MOCK_SECRET_KEY)? {
+            secret
+        } else {
+            let secret = random_bytes(SALT_LEN)?;
+            storage.put_metadata(MOCK_SECRET_KEY, &secret)?;
+            secret
+        };
+        Ok(Self {
+            credentials,
+            mock_secret,
+            storage: Some(storage),
+        })
+    }
+
+    /// Credential of `username` for `mechanism`
+    #[must_use]
+    pub fn get(&self, username: &str, mechanism: ScramMechanism) -> Option<ScramCredential> {
+        self.credentials
+            .get(&(username.to_string(), mechanism))
+            .map(|credential| credential.clone())
+    }
+
+    /// Stand-in credential of an unknown user, which no password matches
+    fn mock(&self, username: &str, mechanism: ScramMechanism) -> ScramCredential {
+        let seed = format!("{}:{username}", mechanism.name());
+        let key = mechanism.hmac(&self.mock_secret, seed.as_bytes());
+        ScramCredential {
+            salt: mechanism.hmac(&key, b"Salt")[..SALT_LEN].to_vec(),
+            iterations: MOCK_ITERATIONS,
+            stored_key: mechanism.hmac(&key, b"Stored Key"),
+            server_key: mechanism.hmac(&key, b"Server Key"),
+        }
+    }
+
+    /// Add or replace the credential of `username` for `mechanism`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the username is empty or the credential cannot be persisted.
+    pub fn upsert(
+        &self,
+        username: &str,
+        mechanism: ScramMechanism,
+        credential: ScramCredential,
+    ) -> Result<()> {
+        if username.is_empty() {
+            return Err(Error::Config(
+                "SCRAM username must not be empty".to_string(),
+            ));
+        }
+        if let Some(storage) = &self.storage {
+            let stored = StoredCredential {
+                username: username.to_string(),
+                mechanism,
+                credential: credential.clone(),
+            };
+            let key = credential_key(username, mechanism);
+            storage.put_metadata(&key, &serde_json::to_vec(&stored)?)?;
+        }
+        self.credentials
+            .insert((username.to_string(), mechanism), credential);
+        Ok(())
+    }
+
+    /// Remove the credential of `username` for `mechanism`
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such credential or it cannot be removed from
+    /// storage.
+    pub fn delete(&self, username: &str, mechanism: ScramMechanism) -> Result<()> {
+        let key = (username.to_string(), mechanism);
+        if !self.credentials.contains_key(&key) {
+            return Err(Error::Auth(AuthError::UserNotFound(username.to_string())));
+        }
+        if let Some(storage) = &self.storage {
+            storage.delete_metadata(&credential_key(username, mechanism))?;
+        }
+        self.credentials.remove(&key);
+        Ok(())
+    }
+
+    /// Every credential, ordered by user and mechanism
+    #[must_use]
+    pub fn describe(&self) -> Vec<ScramCredentialInfo> {
+        let mut infos: Vec<ScramCredentialInfo> = self
+            .credentials
+            .iter()
+            .map(|entry| ScramCredentialInfo {
+                username: entry.key().0.clone(),
+                mechanism: entry.key().1,
+                iterations: entry.value().iterations,
+            })
+            .collect();
+        infos.sort_by(|a, b| {
+            (&a.username, a.mechanism.name()).cmp(&(&b.username, b.mechanism.name()))
+        });
+        infos
+    }
+}
+
+impl Default for ScramCredentials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn credential_key(username: &str, mechanism: ScramMechanism) -> String {
+    format!("{CREDENTIAL_PREFIX}{}:{username}", mechanism.name())
+}
+
+/// Server side of one SCRAM exchange
+pub struct ScramServer {
+    mechanism: ScramMechanism,
+    state: ScramState,
+}
+
+enum ScramState {
+    /// Waiting for client-first
+    Initial,
+    /// Sent server-first, waiting for client-final
+    SentFirst {
+        username: String,
+        gs2_header: String,
+        nonce: String,
+        /// client-first-bare and server-first, joined for the auth message
+        messages: String,
+        credential: ScramCredential,
+        /// Whether the user exists, or `credential` is a stand-in
+        known: bool,
+    },
+    /// Finished, successfully or not
+    Done,
+}
+
+impl ScramServer {
+    #[must_use]
+    pub fn new(mechanism: ScramMechanism) -> Self {
+        Self {
+            mechanism,
+            state: ScramState::Initial,
+        }
+    }
+
+    #[must_use]
+    pub fn mechanism(&self) -> ScramMechanism {
+        self.mechanism
+    }
+
+    /// Whether client-first has been taken
+    #[must_use]
+    pub fn is_started(&self) -> bool {
+        !matches!(self.state, ScramState::Initial)
+    }
+
+    /// Answer client-first with server-first
+    ///
+    /// # Errors
+    ///
+    /// Fails if the message is malformed or out of turn, asks to act as another
+    /// user, or uses extensions.
+    pub fn client_first(
+        &mut self,
+        message: &[u8],
+        credentials: &ScramCredentials,
+    ) -> Result<Vec<u8>> {
+        if !matches!(self.state, ScramState::Initial) {
+            return Err(invalid("unexpected client-first message"));
+        }
+        self.state = ScramState::Done;
+
+        let message = std::str::from_utf8(message).map_err(|_| invalid("message is not UTF-8"))?;
+        let (gs2_header, authzid, bare) = split_gs2_header(message)?;
+        let mut attributes = bare.split(',');
+        let username = match attributes.next().and_then(|a| a.strip_prefix("n=")) {
+            Some(name) => decode_saslname(name)?,
+            None => return Err(invalid("missing username")),
+        };
+        // Acting as another user is not supported
+        if let Some(authzid) = authzid {
+            if decode_saslname(authzid)? != username {
+                return Err(Error::Auth(AuthError::PermissionDenied(
+                    "SCRAM authorization id must match the username".to_string(),
+                )));
+            }
+        }
+        let client_nonce = match attributes.next().and_then(|a| a.strip_prefix("r=")) {
+            Some(nonce) if is_valid_nonce(nonce) => nonce,
+            _ => return Err(invalid("missing or malformed nonce")),
+        };
+        // Extensions such as Kafka's `tokenauth` are not supported
+        if attributes.next().is_some() {
+            return Err(invalid("SCRAM extensions are not supported"));
+        }
+
+        let (credential, known) = match credentials.get(&username, self.mechanism) {
+            Some(credential) => (credential, true),
+            None => (credentials.mock(&username, self.mechanism), false),
+        };
+
+        let mut server_nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut server_nonce)
+            .map_err(|_| Error::Internal("failed to generate a nonce".to_string()))?;
+        let nonce = format!("{client_nonce}{}", BASE64.encode(server_nonce));
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            BASE64.encode(&credential.salt),
+            credential.iterations
+        );
+
+        self.state = ScramState::SentFirst {
+            username,
+            gs2_header: gs2_header.to_string(),
+            nonce,
+            messages: format!("{bare},{server_first}"),
+            credential,
+            known,
+        };
+        Ok(server_first.into_bytes())
+    }
+
+    /// Check client-final, returning the authenticated username and
+    /// server-final
+    ///
+    /// # Errors
+    ///
+    /// Fails if the message is malformed, arrives out of turn, or its proof
+    /// does not match.
+    pub fn client_final(&mut self, message: &[u8]) -> Result<(String, Vec<u8>)> {
+        let ScramState::SentFirst {
+            username,
+            gs2_header,
+            nonce,
+            messages,
+            credential,
+            known,
+        } = std::mem::replace(&mut self.state, ScramState::Done)
+        else {
+            return Err(invalid("unexpected client-final message"));
+        };
+
+        let message = std::str::from_utf8(message).map_err(|_| invalid("message is not UTF-8"))?;
+        let (without_proof, proof) = message
+            .rsplit_once(",p=")
+            .ok_or_else(|| invalid("missing client proof"))?;
+        let mut attributes = without_proof.split(',');
+        let binding = attributes
+            .next()
+            .and_then(|a| a.strip_prefix("c="))
+            .ok_or_else(|| invalid("missing channel binding"))?;
+        if BASE64.decode(binding).ok().as_deref() != Some(gs2_header.as_bytes()) {
+            return Err(invalid("channel binding does not match"));
+        }
+        if attributes.next().and_then(|a| a.strip_prefix("r=")) != Some(nonce.as_str()) {
+            return Err(invalid("nonce does not match"));
+        }
+        let proof = BASE64
+            .decode(proof)
+            .map_err(|_| invalid("malformed client proof"))?;
+
+        let auth_message = format!("{messages},{without_proof}");
+        let client_signature = self
+            .mechanism
+            .hmac(&credential.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return Err(Error::Auth(AuthError::InvalidCredentials));
+        }
+        let client_key: Vec<u8> = proof
+            .iter()
+            .zip(&client_signature)
+            .map(|(p, s)| p ^ s)
+            .collect();
+        let matches = constant_time_eq(&self.mechanism.hash(&client_key), &credential.stored_key);
+        if !(matches && known) {
+            return Err(Error::Auth(AuthError::InvalidCredentials));
+        }
+
+        let server_signature = self
+            .mechanism
+            .hmac(&credential.server_key, auth_message.as_bytes());
+        Ok((
+            username,
+            format!("v={}", BASE64.encode(server_signature)).into_bytes(),
+        ))
+    }
+}
+
+/// Split a client-first message into its GS2 header, the authorization id
+/// it names, if any, and the bare message
+fn split_gs2_header(message: &str) -> Result<(&str, Option<&str>, &str)> {
+    let (flag, rest) = message
+        .split_once(',')
+        .ok_or_else(|| invalid("missing GS2 header"))?;
+    match flag {
+        "n" | "y" => {}
+        _ if flag.starts_with("p=") => {
+            return Err(invalid("channel binding is not supported"));
+        }
+        _ => return Err(invalid("malformed GS2 header")),
+    }
+    let (authzid, bare) = rest
+        .split_once(',')
+        .ok_or_else(|| invalid("missing GS2 header"))?;
+    let header_len = flag.len() + authzid.len() + 2;
+    let authzid = match authzid {
+        "" => None,
+        _ => Some(
+            authzid
+                .strip_prefix("a=")
+                .ok_or_else(|| invalid("malformed authorization id"))?,
+        ),
+    };
+    Ok((&message[..header_len], authzid, bare))
+}
+
+/// Decode a `saslname`, in which `,` and `=` are escaped as `=2C` and
+/// `=3D`
+fn decode_saslname(name: &str) -> Result<String> {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(at) = rest.find('=') {
+        decoded.push_str(&rest[..at]);
+        match rest.get(at..at + 3) {
+            Some("=2C") => decoded.push(','),
+            Some("=3D") => decoded.push('='),
+            _ => return Err(invalid("malformed username")),
+        }
+        rest = &rest[at + 3..];
+    }
+    decoded.push_str(rest);
+    if decoded.is_empty() {
+        return Err(invalid("missing username"));
+    }
+    Ok(decoded)
+}
+
+/// Whether a client nonce is printable ASCII without commas
+fn is_valid_nonce(nonce: &str) -> bool {
+    !nonce.is_empty()
+        && nonce
+            .bytes()
+            .all(|b| (0x21..=0x7e).contains(&b) && b != b',')
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Auth(AuthError::AuthenticationFailed(format!(
+        "invalid SCRAM message: {message}"
+    )))
+}
+
+/// Serde for byte strings as base64
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Client side of an exchange, as in RFC 5802 section 3
+    fn client_final(
+        mechanism: ScramMechanism,
+        password: &str,
+        client_first_bare: &str,
+        server_first: &str,
+    ) -> (String, Vec<u8>) {
+        let mut attributes = server_first.split(',');
+        let nonce = attributes.next().unwrap().strip_prefix("r=").unwrap();
+        let salt = BASE64
+            .decode(attributes.next().unwrap().strip_prefix("s=").unwrap())
+            .unwrap();
+        let iterations = attributes
+            .next()
+            .unwrap()
+            .strip_prefix("i=")
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let salted_password = mechanism.salted_password(password, &salt, iterations);
+        let client_key = mechanism.hmac(&salted_password, b"Client Key");
+        let stored_key = mechanism.hash(&client_key);
+        let without_proof = format!("c=biws,r={nonce}");
+        let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+        let signature = mechanism.hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(&signature)
+            .map(|(k, s)| k ^ s)
+            .collect();
+
+        let server_key = mechanism.hmac(&salted_password, b"Server Key");
+        let expected = format!(
+            "v={}",
+            BASE64.encode(mechanism.hmac(&server_key, auth_message.as_bytes()))
+        );
+        (
+            format!("{without_proof},p={}", BASE64.encode(proof)),
+            expected.into_bytes(),
+        )
+    }
+
+    #[test]
+    fn exchange_authenticates_the_right_password_only() {
+        let credentials = ScramCredentials::new();
+        for mechanism in ScramMechanism::ALL {
+            let credential = ScramCredential::new(mechanism, "s3cret", MIN_ITERATIONS).unwrap();
+            credentials.upsert("ali,ce", mechanism, credential).unwrap();
+
+            for (password, accepted) in [("s3cret", true), ("guess", false)] {
+                let mut server = ScramServer::new(mechanism);
+                let bare = "n=ali=2Cce,r=fyko+d2lbbFgONRv9qkxdawL";
+                let server_first = server
+                    .client_first(format!("n,,{bare}").as_bytes(), &credentials)
+                    .unwrap();
+                let server_first = String::from_utf8(server_first).unwrap();
+                assert!(server_first.starts_with("r=fyko+d2lbbFgONRv9qkxdawL"));
+
+                let (message, expected) = client_final(mechanism, password, bare, &server_first);
+                let result = server.client_final(message.as_bytes());
+                if accepted {
+                    let (username, server_final) = result.unwrap();
+                    assert_eq!(username, "ali,ce");
+                    assert_eq!(server_final, expected);
+                } else {
+                    assert!(result.is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rfc7677_test_vector() {
+        let mechanism = ScramMechanism::Sha256;
+        let salt = BASE64.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let salted_password = mechanism.salted_password("pencil", &salt, 4096);
+        let credential =
+            ScramCredential::from_salted_password(mechanism, salt, &salted_password, 4096).unwrap();
+
+        let nonce = "rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+        let mut server = ScramServer {
+            mechanism,
+            state: ScramState::SentFirst {
+                username: "user".to_string(),
+                gs2_header: "n,,".to_string(),
+                nonce: nonce.to_string(),
+                messages: format!(
+                    "n=user,r=rOprNGfwEbeRWgbNEkqO,r={nonce},s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+                ),
+                credential,
+                known: true,
+            },
+        };
+        let client_final =
+            format!("c=biws,r={nonce},p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=");
+        let (username, server_final) = server.client_final(client_final.as_bytes()).unwrap();
+        assert_eq!(username, "user");
+        assert_eq!(
+            server_final,
+            b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+    }
+
+    #[test]
+    fn exchange_rejects_bad_messages() {
+        let credentials = ScramCredentials::new();
+        let credential =
+            ScramCredential::new(ScramMechanism::Sha256, "pw", MIN_ITERATIONS).unwrap();
+        credentials
+            .upsert("bob", ScramMechanism::Sha256, credential)
+            .unwrap();
+
+        for first in [
+            "p=tls-unique,,n=bob,r=abc",
+            "n,,n=bob",
+            "n,,n=bob,r=abc,tokenauth=true",
+            "n,,n=b=2Xob,r=abc",
+            "n,a=alice,n=bob,r=abc",
+        ] {
+            let mut server = ScramServer::new(ScramMechanism::Sha256);
+            assert!(
+                server.client_first(first.as_bytes(), &credentials).is_err(),
+                "{first}"
+            );
+        }
+
+        // The final message must carry the combined nonce, not the client's
+        let mut server = ScramServer::new(ScramMechanism::Sha256);
+        server
+            .client_first(b"n,,n=bob,r=abc", &credentials)
+            .unwrap();
+        assert!(server.client_final(b"c=biws,r=abc,p=AAAA").is_err());
+        // and an exchange cannot be replayed
+        assert!(server.client_final(b"c=biws,r=abc,p=AAAA").is_err());
+
+        assert!(ScramCredential::new(ScramMechanism::Sha512, "pw", 10).is_err());
+    }
+
+    #[test]
+    fn unknown_users_fail_only_at_client_final() {
+        let credentials = ScramCredentials::new();
+        let mechanism = ScramMechanism::Sha256;
+        let server_first = |first: &str| {
+            let mut server = ScramServer::new(mechanism);
+            let reply = server.client_first(first.as_bytes(), &credentials).unwrap();
+            (server, String::from_utf8(reply).unwrap())
+        };
+        let salt = |reply: &str| reply.split_once(",s=").unwrap().1.to_string();
+
+        // An unknown user is offered a salt of its own, the same every time
+        let bare = "n=carol,r=abc";
+        let (mut server, reply) = server_first(&format!("n,,{bare}"));
+        assert_eq!(salt(&reply), salt(&server_first("n,,n=carol,r=xyz").1));
+        assert_ne!(salt(&reply), salt(&server_first("n,,n=dave,r=abc").1));
+        assert!(reply.ends_with(",i=4096"));
+
+        let (message, _) = client_final(mechanism, "guess", bare, &reply);
+        assert!(matches!(
+            server.client_final(message.as_bytes()),
+            Err(Error::Auth(AuthError::InvalidCredentials))
+        ));
+    }
+
+    #[test]
+    fn authorization_id_must_name_the_user() {
+        let credentials = ScramCredentials::new();
+        let mechanism = ScramMechanism::Sha256;
+        let credential = ScramCredential::new(mechanism, "pw", MIN_ITERATIONS).unwrap();
+        credentials.upsert("bob", mechanism, credential).unwrap();
+
+        let mut server = ScramServer::new(mechanism);
+        assert!(matches!(
+            server.client_first(b"n,a=alice,n=bob,r=abc", &credentials),
+            Err(Error::Auth(AuthError::PermissionDenied(_)))
+        ));
+
+        let mut server = ScramServer::new(mechanism);
+        assert!(server
+            .client_first(b"n,a=bob,n=bob,r=abc", &credentials)
+            .is_ok());
+    }
+}
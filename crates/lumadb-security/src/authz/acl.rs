@@ -0,0 +1,334 @@
+//! Access control lists
+//!
+//! ACLs follow Kafka's model: a binding allows or denies a principal an
+//! operation on the resources matching a pattern, from a host. Resources
+//! are named `type:name` (`collection:users`, `topic:orders`,
+//! `group:billing`, `transactional_id:tx-1`) or `cluster`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{Error, Result};
+
+/// Principal or host that matches every other
+pub const WILDCARD: &str = "*";
+
+/// Kind of a protected resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceType {
+    /// A document collection
+    Collection,
+    /// A streaming topic
+    Topic,
+    /// A consumer group
+    Group,
+    /// A transactional producer id
+    TransactionalId,
+    /// The cluster itself, for administrative operations
+    Cluster,
+}
+
+impl ResourceType {
+    /// Name used in resource strings
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Collection => "collection",
+            Self::Topic => "topic",
+            Self::Group => "group",
+            Self::TransactionalId => "transactional_id",
+            Self::Cluster => "cluster",
+        }
+    }
+}
+
+impl FromStr for ResourceType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "collection" => Self::Collection,
+            "topic" => Self::Topic,
+            "group" => Self::Group,
+            "transactional_id" => Self::TransactionalId,
+            "cluster" => Self::Cluster,
+            other => return Err(invalid(format!("unknown resource type {other}"))),
+        })
+    }
+}
+
+/// A protected resource
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Resource {
+    pub resource_type: ResourceType,
+    /// Resource name, empty for the cluster
+    pub name: String,
+}
+
+impl Resource {
+    pub fn new(resource_type: ResourceType, name: impl Into<String>) -> Self {
+        Self {
+            resource_type,
+            name: name.into(),
+        }
+    }
+
+    /// The cluster
+    #[must_use]
+    pub fn cluster() -> Self {
+        Self::new(ResourceType::Cluster, "")
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.resource_type {
+            ResourceType::Cluster => f.write_str("cluster"),
+            resource_type => write!(f, "{}:{}", resource_type.as_str(), self.name),
+        }
+    }
+}
+
+impl FromStr for Resource {
+    type Err = Error;
+
+    /// Parse `type:name`, or `cluster`
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "cluster" {
+            return Ok(Self::cluster());
+        }
+        let (resource_type, name) = s
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("invalid resource {s}")))?;
+        Ok(Self::new(resource_type.parse()?, name))
+    }
+}
+
+/// How a binding's resource name matches resource names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternType {
+    /// The name itself, or every name for `*`
+    Literal,
+    /// Every name starting with the name
+    Prefixed,
+}
+
+/// An action on a resource
+///
+/// As in Kafka, `all` grants every operation, and `read`, `write`,
+/// `delete` and `alter` each grant `describe`; `alter_configs` grants
+/// `describe_configs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    All,
+    Read,
+    Write,
+    Create,
+    Delete,
+    Alter,
+    Describe,
+    ClusterAction,
+    DescribeConfigs,
+    AlterConfigs,
+    IdempotentWrite,
+}
+
+impl Operation {
+    /// Whether holding this operation grants `other`
+    #[must_use]
+    pub fn implies(self, other: Operation) -> bool {
+        self == other
+            || self == Self::All
+            || (other == Self::Describe
+                && matches!(self, Self::Read | Self::Write | Self::Delete | Self::Alter))
+            || (other == Self::DescribeConfigs && self == Self::AlterConfigs)
+    }
+}
+
+impl FromStr for Operation {
+    type Err = Error;
+
+    /// Parse an operation name in snake case or Kafka's camel case; `*`
+    /// means `all`
+    fn from_str(s: &str) -> Result<Self> {
+        let name: String = s
+            .chars()
+            .filter(|c| *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        Ok(match name.as_str() {
+            "*" | "all" => Self::All,
+            "read" => Self::Read,
+            "write" => Self::Write,
+            "create" => Self::Create,
+            "delete" => Self::Delete,
+            "alter" => Self::Alter,
+            "describe" => Self::Describe,
+            "clusteraction" => Self::ClusterAction,
+            "describeconfigs" => Self::DescribeConfigs,
+            "alterconfigs" => Self::AlterConfigs,
+            "idempotentwrite" => Self::IdempotentWrite,
+            _ => return Err(invalid(format!("unknown operation {s}"))),
+        })
+    }
+}
+
+/// Whether a binding allows or denies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionType {
+    Allow,
+    Deny,
+}
+
+/// One ACL: `permission` of `operation` for `principal` connecting from
+/// `host` on the resources matching `resource_name`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AclBinding {
+    pub resource_type: ResourceType,
+    pub resource_name: String,
+    pub pattern_type: PatternType,
+    /// `User:<name>`, or `User:*` for every user
+    pub principal: String,
+    /// Client host, or `*` for any
+    #[serde(default = "wildcard")]
+    pub host: String,
+    pub operation: Operation,
+    pub permission: PermissionType,
+}
+
+fn wildcard() -> String {
+    WILDCARD.to_string()
+}
+
+impl AclBinding {
+    /// Check that the binding is complete
+    ///
+    /// # Errors
+    ///
+    /// Fails if the principal, host or resource name is missing.
+    pub fn validate(&self) -> Result<()> {
+        match self.principal.split_once(':') {
+            Some((kind, name)) if !kind.is_empty() && !name.is_empty() => {}
+            _ => {
+                return Err(invalid(format!(
+                    "principal {} must have the form User:<name>",
+                    self.principal
+                )))
+            }
+        }
+        if self.host.is_empty() {
+            return Err(invalid("host must not be empty".to_string()));
+        }
+        if self.resource_name.is_empty() && self.resource_type != ResourceType::Cluster {
+            return Err(invalid("resource name must not be empty".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether the binding covers `resource`; every cluster binding covers
+    /// the one cluster
+    #[must_use]
+    pub fn matches_resource(&self, resource: &Resource) -> bool {
+        if self.resource_type != resource.resource_type {
+            return false;
+        }
+        match (self.resource_type, self.pattern_type) {
+            (ResourceType::Cluster, _) => true,
+            (_, PatternType::Literal) => {
+                self.resource_name == WILDCARD || self.resource_name == resource.name
+            }
+            (_, PatternType::Prefixed) => resource.name.starts_with(&self.resource_name),
+        }
+    }
+
+    /// Whether the binding applies to `principal` connecting from `host`
+    #[must_use]
+    pub fn matches_principal(&self, principal: &str, host: Option<&str>) -> bool {
+        let principal_matches = self.principal == principal
+            || self
+                .principal
+                .strip_suffix(WILDCARD)
+                .is_some_and(|kind| kind.ends_with(':') && principal.starts_with(kind));
+        let host_matches = self.host == WILDCARD || host == Some(self.host.as_str());
+        principal_matches && host_matches
+    }
+}
+
+/// How a filter selects bindings by resource pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternFilter {
+    /// Any pattern type, with the exact resource name
+    #[default]
+    Any,
+    /// Every binding that would apply to the named resource: literal ones
+    /// with its name or `*`, and prefixed ones with a prefix of it
+    Match,
+    Literal,
+    Prefixed,
+}
+
+/// Selects ACL bindings; unset fields match anything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclBindingFilter {
+    #[serde(default)]
+    pub resource_type: Option<ResourceType>,
+    #[serde(default)]
+    pub resource_name: Option<String>,
+    #[serde(default)]
+    pub pattern_type: PatternFilter,
+    #[serde(default)]
+    pub principal: Option<String>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub operation: Option<Operation>,
+    #[serde(default)]
+    pub permission: Option<PermissionType>,
+}
+
+impl AclBindingFilter {
+    /// Whether `binding` is selected
+    #[must_use]
+    pub fn matches(&self, binding: &AclBinding) -> bool {
+        let name_matches = match (&self.resource_name, self.pattern_type) {
+            (None, PatternFilter::Match) => true,
+            (Some(name), PatternFilter::Match) => {
+                let resource_type = self.resource_type.unwrap_or(binding.resource_type);
+                binding.matches_resource(&Resource::new(resource_type, name.clone()))
+            }
+            (name, _) => selects(name.as_ref(), &binding.resource_name),
+        };
+        let pattern_matches = match self.pattern_type {
+            PatternFilter::Any | PatternFilter::Match => true,
+            PatternFilter::Literal => binding.pattern_type == PatternType::Literal,
+            PatternFilter::Prefixed => binding.pattern_type == PatternType::Prefixed,
+        };
+
+        selects(self.resource_type.as_ref(), &binding.resource_type)
+            && name_matches
+            && pattern_matches
+            && selects(self.principal.as_ref(), &binding.principal)
+            && selects(self.host.as_ref(), &binding.host)
+            && selects(self.operation.as_ref(), &binding.operation)
+            && selects(self.permission.as_ref(), &binding.permission)
+    }
+}
+
+/// Whether an optional filter field selects `value`
+fn selects<T: PartialEq>(filter: Option<&T>, value: &T) -> bool {
+    match filter {
+        Some(expected) => expected == value,
+        None => true,
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::Serialization(message)
+}
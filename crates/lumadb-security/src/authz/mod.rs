@@ -1,131 +1,374 @@
 //! Authorization (RBAC and ACLs)
+//!
+//! A request is checked against the roles of its session and against the
+//! ACL bindings of its principal:
+//! - sessions holding a role that grants every action on every resource
+//!   (`admin`) are allowed everything, like Kafka super users
+//! - otherwise a matching `deny` binding refuses the request
+//! - otherwise a role permission or a matching `allow` binding grants it
+//! - anything not granted is refused
+//!
+//! Roles and bindings are kept in the storage engine's metadata when the
+//! authorizer has one, so they survive restarts.
+
+pub mod acl;
 
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{AuthError, Error, Result};
+use lumadb_storage::StorageEngine;
 
-use dashmap::DashMap;
+pub use acl::{
+    AclBinding, AclBindingFilter, Operation, PatternFilter, PatternType, PermissionType, Resource,
+    ResourceType,
+};
+
+/// Metadata key prefix of persisted roles
+const ROLE_PREFIX: &str = "security:role:";
+
+/// Metadata key prefix of persisted ACL bindings
+const ACL_PREFIX: &str = "security:acl:";
 
 /// Authorization manager
 pub struct Authorizer {
     /// Role definitions
-    roles: DashMap<String, Role>,
-    /// Resource ACLs
-    acls: DashMap<String, Vec<AclEntry>>,
+    roles: RwLock<HashMap<String, Role>>,
+    /// ACL bindings
+    acls: RwLock<Vec<AclBinding>>,
+    /// Metadata store roles and bindings persist to
+    storage: Option<Arc<StorageEngine>>,
 }
 
 /// Role definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Role {
     pub name: String,
     pub permissions: Vec<Permission>,
 }
 
 /// Permission
-#[derive(Debug, Clone)]
+///
+/// `resource` is a resource string, `*` for every resource or `type:*`
+/// for every resource of a type; `action` is an [`Operation`] name or `*`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Permission {
     pub resource: String,
     pub action: String,
 }
 
-/// ACL entry
+impl Permission {
+    #[must_use]
+    pub fn new(resource: &str, action: &str) -> Self {
+        Self {
+            resource: resource.to_string(),
+            action: action.to_string(),
+        }
+    }
+
+    /// Whether this permission grants `operation` on `resource`
+    fn grants(&self, resource: &str, operation: Operation) -> bool {
+        let resource_match = self.resource == "*"
+            || self.resource == resource
+            || (self.resource.ends_with(":*")
+                && resource.starts_with(&self.resource[..self.resource.len() - 1]));
+
+        let action_match = self
+            .action
+            .parse::<Operation>()
+            .is_ok_and(|have| have.implies(operation));
+
+        resource_match && action_match
+    }
+
+    /// Whether this permission grants `operation` on some resource of
+    /// `resource_type`
+    fn grants_any(&self, resource_type: ResourceType, operation: Operation) -> bool {
+        let resource_match = self.resource == "*"
+            || self.resource == resource_type.as_str()
+            || self
+                .resource
+                .split_once(':')
+                .is_some_and(|(kind, _)| kind == resource_type.as_str());
+
+        resource_match
+            && self
+                .action
+                .parse::<Operation>()
+                .is_ok_and(|have| have.implies(operation))
+    }
+
+    /// Whether this permission grants everything
+    fn is_superuser(&self) -> bool {
+        self.resource == "*" && self.action == "*"
+    }
+}
+
+/// Caller of a request, as the authorizer sees it
 #[derive(Debug, Clone)]
-pub struct AclEntry {
+pub struct Caller<'a> {
+    /// `User:<name>`
     pub principal: String,
-    pub permission: Permission,
-    pub allow: bool,
+    pub roles: &'a [String],
+    /// Client host, if known
+    pub host: Option<&'a str>,
+}
+
+/// Roles every authorizer starts with
+fn default_roles() -> Vec<Role> {
+    vec![
+        Role {
+            name: "admin".to_string(),
+            permissions: vec![Permission::new("*", "*")],
+        },
+        Role {
+            name: "user".to_string(),
+            permissions: vec![
+                Permission::new("topic:*", "read"),
+                Permission::new("topic:*", "write"),
+                Permission::new("collection:*", "read"),
+            ],
+        },
+        Role {
+            name: "readonly".to_string(),
+            permissions: vec![Permission::new("*", "read")],
+        },
+    ]
 }
 
 impl Authorizer {
-    /// Create a new authorizer with default roles
+    /// Create a new in-memory authorizer with default roles
     pub fn new() -> Self {
-        let auth = Self {
-            roles: DashMap::new(),
-            acls: DashMap::new(),
-        };
+        Self {
+            roles: RwLock::new(
+                default_roles()
+                    .into_iter()
+                    .map(|role| (role.name.clone(), role))
+                    .collect(),
+            ),
+            acls: RwLock::new(Vec::new()),
+            storage: None,
+        }
+    }
 
-        // Define default roles
-        auth.roles.insert(
-            "admin".to_string(),
-            Role {
-                name: "admin".to_string(),
-                permissions: vec![Permission {
-                    resource: "*".to_string(),
-                    action: "*".to_string(),
-                }],
-            },
-        );
-
-        auth.roles.insert(
-            "user".to_string(),
-            Role {
-                name: "user".to_string(),
-                permissions: vec![
-                    Permission {
-                        resource: "topic:*".to_string(),
-                        action: "read".to_string(),
-                    },
-                    Permission {
-                        resource: "topic:*".to_string(),
-                        action: "write".to_string(),
-                    },
-                    Permission {
-                        resource: "collection:*".to_string(),
-                        action: "read".to_string(),
-                    },
-                ],
-            },
-        );
-
-        auth.roles.insert(
-            "readonly".to_string(),
-            Role {
-                name: "readonly".to_string(),
-                permissions: vec![Permission {
-                    resource: "*".to_string(),
-                    action: "read".to_string(),
-                }],
-            },
-        );
-
-        auth
+    /// Create an authorizer keeping its roles and bindings in `storage`
+    ///
+    /// Loads what an earlier run persisted; the default roles are written
+    /// on first use.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the persisted roles or bindings cannot be read or decoded.
+    pub fn with_storage(storage: Arc<StorageEngine>) -> Result<Self> {
+        let mut roles = HashMap::new();
+        for (_, value) in storage.scan_metadata(ROLE_PREFIX)? {
+            let role: Role = serde_json::from_slice(&value)?;
+            roles.insert(role.name.clone(), role);
+        }
+        if roles.is_empty() {
+            for role in default_roles() {
+                storage.put_metadata(&role_key(&role.name), &serde_json::to_vec(&role)?)?;
+                roles.insert(role.name.clone(), role);
+            }
+        }
+
+        let acls = storage
+            .scan_metadata(ACL_PREFIX)?
+            .into_iter()
+            .map(|(_, value)| Ok(serde_json::from_slice(&value)?))
+            .collect::<Result<Vec<AclBinding>>>()?;
+
+        Ok(Self {
+            roles: RwLock::new(roles),
+            acls: RwLock::new(acls),
+            storage: Some(storage),
+        })
     }
 
     /// Check if roles have permission
     pub fn check(&self, roles: &[String], permission: &Permission) -> bool {
-        for role_name in roles {
-            if let Some(role) = self.roles.get(role_name) {
-                for perm in &role.permissions {
-                    if self.matches_permission(perm, permission) {
-                        return true;
-                    }
-                }
-            }
+        let Ok(operation) = permission.action.parse::<Operation>() else {
+            return false;
+        };
+        let defined = self.roles.read();
+        roles
+            .iter()
+            .filter_map(|name| defined.get(name))
+            .flat_map(|role| &role.permissions)
+            .any(|perm| perm.grants(&permission.resource, operation))
+    }
+
+    /// Whether `caller` may perform `operation` on `resource`
+    pub fn authorize(
+        &self,
+        caller: &Caller<'_>,
+        resource: &Resource,
+        operation: Operation,
+    ) -> bool {
+        if self.is_superuser(caller.roles) {
+            return true;
         }
 
-        false
+        let acls = self.acls.read();
+        let mut matching = acls.iter().filter(|binding| {
+            binding.matches_principal(&caller.principal, caller.host)
+                && binding.matches_resource(resource)
+        });
+        let denied = matching.clone().any(|binding| {
+            binding.permission == PermissionType::Deny
+                && (binding.operation == operation || binding.operation == Operation::All)
+        });
+        if denied {
+            return false;
+        }
+
+        self.check(
+            caller.roles,
+            &Permission::new(&resource.to_string(), operation_name(operation)),
+        ) || matching.any(|binding| {
+            binding.permission == PermissionType::Allow && binding.operation.implies(operation)
+        })
     }
 
-    fn matches_permission(&self, have: &Permission, want: &Permission) -> bool {
-        let resource_match = have.resource == "*"
-            || have.resource == want.resource
-            || (have.resource.ends_with(":*")
-                && want.resource.starts_with(&have.resource[..have.resource.len() - 1]));
+    /// Whether `caller` may perform `operation` on at least one resource
+    /// of `resource_type`, ignoring deny bindings
+    ///
+    /// Kafka lets producers allowed to write to some topic use idempotence
+    /// this way.
+    pub fn authorize_any(
+        &self,
+        caller: &Caller<'_>,
+        resource_type: ResourceType,
+        operation: Operation,
+    ) -> bool {
+        if self.is_superuser(caller.roles) {
+            return true;
+        }
+        let roles = self.roles.read();
+        let by_role = caller
+            .roles
+            .iter()
+            .filter_map(|name| roles.get(name))
+            .flat_map(|role| &role.permissions)
+            .any(|perm| perm.grants_any(resource_type, operation));
 
-        let action_match = have.action == "*" || have.action == want.action;
+        by_role
+            || self.acls.read().iter().any(|binding| {
+                binding.resource_type == resource_type
+                    && binding.permission == PermissionType::Allow
+                    && binding.operation.implies(operation)
+                    && binding.matches_principal(&caller.principal, caller.host)
+            })
+    }
 
-        resource_match && action_match
+    fn is_superuser(&self, roles: &[String]) -> bool {
+        let defined = self.roles.read();
+        roles
+            .iter()
+            .filter_map(|name| defined.get(name))
+            .any(|role| role.permissions.iter().any(Permission::is_superuser))
     }
 
-    /// Add a role
-    pub fn add_role(&self, role: Role) {
-        self.roles.insert(role.name.clone(), role);
+    /// Defined roles, by name
+    pub fn roles(&self) -> Vec<Role> {
+        let mut roles: Vec<Role> = self.roles.read().values().cloned().collect();
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        roles
     }
 
-    /// Add an ACL entry
-    pub fn add_acl(&self, resource: &str, entry: AclEntry) {
+    /// Add a role, replacing any of the same name
+    ///
+    /// # Errors
+    ///
+    /// Fails if a permission names an unknown operation, or the role cannot be
+    /// persisted.
+    pub fn add_role(&self, role: Role) -> Result<()> {
+        for permission in &role.permissions {
+            permission.action.parse::<Operation>()?;
+        }
+        if let Some(storage) = &self.storage {
+            storage.put_metadata(&role_key(&role.name), &serde_json::to_vec(&role)?)?;
+        }
+        self.roles.write().insert(role.name.clone(), role);
+        Ok(())
+    }
+
+    /// Remove a role
+    ///
+    /// # Errors
+    ///
+    /// Fails if the role does not exist or cannot be removed from storage.
+    pub fn remove_role(&self, name: &str) -> Result<()> {
+        let mut roles = self.roles.write();
+        if !roles.contains_key(name) {
+            return Err(Error::Auth(AuthError::RoleNotFound(name.to_string())));
+        }
+        if let Some(storage) = &self.storage {
+            storage.delete_metadata(&role_key(name))?;
+        }
+        roles.remove(name);
+        Ok(())
+    }
+
+    /// Add ACL bindings; bindings that already exist are kept once
+    ///
+    /// # Errors
+    ///
+    /// Fails if a binding is incomplete or cannot be persisted.
+    pub fn create_acls(&self, bindings: &[AclBinding]) -> Result<()> {
+        for binding in bindings {
+            binding.validate()?;
+        }
+        let mut acls = self.acls.write();
+        for binding in bindings {
+            if acls.contains(binding) {
+                continue;
+            }
+            if let Some(storage) = &self.storage {
+                storage.put_metadata(&acl_key(binding)?, &serde_json::to_vec(binding)?)?;
+            }
+            acls.push(binding.clone());
+        }
+        Ok(())
+    }
+
+    /// ACL bindings selected by `filter`
+    pub fn describe_acls(&self, filter: &AclBindingFilter) -> Vec<AclBinding> {
         self.acls
-            .entry(resource.to_string())
-            .or_insert_with(Vec::new)
-            .push(entry);
+            .read()
+            .iter()
+            .filter(|binding| filter.matches(binding))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the ACL bindings selected by `filter`, returning them
+    ///
+    /// # Errors
+    ///
+    /// Fails if the removal cannot be persisted.
+    pub fn delete_acls(&self, filter: &AclBindingFilter) -> Result<Vec<AclBinding>> {
+        let mut acls = self.acls.write();
+        let mut deleted = Vec::new();
+        let mut kept = Vec::with_capacity(acls.len());
+        for binding in acls.drain(..) {
+            if filter.matches(&binding) {
+                deleted.push(binding);
+            } else {
+                kept.push(binding);
+            }
+        }
+        *acls = kept;
+
+        if let Some(storage) = &self.storage {
+            for binding in &deleted {
+                storage.delete_metadata(&acl_key(binding)?)?;
+            }
+        }
+        Ok(deleted)
     }
 }
 
@@ -134,3 +377,138 @@ impl Default for Authorizer {
         Self::new()
     }
 }
+
+fn role_key(name: &str) -> String {
+    format!("{ROLE_PREFIX}{name}")
+}
+
+/// Key of a binding: its JSON form, which is unique and unambiguous
+fn acl_key(binding: &AclBinding) -> Result<String> {
+    Ok(format!("{ACL_PREFIX}{}", serde_json::to_string(binding)?))
+}
+
+/// Name role permissions use for `operation`
+pub(crate) fn operation_name(operation: Operation) -> &'static str {
+    match operation {
+        Operation::All => "*",
+        Operation::Read => "read",
+        Operation::Write => "write",
+        Operation::Create => "create",
+        Operation::Delete => "delete",
+        Operation::Alter => "alter",
+        Operation::Describe => "describe",
+        Operation::ClusterAction => "cluster_action",
+        Operation::DescribeConfigs => "describe_configs",
+        Operation::AlterConfigs => "alter_configs",
+        Operation::IdempotentWrite => "idempotent_write",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(
+        name: &str,
+        pattern: PatternType,
+        operation: Operation,
+        permission: PermissionType,
+    ) -> AclBinding {
+        AclBinding {
+            resource_type: ResourceType::Topic,
+            resource_name: name.to_string(),
+            pattern_type: pattern,
+            principal: "User:alice".to_string(),
+            host: "*".to_string(),
+            operation,
+            permission,
+        }
+    }
+
+    fn caller(roles: &[String]) -> Caller<'_> {
+        Caller {
+            principal: "User:alice".to_string(),
+            roles,
+            host: Some("10.0.0.1"),
+        }
+    }
+
+    #[test]
+    fn acls_grant_and_deny_on_top_of_roles() {
+        let authz = Authorizer::new();
+        let readonly = vec!["readonly".to_string()];
+        let orders = Resource::new(ResourceType::Topic, "orders-eu");
+        let payments = Resource::new(ResourceType::Topic, "payments");
+
+        assert!(authz.authorize(&caller(&readonly), &orders, Operation::Read));
+        assert!(!authz.authorize(&caller(&readonly), &orders, Operation::Write));
+
+        authz
+            .create_acls(&[
+                binding(
+                    "orders-",
+                    PatternType::Prefixed,
+                    Operation::Write,
+                    PermissionType::Allow,
+                ),
+                binding(
+                    "payments",
+                    PatternType::Literal,
+                    Operation::All,
+                    PermissionType::Deny,
+                ),
+            ])
+            .unwrap();
+        assert!(authz.authorize(&caller(&readonly), &orders, Operation::Write));
+        // Write implies describe
+        assert!(authz.authorize(&caller(&readonly), &orders, Operation::Describe));
+        // Deny overrides the readonly role
+        assert!(!authz.authorize(&caller(&readonly), &payments, Operation::Read));
+        // but not the admin role
+        assert!(authz.authorize(&caller(&["admin".to_string()]), &payments, Operation::Read));
+
+        let deleted = authz
+            .delete_acls(&AclBindingFilter {
+                permission: Some(PermissionType::Deny),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert!(authz.authorize(&caller(&readonly), &payments, Operation::Read));
+    }
+
+    #[test]
+    fn match_filter_finds_bindings_applying_to_a_resource() {
+        let authz = Authorizer::new();
+        authz
+            .create_acls(&[
+                binding(
+                    "orders-",
+                    PatternType::Prefixed,
+                    Operation::Read,
+                    PermissionType::Allow,
+                ),
+                binding(
+                    "*",
+                    PatternType::Literal,
+                    Operation::Describe,
+                    PermissionType::Allow,
+                ),
+                binding(
+                    "invoices",
+                    PatternType::Literal,
+                    Operation::Read,
+                    PermissionType::Allow,
+                ),
+            ])
+            .unwrap();
+
+        let filter = AclBindingFilter {
+            resource_type: Some(ResourceType::Topic),
+            resource_name: Some("orders-eu".to_string()),
+            pattern_type: PatternFilter::Match,
+            ..Default::default()
+        };
+        assert_eq!(authz.describe_acls(&filter).len(), 2);
+    }
+}
@@ -1,7 +1,14 @@
 //! Cryptographic utilities
+//!
+//! Encryption at rest lives in the storage layer, which owns the files it
+//! seals; its key management types are re-exported here.
 
 use lumadb_common::error::Result;
 
+pub use lumadb_storage::encryption::{
+    DataKeys, KeyInfo, KeyManager, KeyProvider, LocalKeyProvider, WrappedKey,
+};
+
 /// TLS configuration builder
 pub struct TlsConfig {
     pub cert_path: Option<String>,
@@ -9,13 +9,15 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
+pub mod audit;
 pub mod auth;
 pub mod authz;
 pub mod crypto;
 
 mod manager;
 
-pub use manager::SecurityManager;
+pub use audit::{AuditCategory, AuditEvent, AuditLog};
+pub use manager::{AuthMechanism, AuthenticatedUser, Credentials, SecurityManager, Session};
 
 use lumadb_common::config::SecurityConfig;
 use lumadb_common::error::Result;
@@ -3,14 +3,22 @@
 use std::sync::Arc;
 
 use dashmap::DashMap;
-use parking_lot::RwLock;
 use tracing::info;
 
 use lumadb_common::config::SecurityConfig;
 use lumadb_common::error::{Result, Error, AuthError};
+use lumadb_storage::StorageEngine;
 
-use crate::auth::{Authenticator, JwtAuth, SaslAuth};
-use crate::authz::{Authorizer, Permission, Role};
+use crate::audit::{self, AuditCategory, AuditEvent, AuditLog};
+use crate::auth::sasl::{self, SaslExchange, SaslMechanism, SaslStep};
+use crate::auth::{
+    Authenticator, JwtAuth, MtlsAuth, SaslAuth, ScramCredential, ScramCredentialInfo,
+    ScramCredentials, ScramMechanism,
+};
+use crate::authz::{
+    operation_name, AclBinding, AclBindingFilter, Authorizer, Caller, Operation, Resource,
+    ResourceType, Role,
+};
 
 /// Main security manager
 pub struct SecurityManager {
@@ -18,18 +26,90 @@ pub struct SecurityManager {
     authenticator: Box<dyn Authenticator>,
     authorizer: Authorizer,
     sessions: DashMap<String, Session>,
+    /// SCRAM credentials of SASL users
+    scram: ScramCredentials,
+    /// Validator of OAUTHBEARER tokens, when a JWT secret is configured
+    oauth: Option<JwtAuth>,
+    /// Audit trail, when `audit_enabled`
+    audit: Option<Arc<AuditLog>>,
 }
 
 /// User session
+///
+/// Every entry point authenticates its callers into a session, whichever
+/// mechanism they used, and authorizes requests against it.
 #[derive(Debug, Clone)]
 pub struct Session {
     pub user_id: String,
     pub username: String,
     pub roles: Vec<String>,
+    /// How the caller authenticated
+    pub mechanism: AuthMechanism,
+    /// Address the caller connected from, if known
+    pub host: Option<String>,
     pub created_at: i64,
     pub expires_at: i64,
 }
 
+impl Session {
+    /// Session of every caller when authentication is disabled
+    #[must_use]
+    pub fn anonymous() -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        Self {
+            user_id: "anonymous".to_string(),
+            username: "anonymous".to_string(),
+            roles: vec!["admin".to_string()],
+            mechanism: AuthMechanism::Anonymous,
+            host: None,
+            created_at: now,
+            expires_at: now + 86_400_000,
+        }
+    }
+
+    /// Principal ACLs name the caller by: `User:<username>`
+    #[must_use]
+    pub fn principal(&self) -> String {
+        format!("User:{}", self.username)
+    }
+
+    /// Record the address the caller connected from
+    #[must_use]
+    #[allow(clippy::needless_pass_by_value)] // takes addresses and strings alike
+    pub fn with_host(mut self, host: impl ToString) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+}
+
+/// Mechanism a session was authenticated with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMechanism {
+    /// Authentication is disabled
+    Anonymous,
+    /// Bearer JWT
+    Jwt,
+    /// Username and password, as in SASL/PLAIN
+    Password,
+    /// SASL/SCRAM challenge-response
+    Scram,
+    /// Client certificate
+    Mtls,
+}
+
+impl AuthMechanism {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Anonymous => "anonymous",
+            Self::Jwt => "jwt",
+            Self::Password => "password",
+            Self::Scram => "scram",
+            Self::Mtls => "mtls",
+        }
+    }
+}
+
 impl SecurityManager {
     /// Create a new security manager
     pub async fn new(config: &SecurityConfig) -> Result<Self> {
@@ -46,6 +126,7 @@ impl SecurityManager {
                     Box::new(JwtAuth::new(&config.jwt_secret))
                 },
                 "sasl" => Box::new(SaslAuth::new()),
+                "mtls" => Box::new(MtlsAuth::new()),
                 _ => Box::new(NoAuth),
             }
         } else {
@@ -53,12 +134,22 @@ impl SecurityManager {
         };
 
         let authorizer = Authorizer::new();
+        let audit = if config.audit_enabled {
+            Some(Arc::new(AuditLog::open(&config.audit)?))
+        } else {
+            None
+        };
+
+        let oauth = (!config.jwt_secret.is_empty()).then(|| JwtAuth::new(&config.jwt_secret));
 
         Ok(Self {
             config: config.clone(),
             authenticator,
             authorizer,
             sessions: DashMap::new(),
+            scram: ScramCredentials::new(),
+            oauth,
+            audit,
         })
     }
 
@@ -68,31 +159,58 @@ impl SecurityManager {
         credentials: &Credentials,
     ) -> Result<Session> {
         if !self.config.auth_enabled {
-            return Ok(Session {
-                user_id: "anonymous".to_string(),
-                username: "anonymous".to_string(),
-                roles: vec!["admin".to_string()],
-                created_at: chrono::Utc::now().timestamp_millis(),
-                expires_at: chrono::Utc::now().timestamp_millis() + 86400000,
-            });
+            return Ok(Session::anonymous());
         }
 
-        let user = self.authenticator.authenticate(credentials).await?;
+        let mechanism = match credentials {
+            Credentials::UsernamePassword { .. } => AuthMechanism::Password,
+            Credentials::Token(_) => AuthMechanism::Jwt,
+            Credentials::Certificate(_) => AuthMechanism::Mtls,
+        };
+        let attempt = AuditEvent::new(AuditCategory::Authentication, mechanism.as_str());
+        let user = match self.authenticator.authenticate(credentials).await {
+            Ok(user) => user,
+            Err(e) => {
+                let attempt = match credentials {
+                    Credentials::UsernamePassword { username, .. } => {
+                        attempt.by_principal(format!("User:{username}"))
+                    }
+                    _ => attempt,
+                };
+                self.audit(attempt.failed(e.to_string()));
+                return Err(e);
+            }
+        };
 
         let session = Session {
             user_id: user.id.clone(),
             username: user.username.clone(),
             roles: user.roles.clone(),
+            mechanism,
+            host: None,
             created_at: chrono::Utc::now().timestamp_millis(),
             expires_at: chrono::Utc::now().timestamp_millis() + 86400000,
         };
 
         let session_id = uuid::Uuid::new_v4().to_string();
         self.sessions.insert(session_id.clone(), session.clone());
+        self.audit(attempt.by(&session));
 
         Ok(session)
     }
 
+    /// Security configuration
+    #[must_use]
+    pub fn config(&self) -> &SecurityConfig {
+        &self.config
+    }
+
+    /// Whether clients must authenticate
+    #[must_use]
+    pub fn auth_enabled(&self) -> bool {
+        self.config.auth_enabled
+    }
+
     /// Validate a session
     pub fn validate_session(&self, session_id: &str) -> Result<Session> {
         self.sessions
@@ -101,7 +219,166 @@ impl SecurityManager {
             .ok_or_else(|| Error::Auth(AuthError::InvalidToken("Session not found".to_string())))
     }
 
+    /// Keep roles, ACLs and SCRAM credentials in the storage engine's
+    /// metadata, loading those an earlier run persisted
+    ///
+    /// # Errors
+    ///
+    /// Fails if the persisted roles or bindings cannot be loaded.
+    pub fn with_storage(mut self, storage: Arc<StorageEngine>) -> Result<Self> {
+        self.authorizer = Authorizer::with_storage(storage.clone())?;
+        self.scram = ScramCredentials::with_storage(storage)?;
+        Ok(self)
+    }
+
+    /// SASL mechanisms clients may authenticate with
+    pub fn sasl_mechanisms(&self) -> Vec<SaslMechanism> {
+        let mut mechanisms = vec![SaslMechanism::Plain];
+        mechanisms.extend(ScramMechanism::ALL.map(SaslMechanism::Scram));
+        if self.oauth.is_some() {
+            mechanisms.push(SaslMechanism::OAuthBearer);
+        }
+        mechanisms
+    }
+
+    /// Start a SASL exchange with the named mechanism, if it is offered
+    pub fn sasl_exchange(&self, mechanism: &str) -> Option<SaslExchange> {
+        let mechanism = mechanism.parse::<SaslMechanism>().ok()?;
+        self.sasl_mechanisms()
+            .contains(&mechanism)
+            .then(|| SaslExchange::new(mechanism))
+    }
+
+    /// Take the client's next message of a SASL exchange
+    ///
+    /// Any error ends the exchange; a completed exchange takes no more
+    /// messages.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the message is malformed or the credentials are rejected.
+    pub async fn sasl_step(&self, exchange: &mut SaslExchange, message: &[u8]) -> Result<SaslStep> {
+        if exchange.complete {
+            return Err(Error::Auth(AuthError::AuthenticationFailed(
+                "SASL exchange already completed".to_string(),
+            )));
+        }
+        let mechanism = exchange.mechanism;
+        let step = match (mechanism, exchange.scram.as_mut()) {
+            (SaslMechanism::Plain, _) => {
+                exchange.complete = true;
+                let (username, password) = sasl::parse_plain(message)?;
+                let credentials = Credentials::UsernamePassword { username, password };
+                // Audited by `authenticate`
+                return Ok(SaslStep::Complete(
+                    self.authenticate(&credentials).await?,
+                    Vec::new(),
+                ));
+            }
+            (SaslMechanism::Scram(_), Some(scram)) if !scram.is_started() => {
+                match scram.client_first(message, &self.scram) {
+                    Ok(challenge) => return Ok(SaslStep::Challenge(challenge)),
+                    Err(e) => Err(e),
+                }
+            }
+            (SaslMechanism::Scram(_), Some(scram)) => scram
+                .client_final(message)
+                .map(|(username, server_final)| (scram_session(username), server_final)),
+            (SaslMechanism::OAuthBearer, _) => self.oauthbearer_session(message),
+            (SaslMechanism::Scram(_), None) => unreachable!("SCRAM exchanges hold a SCRAM server"),
+        };
+        exchange.complete = true;
+
+        let attempt = AuditEvent::new(AuditCategory::Authentication, mechanism.name());
+        match step {
+            Ok((session, last)) => {
+                self.audit(attempt.by(&session));
+                Ok(SaslStep::Complete(session, last))
+            }
+            Err(e) => {
+                self.audit(attempt.failed(e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Session of the holder of the JWT in an OAUTHBEARER message
+    fn oauthbearer_session(&self, message: &[u8]) -> Result<(Session, Vec<u8>)> {
+        let oauth = self.oauth.as_ref().ok_or_else(|| {
+            Error::Auth(AuthError::AuthenticationFailed(
+                "OAUTHBEARER is not enabled".to_string(),
+            ))
+        })?;
+        let (authzid, token) = sasl::parse_oauthbearer(message)?;
+        let claims = oauth.validate_token(&token)?;
+        if authzid.is_some_and(|authzid| authzid != claims.username) {
+            return Err(Error::Auth(AuthError::AuthenticationFailed(
+                "authorization id must match the token's subject".to_string(),
+            )));
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let session = Session {
+            user_id: claims.sub,
+            username: claims.username,
+            roles: claims.roles,
+            mechanism: AuthMechanism::Jwt,
+            host: None,
+            created_at: now,
+            expires_at: i64::try_from(claims.exp).map_or(i64::MAX, |exp| exp.saturating_mul(1000)),
+        };
+        Ok((session, Vec::new()))
+    }
+
+    /// SCRAM credentials, without their secrets
+    pub fn scram_credentials(&self) -> Vec<ScramCredentialInfo> {
+        self.scram.describe()
+    }
+
+    /// Add or replace the SCRAM credential of `username` on behalf of
+    /// `actor`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the username is empty or the credential cannot be persisted.
+    pub fn upsert_scram_credential(
+        &self,
+        actor: &Session,
+        username: &str,
+        mechanism: ScramMechanism,
+        credential: ScramCredential,
+    ) -> Result<()> {
+        info!("Setting {} credential of {}", mechanism, username);
+        let event = AuditEvent::new(AuditCategory::Admin, "upsert_scram_credential")
+            .by(actor)
+            .on(format!("user:{username}"))
+            .detail(format!("{mechanism}, {} iterations", credential.iterations));
+        self.audited(event, self.scram.upsert(username, mechanism, credential))
+    }
+
+    /// Remove the SCRAM credential of `username` on behalf of `actor`
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such credential or it cannot be removed from
+    /// storage.
+    pub fn delete_scram_credential(
+        &self,
+        actor: &Session,
+        username: &str,
+        mechanism: ScramMechanism,
+    ) -> Result<()> {
+        info!("Removing {} credential of {}", mechanism, username);
+        let event = AuditEvent::new(AuditCategory::Admin, "delete_scram_credential")
+            .by(actor)
+            .on(format!("user:{username}"))
+            .detail(mechanism.name());
+        self.audited(event, self.scram.delete(username, mechanism))
+    }
+
     /// Check authorization
+    ///
+    /// `resource` is a resource string such as `collection:users` or
+    /// `cluster`, and `action` an operation name such as `read`.
     pub fn authorize(
         &self,
         session: &Session,
@@ -112,12 +389,21 @@ impl SecurityManager {
             return Ok(());
         }
 
-        let permission = Permission {
-            resource: resource.to_string(),
-            action: action.to_string(),
+        let allowed = if let (Ok(resource), Ok(operation)) =
+            (resource.parse::<Resource>(), action.parse::<Operation>())
+        {
+            self.permits(session, &resource, operation)
+        } else {
+            self.audit(
+                AuditEvent::new(AuditCategory::Authorization, action)
+                    .by(session)
+                    .on(resource)
+                    .failed("invalid resource or action"),
+            );
+            false
         };
 
-        if self.authorizer.check(&session.roles, &permission) {
+        if allowed {
             Ok(())
         } else {
             Err(Error::Auth(AuthError::PermissionDenied(format!(
@@ -127,12 +413,206 @@ impl SecurityManager {
         }
     }
 
+    /// Whether `session` may perform `action` on `resource`, without
+    /// recording a denial; for filtering what a caller is shown
+    pub fn is_authorized(&self, session: &Session, resource: &str, action: &str) -> bool {
+        match (resource.parse::<Resource>(), action.parse::<Operation>()) {
+            (Ok(resource), Ok(operation)) => self.allows(session, &resource, operation),
+            _ => !self.config.auth_enabled,
+        }
+    }
+
+    /// Whether `session` may perform `operation` on `resource`; denials
+    /// are audited
+    pub fn permits(&self, session: &Session, resource: &Resource, operation: Operation) -> bool {
+        let allowed = self.allows(session, resource, operation);
+        if !allowed {
+            self.audit(
+                AuditEvent::new(AuditCategory::Authorization, operation_name(operation))
+                    .by(session)
+                    .on(resource.to_string())
+                    .failed("denied"),
+            );
+        }
+        allowed
+    }
+
+    /// Whether `session` may perform `operation` on `resource`, without
+    /// recording a denial
+    pub fn allows(&self, session: &Session, resource: &Resource, operation: Operation) -> bool {
+        !self.config.auth_enabled
+            || self
+                .authorizer
+                .authorize(&caller(session), resource, operation)
+    }
+
+    /// Whether `session` may perform `operation` on some resource of
+    /// `resource_type`, without recording a denial
+    pub fn is_authorized_any(
+        &self,
+        session: &Session,
+        resource_type: ResourceType,
+        operation: Operation,
+    ) -> bool {
+        !self.config.auth_enabled
+            || self
+                .authorizer
+                .authorize_any(&caller(session), resource_type, operation)
+    }
+
+    /// Defined roles
+    pub fn roles(&self) -> Vec<Role> {
+        self.authorizer.roles()
+    }
+
+    /// Define or replace a role on behalf of `actor`
+    ///
+    /// # Errors
+    ///
+    /// Fails if a permission names an unknown operation, or the role cannot be
+    /// persisted.
+    pub fn put_role(&self, actor: &Session, role: Role) -> Result<()> {
+        info!("Defining role {}", role.name);
+        let event = AuditEvent::new(AuditCategory::Admin, "put_role")
+            .by(actor)
+            .on(format!("role:{}", role.name))
+            .detail(serde_json::to_string(&role.permissions)?);
+        self.audited(event, self.authorizer.add_role(role))
+    }
+
+    /// Remove a role on behalf of `actor`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the role does not exist or cannot be removed from storage.
+    pub fn delete_role(&self, actor: &Session, name: &str) -> Result<()> {
+        info!("Removing role {}", name);
+        let event = AuditEvent::new(AuditCategory::Admin, "delete_role")
+            .by(actor)
+            .on(format!("role:{name}"));
+        self.audited(event, self.authorizer.remove_role(name))
+    }
+
+    /// Add ACL bindings on behalf of `actor`
+    ///
+    /// # Errors
+    ///
+    /// Fails if a binding is incomplete or cannot be persisted.
+    pub fn create_acls(&self, actor: &Session, bindings: &[AclBinding]) -> Result<()> {
+        info!("Creating {} ACL bindings", bindings.len());
+        let event = AuditEvent::new(AuditCategory::Admin, "create_acls")
+            .by(actor)
+            .detail(serde_json::to_string(bindings)?);
+        self.audited(event, self.authorizer.create_acls(bindings))
+    }
+
+    /// ACL bindings selected by `filter`
+    pub fn describe_acls(&self, filter: &AclBindingFilter) -> Vec<AclBinding> {
+        self.authorizer.describe_acls(filter)
+    }
+
+    /// Remove the ACL bindings selected by `filter` on behalf of `actor`,
+    /// returning them
+    ///
+    /// # Errors
+    ///
+    /// Fails if the removal cannot be persisted.
+    pub fn delete_acls(
+        &self,
+        actor: &Session,
+        filter: &AclBindingFilter,
+    ) -> Result<Vec<AclBinding>> {
+        let event = AuditEvent::new(AuditCategory::Admin, "delete_acls").by(actor);
+        let deleted = match self.authorizer.delete_acls(filter) {
+            Ok(deleted) => deleted,
+            Err(e) => {
+                self.audit(event.failed(e.to_string()));
+                return Err(e);
+            }
+        };
+        info!("Deleted {} ACL bindings", deleted.len());
+        self.audit(event.detail(serde_json::to_string(&deleted)?));
+        Ok(deleted)
+    }
+
+    /// Record `event` in the audit trail, if auditing is enabled
+    pub fn audit(&self, event: AuditEvent) {
+        if let Some(log) = &self.audit {
+            if let Err(e) = log.record(event) {
+                tracing::error!("Failed to write audit record: {}", e);
+            }
+        }
+    }
+
+    /// Record a statement accepted for execution, given the action it
+    /// takes on each resource it touches: DDL when it creates, drops or
+    /// alters one, DML when it writes one; its literals are redacted
+    pub fn audit_statement(&self, session: &Session, statement: &str, accesses: &[(String, &str)]) {
+        if self.audit.is_none() {
+            return;
+        }
+        let ddl = accesses
+            .iter()
+            .find(|(_, action)| matches!(*action, "create" | "delete" | "alter"));
+        let (category, (resource, action)) = match ddl {
+            Some(access) => (AuditCategory::Ddl, access),
+            None => match accesses.iter().find(|(_, action)| *action == "write") {
+                Some(access) => (AuditCategory::Dml, access),
+                None => return,
+            },
+        };
+        self.audit(
+            AuditEvent::new(category, *action)
+                .by(session)
+                .on(resource.clone())
+                .detail(audit::redact(statement)),
+        );
+    }
+
+    /// The audit trail, if auditing is enabled
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit.as_ref()
+    }
+
+    /// Record the outcome of an action described by `event`
+    fn audited<T>(&self, event: AuditEvent, result: Result<T>) -> Result<T> {
+        match &result {
+            Ok(_) => self.audit(event),
+            Err(e) => self.audit(event.failed(e.to_string())),
+        }
+        result
+    }
+
     /// Invalidate a session
     pub fn invalidate_session(&self, session_id: &str) {
         self.sessions.remove(session_id);
     }
 }
 
+/// The authorizer's view of `session`
+fn caller(session: &Session) -> Caller<'_> {
+    Caller {
+        principal: session.principal(),
+        roles: &session.roles,
+        host: session.host.as_deref(),
+    }
+}
+
+/// Session of a user who proved their SCRAM credential; SASL users hold
+/// no roles, ACLs name them by principal
+fn scram_session(username: String) -> Session {
+    let now = chrono::Utc::now().timestamp_millis();
+    Session {
+        user_id: username.clone(),
+        username,
+        roles: Vec::new(),
+        mechanism: AuthMechanism::Scram,
+        host: None,
+        created_at: now,
+        expires_at: now + 86_400_000,
+    }
+}
+
 /// User credentials
 #[derive(Debug, Clone)]
 pub enum Credentials {
@@ -0,0 +1,470 @@
+//! Change stream of committed document writes
+//!
+//! Every commit publishes its document changes to the engine's
+//! [`ChangeStream`] once they are applied, so a subscriber that sees a
+//! change can read the document it describes. Each change has a
+//! [`ChangePosition`]; a subscriber that remembers the position of the
+//! last change it processed can resume from there without missing any.
+//!
+//! The most recent changes are retained in a tree of the metadata
+//! database, sealed with the WAL's data key when encryption at rest is on,
+//! so positions stay valid across restarts. Resuming from a position that
+//! has fallen out of the retained window, that belongs to another
+//! database, or whose later changes were lost in a crash fails with
+//! [`StorageError::ChangeStreamExpired`].
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::error;
+
+use lumadb_common::error::{Error, Result, StorageError};
+use lumadb_common::types::Document;
+
+use crate::encryption::DataKeys;
+
+/// Tree of the metadata database holding the retained changes by
+/// sequence number
+const CHANGES_TREE: &str = "changes";
+
+/// Metadata key of the stream's epoch
+const EPOCH_KEY: &str = "changes:epoch";
+
+/// Metadata key of the sequence number of the latest change dropped from
+/// the log
+const TRIMMED_KEY: &str = "changes:trimmed";
+
+/// What a change did to its document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The document was created
+    Insert,
+    /// An existing document was replaced
+    Update,
+    /// The document was deleted
+    Delete,
+}
+
+impl ChangeKind {
+    /// Lowercase name of the kind
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// A committed write of one document
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    /// Position of the change in the stream
+    pub position: ChangePosition,
+    /// Commit timestamp
+    pub ts: u64,
+    /// Collection of the document
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// What the change did
+    pub kind: ChangeKind,
+    /// Document before the change, `None` for insertions
+    pub before: Option<Document>,
+    /// Document after the change, `None` for deletions
+    pub after: Option<Document>,
+}
+
+/// Position of a change in a [`ChangeStream`]
+///
+/// Positions format as opaque resume tokens and parse back from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChangePosition {
+    /// Creation time of the stream that issued the position
+    epoch: u64,
+    /// Sequence number of the change, 0 before the first
+    seq: u64,
+}
+
+impl fmt::Display for ChangePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}.{:x}", self.epoch, self.seq)
+    }
+}
+
+impl FromStr for ChangePosition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Serialization(format!("invalid change stream position {s}"));
+        let (epoch, seq) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            epoch: u64::from_str_radix(epoch, 16).map_err(|_| invalid())?,
+            seq: u64::from_str_radix(seq, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A change as published by a commit: collection, ID and the document
+/// before and after it
+pub(crate) type PublishedChange = (String, String, Option<Document>, Option<Document>);
+
+/// A change as stored in the log
+#[derive(Serialize, Deserialize)]
+struct StoredChange {
+    ts: u64,
+    collection: String,
+    id: String,
+    before: Option<Document>,
+    after: Option<Document>,
+}
+
+fn read_failed(e: &sled::Error) -> Error {
+    Error::Storage(StorageError::ReadFailed(e.to_string()))
+}
+
+fn write_failed(e: &sled::Error) -> Error {
+    Error::Storage(StorageError::WriteFailed(e.to_string()))
+}
+
+fn expired(message: &str) -> Error {
+    Error::Storage(StorageError::ChangeStreamExpired(message.to_string()))
+}
+
+fn closed() -> Error {
+    Error::Storage(StorageError::ReadFailed(
+        "the storage engine was shut down".to_string(),
+    ))
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    bytes.try_into().map_or(0, u64::from_be_bytes)
+}
+
+/// Recent document changes, in the order their commits were applied
+///
+/// Sequence numbers come from the metadata database's ID generator, which
+/// never hands out an ID twice, even after a crash; they increase but may
+/// skip values.
+pub struct ChangeStream {
+    epoch: u64,
+    /// Number of changes retained
+    capacity: usize,
+    db: sled::Db,
+    tree: sled::Tree,
+    /// Data keys sealing stored changes, when encryption is enabled
+    cipher: Option<Arc<DataKeys>>,
+    log: Mutex<ChangeLog>,
+    /// Sequence number of the latest change
+    latest: watch::Sender<u64>,
+}
+
+struct ChangeLog {
+    /// Sequence number of the latest change
+    last_seq: u64,
+    /// Sequence number of the latest change no longer retained
+    trimmed: u64,
+    /// Number of changes retained
+    len: usize,
+}
+
+impl ChangeStream {
+    /// Open the stream stored in `db`, retaining the latest `capacity`
+    /// changes
+    pub(crate) fn open(
+        db: &sled::Db,
+        capacity: usize,
+        cipher: Option<Arc<DataKeys>>,
+    ) -> Result<Self> {
+        let tree = db.open_tree(CHANGES_TREE).map_err(|e| read_failed(&e))?;
+        let epoch = if let Some(epoch) = db.get(EPOCH_KEY).map_err(|e| read_failed(&e))? {
+            decode_u64(&epoch)
+        } else {
+            let epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| u64::try_from(d.as_micros()).unwrap_or(u64::MAX));
+            db.insert(EPOCH_KEY, &epoch.to_be_bytes())
+                .map_err(|e| write_failed(&e))?;
+            epoch
+        };
+        let trimmed = db
+            .get(TRIMMED_KEY)
+            .map_err(|e| read_failed(&e))?
+            .map_or(0, |trimmed| decode_u64(&trimmed));
+        let last_seq = tree
+            .last()
+            .map_err(|e| read_failed(&e))?
+            .map_or(trimmed, |(key, _)| decode_u64(&key));
+
+        Ok(Self {
+            epoch,
+            capacity: capacity.max(1),
+            db: db.clone(),
+            log: Mutex::new(ChangeLog {
+                last_seq,
+                trimmed,
+                len: tree.len(),
+            }),
+            tree,
+            cipher,
+            latest: watch::Sender::new(last_seq),
+        })
+    }
+
+    /// Position of the latest change; subscribing from it receives only
+    /// changes published afterwards
+    pub fn position(&self) -> ChangePosition {
+        ChangePosition {
+            epoch: self.epoch,
+            seq: self.log.lock().last_seq,
+        }
+    }
+
+    /// Publish the changes of a commit applied at `ts`
+    ///
+    /// The commit has been applied already, so a change that cannot be
+    /// stored is logged and expires every position before it rather than
+    /// failing the commit.
+    pub(crate) fn publish(&self, ts: u64, changes: Vec<PublishedChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        let mut log = self.log.lock();
+        if let Err(e) = self.append(&mut log, ts, changes) {
+            error!("Failed to record document changes: {}", e);
+            log.trimmed = log.last_seq;
+        }
+        self.latest.send_replace(log.last_seq);
+    }
+
+    fn append(&self, log: &mut ChangeLog, ts: u64, changes: Vec<PublishedChange>) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (collection, id, before, after) in changes {
+            let seq = self.db.generate_id().map_err(|e| write_failed(&e))? + 1;
+            let key = seq.to_be_bytes();
+            let change = StoredChange {
+                ts,
+                collection,
+                id,
+                before,
+                after,
+            };
+            let mut value = serde_json::to_vec(&change)?;
+            if let Some(cipher) = &self.cipher {
+                value = cipher.seal(&key, &value)?;
+            }
+            batch.insert(&key, value);
+            log.last_seq = seq;
+            log.len += 1;
+        }
+        self.tree.apply_batch(batch).map_err(|e| write_failed(&e))?;
+
+        let trimmed = log.trimmed;
+        while log.len > self.capacity {
+            let Some((key, _)) = self.tree.pop_min().map_err(|e| write_failed(&e))? else {
+                break;
+            };
+            log.trimmed = decode_u64(&key);
+            log.len -= 1;
+        }
+        if log.trimmed != trimmed {
+            self.db
+                .insert(TRIMMED_KEY, &log.trimmed.to_be_bytes())
+                .map_err(|e| write_failed(&e))?;
+        }
+        Ok(())
+    }
+
+    /// The change after sequence number `seq`, or `None` if it has not
+    /// happened yet
+    fn after(&self, seq: u64) -> Result<Option<Arc<ChangeEvent>>> {
+        // Hold the log so no change is trimmed between the check and the read
+        let log = self.log.lock();
+        if seq < log.trimmed {
+            return Err(expired("changes after it are no longer retained"));
+        }
+        let Some((key, value)) = self
+            .tree
+            .range((seq + 1).to_be_bytes()..)
+            .next()
+            .transpose()
+            .map_err(|e| read_failed(&e))?
+        else {
+            return Ok(None);
+        };
+        drop(log);
+
+        let value = match &self.cipher {
+            Some(cipher) => cipher.open_or_plain(&key, &value)?,
+            None => value.to_vec(),
+        };
+        let change: StoredChange = serde_json::from_slice(&value)?;
+        let kind = match (&change.before, &change.after) {
+            (None, _) => ChangeKind::Insert,
+            (Some(_), Some(_)) => ChangeKind::Update,
+            (Some(_), None) => ChangeKind::Delete,
+        };
+        Ok(Some(Arc::new(ChangeEvent {
+            position: ChangePosition {
+                epoch: self.epoch,
+                seq: decode_u64(&key),
+            },
+            ts: change.ts,
+            collection: change.collection,
+            id: change.id,
+            kind,
+            before: change.before,
+            after: change.after,
+        })))
+    }
+
+    /// Read the changes after `after`, or those published from now on
+    ///
+    /// # Errors
+    ///
+    /// Fails if `after` has expired.
+    pub fn subscribe(
+        self: &Arc<Self>,
+        after: Option<ChangePosition>,
+    ) -> Result<ChangeSubscription> {
+        let cursor = match after {
+            None => self.log.lock().last_seq,
+            Some(position) => {
+                if position.epoch != self.epoch {
+                    return Err(expired("position was issued by another database"));
+                }
+                if position.seq > self.log.lock().last_seq {
+                    return Err(expired("changes up to the position were lost in a crash"));
+                }
+                self.after(position.seq)?;
+                position.seq
+            }
+        };
+        Ok(ChangeSubscription {
+            stream: Arc::downgrade(self),
+            epoch: self.epoch,
+            cursor,
+            latest: self.latest.subscribe(),
+        })
+    }
+}
+
+/// Reader of a [`ChangeStream`] from a position
+///
+/// Changes are kept by the stream rather than queued per subscriber, so a
+/// subscriber that reads too slowly falls out of the retained window and
+/// gets [`StorageError::ChangeStreamExpired`] instead of holding memory.
+///
+/// A subscription does not keep the stream, and with it the metadata
+/// database, open: it ends once the storage engine is dropped.
+pub struct ChangeSubscription {
+    stream: Weak<ChangeStream>,
+    epoch: u64,
+    /// Sequence number of the last change read
+    cursor: u64,
+    latest: watch::Receiver<u64>,
+}
+
+impl ChangeSubscription {
+    /// Wait for the next change
+    ///
+    /// # Errors
+    ///
+    /// Fails if the stream was closed, or this subscriber fell behind the
+    /// retained changes.
+    pub async fn next(&mut self) -> Result<Arc<ChangeEvent>> {
+        loop {
+            let stream = self.stream.upgrade().ok_or_else(closed)?;
+            if let Some(event) = stream.after(self.cursor)? {
+                self.cursor = event.position.seq;
+                return Ok(event);
+            }
+            drop(stream);
+            let cursor = self.cursor;
+            self.latest
+                .wait_for(|latest| *latest > cursor)
+                .await
+                .map_err(|_| closed())?;
+        }
+    }
+
+    /// Position of the last change read, to resume from
+    #[must_use]
+    pub fn position(&self) -> ChangePosition {
+        ChangePosition {
+            epoch: self.epoch,
+            seq: self.cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(id: &str, before: Option<i64>, after: Option<i64>) -> PublishedChange {
+        let doc = |v| Document::with_id(id, serde_json::json!({ "v": v }));
+        (
+            "items".to_string(),
+            id.to_string(),
+            before.map(doc),
+            after.map(doc),
+        )
+    }
+
+    fn stream(capacity: usize) -> Arc<ChangeStream> {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Arc::new(ChangeStream::open(&db, capacity, None).unwrap())
+    }
+
+    #[tokio::test]
+    async fn subscribers_resume_after_their_position() {
+        let stream = stream(16);
+        let mut live = stream.subscribe(None).unwrap();
+        stream.publish(
+            1,
+            vec![change("a", None, Some(1)), change("a", Some(1), Some(2))],
+        );
+        stream.publish(2, vec![change("a", Some(2), None)]);
+
+        let first = live.next().await.unwrap();
+        assert_eq!(first.kind, ChangeKind::Insert);
+        let token = live.position().to_string();
+
+        // A new subscriber resuming from the token sees the rest
+        let mut resumed = stream.subscribe(Some(token.parse().unwrap())).unwrap();
+        assert_eq!(resumed.next().await.unwrap().kind, ChangeKind::Update);
+        assert_eq!(resumed.next().await.unwrap().kind, ChangeKind::Delete);
+        assert_eq!(resumed.position(), stream.position());
+    }
+
+    #[tokio::test]
+    async fn positions_outside_the_window_expire() {
+        let stream = stream(2);
+        let start = stream.position();
+        let mut slow = stream.subscribe(None).unwrap();
+        for i in 0..3 {
+            stream.publish(i, vec![change(&i.to_string(), None, Some(0))]);
+        }
+
+        assert!(matches!(
+            slow.next().await,
+            Err(Error::Storage(StorageError::ChangeStreamExpired(_)))
+        ));
+        assert!(stream.subscribe(Some(start)).is_err());
+
+        // Positions of an earlier run of the engine expire too
+        let restarted = ChangePosition {
+            epoch: start.epoch - 1,
+            seq: 3,
+        };
+        assert!(stream.subscribe(Some(restarted)).is_err());
+    }
+}
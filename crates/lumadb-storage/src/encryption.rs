@@ -0,0 +1,728 @@
+//! Encryption at rest
+//!
+//! WAL frames, SSTable blocks and stream segment records are encrypted with
+//! AES-256-GCM under data keys scoped to what they protect: `wal` for the
+//! write-ahead log, `collection:{name}` for a collection's tables and
+//! secondary indexes, and `topic:{name}` for a topic's partitions. Data keys
+//! are persisted only in wrapped form, encrypted by the master key of a
+//! [`KeyProvider`] (envelope encryption), so the keyring under `keys/` is
+//! useless without the provider.
+//!
+//! A sealed value is laid out as
+//!
+//! ```text
+//! [magic 0xEC][key version u32][nonce 12][ciphertext][tag 16]
+//! ```
+//!
+//! The version tells readers which data key sealed it, and the magic byte
+//! tells sealed values from plaintext written before encryption was enabled.
+//! Rotating a scope adds a key version that seals everything written from
+//! then on; older versions stay readable until compaction and the log
+//! cleaner have rewritten the data they sealed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use lumadb_common::config::EncryptionConfig;
+use lumadb_common::error::{Error, Result, StorageError};
+
+/// First byte of every sealed value
+const SEALED_MAGIC: u8 = 0xEC;
+
+/// Length of the AES-GCM authentication tag
+const TAG_LEN: usize = 16;
+
+/// Bytes a sealed value adds to its plaintext
+pub const SEALED_OVERHEAD: usize = 1 + 4 + NONCE_LEN + TAG_LEN;
+
+/// Length of data and master keys
+const KEY_LEN: usize = 32;
+
+/// Keyring file inside the key directory
+const KEYRING_FILE: &str = "KEYRING";
+
+/// Scope of the write-ahead log's data key
+pub const WAL_SCOPE: &str = "wal";
+
+/// Scope of a collection's data key
+#[must_use]
+pub fn collection_scope(name: &str) -> String {
+    format!("collection:{name}")
+}
+
+/// Scope of a topic's data key
+#[must_use]
+pub fn topic_scope(name: &str) -> String {
+    format!("topic:{name}")
+}
+
+/// Whether `data` was produced by [`DataKey::seal`]
+#[must_use]
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.len() >= SEALED_OVERHEAD && data[0] == SEALED_MAGIC
+}
+
+/// Version of the data key that sealed `data`, or `None` for plaintext
+#[must_use]
+pub fn sealed_version(data: &[u8]) -> Option<u32> {
+    is_sealed(data).then(|| u32::from_le_bytes([data[1], data[2], data[3], data[4]]))
+}
+
+/// A data key encrypted by a provider's master key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Master key that wrapped the data key
+    pub master_key_id: String,
+    /// Nonce followed by the encrypted key and tag
+    pub ciphertext: Vec<u8>,
+}
+
+/// Holder of master keys that wraps and unwraps data keys
+///
+/// Implementations stand in for a KMS: data keys are handed over only to be
+/// wrapped or unwrapped, and master keys never leave the provider.
+pub trait KeyProvider: Send + Sync {
+    /// Provider name, for logs
+    fn name(&self) -> &str;
+
+    /// Encrypt a data key under the current master key
+    ///
+    /// # Errors
+    ///
+    /// Fails if the master key cannot be used.
+    fn wrap_key(&self, key: &[u8]) -> Result<WrappedKey>;
+
+    /// Decrypt a data key returned by [`KeyProvider::wrap_key`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the master key that wrapped it is unknown or the key does not
+    /// authenticate.
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<Vec<u8>>;
+}
+
+/// Key provider holding master keys read from a local keyfile
+///
+/// Each line of the file is `<id> <64 hex digits>`; blank lines and lines
+/// starting with `#` are ignored. The last key wraps new data keys and every
+/// key unwraps, so appending a line rotates the master key and
+/// [`KeyManager::rewrap`] moves existing data keys onto it.
+pub struct LocalKeyProvider {
+    /// Master keys in file order; the last one is current
+    keys: Vec<(String, LessSafeKey)>,
+}
+
+impl LocalKeyProvider {
+    /// Build a provider from master keys; the last one is current
+    ///
+    /// # Errors
+    ///
+    /// Fails if no key is given or a key cannot be used.
+    pub fn new(keys: Vec<(String, [u8; KEY_LEN])>) -> Result<Self> {
+        if keys.is_empty() {
+            return Err(encryption_error("at least one master key is required"));
+        }
+        let keys = keys
+            .into_iter()
+            .map(|(id, key)| Ok((id, aead_key(&key)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { keys })
+    }
+
+    /// Read the master keys in `path`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or a line is not an id and a 256-bit
+    /// hex key.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut keys = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line
+                .split_once(char::is_whitespace)
+                .and_then(|(id, hex_key)| {
+                    let bytes = hex::decode(hex_key.trim()).ok()?;
+                    Some((id.to_string(), <[u8; KEY_LEN]>::try_from(bytes).ok()?))
+                });
+            let Some(key) = parsed else {
+                return Err(encryption_error(&format!(
+                    "{} line {}: expected '<id> <64 hex digits>'",
+                    path.display(),
+                    number + 1
+                )));
+            };
+            keys.push(key);
+        }
+        Self::new(keys)
+    }
+
+    /// Read the master keys in `path`, first writing a keyfile with one
+    /// random key if it does not exist
+    ///
+    /// # Errors
+    ///
+    /// Fails if the keyfile cannot be written or read.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let key = random_key()?;
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path)?;
+            writeln!(file, "# LumaDB master keys: <id> <hex key>, newest last")?;
+            writeln!(file, "1 {}", hex::encode(key))?;
+            file.sync_all()?;
+            info!("Generated master keyfile {:?}", path);
+        }
+        Self::load(path)
+    }
+
+    fn current(&self) -> &(String, LessSafeKey) {
+        // `new` rejects an empty key list
+        &self.keys[self.keys.len() - 1]
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn wrap_key(&self, key: &[u8]) -> Result<WrappedKey> {
+        let (id, master) = self.current();
+        Ok(WrappedKey {
+            master_key_id: id.clone(),
+            ciphertext: seal_raw(master, id.as_bytes(), key)?,
+        })
+    }
+
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<Vec<u8>> {
+        let (id, master) = self
+            .keys
+            .iter()
+            .find(|(id, _)| *id == wrapped.master_key_id)
+            .ok_or_else(|| {
+                encryption_error(&format!("unknown master key {}", wrapped.master_key_id))
+            })?;
+        open_raw(master, id.as_bytes(), &wrapped.ciphertext)
+    }
+}
+
+/// One version of a scope's data key
+#[derive(Clone)]
+pub struct DataKey {
+    version: u32,
+    key: Arc<LessSafeKey>,
+}
+
+impl DataKey {
+    /// Key version, recorded in everything it seals
+    #[must_use]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Encrypt `plaintext`, authenticating `aad` along with it
+    ///
+    /// # Errors
+    ///
+    /// Fails if no random nonce can be drawn.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(plaintext.len() + SEALED_OVERHEAD);
+        out.push(SEALED_MAGIC);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&seal_raw(&self.key, aad, plaintext)?);
+        Ok(out)
+    }
+}
+
+/// The data key versions of one scope
+///
+/// Handles are shared: a rotation through [`KeyManager::rotate`] is seen by
+/// every holder.
+pub struct DataKeys {
+    scope: String,
+    /// Versions in ascending order; the last one is active
+    versions: RwLock<Vec<DataKey>>,
+}
+
+impl std::fmt::Debug for DataKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataKeys")
+            .field("scope", &self.scope)
+            .field("active_version", &self.active_version())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DataKeys {
+    /// Scope the keys belong to
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// The active key, which seals new data
+    pub fn current(&self) -> DataKey {
+        let versions = self.versions.read();
+        versions[versions.len() - 1].clone()
+    }
+
+    /// Version of the active key
+    pub fn active_version(&self) -> u32 {
+        self.current().version
+    }
+
+    /// Seal `plaintext` with the active key
+    ///
+    /// # Errors
+    ///
+    /// Fails if no random nonce can be drawn.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.current().seal(aad, plaintext)
+    }
+
+    /// Decrypt a value sealed with any version of this scope's key
+    ///
+    /// # Errors
+    ///
+    /// Fails if the key version is unknown or the value does not authenticate
+    /// with `aad`.
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        let version = sealed_version(sealed)
+            .ok_or_else(|| encryption_error(&format!("{}: value is not sealed", self.scope)))?;
+        let key = self
+            .versions
+            .read()
+            .iter()
+            .find(|k| k.version == version)
+            .map(|k| k.key.clone())
+            .ok_or_else(|| {
+                encryption_error(&format!("{}: unknown key version {}", self.scope, version))
+            })?;
+        open_raw(&key, aad, &sealed[5..]).map_err(|_| {
+            encryption_error(&format!(
+                "{}: authentication failed for data sealed with key version {}",
+                self.scope, version
+            ))
+        })
+    }
+
+    /// Decrypt `data` if it is sealed, or return plaintext as is
+    ///
+    /// # Errors
+    ///
+    /// Fails if sealed `data` cannot be opened.
+    pub fn open_or_plain(&self, aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        if is_sealed(data) {
+            self.open(aad, data)
+        } else {
+            Ok(data.to_vec())
+        }
+    }
+}
+
+/// Key versions and wrapping master key of one scope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// Scope of the data key
+    pub scope: String,
+    /// Version sealing new data
+    pub active_version: u32,
+    /// Number of versions still kept for reading
+    pub versions: usize,
+    /// Master key wrapping the active version
+    pub master_key_id: String,
+    /// When the active version was created (ms since epoch)
+    pub created_at: i64,
+}
+
+/// A wrapped data key version as persisted in the keyring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    version: u32,
+    created_at: i64,
+    wrapped: WrappedKey,
+}
+
+/// Persistent keyring: wrapped data keys by scope
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Keyring {
+    scopes: BTreeMap<String, Vec<StoredKey>>,
+}
+
+/// Data keys of every scope, persisted wrapped by a [`KeyProvider`]
+pub struct KeyManager {
+    /// Directory holding the keyring
+    dir: PathBuf,
+    provider: Arc<dyn KeyProvider>,
+    /// Persisted form of the keys; also serializes key creation
+    keyring: Mutex<Keyring>,
+    /// Unwrapped keys by scope
+    scopes: RwLock<HashMap<String, Arc<DataKeys>>>,
+}
+
+impl KeyManager {
+    /// Open the keyring in `dir`, unwrapping every stored data key
+    ///
+    /// Fails if the provider cannot unwrap a key, so a missing master key is
+    /// reported at startup rather than on first read.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the keyring cannot be read, or the provider cannot unwrap a
+    /// key.
+    pub fn open(dir: &Path, provider: Arc<dyn KeyProvider>) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(KEYRING_FILE);
+        let keyring: Keyring = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                Error::Storage(StorageError::CorruptData(format!(
+                    "Keyring {}: {e}",
+                    path.display()
+                )))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Keyring::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut scopes = HashMap::new();
+        for (scope, stored) in &keyring.scopes {
+            let versions = stored
+                .iter()
+                .map(|s| {
+                    let key = provider.unwrap_key(&s.wrapped)?;
+                    Ok(DataKey {
+                        version: s.version,
+                        key: Arc::new(aead_key(&key)?),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if versions.is_empty() {
+                continue;
+            }
+            let keys = DataKeys {
+                scope: scope.clone(),
+                versions: RwLock::new(versions),
+            };
+            scopes.insert(scope.clone(), Arc::new(keys));
+        }
+
+        info!(
+            "Opened keyring at {:?} with {} data key(s) wrapped by the {} key provider",
+            dir,
+            scopes.len(),
+            provider.name()
+        );
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            provider,
+            keyring: Mutex::new(keyring),
+            scopes: RwLock::new(scopes),
+        })
+    }
+
+    /// Open the keyring in `dir` with the provider named by `config`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the master keys cannot be loaded or the keyring cannot be
+    /// opened.
+    pub fn from_config(dir: &Path, config: &EncryptionConfig) -> Result<Self> {
+        let provider: Arc<dyn KeyProvider> = match config.key_provider.as_str() {
+            "local" => Arc::new(LocalKeyProvider::load_or_create(Path::new(
+                &config.key_file,
+            ))?),
+            other => {
+                return Err(Error::Config(format!("unknown key provider {other}")));
+            }
+        };
+        Self::open(dir, provider)
+    }
+
+    /// Keys of `scope`, creating its first version if it has none
+    ///
+    /// # Errors
+    ///
+    /// Fails if a new key cannot be wrapped or the keyring cannot be saved.
+    pub fn data_keys(&self, scope: &str) -> Result<Arc<DataKeys>> {
+        if let Some(keys) = self.scopes.read().get(scope) {
+            return Ok(keys.clone());
+        }
+
+        let mut keyring = self.keyring.lock();
+        if let Some(keys) = self.scopes.read().get(scope) {
+            return Ok(keys.clone());
+        }
+        let key = self.new_version(&mut keyring, scope, 1)?;
+        let keys = Arc::new(DataKeys {
+            scope: scope.to_string(),
+            versions: RwLock::new(vec![key]),
+        });
+        self.scopes.write().insert(scope.to_string(), keys.clone());
+        Ok(keys)
+    }
+
+    /// Add a new version of `scope`'s key and make it active
+    ///
+    /// Existing data stays readable; it is re-encrypted as compaction and
+    /// the log cleaner rewrite it. Returns the new version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the new key cannot be wrapped or the keyring cannot be saved.
+    pub fn rotate(&self, scope: &str) -> Result<u32> {
+        let keys = self.data_keys(scope)?;
+        let mut keyring = self.keyring.lock();
+        let version = keys.active_version() + 1;
+        let key = self.new_version(&mut keyring, scope, version)?;
+        keys.versions.write().push(key);
+        info!("Rotated data key {} to version {}", scope, version);
+        Ok(version)
+    }
+
+    /// Re-wrap every data key under the provider's current master key
+    ///
+    /// Returns the number of keys re-wrapped; afterwards older master keys
+    /// can be removed from the provider.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a key cannot be wrapped or the keyring cannot be saved.
+    pub fn rewrap(&self) -> Result<usize> {
+        let mut keyring = self.keyring.lock();
+        let mut updated = Keyring::default();
+        let mut count = 0;
+        for (scope, stored) in &keyring.scopes {
+            let mut rewrapped = Vec::with_capacity(stored.len());
+            for s in stored {
+                let key = self.provider.unwrap_key(&s.wrapped)?;
+                let wrapped = self.provider.wrap_key(&key)?;
+                if wrapped.master_key_id != s.wrapped.master_key_id {
+                    count += 1;
+                }
+                rewrapped.push(StoredKey {
+                    wrapped,
+                    ..s.clone()
+                });
+            }
+            updated.scopes.insert(scope.clone(), rewrapped);
+        }
+        self.save(&updated)?;
+        *keyring = updated;
+        Ok(count)
+    }
+
+    /// Active version and wrapping of every scope's key, by scope
+    pub fn keys(&self) -> Vec<KeyInfo> {
+        self.keyring
+            .lock()
+            .scopes
+            .iter()
+            .filter_map(|(scope, stored)| {
+                let active = stored.last()?;
+                Some(KeyInfo {
+                    scope: scope.clone(),
+                    active_version: active.version,
+                    versions: stored.len(),
+                    master_key_id: active.wrapped.master_key_id.clone(),
+                    created_at: active.created_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Generate, wrap and persist a key version
+    fn new_version(&self, keyring: &mut Keyring, scope: &str, version: u32) -> Result<DataKey> {
+        let key = random_key()?;
+        let stored = StoredKey {
+            version,
+            created_at: chrono::Utc::now().timestamp_millis(),
+            wrapped: self.provider.wrap_key(&key)?,
+        };
+        keyring
+            .scopes
+            .entry(scope.to_string())
+            .or_default()
+            .push(stored);
+        if let Err(e) = self.save(keyring) {
+            if let Some(stored) = keyring.scopes.get_mut(scope) {
+                stored.pop();
+            }
+            return Err(e);
+        }
+        Ok(DataKey {
+            version,
+            key: Arc::new(aead_key(&key)?),
+        })
+    }
+
+    /// Atomically persist the keyring (write temp file, fsync, rename)
+    fn save(&self, keyring: &Keyring) -> Result<()> {
+        let tmp = self.dir.join(format!("{KEYRING_FILE}.tmp"));
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&serde_json::to_vec_pretty(keyring)?)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, self.dir.join(KEYRING_FILE))?;
+        if let Ok(dir) = fs::File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| encryption_error("invalid AES-256 key length"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn random_key() -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| encryption_error("failed to generate key"))?;
+    Ok(key)
+}
+
+/// `[nonce][ciphertext][tag]` under a random nonce
+fn seal_raw(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| encryption_error("failed to generate nonce"))?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(plaintext);
+    let tag = key
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut out[NONCE_LEN..],
+        )
+        .map_err(|_| encryption_error("encryption failed"))?;
+    out.extend_from_slice(tag.as_ref());
+    Ok(out)
+}
+
+fn open_raw(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(encryption_error("sealed value is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce =
+        Nonce::try_assume_unique_for_key(nonce).map_err(|_| encryption_error("invalid nonce"))?;
+    let mut buf = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, Aad::from(aad), &mut buf)
+        .map_err(|_| encryption_error("authentication failed"))?
+        .len();
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn encryption_error(message: &str) -> Error {
+    Error::Storage(StorageError::Encryption(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(keys: &[(&str, u8)]) -> Arc<LocalKeyProvider> {
+        let keys = keys
+            .iter()
+            .map(|(id, b)| ((*id).to_string(), [*b; KEY_LEN]))
+            .collect();
+        Arc::new(LocalKeyProvider::new(keys).unwrap())
+    }
+
+    #[test]
+    fn test_seal_open_and_tamper_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = KeyManager::open(dir.path(), provider(&[("1", 7)])).unwrap();
+        let keys = manager.data_keys("collection:users").unwrap();
+
+        let sealed = keys.seal(b"aad", b"secret document").unwrap();
+        assert_eq!(sealed_version(&sealed), Some(1));
+        assert_eq!(sealed.len(), b"secret document".len() + SEALED_OVERHEAD);
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+        assert_eq!(keys.open(b"aad", &sealed).unwrap(), b"secret document");
+        assert!(keys.open(b"other", &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[10] ^= 1;
+        assert!(keys.open(b"aad", &tampered).is_err());
+        assert_eq!(keys.open_or_plain(b"aad", b"plain").unwrap(), b"plain");
+
+        // Another scope's key cannot open it
+        let other = manager.data_keys("collection:orders").unwrap();
+        assert!(other.open(b"aad", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_rotation_and_rewrap_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let sealed = {
+            let manager = KeyManager::open(dir.path(), provider(&[("1", 7)])).unwrap();
+            let keys = manager.data_keys("topic:orders").unwrap();
+            let old = keys.seal(&[], b"v1").unwrap();
+            assert_eq!(manager.rotate("topic:orders").unwrap(), 2);
+            assert_eq!(keys.active_version(), 2);
+            (old, keys.seal(&[], b"v2").unwrap())
+        };
+        assert_eq!(sealed_version(&sealed.1), Some(2));
+
+        // A new master key wraps after rewrap; the old one can then go
+        let manager = KeyManager::open(dir.path(), provider(&[("1", 7), ("2", 9)])).unwrap();
+        assert_eq!(manager.rewrap().unwrap(), 2);
+        let info = manager.keys();
+        assert_eq!(info[0].scope, "topic:orders");
+        assert_eq!((info[0].active_version, info[0].versions), (2, 2));
+        assert_eq!(info[0].master_key_id, "2");
+
+        let manager = KeyManager::open(dir.path(), provider(&[("2", 9)])).unwrap();
+        let keys = manager.data_keys("topic:orders").unwrap();
+        assert_eq!(keys.open(&[], &sealed.0).unwrap(), b"v1");
+        assert_eq!(keys.open(&[], &sealed.1).unwrap(), b"v2");
+
+        // Without the wrapping master key the keyring cannot be opened
+        assert!(KeyManager::open(dir.path(), provider(&[("1", 7)])).is_err());
+    }
+
+    #[test]
+    fn test_local_keyfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join("master.keys");
+        let created = LocalKeyProvider::load_or_create(&path).unwrap();
+        let wrapped = created.wrap_key(&[1; KEY_LEN]).unwrap();
+        assert_eq!(wrapped.master_key_id, "1");
+
+        let loaded = LocalKeyProvider::load_or_create(&path).unwrap();
+        assert_eq!(loaded.unwrap_key(&wrapped).unwrap(), vec![1; KEY_LEN]);
+
+        fs::write(&path, "1 not-hex\n").unwrap();
+        assert!(LocalKeyProvider::load(&path).is_err());
+    }
+}
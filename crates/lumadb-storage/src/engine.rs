@@ -1,10 +1,13 @@
 //! Main storage engine implementation
 
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use dashmap::DashMap;
+use fs2::FileExt;
 use parking_lot::RwLock;
 use sled::Db;
 use tracing::{info, warn};
@@ -12,14 +15,23 @@ use tracing::{info, warn};
 use serde::{Deserialize, Serialize};
 
 use lumadb_common::config::StorageConfig;
-use lumadb_common::error::{Result, StorageError};
-use lumadb_common::types::{CollectionInfo, CollectionMetadata, Document};
+use lumadb_common::error::{Result, StorageError, TransactionError};
+use lumadb_common::types::{
+    CollectionInfo, CollectionMetadata, Document, IndexMetadata, PayloadIndexSpec,
+    VectorIndexConfig,
+};
 
 use crate::cache::BufferPool;
+use crate::changes::{ChangeStream, PublishedChange};
+use crate::encryption::{self, KeyManager, KeyProvider};
 use crate::fulltext::FullTextIndex;
-use crate::lsm::LsmTree;
-use crate::vector::VectorIndex;
-use crate::wal::WriteAheadLog;
+use crate::hybrid::{self, HybridQuery, HybridSearchResult};
+use crate::index::{IndexRange, IndexScan, SecondaryIndex};
+use crate::lsm::{LsmOptions, LsmTree, VersionScan};
+use crate::mvcc::{CommitClock, DocumentWrite, ReadKey};
+use crate::snapshot::{self, SnapshotDescription, SnapshotReader, SnapshotWriter};
+use crate::vector::{PayloadFilter, QuantizationReport, SearchOptions, VectorIndex};
+use crate::wal::{WalRecord, WalSyncPolicy, WalWrite, WriteAheadLog};
 
 /// Vector search result from storage engine
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +44,107 @@ pub struct VectorSearchResult {
     pub payload: serde_json::Value,
 }
 
+/// Lazy iterator over the documents of a collection, in ID order
+pub struct DocumentScan {
+    entries: VersionScan,
+}
+
+impl Iterator for DocumentScan {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(entry.and_then(|(_, value)| Ok(serde_json::from_slice(&value)?)))
+    }
+}
+
+/// Documents found through a secondary index, in index order
+pub struct IndexedDocuments {
+    entries: IndexScan,
+    index: Arc<SecondaryIndex>,
+    lsm: Arc<LsmTree>,
+}
+
+impl Iterator for IndexedDocuments {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let found = self.entries.next()?.and_then(|(key, id)| {
+                let Some(value) = self.lsm.get_at(id.as_bytes(), LATEST)? else {
+                    return Ok(None);
+                };
+                let doc: Document = serde_json::from_slice(&value)?;
+                // Skip entries left behind by an older version of the document
+                Ok((self.index.entry_key(&doc).as_ref() == Some(&key)).then_some(doc))
+            });
+            match found {
+                Ok(Some(doc)) => return Some(Ok(doc)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Statistics the query optimizer plans with
+#[derive(Debug, Clone)]
+pub struct CollectionStatistics {
+    /// Number of documents
+    pub documents: usize,
+    /// Secondary indexes ready for use, with their entry counts
+    pub indexes: Vec<IndexMetadata>,
+}
+
+/// Secondary indexes of one collection
+#[derive(Default)]
+struct CollectionIndexes {
+    /// Registered indexes, including ones still being backfilled
+    indexes: RwLock<Vec<Arc<SecondaryIndex>>>,
+    /// Held shared by writers for a whole write and exclusively while the
+    /// index set changes, so every write maintains the indexes it saw
+    registration: tokio::sync::RwLock<()>,
+    /// Serializes writes to a collection that has indexes
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+/// Documents one commit writes to a collection
+struct CollectionWrites {
+    collection: String,
+    lsm: Arc<LsmTree>,
+    /// Indexes to maintain, fixed once the collection is locked
+    indexes: Vec<Arc<SecondaryIndex>>,
+    /// Writes in ID order
+    writes: Vec<PendingWrite>,
+}
+
+/// One document write of a commit
+struct PendingWrite {
+    id: String,
+    /// New document, or `None` for a deletion
+    document: Option<Document>,
+    /// Serialized new document
+    value: Option<Vec<u8>>,
+    /// Document being replaced, read once the commit holds its locks
+    old: Option<Document>,
+}
+
+/// Number of WAL segments written before a checkpoint is taken
+const WAL_CHECKPOINT_SEGMENTS: u64 = 4;
+
+/// Times a closed engine checks, 10ms apart, that sled has released the
+/// metadata database
+const METADATA_LOCK_POLLS: u32 = 100;
+
+/// Snapshot timestamp that reads the newest version of every document
+const LATEST: u64 = u64::MAX;
+
+/// Metadata key holding the last commit timestamp issued
+const CLOCK_KEY: &str = "mvcc:timestamp";
+
+/// Documents written per commit while restoring a snapshot
+const RESTORE_BATCH: usize = 1000;
+
 /// Main storage engine orchestrating all storage components
 pub struct StorageEngine {
     /// Configuration
@@ -44,10 +157,25 @@ pub struct StorageEngine {
     vector_indexes: DashMap<String, Arc<VectorIndex>>,
     /// Full-text indexes
     fulltext_indexes: DashMap<String, Arc<FullTextIndex>>,
+    /// Secondary indexes by collection
+    indexes: DashMap<String, Arc<CollectionIndexes>>,
     /// Buffer pool for caching
     buffer_pool: Arc<BufferPool>,
     /// Write-ahead log
     wal: Option<Arc<WriteAheadLog>>,
+    /// Data keys, when encryption at rest is enabled
+    encryption: Option<Arc<KeyManager>>,
+    /// Held shared by writers across WAL append + apply, exclusively by
+    /// checkpoints while sealing the WAL segment
+    write_gate: tokio::sync::RwLock<()>,
+    /// Serializes checkpoints
+    checkpoint_lock: tokio::sync::Mutex<()>,
+    /// Commit timestamps and open snapshots
+    clock: CommitClock,
+    /// Timestamp of the latest commit to each collection since startup
+    last_commits: DashMap<String, u64>,
+    /// Recent document changes
+    changes: Arc<ChangeStream>,
     /// Running state
     running: Arc<RwLock<bool>>,
 }
@@ -55,6 +183,33 @@ pub struct StorageEngine {
 impl StorageEngine {
     /// Create a new storage engine
     pub async fn new(config: &StorageConfig) -> Result<Self> {
+        let encryption = if config.encryption.enabled {
+            let dir = Path::new(&config.path).join("keys");
+            Some(Arc::new(KeyManager::from_config(&dir, &config.encryption)?))
+        } else {
+            None
+        };
+        Self::open(config, encryption).await
+    }
+
+    /// Create a storage engine whose data keys are wrapped by `provider`
+    ///
+    /// Encrypts at rest whatever `config.encryption` says; this is how a KMS
+    /// client stands in for the configured key provider.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the data directory cannot be opened or recovered, or the
+    /// provider cannot unwrap a stored data key.
+    pub async fn with_key_provider(
+        config: &StorageConfig,
+        provider: Arc<dyn KeyProvider>,
+    ) -> Result<Self> {
+        let dir = Path::new(&config.path).join("keys");
+        Self::open(config, Some(Arc::new(KeyManager::open(&dir, provider)?))).await
+    }
+
+    async fn open(config: &StorageConfig, encryption: Option<Arc<KeyManager>>) -> Result<Self> {
         info!("Initializing storage engine at {}", config.path);
 
         // Create storage directory if it doesn't exist
@@ -72,23 +227,335 @@ impl StorageEngine {
         // Initialize WAL if enabled
         let wal = if config.wal_enabled {
             let wal_path = Path::new(&config.path).join("wal");
-            Some(Arc::new(WriteAheadLog::new(&wal_path).await?))
+            let policy = WalSyncPolicy::from_config(config);
+            let cipher = encryption
+                .as_ref()
+                .map(|keys| keys.data_keys(encryption::WAL_SCOPE))
+                .transpose()?;
+            let migrate = config.encryption.migrate_plaintext_wal;
+            Some(Arc::new(
+                WriteAheadLog::open(&wal_path, policy, cipher, migrate).await?,
+            ))
         } else {
             None
         };
 
-        info!("Storage engine initialized successfully");
-
-        Ok(Self {
+        // Timestamps must stay above those of versions already written
+        let floor = db
+            .get(CLOCK_KEY)
+            .map_err(|e| {
+                lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
+            })?
+            .and_then(|value| value.as_ref().try_into().ok())
+            .map_or(0, u64::from_be_bytes);
+
+        // Changes hold what the WAL holds, so they share its data key
+        let changes_cipher = encryption
+            .as_ref()
+            .map(|keys| keys.data_keys(encryption::WAL_SCOPE))
+            .transpose()?;
+        let changes = ChangeStream::open(&db, config.change_stream_capacity, changes_cipher)?;
+
+        let engine = Self {
             config: config.clone(),
             db,
             lsm_trees: DashMap::new(),
             vector_indexes: DashMap::new(),
             fulltext_indexes: DashMap::new(),
+            indexes: DashMap::new(),
             buffer_pool,
             wal,
+            encryption,
+            write_gate: tokio::sync::RwLock::new(()),
+            checkpoint_lock: tokio::sync::Mutex::new(()),
+            clock: CommitClock::new(floor),
+            last_commits: DashMap::new(),
+            changes: Arc::new(changes),
             running: Arc::new(RwLock::new(true)),
+        };
+
+        engine.load_indexes()?;
+        engine.recover().await?;
+        engine.load_payload_indexes().await?;
+        engine.load_text_indexes().await?;
+
+        info!("Storage engine initialized successfully");
+
+        Ok(engine)
+    }
+
+    /// Open the secondary indexes recorded in collection metadata
+    ///
+    /// Indexes whose backfill was interrupted are dropped, as they may be
+    /// missing entries.
+    fn load_indexes(&self) -> Result<()> {
+        for result in self.db.scan_prefix("collection:") {
+            let (_, value) = result.map_err(|e| {
+                lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
+            })?;
+            let mut metadata: CollectionMetadata = serde_json::from_slice(&value)?;
+            if let Some(config) = metadata.vector.clone() {
+                self.open_vector_index(&metadata.name, config)?;
+            }
+
+            let (building, ready): (Vec<_>, Vec<_>) =
+                metadata.indexes.drain(..).partition(|index| index.building);
+
+            for index in &building {
+                warn!(
+                    "Dropping index {} on {}, whose build was interrupted",
+                    index.name, metadata.name
+                );
+                let path = self.index_path(&metadata.name, &index.name);
+                if path.exists() {
+                    std::fs::remove_dir_all(&path)?;
+                }
+            }
+
+            let registry = self.collection_indexes(&metadata.name);
+            for index in &ready {
+                let path = self.index_path(&metadata.name, &index.name);
+                let options = self.lsm_options(&metadata.name)?;
+                let index = SecondaryIndex::open(&path, index.clone(), options)?;
+                registry.indexes.write().push(Arc::new(index));
+            }
+
+            if !building.is_empty() {
+                metadata.indexes = ready;
+                self.put_collection_metadata(&metadata)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the payload indexes of vector collections from their
+    /// documents
+    async fn load_payload_indexes(&self) -> Result<()> {
+        for metadata in self.list_collections().await? {
+            if metadata.payload_indexes.is_empty() {
+                continue;
+            }
+            let Some(index) = self.vector_index(&metadata.name) else {
+                continue;
+            };
+            for spec in &metadata.payload_indexes {
+                index.payload_index().add_field(spec);
+            }
+            for doc in self.document_scan(&metadata.name, None, None)? {
+                let doc = doc?;
+                index.payload_index().update(&doc.id, Some(&doc.data));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the full-text indexes of collections from their documents
+    async fn load_text_indexes(&self) -> Result<()> {
+        for metadata in self.list_collections().await? {
+            if !metadata.text_fields.is_empty() {
+                self.build_text_index(&metadata.name, metadata.text_fields)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay WAL records written since the last checkpoint into the LSM
+    /// trees and collection metadata, then checkpoint
+    async fn recover(&self) -> Result<()> {
+        let Some(wal) = self.wal.clone() else {
+            return Ok(());
+        };
+
+        let mut touched = HashSet::new();
+        let replayed = wal
+            .replay(|payload| {
+                let record = match WalRecord::decode(payload) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        warn!("Skipping undecodable WAL record: {}", e);
+                        return Ok(());
+                    }
+                };
+                self.replay_record(record, &mut touched)
+            })
+            .await?;
+
+        if replayed == 0 {
+            return Ok(());
+        }
+
+        // Documents may already have been flushed to SSTables before the
+        // crash, so recount instead of trusting per-record deltas
+        for name in &touched {
+            self.recount_collection(name)?;
+        }
+
+        self.checkpoint().await
+    }
+
+    /// Apply one replayed WAL record
+    fn replay_record(&self, record: WalRecord, touched: &mut HashSet<String>) -> Result<()> {
+        match record {
+            WalRecord::CreateCollection { name } => {
+                let key = format!("collection:{name}");
+                if !self.db.contains_key(&key).map_err(|e| {
+                    lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
+                })? {
+                    self.put_collection_metadata(&CollectionMetadata {
+                        name: name.clone(),
+                        count: 0,
+                        size_bytes: 0,
+                        indexes: vec![],
+                        schema: None,
+                        vector: None,
+                        payload_indexes: vec![],
+                        text_fields: vec![],
+                    })?;
+                }
+                self.get_or_create_lsm(&name)?;
+                touched.insert(name);
+            }
+            WalRecord::DeleteCollection { name } => {
+                self.db.remove(format!("collection:{name}")).map_err(|e| {
+                    lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
+                })?;
+                if let Some((_, lsm)) = self.lsm_trees.remove(&name) {
+                    lsm.close();
+                }
+                let collection_path = self.collection_path(&name);
+                if collection_path.exists() {
+                    std::fs::remove_dir_all(&collection_path)?;
+                }
+                self.vector_indexes.remove(&name);
+                let vector_path = self.vector_path(&name);
+                if vector_path.exists() {
+                    std::fs::remove_dir_all(&vector_path)?;
+                }
+                self.remove_indexes(&name)?;
+                touched.remove(&name);
+            }
+            WalRecord::Commit { ts, writes } => {
+                self.clock.observe(ts);
+                for write in writes {
+                    self.replay_write(&write.collection, &write.id, write.value.as_deref(), ts)?;
+                    touched.insert(write.collection);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply one replayed document write as the version committed at `ts`
+    fn replay_write(
+        &self,
+        collection: &str,
+        id: &str,
+        value: Option<&[u8]>,
+        ts: u64,
+    ) -> Result<()> {
+        let lsm = self.get_or_create_lsm(collection)?;
+        let indexes = self.index_snapshot(collection);
+        if !indexes.is_empty() {
+            let old = Self::read_document(&lsm, id)?;
+            let new: Option<Document> = value.map(serde_json::from_slice).transpose()?;
+            Self::update_indexes(&indexes, old.as_ref(), new.as_ref())?;
+        }
+        if value.is_none() {
+            if let Some(index) = self.vector_index(collection) {
+                index.delete(id)?;
+            }
+        }
+        lsm.put_version(id.as_bytes(), ts, value)
+    }
+
+    /// Recompute a collection's document count from its LSM tree
+    fn recount_collection(&self, name: &str) -> Result<()> {
+        let key = format!("collection:{name}");
+        let Ok(Some(value)) = self.db.get(&key) else {
+            return Ok(());
+        };
+
+        let mut metadata: CollectionMetadata = serde_json::from_slice(&value)?;
+        let mut count = 0;
+        for entry in self.get_or_create_lsm(name)?.scan_at(&[], None, LATEST)? {
+            entry?;
+            count += 1;
+        }
+        metadata.count = count;
+        self.put_collection_metadata(&metadata)
+    }
+
+    /// Make everything in the WAL durable in SSTables and metadata, then
+    /// drop the WAL segments that are no longer needed
+    ///
+    /// Document versions no open snapshot can read are removed on the way.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memtables, the metadata or the checkpoint marker
+    /// cannot be written.
+    pub async fn checkpoint(&self) -> Result<()> {
+        let _checkpoint = self.checkpoint_lock.lock().await;
+        self.run_checkpoint().await
+    }
+
+    /// Checkpoint, with `checkpoint_lock` held by the caller
+    async fn run_checkpoint(&self) -> Result<()> {
+        let Some(ref wal) = self.wal else {
+            return Ok(());
+        };
+
+        // Seal the WAL while no writer is between append and apply, so every
+        // record in earlier segments is already in a memtable
+        let segment = {
+            let _gate = self.write_gate.write().await;
+            wal.begin_checkpoint().await?
+        };
+
+        let trees: Vec<Arc<LsmTree>> = self.lsm_trees.iter().map(|e| e.value().clone()).collect();
+        let indexes = self.all_indexes();
+        let vectors = self.all_vector_indexes();
+        let watermark = self.clock.watermark();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            for lsm in trees {
+                lsm.collect_versions(watermark)?;
+                lsm.flush()?;
+            }
+            for index in indexes {
+                index.flush()?;
+            }
+            for index in vectors {
+                index.snapshot(false)?;
+            }
+            Ok(())
         })
+        .await
+        .map_err(|e| lumadb_common::error::Error::Internal(e.to_string()))??;
+
+        self.persist_index_statistics().await?;
+        self.persist_clock()?;
+
+        self.db.flush_async().await.map_err(|e| {
+            lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
+        })?;
+
+        wal.complete_checkpoint(segment).await
+    }
+
+    /// Checkpoint once enough WAL segments have accumulated
+    async fn maybe_checkpoint(&self) -> Result<()> {
+        let due = self
+            .wal
+            .as_ref()
+            .is_some_and(|wal| wal.segments_since_checkpoint() >= WAL_CHECKPOINT_SEGMENTS);
+        if !due {
+            return Ok(());
+        }
+        // Skip the checkpoint if one is already running
+        if let Ok(_checkpoint) = self.checkpoint_lock.try_lock() {
+            self.run_checkpoint().await?;
+        }
+        Ok(())
     }
 
     /// Check if storage engine is ready
@@ -107,45 +574,271 @@ impl StorageEngine {
         self.db.contains_key("__health_check__").is_ok()
     }
 
-    /// Get or create an LSM tree for a collection
-    pub fn get_or_create_lsm(&self, name: &str) -> Arc<LsmTree> {
-        self.lsm_trees
-            .entry(name.to_string())
-            .or_insert_with(|| {
-                let path = Path::new(&self.config.path)
-                    .join("collections")
-                    .join(name);
-                Arc::new(LsmTree::new(&path, self.buffer_pool.clone()))
+    /// Stream of the document changes committed from now on
+    pub fn changes(&self) -> &Arc<ChangeStream> {
+        &self.changes
+    }
+
+    /// Root data directory of this engine
+    pub fn data_dir(&self) -> &Path {
+        Path::new(&self.config.path)
+    }
+
+    /// Read a metadata entry kept by another component, such as the
+    /// security layer's roles and ACLs
+    ///
+    /// # Errors
+    ///
+    /// Fails if the metadata database cannot be read.
+    pub fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.db
+            .get(key)
+            .map(|value| value.map(|v| v.to_vec()))
+            .map_err(|e| {
+                lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
             })
-            .clone()
+    }
+
+    /// Write a metadata entry
+    ///
+    /// # Errors
+    ///
+    /// Fails if the metadata database cannot be written.
+    pub fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.db.insert(key, value).map_err(|e| {
+            lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
+        })?;
+        Ok(())
+    }
+
+    /// Remove a metadata entry
+    ///
+    /// # Errors
+    ///
+    /// Fails if the metadata database cannot be written.
+    pub fn delete_metadata(&self, key: &str) -> Result<()> {
+        self.db.remove(key).map_err(|e| {
+            lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
+        })?;
+        Ok(())
+    }
+
+    /// Metadata entries whose keys start with `prefix`, in key order
+    ///
+    /// # Errors
+    ///
+    /// Fails if the metadata database cannot be read.
+    pub fn scan_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.db
+            .scan_prefix(prefix)
+            .map(|entry| {
+                let (key, value) = entry.map_err(|e| {
+                    lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
+                })?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    /// Get or create an LSM tree for a collection
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree's directory cannot be opened or recovered.
+    pub fn get_or_create_lsm(&self, name: &str) -> Result<Arc<LsmTree>> {
+        if let Some(lsm) = self.lsm_trees.get(name) {
+            return Ok(lsm.clone());
+        }
+
+        match self.lsm_trees.entry(name.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(entry) => Ok(entry.get().clone()),
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                let lsm = LsmTree::open(
+                    &self.collection_path(name),
+                    self.buffer_pool.clone(),
+                    self.lsm_options(name)?,
+                )?;
+                entry.insert(lsm.clone());
+                Ok(lsm)
+            }
+        }
+    }
+
+    /// Directory holding a collection's SSTables and manifest
+    fn collection_path(&self, name: &str) -> std::path::PathBuf {
+        Path::new(&self.config.path).join("collections").join(name)
+    }
+
+    /// Directory holding a collection's vector index
+    fn vector_path(&self, name: &str) -> std::path::PathBuf {
+        Path::new(&self.config.path).join("vectors").join(name)
+    }
+
+    /// Directory holding a collection's full-text index
+    fn text_path(&self, name: &str) -> std::path::PathBuf {
+        Path::new(&self.config.path).join("fulltext").join(name)
+    }
+
+    /// Directory holding one secondary index's tree
+    fn index_path(&self, collection: &str, index: &str) -> std::path::PathBuf {
+        Path::new(&self.config.path)
+            .join("indexes")
+            .join(collection)
+            .join(index)
+    }
+
+    /// LSM tuning derived from the storage configuration, for the trees of
+    /// a collection and its indexes
+    fn lsm_options(&self, collection: &str) -> Result<LsmOptions> {
+        let mut options = LsmOptions::default();
+        options.sstable.compression = self.config.compression_enabled;
+        if self.config.compaction_interval_secs > 0 {
+            options.compaction_interval = Duration::from_secs(self.config.compaction_interval_secs);
+        }
+        if let Some(ref keys) = self.encryption {
+            options.sstable.cipher =
+                Some(keys.data_keys(&encryption::collection_scope(collection))?);
+        }
+        Ok(options)
+    }
+
+    /// Data keys, when encryption at rest is enabled
+    pub fn encryption(&self) -> Option<&Arc<KeyManager>> {
+        self.encryption.as_ref()
+    }
+
+    /// Rotate a collection's data key
+    ///
+    /// New tables are sealed with the new version at once; the collection
+    /// and its indexes are compacted in the background to re-encrypt the
+    /// existing ones, and its vector index is snapshotted with the new key.
+    /// Returns the new key version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if encryption at rest is off, the collection does not exist, or
+    /// the keyring cannot be saved.
+    pub async fn rotate_collection_key(&self, name: &str) -> Result<u32> {
+        let keys = self.require_encryption()?;
+        if self.get_collection(name).await?.is_none() {
+            return Err(StorageError::CollectionNotFound(name.to_string()).into());
+        }
+
+        let version = keys.rotate(&encryption::collection_scope(name))?;
+        self.get_or_create_lsm(name)?.schedule_compaction();
+        if let Some(registry) = self.indexes.get(name) {
+            for index in registry.indexes.read().iter() {
+                index.schedule_compaction();
+            }
+        }
+        if let Some(index) = self.vector_index(name) {
+            tokio::task::spawn_blocking(move || index.snapshot(true))
+                .await
+                .map_err(|e| lumadb_common::error::Error::Internal(e.to_string()))??;
+        }
+        Ok(version)
+    }
+
+    /// Rotate the WAL's data key
+    ///
+    /// Records are sealed with the new version from now on, and a
+    /// checkpoint drops the segments sealed with older ones. Returns the new
+    /// key version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if encryption at rest is off, the keyring cannot be saved, or
+    /// the checkpoint fails.
+    pub async fn rotate_wal_key(&self) -> Result<u32> {
+        let version = self.require_encryption()?.rotate(encryption::WAL_SCOPE)?;
+        self.checkpoint().await?;
+        Ok(version)
+    }
+
+    fn require_encryption(&self) -> Result<&Arc<KeyManager>> {
+        self.encryption.as_ref().ok_or_else(|| {
+            StorageError::Encryption("encryption at rest is not enabled".to_string()).into()
+        })
     }
 
     /// Get or create a vector index for a collection
-    pub fn get_or_create_vector_index(
+    ///
+    /// A new index uses the default settings; see
+    /// [`StorageEngine::create_vector_collection_with_config`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the collection metadata cannot be read or the index cannot be
+    /// opened.
+    pub async fn get_or_create_vector_index(
         &self,
         name: &str,
         dimensions: usize,
-    ) -> Arc<VectorIndex> {
+    ) -> Result<Arc<VectorIndex>> {
+        if let Some(index) = self.vector_index(name) {
+            return Ok(index);
+        }
+        self.create_vector_collection(name, dimensions).await?;
+        self.vector_index(name)
+            .ok_or_else(|| StorageError::CollectionNotFound(name.to_string()).into())
+    }
+
+    /// The vector index of a collection, if it has one
+    pub fn vector_index(&self, name: &str) -> Option<Arc<VectorIndex>> {
+        self.vector_indexes.get(name).map(|index| index.clone())
+    }
+
+    /// Open a collection's vector index from its directory
+    fn open_vector_index(&self, name: &str, config: VectorIndexConfig) -> Result<Arc<VectorIndex>> {
+        let cipher = self
+            .encryption
+            .as_ref()
+            .map(|keys| keys.data_keys(&encryption::collection_scope(name)))
+            .transpose()?;
+        let sync = self.config.wal_sync_mode == "fsync";
+        let index = Arc::new(VectorIndex::open(
+            &self.vector_path(name),
+            config,
+            cipher,
+            sync,
+        )?);
+        self.vector_indexes.insert(name.to_string(), index.clone());
+        Ok(index)
+    }
+
+    fn all_vector_indexes(&self) -> Vec<Arc<VectorIndex>> {
         self.vector_indexes
-            .entry(name.to_string())
-            .or_insert_with(|| {
-                Arc::new(VectorIndex::new(dimensions))
-            })
-            .clone()
+            .iter()
+            .map(|e| e.value().clone())
+            .collect()
+    }
+
+    /// The full-text index of a collection, if it has one
+    pub fn text_index(&self, name: &str) -> Option<Arc<FullTextIndex>> {
+        self.fulltext_indexes.get(name).map(|index| index.clone())
     }
 
-    /// Get or create a full-text index for a collection
-    pub fn get_or_create_fulltext_index(&self, name: &str) -> Result<Arc<FullTextIndex>> {
-        if let Some(idx) = self.fulltext_indexes.get(name) {
-            return Ok(idx.clone());
+    /// Build a collection's full-text index over `fields` afresh from its
+    /// documents, replacing the one it has; no fields drop the index
+    fn build_text_index(&self, name: &str, fields: Vec<String>) -> Result<()> {
+        self.fulltext_indexes.remove(name);
+        let path = self.text_path(name);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        if fields.is_empty() {
+            return Ok(());
         }
 
-        let path = Path::new(&self.config.path)
-            .join("fulltext")
-            .join(name);
-        let index = Arc::new(FullTextIndex::new(&path)?);
-        self.fulltext_indexes.insert(name.to_string(), index.clone());
-        Ok(index)
+        let index = FullTextIndex::with_fields(&path, fields)?;
+        for doc in self.document_scan(name, None, None)? {
+            let doc = doc?;
+            index.update(&doc.id, Some(&doc.data))?;
+        }
+        index.commit()?;
+        self.fulltext_indexes
+            .insert(name.to_string(), Arc::new(index));
+        Ok(())
     }
 
     // ========================================================================
@@ -173,22 +866,26 @@ impl StorageEngine {
             size_bytes: 0,
             indexes: vec![],
             schema: None,
+            vector: None,
+            payload_indexes: vec![],
+            text_fields: vec![],
         };
 
-        let value = serde_json::to_vec(&metadata)?;
-        self.db.insert(&key, value).map_err(|e| {
-            lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
-        })?;
-
-        // Create LSM tree for the collection
-        self.get_or_create_lsm(name);
+        let _gate = self.write_gate.read().await;
 
         // Write to WAL if enabled
         if let Some(ref wal) = self.wal {
-            wal.append(&format!("CREATE_COLLECTION:{}", name).into_bytes())
-                .await?;
+            let record = WalRecord::CreateCollection {
+                name: name.to_string(),
+            };
+            wal.append(&record.encode()?).await?;
         }
 
+        self.put_collection_metadata(&metadata)?;
+
+        // Create LSM tree for the collection
+        self.get_or_create_lsm(name)?;
+
         Ok(())
     }
 
@@ -226,24 +923,51 @@ impl StorageEngine {
     pub async fn delete_collection(&self, name: &str) -> Result<()> {
         info!("Deleting collection: {}", name);
 
+        let _gate = self.write_gate.read().await;
+
+        // Write to WAL
+        if let Some(ref wal) = self.wal {
+            let record = WalRecord::DeleteCollection {
+                name: name.to_string(),
+            };
+            wal.append(&record.encode()?).await?;
+        }
+
         let key = format!("collection:{}", name);
         self.db.remove(&key).map_err(|e| {
             lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
         })?;
 
-        // Remove LSM tree
-        self.lsm_trees.remove(name);
+        // Remove secondary indexes
+        if let Some(registry) = self.indexes.get(name).map(|r| r.clone()) {
+            let _registration = registry.registration.write().await;
+            self.remove_indexes(name)?;
+        }
+
+        // Remove LSM tree and its files
+        if let Some((_, lsm)) = self.lsm_trees.remove(name) {
+            lsm.close();
+        }
+        let collection_path = self.collection_path(name);
+        if tokio::fs::try_exists(&collection_path)
+            .await
+            .unwrap_or(false)
+        {
+            tokio::fs::remove_dir_all(&collection_path).await?;
+        }
 
         // Remove vector index if exists
         self.vector_indexes.remove(name);
+        let vector_path = self.vector_path(name);
+        if tokio::fs::try_exists(&vector_path).await.unwrap_or(false) {
+            tokio::fs::remove_dir_all(&vector_path).await?;
+        }
 
         // Remove full-text index if exists
         self.fulltext_indexes.remove(name);
-
-        // Write to WAL
-        if let Some(ref wal) = self.wal {
-            wal.append(&format!("DELETE_COLLECTION:{}", name).into_bytes())
-                .await?;
+        let text_path = self.text_path(name);
+        if tokio::fs::try_exists(&text_path).await.unwrap_or(false) {
+            tokio::fs::remove_dir_all(&text_path).await?;
         }
 
         Ok(())
@@ -254,33 +978,40 @@ impl StorageEngine {
     // ========================================================================
 
     /// Insert a document
+    ///
+    /// Fails without writing anything if the document would duplicate the
+    /// values of another document in a unique index.
     pub async fn insert_document(&self, collection: &str, doc: &Document) -> Result<()> {
-        let lsm = self.get_or_create_lsm(collection);
-
-        let key = doc.id.as_bytes().to_vec();
-        let value = serde_json::to_vec(doc)?;
-
-        // Write to WAL first
-        if let Some(ref wal) = self.wal {
-            let entry = format!("INSERT:{}:{}", collection, doc.id);
-            wal.append(&entry.into_bytes()).await?;
-            wal.append(&value).await?;
-        }
-
-        // Write to LSM tree
-        lsm.put(key, value)?;
-
-        // Update collection metadata
-        self.increment_collection_count(collection).await?;
-
+        let write = DocumentWrite {
+            collection: collection.to_string(),
+            id: doc.id.clone(),
+            document: Some(doc.clone()),
+        };
+        self.apply(vec![write], None).await?;
         Ok(())
     }
 
     /// Get a document by ID
     pub async fn get_document(&self, collection: &str, id: &str) -> Result<Option<Document>> {
-        let lsm = self.get_or_create_lsm(collection);
+        self.get_document_at(collection, id, LATEST).await
+    }
+
+    /// Get a document by ID as of a snapshot from
+    /// [`StorageEngine::begin_snapshot`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be read or decoded.
+    #[allow(clippy::unused_async)] // async like the other document reads
+    pub async fn get_document_at(
+        &self,
+        collection: &str,
+        id: &str,
+        snapshot: u64,
+    ) -> Result<Option<Document>> {
+        let lsm = self.get_or_create_lsm(collection)?;
 
-        match lsm.get(id.as_bytes())? {
+        match lsm.get_at(id.as_bytes(), snapshot)? {
             Some(value) => {
                 let doc: Document = serde_json::from_slice(&value)?;
                 Ok(Some(doc))
@@ -291,75 +1022,631 @@ impl StorageEngine {
 
     /// Delete a document
     pub async fn delete_document(&self, collection: &str, id: &str) -> Result<bool> {
-        let lsm = self.get_or_create_lsm(collection);
-
-        // Write to WAL first
-        if let Some(ref wal) = self.wal {
-            let entry = format!("DELETE:{}:{}", collection, id);
-            wal.append(&entry.into_bytes()).await?;
-        }
-
-        let deleted = lsm.delete(id.as_bytes())?;
-
-        if deleted {
-            self.decrement_collection_count(collection).await?;
-        }
-
-        Ok(deleted)
+        let write = DocumentWrite {
+            collection: collection.to_string(),
+            id: id.to_string(),
+            document: None,
+        };
+        let (_, changed) = self.apply(vec![write], None).await?;
+        Ok(changed > 0)
     }
 
     /// Scan documents in a collection
+    ///
+    /// Without a `limit` every matching document is returned.
     pub async fn scan_documents(
         &self,
         collection: &str,
         prefix: Option<&[u8]>,
         limit: Option<usize>,
     ) -> Result<Vec<Document>> {
-        let lsm = self.get_or_create_lsm(collection);
-
-        let mut docs = Vec::new();
-        let limit = limit.unwrap_or(1000);
-
-        for entry in lsm.scan(prefix.unwrap_or(&[]))? {
-            let (_, value) = entry?;
-            let doc: Document = serde_json::from_slice(&value)?;
-            docs.push(doc);
+        self.document_scan(collection, prefix, None)?
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
 
-            if docs.len() >= limit {
-                break;
-            }
-        }
+    /// Lazily iterate the documents of a collection in ID order
+    ///
+    /// With `start_after` the scan begins at the first document whose ID
+    /// sorts after it, so an interrupted scan can be resumed from the last
+    /// ID it returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection's tree cannot be opened.
+    pub fn document_scan(
+        &self,
+        collection: &str,
+        prefix: Option<&[u8]>,
+        start_after: Option<&str>,
+    ) -> Result<DocumentScan> {
+        self.document_scan_at(collection, prefix, start_after, LATEST)
+    }
 
-        Ok(docs)
+    /// Lazily iterate the documents of a collection as of a snapshot from
+    /// [`StorageEngine::begin_snapshot`], in ID order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection's tree cannot be opened or read.
+    pub fn document_scan_at(
+        &self,
+        collection: &str,
+        prefix: Option<&[u8]>,
+        start_after: Option<&str>,
+        snapshot: u64,
+    ) -> Result<DocumentScan> {
+        let lsm = self.get_or_create_lsm(collection)?;
+        let entries = lsm.scan_at(
+            prefix.unwrap_or(&[]),
+            start_after.map(str::as_bytes),
+            snapshot,
+        )?;
+        Ok(DocumentScan { entries })
     }
 
     // ========================================================================
-    // Vector Operations (for compatibility layer)
+    // Transactions
     // ========================================================================
 
-    /// Get collection info with vector dimensions
-    pub async fn get_collection_info(&self, name: &str) -> Result<Option<CollectionInfo>> {
-        match self.get_collection(name).await? {
-            Some(metadata) => {
-                // Get vector index dimensions if exists
-                let vector_dimensions = self.vector_indexes.get(name).map(|idx| idx.dimensions());
-                Ok(Some(CollectionInfo {
-                    name: metadata.name,
-                    count: metadata.count,
-                    size_bytes: metadata.size_bytes,
-                    vector_dimensions,
+    /// Open a snapshot of everything committed so far
+    ///
+    /// Reads at the returned timestamp see the same documents until the
+    /// snapshot is released with [`StorageEngine::release_snapshot`], and
+    /// the versions they read are not garbage collected before that.
+    pub fn begin_snapshot(&self) -> u64 {
+        self.clock.begin_snapshot()
+    }
+
+    /// Release a snapshot opened by [`StorageEngine::begin_snapshot`]
+    pub fn release_snapshot(&self, snapshot: u64) {
+        self.clock.release_snapshot(snapshot);
+    }
+
+    /// Commit a transaction's writes atomically at a new timestamp
+    ///
+    /// The first committer wins: the commit fails with a conflict if
+    /// another commit changed a document it writes after `snapshot`, and
+    /// with a serialization failure if another commit changed anything in
+    /// `reads`. Returns the commit timestamp.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on a conflict or serialization failure as described
+    /// above, or if the commit cannot be written.
+    pub async fn commit(
+        &self,
+        snapshot: u64,
+        writes: Vec<DocumentWrite>,
+        reads: &[ReadKey],
+    ) -> Result<u64> {
+        let (ts, _) = self.apply(writes, Some((snapshot, reads))).await?;
+        Ok(ts)
+    }
+
+    /// Remove the document versions no open snapshot can read
+    ///
+    /// Checkpoints collect garbage as well. Returns the number of versions
+    /// removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if versions cannot be read or removed.
+    pub async fn collect_garbage(&self) -> Result<usize> {
+        let trees: Vec<Arc<LsmTree>> = self.lsm_trees.iter().map(|e| e.value().clone()).collect();
+        let watermark = self.clock.watermark();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut removed = 0;
+            for lsm in trees {
+                removed += lsm.collect_versions(watermark)?;
+            }
+            Ok(removed)
+        })
+        .await
+        .map_err(|e| lumadb_common::error::Error::Internal(e.to_string()))?
+    }
+
+    /// Group writes by collection, in name order, keeping the last write
+    /// of each document
+    fn collection_writes(&self, writes: Vec<DocumentWrite>) -> Result<Vec<CollectionWrites>> {
+        let mut batch: BTreeMap<String, BTreeMap<String, Option<Document>>> = BTreeMap::new();
+        for write in writes {
+            batch
+                .entry(write.collection)
+                .or_default()
+                .insert(write.id, write.document);
+        }
+
+        let mut targets = Vec::with_capacity(batch.len());
+        for (collection, documents) in batch {
+            let mut writes = Vec::with_capacity(documents.len());
+            for (id, document) in documents {
+                writes.push(PendingWrite {
+                    value: document.as_ref().map(serde_json::to_vec).transpose()?,
+                    id,
+                    document,
+                    old: None,
+                });
+            }
+            targets.push(CollectionWrites {
+                lsm: self.get_or_create_lsm(&collection)?,
+                collection,
+                indexes: Vec::new(),
+                writes,
+            });
+        }
+        Ok(targets)
+    }
+
+    /// Read the documents a commit replaces and check its unique indexes
+    fn prepare_writes(target: &mut CollectionWrites) -> Result<()> {
+        for write in &mut target.writes {
+            write.old = Self::read_document(&target.lsm, &write.id)?;
+        }
+        for write in &target.writes {
+            if let Some(doc) = &write.document {
+                let rewritten = |id: &str| {
+                    target
+                        .writes
+                        .binary_search_by(|w| w.id.as_str().cmp(id))
+                        .is_ok()
+                };
+                Self::check_unique(
+                    &target.collection,
+                    &target.lsm,
+                    &target.indexes,
+                    doc,
+                    rewritten,
+                )?;
+            }
+        }
+        Self::check_unique_within(target)
+    }
+
+    /// Append a commit's writes to the WAL, if there is one
+    async fn log_commit(&self, ts: u64, targets: &[CollectionWrites]) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            let writes = targets
+                .iter()
+                .flat_map(|target| {
+                    target.writes.iter().map(|write| WalWrite {
+                        collection: target.collection.clone(),
+                        id: write.id.clone(),
+                        value: write.value.clone(),
+                    })
+                })
+                .collect();
+            let record = WalRecord::Commit { ts, writes };
+            wal.append(&record.encode()?).await?;
+        }
+        Ok(())
+    }
+
+    /// Write documents as one commit at a new timestamp
+    ///
+    /// With `validate`, the commit first waits for earlier commits and then
+    /// checks that nothing it writes or read changed after the snapshot.
+    /// Returns the commit timestamp and the number of documents changed.
+    async fn apply(
+        &self,
+        writes: Vec<DocumentWrite>,
+        validate: Option<(u64, &[ReadKey])>,
+    ) -> Result<(u64, usize)> {
+        let mut targets = self.collection_writes(writes)?;
+
+        // Collections are locked in name order, so commits cannot deadlock
+        let registries: Vec<Arc<CollectionIndexes>> = targets
+            .iter()
+            .map(|target| self.collection_indexes(&target.collection))
+            .collect();
+        let mut guards = Vec::with_capacity(registries.len());
+        for (target, registry) in targets.iter_mut().zip(&registries) {
+            let registration = registry.registration.read().await;
+            target.indexes.clone_from(&registry.indexes.read());
+            let payload_indexed = self
+                .vector_index(&target.collection)
+                .is_some_and(|index| !index.payload_index().is_empty())
+                || self.fulltext_indexes.contains_key(&target.collection);
+            let write = if target.indexes.is_empty() && !payload_indexed {
+                None
+            } else {
+                Some(registry.write_lock.lock().await)
+            };
+            guards.push((registration, write));
+        }
+
+        let commit = self.clock.begin_commit();
+        if let Some((snapshot, reads)) = validate {
+            // Every commit the checks must see has a smaller timestamp
+            self.clock.wait_for_earlier(commit.ts).await?;
+            self.validate(snapshot, &targets, reads)?;
+        }
+
+        for target in &mut targets {
+            Self::prepare_writes(target)?;
+        }
+
+        let changed = {
+            let _gate = self.write_gate.read().await;
+
+            // Write to WAL first
+            self.log_commit(commit.ts, &targets).await?;
+
+            let mut changed = 0;
+            let mut published: Vec<PublishedChange> = Vec::new();
+            for target in &mut targets {
+                let mut delta = 0;
+                for write in &target.writes {
+                    if write.old.is_none() && write.document.is_none() {
+                        continue;
+                    }
+
+                    // Index entries go first, so a reader never finds a
+                    // document missing from an index that covers it
+                    Self::update_indexes(
+                        &target.indexes,
+                        write.old.as_ref(),
+                        write.document.as_ref(),
+                    )?;
+                    target.lsm.put_version(
+                        write.id.as_bytes(),
+                        commit.ts,
+                        write.value.as_deref(),
+                    )?;
+                    if let Some(index) = self.vector_index(&target.collection) {
+                        if write.document.is_none() {
+                            index.delete(&write.id)?;
+                        }
+                        let payload = write.document.as_ref().map(|doc| &doc.data);
+                        index.payload_index().update(&write.id, payload);
+                    }
+                    if let Some(index) = self.text_index(&target.collection) {
+                        index.update(&write.id, write.document.as_ref().map(|doc| &doc.data))?;
+                    }
+
+                    delta +=
+                        isize::from(write.document.is_some()) - isize::from(write.old.is_some());
+                    changed += 1;
+                }
+                published.extend(target.writes.iter_mut().filter_map(|write| {
+                    (write.old.is_some() || write.document.is_some()).then(|| {
+                        (
+                            target.collection.clone(),
+                            std::mem::take(&mut write.id),
+                            write.old.take(),
+                            write.document.take(),
+                        )
+                    })
+                }));
+                if delta != 0 {
+                    self.adjust_collection_count(&target.collection, delta)?;
+                }
+                self.last_commits
+                    .insert(target.collection.clone(), commit.ts);
+            }
+            self.changes.publish(commit.ts, published);
+            changed
+        };
+
+        let ts = commit.ts;
+        drop(commit);
+        drop(guards);
+
+        self.maybe_checkpoint().await?;
+
+        Ok((ts, changed))
+    }
+
+    /// Fail if another commit changed a document a commit writes, or
+    /// anything it read, after its snapshot
+    fn validate(
+        &self,
+        snapshot: u64,
+        targets: &[CollectionWrites],
+        reads: &[ReadKey],
+    ) -> Result<()> {
+        for target in targets {
+            for write in &target.writes {
+                if target.lsm.latest_version(write.id.as_bytes())? > Some(snapshot) {
+                    return Err(lumadb_common::error::Error::Transaction(
+                        TransactionError::Conflict(format!(
+                            "document {} in {} was changed by a concurrent transaction",
+                            write.id, target.collection
+                        )),
+                    ));
+                }
+            }
+        }
+
+        for read in reads {
+            let (changed, what) = match read {
+                ReadKey::Document { collection, id } => {
+                    let lsm = self.get_or_create_lsm(collection)?;
+                    (
+                        lsm.latest_version(id.as_bytes())? > Some(snapshot),
+                        format!("document {id} in {collection}"),
+                    )
+                }
+                ReadKey::Collection(collection) => (
+                    self.last_commits
+                        .get(collection)
+                        .is_some_and(|ts| *ts > snapshot),
+                    format!("collection {collection}"),
+                ),
+            };
+            if changed {
+                return Err(lumadb_common::error::Error::Transaction(
+                    TransactionError::SerializationFailure(format!(
+                        "{what} was changed after the transaction read it"
+                    )),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // ========================================================================
+    // Secondary Index Operations
+    // ========================================================================
+
+    /// Create a secondary index and backfill it from existing documents
+    ///
+    /// Writes continue during the backfill and maintain the new index as
+    /// they go, but queries only use it once the backfill has finished. If
+    /// existing documents violate a unique index, the index is dropped and
+    /// the violation returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection does not exist, an index with that
+    /// name already exists, existing documents violate a unique index, or the
+    /// index cannot be written.
+    pub async fn create_index(&self, collection: &str, metadata: IndexMetadata) -> Result<()> {
+        info!("Creating index {} on {}", metadata.name, collection);
+
+        if self.get_collection(collection).await?.is_none() {
+            return Err(lumadb_common::error::Error::Storage(
+                StorageError::CollectionNotFound(collection.to_string()),
+            ));
+        }
+        let lsm = self.get_or_create_lsm(collection)?;
+        let registry = self.collection_indexes(collection);
+        let name = metadata.name.clone();
+
+        let index = {
+            let _registration = registry.registration.write().await;
+            if registry.indexes.read().iter().any(|i| i.name() == name) {
+                return Err(lumadb_common::error::Error::Storage(
+                    StorageError::WriteFailed(format!(
+                        "Index {name} already exists on {collection}"
+                    )),
+                ));
+            }
+            let metadata = IndexMetadata {
+                building: true,
+                entries: 0,
+                distinct_keys: 0,
+                ..metadata
+            };
+            let path = self.index_path(collection, &name);
+            if path.exists() {
+                std::fs::remove_dir_all(&path)?;
+            }
+            let options = self.lsm_options(collection)?;
+            let index = Arc::new(SecondaryIndex::open(&path, metadata, options)?);
+            registry.indexes.write().push(index.clone());
+            self.save_index_metadata(collection, &registry)?;
+            index
+        };
+
+        if let Err(e) = self
+            .backfill_index(collection, &lsm, &registry, &index)
+            .await
+        {
+            self.drop_index(collection, &name).await?;
+            return Err(e);
+        }
+
+        // Later writes are in the WAL, earlier ones must be on disk before
+        // the index counts as built
+        index.flush()?;
+
+        let _registration = registry.registration.write().await;
+        index.set_ready();
+        self.save_index_metadata(collection, &registry)?;
+        info!("Index {} on {} is ready", name, collection);
+        Ok(())
+    }
+
+    /// Add entries for the documents already in a collection
+    async fn backfill_index(
+        &self,
+        collection: &str,
+        lsm: &Arc<LsmTree>,
+        registry: &CollectionIndexes,
+        index: &Arc<SecondaryIndex>,
+    ) -> Result<()> {
+        let index = std::slice::from_ref(index);
+        for doc in self.document_scan(collection, None, None)? {
+            let id = doc?.id;
+            // Writers maintain the index already; re-read the document under
+            // their lock so this does not index a version they replaced
+            let _write = registry.write_lock.lock().await;
+            let Some(doc) = Self::read_document(lsm, &id)? else {
+                continue;
+            };
+            Self::check_unique(collection, lsm, index, &doc, |_| false)?;
+            Self::update_indexes(index, None, Some(&doc))?;
+        }
+        Ok(())
+    }
+
+    /// Drop a secondary index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index does not exist or its metadata or files
+    /// cannot be removed.
+    pub async fn drop_index(&self, collection: &str, name: &str) -> Result<()> {
+        info!("Dropping index {} on {}", name, collection);
+
+        let registry = self.collection_indexes(collection);
+        let index = {
+            let _registration = registry.registration.write().await;
+            let index = {
+                let mut indexes = registry.indexes.write();
+                let position = indexes
+                    .iter()
+                    .position(|i| i.name() == name)
+                    .ok_or_else(|| {
+                        lumadb_common::error::Error::Storage(StorageError::KeyNotFound(format!(
+                            "index {name} on {collection}"
+                        )))
+                    })?;
+                indexes.remove(position)
+            };
+            self.save_index_metadata(collection, &registry)?;
+            index
+        };
+
+        index.close();
+        let path = self.index_path(collection, name);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            tokio::fs::remove_dir_all(&path).await?;
+        }
+        Ok(())
+    }
+
+    /// Lazily iterate the documents whose entries in an index fall within
+    /// `range`, in index order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index does not exist or is still being built, or
+    /// the index or collection cannot be read.
+    pub fn index_scan(
+        &self,
+        collection: &str,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<IndexedDocuments> {
+        let index = self
+            .index_snapshot(collection)
+            .into_iter()
+            .find(|i| i.name() == index && !i.is_building())
+            .ok_or_else(|| {
+                lumadb_common::error::Error::Storage(StorageError::KeyNotFound(format!(
+                    "index {index} on {collection}"
+                )))
+            })?;
+
+        Ok(IndexedDocuments {
+            entries: index.scan(range)?,
+            lsm: self.get_or_create_lsm(collection)?,
+            index,
+        })
+    }
+
+    /// Document count and ready indexes of a collection
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection metadata cannot be read.
+    pub fn collection_statistics(&self, collection: &str) -> Result<Option<CollectionStatistics>> {
+        let key = format!("collection:{collection}");
+        let Some(value) = self.db.get(&key).map_err(|e| {
+            lumadb_common::error::Error::Storage(StorageError::ReadFailed(e.to_string()))
+        })?
+        else {
+            return Ok(None);
+        };
+        let metadata: CollectionMetadata = serde_json::from_slice(&value)?;
+
+        Ok(Some(CollectionStatistics {
+            documents: metadata.count,
+            indexes: self
+                .index_snapshot(collection)
+                .iter()
+                .filter(|i| !i.is_building())
+                .map(|i| i.metadata())
+                .collect(),
+        }))
+    }
+
+    // ========================================================================
+    // Vector Operations (for compatibility layer)
+    // ========================================================================
+
+    /// Get collection info with vector dimensions
+    pub async fn get_collection_info(&self, name: &str) -> Result<Option<CollectionInfo>> {
+        match self.get_collection(name).await? {
+            Some(metadata) => {
+                // Get vector index settings if exists
+                let vector_config = self.vector_index(name).map(|idx| idx.config().clone());
+                Ok(Some(CollectionInfo {
+                    name: metadata.name,
+                    count: metadata.count,
+                    size_bytes: metadata.size_bytes,
+                    vector_dimensions: vector_config.as_ref().map(|config| config.dimensions),
+                    vector_config,
                 }))
             }
             None => Ok(None),
         }
     }
 
-    /// Create a vector-enabled collection
+    /// Create a vector-enabled collection with the default index settings
     pub async fn create_vector_collection(&self, name: &str, dimensions: usize) -> Result<()> {
+        self.create_vector_collection_with_config(name, VectorIndexConfig::new(dimensions))
+            .await
+    }
+
+    /// Create a vector-enabled collection, or add a vector index to an
+    /// existing collection
+    ///
+    /// The settings are kept in the collection metadata, and the index is
+    /// reopened with them on restart.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the collection already has a vector index with different
+    /// settings, or the metadata or index cannot be written.
+    pub async fn create_vector_collection_with_config(
+        &self,
+        name: &str,
+        config: VectorIndexConfig,
+    ) -> Result<()> {
+        config
+            .validate()
+            .map_err(lumadb_common::error::Error::Config)?;
+
         // Create regular collection
-        let _ = self.create_collection(name).await;
+        if self.get_collection(name).await?.is_none() {
+            let _ = self.create_collection(name).await;
+        }
+        let mut metadata = self
+            .get_collection(name)
+            .await?
+            .ok_or_else(|| StorageError::CollectionNotFound(name.to_string()))?;
+
+        match metadata.vector {
+            Some(ref existing) if *existing != config => {
+                return Err(StorageError::WriteFailed(format!(
+                    "Collection {name} already has a vector index with different settings"
+                ))
+                .into());
+            }
+            Some(_) => {}
+            None => {
+                metadata.vector = Some(config.clone());
+                self.put_collection_metadata(&metadata)?;
+            }
+        }
+
         // Initialize vector index
-        self.get_or_create_vector_index(name, dimensions);
+        if self.vector_index(name).is_none() {
+            self.open_vector_index(name, config)?;
+        }
         Ok(())
     }
 
@@ -370,14 +1657,24 @@ impl StorageEngine {
         doc: &Document,
         vector: &[f32],
     ) -> Result<()> {
+        let index = self.vector_index(collection);
+        if let Some(ref index) = index {
+            if vector.len() != index.dimensions() {
+                return Err(StorageError::WriteFailed(format!(
+                    "Vector dimension mismatch: expected {}, got {}",
+                    index.dimensions(),
+                    vector.len()
+                ))
+                .into());
+            }
+        }
+
         // Insert document
         self.insert_document(collection, doc).await?;
 
         // Index vector
-        if let Some(index) = self.vector_indexes.get(collection) {
-            index.insert(doc.id.clone(), vector.to_vec()).map_err(|e| {
-                lumadb_common::error::Error::Storage(StorageError::WriteFailed(e))
-            })?;
+        if let Some(index) = index {
+            index.insert(doc.id.clone(), vector.to_vec())?;
         }
         Ok(())
     }
@@ -389,16 +1686,34 @@ impl StorageEngine {
         query: &[f32],
         k: usize,
     ) -> Result<Vec<VectorSearchResult>> {
-        let index = self.vector_indexes.get(collection).ok_or_else(|| {
-            lumadb_common::error::Error::Storage(StorageError::ReadFailed(format!(
-                "No vector index for collection: {}",
-                collection
-            )))
-        })?;
+        self.vector_search_filtered(collection, query, k, None, SearchOptions::default())
+            .await
+    }
 
-        let results = index.search(query, k);
-        let mut search_results = Vec::with_capacity(results.len());
+    /// Vector similarity search over the points whose document passes
+    /// `filter`
+    ///
+    /// The payload indexes bound the points a filter can match. When fewer
+    /// than the index's `full_scan_threshold` can, the query is compared
+    /// with each of them; otherwise the graph is traversed with the filter
+    /// checked along the way, falling back to comparing every bounded point
+    /// if the traversal finds fewer than `k` matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection is not a vector collection, the query
+    /// has the wrong dimension, or the documents cannot be read.
+    pub async fn vector_search_filtered(
+        &self,
+        collection: &str,
+        query: &[f32],
+        k: usize,
+        filter: Option<&PayloadFilter>,
+        options: SearchOptions,
+    ) -> Result<Vec<VectorSearchResult>> {
+        let results = self.vector_ranking(collection, query, k, filter, options)?;
 
+        let mut search_results = Vec::with_capacity(results.len());
         for (doc_id, score) in results {
             let payload = self.get_document(collection, &doc_id).await?
                 .map(|d| d.data)
@@ -413,6 +1728,388 @@ impl StorageEngine {
         Ok(search_results)
     }
 
+    /// IDs and distances of the `k` nearest points passing `filter`
+    fn vector_ranking(
+        &self,
+        collection: &str,
+        query: &[f32],
+        k: usize,
+        filter: Option<&PayloadFilter>,
+        options: SearchOptions,
+    ) -> Result<Vec<(String, f32)>> {
+        let index = self.vector_index(collection).ok_or_else(|| {
+            lumadb_common::error::Error::Storage(StorageError::ReadFailed(format!(
+                "No vector index for collection: {collection}"
+            )))
+        })?;
+        let results = match filter {
+            None => index.search_with_options(query, k, &options),
+            Some(filter) => {
+                let lsm = self.get_or_create_lsm(collection)?;
+                let candidates = index.payload_index().candidates(filter);
+                let estimate = candidates
+                    .as_ref()
+                    .map_or_else(|| index.len(), HashSet::len);
+
+                let mut failure = None;
+                let results = {
+                    let mut accept = |id: &str| match Self::read_document(&lsm, id) {
+                        Ok(Some(doc)) => filter.matches(id, &doc.data),
+                        Ok(None) => false,
+                        Err(e) => {
+                            failure.get_or_insert(e);
+                            false
+                        }
+                    };
+                    if options.exact || estimate < index.config().full_scan_threshold {
+                        index.search_exact(query, k, candidates.as_ref(), &options, &mut accept)
+                    } else {
+                        let mut bounded = |id: &str| {
+                            candidates.as_ref().map_or(true, |ids| ids.contains(id)) && accept(id)
+                        };
+                        let results = index.search_filtered(query, k, &options, &mut bounded);
+                        if results.len() < k {
+                            let candidates = candidates.as_ref();
+                            index.search_exact(query, k, candidates, &options, &mut accept)
+                        } else {
+                            results
+                        }
+                    }
+                };
+                if let Some(e) = failure {
+                    return Err(e);
+                }
+                results
+            }
+        };
+        Ok(results)
+    }
+
+    /// Full-text search: IDs and BM25 scores of the `k` documents matching
+    /// `text` best, among those passing `filter`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection has no full-text index or its
+    /// documents cannot be read.
+    pub fn text_search(
+        &self,
+        collection: &str,
+        text: &str,
+        k: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> Result<Vec<(String, f32)>> {
+        let index = self.text_index(collection).ok_or_else(|| {
+            lumadb_common::error::Error::Storage(StorageError::ReadFailed(format!(
+                "No full-text index for collection: {collection}"
+            )))
+        })?;
+        let Some(filter) = filter else {
+            let hits = index.search(text, k)?;
+            return Ok(hits.into_iter().map(|hit| (hit.id, hit.score)).collect());
+        };
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Fetch more matches until enough pass the filter or none are left
+        let lsm = self.get_or_create_lsm(collection)?;
+        let mut limit = k.saturating_mul(hybrid::PREFETCH_FACTOR);
+        loop {
+            let hits = index.search(text, limit)?;
+            let exhausted = hits.len() < limit;
+            let mut results = Vec::with_capacity(k);
+            for hit in hits {
+                if results.len() == k {
+                    break;
+                }
+                if let Some(doc) = Self::read_document(&lsm, &hit.id)? {
+                    if filter.matches(&hit.id, &doc.data) {
+                        results.push((hit.id, hit.score));
+                    }
+                }
+            }
+            if results.len() == k || exhausted {
+                return Ok(results);
+            }
+            limit = limit.saturating_mul(hybrid::PREFETCH_FACTOR);
+        }
+    }
+
+    /// Hybrid search: ranks the documents by vector similarity and by BM25
+    /// for the query text, fetching [`HybridQuery::prefetch_limit`] from
+    /// each, and fuses the rankings
+    ///
+    /// A query with only a vector or only text fuses a single ranking.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection has no vector or full-text index the
+    /// query needs, or the search fails.
+    pub async fn hybrid_search(
+        &self,
+        collection: &str,
+        query: &HybridQuery,
+    ) -> Result<Vec<HybridSearchResult>> {
+        if query.vector.is_none() && query.text.is_none() {
+            return Err(lumadb_common::error::Error::Storage(
+                StorageError::ReadFailed(
+                    "A hybrid search needs a query vector or text".to_string(),
+                ),
+            ));
+        }
+        let prefetch = query.prefetch_limit();
+        let filter = query.filter.as_ref();
+        let vector = match query.vector {
+            Some(ref vector) => {
+                self.vector_ranking(collection, vector, prefetch, filter, query.options)?
+            }
+            None => Vec::new(),
+        };
+        let text = match query.text {
+            Some(ref text) => self.text_search(collection, text, prefetch, filter)?,
+            None => Vec::new(),
+        };
+
+        let mut results = hybrid::fuse(query, &vector, &text);
+        for result in &mut results {
+            result.payload = self
+                .get_document(collection, &result.doc_id)
+                .await?
+                .map(|d| d.data)
+                .unwrap_or_default();
+        }
+        Ok(results)
+    }
+
+    /// Documents of a collection that pass `filter` (all of them without
+    /// one), in ID order from the document with ID `from` on
+    ///
+    /// Payload indexes bound the documents read when they cover the filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection cannot be read.
+    pub fn filter_documents(
+        &self,
+        collection: &str,
+        filter: Option<&PayloadFilter>,
+        from: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Document>> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let lsm = self.get_or_create_lsm(collection)?;
+        let passes = |doc: &Document| filter.map_or(true, |f| f.matches(&doc.id, &doc.data));
+        let candidates = filter.and_then(|filter| {
+            self.vector_index(collection)?
+                .payload_index()
+                .candidates(filter)
+        });
+
+        let mut documents = Vec::new();
+        if let Some(candidates) = candidates {
+            let mut ids: Vec<String> = candidates
+                .into_iter()
+                .filter(|id| from.map_or(true, |from| id.as_str() >= from))
+                .collect();
+            ids.sort();
+            for id in ids {
+                if documents.len() == limit {
+                    break;
+                }
+                if let Some(doc) = Self::read_document(&lsm, &id)? {
+                    if passes(&doc) {
+                        documents.push(doc);
+                    }
+                }
+            }
+            return Ok(documents);
+        }
+
+        let first = from
+            .map(|from| Self::read_document(&lsm, from))
+            .transpose()?
+            .flatten();
+        let rest = self.document_scan(collection, None, from)?;
+        for doc in first.into_iter().map(Ok).chain(rest) {
+            if documents.len() == limit {
+                break;
+            }
+            let doc = doc?;
+            if passes(&doc) {
+                documents.push(doc);
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Delete the documents of a collection that pass `filter` in one
+    /// commit; returns how many were deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection cannot be read or the deletes cannot
+    /// be committed.
+    pub async fn delete_documents_matching(
+        &self,
+        collection: &str,
+        filter: &PayloadFilter,
+    ) -> Result<usize> {
+        let writes: Vec<DocumentWrite> = self
+            .filter_documents(collection, Some(filter), None, None)?
+            .into_iter()
+            .map(|doc| DocumentWrite {
+                collection: collection.to_string(),
+                id: doc.id,
+                document: None,
+            })
+            .collect();
+        if writes.is_empty() {
+            return Ok(0);
+        }
+        let (_, changed) = self.apply(writes, None).await?;
+        Ok(changed)
+    }
+
+    /// Index a payload field of a vector collection, to plan filtered
+    /// searches with
+    ///
+    /// Writes to the collection wait while the existing documents are
+    /// indexed. Payload indexes are kept in memory and rebuilt from the
+    /// documents on restart. Returns `false` if the field is already
+    /// indexed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection is not a vector collection or its
+    /// documents cannot be read.
+    pub async fn create_payload_index(
+        &self,
+        collection: &str,
+        spec: PayloadIndexSpec,
+    ) -> Result<bool> {
+        let index = self.vector_index(collection).ok_or_else(|| {
+            lumadb_common::error::Error::Storage(StorageError::WriteFailed(format!(
+                "No vector index for collection: {collection}"
+            )))
+        })?;
+        info!("Creating payload index on {}.{}", collection, spec.field);
+
+        let registry = self.collection_indexes(collection);
+        let _registration = registry.registration.write().await;
+        if !index.payload_index().add_field(&spec) {
+            return Ok(false);
+        }
+
+        let built = (|| -> Result<()> {
+            for doc in self.document_scan(collection, None, None)? {
+                let doc = doc?;
+                index.payload_index().update(&doc.id, Some(&doc.data));
+            }
+            self.save_payload_indexes(collection, &index)
+        })();
+        if let Err(e) = built {
+            index.payload_index().remove_field(&spec.field);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Drop a payload index; returns whether the field was indexed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection is not a vector collection.
+    pub async fn drop_payload_index(&self, collection: &str, field: &str) -> Result<bool> {
+        let Some(index) = self.vector_index(collection) else {
+            return Ok(false);
+        };
+        let registry = self.collection_indexes(collection);
+        let _registration = registry.registration.write().await;
+        if !index.payload_index().remove_field(field) {
+            return Ok(false);
+        }
+        self.save_payload_indexes(collection, &index)?;
+        Ok(true)
+    }
+
+    /// Index the text of a document field for full-text and hybrid search
+    ///
+    /// A collection has one full-text index covering all its text fields;
+    /// adding a field rebuilds it from the documents while writes to the
+    /// collection wait. Full-text indexes are rebuilt from the documents on
+    /// restart. Returns `false` if the field is already indexed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection does not exist or its documents
+    /// cannot be read.
+    pub async fn create_text_index(&self, collection: &str, field: &str) -> Result<bool> {
+        let registry = self.collection_indexes(collection);
+        let _registration = registry.registration.write().await;
+        let mut metadata = self.get_collection(collection).await?.ok_or_else(|| {
+            lumadb_common::error::Error::Storage(StorageError::CollectionNotFound(
+                collection.to_string(),
+            ))
+        })?;
+        if metadata.text_fields.iter().any(|f| f == field) {
+            return Ok(false);
+        }
+        info!("Creating full-text index on {}.{}", collection, field);
+
+        metadata.text_fields.push(field.to_string());
+        if let Err(e) = self.build_text_index(collection, metadata.text_fields.clone()) {
+            metadata.text_fields.pop();
+            self.build_text_index(collection, metadata.text_fields)?;
+            return Err(e);
+        }
+        self.put_collection_metadata(&metadata)?;
+        Ok(true)
+    }
+
+    /// Stop indexing the text of a document field, rebuilding the
+    /// full-text index without it; returns whether the field was indexed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the full-text index cannot be rebuilt.
+    pub async fn drop_text_index(&self, collection: &str, field: &str) -> Result<bool> {
+        let registry = self.collection_indexes(collection);
+        let _registration = registry.registration.write().await;
+        let Some(mut metadata) = self.get_collection(collection).await? else {
+            return Ok(false);
+        };
+        let before = metadata.text_fields.len();
+        metadata.text_fields.retain(|f| f != field);
+        if metadata.text_fields.len() == before {
+            return Ok(false);
+        }
+        self.build_text_index(collection, metadata.text_fields.clone())?;
+        self.put_collection_metadata(&metadata)?;
+        Ok(true)
+    }
+
+    /// Fields covered by a collection's full-text index
+    pub fn text_fields(&self, collection: &str) -> Vec<String> {
+        self.text_index(collection)
+            .map(|index| index.fields().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Payload indexes of a vector collection, with the number of points
+    /// each holds values for
+    pub fn payload_indexes(&self, collection: &str) -> Vec<(PayloadIndexSpec, usize)> {
+        self.vector_index(collection)
+            .map(|index| index.payload_index().fields())
+            .unwrap_or_default()
+    }
+
+    /// How a quantized vector collection's codes trade recall for memory
+    /// and speed, if it has a vector index with quantization configured
+    pub fn quantization_report(&self, collection: &str) -> Option<QuantizationReport> {
+        self.vector_index(collection)?.quantization_report()
+    }
+
     /// Count documents in a collection
     pub async fn count_documents(&self, collection: &str) -> Result<usize> {
         match self.get_collection(collection).await? {
@@ -421,28 +2118,393 @@ impl StorageEngine {
         }
     }
 
+    // ========================================================================
+    // Snapshots
+    // ========================================================================
+
+    /// Directory holding a collection's snapshot files
+    fn snapshot_dir(&self, collection: &str) -> std::path::PathBuf {
+        Path::new(&self.config.path)
+            .join("snapshots")
+            .join(collection)
+    }
+
+    /// Write a snapshot of a collection, with its settings, indexes and
+    /// every document and vector, for [`StorageEngine::restore_snapshot`]
+    ///
+    /// Writes to the collection wait while the snapshot is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection does not exist or the snapshot file
+    /// cannot be written.
+    pub async fn create_snapshot(&self, collection: &str) -> Result<SnapshotDescription> {
+        let registry = self.collection_indexes(collection);
+        let _registration = registry.registration.write().await;
+        let metadata = self.get_collection(collection).await?.ok_or_else(|| {
+            lumadb_common::error::Error::Storage(StorageError::CollectionNotFound(
+                collection.to_string(),
+            ))
+        })?;
+        info!("Creating snapshot of collection {}", collection);
+
+        let dir = self.snapshot_dir(collection);
+        std::fs::create_dir_all(&dir)?;
+        let name = format!(
+            "{}-{}.{}",
+            collection,
+            chrono::Utc::now().format("%Y-%m-%d-%H-%M-%S-%6f"),
+            snapshot::SNAPSHOT_EXTENSION
+        );
+        let mut writer = SnapshotWriter::create(&dir.join(name), &metadata)?;
+        let vectors = self.vector_index(collection);
+        for doc in self.document_scan(collection, None, None)? {
+            let doc = doc?;
+            let vector = vectors.as_ref().and_then(|index| index.get(&doc.id));
+            writer.document(doc, vector)?;
+        }
+        writer.finish()
+    }
+
+    /// Snapshots of a collection, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot directory or a snapshot file cannot be
+    /// read.
+    pub fn list_snapshots(&self, collection: &str) -> Result<Vec<SnapshotDescription>> {
+        let dir = self.snapshot_dir(collection);
+        if !is_plain_name(collection) || !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == snapshot::SNAPSHOT_EXTENSION)
+            {
+                snapshots.push(SnapshotDescription::read(&path)?);
+            }
+        }
+        snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(snapshots)
+    }
+
+    /// Path of one of a collection's snapshot files, if it exists
+    ///
+    /// Names that could lead outside the collection's snapshot directory
+    /// are never found.
+    pub fn snapshot_path(&self, collection: &str, name: &str) -> Option<std::path::PathBuf> {
+        let is_snapshot = Path::new(name)
+            .extension()
+            .is_some_and(|ext| ext == snapshot::SNAPSHOT_EXTENSION);
+        if !is_plain_name(collection) || !is_plain_name(name) || !is_snapshot {
+            return None;
+        }
+        let path = self.snapshot_dir(collection).join(name);
+        path.is_file().then_some(path)
+    }
+
+    /// Delete one of a collection's snapshot files; returns whether it
+    /// existed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot file cannot be removed.
+    pub fn delete_snapshot(&self, collection: &str, name: &str) -> Result<bool> {
+        let Some(path) = self.snapshot_path(collection, name) else {
+            return Ok(false);
+        };
+        std::fs::remove_file(&path)?;
+        let checksum = snapshot::checksum_path(&path);
+        if checksum.exists() {
+            std::fs::remove_file(checksum)?;
+        }
+        Ok(true)
+    }
+
+    /// Replace a collection with the contents of a snapshot file written by
+    /// [`StorageEngine::create_snapshot`], on this node or another
+    ///
+    /// The whole file is checked, against `checksum` (SHA-256 in hex) if
+    /// given, before the collection is touched. Vectors and secondary,
+    /// payload and text indexes are rebuilt from the restored documents.
+    /// Returns the number of documents restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot file cannot be read, fails its
+    /// checksum, or the collection cannot be rewritten.
+    pub async fn restore_snapshot(
+        &self,
+        collection: &str,
+        path: &Path,
+        checksum: Option<&str>,
+    ) -> Result<usize> {
+        if let Some(expected) = checksum {
+            let actual = snapshot::file_checksum(path)?;
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(StorageError::CorruptData(format!(
+                    "Snapshot {} has checksum {actual}, expected {expected}",
+                    path.display()
+                ))
+                .into());
+            }
+        }
+        let mut reader = SnapshotReader::open(path)?;
+        while reader.next_document()?.is_some() {}
+        let metadata = reader.collection().clone();
+        info!(
+            "Restoring collection {} from snapshot {:?}",
+            collection, path
+        );
+
+        if self.get_collection(collection).await?.is_some() {
+            self.delete_collection(collection).await?;
+        }
+        match metadata.vector {
+            Some(config) => {
+                self.create_vector_collection_with_config(collection, config)
+                    .await?;
+            }
+            None => self.create_collection(collection).await?,
+        }
+
+        let vectors = self.vector_index(collection);
+        let mut reader = SnapshotReader::open(path)?;
+        let mut batch = Vec::with_capacity(RESTORE_BATCH);
+        let mut restored = 0;
+        while let Some((document, vector)) = reader.next_document()? {
+            if let (Some(index), Some(vector)) = (&vectors, vector) {
+                index.insert(document.id.clone(), vector)?;
+            }
+            batch.push(DocumentWrite {
+                collection: collection.to_string(),
+                id: document.id.clone(),
+                document: Some(document),
+            });
+            if batch.len() == RESTORE_BATCH {
+                restored += self.apply(std::mem::take(&mut batch), None).await?.1;
+            }
+        }
+        if !batch.is_empty() {
+            restored += self.apply(batch, None).await?.1;
+        }
+
+        for index in metadata.indexes {
+            self.create_index(collection, index).await?;
+        }
+        for spec in metadata.payload_indexes {
+            self.create_payload_index(collection, spec).await?;
+        }
+        for field in &metadata.text_fields {
+            self.create_text_index(collection, field).await?;
+        }
+        Ok(restored)
+    }
+
     // ========================================================================
     // Internal Helpers
     // ========================================================================
 
-    async fn increment_collection_count(&self, name: &str) -> Result<()> {
-        let key = format!("collection:{}", name);
+    fn collection_indexes(&self, collection: &str) -> Arc<CollectionIndexes> {
+        self.indexes
+            .entry(collection.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Indexes of a collection, including ones being backfilled
+    fn index_snapshot(&self, collection: &str) -> Vec<Arc<SecondaryIndex>> {
+        self.indexes
+            .get(collection)
+            .map(|registry| registry.indexes.read().clone())
+            .unwrap_or_default()
+    }
+
+    fn all_indexes(&self) -> Vec<Arc<SecondaryIndex>> {
+        self.indexes
+            .iter()
+            .flat_map(|registry| registry.indexes.read().clone())
+            .collect()
+    }
+
+    fn read_document(lsm: &LsmTree, id: &str) -> Result<Option<Document>> {
+        lsm.get_at(id.as_bytes(), LATEST)?
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Move a document's index entries from its old version to its new one
+    fn update_indexes(
+        indexes: &[Arc<SecondaryIndex>],
+        old: Option<&Document>,
+        new: Option<&Document>,
+    ) -> Result<()> {
+        for index in indexes {
+            if let Some(old) = old {
+                if new.map(|new| index.entry_key(new)) != Some(index.entry_key(old)) {
+                    index.remove(old)?;
+                }
+            }
+            if let Some(new) = new {
+                index.insert(new)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if `doc` has the same values as another document in a unique
+    /// index
+    ///
+    /// Documents for which `rewritten` holds are being replaced by the same
+    /// commit and not checked.
+    fn check_unique(
+        collection: &str,
+        lsm: &LsmTree,
+        indexes: &[Arc<SecondaryIndex>],
+        doc: &Document,
+        rewritten: impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        for index in indexes {
+            for (key, id) in index.conflicts(doc)? {
+                if rewritten(&id) {
+                    continue;
+                }
+                let Some(other) = Self::read_document(lsm, &id)? else {
+                    continue;
+                };
+                if index.entry_key(&other).as_ref() == Some(&key) {
+                    return Err(Self::duplicate_key(index, collection, &id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if two documents written by one commit have the same values in
+    /// a unique index
+    fn check_unique_within(target: &CollectionWrites) -> Result<()> {
+        for index in &target.indexes {
+            let mut seen = HashSet::new();
+            for write in &target.writes {
+                let values = write
+                    .document
+                    .as_ref()
+                    .and_then(|doc| index.unique_values(doc));
+                if let Some(values) = values {
+                    if !seen.insert(values) {
+                        return Err(Self::duplicate_key(index, &target.collection, &write.id));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn duplicate_key(
+        index: &SecondaryIndex,
+        collection: &str,
+        id: &str,
+    ) -> lumadb_common::error::Error {
+        lumadb_common::error::Error::Storage(StorageError::WriteFailed(format!(
+            "Duplicate key in unique index {} on {}: document {} has the same values",
+            index.name(),
+            collection,
+            id
+        )))
+    }
+
+    /// Record a collection's index set in its metadata
+    fn save_index_metadata(&self, collection: &str, registry: &CollectionIndexes) -> Result<()> {
+        let key = format!("collection:{collection}");
         if let Ok(Some(value)) = self.db.get(&key) {
             let mut metadata: CollectionMetadata = serde_json::from_slice(&value)?;
-            metadata.count += 1;
-            let new_value = serde_json::to_vec(&metadata)?;
-            self.db.insert(&key, new_value).map_err(|e| {
+            metadata.indexes = registry
+                .indexes
+                .read()
+                .iter()
+                .map(|i| i.metadata())
+                .collect();
+            self.put_collection_metadata(&metadata)?;
+        }
+        Ok(())
+    }
+
+    fn save_payload_indexes(&self, collection: &str, index: &VectorIndex) -> Result<()> {
+        let key = format!("collection:{collection}");
+        if let Ok(Some(value)) = self.db.get(&key) {
+            let mut metadata: CollectionMetadata = serde_json::from_slice(&value)?;
+            metadata.payload_indexes = index
+                .payload_index()
+                .fields()
+                .into_iter()
+                .map(|(spec, _)| spec)
+                .collect();
+            self.put_collection_metadata(&metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Record the current index statistics in collection metadata
+    async fn persist_index_statistics(&self) -> Result<()> {
+        let registries: Vec<(String, Arc<CollectionIndexes>)> = self
+            .indexes
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        for (collection, registry) in registries {
+            let _registration = registry.registration.write().await;
+            if !registry.indexes.read().is_empty() {
+                self.save_index_metadata(&collection, &registry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close and delete every index of a collection
+    fn remove_indexes(&self, collection: &str) -> Result<()> {
+        if let Some((_, registry)) = self.indexes.remove(collection) {
+            for index in registry.indexes.write().drain(..) {
+                index.close();
+            }
+        }
+        let path = Path::new(&self.config.path)
+            .join("indexes")
+            .join(collection);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Record the last commit timestamp, which later runs start above
+    fn persist_clock(&self) -> Result<()> {
+        self.db
+            .insert(CLOCK_KEY, &self.clock.last().to_be_bytes())
+            .map_err(|e| {
+                lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
+            })?;
+        Ok(())
+    }
+
+    fn put_collection_metadata(&self, metadata: &CollectionMetadata) -> Result<()> {
+        let value = serde_json::to_vec(metadata)?;
+        self.db
+            .insert(format!("collection:{}", metadata.name), value)
+            .map_err(|e| {
                 lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
             })?;
-        }
         Ok(())
     }
 
-    async fn decrement_collection_count(&self, name: &str) -> Result<()> {
+    fn adjust_collection_count(&self, name: &str, delta: isize) -> Result<()> {
         let key = format!("collection:{}", name);
         if let Ok(Some(value)) = self.db.get(&key) {
             let mut metadata: CollectionMetadata = serde_json::from_slice(&value)?;
-            metadata.count = metadata.count.saturating_sub(1);
+            metadata.count = metadata.count.saturating_add_signed(delta);
             let new_value = serde_json::to_vec(&metadata)?;
             self.db.insert(&key, new_value).map_err(|e| {
                 lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
@@ -456,11 +2518,36 @@ impl StorageEngine {
         info!("Shutting down storage engine...");
         *self.running.write() = false;
 
+        // Flush memtables to SSTables and truncate the WAL
+        if self.wal.is_some() {
+            self.checkpoint().await?;
+        } else {
+            for entry in &self.lsm_trees {
+                entry.value().flush()?;
+            }
+            for index in self.all_indexes() {
+                index.flush()?;
+            }
+            for index in self.all_vector_indexes() {
+                index.snapshot(false)?;
+            }
+            self.persist_index_statistics().await?;
+            self.persist_clock()?;
+        }
+
         // Flush WAL
         if let Some(ref wal) = self.wal {
             wal.sync().await?;
         }
 
+        // Stop background workers
+        for entry in &self.lsm_trees {
+            entry.value().close();
+        }
+        for index in self.all_indexes() {
+            index.close();
+        }
+
         // Flush database
         self.db.flush().map_err(|e| {
             lumadb_common::error::Error::Storage(StorageError::WriteFailed(e.to_string()))
@@ -469,4 +2556,704 @@ impl StorageEngine {
         info!("Storage engine shutdown complete");
         Ok(())
     }
+
+    /// Drop the engine and wait for sled to release the metadata database
+    ///
+    /// sled finishes some writes and file truncations on threads of its own,
+    /// which keep the database file, and so its lock, open for a moment after
+    /// the last handle is dropped. Once this returns, the directory can be
+    /// opened again straight away. Without a [`StorageEngine::shutdown`]
+    /// first, the engine stops as it would on a crash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata database is still locked after a
+    /// second.
+    pub async fn close(self) -> Result<()> {
+        let path = Path::new(&self.config.path).join("metadata").join("db");
+        drop(self);
+        tokio::task::spawn_blocking(move || wait_for_unlock(&path))
+            .await
+            .map_err(|e| lumadb_common::error::Error::Internal(e.to_string()))?
+    }
+}
+
+/// Wait for the lock on sled's database file at `path` to be released
+fn wait_for_unlock(path: &Path) -> Result<()> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Ok(());
+    };
+    for _ in 0..METADATA_LOCK_POLLS {
+        if file.try_lock_exclusive().is_ok() {
+            let _ = FileExt::unlock(&file);
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let message = format!("metadata database {} is still locked", path.display());
+    Err(StorageError::WriteFailed(message).into())
+}
+
+/// Whether a name stays within the directory it is joined to
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::ChangeKind;
+
+    fn test_config(dir: &Path) -> StorageConfig {
+        StorageConfig {
+            path: dir.to_string_lossy().to_string(),
+            max_memory_bytes: 64 * 1024 * 1024,
+            ..StorageConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wal_replay_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+
+        {
+            let engine = StorageEngine::new(&config).await.unwrap();
+            engine.create_collection("users").await.unwrap();
+            for i in 0..10 {
+                let doc = Document::with_id(format!("u{i}"), serde_json::json!({ "n": i }));
+                engine.insert_document("users", &doc).await.unwrap();
+            }
+            engine.delete_document("users", "u3").await.unwrap();
+            // Closed without shutdown: memtables are lost, only the WAL survives
+            engine.close().await.unwrap();
+        }
+
+        // Simulate a torn write at the tail of the last segment
+        let wal_dir = dir.path().join("wal");
+        let mut segments: Vec<_> = std::fs::read_dir(&wal_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "wal"))
+            .filter(|p| std::fs::metadata(p).unwrap().len() > 0)
+            .collect();
+        segments.sort();
+        let last = segments.last().unwrap();
+        let mut data = std::fs::read(last).unwrap();
+        data.extend_from_slice(&[42, 0, 0, 0, 1, 2, 3, 4, 9]);
+        std::fs::write(last, data).unwrap();
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert!(engine.get_document("users", "u0").await.unwrap().is_some());
+        assert!(engine.get_document("users", "u3").await.unwrap().is_none());
+        assert_eq!(engine.count_documents("users").await.unwrap(), 9);
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+
+        // A clean shutdown checkpoints, leaving nothing to replay
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert_eq!(engine.count_documents("users").await.unwrap(), 9);
+        assert!(engine.get_document("users", "u9").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_secondary_index_backfill_and_replay() {
+        use lumadb_common::types::IndexType;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let by_team = |range: IndexRange, engine: &StorageEngine| -> Vec<String> {
+            engine
+                .index_scan("users", "by_team", &range)
+                .unwrap()
+                .map(|doc| doc.unwrap().id)
+                .collect()
+        };
+        let red = || IndexRange::equal(vec![serde_json::json!("red")]);
+
+        {
+            let engine = StorageEngine::new(&config).await.unwrap();
+            engine.create_collection("users").await.unwrap();
+            for i in 0..6 {
+                let team = if i % 2 == 0 { "red" } else { "blue" };
+                let doc = Document::with_id(format!("u{i}"), serde_json::json!({ "team": team }));
+                engine.insert_document("users", &doc).await.unwrap();
+            }
+
+            let metadata = IndexMetadata {
+                name: "by_team".to_string(),
+                index_type: IndexType::BTree,
+                fields: vec!["team".to_string()],
+                unique: false,
+                filter: vec![],
+                building: false,
+                entries: 0,
+                distinct_keys: 0,
+            };
+            engine.create_index("users", metadata).await.unwrap();
+            assert_eq!(by_team(red(), &engine), ["u0", "u2", "u4"]);
+
+            // Changes after the backfill are only in the WAL when the
+            // engine is closed without shutdown
+            let moved = Document::with_id("u2", serde_json::json!({ "team": "blue" }));
+            engine.insert_document("users", &moved).await.unwrap();
+            engine.delete_document("users", "u4").await.unwrap();
+            let joined = Document::with_id("u9", serde_json::json!({ "team": "red" }));
+            engine.insert_document("users", &joined).await.unwrap();
+            engine.close().await.unwrap();
+        }
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert_eq!(by_team(red(), &engine), ["u0", "u9"]);
+        let statistics = engine.collection_statistics("users").unwrap().unwrap();
+        assert_eq!(statistics.indexes[0].entries, 6);
+        assert_eq!(statistics.indexes[0].distinct_keys, 2);
+
+        engine.drop_index("users", "by_team").await.unwrap();
+        assert!(engine.index_scan("users", "by_team", &red()).is_err());
+        assert!(!dir
+            .path()
+            .join("indexes")
+            .join("users")
+            .join("by_team")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_encryption_at_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config(dir.path());
+        config.encryption.enabled = true;
+        config.encryption.key_file = dir.path().join("master.keys").to_string_lossy().to_string();
+        let marker = b"plaintext-marker";
+        let leaks = |sub: &str| -> bool {
+            let mut pending = vec![dir.path().join(sub)];
+            while let Some(path) = pending.pop() {
+                if path.is_dir() {
+                    pending.extend(std::fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+                } else {
+                    let data = std::fs::read(&path).unwrap();
+                    if data.windows(marker.len()).any(|w| w == marker) {
+                        return true;
+                    }
+                }
+            }
+            false
+        };
+
+        {
+            let engine = StorageEngine::new(&config).await.unwrap();
+            engine.create_collection("secrets").await.unwrap();
+            for i in 0..10 {
+                let doc = Document::with_id(
+                    format!("s{i}"),
+                    serde_json::json!({
+                        "value": "plaintext-marker"
+                    }),
+                );
+                engine.insert_document("secrets", &doc).await.unwrap();
+            }
+            // Closed without shutdown: the documents are only in the WAL
+            engine.close().await.unwrap();
+        }
+        assert!(!leaks("wal"));
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert_eq!(engine.count_documents("secrets").await.unwrap(), 10);
+        assert_eq!(engine.rotate_collection_key("secrets").await.unwrap(), 2);
+        assert_eq!(engine.rotate_wal_key().await.unwrap(), 2);
+        assert!(engine.rotate_collection_key("missing").await.is_err());
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+        assert!(!leaks("wal"));
+        assert!(!leaks("collections"));
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        let doc = engine.get_document("secrets", "s7").await.unwrap().unwrap();
+        assert_eq!(doc.data["value"], "plaintext-marker");
+        let keys = engine.encryption().unwrap().keys();
+        assert!(keys.iter().all(|info| info.active_version == 2));
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+
+        // A different master key cannot unwrap the data keys
+        std::fs::remove_file(&config.encryption.key_file).unwrap();
+        assert!(StorageEngine::new(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vector_collection_survives_restart() {
+        use lumadb_common::types::DistanceMetric;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let settings = VectorIndexConfig::new(2)
+            .with_metric(DistanceMetric::Euclidean)
+            .with_hnsw(8, 50, 20);
+
+        {
+            let engine = StorageEngine::new(&config).await.unwrap();
+            engine
+                .create_vector_collection_with_config("points", settings.clone())
+                .await
+                .unwrap();
+            for i in 0..20u8 {
+                let doc = Document::with_id(format!("p{i}"), serde_json::json!({ "i": i }));
+                engine
+                    .insert_vector_document("points", &doc, &[f32::from(i), 0.0])
+                    .await
+                    .unwrap();
+            }
+            let wrong = Document::with_id("x", serde_json::json!({}));
+            assert!(engine
+                .insert_vector_document("points", &wrong, &[1.0])
+                .await
+                .is_err());
+            assert!(engine.delete_document("points", "p3").await.unwrap());
+            assert!(engine.create_vector_collection("points", 2).await.is_err());
+            // Closed without shutdown: the graph is only in the vector log
+            engine.close().await.unwrap();
+        }
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        let info = engine.get_collection_info("points").await.unwrap().unwrap();
+        assert_eq!(info.vector_config.as_ref(), Some(&settings));
+        let index = engine.vector_index("points").unwrap();
+        assert_eq!(index.len(), 19);
+
+        let hits = engine
+            .vector_search("points", &[3.1, 0.0], 2)
+            .await
+            .unwrap();
+        let ids: Vec<_> = hits.iter().map(|hit| hit.doc_id.as_str()).collect();
+        assert_eq!(ids, ["p4", "p2"]);
+        assert_eq!(hits[0].payload["i"], 4);
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+
+        // A clean shutdown snapshots the graph
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert_eq!(engine.vector_index("points").unwrap().len(), 19);
+        engine.delete_collection("points").await.unwrap();
+        assert!(engine.vector_index("points").is_none());
+        assert!(!dir.path().join("vectors").join("points").exists());
+    }
+
+    /// Create a vector collection of 200 points on a line, with indexed
+    /// `color` and `n` payload fields
+    async fn create_indexed_points(config: &StorageConfig) {
+        use lumadb_common::types::{DistanceMetric, PayloadFieldType};
+
+        let colors = ["red", "green", "blue", "black"];
+        let mut settings = VectorIndexConfig::new(2).with_metric(DistanceMetric::Euclidean);
+        settings.full_scan_threshold = 20;
+
+        let engine = StorageEngine::new(config).await.unwrap();
+        engine
+            .create_vector_collection_with_config("points", settings)
+            .await
+            .unwrap();
+        for i in 0..200u16 {
+            let data = serde_json::json!({ "color": colors[usize::from(i % 4)], "n": i });
+            let doc = Document::with_id(format!("p{i:03}"), data);
+            engine
+                .insert_vector_document("points", &doc, &[f32::from(i), 0.0])
+                .await
+                .unwrap();
+        }
+        let keyword = PayloadIndexSpec {
+            field: "color".to_string(),
+            field_type: PayloadFieldType::Keyword,
+        };
+        assert!(engine
+            .create_payload_index("points", keyword.clone())
+            .await
+            .unwrap());
+        assert!(!engine
+            .create_payload_index("points", keyword)
+            .await
+            .unwrap());
+        let integer = PayloadIndexSpec {
+            field: "n".to_string(),
+            field_type: PayloadFieldType::Integer,
+        };
+        assert!(engine
+            .create_payload_index("points", integer)
+            .await
+            .unwrap());
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_filtered_vector_search() {
+        use crate::vector::NumberRange;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        create_indexed_points(&config).await;
+
+        // Payload indexes are rebuilt on restart
+        let engine = StorageEngine::new(&config).await.unwrap();
+        let indexes = engine.payload_indexes("points");
+        assert_eq!(indexes.len(), 2);
+        assert!(indexes.iter().all(|(_, points)| *points == 200));
+
+        let search = |filter: PayloadFilter| {
+            let engine = &engine;
+            async move {
+                let options = SearchOptions::default();
+                engine
+                    .vector_search_filtered("points", &[100.2, 0.0], 3, Some(&filter), options)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|hit| hit.doc_id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let red = PayloadFilter::Match {
+            key: "color".to_string(),
+            values: vec![serde_json::json!("red")],
+        };
+        // Matches 50 points, so the graph is traversed
+        assert_eq!(search(red.clone()).await, ["p100", "p104", "p096"]);
+        // Matches 5 points, so those are compared directly
+        let low = PayloadFilter::Range {
+            key: "n".to_string(),
+            range: NumberRange {
+                lt: Some(20.0),
+                ..NumberRange::default()
+            },
+        };
+        assert_eq!(
+            search(PayloadFilter::all(vec![red.clone(), low.clone()])).await,
+            ["p016", "p012", "p008"]
+        );
+        // Unindexed conditions are checked on the documents
+        let not_red = PayloadFilter::negate(PayloadFilter::Text {
+            key: "color".to_string(),
+            text: "re".to_string(),
+        });
+        assert_eq!(search(not_red).await, ["p099", "p102", "p098"]);
+
+        let page = engine
+            .filter_documents("points", Some(&red), Some("p010"), Some(2))
+            .unwrap();
+        let ids: Vec<_> = page.iter().map(|doc| doc.id.as_str()).collect();
+        assert_eq!(ids, ["p012", "p016"]);
+        let all_red = engine
+            .filter_documents("points", Some(&red), None, None)
+            .unwrap();
+        assert_eq!(all_red.len(), 50);
+
+        assert_eq!(
+            engine
+                .delete_documents_matching("points", &low)
+                .await
+                .unwrap(),
+            20
+        );
+        assert_eq!(engine.count_documents("points").await.unwrap(), 180);
+        assert!(!engine.vector_index("points").unwrap().contains("p016"));
+        assert_eq!(
+            search(PayloadFilter::all(vec![red, low])).await,
+            Vec::<String>::new()
+        );
+        engine.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        use crate::hybrid::Fusion;
+        use lumadb_common::types::DistanceMetric;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let titles = [
+            "rust storage engine",
+            "vector search in rust",
+            "vector search with bm25 ranking",
+            "cooking recipes",
+        ];
+
+        {
+            let engine = StorageEngine::new(&config).await.unwrap();
+            let settings = VectorIndexConfig::new(2).with_metric(DistanceMetric::Euclidean);
+            engine
+                .create_vector_collection_with_config("docs", settings)
+                .await
+                .unwrap();
+            for (i, title) in (0u8..).zip(titles) {
+                if i == 2 {
+                    // Documents written before and after the index is made
+                    assert!(engine.create_text_index("docs", "title").await.unwrap());
+                    assert!(!engine.create_text_index("docs", "title").await.unwrap());
+                }
+                let data = serde_json::json!({ "title": title, "tags": [format!("t{}", i % 2)] });
+                let doc = Document::with_id(format!("d{i}"), data);
+                engine
+                    .insert_vector_document("docs", &doc, &[f32::from(i) * 3.0, 0.0])
+                    .await
+                    .unwrap();
+            }
+            engine.shutdown().await.unwrap();
+            engine.close().await.unwrap();
+        }
+
+        // Full-text indexes are rebuilt on restart
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert_eq!(engine.text_fields("docs"), ["title"]);
+        let hits = engine
+            .text_search("docs", "vector search", 10, None)
+            .unwrap();
+        let mut ids: Vec<_> = hits.iter().map(|(id, _)| id.as_str()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, ["d1", "d2"]);
+
+        // d1 is near the vector and matches the text, so it leads
+        let query = HybridQuery::new(3)
+            .with_vector(vec![2.0, 0.0])
+            .with_text("vector search");
+        let results = engine.hybrid_search("docs", &query).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].doc_id, "d1");
+        assert_eq!(results[0].vector.unwrap().rank, 1);
+        assert!(results[0].text.is_some());
+        assert_eq!(results[0].payload["title"], titles[1]);
+        let near = results.iter().find(|hit| hit.doc_id == "d0").unwrap();
+        assert!(near.text.is_none());
+
+        // Weighting the text alone puts the best BM25 match first
+        let query = query.with_fusion(Fusion::Weighted).with_weights(0.0, 1.0);
+        let results = engine.hybrid_search("docs", &query).await.unwrap();
+        assert_eq!(results[0].doc_id, hits[0].0);
+        assert_eq!(results[0].score, 1.0);
+        assert_eq!(results[0].vector.unwrap().contribution, 0.0);
+
+        // Filters apply to both rankings
+        let even = PayloadFilter::Match {
+            key: "tags".to_string(),
+            values: vec![serde_json::json!("t0")],
+        };
+        let query = HybridQuery::new(5)
+            .with_text("vector search")
+            .with_filter(even);
+        let results = engine.hybrid_search("docs", &query).await.unwrap();
+        let ids: Vec<_> = results.iter().map(|hit| hit.doc_id.as_str()).collect();
+        assert_eq!(ids, ["d2"]);
+
+        // Writes reach the index
+        let doc = Document::with_id("d3", serde_json::json!({ "title": "vector soup" }));
+        engine.insert_document("docs", &doc).await.unwrap();
+        engine.delete_document("docs", "d1").await.unwrap();
+        let hits = engine.text_search("docs", "vector", 10, None).unwrap();
+        let mut ids: Vec<_> = hits.iter().map(|(id, _)| id.as_str()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, ["d2", "d3"]);
+
+        assert!(engine
+            .hybrid_search("docs", &HybridQuery::new(5))
+            .await
+            .is_err());
+        assert!(engine.drop_text_index("docs", "title").await.unwrap());
+        assert!(engine.text_search("docs", "vector", 10, None).is_err());
+        engine.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restores_on_fresh_node() {
+        use lumadb_common::types::{DistanceMetric, IndexType, PayloadFieldType};
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = StorageEngine::new(&test_config(&dir.path().join("a")))
+            .await
+            .unwrap();
+        let settings = VectorIndexConfig::new(2).with_metric(DistanceMetric::Euclidean);
+        engine
+            .create_vector_collection_with_config("docs", settings.clone())
+            .await
+            .unwrap();
+        for i in 0..5u8 {
+            let data = serde_json::json!({ "title": format!("note {i}"), "team": i % 2 });
+            let doc = Document::with_id(format!("d{i}"), data);
+            engine
+                .insert_vector_document("docs", &doc, &[f32::from(i), 0.0])
+                .await
+                .unwrap();
+        }
+        let index = IndexMetadata {
+            name: "by_team".to_string(),
+            index_type: IndexType::BTree,
+            fields: vec!["team".to_string()],
+            unique: false,
+            filter: vec![],
+            building: false,
+            entries: 0,
+            distinct_keys: 0,
+        };
+        engine.create_index("docs", index).await.unwrap();
+        let spec = PayloadIndexSpec {
+            field: "team".to_string(),
+            field_type: PayloadFieldType::Integer,
+        };
+        engine.create_payload_index("docs", spec).await.unwrap();
+        engine.create_text_index("docs", "title").await.unwrap();
+
+        let snapshot = engine.create_snapshot("docs").await.unwrap();
+        assert!(snapshot.name.starts_with("docs-"));
+        assert_eq!(
+            engine.list_snapshots("docs").unwrap(),
+            std::slice::from_ref(&snapshot)
+        );
+        let path = engine.snapshot_path("docs", &snapshot.name).unwrap();
+        let checksum = snapshot.checksum.clone().unwrap();
+        assert_eq!(snapshot::file_checksum(&path).unwrap(), checksum);
+        assert!(engine.snapshot_path("docs", "../docs.snapshot").is_none());
+
+        // A fresh node rebuilds the collection, vectors and indexes
+        let fresh = StorageEngine::new(&test_config(&dir.path().join("b")))
+            .await
+            .unwrap();
+        let restored = fresh.restore_snapshot("docs", &path, Some(&checksum)).await;
+        assert_eq!(restored.unwrap(), 5);
+        let metadata = fresh.get_collection("docs").await.unwrap().unwrap();
+        assert_eq!(metadata.count, 5);
+        assert_eq!(metadata.vector, Some(settings));
+        let doc = fresh.get_document("docs", "d3").await.unwrap().unwrap();
+        assert_eq!(doc.data["title"], "note 3");
+        let results = fresh.vector_search("docs", &[3.2, 0.0], 2).await.unwrap();
+        let ids: Vec<_> = results.iter().map(|r| r.doc_id.as_str()).collect();
+        assert_eq!(ids, ["d3", "d4"]);
+        let statistics = fresh.collection_statistics("docs").unwrap().unwrap();
+        assert_eq!(statistics.indexes[0].name, "by_team");
+        assert_eq!(statistics.indexes[0].entries, 5);
+        assert_eq!(fresh.payload_indexes("docs")[0].1, 5);
+        let hits = fresh.text_search("docs", "note", 10, None).unwrap();
+        assert_eq!(hits.len(), 5);
+
+        // Restoring replaces what the collection holds
+        let doc = Document::with_id("d9", serde_json::json!({ "title": "later" }));
+        fresh
+            .insert_vector_document("docs", &doc, &[9.0, 0.0])
+            .await
+            .unwrap();
+        fresh.restore_snapshot("docs", &path, None).await.unwrap();
+        assert!(fresh.get_document("docs", "d9").await.unwrap().is_none());
+        assert_eq!(fresh.count_documents("docs").await.unwrap(), 5);
+
+        // Damaged or mismatched snapshots leave the collection alone
+        assert!(fresh
+            .restore_snapshot("docs", &path, Some("00"))
+            .await
+            .is_err());
+        let data = std::fs::read(&path).unwrap();
+        let truncated = dir.path().join("truncated.snapshot");
+        std::fs::write(&truncated, &data[..data.len() - 10]).unwrap();
+        assert!(fresh
+            .restore_snapshot("docs", &truncated, None)
+            .await
+            .is_err());
+        let mut flipped = data.clone();
+        flipped[40] ^= 0xff;
+        std::fs::write(&truncated, &flipped).unwrap();
+        assert!(fresh
+            .restore_snapshot("docs", &truncated, None)
+            .await
+            .is_err());
+        assert_eq!(fresh.count_documents("docs").await.unwrap(), 5);
+
+        assert!(engine.delete_snapshot("docs", &snapshot.name).unwrap());
+        assert!(engine.list_snapshots("docs").unwrap().is_empty());
+        assert!(!engine.delete_snapshot("docs", &snapshot.name).unwrap());
+        engine.shutdown().await.unwrap();
+        fresh.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commits_publish_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = StorageEngine::new(&test_config(dir.path())).await.unwrap();
+        engine.create_collection("users").await.unwrap();
+        let mut changes = engine.changes().subscribe(None).unwrap();
+
+        let doc = |age| Document::with_id("u1", serde_json::json!({ "age": age }));
+        engine.insert_document("users", &doc(30)).await.unwrap();
+        engine.insert_document("users", &doc(31)).await.unwrap();
+        engine.delete_document("users", "u1").await.unwrap();
+        // Deleting a missing document changes nothing
+        engine.delete_document("users", "u1").await.unwrap();
+
+        let insert = changes.next().await.unwrap();
+        assert_eq!(
+            (insert.kind, insert.id.as_str()),
+            (ChangeKind::Insert, "u1")
+        );
+        let update = changes.next().await.unwrap();
+        assert_eq!(update.kind, ChangeKind::Update);
+        assert_eq!(
+            update.before.as_ref().unwrap().get("age"),
+            Some(&serde_json::json!(30))
+        );
+        assert_eq!(
+            update.after.as_ref().unwrap().get("age"),
+            Some(&serde_json::json!(31))
+        );
+        let delete = changes.next().await.unwrap();
+        assert_eq!(delete.kind, ChangeKind::Delete);
+        assert!(delete.after.is_none());
+        assert_eq!(changes.position(), engine.changes().position());
+    }
+
+    #[tokio::test]
+    async fn test_change_positions_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let engine = StorageEngine::new(&config).await.unwrap();
+        engine.create_collection("users").await.unwrap();
+        let mut changes = engine.changes().subscribe(None).unwrap();
+
+        let doc = |age| Document::with_id("u1", serde_json::json!({ "age": age }));
+        engine.insert_document("users", &doc(30)).await.unwrap();
+        engine.insert_document("users", &doc(31)).await.unwrap();
+        changes.next().await.unwrap();
+        let token = changes.position().to_string();
+        drop(changes);
+        engine.shutdown().await.unwrap();
+        engine.close().await.unwrap();
+
+        let engine = StorageEngine::new(&config).await.unwrap();
+        let mut resumed = engine
+            .changes()
+            .subscribe(Some(token.parse().unwrap()))
+            .unwrap();
+        let update = resumed.next().await.unwrap();
+        assert_eq!(update.kind, ChangeKind::Update);
+        assert_eq!(
+            update.after.as_ref().unwrap().get("age"),
+            Some(&serde_json::json!(31))
+        );
+
+        // Changes after the restart follow on from those before it
+        engine.delete_document("users", "u1").await.unwrap();
+        assert_eq!(resumed.next().await.unwrap().kind, ChangeKind::Delete);
+    }
+
+    #[tokio::test]
+    async fn test_closed_engine_releases_its_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let engine = StorageEngine::new(&config).await.unwrap();
+        engine.create_collection("users").await.unwrap();
+        let mut changes = engine.changes().subscribe(None).unwrap();
+        let doc = Document::with_id("u1", serde_json::json!({ "age": 30 }));
+        engine.insert_document("users", &doc).await.unwrap();
+        engine.close().await.unwrap();
+
+        // A subscription left open neither keeps the database locked nor
+        // outlives the engine
+        let engine = StorageEngine::new(&config).await.unwrap();
+        assert!(changes.next().await.is_err());
+        assert!(engine.get_document("users", "u1").await.unwrap().is_some());
+    }
 }
@@ -1,17 +1,25 @@
 //! Full-text search using Tantivy
+//!
+//! A collection's full-text index holds the text of the document fields it
+//! covers (dotted paths, arrays flattened as in payload filters), one entry
+//! per document, and ranks matches with BM25. Writes are buffered and
+//! committed by the next search, so a search sees every write made before
+//! it.
 
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use parking_lot::RwLock;
 use tantivy::collector::TopDocs;
 use tantivy::query::QueryParser;
-use tantivy::schema::{Schema, STORED, TEXT, Field, Value};
-use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument};
+use tantivy::schema::{Schema, STORED, STRING, TEXT, Field, Value};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
 use tracing::info;
 
 use lumadb_common::error::{Result, Error};
 
+use crate::vector::field_values;
+
 /// Full-text search index
 pub struct FullTextIndex {
     /// Tantivy index
@@ -26,16 +34,29 @@ pub struct FullTextIndex {
     id_field: Field,
     /// Content field
     content_field: Field,
+    /// Document fields whose text is indexed
+    fields: Vec<String>,
+    /// Whether writes are waiting to be committed
+    pending: AtomicBool,
 }
 
 impl FullTextIndex {
     /// Create a new full-text index
     pub fn new(path: &Path) -> Result<Self> {
+        Self::with_fields(path, Vec::new())
+    }
+
+    /// Create a full-text index over the text of document `fields`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index directory cannot be created or opened.
+    pub fn with_fields(path: &Path, fields: Vec<String>) -> Result<Self> {
         info!("Creating full-text index at {:?}", path);
 
         // Create schema
         let mut schema_builder = Schema::builder();
-        let id_field = schema_builder.add_text_field("id", STORED);
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
         let content_field = schema_builder.add_text_field("content", TEXT | STORED);
         let schema = schema_builder.build();
 
@@ -63,37 +84,78 @@ impl FullTextIndex {
             schema,
             id_field,
             content_field,
+            fields,
+            pending: AtomicBool::new(false),
         })
     }
 
+    /// Document fields whose text is indexed
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
     /// Index a document
     pub fn index_document(&self, id: &str, content: &str) -> Result<()> {
-        let mut writer = self.writer.write();
+        let writer = self.writer.write();
 
         writer.add_document(doc!(
             self.id_field => id,
             self.content_field => content
         )).map_err(|e| Error::Internal(format!("Failed to index document: {}", e)))?;
+        self.pending.store(true, Ordering::Release);
+
+        Ok(())
+    }
 
+    /// Index the text of a document's fields, replacing what was indexed
+    /// for its ID; `None` removes the document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be written.
+    pub fn update(&self, id: &str, data: Option<&serde_json::Value>) -> Result<()> {
+        let text = data.map(|data| self.text_of(data)).unwrap_or_default();
+
+        // Held across both steps, so concurrent updates of a document
+        // cannot leave two entries behind
+        let writer = self.writer.write();
+        writer.delete_term(Term::from_field_text(self.id_field, id));
+        if !text.is_empty() {
+            writer
+                .add_document(doc!(
+                    self.id_field => id,
+                    self.content_field => text
+                ))
+                .map_err(|e| Error::Internal(format!("Failed to index document: {e}")))?;
+        }
+        self.pending.store(true, Ordering::Release);
         Ok(())
     }
 
     /// Commit pending changes
     pub fn commit(&self) -> Result<()> {
         let mut writer = self.writer.write();
+        self.pending.store(false, Ordering::Release);
         writer.commit()
             .map_err(|e| Error::Internal(format!("Failed to commit: {}", e)))?;
+        self.reader
+            .reload()
+            .map_err(|e| Error::Internal(format!("Failed to reload reader: {e}")))?;
         Ok(())
     }
 
-    /// Search for documents
+    /// Search for documents, best BM25 score first
+    ///
+    /// The query is parsed leniently: what does not parse as query syntax
+    /// is searched for as plain terms.
     pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        if self.pending.load(Ordering::Acquire) {
+            self.commit()?;
+        }
         let searcher = self.reader.searcher();
 
         let query_parser = QueryParser::for_index(&self.index, vec![self.content_field]);
-        let query = query_parser
-            .parse_query(query)
-            .map_err(|e| Error::Internal(format!("Failed to parse query: {}", e)))?;
+        let (query, _) = query_parser.parse_query_lenient(query);
 
         let top_docs = searcher
             .search(&query, &TopDocs::with_limit(limit))
@@ -124,11 +186,22 @@ impl FullTextIndex {
 
     /// Delete a document by ID
     pub fn delete(&self, id: &str) -> Result<()> {
-        let mut writer = self.writer.write();
-        let term = tantivy::Term::from_field_text(self.id_field, id);
+        let writer = self.writer.write();
+        let term = Term::from_field_text(self.id_field, id);
         writer.delete_term(term);
+        self.pending.store(true, Ordering::Release);
         Ok(())
     }
+
+    /// The indexed fields' string values, one per line
+    fn text_of(&self, data: &serde_json::Value) -> String {
+        self.fields
+            .iter()
+            .flat_map(|field| field_values(data, field))
+            .filter_map(|value| value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 /// Full-text search result
@@ -0,0 +1,302 @@
+//! Hybrid search: merging vector and full-text rankings
+//!
+//! A hybrid search ranks a collection's documents twice, by vector
+//! similarity and by BM25 over its full-text index, and merges the two
+//! rankings into one:
+//!
+//! - reciprocal-rank fusion adds `weight / (RRF_K + rank)` for each ranking
+//!   a document appears in, so only positions matter
+//! - weighted fusion rescales each ranking's scores to [0, 1], best to
+//!   worst, and adds them times their weights
+//!
+//! Every hit records its rank, raw score and contribution in each ranking,
+//! so results can be explained.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vector::{PayloadFilter, SearchOptions};
+
+/// Rank offset of reciprocal-rank fusion, damping the lead of top ranks
+pub const RRF_K: f32 = 60.0;
+
+/// Candidates each ranking contributes per result wanted, unless a query
+/// says otherwise
+pub const PREFETCH_FACTOR: usize = 4;
+
+/// How a hybrid search merges its rankings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fusion {
+    /// Reciprocal-rank fusion
+    #[default]
+    Rrf,
+    /// Weighted sum of min-max normalized scores
+    Weighted,
+}
+
+/// A hybrid search
+#[derive(Debug, Clone)]
+pub struct HybridQuery {
+    /// Query vector, for the vector ranking
+    pub vector: Option<Vec<f32>>,
+    /// Query text, for the full-text ranking
+    pub text: Option<String>,
+    /// Results wanted
+    pub limit: usize,
+    /// How the rankings merge
+    pub fusion: Fusion,
+    /// Weight of the vector ranking
+    pub vector_weight: f32,
+    /// Weight of the full-text ranking
+    pub text_weight: f32,
+    /// Candidates each ranking contributes; `limit` times
+    /// [`PREFETCH_FACTOR`] if unset
+    pub prefetch: Option<usize>,
+    /// Condition every result must meet
+    pub filter: Option<PayloadFilter>,
+    /// How the vector search runs
+    pub options: SearchOptions,
+}
+
+impl HybridQuery {
+    /// A query for `limit` results, fused by reciprocal rank with equal
+    /// weights
+    #[must_use]
+    pub fn new(limit: usize) -> Self {
+        Self {
+            vector: None,
+            text: None,
+            limit,
+            fusion: Fusion::default(),
+            vector_weight: 1.0,
+            text_weight: 1.0,
+            prefetch: None,
+            filter: None,
+            options: SearchOptions::default(),
+        }
+    }
+
+    /// Rank by similarity to `vector`
+    #[must_use]
+    pub fn with_vector(mut self, vector: Vec<f32>) -> Self {
+        self.vector = Some(vector);
+        self
+    }
+
+    /// Rank by BM25 for `text`
+    #[must_use]
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Set how the rankings merge
+    #[must_use]
+    pub fn with_fusion(mut self, fusion: Fusion) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Set the weights of the vector and full-text rankings
+    #[must_use]
+    pub fn with_weights(mut self, vector: f32, text: f32) -> Self {
+        self.vector_weight = vector;
+        self.text_weight = text;
+        self
+    }
+
+    /// Set the candidates each ranking contributes
+    #[must_use]
+    pub fn with_prefetch(mut self, prefetch: usize) -> Self {
+        self.prefetch = Some(prefetch);
+        self
+    }
+
+    /// Only return documents passing `filter`
+    #[must_use]
+    pub fn with_filter(mut self, filter: PayloadFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Candidates each ranking contributes, at least `limit`
+    #[must_use]
+    pub fn prefetch_limit(&self) -> usize {
+        self.prefetch
+            .unwrap_or_else(|| self.limit.saturating_mul(PREFETCH_FACTOR))
+            .max(self.limit)
+    }
+}
+
+/// Where a hit stood in one ranking
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComponentScore {
+    /// Position in the ranking, from 1
+    pub rank: usize,
+    /// Score the ranking gave: the vector distance (lower is nearer) or
+    /// the BM25 score
+    pub score: f32,
+    /// What the ranking added to the fused score
+    pub contribution: f32,
+}
+
+/// Hybrid search result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HybridSearchResult {
+    /// Document ID
+    pub doc_id: String,
+    /// Fused score; higher is better
+    pub score: f32,
+    /// Standing in the vector ranking, if the document is in it
+    pub vector: Option<ComponentScore>,
+    /// Standing in the full-text ranking, if the document is in it
+    pub text: Option<ComponentScore>,
+    /// Document payload
+    pub payload: serde_json::Value,
+}
+
+/// The `query.limit` best documents of a vector ranking (distances,
+/// nearest first) and a full-text ranking (BM25 scores, best first), with
+/// null payloads
+///
+/// Only the limit, fusion and weights of `query` are used, so rankings run
+/// by the caller can be fused too.
+#[must_use]
+pub fn fuse(
+    query: &HybridQuery,
+    vector: &[(String, f32)],
+    text: &[(String, f32)],
+) -> Vec<HybridSearchResult> {
+    let mut fused: HashMap<&str, HybridSearchResult> = HashMap::new();
+    let rankings = [
+        (vector, query.vector_weight, false),
+        (text, query.text_weight, true),
+    ];
+    for (ranking, weight, higher_is_better) in rankings {
+        let contributions = contributions(query.fusion, weight, ranking, higher_is_better);
+        for (i, ((id, score), contribution)) in ranking.iter().zip(contributions).enumerate() {
+            let hit = fused.entry(id).or_insert_with(|| HybridSearchResult {
+                doc_id: id.clone(),
+                score: 0.0,
+                vector: None,
+                text: None,
+                payload: serde_json::Value::Null,
+            });
+            hit.score += contribution;
+            let component = Some(ComponentScore {
+                rank: i + 1,
+                score: *score,
+                contribution,
+            });
+            if higher_is_better {
+                hit.text = component;
+            } else {
+                hit.vector = component;
+            }
+        }
+    }
+
+    let mut hits: Vec<HybridSearchResult> = fused.into_values().collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.doc_id.cmp(&b.doc_id))
+    });
+    hits.truncate(query.limit);
+    hits
+}
+
+/// What each entry of a ranking adds to the fused score
+#[allow(clippy::cast_precision_loss)] // ranks are small
+fn contributions(
+    fusion: Fusion,
+    weight: f32,
+    ranking: &[(String, f32)],
+    higher_is_better: bool,
+) -> Vec<f32> {
+    match fusion {
+        Fusion::Rrf => (1..=ranking.len())
+            .map(|rank| weight / (RRF_K + rank as f32))
+            .collect(),
+        Fusion::Weighted => {
+            let (min, max) = ranking
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |acc, hit| {
+                    (acc.0.min(hit.1), acc.1.max(hit.1))
+                });
+            let span = max - min;
+            ranking
+                .iter()
+                .map(|&(_, score)| {
+                    // A ranking whose scores all tie counts each in full
+                    if span <= 0.0 || !span.is_finite() {
+                        return weight;
+                    }
+                    let normalized = if higher_is_better {
+                        (score - min) / span
+                    } else {
+                        (max - score) / span
+                    };
+                    weight * normalized
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(hits: &[(&str, f32)]) -> Vec<(String, f32)> {
+        hits.iter()
+            .map(|&(id, score)| (id.to_string(), score))
+            .collect()
+    }
+
+    fn ids(hits: &[HybridSearchResult]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.doc_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fusion() {
+        // Distances, nearest first, and BM25 scores, best first
+        let vector = ranking(&[("a", 0.1), ("b", 0.2), ("c", 0.5)]);
+        let text = ranking(&[("c", 9.0), ("d", 4.0), ("b", 1.0)]);
+
+        // Appearing in both rankings beats leading one
+        let rrf = fuse(&HybridQuery::new(10), &vector, &text);
+        assert_eq!(ids(&rrf), ["c", "b", "a", "d"]);
+        let b = &rrf[1];
+        assert_eq!(b.vector.unwrap().rank, 2);
+        assert_eq!(b.vector.unwrap().score, 0.2);
+        assert_eq!(b.text.unwrap().rank, 3);
+        assert!((b.score - (1.0 / 62.0 + 1.0 / 63.0)).abs() < 1e-6);
+        assert!(rrf[2].text.is_none());
+
+        // Normalized scores: the best of each ranking counts 1, the worst 0
+        let query = HybridQuery::new(2).with_fusion(Fusion::Weighted);
+        let weighted = fuse(&query, &vector, &text);
+        assert_eq!(ids(&weighted), ["a", "c"]);
+        assert_eq!(weighted[0].score, 1.0);
+        assert_eq!(weighted[1].vector.unwrap().contribution, 0.0);
+        assert_eq!(weighted[1].text.unwrap().contribution, 1.0);
+
+        // Weights shift the order
+        let query = query.with_weights(0.2, 1.0);
+        assert_eq!(ids(&fuse(&query, &vector, &text)), ["c", "d"]);
+
+        // A single ranking keeps its order
+        let only_text = fuse(&HybridQuery::new(10), &[], &text);
+        assert_eq!(ids(&only_text), ["c", "d", "b"]);
+        assert!(only_text.iter().all(|hit| hit.vector.is_none()));
+        let tied = fuse(
+            &HybridQuery::new(10).with_fusion(Fusion::Weighted),
+            &[],
+            &text[..1],
+        );
+        assert_eq!(tied[0].score, 1.0);
+    }
+}
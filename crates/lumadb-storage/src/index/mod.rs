@@ -0,0 +1,542 @@
+//! Secondary indexes over document fields
+//!
+//! Each index is an LSM tree whose keys are the indexed field values,
+//! encoded so that byte order matches value order, followed by the ID of
+//! the document they came from. Looking up a value is then a prefix scan
+//! and a range of values a bounded scan.
+//!
+//! Entries are maintained by the storage engine as documents change, so an
+//! entry can briefly outlive the document version that produced it (for
+//! example after a crash between the document and index flushes). Readers
+//! therefore check every entry against the current document.
+
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use lumadb_common::error::Result;
+use lumadb_common::types::{ConditionOp, Document, IndexCondition, IndexMetadata};
+
+use crate::cache::BufferPool;
+use crate::lsm::{LsmOptions, LsmTree, ScanIterator};
+
+const TAG_BOOL: u8 = 0x01;
+const TAG_NUMBER: u8 = 0x02;
+const TAG_STRING: u8 = 0x03;
+const TAG_ARRAY: u8 = 0x04;
+const TAG_OBJECT: u8 = 0x05;
+const TAG_NULL: u8 = 0x06;
+
+/// Cache size of each index tree; index entries are small and mostly read
+/// through scans, which bypass the cache
+const INDEX_CACHE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Value of a dotted field path in a document, if present
+#[must_use]
+pub fn field_value<'a>(doc: &'a Document, path: &str) -> Option<&'a Value> {
+    if path == "_id" {
+        return None;
+    }
+    path.split('.')
+        .try_fold(&doc.data, |value, part| match value {
+            Value::Object(map) => map.get(part),
+            Value::Array(items) => part.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+}
+
+/// Append `bytes` so that the result sorts like `bytes` and ends where it
+/// ends: zero bytes are escaped and the value is terminated by two zeros
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for &b in bytes {
+        out.push(b);
+        if b == 0 {
+            out.push(0xFF);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+/// Append the order-preserving encoding of a value
+///
+/// Values of different types sort by type (booleans, numbers, strings,
+/// arrays, objects, then NULL), like the query engine orders them. Numbers
+/// are compared as `f64`.
+pub fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Bool(b) => {
+            out.push(TAG_BOOL);
+            out.push(u8::from(*b));
+        }
+        Value::Number(n) => {
+            out.push(TAG_NUMBER);
+            let f = n.as_f64().unwrap_or(0.0);
+            // Treat -0.0 as 0.0 so equal numbers encode the same
+            let bits = if f == 0.0 {
+                0.0f64.to_bits()
+            } else {
+                f.to_bits()
+            };
+            let ordered = if bits >> 63 == 1 {
+                !bits
+            } else {
+                bits | (1 << 63)
+            };
+            out.extend_from_slice(&ordered.to_be_bytes());
+        }
+        Value::String(s) => {
+            out.push(TAG_STRING);
+            encode_bytes(s.as_bytes(), out);
+        }
+        Value::Array(_) => {
+            out.push(TAG_ARRAY);
+            encode_bytes(value.to_string().as_bytes(), out);
+        }
+        Value::Object(_) => {
+            out.push(TAG_OBJECT);
+            encode_bytes(value.to_string().as_bytes(), out);
+        }
+        Value::Null => out.push(TAG_NULL),
+    }
+}
+
+fn type_tag(value: &Value) -> u8 {
+    match value {
+        Value::Bool(_) => TAG_BOOL,
+        Value::Number(_) => TAG_NUMBER,
+        Value::String(_) => TAG_STRING,
+        Value::Array(_) => TAG_ARRAY,
+        Value::Object(_) => TAG_OBJECT,
+        Value::Null => TAG_NULL,
+    }
+}
+
+/// Smallest key greater than every key starting with `prefix`, or `None`
+/// when no such key exists
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xFF {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+fn condition_holds(condition: &IndexCondition, doc: &Document) -> bool {
+    let value = field_value(doc, &condition.field).unwrap_or(&Value::Null);
+    let cmp = || {
+        if value.is_null() || type_tag(value) != type_tag(&condition.value) {
+            return None;
+        }
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        encode_value(value, &mut a);
+        encode_value(&condition.value, &mut b);
+        Some(a.cmp(&b))
+    };
+    match condition.op {
+        ConditionOp::IsNull => value.is_null(),
+        ConditionOp::IsNotNull => !value.is_null(),
+        ConditionOp::Eq => cmp() == Some(std::cmp::Ordering::Equal),
+        ConditionOp::NotEq => cmp().is_some_and(std::cmp::Ordering::is_ne),
+        ConditionOp::Lt => cmp() == Some(std::cmp::Ordering::Less),
+        ConditionOp::LtEq => cmp().is_some_and(std::cmp::Ordering::is_le),
+        ConditionOp::Gt => cmp() == Some(std::cmp::Ordering::Greater),
+        ConditionOp::GtEq => cmp().is_some_and(std::cmp::Ordering::is_ge),
+    }
+}
+
+/// Values an index scan reads: equal to `equal` on the leading fields and,
+/// on the field after them, within `lower` and `upper`
+///
+/// A bounded field only matches values of the bounds' type, as comparisons
+/// across types never hold. Exclusive bounds are widened to inclusive ones,
+/// so callers must still check the bounds on what the scan returns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexRange {
+    /// Values of the leading fields
+    pub equal: Vec<Value>,
+    /// Lower bound of the next field
+    pub lower: Bound<Value>,
+    /// Upper bound of the next field
+    pub upper: Bound<Value>,
+}
+
+impl IndexRange {
+    /// Range matching the given values of the leading fields
+    #[must_use]
+    pub fn equal(values: Vec<Value>) -> Self {
+        Self {
+            equal: values,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    /// Prefix every key of the range shares, its first key and the key it
+    /// ends before, if any
+    fn keys(&self) -> (Vec<u8>, Vec<u8>, Option<Vec<u8>>) {
+        let mut prefix = Vec::new();
+        for value in &self.equal {
+            encode_value(value, &mut prefix);
+        }
+
+        let bound_value = |bound: &Bound<Value>| match bound {
+            Bound::Included(v) | Bound::Excluded(v) => Some(v.clone()),
+            Bound::Unbounded => None,
+        };
+        let (lower, upper) = (bound_value(&self.lower), bound_value(&self.upper));
+        let Some(tag) = lower.as_ref().or(upper.as_ref()).map(type_tag) else {
+            let end = prefix_successor(&prefix);
+            return (prefix.clone(), prefix, end);
+        };
+
+        let mut start = prefix.clone();
+        match &lower {
+            Some(v) => encode_value(v, &mut start),
+            None => start.push(tag),
+        }
+        let mut end = prefix.clone();
+        match &upper {
+            Some(v) => encode_value(v, &mut end),
+            None => end.push(tag),
+        }
+        let end = prefix_successor(&end);
+        // Keep the range within the bounds' type
+        let end = match (lower, upper) {
+            (Some(l), Some(u)) if type_tag(&l) != type_tag(&u) => Some(start.clone()),
+            _ => end,
+        };
+        (prefix, start, end)
+    }
+}
+
+/// Index entries in key order, yielding the document IDs they point to
+pub struct IndexScan {
+    entries: ScanIterator,
+    end: Option<Vec<u8>>,
+}
+
+impl Iterator for IndexScan {
+    /// Entry key and document ID
+    type Item = Result<(Vec<u8>, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, id) = match self.entries.next()? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        if self.end.as_ref().is_some_and(|end| key >= *end) {
+            return None;
+        }
+        Some(Ok((key, String::from_utf8_lossy(&id).into_owned())))
+    }
+}
+
+/// A secondary index on one or more document fields
+pub struct SecondaryIndex {
+    metadata: IndexMetadata,
+    tree: Arc<LsmTree>,
+    building: AtomicBool,
+    entries: AtomicU64,
+    distinct_keys: AtomicU64,
+    /// Serializes entry changes, which read and update the statistics
+    stats_lock: Mutex<()>,
+}
+
+impl SecondaryIndex {
+    /// Open (or create) the index tree at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index tree cannot be opened.
+    pub fn open(path: &Path, metadata: IndexMetadata, options: LsmOptions) -> Result<Self> {
+        let tree = LsmTree::open(path, Arc::new(BufferPool::new(INDEX_CACHE_BYTES)), options)?;
+        Ok(Self {
+            building: AtomicBool::new(metadata.building),
+            entries: AtomicU64::new(metadata.entries),
+            distinct_keys: AtomicU64::new(metadata.distinct_keys),
+            stats_lock: Mutex::new(()),
+            metadata,
+            tree,
+        })
+    }
+
+    /// Index name
+    pub fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    /// Metadata with the current state and statistics
+    pub fn metadata(&self) -> IndexMetadata {
+        IndexMetadata {
+            building: self.is_building(),
+            entries: self.entries.load(Ordering::Relaxed),
+            distinct_keys: self.distinct_keys.load(Ordering::Relaxed),
+            ..self.metadata.clone()
+        }
+    }
+
+    /// Whether the index is still being backfilled
+    pub fn is_building(&self) -> bool {
+        self.building.load(Ordering::Acquire)
+    }
+
+    /// Mark the backfill complete
+    pub fn set_ready(&self) {
+        self.building.store(false, Ordering::Release);
+    }
+
+    /// Encoded field values of a document, or `None` if the index's filter
+    /// excludes it
+    fn prefix(&self, doc: &Document) -> Option<Vec<u8>> {
+        if !self.metadata.filter.iter().all(|c| condition_holds(c, doc)) {
+            return None;
+        }
+        let mut prefix = Vec::new();
+        for field in &self.metadata.fields {
+            encode_value(field_value(doc, field).unwrap_or(&Value::Null), &mut prefix);
+        }
+        Some(prefix)
+    }
+
+    /// Key of a document's entry, or `None` if it has none
+    pub fn entry_key(&self, doc: &Document) -> Option<Vec<u8>> {
+        let mut key = self.prefix(doc)?;
+        key.extend_from_slice(doc.id.as_bytes());
+        Some(key)
+    }
+
+    /// Encoded field values no other document may share, or `None` if the
+    /// index is not unique or `doc` cannot conflict
+    pub fn unique_values(&self, doc: &Document) -> Option<Vec<u8>> {
+        if !self.metadata.unique {
+            return None;
+        }
+        let any_null = self
+            .metadata
+            .fields
+            .iter()
+            .any(|f| field_value(doc, f).map_or(true, Value::is_null));
+        self.prefix(doc).filter(|_| !any_null)
+    }
+
+    /// IDs of other documents with entries holding the same values as
+    /// `doc`, which a unique index rejects
+    ///
+    /// Documents with a NULL or missing field never conflict. The entries
+    /// may be stale, so the caller must check the documents themselves.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index tree cannot be read.
+    pub fn conflicts(&self, doc: &Document) -> Result<Vec<(Vec<u8>, String)>> {
+        let Some(prefix) = self.unique_values(doc) else {
+            return Ok(Vec::new());
+        };
+
+        let mut conflicts = Vec::new();
+        for entry in self.tree.scan(&prefix)? {
+            let (key, id) = entry?;
+            // A longer ID could share the prefix bytes of this one
+            if key.len() - prefix.len() == id.len() && id != doc.id.as_bytes() {
+                conflicts.push((key, String::from_utf8_lossy(&id).into_owned()));
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Whether any entry starts with `prefix`
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool> {
+        Ok(self.tree.scan(prefix)?.next().transpose()?.is_some())
+    }
+
+    /// Add the entry for a document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry cannot be written.
+    pub fn insert(&self, doc: &Document) -> Result<()> {
+        let Some(prefix) = self.prefix(doc) else {
+            return Ok(());
+        };
+        let mut key = prefix.clone();
+        key.extend_from_slice(doc.id.as_bytes());
+
+        let _stats = self.stats_lock.lock();
+        if self.tree.get(&key)?.is_some() {
+            return Ok(());
+        }
+        if !self.has_prefix(&prefix)? {
+            self.distinct_keys.fetch_add(1, Ordering::Relaxed);
+        }
+        self.tree.put(key, doc.id.as_bytes().to_vec())?;
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Remove the entry for a document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry cannot be written.
+    pub fn remove(&self, doc: &Document) -> Result<()> {
+        let Some(prefix) = self.prefix(doc) else {
+            return Ok(());
+        };
+        let mut key = prefix.clone();
+        key.extend_from_slice(doc.id.as_bytes());
+
+        let _stats = self.stats_lock.lock();
+        if !self.tree.delete(&key)? {
+            return Ok(());
+        }
+        let _ = self
+            .entries
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        if !self.has_prefix(&prefix)? {
+            let _ = self
+                .distinct_keys
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        }
+        Ok(())
+    }
+
+    /// Entries within a range, in key order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index tree cannot be read.
+    pub fn scan(&self, range: &IndexRange) -> Result<IndexScan> {
+        let (prefix, start, end) = range.keys();
+        Ok(IndexScan {
+            entries: self.tree.scan_from(&prefix, &start)?,
+            end,
+        })
+    }
+
+    /// Flush buffered entries to disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index tree cannot be flushed.
+    pub fn flush(&self) -> Result<()> {
+        self.tree.flush()
+    }
+
+    /// Ask the index tree to compact now, re-encrypting stale tables
+    pub fn schedule_compaction(&self) {
+        self.tree.schedule_compaction();
+    }
+
+    /// Stop the index tree's background worker
+    pub fn close(&self) {
+        self.tree.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumadb_common::types::IndexType;
+    use serde_json::json;
+
+    fn encoded(value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_value(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn encoding_preserves_value_order() {
+        let ordered = [
+            json!(false),
+            json!(true),
+            json!(-1e300),
+            json!(-2.5),
+            json!(-1),
+            json!(0),
+            json!(0.5),
+            json!(3),
+            json!(1e300),
+            json!(""),
+            json!("a"),
+            json!("a\u{0}"),
+            json!("a\u{0}b"),
+            json!("ab"),
+            json!("b"),
+            json!([1]),
+            json!({"a": 1}),
+            Value::Null,
+        ];
+        for pair in ordered.windows(2) {
+            assert!(
+                encoded(&pair[0]) < encoded(&pair[1]),
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        assert_eq!(encoded(&json!(-0.0)), encoded(&json!(0)));
+    }
+
+    #[test]
+    fn ranges_stay_within_their_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = IndexMetadata {
+            name: "by_age".to_string(),
+            index_type: IndexType::BTree,
+            fields: vec!["age".to_string()],
+            unique: false,
+            filter: vec![],
+            building: false,
+            entries: 0,
+            distinct_keys: 0,
+        };
+        let index = SecondaryIndex::open(dir.path(), metadata, LsmOptions::default()).unwrap();
+        let docs = [
+            json!(5),
+            json!(17),
+            json!(30),
+            json!("20"),
+            Value::Null,
+            json!(17),
+        ];
+        for (i, age) in docs.iter().enumerate() {
+            index
+                .insert(&Document::with_id(format!("d{i}"), json!({ "age": age })))
+                .unwrap();
+        }
+        assert_eq!(index.metadata().entries, 6);
+        assert_eq!(index.metadata().distinct_keys, 5);
+
+        let ids = |range: IndexRange| -> Vec<String> {
+            index.scan(&range).unwrap().map(|e| e.unwrap().1).collect()
+        };
+        let range = |lower, upper| IndexRange {
+            equal: vec![],
+            lower,
+            upper,
+        };
+        assert_eq!(ids(IndexRange::equal(vec![json!(17)])), ["d1", "d5"]);
+        assert_eq!(
+            ids(range(Bound::Included(json!(10)), Bound::Unbounded)),
+            ["d1", "d5", "d2"]
+        );
+        assert_eq!(
+            ids(range(Bound::Unbounded, Bound::Excluded(json!(17)))),
+            ["d0", "d1", "d5"]
+        );
+        assert_eq!(
+            ids(range(Bound::Included(json!("")), Bound::Unbounded)),
+            ["d3"]
+        );
+        index.close();
+    }
+}
@@ -2,26 +2,40 @@
 //!
 //! High-performance storage engine providing:
 //! - LSM-Tree based key-value storage
+//! - Multi-version documents for snapshot-isolated transactions
 //! - Columnar storage using Apache Arrow
 //! - Vector indexing with HNSW
 //! - Time-series optimized storage
 //! - Full-text search with Tantivy
+//! - Hybrid search fusing vector and full-text rankings
+//! - Encryption at rest with per-collection data keys
+//! - Collection snapshots that restore onto any node
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
 pub mod cache;
+pub mod changes;
 pub mod columnar;
+pub mod encryption;
 pub mod fulltext;
+pub mod hybrid;
+pub mod index;
 pub mod lsm;
+pub mod mvcc;
+pub mod snapshot;
 pub mod timeseries;
 pub mod vector;
 
 mod engine;
 mod wal;
 
-pub use engine::{StorageEngine, VectorSearchResult};
-pub use wal::WriteAheadLog;
+pub use changes::{ChangeEvent, ChangeKind, ChangePosition, ChangeStream, ChangeSubscription};
+pub use engine::{
+    CollectionStatistics, DocumentScan, IndexedDocuments, StorageEngine, VectorSearchResult,
+};
+pub use mvcc::{DocumentWrite, ReadKey};
+pub use wal::{WalRecord, WalSyncPolicy, WalWrite, WriteAheadLog};
 
 use lumadb_common::config::StorageConfig;
 use lumadb_common::error::Result;
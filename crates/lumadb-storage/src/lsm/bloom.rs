@@ -0,0 +1,89 @@
+//! Bloom filter used to skip SSTables that cannot contain a key
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+/// Seed for the second hash used in double hashing
+const SECOND_HASH_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Space-efficient probabilistic membership filter
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    /// Bit array
+    bits: Vec<u8>,
+    /// Number of hash functions
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Create a filter sized for `expected_keys` entries at `bits_per_key` bits each
+    #[must_use]
+    pub fn with_capacity(expected_keys: usize, bits_per_key: usize) -> Self {
+        let num_bits = (expected_keys.max(1) * bits_per_key.max(1)).max(64);
+        let num_bytes = num_bits.div_ceil(8);
+
+        // k = ln(2) * bits_per_key (~0.69) minimizes the false-positive rate
+        let num_hashes = u32::try_from(bits_per_key * 69 / 100)
+            .unwrap_or(30)
+            .clamp(1, 30);
+
+        Self {
+            bits: vec![0; num_bytes],
+            num_hashes,
+        }
+    }
+
+    /// Add a key to the filter
+    pub fn insert(&mut self, key: &[u8]) {
+        let num_bits = self.num_bits();
+        for bit in Self::probes(key, self.num_hashes, num_bits) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Check whether the key may be present (false positives possible, no false negatives)
+    #[must_use]
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let num_bits = self.num_bits();
+        if num_bits == 0 {
+            return true;
+        }
+        Self::probes(key, self.num_hashes, num_bits)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Serialize the filter: `[num_hashes u32][bits...]`
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + self.bits.len());
+        out.extend_from_slice(&self.num_hashes.to_le_bytes());
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    /// Deserialize a filter produced by [`BloomFilter::encode`]
+    #[must_use]
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 {
+            return None;
+        }
+        let num_hashes = u32::from_le_bytes(data[..4].try_into().ok()?);
+        Some(Self {
+            bits: data[4..].to_vec(),
+            num_hashes,
+        })
+    }
+
+    fn num_bits(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    // Each probe is below `num_bits`, so narrowing back to usize cannot truncate
+    #[allow(clippy::cast_possible_truncation)]
+    fn probes(key: &[u8], num_hashes: u32, num_bits: usize) -> impl Iterator<Item = usize> {
+        let h1 = xxh3_64_with_seed(key, 0);
+        let h2 = xxh3_64_with_seed(key, SECOND_HASH_SEED) | 1;
+        let num_bits = num_bits as u64;
+        (0..u64::from(num_hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
@@ -0,0 +1,333 @@
+//! Leveled compaction
+//!
+//! Level 0 is compacted into level 1 once it holds
+//! `level0_compaction_trigger` tables. Level `n >= 1` is compacted into
+//! `n + 1` once its total size exceeds
+//! `level_base_bytes * level_multiplier^(n - 1)`, one table at a time in
+//! round-robin key order. Tombstones are dropped when no deeper level can
+//! hold an older value for the key.
+//!
+//! When no level is over its target, tables not sealed with the tree's
+//! active data key are rewritten: level-0 tables by merging them into level
+//! 1, deeper tables one at a time in place. This is how a key rotation
+//! re-encrypts existing data.
+
+use std::sync::Arc;
+
+use tracing::{debug, warn};
+
+use lumadb_common::error::Result;
+
+use super::iterator::{EntryIterator, MergeIterator};
+use super::manifest::{Manifest, TableMeta};
+use super::sstable::{SsTable, SsTableWriter};
+use super::{Entry, LsmOptions, LsmTree, Version};
+use crate::encryption::DataKeys;
+
+/// A unit of compaction work
+#[derive(Debug, Clone)]
+pub(super) struct CompactionTask {
+    /// Level the inputs are taken from
+    pub level: usize,
+    /// Tables from `level`, newest first
+    pub inputs: Vec<TableMeta>,
+    /// Overlapping tables from `level + 1`
+    pub overlapping: Vec<TableMeta>,
+    /// Rewrite the single input in place under the active data key
+    pub reencrypt: bool,
+}
+
+impl CompactionTask {
+    /// Level the output is written to
+    pub fn output_level(&self) -> usize {
+        if self.reencrypt {
+            self.level
+        } else {
+            self.level + 1
+        }
+    }
+
+    /// Smallest and largest key across all inputs
+    fn key_range(&self) -> (Vec<u8>, Vec<u8>) {
+        let all = self.inputs.iter().chain(&self.overlapping);
+        let smallest = all
+            .clone()
+            .map(|t| t.smallest.clone())
+            .min()
+            .unwrap_or_default();
+        let largest = all.map(|t| t.largest.clone()).max().unwrap_or_default();
+        (smallest, largest)
+    }
+}
+
+/// Maximum total bytes for a level (level 0 is bounded by file count instead)
+pub(super) fn level_target_bytes(options: &LsmOptions, level: usize) -> u64 {
+    let exp = u32::try_from(level.saturating_sub(1)).unwrap_or(u32::MAX);
+    options
+        .level_base_bytes
+        .saturating_mul(options.level_multiplier.saturating_pow(exp))
+}
+
+/// Choose the most urgent compaction, if any level is over its target
+#[allow(clippy::cast_precision_loss)] // scores are approximate ratios
+pub(super) fn pick_compaction(manifest: &Manifest, options: &LsmOptions) -> Option<CompactionTask> {
+    let last_level = options.max_levels.checked_sub(1)?;
+    let mut best: Option<(f64, usize)> = None;
+
+    for level in 0..last_level {
+        let tables = &manifest.levels[level];
+        if tables.is_empty() {
+            continue;
+        }
+
+        let score = if level == 0 {
+            tables.len() as f64 / options.level0_compaction_trigger.max(1) as f64
+        } else {
+            let size: u64 = tables.iter().map(|t| t.size).sum();
+            size as f64 / level_target_bytes(options, level) as f64
+        };
+
+        if score >= 1.0 && best.map_or(true, |(s, _)| score > s) {
+            best = Some((score, level));
+        }
+    }
+
+    let (_, level) = best?;
+
+    let inputs = if level == 0 {
+        manifest.levels[0].clone()
+    } else {
+        // Round-robin through the level's key space
+        let tables = &manifest.levels[level];
+        let pointer = manifest
+            .compact_pointers
+            .get(level)
+            .cloned()
+            .unwrap_or_default();
+        let table = tables
+            .iter()
+            .find(|t| t.smallest > pointer)
+            .unwrap_or(&tables[0]);
+        vec![table.clone()]
+    };
+
+    merge_task(manifest, level, inputs)
+}
+
+/// Choose a table to rewrite under the active data key, if any is sealed
+/// with an older version or not encrypted
+pub(super) fn pick_reencryption(
+    manifest: &Manifest,
+    options: &LsmOptions,
+) -> Option<CompactionTask> {
+    let active = options.sstable.cipher.as_ref()?.active_version();
+    let stale = |t: &&TableMeta| t.key_version != Some(active);
+
+    // Level-0 tables may overlap, so they move down together
+    let level0 = manifest.levels.first()?;
+    if level0.iter().any(|t| stale(&t)) && manifest.levels.len() > 1 {
+        return merge_task(manifest, 0, level0.clone());
+    }
+
+    let (level, table) = manifest
+        .levels
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find_map(|(level, tables)| tables.iter().find(stale).map(|t| (level, t)))?;
+    Some(CompactionTask {
+        level,
+        inputs: vec![table.clone()],
+        overlapping: Vec::new(),
+        reencrypt: true,
+    })
+}
+
+/// Merge `inputs` from `level` with the tables they overlap one level down
+fn merge_task(manifest: &Manifest, level: usize, inputs: Vec<TableMeta>) -> Option<CompactionTask> {
+    let smallest = inputs.iter().map(|t| t.smallest.as_slice()).min()?;
+    let largest = inputs.iter().map(|t| t.largest.as_slice()).max()?;
+    let overlapping = manifest.levels[level + 1]
+        .iter()
+        .filter(|t| t.overlaps(smallest, largest))
+        .cloned()
+        .collect();
+
+    Some(CompactionTask {
+        level,
+        inputs,
+        overlapping,
+        reencrypt: false,
+    })
+}
+
+impl LsmTree {
+    /// Run one compaction if needed; returns whether any work was done
+    ///
+    /// Callers must hold `flush_lock`.
+    pub(super) fn run_compaction(&self) -> Result<bool> {
+        let version = self.version.read().clone();
+        let Some(task) = pick_compaction(&version.manifest, &self.options)
+            .or_else(|| pick_reencryption(&version.manifest, &self.options))
+        else {
+            return Ok(false);
+        };
+
+        let output_level = task.output_level();
+        let (smallest, largest) = task.key_range();
+
+        debug!(
+            "Compacting {} table(s) from L{} with {} table(s) from L{}",
+            task.inputs.len(),
+            task.level,
+            task.overlapping.len(),
+            output_level
+        );
+
+        // Trivial move: a single table with nothing to merge against
+        if task.level > 0
+            && task.inputs.len() == 1
+            && task.overlapping.is_empty()
+            && !task.reencrypt
+        {
+            self.move_table(&version, &task)?;
+            return Ok(true);
+        }
+
+        // Tombstones can only be dropped if no deeper level may hold the key
+        let drop_tombstones = version.manifest.levels[output_level + 1..]
+            .iter()
+            .flatten()
+            .all(|t| !t.overlaps(&smallest, &largest));
+
+        let sources: Vec<EntryIterator> = task
+            .inputs
+            .iter()
+            .chain(&task.overlapping)
+            .map(|meta| Box::new(version.tables[&meta.id].iter()) as EntryIterator)
+            .collect();
+
+        let mut manifest = version.manifest.clone();
+        let mut outputs: Vec<(TableMeta, Arc<SsTable>)> = Vec::new();
+        let cipher = &self.options.sstable.cipher;
+        let mut writer: Option<(u64, SsTableWriter)> = None;
+
+        for item in MergeIterator::new(sources) {
+            let (key, entry) = item?;
+            if drop_tombstones && entry == Entry::Tombstone {
+                continue;
+            }
+
+            if writer.is_none() {
+                let id = manifest.allocate_file_id();
+                let path = Manifest::table_path(&self.path, id);
+                writer = Some((
+                    id,
+                    SsTableWriter::create(&path, self.options.sstable.clone())?,
+                ));
+            }
+
+            if let Some((_, w)) = writer.as_mut() {
+                w.add(&key, &entry)?;
+                if w.estimated_size() >= self.options.target_file_size {
+                    if let Some((id, w)) = writer.take() {
+                        outputs.push(finish_output(id, w, output_level, cipher.clone())?);
+                    }
+                }
+            }
+        }
+
+        if let Some((id, w)) = writer.take() {
+            outputs.push(finish_output(id, w, output_level, cipher.clone())?);
+        }
+
+        // Install the new level layout
+        let removed: Vec<u64> = task
+            .inputs
+            .iter()
+            .chain(&task.overlapping)
+            .map(|t| t.id)
+            .collect();
+        manifest.levels[task.level].retain(|t| !removed.contains(&t.id));
+        manifest.levels[output_level].retain(|t| !removed.contains(&t.id));
+        for (meta, _) in &outputs {
+            insert_sorted(&mut manifest.levels[output_level], meta.clone());
+        }
+        if task.level > 0 && !task.reencrypt {
+            set_compact_pointer(&mut manifest, task.level, largest.clone());
+        }
+        manifest.save(&self.path, self.cipher())?;
+
+        let mut next: Version = (*version).clone();
+        next.manifest = manifest;
+        for id in &removed {
+            next.tables.remove(id);
+        }
+        for (meta, table) in outputs {
+            next.tables.insert(meta.id, table);
+        }
+        *self.version.write() = Arc::new(next);
+
+        // Old tables are unreachable from the new version; readers still
+        // holding the previous version keep their mappings alive
+        for id in removed {
+            let path = Manifest::table_path(&self.path, id);
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove compacted SSTable {:?}: {}", path, e);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Move a table one level down without rewriting it
+    fn move_table(&self, version: &Version, task: &CompactionTask) -> Result<()> {
+        let output_level = task.output_level();
+        let mut manifest = version.manifest.clone();
+        let mut meta = task.inputs[0].clone();
+        manifest.levels[task.level].retain(|t| t.id != meta.id);
+        meta.level = output_level;
+        insert_sorted(&mut manifest.levels[output_level], meta.clone());
+        set_compact_pointer(&mut manifest, task.level, meta.largest);
+        manifest.save(&self.path, self.cipher())?;
+
+        let mut next = version.clone();
+        next.manifest = manifest;
+        *self.version.write() = Arc::new(next);
+        Ok(())
+    }
+}
+
+/// Seal a compaction output and reopen it for reads
+fn finish_output(
+    id: u64,
+    writer: SsTableWriter,
+    level: usize,
+    cipher: Option<Arc<DataKeys>>,
+) -> Result<(TableMeta, Arc<SsTable>)> {
+    let info = writer.finish()?;
+    let table = Arc::new(SsTable::open(id, &info.path, cipher)?);
+    let meta = TableMeta {
+        id,
+        level,
+        size: info.size,
+        entries: info.entries,
+        smallest: info.smallest,
+        largest: info.largest,
+        key_version: info.key_version,
+    };
+    Ok((meta, table))
+}
+
+/// Insert into a non-overlapping level keeping it sorted by smallest key
+fn insert_sorted(tables: &mut Vec<TableMeta>, meta: TableMeta) {
+    let idx = tables.partition_point(|t| t.smallest < meta.smallest);
+    tables.insert(idx, meta);
+}
+
+fn set_compact_pointer(manifest: &mut Manifest, level: usize, key: Vec<u8>) {
+    if manifest.compact_pointers.len() <= level {
+        manifest.compact_pointers.resize(level + 1, Vec::new());
+    }
+    manifest.compact_pointers[level] = key;
+}
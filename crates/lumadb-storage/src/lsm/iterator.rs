@@ -0,0 +1,124 @@
+//! Iterators over memtables and SSTables, and the k-way merge that combines them
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crossbeam_skiplist::SkipMap;
+
+use lumadb_common::error::Result;
+
+use super::Entry;
+
+/// Boxed source of sorted entries
+pub type EntryIterator = Box<dyn Iterator<Item = Result<(Vec<u8>, Entry)>> + Send>;
+
+/// Owned iterator over a memtable starting at a given key
+pub struct MemtableIterator {
+    memtable: Arc<SkipMap<Vec<u8>, Entry>>,
+    bound: Bound<Vec<u8>>,
+}
+
+impl MemtableIterator {
+    /// Iterate entries with keys `>= start`
+    pub fn new(memtable: Arc<SkipMap<Vec<u8>, Entry>>, start: &[u8]) -> Self {
+        Self {
+            memtable,
+            bound: Bound::Included(start.to_vec()),
+        }
+    }
+}
+
+impl Iterator for MemtableIterator {
+    type Item = Result<(Vec<u8>, Entry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bound = match &self.bound {
+            Bound::Included(k) => Bound::Included(k.as_slice()),
+            Bound::Excluded(k) => Bound::Excluded(k.as_slice()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let entry = self.memtable.lower_bound(bound)?;
+        let key = entry.key().clone();
+        let value = entry.value().clone();
+        drop(entry);
+
+        self.bound = Bound::Excluded(key.clone());
+        Some(Ok((key, value)))
+    }
+}
+
+/// Merges sorted sources; on duplicate keys the source with the lowest index wins
+///
+/// Sources must be ordered newest first so that the most recent write for a
+/// key shadows older ones.
+pub struct MergeIterator {
+    sources: Vec<EntryIterator>,
+    heads: Vec<Option<(Vec<u8>, Entry)>>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+    error: Option<lumadb_common::error::Error>,
+}
+
+impl MergeIterator {
+    /// Create a merge over `sources` (newest first)
+    #[must_use]
+    pub fn new(sources: Vec<EntryIterator>) -> Self {
+        let mut merge = Self {
+            heads: (0..sources.len()).map(|_| None).collect(),
+            sources,
+            heap: BinaryHeap::new(),
+            error: None,
+        };
+
+        for idx in 0..merge.sources.len() {
+            merge.advance(idx);
+        }
+
+        merge
+    }
+
+    fn advance(&mut self, idx: usize) {
+        match self.sources[idx].next() {
+            Some(Ok((key, entry))) => {
+                self.heap.push(Reverse((key.clone(), idx)));
+                self.heads[idx] = Some((key, entry));
+            }
+            Some(Err(e)) => {
+                self.heads[idx] = None;
+                if self.error.is_none() {
+                    self.error = Some(e);
+                }
+            }
+            None => self.heads[idx] = None,
+        }
+    }
+}
+
+impl Iterator for MergeIterator {
+    type Item = Result<(Vec<u8>, Entry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.heap.clear();
+            return Some(Err(e));
+        }
+
+        let Reverse((key, idx)) = self.heap.pop()?;
+        let (_, entry) = self.heads[idx].take()?;
+        self.advance(idx);
+
+        // Skip shadowed versions of the same key from older sources
+        while let Some(Reverse((next_key, _))) = self.heap.peek() {
+            if *next_key != key {
+                break;
+            }
+            let Reverse((_, older)) = self.heap.pop()?;
+            self.heads[older] = None;
+            self.advance(older);
+        }
+
+        Some(Ok((key, entry)))
+    }
+}
@@ -0,0 +1,133 @@
+//! LSM manifest: the durable record of which SSTables make up each level
+//!
+//! The manifest of an encrypted tree is sealed under the tree's data key, as
+//! it records the key range of every table.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{Error, Result, StorageError};
+
+use crate::encryption::{self, DataKeys};
+
+/// Manifest file name inside the tree directory
+const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Metadata for one SSTable recorded in the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableMeta {
+    /// File identifier (file name is `{id:020}.sst`)
+    pub id: u64,
+    /// Level the table belongs to
+    pub level: usize,
+    /// File size in bytes
+    pub size: u64,
+    /// Number of entries
+    pub entries: u64,
+    /// Smallest key
+    pub smallest: Vec<u8>,
+    /// Largest key
+    pub largest: Vec<u8>,
+    /// Version of the data key sealing the table, or `None` if unencrypted
+    #[serde(default)]
+    pub key_version: Option<u32>,
+}
+
+impl TableMeta {
+    /// Whether the table's key range intersects `[smallest, largest]`
+    #[must_use]
+    pub fn overlaps(&self, smallest: &[u8], largest: &[u8]) -> bool {
+        self.smallest.as_slice() <= largest && self.largest.as_slice() >= smallest
+    }
+}
+
+/// Persistent manifest state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Next file identifier to allocate
+    pub next_file_id: u64,
+    /// Tables per level; level 0 is ordered newest first, deeper levels by key
+    pub levels: Vec<Vec<TableMeta>>,
+    /// Largest key of the last table compacted out of each level
+    #[serde(default)]
+    pub compact_pointers: Vec<Vec<u8>>,
+}
+
+impl Manifest {
+    /// Load the manifest from `dir`, or return an empty one if absent
+    ///
+    /// A sealed manifest is opened with `cipher`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest cannot be read, is corrupt, or is
+    /// sealed and `cipher` is missing or cannot open it.
+    pub fn load(dir: &Path, cipher: Option<&DataKeys>) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read(&path) {
+            Ok(mut data) => {
+                if encryption::is_sealed(&data) {
+                    let cipher = cipher.ok_or_else(|| {
+                        Error::Storage(StorageError::Encryption(format!(
+                            "Manifest {} is encrypted but no data key is configured",
+                            path.display()
+                        )))
+                    })?;
+                    data = cipher.open(MANIFEST_FILE.as_bytes(), &data)?;
+                }
+                serde_json::from_slice(&data).map_err(|e| {
+                    Error::Storage(StorageError::CorruptData(format!(
+                        "Manifest {}: {e}",
+                        path.display()
+                    )))
+                })
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically persist the manifest to `dir` (write temp file, fsync, rename),
+    /// sealed with `cipher` if given
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest cannot be sealed or written.
+    pub fn save(&self, dir: &Path, cipher: Option<&DataKeys>) -> Result<()> {
+        let tmp = dir.join(format!("{MANIFEST_FILE}.tmp"));
+        let mut data = serde_json::to_vec(self)?;
+        if let Some(cipher) = cipher {
+            data = cipher.seal(MANIFEST_FILE.as_bytes(), &data)?;
+        }
+
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+
+        // Make the rename durable
+        if let Ok(dir_handle) = fs::File::open(dir) {
+            let _ = dir_handle.sync_all();
+        }
+
+        Ok(())
+    }
+
+    /// Allocate a new file identifier
+    pub fn allocate_file_id(&mut self) -> u64 {
+        let id = self.next_file_id;
+        self.next_file_id += 1;
+        id
+    }
+
+    /// Path of the SSTable with the given identifier
+    #[must_use]
+    pub fn table_path(dir: &Path, id: u64) -> PathBuf {
+        dir.join(format!("{id:020}.sst"))
+    }
+}
@@ -1,70 +1,252 @@
 //! LSM-Tree based key-value storage
-
-use std::path::Path;
+//!
+//! Writes go to an in-memory skip list (the active memtable). When it grows
+//! past `max_memtable_size` it is frozen into the immutable list and a
+//! background flusher writes it out as a level-0 SSTable. Leveled compaction
+//! merges level-0 tables into level 1 and pushes oversized levels down, so a
+//! point read touches at most one table per level below level 0.
+//!
+//! [`LsmTree::put_version`] and the reads built on it store several
+//! timestamped versions per key, for snapshot reads.
+//!
+//! With a data key in [`SsTableOptions::cipher`], tables and the manifest are
+//! encrypted. After the key is rotated, compaction also rewrites tables
+//! sealed with older versions once no level needs merging, so the whole tree
+//! moves onto the new key in the background.
+
+mod bloom;
+mod compaction;
+mod iterator;
+mod manifest;
+mod sstable;
+mod version;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 
+use crossbeam::channel::{RecvTimeoutError, Sender};
 use crossbeam_skiplist::SkipMap;
-use parking_lot::RwLock;
-use tracing::debug;
+use parking_lot::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
 
 use lumadb_common::error::Result;
 
 use crate::cache::BufferPool;
+use crate::encryption::DataKeys;
+
+pub use bloom::BloomFilter;
+pub use iterator::{EntryIterator, MemtableIterator, MergeIterator};
+pub use manifest::{Manifest, TableMeta};
+pub use sstable::{SsTable, SsTableInfo, SsTableIterator, SsTableOptions, SsTableWriter};
+pub use version::{decode_versioned_key, versioned_key, KeyVersion, VersionScan};
+
+/// A value or deletion marker stored in memtables and SSTables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// Live value
+    Value(Vec<u8>),
+    /// Deletion marker shadowing older values
+    Tombstone,
+}
+
+/// Tuning options for an LSM tree
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    /// Maximum memtable size before it is frozen and flushed
+    pub max_memtable_size: usize,
+    /// Number of frozen memtables after which writers flush inline
+    pub max_immutable_memtables: usize,
+    /// Number of level-0 tables that triggers a level-0 compaction
+    pub level0_compaction_trigger: usize,
+    /// Target size of level 1 in bytes
+    pub level_base_bytes: u64,
+    /// Size ratio between adjacent levels
+    pub level_multiplier: u64,
+    /// Target size of compaction output files
+    pub target_file_size: u64,
+    /// Number of levels
+    pub max_levels: usize,
+    /// How often the background worker checks for compaction work
+    pub compaction_interval: Duration,
+    /// SSTable layout options
+    pub sstable: SsTableOptions,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self {
+            max_memtable_size: 64 * 1024 * 1024, // 64MB
+            max_immutable_memtables: 4,
+            level0_compaction_trigger: 4,
+            level_base_bytes: 256 * 1024 * 1024, // 256MB
+            level_multiplier: 10,
+            target_file_size: 64 * 1024 * 1024, // 64MB
+            max_levels: 7,
+            compaction_interval: Duration::from_secs(60),
+            sstable: SsTableOptions::default(),
+        }
+    }
+}
+
+/// Immutable snapshot of the on-disk table set
+#[derive(Clone)]
+struct Version {
+    /// Manifest describing the levels
+    manifest: Manifest,
+    /// Open table readers by file ID
+    tables: HashMap<u64, Arc<SsTable>>,
+}
+
+impl Version {
+    /// Look up a key in the on-disk levels, newest data first
+    fn get(&self, key: &[u8]) -> Result<Option<Entry>> {
+        for (level, tables) in self.manifest.levels.iter().enumerate() {
+            if level == 0 {
+                // Level 0 tables may overlap; check newest first
+                for meta in tables {
+                    if meta.overlaps(key, key) {
+                        if let Some(entry) = self.tables[&meta.id].get(key)? {
+                            return Ok(Some(entry));
+                        }
+                    }
+                }
+            } else {
+                let idx = tables.partition_point(|t| t.largest.as_slice() < key);
+                if let Some(meta) = tables.get(idx) {
+                    if meta.smallest.as_slice() <= key {
+                        if let Some(entry) = self.tables[&meta.id].get(key)? {
+                            return Ok(Some(entry));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterators over tables that may hold keys with `prefix` at or after
+    /// `start`, newest first
+    fn iterators(&self, prefix: &[u8], start: &[u8]) -> Vec<EntryIterator> {
+        let mut sources: Vec<EntryIterator> = Vec::new();
+        for tables in &self.manifest.levels {
+            for meta in tables {
+                let may_match = meta.largest.as_slice() >= start
+                    && (meta.smallest.as_slice() <= start || meta.smallest.starts_with(prefix));
+                if may_match {
+                    sources.push(Box::new(self.tables[&meta.id].iter_from(start)));
+                }
+            }
+        }
+        sources
+    }
+}
+
+/// In-memory sorted buffer of recent writes
+type Memtable = SkipMap<Vec<u8>, Entry>;
+
+/// Signals sent to the background flush/compaction worker
+enum WorkerSignal {
+    /// A memtable was frozen and should be flushed
+    Flush,
+    /// Stop the worker
+    Shutdown,
+}
 
 /// LSM-Tree implementation
 pub struct LsmTree {
     /// Active memtable (skip list for fast writes)
-    memtable: RwLock<Arc<SkipMap<Vec<u8>, Vec<u8>>>>,
-    /// Immutable memtables waiting to be flushed
-    immutable_memtables: RwLock<Vec<Arc<SkipMap<Vec<u8>, Vec<u8>>>>>,
+    memtable: RwLock<Arc<Memtable>>,
+    /// Immutable memtables waiting to be flushed (oldest first)
+    immutable_memtables: RwLock<Vec<Arc<Memtable>>>,
+    /// Current on-disk table set
+    version: RwLock<Arc<Version>>,
     /// Buffer pool for caching
     buffer_pool: Arc<BufferPool>,
     /// Storage path
-    #[allow(dead_code)]
-    path: std::path::PathBuf,
-    /// Maximum memtable size before flush
-    max_memtable_size: usize,
+    path: PathBuf,
+    /// Tuning options
+    options: LsmOptions,
     /// Current memtable size
-    memtable_size: std::sync::atomic::AtomicUsize,
+    memtable_size: AtomicUsize,
+    /// Serializes flushes and compactions (the only manifest writers)
+    flush_lock: Mutex<()>,
+    /// Channel to the background worker
+    worker_tx: Mutex<Option<Sender<WorkerSignal>>>,
+    /// Background worker handle
+    worker: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl LsmTree {
-    /// Create a new LSM tree
-    pub fn new(path: &Path, buffer_pool: Arc<BufferPool>) -> Self {
-        Self {
+    /// Open (or create) an LSM tree at `path` and start its background worker
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest or one of its SSTables cannot be opened.
+    pub fn open(
+        path: &Path,
+        buffer_pool: Arc<BufferPool>,
+        options: LsmOptions,
+    ) -> Result<Arc<Self>> {
+        std::fs::create_dir_all(path)?;
+
+        let cipher = options.sstable.cipher.clone();
+        let mut manifest = Manifest::load(path, cipher.as_deref())?;
+        if manifest.levels.len() < options.max_levels {
+            manifest.levels.resize_with(options.max_levels, Vec::new);
+        }
+
+        let mut tables = HashMap::new();
+        for meta in manifest.levels.iter().flatten() {
+            let table_path = Manifest::table_path(path, meta.id);
+            let table = SsTable::open(meta.id, &table_path, cipher.clone())?;
+            tables.insert(meta.id, Arc::new(table));
+        }
+
+        Self::remove_orphans(path, &tables)?;
+
+        info!(
+            "Opened LSM tree at {:?} with {} SSTables",
+            path,
+            tables.len()
+        );
+
+        let tree = Arc::new(Self {
             memtable: RwLock::new(Arc::new(SkipMap::new())),
             immutable_memtables: RwLock::new(Vec::new()),
+            version: RwLock::new(Arc::new(Version { manifest, tables })),
             buffer_pool,
             path: path.to_path_buf(),
-            max_memtable_size: 64 * 1024 * 1024, // 64MB
-            memtable_size: std::sync::atomic::AtomicUsize::new(0),
-        }
+            options,
+            memtable_size: AtomicUsize::new(0),
+            flush_lock: Mutex::new(()),
+            worker_tx: Mutex::new(None),
+            worker: Mutex::new(None),
+        });
+
+        tree.start_worker()?;
+
+        Ok(tree)
     }
 
     /// Put a key-value pair
     pub fn put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
         let size = key.len() + value.len();
 
+        // Update cache
+        self.buffer_pool.put(&key, &value);
+
         // Insert into memtable
         {
             let memtable = self.memtable.read();
-            memtable.insert(key.clone(), value.clone());
-        }
-
-        // Update size
-        let current_size = self
-            .memtable_size
-            .fetch_add(size, std::sync::atomic::Ordering::SeqCst);
-
-        // Check if we need to flush
-        if current_size + size > self.max_memtable_size {
-            self.maybe_flush()?;
+            memtable.insert(key, Entry::Value(value));
         }
 
-        // Update cache
-        self.buffer_pool.put(&key, &value);
-
-        Ok(())
+        self.account_write(size)
     }
 
     /// Get a value by key
@@ -74,30 +256,35 @@ impl LsmTree {
             return Ok(Some(value));
         }
 
+        let found = self.get_entry(key)?;
+        match found {
+            Some(Entry::Value(value)) => {
+                self.buffer_pool.put(key, &value);
+                Ok(Some(value))
+            }
+            Some(Entry::Tombstone) | None => Ok(None),
+        }
+    }
+
+    /// Look up the newest entry for a key, including tombstones
+    fn get_entry(&self, key: &[u8]) -> Result<Option<Entry>> {
         // Check active memtable
-        let memtable = self.memtable.read();
+        let memtable = self.memtable.read().clone();
         if let Some(entry) = memtable.get(key) {
-            let value = entry.value().clone();
-            drop(entry); // Explicitly drop the entry before returning
-            self.buffer_pool.put(key, &value);
-            return Ok(Some(value));
+            return Ok(Some(entry.value().clone()));
         }
-        drop(memtable);
 
-        // Check immutable memtables
-        let immutables = self.immutable_memtables.read();
+        // Check immutable memtables, newest first
+        let immutables = self.immutable_memtables.read().clone();
         for imm in immutables.iter().rev() {
             if let Some(entry) = imm.get(key) {
-                let value = entry.value().clone();
-                drop(entry);
-                self.buffer_pool.put(key, &value);
-                return Ok(Some(value));
+                return Ok(Some(entry.value().clone()));
             }
         }
 
-        // TODO: Check SSTable files on disk
-
-        Ok(None)
+        // Check SSTable files on disk
+        let version = self.version.read().clone();
+        version.get(key)
     }
 
     /// Delete a key
@@ -106,10 +293,13 @@ impl LsmTree {
         let existed = self.get(key)?.is_some();
 
         if existed {
-            // Remove from memtable
-            let memtable = self.memtable.read();
-            memtable.remove(key);
+            // Shadow older values with a tombstone
+            {
+                let memtable = self.memtable.read();
+                memtable.insert(key.to_vec(), Entry::Tombstone);
+            }
             self.buffer_pool.remove(key);
+            self.account_write(key.len())?;
         }
 
         Ok(existed)
@@ -117,80 +307,485 @@ impl LsmTree {
 
     /// Scan keys with a prefix
     pub fn scan(&self, prefix: &[u8]) -> Result<ScanIterator> {
+        self.scan_from(prefix, prefix)
+    }
+
+    /// Scan keys with a prefix, starting at the first key `>= start`
+    ///
+    /// Lets a scan resume where an earlier one stopped without reading the
+    /// keys before it.
+    ///
+    /// # Errors
+    ///
+    /// Does not currently fail; errors reading tables surface from the
+    /// iterator.
+    pub fn scan_from(&self, prefix: &[u8], start: &[u8]) -> Result<ScanIterator> {
+        let start = start.max(prefix);
+        let mut sources: Vec<EntryIterator> = Vec::new();
+
         let memtable = self.memtable.read().clone();
+        sources.push(Box::new(MemtableIterator::new(memtable, start)));
+
+        let immutables = self.immutable_memtables.read().clone();
+        for imm in immutables.into_iter().rev() {
+            sources.push(Box::new(MemtableIterator::new(imm, start)));
+        }
+
+        let version = self.version.read().clone();
+        sources.extend(version.iterators(prefix, start));
+
         Ok(ScanIterator {
-            memtable,
+            merge: MergeIterator::new(sources),
             prefix: prefix.to_vec(),
-            started: false,
+            done: false,
         })
     }
 
+    /// Track memtable growth and freeze it once it is full
+    fn account_write(&self, size: usize) -> Result<()> {
+        let current_size = self.memtable_size.fetch_add(size, Ordering::SeqCst);
+
+        // Check if we need to flush
+        if current_size + size > self.options.max_memtable_size {
+            self.maybe_flush()?;
+        }
+
+        Ok(())
+    }
+
     /// Maybe flush memtable to disk
     fn maybe_flush(&self) -> Result<()> {
-        let current_size = self
-            .memtable_size
-            .load(std::sync::atomic::Ordering::SeqCst);
+        let current_size = self.memtable_size.load(Ordering::SeqCst);
 
-        if current_size > self.max_memtable_size {
+        if current_size > self.options.max_memtable_size {
             debug!("Flushing memtable, size: {}", current_size);
 
-            // Swap memtable with a new one
-            let old_memtable = {
-                let mut memtable_lock = self.memtable.write();
-                std::mem::replace(&mut *memtable_lock, Arc::new(SkipMap::new()))
-            };
+            if self.rotate_memtable() >= self.options.max_immutable_memtables {
+                // The background worker is falling behind; apply backpressure
+                self.flush_immutables()?;
+            } else {
+                self.signal_worker(WorkerSignal::Flush);
+            }
+        }
+
+        Ok(())
+    }
 
-            // Add old memtable to immutable list
-            self.immutable_memtables.write().push(old_memtable);
+    /// Freeze the active memtable; returns the number of immutable memtables
+    fn rotate_memtable(&self) -> usize {
+        // Swap memtable with a new one; hold the immutable lock so readers
+        // never observe the frozen memtable in neither list
+        let mut immutables = self.immutable_memtables.write();
+        let old_memtable = {
+            let mut memtable_lock = self.memtable.write();
+            if memtable_lock.is_empty() {
+                return immutables.len();
+            }
+            std::mem::replace(&mut *memtable_lock, Arc::new(SkipMap::new()))
+        };
 
-            // Reset size
-            self.memtable_size
-                .store(0, std::sync::atomic::Ordering::SeqCst);
+        // Add old memtable to immutable list
+        immutables.push(old_memtable);
 
-            // TODO: Schedule background flush to SSTable
-        }
+        // Reset size
+        self.memtable_size.store(0, Ordering::SeqCst);
 
-        Ok(())
+        immutables.len()
+    }
+
+    /// Write every immutable memtable to a level-0 SSTable
+    fn flush_immutables(&self) -> Result<()> {
+        let _guard = self.flush_lock.lock();
+
+        loop {
+            let Some(memtable) = self.immutable_memtables.read().first().cloned() else {
+                return Ok(());
+            };
+
+            if !memtable.is_empty() {
+                let mut manifest = self.version.read().manifest.clone();
+                let id = manifest.allocate_file_id();
+                let path = Manifest::table_path(&self.path, id);
+
+                let mut writer = SsTableWriter::create(&path, self.options.sstable.clone())?;
+                for item in MemtableIterator::new(memtable.clone(), &[]) {
+                    let (key, entry) = item?;
+                    writer.add(&key, &entry)?;
+                }
+                let info = writer.finish()?;
+                let cipher = self.options.sstable.cipher.clone();
+                let table = Arc::new(SsTable::open(id, &path, cipher)?);
+
+                manifest.levels[0].insert(
+                    0,
+                    TableMeta {
+                        id,
+                        level: 0,
+                        size: info.size,
+                        entries: info.entries,
+                        smallest: info.smallest,
+                        largest: info.largest,
+                        key_version: info.key_version,
+                    },
+                );
+                manifest.save(&self.path, self.cipher())?;
+
+                // Publish the table before dropping the memtable so readers
+                // always find the data in one of the two places
+                let mut version = (**self.version.read()).clone();
+                version.manifest = manifest;
+                version.tables.insert(id, table);
+                *self.version.write() = Arc::new(version);
+
+                debug!(
+                    "Flushed memtable to SSTable {} ({} entries, {} bytes)",
+                    id, info.entries, info.size
+                );
+            }
+
+            let mut immutables = self.immutable_memtables.write();
+            if immutables
+                .first()
+                .is_some_and(|m| Arc::ptr_eq(m, &memtable))
+            {
+                immutables.remove(0);
+            }
+        }
     }
 
     /// Force flush all memtables
     pub fn flush(&self) -> Result<()> {
-        self.maybe_flush()
+        self.rotate_memtable();
+        self.flush_immutables()
+    }
+
+    /// Run compactions until no level exceeds its target
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an SSTable cannot be read or written.
+    pub fn compact(&self) -> Result<()> {
+        let _guard = self.flush_lock.lock();
+        while self.run_compaction()? {}
+        Ok(())
+    }
+
+    /// Ask the background worker to flush and compact now
+    ///
+    /// Used after a key rotation so re-encryption starts without waiting for
+    /// the next compaction interval.
+    pub fn schedule_compaction(&self) {
+        self.signal_worker(WorkerSignal::Flush);
+    }
+
+    /// Number of tables not yet sealed with the active data key
+    pub fn tables_pending_reencryption(&self) -> usize {
+        let Some(cipher) = self.cipher() else {
+            return 0;
+        };
+        let active = cipher.active_version();
+        self.version
+            .read()
+            .manifest
+            .levels
+            .iter()
+            .flatten()
+            .filter(|t| t.key_version != Some(active))
+            .count()
+    }
+
+    /// Data keys sealing the tree's tables, if encrypted
+    fn cipher(&self) -> Option<&DataKeys> {
+        self.options.sstable.cipher.as_deref()
     }
 
     /// Get approximate size
     pub fn approximate_size(&self) -> usize {
-        self.memtable_size
-            .load(std::sync::atomic::Ordering::SeqCst)
+        self.memtable_size.load(Ordering::SeqCst)
+    }
+
+    /// Number of SSTables in each level
+    pub fn level_table_counts(&self) -> Vec<usize> {
+        self.version
+            .read()
+            .manifest
+            .levels
+            .iter()
+            .map(Vec::len)
+            .collect()
+    }
+
+    /// Total size of SSTables on disk in bytes
+    pub fn disk_size(&self) -> u64 {
+        self.version
+            .read()
+            .manifest
+            .levels
+            .iter()
+            .flatten()
+            .map(|t| t.size)
+            .sum()
+    }
+
+    /// Stop the background worker and wait for it to exit
+    pub fn close(&self) {
+        self.signal_worker(WorkerSignal::Shutdown);
+        self.worker_tx.lock().take();
+
+        if let Some(handle) = self.worker.lock().take() {
+            if handle.thread().id() != std::thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    fn start_worker(self: &Arc<Self>) -> Result<()> {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let tree = Arc::downgrade(self);
+        let interval = self.options.compaction_interval;
+
+        let handle = std::thread::Builder::new()
+            .name("lsm-worker".to_string())
+            .spawn(move || loop {
+                match rx.recv_timeout(interval) {
+                    Ok(WorkerSignal::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+                    Ok(WorkerSignal::Flush) | Err(RecvTimeoutError::Timeout) => {}
+                }
+
+                let Some(tree) = tree.upgrade() else {
+                    break;
+                };
+
+                if let Err(e) = tree.flush_immutables() {
+                    error!("Background flush failed for {:?}: {}", tree.path, e);
+                    continue;
+                }
+                if let Err(e) = tree.compact() {
+                    error!("Background compaction failed for {:?}: {}", tree.path, e);
+                }
+            })?;
+
+        *self.worker_tx.lock() = Some(tx);
+        *self.worker.lock() = Some(handle);
+        Ok(())
+    }
+
+    fn signal_worker(&self, signal: WorkerSignal) {
+        if let Some(tx) = self.worker_tx.lock().as_ref() {
+            let _ = tx.send(signal);
+        }
+    }
+
+    /// Remove SSTables and temp files not referenced by the manifest
+    /// (left behind by a crash during flush or compaction)
+    fn remove_orphans(path: &Path, live: &HashMap<u64, Arc<SsTable>>) -> Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+
+            let orphan = match name.strip_suffix(".sst") {
+                Some(id) => id.parse::<u64>().is_ok_and(|id| !live.contains_key(&id)),
+                None => std::path::Path::new(name)
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("tmp")),
+            };
+
+            if orphan {
+                warn!("Removing orphaned LSM file {:?}", entry.path());
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LsmTree {
+    fn drop(&mut self) {
+        // Do not join here: the last reference may be dropped by the worker itself
+        self.signal_worker(WorkerSignal::Shutdown);
     }
 }
 
 /// Iterator for scanning keys
 pub struct ScanIterator {
-    memtable: Arc<SkipMap<Vec<u8>, Vec<u8>>>,
+    merge: MergeIterator,
     prefix: Vec<u8>,
-    started: bool,
+    done: bool,
 }
 
 impl Iterator for ScanIterator {
     type Item = Result<(Vec<u8>, Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.started {
-            self.started = true;
-        }
-
-        // Find next entry with prefix
-        for entry in self.memtable.iter() {
-            let key = entry.key();
-            if key.starts_with(&self.prefix) {
-                return Some(Ok((key.clone(), entry.value().clone())));
-            }
-            if key > &self.prefix {
-                break;
+        while !self.done {
+            match self.merge.next()? {
+                Ok((key, entry)) => {
+                    if !key.starts_with(&self.prefix) {
+                        self.done = true;
+                        return None;
+                    }
+                    if let Entry::Value(value) = entry {
+                        return Some(Ok((key, value)));
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
             }
         }
 
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_options() -> LsmOptions {
+        LsmOptions {
+            max_memtable_size: 4 * 1024,
+            level0_compaction_trigger: 2,
+            level_base_bytes: 16 * 1024,
+            target_file_size: 8 * 1024,
+            sstable: SsTableOptions {
+                block_size: 512,
+                ..SsTableOptions::default()
+            },
+            ..LsmOptions::default()
+        }
+    }
+
+    fn open(dir: &Path) -> Arc<LsmTree> {
+        LsmTree::open(dir, Arc::new(BufferPool::new(1024 * 1024)), small_options()).unwrap()
+    }
+
+    #[test]
+    fn test_flush_reopen_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let tree = open(dir.path());
+        for i in 0..2000u32 {
+            let key = format!("key{i:05}").into_bytes();
+            tree.put(key, vec![b'v'; 32]).unwrap();
+        }
+        for i in (0..2000u32).step_by(2) {
+            assert!(tree.delete(format!("key{i:05}").as_bytes()).unwrap());
+        }
+        tree.flush().unwrap();
+        tree.compact().unwrap();
+        tree.close();
+        drop(tree);
+
+        let tree = open(dir.path());
+        assert!(tree.level_table_counts()[0] < 2);
+        assert_eq!(tree.get(b"key00000").unwrap(), None);
+        assert_eq!(tree.get(b"key00001").unwrap(), Some(vec![b'v'; 32]));
+
+        let keys: Vec<_> = tree.scan(b"key").unwrap().map(|r| r.unwrap().0).collect();
+        assert_eq!(keys.len(), 1000);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // Resumed scans merge the memtable with the tables past the start key
+        tree.put(b"key01500x".to_vec(), b"new".to_vec()).unwrap();
+        let keys: Vec<_> = tree
+            .scan_from(b"key", b"key01000")
+            .unwrap()
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(keys.len(), 501);
+        assert_eq!(keys[0], b"key01001");
+        assert!(keys.contains(&b"key01500x".to_vec()));
+        assert_eq!(tree.scan_from(b"key", b"zzz").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_encrypted_tables_and_reencryption() {
+        use crate::encryption::{KeyManager, LocalKeyProvider};
+
+        let dir = tempfile::tempdir().unwrap();
+        let provider = LocalKeyProvider::new(vec![("1".to_string(), [7u8; 32])]).unwrap();
+        let keys = KeyManager::open(&dir.path().join("keys"), Arc::new(provider)).unwrap();
+        let cipher = keys.data_keys("collection:secret").unwrap();
+        let tree_dir = dir.path().join("tree");
+        let open = || {
+            let mut options = small_options();
+            options.sstable.cipher = Some(cipher.clone());
+            LsmTree::open(&tree_dir, Arc::new(BufferPool::new(1024 * 1024)), options).unwrap()
+        };
+        let on_disk = || -> Vec<u8> {
+            std::fs::read_dir(&tree_dir)
+                .unwrap()
+                .flat_map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+                .collect()
+        };
+        let contains =
+            |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+
+        let tree = open();
+        for i in 0..500u32 {
+            tree.put(
+                format!("key{i:05}").into_bytes(),
+                b"plaintext-marker".to_vec(),
+            )
+            .unwrap();
+        }
+        tree.flush().unwrap();
+        tree.compact().unwrap();
+        let bytes = on_disk();
+        assert!(!contains(&bytes, b"plaintext-marker"));
+        assert!(!contains(&bytes, b"key00042"));
+        assert_eq!(tree.tables_pending_reencryption(), 0);
+
+        // Rotation leaves the old tables readable until compaction reseals them
+        assert_eq!(keys.rotate("collection:secret").unwrap(), 2);
+        assert!(tree.tables_pending_reencryption() > 0);
+        assert_eq!(
+            tree.get(b"key00042").unwrap(),
+            Some(b"plaintext-marker".to_vec())
+        );
+        tree.compact().unwrap();
+        assert_eq!(tree.tables_pending_reencryption(), 0);
+        tree.close();
+        drop(tree);
+
+        let tree = open();
+        assert_eq!(
+            tree.get(b"key00499").unwrap(),
+            Some(b"plaintext-marker".to_vec())
+        );
+        assert_eq!(tree.scan(b"key").unwrap().count(), 500);
+        tree.close();
+        drop(tree);
+
+        // Without the key the manifest cannot be read
+        let result = LsmTree::open(&tree_dir, Arc::new(BufferPool::new(1024)), small_options());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sstable_rejects_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.sst");
+
+        let mut writer = SsTableWriter::create(&path, SsTableOptions::default()).unwrap();
+        writer.add(b"a", &Entry::Value(b"1".to_vec())).unwrap();
+        writer.add(b"b", &Entry::Tombstone).unwrap();
+        writer.finish().unwrap();
+
+        let table = SsTable::open(1, &path, None).unwrap();
+        assert_eq!(table.get(b"a").unwrap(), Some(Entry::Value(b"1".to_vec())));
+        assert_eq!(table.get(b"b").unwrap(), Some(Entry::Tombstone));
+        assert_eq!(table.get(b"c").unwrap(), None);
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[2] ^= 0xFF;
+        std::fs::write(&path, data).unwrap();
+        let table = SsTable::open(1, &path, None).unwrap();
+        assert!(table.get(b"a").is_err());
+    }
+}
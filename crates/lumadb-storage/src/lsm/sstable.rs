@@ -0,0 +1,618 @@
+//! Sorted String Table (SSTable) files
+//!
+//! On-disk layout:
+//!
+//! ```text
+//! [data block 0] ... [data block N] [index block] [bloom block] [footer]
+//! ```
+//!
+//! Every block is `[codec u8][payload][crc32 u32]`, where the CRC covers the
+//! codec byte and payload. Data block payloads are a run of
+//! `[key_len u32][kind u8][value_len u32][key][value]` records in key order.
+//! The index block holds `[last_key_len u32][last_key][offset u64][len u32]`
+//! for each data block, and the fixed-size footer locates the index and bloom
+//! blocks.
+//!
+//! Tables of an encrypted tree seal every block payload under the tree's
+//! data key (see [`crate::encryption`]), with the block offset as associated
+//! data so blocks cannot be moved around, and set `CODEC_SEALED` in the
+//! codec byte. Only the footer stays in the clear.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use lumadb_common::error::{Error, Result, StorageError};
+
+use crate::encryption::{DataKey, DataKeys};
+
+use super::bloom::BloomFilter;
+use super::Entry;
+
+/// Magic number identifying an SSTable footer ("LUMASST1")
+const MAGIC: u64 = 0x4C55_4D41_5353_5431;
+
+/// Footer size: index offset/len, bloom offset/len, entry count, magic
+const FOOTER_SIZE: usize = 8 + 4 + 8 + 4 + 8 + 8;
+
+/// Block codec: stored as-is
+const CODEC_NONE: u8 = 0;
+/// Block codec: LZ4 with prepended size
+const CODEC_LZ4: u8 = 1;
+/// Codec flag: the payload is sealed under the table's data key
+const CODEC_SEALED: u8 = 0x80;
+
+const KIND_VALUE: u8 = 0;
+const KIND_TOMBSTONE: u8 = 1;
+
+/// Options controlling SSTable layout
+#[derive(Debug, Clone)]
+pub struct SsTableOptions {
+    /// Target uncompressed size of a data block
+    pub block_size: usize,
+    /// Bloom filter bits per key
+    pub bloom_bits_per_key: usize,
+    /// Compress data blocks with LZ4
+    pub compression: bool,
+    /// Data keys sealing the blocks of new tables, if encrypted
+    pub cipher: Option<Arc<DataKeys>>,
+}
+
+impl Default for SsTableOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 4 * 1024,
+            bloom_bits_per_key: 10,
+            compression: true,
+            cipher: None,
+        }
+    }
+}
+
+/// Index entry describing one data block
+#[derive(Debug, Clone)]
+struct BlockHandle {
+    /// Largest key stored in the block
+    last_key: Vec<u8>,
+    /// Offset of the block in the file
+    offset: u64,
+    /// Length of the block including codec byte and CRC
+    len: u32,
+}
+
+/// Streaming SSTable builder; keys must be added in strictly increasing order
+pub struct SsTableWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    options: SsTableOptions,
+    /// Key sealing every block of this table
+    key: Option<DataKey>,
+    block: Vec<u8>,
+    block_last_key: Vec<u8>,
+    index: Vec<BlockHandle>,
+    keys: Vec<Vec<u8>>,
+    offset: u64,
+    entries: u64,
+    smallest: Option<Vec<u8>>,
+    largest: Vec<u8>,
+}
+
+impl SsTableWriter {
+    /// Create a writer for a new SSTable file at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: &Path, options: SsTableOptions) -> Result<Self> {
+        let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            key: options.cipher.as_ref().map(|cipher| cipher.current()),
+            options,
+            block: Vec::new(),
+            block_last_key: Vec::new(),
+            index: Vec::new(),
+            keys: Vec::new(),
+            offset: 0,
+            entries: 0,
+            smallest: None,
+            largest: Vec::new(),
+        })
+    }
+
+    /// Append an entry; keys must be added in strictly increasing order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `key` is out of order or the write fails.
+    pub fn add(&mut self, key: &[u8], entry: &Entry) -> Result<()> {
+        if self.smallest.is_some() && key <= self.largest.as_slice() {
+            return Err(Error::Storage(StorageError::WriteFailed(
+                "SSTable keys must be added in increasing order".to_string(),
+            )));
+        }
+
+        let (kind, value): (u8, &[u8]) = match entry {
+            Entry::Value(v) => (KIND_VALUE, v),
+            Entry::Tombstone => (KIND_TOMBSTONE, &[]),
+        };
+
+        self.block
+            .extend_from_slice(&len_u32(key.len())?.to_le_bytes());
+        self.block.push(kind);
+        self.block
+            .extend_from_slice(&len_u32(value.len())?.to_le_bytes());
+        self.block.extend_from_slice(key);
+        self.block.extend_from_slice(value);
+
+        self.block_last_key.clear();
+        self.block_last_key.extend_from_slice(key);
+        self.keys.push(key.to_vec());
+
+        if self.smallest.is_none() {
+            self.smallest = Some(key.to_vec());
+        }
+        self.largest.clear();
+        self.largest.extend_from_slice(key);
+        self.entries += 1;
+
+        if self.block.len() >= self.options.block_size {
+            self.finish_block()?;
+        }
+
+        Ok(())
+    }
+
+    /// Approximate number of bytes written so far
+    #[must_use]
+    pub fn estimated_size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Number of entries added so far
+    #[must_use]
+    pub fn entry_count(&self) -> u64 {
+        self.entries
+    }
+
+    /// Write index, bloom filter and footer, fsync, and return file metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or synced.
+    pub fn finish(mut self) -> Result<SsTableInfo> {
+        self.finish_block()?;
+
+        let mut index_payload = Vec::new();
+        for handle in &self.index {
+            index_payload.extend_from_slice(&len_u32(handle.last_key.len())?.to_le_bytes());
+            index_payload.extend_from_slice(&handle.last_key);
+            index_payload.extend_from_slice(&handle.offset.to_le_bytes());
+            index_payload.extend_from_slice(&handle.len.to_le_bytes());
+        }
+        let index_offset = self.offset;
+        let index_len = self.write_block(CODEC_NONE, &index_payload)?;
+
+        let mut bloom =
+            BloomFilter::with_capacity(self.keys.len(), self.options.bloom_bits_per_key);
+        for key in &self.keys {
+            bloom.insert(key);
+        }
+        let bloom_offset = self.offset;
+        let bloom_len = self.write_block(CODEC_NONE, &bloom.encode())?;
+
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        footer.extend_from_slice(&index_offset.to_le_bytes());
+        footer.extend_from_slice(&index_len.to_le_bytes());
+        footer.extend_from_slice(&bloom_offset.to_le_bytes());
+        footer.extend_from_slice(&bloom_len.to_le_bytes());
+        footer.extend_from_slice(&self.entries.to_le_bytes());
+        footer.extend_from_slice(&MAGIC.to_le_bytes());
+        self.writer.write_all(&footer)?;
+        self.offset += footer.len() as u64;
+
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+
+        Ok(SsTableInfo {
+            path: self.path,
+            size: self.offset,
+            entries: self.entries,
+            smallest: self.smallest.unwrap_or_default(),
+            largest: self.largest,
+            key_version: self.key.map(|key| key.version()),
+        })
+    }
+
+    fn finish_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let block = std::mem::take(&mut self.block);
+        let offset = self.offset;
+        let len = if self.options.compression {
+            let compressed = lz4_flex::compress_prepend_size(&block);
+            if compressed.len() < block.len() {
+                self.write_block(CODEC_LZ4, &compressed)?
+            } else {
+                self.write_block(CODEC_NONE, &block)?
+            }
+        } else {
+            self.write_block(CODEC_NONE, &block)?
+        };
+
+        self.index.push(BlockHandle {
+            last_key: self.block_last_key.clone(),
+            offset,
+            len,
+        });
+
+        Ok(())
+    }
+
+    fn write_block(&mut self, codec: u8, payload: &[u8]) -> Result<u32> {
+        let sealed;
+        let (codec, payload) = match &self.key {
+            Some(key) => {
+                sealed = key.seal(&self.offset.to_le_bytes(), payload)?;
+                (codec | CODEC_SEALED, sealed.as_slice())
+            }
+            None => (codec, payload),
+        };
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[codec]);
+        hasher.update(payload);
+        let crc = hasher.finalize();
+
+        self.writer.write_all(&[codec])?;
+        self.writer.write_all(payload)?;
+        self.writer.write_all(&crc.to_le_bytes())?;
+
+        let len = len_u32(payload.len() + 5)?;
+        self.offset += u64::from(len);
+        Ok(len)
+    }
+}
+
+/// Metadata about a finished SSTable
+#[derive(Debug, Clone)]
+pub struct SsTableInfo {
+    /// File path
+    pub path: PathBuf,
+    /// File size in bytes
+    pub size: u64,
+    /// Number of entries (including tombstones)
+    pub entries: u64,
+    /// Smallest key
+    pub smallest: Vec<u8>,
+    /// Largest key
+    pub largest: Vec<u8>,
+    /// Version of the data key sealing the table, if encrypted
+    pub key_version: Option<u32>,
+}
+
+/// Immutable, memory-mapped SSTable reader
+pub struct SsTable {
+    /// File identifier assigned by the manifest
+    id: u64,
+    /// Memory-mapped file contents
+    mmap: Mmap,
+    /// Block index
+    index: Vec<BlockHandle>,
+    /// Bloom filter over all keys
+    bloom: BloomFilter,
+    /// Number of entries
+    entries: u64,
+    /// Data keys for opening sealed blocks
+    cipher: Option<Arc<DataKeys>>,
+}
+
+impl SsTable {
+    /// Open and validate an SSTable file
+    ///
+    /// `cipher` must hold the key versions that sealed the table, if it is
+    /// encrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, fails validation, or
+    /// cannot be opened with `cipher`.
+    pub fn open(id: u64, path: &Path, cipher: Option<Arc<DataKeys>>) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: SSTables are immutable once written; files are only removed
+        // after no version references them, and unlinking keeps mappings valid.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < FOOTER_SIZE {
+            return Err(corrupt(path, "file too small"));
+        }
+
+        let footer = &mmap[mmap.len() - FOOTER_SIZE..];
+        let index_offset = read_u64(footer, 0);
+        let index_len = read_u32(footer, 8);
+        let bloom_offset = read_u64(footer, 12);
+        let bloom_len = read_u32(footer, 20);
+        let entries = read_u64(footer, 24);
+        let magic = read_u64(footer, 32);
+
+        if magic != MAGIC {
+            return Err(corrupt(path, "bad magic number"));
+        }
+
+        let (_, index_payload) = read_block(&mmap, index_offset, index_len, cipher.as_deref())
+            .map_err(|reason| corrupt(path, &format!("index block {reason}")))?;
+        let index = decode_index(&index_payload).ok_or_else(|| corrupt(path, "malformed index"))?;
+
+        let (_, bloom_payload) = read_block(&mmap, bloom_offset, bloom_len, cipher.as_deref())
+            .map_err(|reason| corrupt(path, &format!("bloom block {reason}")))?;
+        let bloom = BloomFilter::decode(&bloom_payload)
+            .ok_or_else(|| corrupt(path, "malformed bloom filter"))?;
+
+        Ok(Self {
+            id,
+            mmap,
+            index,
+            bloom,
+            entries,
+            cipher,
+        })
+    }
+
+    /// File identifier
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Number of entries (including tombstones)
+    #[must_use]
+    pub fn entry_count(&self) -> u64 {
+        self.entries
+    }
+
+    /// Look up a key; `None` means the table has no entry for it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a block cannot be read or is corrupt.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Entry>> {
+        if !self.bloom.may_contain(key) {
+            return Ok(None);
+        }
+
+        let block_idx = self.index.partition_point(|h| h.last_key.as_slice() < key);
+        if block_idx >= self.index.len() {
+            return Ok(None);
+        }
+
+        let block = self.load_block(block_idx)?;
+        for (k, entry) in BlockEntries::new(&block) {
+            match k.as_slice().cmp(key) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Ok(Some(entry)),
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterate entries with keys `>= start`
+    #[must_use]
+    pub fn iter_from(self: &Arc<Self>, start: &[u8]) -> SsTableIterator {
+        let block_idx = self
+            .index
+            .partition_point(|h| h.last_key.as_slice() < start);
+        SsTableIterator {
+            table: self.clone(),
+            block_idx,
+            entries: Vec::new().into_iter(),
+            start: Some(start.to_vec()),
+        }
+    }
+
+    /// Iterate all entries
+    #[must_use]
+    pub fn iter(self: &Arc<Self>) -> SsTableIterator {
+        self.iter_from(&[])
+    }
+
+    fn load_block(&self, idx: usize) -> Result<Vec<u8>> {
+        let handle = &self.index[idx];
+        let (codec, payload) = read_block(
+            &self.mmap,
+            handle.offset,
+            handle.len,
+            self.cipher.as_deref(),
+        )
+        .map_err(|reason| {
+            Error::Storage(StorageError::CorruptData(format!(
+                "SSTable {} block {} {}",
+                self.id, idx, reason
+            )))
+        })?;
+
+        match codec {
+            CODEC_NONE => Ok(payload),
+            CODEC_LZ4 => lz4_flex::decompress_size_prepended(&payload).map_err(|e| {
+                Error::Storage(StorageError::CorruptData(format!(
+                    "SSTable {} block {}: {}",
+                    self.id, idx, e
+                )))
+            }),
+            _ => Err(Error::Storage(StorageError::CorruptData(format!(
+                "SSTable {} block {}: unknown codec",
+                self.id, idx
+            )))),
+        }
+    }
+}
+
+/// Owned iterator over an SSTable's entries
+pub struct SsTableIterator {
+    table: Arc<SsTable>,
+    block_idx: usize,
+    entries: std::vec::IntoIter<(Vec<u8>, Entry)>,
+    start: Option<Vec<u8>>,
+}
+
+impl Iterator for SsTableIterator {
+    type Item = Result<(Vec<u8>, Entry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.entries.next() {
+                return Some(Ok(item));
+            }
+
+            if self.block_idx >= self.table.index.len() {
+                return None;
+            }
+
+            let block = match self.table.load_block(self.block_idx) {
+                Ok(block) => block,
+                Err(e) => {
+                    self.block_idx = self.table.index.len();
+                    return Some(Err(e));
+                }
+            };
+            self.block_idx += 1;
+
+            let start = self.start.take();
+            let entries: Vec<_> = BlockEntries::new(&block)
+                .filter(|(k, _)| start.as_ref().map_or(true, |s| k >= s))
+                .collect();
+            self.entries = entries.into_iter();
+        }
+    }
+}
+
+/// Decoder over the records of a data block
+struct BlockEntries<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BlockEntries<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl Iterator for BlockEntries<'_> {
+    type Item = (Vec<u8>, Entry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header_end = self.pos + 9;
+        if header_end > self.data.len() {
+            return None;
+        }
+
+        let key_len = read_u32(self.data, self.pos) as usize;
+        let kind = self.data[self.pos + 4];
+        let value_len = read_u32(self.data, self.pos + 5) as usize;
+
+        let key_end = header_end + key_len;
+        let value_end = key_end + value_len;
+        if value_end > self.data.len() {
+            return None;
+        }
+
+        let key = self.data[header_end..key_end].to_vec();
+        let entry = if kind == KIND_TOMBSTONE {
+            Entry::Tombstone
+        } else {
+            Entry::Value(self.data[key_end..value_end].to_vec())
+        };
+        self.pos = value_end;
+
+        Some((key, entry))
+    }
+}
+
+/// Read a block, verify its checksum and open it if sealed; returns the
+/// compression codec and payload, or why the block is unreadable
+fn read_block(
+    data: &[u8],
+    offset: u64,
+    len: u32,
+    cipher: Option<&DataKeys>,
+) -> std::result::Result<(u8, Vec<u8>), &'static str> {
+    const TRUNCATED: &str = "is truncated";
+    let start = usize::try_from(offset).map_err(|_| TRUNCATED)?;
+    let end = start.checked_add(len as usize).ok_or(TRUNCATED)?;
+    if len < 5 || end > data.len() {
+        return Err(TRUNCATED);
+    }
+
+    let body = &data[start..end - 4];
+    let expected = read_u32(data, end - 4);
+    if crc32fast::hash(body) != expected {
+        return Err("checksum mismatch");
+    }
+
+    let codec = body[0];
+    if codec & CODEC_SEALED == 0 {
+        return Ok((codec, body[1..].to_vec()));
+    }
+    let cipher = cipher.ok_or("is encrypted but no data key is configured")?;
+    let payload = cipher
+        .open(&offset.to_le_bytes(), &body[1..])
+        .map_err(|_| "failed decryption")?;
+    Ok((codec & !CODEC_SEALED, payload))
+}
+
+fn decode_index(data: &[u8]) -> Option<Vec<BlockHandle>> {
+    let mut handles = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let key_len = read_u32(data.get(pos..pos + 4)?, 0) as usize;
+        pos += 4;
+        let last_key = data.get(pos..pos + key_len)?.to_vec();
+        pos += key_len;
+        let offset = read_u64(data.get(pos..pos + 8)?, 0);
+        pos += 8;
+        let len = read_u32(data.get(pos..pos + 4)?, 0);
+        pos += 4;
+        handles.push(BlockHandle {
+            last_key,
+            offset,
+            len,
+        });
+    }
+
+    Some(handles)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn read_u64(data: &[u8], pos: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[pos..pos + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn len_u32(len: usize) -> Result<u32> {
+    u32::try_from(len).map_err(|_| {
+        Error::Storage(StorageError::CapacityExceeded(format!(
+            "SSTable record of {len} bytes is too large"
+        )))
+    })
+}
+
+fn corrupt(path: &Path, reason: &str) -> Error {
+    Error::Storage(StorageError::CorruptData(format!(
+        "SSTable {}: {reason}",
+        path.display()
+    )))
+}
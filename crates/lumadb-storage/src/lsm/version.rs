@@ -0,0 +1,313 @@
+//! Multi-version keys
+//!
+//! A versioned key is the user key, with zero bytes escaped and terminated
+//! by two zeros, followed by the bitwise complement of its commit timestamp.
+//! All versions of a key are therefore adjacent, newest first, and keys sort
+//! like the user keys they encode. Values carry a one-byte marker telling
+//! live values from deletions.
+
+use lumadb_common::error::{Error, Result, StorageError};
+
+use super::{LsmTree, ScanIterator};
+
+/// Marker of a version that deleted its key
+const DELETED: u8 = 0;
+/// Marker of a version holding a value
+const LIVE: u8 = 1;
+
+/// One committed version of a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyVersion {
+    /// Commit timestamp
+    pub ts: u64,
+    /// Value, or `None` for a deletion
+    pub value: Option<Vec<u8>>,
+}
+
+/// Append the escaped form of `key`, without its terminator
+fn escape(key: &[u8], out: &mut Vec<u8>) {
+    for &b in key {
+        out.push(b);
+        if b == 0 {
+            out.push(0xFF);
+        }
+    }
+}
+
+/// Encoded prefix shared by every version of `key`
+fn key_prefix(key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(key.len() + 10);
+    escape(key, &mut out);
+    out.extend_from_slice(&[0, 0]);
+    out
+}
+
+/// Storage key of `key` as committed at `ts`
+#[must_use]
+pub fn versioned_key(key: &[u8], ts: u64) -> Vec<u8> {
+    let mut out = key_prefix(key);
+    out.extend_from_slice(&(!ts).to_be_bytes());
+    out
+}
+
+/// Split a storage key into the user key and commit timestamp
+///
+/// # Errors
+///
+/// Returns an error if `encoded` is not a well-formed versioned key.
+pub fn decode_versioned_key(encoded: &[u8]) -> Result<(Vec<u8>, u64)> {
+    let corrupt = || {
+        Error::Storage(StorageError::CorruptData(format!(
+            "Malformed versioned key {:?}",
+            String::from_utf8_lossy(encoded)
+        )))
+    };
+
+    let mut key = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    loop {
+        match (encoded.get(i), encoded.get(i + 1)) {
+            (Some(0), Some(0)) => break,
+            (Some(0), Some(0xFF)) => {
+                key.push(0);
+                i += 2;
+            }
+            (Some(&b), _) if b != 0 => {
+                key.push(b);
+                i += 1;
+            }
+            _ => return Err(corrupt()),
+        }
+    }
+
+    let ts: [u8; 8] = encoded
+        .get(i + 2..)
+        .and_then(|rest| rest.try_into().ok())
+        .ok_or_else(corrupt)?;
+    Ok((key, !u64::from_be_bytes(ts)))
+}
+
+fn decode_value(mut value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    match value.first() {
+        Some(&LIVE) => {
+            value.remove(0);
+            Ok(Some(value))
+        }
+        Some(&DELETED) => Ok(None),
+        _ => Err(Error::Storage(StorageError::CorruptData(
+            "Versioned value without a marker".to_string(),
+        ))),
+    }
+}
+
+impl LsmTree {
+    /// Record the version of `key` committed at `ts`; `None` deletes it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the write cannot be recorded.
+    pub fn put_version(&self, key: &[u8], ts: u64, value: Option<&[u8]>) -> Result<()> {
+        let stored = match value {
+            Some(value) => {
+                let mut stored = Vec::with_capacity(value.len() + 1);
+                stored.push(LIVE);
+                stored.extend_from_slice(value);
+                stored
+            }
+            None => vec![DELETED],
+        };
+        self.put(versioned_key(key, ts), stored)
+    }
+
+    /// Newest version of `key` committed at or before `ts`, including
+    /// deletions
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read.
+    pub fn get_version(&self, key: &[u8], ts: u64) -> Result<Option<KeyVersion>> {
+        let prefix = key_prefix(key);
+        let Some(entry) = self.scan_from(&prefix, &versioned_key(key, ts))?.next() else {
+            return Ok(None);
+        };
+        let (encoded, value) = entry?;
+        let (_, ts) = decode_versioned_key(&encoded)?;
+        Ok(Some(KeyVersion {
+            ts,
+            value: decode_value(value)?,
+        }))
+    }
+
+    /// Value of `key` as of `ts`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read.
+    pub fn get_at(&self, key: &[u8], ts: u64) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_version(key, ts)?.and_then(|version| version.value))
+    }
+
+    /// Commit timestamp of the newest version of `key`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read.
+    pub fn latest_version(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(self.get_version(key, u64::MAX)?.map(|version| version.ts))
+    }
+
+    /// Iterate the keys starting with `prefix` that were live at `ts`, with
+    /// their values as of `ts`
+    ///
+    /// With `after` the scan starts at the first key sorting after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read.
+    pub fn scan_at(&self, prefix: &[u8], after: Option<&[u8]>, ts: u64) -> Result<VersionScan> {
+        let mut encoded_prefix = Vec::with_capacity(prefix.len());
+        escape(prefix, &mut encoded_prefix);
+
+        let entries = match after {
+            Some(after) => {
+                // Past every version of `after`, but before any longer key
+                let mut start = Vec::with_capacity(after.len() + 2);
+                escape(after, &mut start);
+                start.extend_from_slice(&[0, 1]);
+                self.scan_from(&encoded_prefix, &start)?
+            }
+            None => self.scan(&encoded_prefix)?,
+        };
+
+        Ok(VersionScan {
+            entries,
+            ts,
+            decided: None,
+        })
+    }
+
+    /// Remove versions no reader at or after `watermark` can see
+    ///
+    /// Of the versions committed at or before the watermark only the newest
+    /// is kept, and not even that one when it is a deletion. Returns the
+    /// number of versions removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read or the deletions cannot be
+    /// written.
+    pub fn collect_versions(&self, watermark: u64) -> Result<usize> {
+        let mut removed = 0;
+        // Key whose version visible at the watermark was already passed
+        let mut settled: Option<Vec<u8>> = None;
+
+        for entry in self.scan(&[])? {
+            let (encoded, value) = entry?;
+            let (key, ts) = decode_versioned_key(&encoded)?;
+            if ts > watermark {
+                continue;
+            }
+            if settled.as_ref() == Some(&key) {
+                self.delete(&encoded)?;
+                removed += 1;
+                continue;
+            }
+            if value.first() == Some(&DELETED) {
+                self.delete(&encoded)?;
+                removed += 1;
+            }
+            settled = Some(key);
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Iterator over the keys live at a timestamp, in key order
+pub struct VersionScan {
+    entries: ScanIterator,
+    ts: u64,
+    /// Key whose version visible at `ts` was already found
+    decided: Option<Vec<u8>>,
+}
+
+impl Iterator for VersionScan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let found = self.entries.next()?.and_then(|(encoded, value)| {
+                let (key, ts) = decode_versioned_key(&encoded)?;
+                if ts > self.ts || self.decided.as_ref() == Some(&key) {
+                    return Ok(None);
+                }
+                self.decided = Some(key.clone());
+                Ok(decode_value(value)?.map(|value| (key, value)))
+            });
+            match found {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cache::BufferPool;
+    use crate::lsm::LsmOptions;
+
+    #[test]
+    fn versions_are_read_as_of_a_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = LsmTree::open(
+            dir.path(),
+            Arc::new(BufferPool::new(1024 * 1024)),
+            LsmOptions::default(),
+        )
+        .unwrap();
+
+        tree.put_version(b"a", 10, Some(b"a10")).unwrap();
+        tree.put_version(b"a", 20, Some(b"a20")).unwrap();
+        tree.put_version(b"a\0b", 15, Some(b"ab15")).unwrap();
+        tree.put_version(b"b", 12, Some(b"b12")).unwrap();
+        tree.put_version(b"b", 18, None).unwrap();
+
+        assert_eq!(tree.get_at(b"a", 9).unwrap(), None);
+        assert_eq!(tree.get_at(b"a", 15).unwrap(), Some(b"a10".to_vec()));
+        assert_eq!(tree.get_at(b"a", 25).unwrap(), Some(b"a20".to_vec()));
+        assert_eq!(tree.get_at(b"b", 18).unwrap(), None);
+        assert_eq!(tree.latest_version(b"b").unwrap(), Some(18));
+
+        let keys = |ts| -> Vec<Vec<u8>> {
+            tree.scan_at(b"", None, ts)
+                .unwrap()
+                .map(|e| e.unwrap().0)
+                .collect()
+        };
+        assert_eq!(keys(11), vec![b"a".to_vec()]);
+        assert_eq!(
+            keys(16),
+            vec![b"a".to_vec(), b"a\0b".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(keys(30), vec![b"a".to_vec(), b"a\0b".to_vec()]);
+        let after: Vec<_> = tree
+            .scan_at(b"", Some(b"a"), 30)
+            .unwrap()
+            .map(|e| e.unwrap().0)
+            .collect();
+        assert_eq!(after, vec![b"a\0b".to_vec()]);
+
+        // Readers at 16 or later only need a20, a10 for reads before 20,
+        // and nothing of b once its deletion is older than every reader
+        assert_eq!(tree.collect_versions(16).unwrap(), 0);
+        assert_eq!(tree.collect_versions(25).unwrap(), 3);
+        assert_eq!(tree.get_at(b"a", 25).unwrap(), Some(b"a20".to_vec()));
+        assert_eq!(tree.latest_version(b"b").unwrap(), None);
+        assert_eq!(keys(30), vec![b"a".to_vec(), b"a\0b".to_vec()]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Multi-version concurrency control
+//!
+//! Every write is committed at a timestamp from the [`CommitClock`] and
+//! stored as a new version of its document. A reader picks a snapshot
+//! timestamp and sees exactly the writes committed at or before it, so
+//! transactions read a consistent state while others keep writing.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use lumadb_common::error::{Error, Result};
+use lumadb_common::types::Document;
+
+/// A document write committed by [`StorageEngine::commit`]
+///
+/// [`StorageEngine::commit`]: crate::StorageEngine::commit
+#[derive(Debug, Clone)]
+pub struct DocumentWrite {
+    /// Collection of the document
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// New document, or `None` to delete it
+    pub document: Option<Document>,
+}
+
+/// Data a serializable transaction read, validated when it commits
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReadKey {
+    /// A single document, read by ID
+    Document {
+        /// Collection of the document
+        collection: String,
+        /// Document ID
+        id: String,
+    },
+    /// Every document of a collection, read by a scan
+    Collection(String),
+}
+
+/// Issues commit timestamps and tracks which snapshots are still readable
+pub(crate) struct CommitClock {
+    state: Mutex<ClockState>,
+    /// Latest timestamp at or before which every commit has been applied
+    visible: watch::Sender<u64>,
+}
+
+struct ClockState {
+    /// Last timestamp issued
+    last: u64,
+    /// Commits that have a timestamp but are not fully applied yet
+    in_flight: BTreeSet<u64>,
+    /// Open snapshots and how many readers use each
+    snapshots: BTreeMap<u64, usize>,
+}
+
+impl ClockState {
+    fn visible(&self) -> u64 {
+        self.in_flight.first().map_or(self.last, |ts| ts - 1)
+    }
+}
+
+/// A commit timestamp, released when the commit has been applied
+pub(crate) struct CommitTimestamp<'a> {
+    clock: &'a CommitClock,
+    /// The timestamp
+    pub ts: u64,
+}
+
+impl Drop for CommitTimestamp<'_> {
+    fn drop(&mut self) {
+        let mut state = self.clock.state.lock();
+        state.in_flight.remove(&self.ts);
+        self.clock.visible.send_replace(state.visible());
+    }
+}
+
+impl CommitClock {
+    /// Create a clock issuing timestamps above `floor`
+    ///
+    /// Timestamps follow the wall clock in microseconds where they can, so
+    /// they stay above those of earlier runs.
+    pub(crate) fn new(floor: u64) -> Self {
+        let last = floor.max(now_micros());
+        Self {
+            state: Mutex::new(ClockState {
+                last,
+                in_flight: BTreeSet::new(),
+                snapshots: BTreeMap::new(),
+            }),
+            visible: watch::Sender::new(last),
+        }
+    }
+
+    /// Make sure later timestamps are above `ts`
+    pub(crate) fn observe(&self, ts: u64) {
+        let mut state = self.state.lock();
+        if ts > state.last {
+            state.last = ts;
+            self.visible.send_replace(state.visible());
+        }
+    }
+
+    /// Last timestamp issued
+    pub(crate) fn last(&self) -> u64 {
+        self.state.lock().last
+    }
+
+    /// Issue the timestamp of a new commit
+    ///
+    /// Snapshots stay below it until the returned guard is dropped.
+    pub(crate) fn begin_commit(&self) -> CommitTimestamp<'_> {
+        let mut state = self.state.lock();
+        let ts = (state.last + 1).max(now_micros());
+        state.last = ts;
+        state.in_flight.insert(ts);
+        self.visible.send_replace(state.visible());
+        CommitTimestamp { clock: self, ts }
+    }
+
+    /// Wait until every commit with a timestamp below `ts` has been applied
+    pub(crate) async fn wait_for_earlier(&self, ts: u64) -> Result<()> {
+        self.visible
+            .subscribe()
+            .wait_for(|visible| *visible >= ts - 1)
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Open a snapshot of everything committed so far
+    pub(crate) fn begin_snapshot(&self) -> u64 {
+        let mut state = self.state.lock();
+        let ts = state.visible();
+        *state.snapshots.entry(ts).or_insert(0) += 1;
+        ts
+    }
+
+    /// Close a snapshot opened by [`CommitClock::begin_snapshot`]
+    pub(crate) fn release_snapshot(&self, ts: u64) {
+        let mut state = self.state.lock();
+        if let Some(readers) = state.snapshots.get_mut(&ts) {
+            *readers -= 1;
+            if *readers == 0 {
+                state.snapshots.remove(&ts);
+            }
+        }
+    }
+
+    /// Oldest timestamp any current or future snapshot reads at
+    pub(crate) fn watermark(&self) -> u64 {
+        let state = self.state.lock();
+        state
+            .snapshots
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or_else(|| state.visible())
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_micros()).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_stay_below_unapplied_commits() {
+        let clock = CommitClock::new(0);
+        let first = clock.begin_commit();
+        let second = clock.begin_commit();
+        assert!(second.ts > first.ts);
+
+        // Nothing after the oldest unapplied commit is visible
+        drop(second);
+        assert_eq!(clock.state.lock().visible(), first.ts - 1);
+        let snapshot = clock.begin_snapshot();
+        assert_eq!(snapshot, first.ts - 1);
+
+        drop(first);
+        let last = clock.last();
+        assert_eq!(clock.state.lock().visible(), last);
+        assert_eq!(clock.watermark(), snapshot);
+        clock.release_snapshot(snapshot);
+        assert_eq!(clock.watermark(), clock.last());
+    }
+}
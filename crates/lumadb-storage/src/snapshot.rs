@@ -0,0 +1,291 @@
+//! Collection snapshot files
+//!
+//! A snapshot holds everything needed to recreate a collection on another
+//! node: its metadata (vector settings and secondary, payload and text
+//! indexes) and every document with its vector. After the `LUMASNAP`
+//! magic, the file is a sequence of frames `[len u32][crc32 u32][payload]`,
+//! each payload a JSON [`SnapshotRecord`]: a header, the documents in ID
+//! order, then an end record counting them, so a truncated file is
+//! detected. A snapshot is complete once it is renamed into place.
+//!
+//! Snapshot files are written in the clear, since the node restoring one
+//! may not share the data keys of the node that took it. A
+//! `<name>.checksum` file next to each holds the SHA-256 of its contents.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{Error, Result, StorageError};
+use lumadb_common::types::{CollectionMetadata, Document};
+
+/// Extension of snapshot files
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+/// Extension of the checksum file kept next to a snapshot
+const CHECKSUM_EXTENSION: &str = "checksum";
+
+/// First bytes of every snapshot file
+const MAGIC: &[u8; 8] = b"LUMASNAP";
+
+/// Version of the snapshot format written
+const VERSION: u32 = 1;
+
+/// Frame header: length (4) + crc (4)
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// One record of a snapshot file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SnapshotRecord {
+    /// First record: what the collection looked like
+    Header {
+        version: u32,
+        collection: CollectionMetadata,
+        created_at: i64,
+    },
+    /// A document and its vector, if it has one
+    Document {
+        document: Document,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        vector: Option<Vec<f32>>,
+    },
+    /// Last record: how many documents came before it
+    End { documents: u64 },
+}
+
+/// A snapshot file of a collection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDescription {
+    /// File name
+    pub name: String,
+    /// When the snapshot was taken, as `%Y-%m-%dT%H:%M:%S` in UTC
+    pub creation_time: Option<String>,
+    /// File size in bytes
+    pub size: u64,
+    /// SHA-256 of the file, in hex
+    pub checksum: Option<String>,
+}
+
+impl SnapshotDescription {
+    /// Describe the snapshot file at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a snapshot.
+    pub fn read(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let creation_time = metadata.modified().ok().map(|time| {
+            chrono::DateTime::<chrono::Utc>::from(time)
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string()
+        });
+        let checksum = fs::read_to_string(checksum_path(path))
+            .ok()
+            .map(|checksum| checksum.trim().to_string());
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            creation_time,
+            size: metadata.len(),
+            checksum,
+        })
+    }
+}
+
+/// Path of the checksum file of the snapshot at `path`
+pub(crate) fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(CHECKSUM_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// SHA-256 of a file, in hex
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn file_checksum(path: &Path) -> Result<String> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut digest = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        digest.update(&buf[..read]);
+    }
+    Ok(hex::encode(digest.finish()))
+}
+
+/// Writes a snapshot to a temporary file, moved into place by
+/// [`SnapshotWriter::finish`]
+pub(crate) struct SnapshotWriter {
+    path: PathBuf,
+    tmp: PathBuf,
+    file: BufWriter<File>,
+    documents: u64,
+}
+
+impl SnapshotWriter {
+    /// Start a snapshot of the collection described by `metadata`
+    pub fn create(path: &Path, metadata: &CollectionMetadata) -> Result<Self> {
+        let tmp = path.with_extension("tmp");
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        file.write_all(MAGIC)?;
+        let mut writer = Self {
+            path: path.to_path_buf(),
+            tmp,
+            file,
+            documents: 0,
+        };
+        writer.write(&SnapshotRecord::Header {
+            version: VERSION,
+            collection: metadata.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+        })?;
+        Ok(writer)
+    }
+
+    /// Add a document and its vector
+    pub fn document(&mut self, document: Document, vector: Option<Vec<f32>>) -> Result<()> {
+        self.documents += 1;
+        self.write(&SnapshotRecord::Document { document, vector })
+    }
+
+    /// Complete the snapshot: sync it, move it into place and record its
+    /// checksum
+    pub fn finish(mut self) -> Result<SnapshotDescription> {
+        self.write(&SnapshotRecord::End {
+            documents: self.documents,
+        })?;
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        fs::rename(&self.tmp, &self.path)?;
+        fs::write(checksum_path(&self.path), file_checksum(&self.path)?)?;
+        SnapshotDescription::read(&self.path)
+    }
+
+    fn write(&mut self, record: &SnapshotRecord) -> Result<()> {
+        let payload = serde_json::to_vec(record)?;
+        let len = u32::try_from(payload.len()).map_err(|_| {
+            Error::Storage(StorageError::WriteFailed(
+                "Snapshot record exceeds 4 GiB".to_string(),
+            ))
+        })?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file
+            .write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        self.file.write_all(&payload)?;
+        Ok(())
+    }
+}
+
+impl Drop for SnapshotWriter {
+    fn drop(&mut self) {
+        // Left behind only by a snapshot that failed part way
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+/// Reads a snapshot file, checking every frame
+pub(crate) struct SnapshotReader {
+    path: PathBuf,
+    file: BufReader<File>,
+    collection: CollectionMetadata,
+    documents: u64,
+    done: bool,
+}
+
+impl SnapshotReader {
+    /// Open a snapshot and read its header
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        if file.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Err(corrupt(path, "is not a snapshot file"));
+        }
+        let collection = match read_record(&mut file, path)? {
+            SnapshotRecord::Header {
+                version,
+                collection,
+                ..
+            } if version <= VERSION => collection,
+            SnapshotRecord::Header { version, .. } => {
+                return Err(corrupt(
+                    path,
+                    &format!("has unsupported snapshot version {version}"),
+                ));
+            }
+            _ => return Err(corrupt(path, "does not start with a header")),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            collection,
+            documents: 0,
+            done: false,
+        })
+    }
+
+    /// The collection as it was when the snapshot was taken
+    pub fn collection(&self) -> &CollectionMetadata {
+        &self.collection
+    }
+
+    /// The next document and its vector, or `None` once the end record
+    /// confirms every document was read
+    pub fn next_document(&mut self) -> Result<Option<(Document, Option<Vec<f32>>)>> {
+        if self.done {
+            return Ok(None);
+        }
+        match read_record(&mut self.file, &self.path)? {
+            SnapshotRecord::Document { document, vector } => {
+                self.documents += 1;
+                Ok(Some((document, vector)))
+            }
+            SnapshotRecord::End { documents } if documents == self.documents => {
+                let mut rest = [0; 1];
+                if self.file.read(&mut rest)? != 0 {
+                    return Err(corrupt(&self.path, "has data after its end record"));
+                }
+                self.done = true;
+                Ok(None)
+            }
+            SnapshotRecord::End { .. } => Err(corrupt(&self.path, "is missing documents")),
+            SnapshotRecord::Header { .. } => Err(corrupt(&self.path, "has a second header")),
+        }
+    }
+}
+
+/// Read the next frame of a snapshot and decode its record
+fn read_record(file: &mut BufReader<File>, path: &Path) -> Result<SnapshotRecord> {
+    let mut header = [0; FRAME_HEADER_SIZE];
+    file.read_exact(&mut header)
+        .map_err(|_| corrupt(path, "is truncated"))?;
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    // Read incrementally, so a corrupt length cannot allocate gigabytes
+    let mut payload = Vec::new();
+    file.take(u64::from(len)).read_to_end(&mut payload)?;
+    if payload.len() != len as usize {
+        return Err(corrupt(path, "is truncated"));
+    }
+    if crc32fast::hash(&payload) != crc {
+        return Err(corrupt(path, "failed its checksum"));
+    }
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+fn corrupt(path: &Path, problem: &str) -> Error {
+    Error::Storage(StorageError::CorruptData(format!(
+        "Snapshot {} {problem}",
+        path.display()
+    )))
+}
@@ -0,0 +1,386 @@
+//! Payload filters for vector searches
+//!
+//! A filter is a tree of conditions on the JSON payload stored with each
+//! vector (the document the vector belongs to) and on its ID. The
+//! compatibility layers translate Qdrant and Pinecone filters into it.
+//!
+//! Field keys are dotted paths. Arrays along a path are flattened, so a
+//! condition on a field holding an array holds when it holds for any
+//! element, and `tags` or `tags[]` both address the elements of `tags`.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+/// Mean Earth radius in meters, for geo distances
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// A condition a point must meet to be returned by a filtered search
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadFilter {
+    /// Every filter holds (true when empty)
+    And(Vec<PayloadFilter>),
+    /// At least one filter holds (false when empty)
+    Or(Vec<PayloadFilter>),
+    /// The filter does not hold
+    Not(Box<PayloadFilter>),
+    /// A value of the field equals one of `values`
+    Match { key: String, values: Vec<Value> },
+    /// A string value of the field contains `text`
+    Text { key: String, text: String },
+    /// A numeric value of the field is within the bounds
+    Range { key: String, range: NumberRange },
+    /// A geo point in the field lies within `radius` meters of `center`
+    GeoRadius {
+        key: String,
+        center: GeoPoint,
+        radius: f64,
+    },
+    /// A geo point in the field lies within the box
+    GeoBoundingBox {
+        key: String,
+        top_left: GeoPoint,
+        bottom_right: GeoPoint,
+    },
+    /// The number of non-null values of the field is within the bounds
+    ValuesCount { key: String, range: NumberRange },
+    /// The field is missing, null or an empty array
+    IsEmpty { key: String },
+    /// The field holds null
+    IsNull { key: String },
+    /// The point has one of these IDs
+    HasId(HashSet<String>),
+    /// An object in the field (or in the array it holds) matches `filter`
+    Nested {
+        key: String,
+        filter: Box<PayloadFilter>,
+    },
+}
+
+/// Bounds on a number; unset bounds always hold
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NumberRange {
+    pub gt: Option<f64>,
+    pub gte: Option<f64>,
+    pub lt: Option<f64>,
+    pub lte: Option<f64>,
+}
+
+impl NumberRange {
+    /// Whether `value` is within the bounds
+    #[must_use]
+    pub fn contains(&self, value: f64) -> bool {
+        self.gt.map_or(true, |b| value > b)
+            && self.gte.map_or(true, |b| value >= b)
+            && self.lt.map_or(true, |b| value < b)
+            && self.lte.map_or(true, |b| value <= b)
+    }
+}
+
+/// A point on Earth in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    /// Read a `{"lat": .., "lon": ..}` object
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(Self {
+            lat: value.get("lat")?.as_f64()?,
+            lon: value.get("lon")?.as_f64()?,
+        })
+    }
+
+    /// Great-circle distance in meters
+    fn distance(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+    }
+}
+
+impl PayloadFilter {
+    /// Filter that holds when all of `filters` do
+    #[must_use]
+    pub fn all(filters: Vec<PayloadFilter>) -> Self {
+        match <[PayloadFilter; 1]>::try_from(filters) {
+            Ok([filter]) => filter,
+            Err(filters) => PayloadFilter::And(filters),
+        }
+    }
+
+    /// Filter that holds when any of `filters` does
+    #[must_use]
+    pub fn any(filters: Vec<PayloadFilter>) -> Self {
+        match <[PayloadFilter; 1]>::try_from(filters) {
+            Ok([filter]) => filter,
+            Err(filters) => PayloadFilter::Or(filters),
+        }
+    }
+
+    /// Negation of `filter`
+    #[must_use]
+    pub fn negate(filter: PayloadFilter) -> Self {
+        PayloadFilter::Not(Box::new(filter))
+    }
+
+    /// Whether the point with `id` and `payload` passes the filter
+    pub fn matches(&self, id: &str, payload: &Value) -> bool {
+        match self {
+            PayloadFilter::And(filters) => filters.iter().all(|f| f.matches(id, payload)),
+            PayloadFilter::Or(filters) => filters.iter().any(|f| f.matches(id, payload)),
+            PayloadFilter::Not(filter) => !filter.matches(id, payload),
+            PayloadFilter::Match { key, values } => field_values(payload, key)
+                .into_iter()
+                .any(|value| values.iter().any(|v| values_equal(value, v))),
+            PayloadFilter::Text { key, text } => field_values(payload, key)
+                .into_iter()
+                .any(|value| value.as_str().is_some_and(|s| s.contains(text.as_str()))),
+            PayloadFilter::Range { key, range } => field_values(payload, key)
+                .into_iter()
+                .any(|value| value.as_f64().is_some_and(|n| range.contains(n))),
+            PayloadFilter::GeoRadius {
+                key,
+                center,
+                radius,
+            } => field_values(payload, key)
+                .into_iter()
+                .filter_map(GeoPoint::from_value)
+                .any(|point| point.distance(center) <= *radius),
+            PayloadFilter::GeoBoundingBox {
+                key,
+                top_left,
+                bottom_right,
+            } => field_values(payload, key)
+                .into_iter()
+                .filter_map(GeoPoint::from_value)
+                .any(|point| in_box(&point, top_left, bottom_right)),
+            PayloadFilter::ValuesCount { key, range } => {
+                #[allow(clippy::cast_precision_loss)] // small counts
+                let count = field_values(payload, key)
+                    .into_iter()
+                    .filter(|value| !value.is_null())
+                    .count() as f64;
+                range.contains(count)
+            }
+            PayloadFilter::IsEmpty { key } => {
+                field_values(payload, key).into_iter().all(Value::is_null)
+            }
+            PayloadFilter::IsNull { key } => {
+                field_values(payload, key).into_iter().any(Value::is_null)
+            }
+            PayloadFilter::HasId(ids) => ids.contains(id),
+            PayloadFilter::Nested { key, filter } => field_values(payload, key)
+                .into_iter()
+                .any(|value| value.is_object() && filter.matches(id, value)),
+        }
+    }
+}
+
+/// Values at a dotted path, with arrays along the way and at the end
+/// flattened into their elements
+pub(crate) fn field_values<'a>(payload: &'a Value, key: &str) -> Vec<&'a Value> {
+    let mut values = vec![payload];
+    for part in key.split('.') {
+        let part = part.strip_suffix("[]").unwrap_or(part);
+        values = flatten(values)
+            .into_iter()
+            .filter_map(|value| value.as_object()?.get(part))
+            .collect();
+    }
+    flatten(values)
+}
+
+fn flatten(values: Vec<&Value>) -> Vec<&Value> {
+    values
+        .into_iter()
+        .flat_map(|value| match value {
+            Value::Array(items) => items.iter().collect(),
+            _ => vec![value],
+        })
+        .collect()
+}
+
+/// JSON equality, comparing numbers by value (so `1` equals `1.0`)
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// Whether `point` lies in the box, which may cross the antimeridian
+fn in_box(point: &GeoPoint, top_left: &GeoPoint, bottom_right: &GeoPoint) -> bool {
+    let lat_ok = point.lat <= top_left.lat && point.lat >= bottom_right.lat;
+    let lon_ok = if top_left.lon <= bottom_right.lon {
+        point.lon >= top_left.lon && point.lon <= bottom_right.lon
+    } else {
+        point.lon >= top_left.lon || point.lon <= bottom_right.lon
+    };
+    lat_ok && lon_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(filter: &PayloadFilter, payload: &Value) -> bool {
+        filter.matches("p1", payload)
+    }
+
+    fn sample() -> Value {
+        json!({
+            "city": "Berlin",
+            "tags": ["a", "b"],
+            "price": 12,
+            "note": null,
+            "empty": [],
+            "location": { "lat": 52.52, "lon": 13.405 },
+            "offers": [{ "shop": "x", "price": 10 }, { "shop": "y", "price": 30 }],
+        })
+    }
+
+    fn key(k: &str) -> String {
+        k.to_string()
+    }
+
+    #[test]
+    fn test_match_and_range() {
+        let payload = sample();
+
+        let city = PayloadFilter::Match {
+            key: key("city"),
+            values: vec![json!("Berlin"), json!("Paris")],
+        };
+        assert!(matches(&city, &payload));
+        let tag = PayloadFilter::Match {
+            key: key("tags[]"),
+            values: vec![json!("b")],
+        };
+        assert!(matches(&tag, &payload));
+        let price = PayloadFilter::Match {
+            key: key("price"),
+            values: vec![json!(12.0)],
+        };
+        assert!(matches(&price, &payload));
+
+        let range = |gte, lt| NumberRange {
+            gte: Some(gte),
+            lt: Some(lt),
+            ..NumberRange::default()
+        };
+        assert!(matches(
+            &PayloadFilter::Range {
+                key: key("price"),
+                range: range(12.0, 13.0)
+            },
+            &payload
+        ));
+        assert!(!matches(
+            &PayloadFilter::Range {
+                key: key("price"),
+                range: range(13.0, 20.0)
+            },
+            &payload
+        ));
+        // Arrays of objects are flattened along the path
+        assert!(matches(
+            &PayloadFilter::Range {
+                key: key("offers.price"),
+                range: range(25.0, 35.0)
+            },
+            &payload
+        ));
+
+        // The same object must satisfy every condition of a nested filter
+        let offer = |shop: &str, max: f64| PayloadFilter::Nested {
+            key: key("offers"),
+            filter: Box::new(PayloadFilter::all(vec![
+                PayloadFilter::Match {
+                    key: key("shop"),
+                    values: vec![json!(shop)],
+                },
+                PayloadFilter::Range {
+                    key: key("price"),
+                    range: NumberRange {
+                        lte: Some(max),
+                        ..NumberRange::default()
+                    },
+                },
+            ])),
+        };
+        assert!(matches(&offer("x", 15.0), &payload));
+        assert!(!matches(&offer("y", 15.0), &payload));
+    }
+
+    #[test]
+    fn test_empty_geo_and_ids() {
+        let payload = sample();
+        for (field, empty) in [
+            ("missing", true),
+            ("note", true),
+            ("empty", true),
+            ("tags", false),
+        ] {
+            assert_eq!(
+                matches(&PayloadFilter::IsEmpty { key: key(field) }, &payload),
+                empty
+            );
+        }
+        assert!(matches(
+            &PayloadFilter::IsNull { key: key("note") },
+            &payload
+        ));
+        assert!(!matches(
+            &PayloadFilter::IsNull {
+                key: key("missing")
+            },
+            &payload
+        ));
+        assert!(matches(
+            &PayloadFilter::ValuesCount {
+                key: key("tags"),
+                range: NumberRange {
+                    gte: Some(2.0),
+                    ..NumberRange::default()
+                },
+            },
+            &payload
+        ));
+
+        let hamburg = GeoPoint {
+            lat: 53.55,
+            lon: 9.99,
+        };
+        let near = |radius| PayloadFilter::GeoRadius {
+            key: key("location"),
+            center: hamburg,
+            radius,
+        };
+        assert!(!matches(&near(200_000.0), &payload));
+        assert!(matches(&near(300_000.0), &payload));
+        let germany = PayloadFilter::GeoBoundingBox {
+            key: key("location"),
+            top_left: GeoPoint {
+                lat: 55.0,
+                lon: 5.9,
+            },
+            bottom_right: GeoPoint {
+                lat: 47.3,
+                lon: 15.0,
+            },
+        };
+        assert!(matches(&germany, &payload));
+
+        let ids = PayloadFilter::HasId(HashSet::from(["p1".to_string()]));
+        assert!(matches(&ids, &payload));
+        assert!(!matches(&PayloadFilter::negate(ids), &payload));
+        assert!(matches(&PayloadFilter::And(vec![]), &payload));
+        assert!(!matches(&PayloadFilter::Or(vec![]), &payload));
+    }
+}
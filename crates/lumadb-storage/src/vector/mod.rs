@@ -1,53 +1,226 @@
 //! Vector similarity search using HNSW
+//!
+//! Nodes live in slots of one graph, linked per layer by slot number.
+//! Deleting a vector tombstones its node: the node's neighbors are relinked
+//! around it, and it keeps routing searches (but is never returned) until
+//! tombstones make up a quarter of the graph and the graph is vacuumed.
+//! Updating a vector relinks its node in place.
+//!
+//! A persistent index ([`VectorIndex::open`]) logs every change before
+//! applying it and snapshots the graph every [`SNAPSHOT_INTERVAL`] changes
+//! and on [`VectorIndex::snapshot`]; the `store` module describes the files.
+//!
+//! Filtered searches take a predicate on point IDs (the storage engine
+//! checks [`PayloadFilter`]s with it) and apply it while traversing the
+//! bottom layer, so rejected nodes still route the search. Very selective
+//! filters are better served by [`VectorIndex::search_exact`], which only
+//! visits the points a [`PayloadIndex`] bounds the filter to.
+//!
+//! A quantized index stores vectors in full until it holds
+//! [`QUANTIZE_MIN_VECTORS`] of them, then trains a quantizer on a sample,
+//! replaces every vector with its code and moves the originals out of the
+//! graph (to disk for persistent indexes; see the `originals` module).
+//! Searches traverse the graph comparing the query with codes, then rescore
+//! the best candidates, [`DEFAULT_OVERSAMPLING`] times as many as asked
+//! for, against the originals.
 
-use std::collections::BinaryHeap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+mod filter;
+mod originals;
+mod payload;
+mod quantization;
+mod store;
 
-use dashmap::DashMap;
-use parking_lot::RwLock;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::{Mutex, RwLock};
+use rand::seq::SliceRandom;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use lumadb_common::error::{Error, Result, StorageError};
+use lumadb_common::types::{DistanceMetric, VectorIndexConfig, VectorQuantization};
+
+use crate::encryption::DataKeys;
+use originals::Originals;
+use quantization::{Measured, Quantizer};
+use store::{VectorOp, VectorStore};
+
+pub use filter::{GeoPoint, NumberRange, PayloadFilter};
+pub use payload::PayloadIndex;
+pub(crate) use filter::field_values;
+
+/// Changes logged between automatic snapshots of a persistent index
+pub const SNAPSHOT_INTERVAL: u64 = 10_000;
+
+/// Graphs smaller than this are never vacuumed
+const VACUUM_MIN_NODES: usize = 64;
+
+/// Highest layer a node can be assigned
+const MAX_LEVEL: usize = 16;
+
+/// Vectors a quantized index holds before it trains its quantizer and
+/// starts storing codes
+pub const QUANTIZE_MIN_VECTORS: usize = 1_024;
+
+/// Most vectors a quantizer is trained on
+const TRAIN_SAMPLE: usize = 4_096;
+
+/// Candidates rescored per result wanted, unless a search says otherwise
+pub const DEFAULT_OVERSAMPLING: f32 = 2.0;
+
+/// Original vectors read back when timing rescoring
+const MEASURE_READS: usize = 32;
 
-use lumadb_common::types::DistanceMetric;
+/// How a vector search runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    /// Size of the candidate list; the index's `hnsw_ef_search` if unset
+    pub ef: Option<usize>,
+    /// Compare the query with every vector instead of traversing the graph
+    pub exact: bool,
+    /// Score quantized candidates by their codes alone, without reading
+    /// the original vectors back
+    pub skip_rescore: bool,
+    /// Quantized candidates rescored per result wanted (at least 1);
+    /// [`DEFAULT_OVERSAMPLING`] if unset
+    pub oversampling: Option<f32>,
+}
+
+/// What quantization saves and costs an index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantizationReport {
+    /// Configured method
+    pub method: VectorQuantization,
+    /// Whether vectors are stored as codes yet, which they are once the
+    /// index has held [`QUANTIZE_MIN_VECTORS`]
+    pub trained: bool,
+    /// Bytes of a vector's code
+    pub code_bytes: usize,
+    /// Bytes of a full vector
+    pub vector_bytes: usize,
+    /// Bytes of the vectors and codes held in memory
+    pub memory_bytes: usize,
+    /// Share of the 10 nearest neighbors found by comparing codes alone,
+    /// measured on the training sample
+    pub recall: Option<f32>,
+    /// The same, rescoring [`DEFAULT_OVERSAMPLING`] times as many
+    /// candidates against the original vectors
+    pub rescored_recall: Option<f32>,
+    /// Mean time to compare a query with a code, in nanoseconds
+    pub code_distance_ns: Option<f32>,
+    /// Mean time to compare a query with a full vector, in nanoseconds
+    pub full_distance_ns: Option<f32>,
+    /// Mean time to read an original vector back to rescore it, in
+    /// nanoseconds
+    pub original_read_ns: Option<f32>,
+}
 
 /// HNSW-based vector index
 pub struct VectorIndex {
-    /// Number of dimensions
-    dimensions: usize,
-    /// Distance metric
-    metric: DistanceMetric,
-    /// Vectors stored by ID
-    vectors: DashMap<String, Vec<f32>>,
-    /// HNSW graph layers
-    layers: RwLock<Vec<HnswLayer>>,
-    /// Entry point
-    entry_point: RwLock<Option<String>>,
-    /// Maximum layer
-    max_layer: AtomicUsize,
-    /// HNSW M parameter (max connections per layer)
-    m: usize,
+    /// Dimensions, metric and HNSW parameters
+    config: VectorIndexConfig,
     /// HNSW M0 parameter (max connections at layer 0)
     m0: usize,
-    /// ef_construction parameter
-    ef_construction: usize,
+    /// Nodes and links
+    graph: RwLock<Graph>,
+    /// Change log and snapshots, for persistent indexes
+    store: Option<Mutex<VectorStore>>,
+    /// Indexes of the payloads stored with the vectors
+    payload: PayloadIndex,
 }
 
-struct HnswLayer {
-    /// Neighbors for each node
-    neighbors: DashMap<String, Vec<String>>,
+/// The HNSW graph
+#[derive(Default, Serialize, Deserialize)]
+struct Graph {
+    /// Nodes by slot, including tombstones
+    nodes: Vec<Node>,
+    /// Slots of live nodes by ID (rebuilt when a snapshot is loaded)
+    #[serde(skip)]
+    slots: HashMap<String, u32>,
+    /// Slot searches start from; always a live node
+    entry_point: Option<u32>,
+    /// Highest layer of any node
+    max_level: usize,
+    /// Number of tombstones
+    deleted: usize,
+    /// Quantizer of a quantized index, once trained
+    quantizer: Option<Trained>,
+    /// Original vectors of coded nodes
+    originals: Originals,
 }
 
-impl HnswLayer {
-    fn new() -> Self {
-        Self {
-            neighbors: DashMap::new(),
+#[derive(Serialize, Deserialize)]
+struct Node {
+    id: String,
+    vector: Stored,
+    /// Neighbor slots per layer, from layer 0 to the node's level
+    neighbors: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+/// A node's vector as the graph holds it
+#[derive(Serialize, Deserialize)]
+enum Stored {
+    Full(Vec<f32>),
+    /// The vector's code, and the position of the vector in the originals
+    Coded {
+        code: Vec<u8>,
+        original: u64,
+    },
+}
+
+/// A quantizer and how well it measured up when trained
+#[derive(Serialize, Deserialize)]
+struct Trained {
+    quantizer: Quantizer,
+    measured: Measured,
+    /// Mean time to read an original vector back, in nanoseconds
+    original_read_ns: f32,
+}
+
+impl Node {
+    fn level(&self) -> usize {
+        self.neighbors.len() - 1
+    }
+}
+
+impl Graph {
+    /// A node's vector, as decoded from its code in a quantized graph
+    fn vector(&self, slot: u32) -> Cow<'_, [f32]> {
+        match self.nodes[slot as usize].vector {
+            Stored::Full(ref vector) => Cow::Borrowed(vector),
+            Stored::Coded { ref code, .. } => Cow::Owned(self.quantizer().decode(code)),
+        }
+    }
+
+    /// A node's vector as it was stored, read back from the originals in
+    /// a quantized graph
+    fn original(&self, slot: u32) -> Result<Vec<f32>> {
+        match self.nodes[slot as usize].vector {
+            Stored::Full(ref vector) => Ok(vector.clone()),
+            Stored::Coded { original, .. } => self.originals.read(original),
         }
     }
+
+    fn quantizer(&self) -> &Quantizer {
+        &self
+            .quantizer
+            .as_ref()
+            .expect("coded nodes have a quantizer")
+            .quantizer
+    }
 }
 
 impl VectorIndex {
     /// Create a new vector index
     pub fn new(dimensions: usize) -> Self {
-        Self::with_params(dimensions, DistanceMetric::Cosine, 16, 32, 200)
+        Self::with_config(VectorIndexConfig::new(dimensions))
     }
 
     /// Create with custom parameters
@@ -58,286 +231,1416 @@ impl VectorIndex {
         m0: usize,
         ef_construction: usize,
     ) -> Self {
+        let mut config = VectorIndexConfig::new(dimensions).with_metric(metric);
+        config.hnsw_m = m;
+        config.hnsw_ef_construction = ef_construction;
+        let mut index = Self::with_config(config);
+        index.m0 = m0;
+        index
+    }
+
+    /// Create an in-memory index
+    #[must_use]
+    pub fn with_config(config: VectorIndexConfig) -> Self {
         Self {
-            dimensions,
-            metric,
-            vectors: DashMap::new(),
-            layers: RwLock::new(vec![HnswLayer::new()]),
-            entry_point: RwLock::new(None),
-            max_layer: AtomicUsize::new(0),
-            m,
-            m0,
-            ef_construction,
-        }
-    }
-
-    /// Insert a vector
-    pub fn insert(&self, id: String, vector: Vec<f32>) -> Result<(), String> {
-        if vector.len() != self.dimensions {
-            return Err(format!(
+            m0: config.hnsw_m * 2,
+            config,
+            graph: RwLock::new(Graph::default()),
+            store: None,
+            payload: PayloadIndex::default(),
+        }
+    }
+
+    /// Open (or create) a persistent index in `dir`
+    ///
+    /// Loads the latest snapshot and replays the changes logged after it.
+    /// With `sync`, every change is fsynced before it is applied. A
+    /// `cipher` seals the log and snapshots.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the snapshot or log cannot be read, opened with `cipher` or
+    /// decoded.
+    pub fn open(
+        dir: &Path,
+        config: VectorIndexConfig,
+        cipher: Option<Arc<DataKeys>>,
+        sync: bool,
+    ) -> Result<Self> {
+        let (store, recovered) = VectorStore::open(dir, cipher.clone(), sync)?;
+        let mut index = Self::with_config(config);
+
+        if let Some(snapshot) = recovered.snapshot {
+            let mut graph: Graph = bincode::deserialize(&snapshot).map_err(|e| {
+                Error::Storage(StorageError::CorruptData(format!(
+                    "Vector snapshot in {}: {e}",
+                    dir.display()
+                )))
+            })?;
+            graph.slots = graph
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, node)| !node.deleted)
+                .map(|(slot, node)| (node.id.clone(), slot_of(slot)))
+                .collect();
+            index.graph = RwLock::new(graph);
+        }
+        if index.config.quantization.is_some() {
+            index.graph.get_mut().originals.attach(dir, cipher)?;
+        }
+
+        let replayed = recovered.ops.len();
+        {
+            let mut graph = index.graph.write();
+            for op in recovered.ops {
+                index.apply(&mut graph, op)?;
+            }
+        }
+        if replayed > 0 {
+            info!(
+                "Replayed {} vector change(s) into the index at {:?}",
+                replayed, dir
+            );
+        }
+
+        index.store = Some(Mutex::new(store));
+        Ok(index)
+    }
+
+    /// Insert a vector, or replace the vector stored under `id`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the vector has the wrong dimensions or a non-finite component,
+    /// or the change cannot be logged.
+    pub fn insert(&self, id: String, vector: Vec<f32>) -> Result<()> {
+        if vector.len() != self.config.dimensions {
+            return Err(Error::Storage(StorageError::WriteFailed(format!(
                 "Vector dimension mismatch: expected {}, got {}",
-                self.dimensions,
+                self.config.dimensions,
                 vector.len()
-            ));
+            ))));
+        }
+        if vector.iter().any(|x| !x.is_finite()) {
+            return Err(Error::Storage(StorageError::WriteFailed(
+                "Vector components must be finite".to_string(),
+            )));
         }
 
         // Normalize if using cosine similarity
-        let vector = if self.metric == DistanceMetric::Cosine {
-            self.normalize(&vector)
+        let vector = if self.config.metric == DistanceMetric::Cosine {
+            normalize(&vector)
         } else {
             vector
         };
 
-        // Store vector
-        self.vectors.insert(id.clone(), vector.clone());
+        let mut graph = self.graph.write();
+        let op = VectorOp::Upsert { id, vector };
+        self.log(&op)?;
+        self.apply(&mut graph, op)?;
+        self.maybe_snapshot(&graph)
+    }
 
-        // Determine random level for this node
-        let level = self.random_level();
+    /// Delete a vector; returns whether it existed
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change cannot be logged.
+    pub fn delete(&self, id: &str) -> Result<bool> {
+        let mut graph = self.graph.write();
+        if !graph.slots.contains_key(id) {
+            return Ok(false);
+        }
+        let op = VectorOp::Delete { id: id.to_string() };
+        self.log(&op)?;
+        self.apply(&mut graph, op)?;
+        self.maybe_snapshot(&graph)?;
+        Ok(true)
+    }
 
-        // Ensure we have enough layers
-        {
-            let mut layers = self.layers.write();
-            while layers.len() <= level {
-                layers.push(HnswLayer::new());
+    /// The vector stored under `id` (normalized for cosine indexes)
+    ///
+    /// A quantized index reads the original vector back, falling back to
+    /// the one its code stands for if that fails.
+    pub fn get(&self, id: &str) -> Option<Vec<f32>> {
+        let graph = self.graph.read();
+        let slot = *graph.slots.get(id)?;
+        match graph.original(slot) {
+            Ok(vector) => Some(vector),
+            Err(e) => {
+                warn!("Reading the original vector of {}: {}", id, e);
+                Some(graph.vector(slot).into_owned())
             }
         }
+    }
+
+    /// Whether a vector is stored under `id`
+    pub fn contains(&self, id: &str) -> bool {
+        self.graph.read().slots.contains_key(id)
+    }
 
-        // Update max_layer if needed
-        let current_max = self.max_layer.load(Ordering::SeqCst);
-        if level > current_max {
-            self.max_layer.store(level, Ordering::SeqCst);
+    /// Search for nearest neighbors (returns id, score pairs)
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        self.search_with_options(query, k, &SearchOptions::default())
+    }
+
+    /// Search for nearest neighbors with custom ef
+    ///
+    /// Scores are distances: lower is closer.
+    pub fn search_with_ef(&self, query: &[f32], k: usize, ef: usize) -> Vec<(String, f32)> {
+        let options = SearchOptions {
+            ef: Some(ef),
+            ..SearchOptions::default()
+        };
+        self.search_with_options(query, k, &options)
+    }
+
+    /// Search for nearest neighbors as `options` say
+    pub fn search_with_options(
+        &self,
+        query: &[f32],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<(String, f32)> {
+        if options.exact {
+            return self.search_exact(query, k, None, options, &mut |_| true);
+        }
+        if query.len() != self.config.dimensions || k == 0 {
+            return vec![];
         }
 
-        // Insert into HNSW structure
-        let entry_point = self.entry_point.read().clone();
+        let graph = self.graph.read();
+        let Some(entry) = graph.entry_point else {
+            return vec![];
+        };
+        let query = self.prepare_query(&graph, query);
+        let wanted = Self::candidates_wanted(&graph, k, options);
+
+        // Tombstones take up room in the candidate list
+        let ef = options.ef.unwrap_or(self.config.hnsw_ef_search).max(wanted);
+        let ef = ef + graph.deleted.min(ef);
+
+        let entry = self.descend(&graph, &query, entry, graph.max_level, 0);
+        let candidates = self
+            .search_layer(&graph, &query, &[entry], ef, 0)
+            .into_iter()
+            .filter(|candidate| !graph.nodes[candidate.slot as usize].deleted)
+            .take(wanted)
+            .collect();
+        self.results(&graph, &query, candidates, k, options)
+    }
 
-        if entry_point.is_none() {
-            // First node
-            *self.entry_point.write() = Some(id.clone());
+    /// Search for the nearest neighbors whose IDs pass `accept`
+    ///
+    /// `accept` is only asked about nodes close enough to enter the
+    /// results. Rejected nodes still route the traversal, which widens
+    /// until it has `ef` accepted candidates or runs out of nodes, so fewer
+    /// than `k` results means the reachable part of the graph has no more.
+    pub fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        options: &SearchOptions,
+        accept: &mut dyn FnMut(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        if query.len() != self.config.dimensions || k == 0 {
+            return vec![];
+        }
+
+        let graph = self.graph.read();
+        let Some(entry) = graph.entry_point else {
+            return vec![];
+        };
+        let query = self.prepare_query(&graph, query);
+        let wanted = Self::candidates_wanted(&graph, k, options);
+        let ef = options.ef.unwrap_or(self.config.hnsw_ef_search).max(wanted);
+
+        let entry = self.descend(&graph, &query, entry, graph.max_level, 0);
+        let mut candidates = self.search_bottom_filtered(&graph, &query, entry, ef, accept);
+        candidates.truncate(wanted);
+        self.results(&graph, &query, candidates, k, options)
+    }
+
+    /// Find the nearest neighbors by comparing the query with every vector
+    /// whose ID is in `ids` (every vector if `None`) and passes `accept`
+    ///
+    /// In a quantized index the query is compared with codes, and the best
+    /// candidates are rescored as in any other search.
+    pub fn search_exact(
+        &self,
+        query: &[f32],
+        k: usize,
+        ids: Option<&HashSet<String>>,
+        options: &SearchOptions,
+        accept: &mut dyn FnMut(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        if query.len() != self.config.dimensions || k == 0 {
+            return vec![];
+        }
+
+        let graph = self.graph.read();
+        let query = self.prepare_query(&graph, query);
+        let wanted = Self::candidates_wanted(&graph, k, options);
+        let slots: Vec<u32> = match ids {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| graph.slots.get(id))
+                .copied()
+                .collect(),
+            None => graph.slots.values().copied().collect(),
+        };
+
+        let mut results: BinaryHeap<Candidate> = BinaryHeap::new();
+        for slot in slots {
+            let distance = self.distance_to(&graph, &query, slot);
+            let worst = results.peek().map_or(f32::MAX, |c| c.distance);
+            if (results.len() < wanted || distance < worst)
+                && accept(&graph.nodes[slot as usize].id)
+            {
+                results.push(Candidate { distance, slot });
+                if results.len() > wanted {
+                    results.pop();
+                }
+            }
+        }
+        self.results(&graph, &query, results.into_sorted_vec(), k, options)
+    }
+
+    /// What quantization saves and costs this index, if it is quantized
+    pub fn quantization_report(&self) -> Option<QuantizationReport> {
+        let method = self.config.quantization?;
+        let graph = self.graph.read();
+        let memory_bytes = graph
+            .nodes
+            .iter()
+            .map(|node| match node.vector {
+                Stored::Full(ref vector) => vector.len() * std::mem::size_of::<f32>(),
+                Stored::Coded { ref code, .. } => code.len() + std::mem::size_of::<u64>(),
+            })
+            .sum();
+        let trained = graph.quantizer.as_ref();
+        let measured = trained.map(|t| t.measured);
+        Some(QuantizationReport {
+            method,
+            trained: trained.is_some(),
+            code_bytes: method.code_size(self.config.dimensions),
+            vector_bytes: self.config.dimensions * std::mem::size_of::<f32>(),
+            memory_bytes,
+            recall: measured.map(|m| m.recall),
+            rescored_recall: measured.map(|m| m.rescored_recall),
+            code_distance_ns: measured.map(|m| m.code_distance_ns),
+            full_distance_ns: measured.map(|m| m.full_distance_ns),
+            original_read_ns: trained.map(|t| t.original_read_ns),
+        })
+    }
+
+    /// Indexes of the payloads stored with the vectors, maintained by the
+    /// storage engine
+    pub fn payload_index(&self) -> &PayloadIndex {
+        &self.payload
+    }
+
+    /// Write a snapshot of the graph and truncate the change log
+    ///
+    /// Does nothing for in-memory indexes, or when nothing changed since
+    /// the last snapshot unless `force` is set (to reseal it after a key
+    /// rotation).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the snapshot cannot be written or the log cannot be truncated.
+    pub fn snapshot(&self, force: bool) -> Result<()> {
+        let Some(ref store) = self.store else {
+            return Ok(());
+        };
+        let graph = self.graph.read();
+        let mut store = store.lock();
+        if store.pending() == 0 && !force {
             return Ok(());
         }
+        Self::write_snapshot(&graph, &mut store)
+    }
 
-        let entry = entry_point.unwrap();
+    /// Get dimensions of this index
+    pub fn dimensions(&self) -> usize {
+        self.config.dimensions
+    }
 
-        // Find neighbors at each layer
-        let layers = self.layers.read();
-        for l in (0..=level.min(layers.len() - 1)).rev() {
-            let m_max = if l == 0 { self.m0 } else { self.m };
+    /// Dimensions, metric and HNSW parameters
+    pub fn config(&self) -> &VectorIndexConfig {
+        &self.config
+    }
 
-            // Find ef_construction nearest neighbors at layer l
-            let neighbors = self.search_layer(&entry, &vector, self.ef_construction, l);
+    /// Get number of vectors
+    pub fn len(&self) -> usize {
+        self.graph.read().slots.len()
+    }
 
-            // Select M best neighbors
-            let selected: Vec<_> = neighbors.into_iter().take(m_max).collect();
+    /// Check if empty
+    pub fn is_empty(&self) -> bool {
+        self.graph.read().slots.is_empty()
+    }
 
-            // Add bidirectional connections
-            let layer = &layers[l];
-            layer.neighbors.insert(id.clone(), selected.clone());
+    /// Number of deleted nodes still in the graph
+    pub fn tombstones(&self) -> usize {
+        self.graph.read().deleted
+    }
 
-            for neighbor_id in &selected {
-                layer
-                    .neighbors
-                    .entry(neighbor_id.clone())
-                    .or_insert_with(Vec::new)
-                    .push(id.clone());
+    // ========================================================================
+    // Graph maintenance
+    // ========================================================================
+
+    fn log(&self, op: &VectorOp) -> Result<()> {
+        match self.store {
+            Some(ref store) => store.lock().append(op),
+            None => Ok(()),
+        }
+    }
+
+    fn maybe_snapshot(&self, graph: &Graph) -> Result<()> {
+        let Some(ref store) = self.store else {
+            return Ok(());
+        };
+        let mut store = store.lock();
+        if store.pending() >= SNAPSHOT_INTERVAL {
+            Self::write_snapshot(graph, &mut store)?;
+        }
+        Ok(())
+    }
+
+    fn write_snapshot(graph: &Graph, store: &mut VectorStore) -> Result<()> {
+        let data = bincode::serialize(graph).map_err(|e| Error::Serialization(e.to_string()))?;
+        // The snapshot must not refer to originals that could be lost
+        graph.originals.sync()?;
+        store.write_snapshot(&data)?;
+        graph.originals.prune()
+    }
+
+    fn apply(&self, graph: &mut Graph, op: VectorOp) -> Result<()> {
+        match op {
+            VectorOp::Upsert { id, vector } => {
+                self.upsert(graph, id, vector)?;
+                self.maybe_quantize(graph)
+            }
+            VectorOp::Delete { id } => {
+                self.remove(graph, &id);
+                Self::maybe_compact_originals(graph)
             }
         }
+    }
+
+    /// Apply an insert or update to the graph
+    fn upsert(&self, graph: &mut Graph, id: String, vector: Vec<f32>) -> Result<()> {
+        if let Some(&slot) = graph.slots.get(&id) {
+            return self.update(graph, slot, vector);
+        }
+
+        let query = self.query_for(graph, vector);
+        let level = self.random_level();
+        let slot = slot_of(graph.nodes.len());
+        let stored = Self::store(graph, &query.vector)?;
+        graph.nodes.push(Node {
+            id: id.clone(),
+            vector: stored,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        graph.slots.insert(id, slot);
+
+        let Some(entry) = graph.entry_point else {
+            graph.entry_point = Some(slot);
+            graph.max_level = level;
+            return Ok(());
+        };
 
-        // Update entry point if this node is at a higher level
-        if level > current_max {
-            *self.entry_point.write() = Some(id);
+        let top = level.min(graph.max_level);
+        let mut entries = vec![self.descend(graph, &query, entry, graph.max_level, top)];
+        for l in (0..=top).rev() {
+            let candidates =
+                self.search_layer(graph, &query, &entries, self.config.hnsw_ef_construction, l);
+            entries = candidates.iter().map(|c| c.slot).collect();
+            self.link(graph, slot, l, candidates);
         }
 
+        if level > graph.max_level {
+            graph.entry_point = Some(slot);
+            graph.max_level = level;
+        }
         Ok(())
     }
 
-    /// Search for nearest neighbors (returns id, score pairs)
-    pub fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
-        self.search_with_ef(query, k, k * 2)
+    /// Replace a node's vector, relinking it at every layer
+    fn update(&self, graph: &mut Graph, slot: u32, vector: Vec<f32>) -> Result<()> {
+        let level = graph.nodes[slot as usize].level();
+        let query = self.query_for(graph, vector);
+
+        // Find the new neighborhoods while the node still routes searches
+        let mut neighborhoods = Vec::with_capacity(level + 1);
+        if let Some(entry) = graph.entry_point {
+            let mut entries = vec![self.descend(graph, &query, entry, graph.max_level, level)];
+            for l in (0..=level.min(graph.max_level)).rev() {
+                let candidates =
+                    self.search_layer(graph, &query, &entries, self.config.hnsw_ef_construction, l);
+                entries = candidates.iter().map(|c| c.slot).collect();
+                neighborhoods.push((l, candidates));
+            }
+        }
+
+        graph.nodes[slot as usize].vector = Self::store(graph, &query.vector)?;
+        for l in 0..=level {
+            self.unlink(graph, slot, l);
+        }
+        for (l, candidates) in neighborhoods {
+            self.link(graph, slot, l, candidates);
+        }
+        Self::maybe_compact_originals(graph)
     }
 
-    /// Search for nearest neighbors with custom ef
-    pub fn search_with_ef(&self, query: &[f32], k: usize, ef: usize) -> Vec<(String, f32)> {
-        if query.len() != self.dimensions {
-            return vec![];
+    /// A vector as a node of `graph` holds it: its code once the graph is
+    /// quantized, with the vector added to the originals
+    fn store(graph: &mut Graph, vector: &[f32]) -> Result<Stored> {
+        Ok(match graph.quantizer {
+            Some(ref trained) => Stored::Coded {
+                code: trained.quantizer.encode(vector),
+                original: graph.originals.append(vector)?,
+            },
+            None => Stored::Full(vector.to_vec()),
+        })
+    }
+
+    /// Train the quantizer of a quantized index once it holds enough
+    /// vectors, and replace every vector with its code
+    fn maybe_quantize(&self, graph: &mut Graph) -> Result<()> {
+        let Some(method) = self.config.quantization else {
+            return Ok(());
+        };
+        if graph.quantizer.is_some() || graph.slots.len() < QUANTIZE_MIN_VECTORS {
+            return Ok(());
         }
 
-        // Normalize if using cosine
-        let query = if self.metric == DistanceMetric::Cosine {
-            self.normalize(query)
-        } else {
-            query.to_vec()
+        let mut slots: Vec<u32> = graph.slots.values().copied().collect();
+        slots.shuffle(&mut rand::thread_rng());
+        slots.truncate(TRAIN_SAMPLE);
+        let sample: Vec<Vec<f32>> = slots
+            .iter()
+            .map(|&s| graph.vector(s).into_owned())
+            .collect();
+        let quantizer = Quantizer::train(method, &sample);
+        let measured = quantizer.measure(self.config.metric, &sample);
+        graph.quantizer = Some(Trained {
+            quantizer,
+            measured,
+            original_read_ns: 0.0,
+        });
+
+        // Nodes keep their full vectors until coded, so a failure part
+        // way through leaves a graph that still searches correctly
+        let mut positions = Vec::with_capacity(MEASURE_READS);
+        for node in &mut graph.nodes {
+            if let Stored::Full(ref vector) = node.vector {
+                let code = graph
+                    .quantizer
+                    .as_ref()
+                    .expect("just trained")
+                    .quantizer
+                    .encode(vector);
+                let original = graph.originals.append(vector)?;
+                if positions.len() < MEASURE_READS {
+                    positions.push(original);
+                }
+                node.vector = Stored::Coded { code, original };
+            }
+        }
+
+        let start = Instant::now();
+        for &position in &positions {
+            graph.originals.read(position)?;
+        }
+        #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)] // a timing
+        let read_ns = (start.elapsed().as_secs_f64() * 1e9 / positions.len().max(1) as f64) as f32;
+        if let Some(ref mut trained) = graph.quantizer {
+            trained.original_read_ns = read_ns;
+        }
+        info!(
+            "Quantized {} vector(s) with {:?}: recall@{} {:.3} by codes, {:.3} rescored",
+            graph.slots.len(),
+            method,
+            quantization::MEASURE_K,
+            measured.recall,
+            measured.rescored_recall
+        );
+        Ok(())
+    }
+
+    /// Rewrite the originals of a quantized graph once most of their
+    /// records are stale
+    fn maybe_compact_originals(graph: &mut Graph) -> Result<()> {
+        let live = graph.nodes.len();
+        if graph.quantizer.is_none()
+            || live < VACUUM_MIN_NODES
+            || graph.originals.stale(live) <= live as u64
+        {
+            return Ok(());
+        }
+
+        let positions: Vec<u64> = graph
+            .nodes
+            .iter()
+            .filter_map(|node| match node.vector {
+                Stored::Coded { original, .. } => Some(original),
+                Stored::Full(_) => None,
+            })
+            .collect();
+        let mut moved = graph.originals.compact(&positions)?.into_iter();
+        for node in &mut graph.nodes {
+            if let Stored::Coded {
+                ref mut original, ..
+            } = node.vector
+            {
+                *original = moved.next().expect("one position per coded node");
+            }
+        }
+        Ok(())
+    }
+
+    /// Tombstone a node, relinking its neighbors around it
+    fn remove(&self, graph: &mut Graph, id: &str) {
+        let Some(slot) = graph.slots.remove(id) else {
+            return;
         };
 
-        let entry_point = self.entry_point.read().clone();
-        if entry_point.is_none() {
-            return vec![];
+        graph.nodes[slot as usize].deleted = true;
+        graph.deleted += 1;
+        for l in 0..=graph.nodes[slot as usize].level() {
+            self.repair_neighbors(graph, slot, l);
+        }
+
+        if graph.slots.is_empty() {
+            *graph = Graph::default();
+            return;
         }
+        if graph.entry_point == Some(slot) {
+            let (entry, level) = graph
+                .slots
+                .values()
+                .map(|&s| (s, graph.nodes[s as usize].level()))
+                .max_by_key(|&(s, level)| (level, std::cmp::Reverse(s)))
+                .expect("graph has live nodes");
+            graph.entry_point = Some(entry);
+            graph.max_level = level;
+        }
+
+        if graph.nodes.len() >= VACUUM_MIN_NODES && graph.deleted * 4 >= graph.nodes.len() {
+            self.vacuum(graph);
+        }
+    }
+
+    /// Connect `slot` at layer `l` to the best of `candidates` (sorted by
+    /// distance to it) and link them back, pruning their lists if full
+    fn link(&self, graph: &mut Graph, slot: u32, l: usize, candidates: Vec<Candidate>) {
+        let m_max = self.m_max(l);
+        let candidates = candidates
+            .into_iter()
+            .filter(|c| c.slot != slot && !graph.nodes[c.slot as usize].deleted)
+            .collect();
+        let selected = self.select_neighbors(graph, candidates, self.config.hnsw_m.min(m_max));
+        graph.nodes[slot as usize].neighbors[l].clone_from(&selected);
+
+        for neighbor in selected {
+            let links = &mut graph.nodes[neighbor as usize].neighbors[l];
+            if links.contains(&slot) {
+                continue;
+            }
+            links.push(slot);
+            if links.len() > m_max {
+                self.shrink(graph, neighbor, l);
+            }
+        }
+    }
 
-        let entry = entry_point.unwrap();
-        let max_layer = self.max_layer.load(Ordering::SeqCst);
+    /// Drop `slot`'s links at layer `l`, relinking the nodes that pointed
+    /// back at it
+    fn unlink(&self, graph: &mut Graph, slot: u32, l: usize) {
+        self.repair_neighbors(graph, slot, l);
+        graph.nodes[slot as usize].neighbors[l].clear();
+    }
 
-        // Greedy search from top layer to layer 1
-        let mut current_nearest = entry;
-        for l in (1..=max_layer).rev() {
-            let neighbors = self.search_layer(&current_nearest, &query, 1, l);
-            if let Some(nearest) = neighbors.first() {
-                current_nearest = nearest.clone();
+    /// Replace the links to `slot` held by its neighbors at layer `l` with
+    /// links into its neighborhood
+    fn repair_neighbors(&self, graph: &mut Graph, slot: u32, l: usize) {
+        let removed = graph.nodes[slot as usize].neighbors[l].clone();
+        for &neighbor in &removed {
+            let node = &graph.nodes[neighbor as usize];
+            if node.deleted || !node.neighbors[l].contains(&slot) {
+                continue;
             }
+
+            let pool: HashSet<u32> = node.neighbors[l]
+                .iter()
+                .chain(&removed)
+                .copied()
+                .filter(|&s| s != slot && s != neighbor && !graph.nodes[s as usize].deleted)
+                .collect();
+            let candidates = self.sorted_candidates(graph, neighbor, pool);
+            graph.nodes[neighbor as usize].neighbors[l] =
+                self.select_neighbors(graph, candidates, self.m_max(l));
         }
+    }
+
+    /// Prune a node's links at layer `l` back to the layer's limit
+    fn shrink(&self, graph: &mut Graph, slot: u32, l: usize) {
+        let pool = graph.nodes[slot as usize].neighbors[l]
+            .iter()
+            .copied()
+            .collect();
+        let candidates = self.sorted_candidates(graph, slot, pool);
+        graph.nodes[slot as usize].neighbors[l] =
+            self.select_neighbors(graph, candidates, self.m_max(l));
+    }
+
+    /// Rebuild the graph without tombstones
+    ///
+    /// Links into a tombstone are replaced by the live nodes reachable
+    /// through it, so the graph stays connected.
+    fn vacuum(&self, graph: &mut Graph) {
+        let removed = graph.deleted;
+        let live: Vec<u32> = (0..slot_of(graph.nodes.len()))
+            .filter(|&s| !graph.nodes[s as usize].deleted)
+            .collect();
 
-        // Search layer 0 with ef
-        let candidates = self.search_layer(&current_nearest, &query, ef.max(k), 0);
+        for &slot in &live {
+            for l in 0..=graph.nodes[slot as usize].level() {
+                let links = &graph.nodes[slot as usize].neighbors[l];
+                if links.iter().all(|&s| !graph.nodes[s as usize].deleted) {
+                    continue;
+                }
+                let pool = Self::live_links(graph, slot, l);
+                let candidates = self.sorted_candidates(graph, slot, pool);
+                graph.nodes[slot as usize].neighbors[l] =
+                    self.select_neighbors(graph, candidates, self.m_max(l));
+            }
+        }
+
+        let mut remap = vec![u32::MAX; graph.nodes.len()];
+        for (new, &old) in live.iter().enumerate() {
+            remap[old as usize] = slot_of(new);
+        }
+        let nodes = std::mem::take(&mut graph.nodes);
+        graph.nodes = nodes
+            .into_iter()
+            .filter(|node| !node.deleted)
+            .map(|mut node| {
+                for links in &mut node.neighbors {
+                    for link in links.iter_mut() {
+                        *link = remap[*link as usize];
+                    }
+                }
+                node
+            })
+            .collect();
+        graph.slots = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(slot, node)| (node.id.clone(), slot_of(slot)))
+            .collect();
+        graph.entry_point = graph.entry_point.map(|s| remap[s as usize]);
+        graph.deleted = 0;
+
+        info!(
+            "Vacuumed {} deleted vector(s), {} remain",
+            removed,
+            graph.nodes.len()
+        );
+    }
+
+    /// Live nodes linked from `slot` at layer `l`, looking through
+    /// tombstones
+    fn live_links(graph: &Graph, slot: u32, l: usize) -> HashSet<u32> {
+        let mut live = HashSet::new();
+        let mut seen = HashSet::from([slot]);
+        let mut pending: Vec<u32> = graph.nodes[slot as usize].neighbors[l].clone();
+        while let Some(next) = pending.pop() {
+            if !seen.insert(next) {
+                continue;
+            }
+            let node = &graph.nodes[next as usize];
+            if node.deleted {
+                pending.extend(node.neighbors.get(l).into_iter().flatten());
+            } else {
+                live.insert(next);
+            }
+        }
+        live
+    }
+
+    // ========================================================================
+    // Search primitives
+    // ========================================================================
+
+    /// A query vector as the graph stores vectors (normalized for cosine),
+    /// ready to compare with them
+    fn prepare_query(&self, graph: &Graph, query: &[f32]) -> Query {
+        let vector = if self.config.metric == DistanceMetric::Cosine {
+            normalize(query)
+        } else {
+            query.to_vec()
+        };
+        self.query_for(graph, vector)
+    }
+
+    /// A vector the graph stores, ready to compare with the others
+    fn query_for(&self, graph: &Graph, vector: Vec<f32>) -> Query {
+        let table = graph
+            .quantizer
+            .as_ref()
+            .and_then(|trained| trained.quantizer.table(self.config.metric, &vector));
+        Query { vector, table }
+    }
+
+    /// Quantized candidates to rescore for `k` results, or `k` if none are
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )] // small positive counts
+    fn candidates_wanted(graph: &Graph, k: usize, options: &SearchOptions) -> usize {
+        if graph.quantizer.is_none() || options.skip_rescore {
+            return k;
+        }
+        let oversampling = options
+            .oversampling
+            .unwrap_or(DEFAULT_OVERSAMPLING)
+            .max(1.0);
+        ((k as f32 * oversampling).ceil() as usize).max(k)
+    }
 
-        // Return top k results as (id, score) pairs
+    /// The `k` results of a search from its candidates, nearest first,
+    /// rescoring them against the original vectors in a quantized index
+    fn results(
+        &self,
+        graph: &Graph,
+        query: &Query,
+        mut candidates: Vec<Candidate>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<(String, f32)> {
+        if graph.quantizer.is_some() && !options.skip_rescore {
+            for candidate in &mut candidates {
+                match graph.original(candidate.slot) {
+                    Ok(vector) => candidate.distance = self.distance(&query.vector, &vector),
+                    Err(e) => warn!(
+                        "Rescoring {} with its code: {}",
+                        graph.nodes[candidate.slot as usize].id, e
+                    ),
+                }
+            }
+            candidates.sort();
+        }
         candidates
             .into_iter()
             .take(k)
-            .map(|id| {
-                let score = self.vectors.get(&id)
-                    .map(|v| self.distance(&query, &v))
-                    .unwrap_or(f32::MAX);
-                (id, score)
+            .map(|candidate| {
+                (
+                    graph.nodes[candidate.slot as usize].id.clone(),
+                    candidate.distance,
+                )
             })
             .collect()
     }
 
-    /// Get dimensions of this index
-    pub fn dimensions(&self) -> usize {
-        self.dimensions
+    /// Greedily walk from `entry` down to layer `to`, returning the node
+    /// closest to `query` found on layer `to + 1` (or `entry` itself)
+    fn descend(&self, graph: &Graph, query: &Query, entry: u32, from: usize, to: usize) -> u32 {
+        let mut nearest = entry;
+        for l in ((to + 1)..=from).rev() {
+            if let Some(closest) = self.search_layer(graph, query, &[nearest], 1, l).first() {
+                nearest = closest.slot;
+            }
+        }
+        nearest
     }
 
-    /// Search within a layer
-    fn search_layer(&self, entry: &str, query: &[f32], ef: usize, layer: usize) -> Vec<String> {
-        let mut visited = std::collections::HashSet::new();
+    /// Search within a layer, returning up to `ef` nodes closest to `query`
+    /// (tombstones included), nearest first
+    fn search_layer(
+        &self,
+        graph: &Graph,
+        query: &Query,
+        entries: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited = HashSet::new();
         let mut candidates = BinaryHeap::new();
-        let mut results = BinaryHeap::new();
+        let mut results: BinaryHeap<Candidate> = BinaryHeap::new();
 
-        // Get entry vector
-        if let Some(entry_vec) = self.vectors.get(entry) {
-            let dist = self.distance(query, &entry_vec);
-            candidates.push(std::cmp::Reverse(OrderedFloat(dist, entry.to_string())));
-            results.push(OrderedFloat(dist, entry.to_string()));
-            visited.insert(entry.to_string());
+        for &entry in entries {
+            if visited.insert(entry) {
+                let candidate = Candidate {
+                    distance: self.distance_to(graph, query, entry),
+                    slot: entry,
+                };
+                candidates.push(std::cmp::Reverse(candidate));
+                results.push(candidate);
+            }
         }
-
-        let layers = self.layers.read();
-        if layer >= layers.len() {
-            return vec![];
+        while results.len() > ef {
+            results.pop();
         }
 
-        while let Some(std::cmp::Reverse(OrderedFloat(dist, id))) = candidates.pop() {
-            let worst = results.peek().map(|of| of.0).unwrap_or(f32::MAX);
-            if dist > worst && results.len() >= ef {
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::MAX, |c| c.distance);
+            if current.distance > worst && results.len() >= ef {
                 break;
             }
 
-            // Get neighbors
-            if let Some(neighbors) = layers[layer].neighbors.get(&id) {
-                for neighbor in neighbors.iter() {
-                    if visited.contains(neighbor) {
-                        continue;
+            let Some(links) = graph.nodes[current.slot as usize].neighbors.get(layer) else {
+                continue;
+            };
+            for &neighbor in links {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance_to(graph, query, neighbor);
+                let worst = results.peek().map_or(f32::MAX, |c| c.distance);
+                if results.len() < ef || distance < worst {
+                    let candidate = Candidate {
+                        distance,
+                        slot: neighbor,
+                    };
+                    candidates.push(std::cmp::Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
                     }
-                    visited.insert(neighbor.clone());
+                }
+            }
+        }
 
-                    if let Some(vec) = self.vectors.get(neighbor) {
-                        let d = self.distance(query, &vec);
+        results.into_sorted_vec()
+    }
 
-                        if results.len() < ef || d < worst {
-                            candidates.push(std::cmp::Reverse(OrderedFloat(d, neighbor.clone())));
-                            results.push(OrderedFloat(d, neighbor.clone()));
+    /// Search the bottom layer for the `ef` live nodes closest to `query`
+    /// that pass `accept`, nearest first
+    ///
+    /// Unlike [`VectorIndex::search_layer`], only accepted nodes count
+    /// towards `ef`; the others are expanded but never returned.
+    fn search_bottom_filtered(
+        &self,
+        graph: &Graph,
+        query: &Query,
+        entry: u32,
+        ef: usize,
+        accept: &mut dyn FnMut(&str) -> bool,
+    ) -> Vec<Candidate> {
+        let mut accepts = |slot: u32| {
+            let node = &graph.nodes[slot as usize];
+            !node.deleted && accept(&node.id)
+        };
+
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::new();
+        let mut results: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        let start = Candidate {
+            distance: self.distance_to(graph, query, entry),
+            slot: entry,
+        };
+        candidates.push(std::cmp::Reverse(start));
+        if accepts(entry) {
+            results.push(start);
+        }
 
-                            if results.len() > ef {
-                                results.pop();
-                            }
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::MAX, |c| c.distance);
+            if current.distance > worst && results.len() >= ef {
+                break;
+            }
+
+            for &neighbor in &graph.nodes[current.slot as usize].neighbors[0] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance_to(graph, query, neighbor);
+                let worst = results.peek().map_or(f32::MAX, |c| c.distance);
+                if results.len() < ef || distance < worst {
+                    let candidate = Candidate {
+                        distance,
+                        slot: neighbor,
+                    };
+                    candidates.push(std::cmp::Reverse(candidate));
+                    if accepts(neighbor) {
+                        results.push(candidate);
+                        if results.len() > ef {
+                            results.pop();
                         }
                     }
                 }
             }
         }
 
-        results
-            .into_sorted_vec()
+        results.into_sorted_vec()
+    }
+
+    /// Pick up to `m` neighbors from `candidates` (sorted by distance to
+    /// the base node), preferring ones not already covered by a closer pick
+    ///
+    /// This is the HNSW selection heuristic; slots it skips fill any room
+    /// left, so sparse regions keep their links.
+    fn select_neighbors(&self, graph: &Graph, candidates: Vec<Candidate>, m: usize) -> Vec<u32> {
+        if candidates.len() <= m {
+            return candidates.into_iter().map(|c| c.slot).collect();
+        }
+
+        let mut selected: Vec<(u32, Cow<'_, [f32]>)> = Vec::with_capacity(m);
+        let mut skipped = Vec::new();
+        for candidate in candidates {
+            if selected.len() == m {
+                break;
+            }
+            let vector = graph.vector(candidate.slot);
+            let diverse = selected
+                .iter()
+                .all(|(_, other)| self.distance(&vector, other) > candidate.distance);
+            if diverse {
+                selected.push((candidate.slot, vector));
+            } else {
+                skipped.push(candidate.slot);
+            }
+        }
+        let room = m - selected.len();
+        let mut selected: Vec<u32> = selected.into_iter().map(|(slot, _)| slot).collect();
+        selected.extend(skipped.into_iter().take(room));
+        selected
+    }
+
+    /// `pool` sorted by distance to `slot`
+    fn sorted_candidates(&self, graph: &Graph, slot: u32, pool: HashSet<u32>) -> Vec<Candidate> {
+        let base = graph.vector(slot);
+        let mut candidates: Vec<Candidate> = pool
             .into_iter()
-            .map(|of| of.1)
-            .collect()
+            .map(|s| Candidate {
+                distance: self.distance(&base, &graph.vector(s)),
+                slot: s,
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+
+    fn m_max(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m0
+        } else {
+            self.config.hnsw_m
+        }
+    }
+
+    /// Distance from a query to a node's vector, or to what its code
+    /// stands for
+    fn distance_to(&self, graph: &Graph, query: &Query, slot: u32) -> f32 {
+        match graph.nodes[slot as usize].vector {
+            Stored::Full(ref vector) => self.distance(&query.vector, vector),
+            Stored::Coded { ref code, .. } => graph.quantizer().distance(
+                self.config.metric,
+                &query.vector,
+                query.table.as_deref(),
+                code,
+            ),
+        }
     }
 
     /// Compute distance between two vectors
     fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
-        match self.metric {
-            DistanceMetric::Euclidean => {
-                a.iter()
-                    .zip(b.iter())
-                    .map(|(x, y)| (x - y).powi(2))
-                    .sum::<f32>()
-                    .sqrt()
-            }
+        match self.config.metric {
+            DistanceMetric::Euclidean => a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| (x - y).powi(2))
+                .sum::<f32>()
+                .sqrt(),
             DistanceMetric::Cosine => {
                 // For normalized vectors, cosine distance = 1 - dot product
                 1.0 - a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>()
             }
-            DistanceMetric::DotProduct => {
-                -a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>()
-            }
+            DistanceMetric::DotProduct => -a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>(),
             DistanceMetric::Manhattan => a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).sum(),
         }
     }
 
-    /// Normalize a vector
-    fn normalize(&self, v: &[f32]) -> Vec<f32> {
-        let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
-        if norm > 0.0 {
-            v.iter().map(|x| x / norm).collect()
-        } else {
-            v.to_vec()
-        }
-    }
-
     /// Generate random level for HNSW
+    // Levels are small and non-negative
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
     fn random_level(&self) -> usize {
-        let mut rng = rand::thread_rng();
-        let mut level = 0;
-        let ml = 1.0 / (self.m as f64).ln();
-
-        while rng.gen::<f64>() < ml.exp().recip() && level < 16 {
-            level += 1;
-        }
-
-        level
+        let ml = 1.0 / (self.config.hnsw_m as f64).ln();
+        let uniform: f64 = rand::thread_rng().gen_range(f64::EPSILON..1.0);
+        ((-uniform.ln() * ml) as usize).min(MAX_LEVEL)
     }
+}
 
-    /// Get number of vectors
-    pub fn len(&self) -> usize {
-        self.vectors.len()
+/// Normalize a vector
+fn normalize(v: &[f32]) -> Vec<f32> {
+    let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter().map(|x| x / norm).collect()
+    } else {
+        v.to_vec()
     }
+}
 
-    /// Check if empty
-    pub fn is_empty(&self) -> bool {
-        self.vectors.is_empty()
-    }
+/// A query vector with what comparing it with codes takes
+struct Query {
+    /// Normalized for cosine indexes
+    vector: Vec<f32>,
+    /// Per-centroid terms, for product codes
+    table: Option<Vec<f32>>,
 }
 
-/// Wrapper for ordered float comparison
-#[derive(Clone)]
-struct OrderedFloat(f32, String);
+/// A node and its distance to a query, ordered by distance
+#[derive(Clone, Copy)]
+struct Candidate {
+    distance: f32,
+    slot: u32,
+}
 
-impl PartialEq for OrderedFloat {
+impl PartialEq for Candidate {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl Eq for OrderedFloat {}
+impl Eq for Candidate {}
 
-impl PartialOrd for OrderedFloat {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for OrderedFloat {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.partial_cmp(&other.0).unwrap_or(std::cmp::Ordering::Equal)
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.slot.cmp(&other.slot))
+    }
+}
+
+/// Slot number of the node at `index` in a graph's node list
+#[allow(clippy::cast_possible_truncation)] // graphs hold fewer than `u32::MAX` nodes
+fn slot_of(index: usize) -> u32 {
+    index as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::cast_precision_loss)] // small coordinates
+    fn point(i: usize) -> Vec<f32> {
+        vec![(i % 17) as f32, (i / 17) as f32, ((i * 7) % 13) as f32]
+    }
+
+    fn euclidean(dimensions: usize) -> VectorIndexConfig {
+        VectorIndexConfig::new(dimensions)
+            .with_metric(DistanceMetric::Euclidean)
+            .with_hnsw(8, 64, 32)
+    }
+
+    /// IDs of the `k` vectors nearest `query`, found by brute force
+    fn exact(index: &VectorIndex, ids: &[usize], query: &[f32], k: usize) -> Vec<String> {
+        let mut all: Vec<_> = ids
+            .iter()
+            .map(|&i| (index.distance(query, &point(i)), format!("p{i}")))
+            .collect();
+        all.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        all.into_iter().take(k).map(|(_, id)| id).collect()
+    }
+
+    /// Deterministic pseudo-random vectors with components in [0, 10)
+    fn random_points(n: usize, dimensions: usize) -> Vec<Vec<f32>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            #[allow(clippy::cast_precision_loss)] // below 10,000
+            let x = (state % 10_000) as f32;
+            x / 1_000.0
+        };
+        (0..n)
+            .map(|_| (0..dimensions).map(|_| next()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_delete_and_update_keep_search_exact() {
+        let index = VectorIndex::with_config(euclidean(3));
+        for i in 0..300 {
+            index.insert(format!("p{i}"), point(i)).unwrap();
+        }
+        assert_eq!(index.len(), 300);
+
+        // Delete enough to vacuum, checking results never include a deleted
+        // vector and still match brute force
+        let mut live: Vec<usize> = (0..300).collect();
+        for i in (0..300).step_by(3) {
+            assert!(index.delete(&format!("p{i}")).unwrap());
+            live.retain(|&j| j != i);
+        }
+        assert!(!index.delete("p0").unwrap());
+        assert_eq!(index.len(), 200);
+        assert!(
+            index.tombstones() < 100,
+            "tombstones should have been vacuumed"
+        );
+
+        for q in [1, 50, 151, 299] {
+            let query = point(q);
+            let found: Vec<_> = index
+                .search(&query, 1)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            assert_eq!(found, exact(&index, &live, &query, 1));
+        }
+        assert!(index.search(&point(0), 10).iter().all(|(id, _)| id != "p0"));
+
+        // Moving a vector relinks it where it now belongs
+        index
+            .insert("p1".to_string(), vec![100.0, 100.0, 100.0])
+            .unwrap();
+        assert_eq!(index.len(), 200);
+        let found = index.search(&[99.0, 99.0, 99.0], 1);
+        assert_eq!(found[0].0, "p1");
+        assert_eq!(index.get("p1"), Some(vec![100.0, 100.0, 100.0]));
+
+        for id in live.iter().map(|i| format!("p{i}")) {
+            index.delete(&id).unwrap();
+        }
+        assert!(index.is_empty());
+        assert!(index.search(&point(1), 5).is_empty());
+        index.insert("again".to_string(), point(1)).unwrap();
+        assert_eq!(index.search(&point(1), 5)[0].0, "again");
+    }
+
+    #[test]
+    fn test_recover_from_snapshot_and_log() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let index = VectorIndex::open(dir.path(), euclidean(3), None, false).unwrap();
+            for i in 0..100 {
+                index.insert(format!("p{i}"), point(i)).unwrap();
+            }
+            index.snapshot(false).unwrap();
+            // Logged after the snapshot
+            index.delete("p5").unwrap();
+            index
+                .insert("p6".to_string(), vec![50.0, 50.0, 50.0])
+                .unwrap();
+            index.insert("p100".to_string(), point(100)).unwrap();
+        }
+
+        // Simulate a crash halfway through the last change
+        let log = dir.path().join("vectors.log");
+        let len = std::fs::metadata(&log).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&log)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let index = VectorIndex::open(dir.path(), euclidean(3), None, false).unwrap();
+        assert_eq!(index.len(), 99);
+        assert!(!index.contains("p5"));
+        assert!(!index.contains("p100"));
+        assert_eq!(index.search(&[49.0, 49.0, 49.0], 1)[0].0, "p6");
+        assert_eq!(index.search(&point(42), 1)[0].0, "p42");
+
+        // Writes continue after the truncated tail
+        index.insert("p100".to_string(), point(100)).unwrap();
+        drop(index);
+        let index = VectorIndex::open(dir.path(), euclidean(3), None, false).unwrap();
+        assert!(index.contains("p100"));
+    }
+
+    #[test]
+    fn test_metrics_and_dimension_checks() {
+        let index = VectorIndex::new(2);
+        index.insert("x".to_string(), vec![1.0, 0.0]).unwrap();
+        index.insert("y".to_string(), vec![0.0, 3.0]).unwrap();
+        assert!(index.insert("bad".to_string(), vec![1.0]).is_err());
+        assert!(index
+            .insert("nan".to_string(), vec![f32::NAN, 1.0])
+            .is_err());
+
+        // Cosine ignores magnitude
+        let found = index.search(&[0.0, 0.1], 2);
+        assert_eq!(found[0].0, "y");
+        assert!(found[0].1.abs() < 1e-6);
+
+        let index = VectorIndex::with_config(
+            VectorIndexConfig::new(2).with_metric(DistanceMetric::DotProduct),
+        );
+        index.insert("small".to_string(), vec![1.0, 1.0]).unwrap();
+        index.insert("large".to_string(), vec![5.0, 5.0]).unwrap();
+        assert_eq!(index.search(&[1.0, 1.0], 1)[0].0, "large");
+    }
+
+    #[test]
+    fn test_filtered_search_matches_exact() {
+        let index = VectorIndex::with_config(euclidean(3));
+        for i in 0..400 {
+            index.insert(format!("p{i}"), point(i)).unwrap();
+        }
+        for i in (0..400).step_by(10) {
+            index.delete(&format!("p{i}")).unwrap();
+        }
+        let live: Vec<usize> = (0..400).filter(|i| i % 10 != 0).collect();
+        let options = SearchOptions {
+            ef: Some(64),
+            ..SearchOptions::default()
+        };
+
+        // Every third point, then a filter only a handful pass
+        for modulus in [3, 97] {
+            let passing: Vec<usize> = live.iter().copied().filter(|i| i % modulus == 1).collect();
+            let mut accept = |id: &str| id[1..].parse::<usize>().unwrap() % modulus == 1;
+            for q in [7, 150, 333] {
+                let query = point(q);
+                // Points may tie, so compare distances rather than IDs
+                let want: Vec<f32> = exact(&index, &passing, &query, 5)
+                    .iter()
+                    .map(|id| index.distance(&query, &point(id[1..].parse().unwrap())))
+                    .collect();
+                let distances = |found: Vec<(String, f32)>| -> Vec<f32> {
+                    found.into_iter().map(|(_, distance)| distance).collect()
+                };
+                let found = distances(index.search_filtered(&query, 5, &options, &mut accept));
+                assert_eq!(found, want, "filtered search, modulus {modulus}, query {q}");
+                let scanned = distances(index.search_exact(&query, 5, None, &options, &mut accept));
+                assert_eq!(scanned, want);
+            }
+        }
+
+        // A bound restricts the points compared
+        let bound: HashSet<String> = ["p1", "p2", "p10", "p3"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let mut accept = |id: &str| id != "p2";
+        let found = index.search_exact(&point(3), 10, Some(&bound), &options, &mut accept);
+        let ids: Vec<&str> = found.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["p3", "p1"]);
+    }
+
+    #[test]
+    fn test_quantized_search_rescores_originals() {
+        let points = random_points(1_200, 16);
+        let queries: Vec<usize> = (0..points.len()).step_by(60).collect();
+        for (method, code_bytes, min_recall) in [
+            (VectorQuantization::Scalar { quantile: 0.99 }, 16, 0.9),
+            (VectorQuantization::Product { segments: 8 }, 8, 0.8),
+            (VectorQuantization::Binary, 2, 0.5),
+        ] {
+            let index = VectorIndex::with_config(euclidean(16).with_quantization(method));
+            for (i, point) in points.iter().enumerate() {
+                index.insert(format!("p{i}"), point.clone()).unwrap();
+            }
+            let report = index.quantization_report().unwrap();
+            assert!(report.trained, "{method:?}");
+            assert_eq!(report.code_bytes, code_bytes);
+            assert!(report.memory_bytes < points.len() * report.vector_bytes);
+            assert!(report.rescored_recall.unwrap() >= report.recall.unwrap());
+
+            // Lookups return the originals, not what the codes decode to
+            assert_eq!(index.get("p7"), Some(points[7].clone()));
+
+            let options = SearchOptions {
+                oversampling: Some(4.0),
+                ..SearchOptions::default()
+            };
+            let mut found = 0;
+            for &q in &queries {
+                let query = &points[q];
+                let mut truth: Vec<(f32, usize)> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, point)| (index.distance(query, point), i))
+                    .collect();
+                truth.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let truth: Vec<String> = truth[..10].iter().map(|(_, i)| format!("p{i}")).collect();
+
+                let results = index.search_with_options(query, 10, &options);
+                assert_eq!(results.len(), 10);
+                for (id, distance) in &results {
+                    // Rescored distances are exact
+                    let point = &points[id[1..].parse::<usize>().unwrap()];
+                    assert_eq!(*distance, index.distance(query, point));
+                }
+                found += results.iter().filter(|(id, _)| truth.contains(id)).count();
+
+                let approximate = SearchOptions {
+                    skip_rescore: true,
+                    ..SearchOptions::default()
+                };
+                assert_eq!(index.search_with_options(query, 10, &approximate).len(), 10);
+            }
+            #[allow(clippy::cast_precision_loss)] // small counts
+            let recall = found as f32 / (queries.len() * 10) as f32;
+            assert!(recall >= min_recall, "{method:?} recall {recall}");
+        }
+    }
+
+    #[test]
+    fn test_quantized_recover_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let points = random_points(1_100, 8);
+        let config = euclidean(8).with_quantization(VectorQuantization::Scalar { quantile: 1.0 });
+        {
+            let index = VectorIndex::open(dir.path(), config.clone(), None, false).unwrap();
+            for (i, point) in points.iter().enumerate().take(1_050) {
+                index.insert(format!("p{i}"), point.clone()).unwrap();
+            }
+            index.snapshot(false).unwrap();
+            // Logged after the snapshot
+            for (i, point) in points.iter().enumerate().skip(1_050) {
+                index.insert(format!("p{i}"), point.clone()).unwrap();
+            }
+            index.insert("p0".to_string(), vec![50.0; 8]).unwrap();
+        }
+
+        let index = VectorIndex::open(dir.path(), config.clone(), None, false).unwrap();
+        assert!(index.quantization_report().unwrap().trained);
+        assert_eq!(index.len(), 1_100);
+        assert_eq!(index.get("p0"), Some(vec![50.0; 8]));
+        assert_eq!(index.get("p1060"), Some(points[1_060].clone()));
+        assert_eq!(index.search(&[49.0; 8], 1)[0].0, "p0");
+
+        // Replacing every vector leaves most original records stale, so the
+        // originals move to a compacted file
+        for i in 0..points.len() {
+            let point = points[(i + 1) % points.len()].clone();
+            index.insert(format!("p{i}"), point).unwrap();
+        }
+        index.snapshot(true).unwrap();
+        let files: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("originals."))
+            .collect();
+        assert_eq!(files, ["originals.1"]);
+        assert_eq!(index.get("p5"), Some(points[6].clone()));
+
+        drop(index);
+        let index = VectorIndex::open(dir.path(), config, None, false).unwrap();
+        assert_eq!(index.get("p1099"), Some(points[0].clone()));
+        assert_eq!(index.search(&points[42], 1)[0].0, "p41");
     }
 }
@@ -0,0 +1,277 @@
+//! Original vectors of a quantized index
+//!
+//! Once an index is quantized its nodes hold codes, and the vectors they
+//! stand for move to an [`Originals`] store, to rescore search candidates
+//! and to answer lookups. Records are only appended; a vector's position is
+//! kept in its node, and replacing or deleting a vector leaves its old
+//! record behind until the store is compacted.
+//!
+//! Persistent indexes keep originals in `originals.{generation}` files of
+//! `[len u32][crc32 u32][payload]` records, the payload being the vector's
+//! little-endian components (sealed when the index has a data key, with the
+//! generation and position as associated data). Snapshots record the
+//! generation and length of the file: recovery truncates the file back to
+//! that length and replays the logged changes, which append their vectors
+//! again. Compaction writes the next generation; older ones are removed once
+//! a snapshot no longer refers to them. In-memory indexes keep originals in
+//! memory.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::error::{Error, Result, StorageError};
+
+use crate::encryption::{self, DataKeys};
+
+const FILE_PREFIX: &str = "originals.";
+
+/// Record header: length (4) + crc (4)
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// Append-only store of original vectors
+///
+/// Only the generation, length and record count are serialized with the
+/// graph; [`Originals::attach`] reopens the file they describe.
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct Originals {
+    /// Generation of the file
+    generation: u64,
+    /// Bytes (or in memory, vectors) written
+    len: u64,
+    /// Records written, including replaced and deleted vectors
+    records: u64,
+    #[serde(skip)]
+    backend: Backend,
+}
+
+enum Backend {
+    Memory(Vec<Vec<f32>>),
+    File {
+        dir: PathBuf,
+        /// Opened for appending, so seeking to read moves no writes
+        file: Mutex<File>,
+        cipher: Option<Arc<DataKeys>>,
+    },
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Memory(Vec::new())
+    }
+}
+
+impl Originals {
+    /// Keep originals in files in `dir`, opening the file this store was
+    /// serialized with, truncated to its serialized length, and removing
+    /// files of other generations
+    pub fn attach(&mut self, dir: &Path, cipher: Option<Arc<DataKeys>>) -> Result<()> {
+        let file = Self::open_file(dir, self.generation)?;
+        if file.metadata()?.len() < self.len {
+            return Err(Error::Storage(StorageError::CorruptData(format!(
+                "Original vectors {} are shorter than the snapshot recorded",
+                Self::path(dir, self.generation).display()
+            ))));
+        }
+        file.set_len(self.len)?;
+        self.backend = Backend::File {
+            dir: dir.to_path_buf(),
+            file: Mutex::new(file),
+            cipher,
+        };
+        self.prune()
+    }
+
+    /// Store a vector, returning its position
+    pub fn append(&mut self, vector: &[f32]) -> Result<u64> {
+        let position = self.len;
+        match self.backend {
+            Backend::Memory(ref mut vectors) => {
+                vectors.push(vector.to_vec());
+                self.len += 1;
+            }
+            Backend::File {
+                ref mut file,
+                ref cipher,
+                ..
+            } => {
+                let record = Self::record(self.generation, position, vector, cipher.as_deref())?;
+                file.get_mut().write_all(&record)?;
+                self.len += record.len() as u64;
+            }
+        }
+        self.records += 1;
+        Ok(position)
+    }
+
+    /// The vector stored at `position`
+    pub fn read(&self, position: u64) -> Result<Vec<f32>> {
+        match self.backend {
+            Backend::Memory(ref vectors) => {
+                let vector = usize::try_from(position)
+                    .ok()
+                    .and_then(|position| vectors.get(position));
+                vector.cloned().ok_or_else(|| {
+                    Error::Storage(StorageError::ReadFailed(format!(
+                        "No original vector at {position}"
+                    )))
+                })
+            }
+            Backend::File {
+                ref dir,
+                ref file,
+                ref cipher,
+            } => {
+                let corrupt = || {
+                    Error::Storage(StorageError::CorruptData(format!(
+                        "Original vector at {} of {} failed its checksum",
+                        position,
+                        Self::path(dir, self.generation).display()
+                    )))
+                };
+                let mut header = [0u8; RECORD_HEADER_SIZE];
+                let payload = {
+                    let mut file = file.lock();
+                    file.seek(SeekFrom::Start(position))?;
+                    file.read_exact(&mut header)?;
+                    let len = u32::from_le_bytes(header[0..4].try_into().map_err(|_| corrupt())?);
+                    let mut payload = vec![0u8; len as usize];
+                    file.read_exact(&mut payload)?;
+                    payload
+                };
+                let crc = u32::from_le_bytes(header[4..8].try_into().map_err(|_| corrupt())?);
+                if crc32fast::hash(&payload) != crc {
+                    return Err(corrupt());
+                }
+
+                let aad = Self::aad(self.generation, position);
+                let payload = match cipher.as_deref() {
+                    Some(cipher) => cipher.open_or_plain(&aad, &payload)?,
+                    None if encryption::is_sealed(&payload) => {
+                        return Err(Error::Storage(StorageError::Encryption(format!(
+                            "{} is encrypted but no data key is configured",
+                            Self::path(dir, self.generation).display()
+                        ))));
+                    }
+                    None => payload,
+                };
+                Ok(payload
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect())
+            }
+        }
+    }
+
+    /// Records that no longer hold a live vector, given how many do
+    pub fn stale(&self, live: usize) -> u64 {
+        self.records.saturating_sub(live as u64)
+    }
+
+    /// Rewrite the store with only the vectors at `positions`, returning
+    /// their new positions in the same order
+    ///
+    /// A file store moves to the next generation, leaving the current file
+    /// for the snapshot that refers to it.
+    pub fn compact(&mut self, positions: &[u64]) -> Result<Vec<u64>> {
+        let mut compacted = Originals {
+            generation: self.generation + 1,
+            ..Originals::default()
+        };
+        if let Backend::File {
+            ref dir,
+            ref cipher,
+            ..
+        } = self.backend
+        {
+            let file = Self::open_file(dir, compacted.generation)?;
+            file.set_len(0)?;
+            compacted.backend = Backend::File {
+                dir: dir.clone(),
+                file: Mutex::new(file),
+                cipher: cipher.clone(),
+            };
+        }
+        let moved = positions
+            .iter()
+            .map(|&position| compacted.append(&self.read(position)?))
+            .collect::<Result<Vec<_>>>()?;
+        *self = compacted;
+        Ok(moved)
+    }
+
+    /// Flush appended records to disk
+    pub fn sync(&self) -> Result<()> {
+        if let Backend::File { ref file, .. } = self.backend {
+            file.lock().sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Remove files of generations other than the current one
+    pub fn prune(&self) -> Result<()> {
+        let Backend::File { ref dir, .. } = self.backend else {
+            return Ok(());
+        };
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(generation) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(FILE_PREFIX))
+                .and_then(|generation| generation.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if generation != self.generation {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_file(dir: &Path, generation: u64) -> Result<File> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(Self::path(dir, generation))?)
+    }
+
+    fn path(dir: &Path, generation: u64) -> PathBuf {
+        dir.join(format!("{FILE_PREFIX}{generation}"))
+    }
+
+    fn aad(generation: u64, position: u64) -> [u8; 16] {
+        let mut aad = [0u8; 16];
+        aad[..8].copy_from_slice(&generation.to_le_bytes());
+        aad[8..].copy_from_slice(&position.to_le_bytes());
+        aad
+    }
+
+    fn record(
+        generation: u64,
+        position: u64,
+        vector: &[f32],
+        cipher: Option<&DataKeys>,
+    ) -> Result<Vec<u8>> {
+        let mut payload: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+        if let Some(cipher) = cipher {
+            payload = cipher.seal(&Self::aad(generation, position), &payload)?;
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        let len = u32::try_from(payload.len()).map_err(|_| {
+            Error::Storage(StorageError::WriteFailed(
+                "Original vector record too large".to_string(),
+            ))
+        })?;
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        Ok(record)
+    }
+}
@@ -0,0 +1,247 @@
+//! Payload indexes of vector collections
+//!
+//! A payload index maps the keyword or integer values of one payload field
+//! to the IDs of the points holding them, element by element for arrays.
+//! Indexes live in memory: the storage engine rebuilds them from the
+//! documents when it opens and updates them as documents change.
+//!
+//! Filtered searches use them to bound the points a filter can match,
+//! which tells the planner how selective the filter is and lets it skip
+//! reading the payloads of points outside the bound.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use parking_lot::RwLock;
+use serde_json::Value;
+
+use lumadb_common::types::{PayloadFieldType, PayloadIndexSpec};
+
+use super::filter::{field_values, NumberRange, PayloadFilter};
+
+/// Payload indexes of one collection
+#[derive(Default)]
+pub struct PayloadIndex {
+    fields: RwLock<HashMap<String, FieldIndex>>,
+}
+
+/// Index of one field
+struct FieldIndex {
+    field_type: PayloadFieldType,
+    /// IDs of the points holding each value
+    entries: BTreeMap<Key, HashSet<String>>,
+    /// Indexed values of each point
+    points: HashMap<String, Vec<Key>>,
+    /// Points holding values of another type, which lookups cannot rule out
+    unindexed: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Key {
+    Integer(i64),
+    Keyword(String),
+}
+
+impl PayloadIndex {
+    /// Start indexing a field; returns `false` if it is already indexed
+    ///
+    /// The index is empty until points are added with
+    /// [`PayloadIndex::update`].
+    pub fn add_field(&self, spec: &PayloadIndexSpec) -> bool {
+        let mut fields = self.fields.write();
+        if fields.contains_key(&spec.field) {
+            return false;
+        }
+        fields.insert(
+            spec.field.clone(),
+            FieldIndex {
+                field_type: spec.field_type,
+                entries: BTreeMap::new(),
+                points: HashMap::new(),
+                unindexed: HashSet::new(),
+            },
+        );
+        true
+    }
+
+    /// Stop indexing a field; returns whether it was indexed
+    pub fn remove_field(&self, field: &str) -> bool {
+        self.fields.write().remove(field).is_some()
+    }
+
+    /// Indexed fields, with the number of points holding an indexed value
+    pub fn fields(&self) -> Vec<(PayloadIndexSpec, usize)> {
+        let mut fields: Vec<_> = self
+            .fields
+            .read()
+            .iter()
+            .map(|(field, index)| {
+                let spec = PayloadIndexSpec {
+                    field: field.clone(),
+                    field_type: index.field_type,
+                };
+                (spec, index.points.len())
+            })
+            .collect();
+        fields.sort_by(|a, b| a.0.field.cmp(&b.0.field));
+        fields
+    }
+
+    /// Whether no field is indexed
+    pub fn is_empty(&self) -> bool {
+        self.fields.read().is_empty()
+    }
+
+    /// Replace the indexed values of a point with those of `payload`, or
+    /// remove the point when `payload` is `None`
+    pub fn update(&self, id: &str, payload: Option<&Value>) {
+        for (field, index) in self.fields.write().iter_mut() {
+            index.remove(id);
+            if let Some(payload) = payload {
+                index.insert(id, field_values(payload, field));
+            }
+        }
+    }
+
+    /// Remove every point
+    pub fn clear(&self) {
+        for index in self.fields.write().values_mut() {
+            index.entries.clear();
+            index.points.clear();
+            index.unindexed.clear();
+        }
+    }
+
+    /// IDs of the points that can match `filter`, or `None` when the
+    /// indexes cannot bound them
+    ///
+    /// The bound may include points the filter rejects, so callers still
+    /// check the filter on each point.
+    pub fn candidates(&self, filter: &PayloadFilter) -> Option<HashSet<String>> {
+        let fields = self.fields.read();
+        Self::bound(&fields, filter)
+    }
+
+    fn bound(
+        fields: &HashMap<String, FieldIndex>,
+        filter: &PayloadFilter,
+    ) -> Option<HashSet<String>> {
+        match filter {
+            PayloadFilter::And(filters) => filters
+                .iter()
+                .filter_map(|f| Self::bound(fields, f))
+                .reduce(|a, b| a.intersection(&b).cloned().collect()),
+            PayloadFilter::Or(filters) => {
+                let mut union = HashSet::new();
+                for filter in filters {
+                    union.extend(Self::bound(fields, filter)?);
+                }
+                Some(union)
+            }
+            PayloadFilter::Match { key, values } => {
+                let index = fields.get(key)?;
+                let mut ids = index.unindexed.clone();
+                for key in values.iter().filter_map(|v| index.key(v)) {
+                    if let Some(points) = index.entries.get(&key) {
+                        ids.extend(points.iter().cloned());
+                    }
+                }
+                Some(ids)
+            }
+            PayloadFilter::Range { key, range } => {
+                let index = fields.get(key)?;
+                if index.field_type != PayloadFieldType::Integer {
+                    return None;
+                }
+                let mut ids = index.unindexed.clone();
+                if let Some((low, high)) = integer_bounds(range) {
+                    for points in index
+                        .entries
+                        .range(Key::Integer(low)..=Key::Integer(high))
+                        .map(|(_, points)| points)
+                    {
+                        ids.extend(points.iter().cloned());
+                    }
+                }
+                Some(ids)
+            }
+            PayloadFilter::HasId(ids) => Some(ids.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FieldIndex {
+    /// Key of a value, if the index holds values like it
+    fn key(&self, value: &Value) -> Option<Key> {
+        match self.field_type {
+            PayloadFieldType::Keyword => value.as_str().map(|s| Key::Keyword(s.to_string())),
+            PayloadFieldType::Integer => integer(value).map(Key::Integer),
+        }
+    }
+
+    fn insert(&mut self, id: &str, values: Vec<&Value>) {
+        let mut keys = Vec::new();
+        for value in values {
+            match self.key(value) {
+                Some(key) => keys.push(key),
+                None => {
+                    self.unindexed.insert(id.to_string());
+                }
+            }
+        }
+        if keys.is_empty() {
+            return;
+        }
+        keys.sort();
+        keys.dedup();
+        for key in &keys {
+            self.entries
+                .entry(key.clone())
+                .or_default()
+                .insert(id.to_string());
+        }
+        self.points.insert(id.to_string(), keys);
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.unindexed.remove(id);
+        for key in self.points.remove(id).unwrap_or_default() {
+            if let Some(points) = self.entries.get_mut(&key) {
+                points.remove(id);
+                if points.is_empty() {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/// A number with no fractional part as an `i64`
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)] // range checked
+fn integer(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| {
+        let f = value.as_f64()?;
+        (f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64).then_some(f as i64)
+    })
+}
+
+/// The integers within a range, as inclusive bounds; `None` if none are
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)] // saturating
+fn integer_bounds(range: &NumberRange) -> Option<(i64, i64)> {
+    let mut low = i64::MIN as f64;
+    let mut high = i64::MAX as f64;
+    if let Some(gt) = range.gt {
+        low = low.max(gt.floor() + 1.0);
+    }
+    if let Some(gte) = range.gte {
+        low = low.max(gte.ceil());
+    }
+    if let Some(lt) = range.lt {
+        high = high.min(lt.ceil() - 1.0);
+    }
+    if let Some(lte) = range.lte {
+        high = high.min(lte.floor());
+    }
+    // Float to integer casts saturate
+    (low <= high).then_some((low as i64, high as i64))
+}
@@ -0,0 +1,428 @@
+//! Vector quantization
+//!
+//! A [`Quantizer`] is trained on a sample of an index's vectors and turns
+//! each vector into a code of a few bytes:
+//!
+//! - scalar: one byte per dimension, the component's position between the
+//!   dimension's bounds
+//! - product: one byte per segment, the nearest of 256 centroids found by
+//!   k-means on that segment of the sample
+//! - binary: one bit per dimension, set when the component is above the
+//!   dimension's mean; it decodes to the mean plus or minus the mean
+//!   deviation
+//!
+//! Queries are compared with codes asymmetrically: the query stays in full
+//! and only the stored side is approximated. Every metric is a sum of
+//! per-component terms passed through a final step, so product codes are
+//! scored with a table of each segment's term for each centroid.
+
+// Codes, counts and statistics convert freely between small integers and floats
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use lumadb_common::types::{DistanceMetric, VectorQuantization};
+
+/// Centroids per product quantization segment, so a segment fits a byte
+const CENTROIDS: usize = 256;
+
+/// k-means iterations when training product quantization codebooks
+const KMEANS_ITERATIONS: usize = 10;
+
+/// Sample vectors used as queries when measuring a trained quantizer
+const MEASURE_QUERIES: usize = 32;
+
+/// Results per query when measuring recall
+pub(super) const MEASURE_K: usize = 10;
+
+/// Trained parameters turning vectors into codes and back
+#[derive(Serialize, Deserialize)]
+pub(super) enum Quantizer {
+    /// Per dimension, the value of code 0 and the step between codes
+    Scalar { offset: Vec<f32>, step: Vec<f32> },
+    /// Segment boundaries, and each segment's centroids laid end to end
+    Product {
+        bounds: Vec<usize>,
+        codebooks: Vec<Vec<f32>>,
+    },
+    /// Per dimension, the mean and the mean absolute deviation from it
+    Binary { mean: Vec<f32>, spread: Vec<f32> },
+}
+
+/// How well a quantizer's codes stand in for the vectors, measured on its
+/// training sample
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(super) struct Measured {
+    /// Share of the true nearest neighbors found by comparing codes
+    pub recall: f32,
+    /// The same, when twice as many candidates are rescored
+    pub rescored_recall: f32,
+    /// Mean time to compare a query with a code, in nanoseconds
+    pub code_distance_ns: f32,
+    /// Mean time to compare a query with a full vector, in nanoseconds
+    pub full_distance_ns: f32,
+}
+
+impl Quantizer {
+    /// Train on `sample`, which must not be empty
+    pub fn train(method: VectorQuantization, sample: &[Vec<f32>]) -> Self {
+        let dimensions = sample[0].len();
+        match method {
+            VectorQuantization::Scalar { quantile } => {
+                let tail = (1.0 - quantile.clamp(0.5, 1.0)) / 2.0;
+                let last = (sample.len() - 1) as f32;
+                let low = (tail * last).floor() as usize;
+                let high = ((1.0 - tail) * last).ceil() as usize;
+                let mut offset = Vec::with_capacity(dimensions);
+                let mut step = Vec::with_capacity(dimensions);
+                let mut values = Vec::with_capacity(sample.len());
+                for d in 0..dimensions {
+                    values.clear();
+                    values.extend(sample.iter().map(|v| v[d]));
+                    values.sort_by(f32::total_cmp);
+                    offset.push(values[low]);
+                    step.push((values[high] - values[low]) / 255.0);
+                }
+                Quantizer::Scalar { offset, step }
+            }
+            VectorQuantization::Product { segments } => {
+                let segments = segments.clamp(1, dimensions);
+                let bounds: Vec<usize> =
+                    (0..=segments).map(|s| s * dimensions / segments).collect();
+                let codebooks = bounds
+                    .windows(2)
+                    .map(|w| {
+                        let parts: Vec<&[f32]> = sample.iter().map(|v| &v[w[0]..w[1]]).collect();
+                        kmeans(&parts)
+                    })
+                    .collect();
+                Quantizer::Product { bounds, codebooks }
+            }
+            VectorQuantization::Binary => {
+                let n = sample.len() as f32;
+                let mean: Vec<f32> = (0..dimensions)
+                    .map(|d| sample.iter().map(|v| v[d]).sum::<f32>() / n)
+                    .collect();
+                let spread = (0..dimensions)
+                    .map(|d| sample.iter().map(|v| (v[d] - mean[d]).abs()).sum::<f32>() / n)
+                    .collect();
+                Quantizer::Binary { mean, spread }
+            }
+        }
+    }
+
+    /// The code of `vector`
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        match self {
+            Quantizer::Scalar { offset, step } => vector
+                .iter()
+                .zip(offset.iter().zip(step))
+                .map(|(x, (offset, step))| {
+                    if *step > 0.0 {
+                        ((x - offset) / step).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    }
+                })
+                .collect(),
+            Quantizer::Product { bounds, codebooks } => bounds
+                .windows(2)
+                .zip(codebooks)
+                .map(|(w, codebook)| nearest(codebook, &vector[w[0]..w[1]]) as u8)
+                .collect(),
+            Quantizer::Binary { mean, .. } => {
+                let mut code = vec![0u8; vector.len().div_ceil(8)];
+                for (i, (x, mean)) in vector.iter().zip(mean).enumerate() {
+                    if x > mean {
+                        code[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                code
+            }
+        }
+    }
+
+    /// The vector a code stands for
+    pub fn decode(&self, code: &[u8]) -> Vec<f32> {
+        match self {
+            Quantizer::Scalar { offset, step } => (0..offset.len())
+                .map(|i| scalar(offset, step, code, i))
+                .collect(),
+            Quantizer::Product { bounds, codebooks } => {
+                let mut vector = Vec::with_capacity(bounds[bounds.len() - 1]);
+                for ((w, codebook), &c) in bounds.windows(2).zip(codebooks).zip(code) {
+                    let len = w[1] - w[0];
+                    vector.extend_from_slice(&codebook[c as usize * len..(c as usize + 1) * len]);
+                }
+                vector
+            }
+            Quantizer::Binary { mean, spread } => (0..mean.len())
+                .map(|i| binary(mean, spread, code, i))
+                .collect(),
+        }
+    }
+
+    /// What comparing `query` with product codes takes: each segment's
+    /// term for each centroid (`None` for other quantizers)
+    pub fn table(&self, metric: DistanceMetric, query: &[f32]) -> Option<Vec<f32>> {
+        let Quantizer::Product { bounds, codebooks } = self else {
+            return None;
+        };
+        let mut table = Vec::with_capacity(codebooks.len() * CENTROIDS);
+        for (w, codebook) in bounds.windows(2).zip(codebooks) {
+            let part = &query[w[0]..w[1]];
+            table.extend(
+                codebook
+                    .chunks_exact(part.len())
+                    .map(|centroid| partial(metric, part, |i| centroid[i])),
+            );
+        }
+        Some(table)
+    }
+
+    /// Distance from `query` (with its [`Quantizer::table`]) to the vector
+    /// a code stands for
+    pub fn distance(
+        &self,
+        metric: DistanceMetric,
+        query: &[f32],
+        table: Option<&[f32]>,
+        code: &[u8],
+    ) -> f32 {
+        let sum = match self {
+            Quantizer::Scalar { offset, step } => {
+                partial(metric, query, |i| scalar(offset, step, code, i))
+            }
+            Quantizer::Product { codebooks, .. } => {
+                debug_assert_eq!(codebooks.len(), code.len());
+                let table = table.expect("product codes are compared through a table");
+                code.iter()
+                    .enumerate()
+                    .map(|(s, &c)| table[s * CENTROIDS + c as usize])
+                    .sum()
+            }
+            Quantizer::Binary { mean, spread } => {
+                partial(metric, query, |i| binary(mean, spread, code, i))
+            }
+        };
+        finish(metric, sum)
+    }
+
+    /// Measure recall and comparison times, using vectors of `sample` as
+    /// queries over the whole sample
+    pub fn measure(&self, metric: DistanceMetric, sample: &[Vec<f32>]) -> Measured {
+        let codes: Vec<Vec<u8>> = sample.iter().map(|v| self.encode(v)).collect();
+        let k = MEASURE_K.min(sample.len());
+        let queries = &sample[..MEASURE_QUERIES.min(sample.len())];
+        let nearest = |mut scored: Vec<(f32, usize)>, n: usize| {
+            scored.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            scored.truncate(n);
+            scored.into_iter().map(|(_, i)| i).collect::<Vec<_>>()
+        };
+
+        let (mut found, mut rescored_found) = (0, 0);
+        let (mut full_time, mut code_time) = (0.0, 0.0);
+        for query in queries {
+            let start = Instant::now();
+            let exact: Vec<f32> = sample
+                .iter()
+                .map(|v| finish(metric, partial(metric, query, |i| v[i])))
+                .collect();
+            full_time += start.elapsed().as_secs_f64();
+
+            let start = Instant::now();
+            let table = self.table(metric, query);
+            let approximate: Vec<f32> = codes
+                .iter()
+                .map(|code| self.distance(metric, query, table.as_deref(), code))
+                .collect();
+            code_time += start.elapsed().as_secs_f64();
+
+            let truth = nearest(exact.iter().copied().zip(0..).collect(), k);
+            let oversampled = (k as f32 * super::DEFAULT_OVERSAMPLING).ceil() as usize;
+            let candidates = nearest(approximate.into_iter().zip(0..).collect(), oversampled);
+            let rescored = nearest(candidates.iter().map(|&i| (exact[i], i)).collect(), k);
+            found += candidates
+                .iter()
+                .take(k)
+                .filter(|i| truth.contains(i))
+                .count();
+            rescored_found += rescored.iter().filter(|i| truth.contains(i)).count();
+        }
+
+        let wanted = (queries.len() * k) as f32;
+        let comparisons = (queries.len() * sample.len()) as f64;
+        Measured {
+            recall: found as f32 / wanted,
+            rescored_recall: rescored_found as f32 / wanted,
+            code_distance_ns: (code_time * 1e9 / comparisons) as f32,
+            full_distance_ns: (full_time * 1e9 / comparisons) as f32,
+        }
+    }
+}
+
+/// Sum of `metric`'s per-component terms between `query` and the vector
+/// whose `i`th component is `component(i)`
+pub(super) fn partial(
+    metric: DistanceMetric,
+    query: &[f32],
+    component: impl Fn(usize) -> f32,
+) -> f32 {
+    let pairs = query.iter().enumerate().map(|(i, q)| (*q, component(i)));
+    match metric {
+        DistanceMetric::Euclidean => pairs.map(|(q, x)| (q - x).powi(2)).sum(),
+        DistanceMetric::Cosine | DistanceMetric::DotProduct => pairs.map(|(q, x)| q * x).sum(),
+        DistanceMetric::Manhattan => pairs.map(|(q, x)| (q - x).abs()).sum(),
+    }
+}
+
+/// The distance whose per-component terms sum to `sum`
+pub(super) fn finish(metric: DistanceMetric, sum: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => sum.sqrt(),
+        // Vectors are normalized, so this is one minus the cosine
+        DistanceMetric::Cosine => 1.0 - sum,
+        DistanceMetric::DotProduct => -sum,
+        DistanceMetric::Manhattan => sum,
+    }
+}
+
+fn scalar(offset: &[f32], step: &[f32], code: &[u8], i: usize) -> f32 {
+    offset[i] + step[i] * f32::from(code[i])
+}
+
+fn binary(mean: &[f32], spread: &[f32], code: &[u8], i: usize) -> f32 {
+    if code[i / 8] & (1 << (i % 8)) != 0 {
+        mean[i] + spread[i]
+    } else {
+        mean[i] - spread[i]
+    }
+}
+
+/// Squared Euclidean distance, which k-means minimizes whatever the metric
+fn squared(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum()
+}
+
+/// Position of the centroid of `codebook` nearest `part`
+fn nearest(codebook: &[f32], part: &[f32]) -> usize {
+    codebook
+        .chunks_exact(part.len())
+        .map(|centroid| squared(centroid, part))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(c, _)| c)
+}
+
+/// [`CENTROIDS`] centroids for `parts` (laid end to end), by k-means
+/// seeded with randomly chosen parts
+fn kmeans(parts: &[&[f32]]) -> Vec<f32> {
+    let len = parts[0].len();
+    let mut rng = rand::thread_rng();
+    let mut seeds: Vec<usize> = (0..parts.len()).collect();
+    seeds.shuffle(&mut rng);
+    let mut codebook: Vec<f32> = (0..CENTROIDS)
+        .flat_map(|c| parts[seeds[c % seeds.len()]].iter().copied())
+        .collect();
+
+    let mut assigned = vec![0; parts.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        for (part, assigned) in parts.iter().zip(assigned.iter_mut()) {
+            *assigned = nearest(&codebook, part);
+        }
+
+        let mut sums = vec![0.0f32; CENTROIDS * len];
+        let mut counts = vec![0usize; CENTROIDS];
+        for (part, &c) in parts.iter().zip(&assigned) {
+            counts[c] += 1;
+            for (sum, x) in sums[c * len..(c + 1) * len].iter_mut().zip(*part) {
+                *sum += x;
+            }
+        }
+        for (c, &count) in counts.iter().enumerate() {
+            let centroid = &mut codebook[c * len..(c + 1) * len];
+            if count == 0 {
+                // Reseed an empty cluster on a random part
+                centroid.copy_from_slice(parts[rng.gen_range(0..parts.len())]);
+            } else {
+                for (x, sum) in centroid.iter_mut().zip(&sums[c * len..(c + 1) * len]) {
+                    *x = sum / count as f32;
+                }
+            }
+        }
+    }
+    codebook
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points scattered around a few clusters, so codes can separate them
+    fn sample(n: usize, dimensions: usize) -> Vec<Vec<f32>> {
+        let mut rng = rand::thread_rng();
+        (0..n)
+            .map(|i| {
+                (0..dimensions)
+                    .map(|d| ((i % 7) * (d + 1) % 11) as f32 + rng.gen_range(-0.3..0.3))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_codes_approximate_vectors() {
+        let sample = sample(600, 16);
+        let mean: Vec<f32> = (0..16)
+            .map(|i| sample.iter().map(|v| v[i]).sum::<f32>() / sample.len() as f32)
+            .collect();
+        // Decoding errors relative to the vector's distance from the mean
+        let methods = [
+            (VectorQuantization::Scalar { quantile: 1.0 }, 16, 0.02),
+            (VectorQuantization::Product { segments: 4 }, 4, 0.3),
+            (VectorQuantization::Binary, 2, 1.0),
+        ];
+        for (method, code_size, tolerance) in methods {
+            let quantizer = Quantizer::train(method, &sample);
+            for vector in sample.iter().take(50) {
+                let code = quantizer.encode(vector);
+                assert_eq!(code.len(), code_size);
+                assert_eq!(code.len(), method.code_size(16));
+
+                // Asymmetric distances agree with the decoded vector
+                let decoded = quantizer.decode(&code);
+                let euclidean = |other: &[f32]| {
+                    let metric = DistanceMetric::Euclidean;
+                    finish(metric, partial(metric, vector, |i| other[i]))
+                };
+                let error = euclidean(&decoded) / euclidean(&mean);
+                assert!(error < tolerance, "{method:?} decodes {error} away");
+                for metric in [DistanceMetric::Euclidean, DistanceMetric::DotProduct] {
+                    let table = quantizer.table(metric, &sample[1]);
+                    let direct = finish(metric, partial(metric, &sample[1], |i| decoded[i]));
+                    let coded = quantizer.distance(metric, &sample[1], table.as_deref(), &code);
+                    assert!((direct - coded).abs() < 1e-3);
+                }
+            }
+
+            let measured = quantizer.measure(DistanceMetric::Euclidean, &sample);
+            assert!(measured.rescored_recall >= measured.recall);
+            if matches!(method, VectorQuantization::Scalar { .. }) {
+                assert!(measured.recall > 0.8, "scalar recall {}", measured.recall);
+            }
+        }
+
+        // Values beyond the bounds clamp to the extreme codes
+        let quantizer = Quantizer::train(VectorQuantization::Scalar { quantile: 0.9 }, &sample);
+        let code = quantizer.encode(&[1000.0; 16]);
+        assert!(code.iter().all(|&c| c == 255));
+    }
+}
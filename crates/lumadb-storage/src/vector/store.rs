@@ -0,0 +1,259 @@
+//! Vector index persistence
+//!
+//! Every change is appended to `vectors.log` as a frame
+//! `[len u32][crc32 u32][seq u64][payload]` before it is applied. A snapshot
+//! (`graph.snapshot`, `[crc32 u32][seq u64][sealed u8][payload]`) holds the whole
+//! graph as of a sequence number and is replaced atomically; the log is
+//! truncated once a snapshot covers it. Recovery loads the snapshot and
+//! replays the logged changes with a later sequence number, dropping a torn
+//! tail.
+//!
+//! With a data key, payloads are sealed with the sequence number as
+//! associated data, so frames cannot be reordered undetected.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use lumadb_common::error::{Error, Result, StorageError};
+
+use crate::encryption::{self, DataKeys};
+
+const LOG_FILE: &str = "vectors.log";
+const SNAPSHOT_FILE: &str = "graph.snapshot";
+
+/// Frame header: length (4) + crc (4)
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// A logged change to a vector index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) enum VectorOp {
+    /// Vector inserted or replaced
+    Upsert { id: String, vector: Vec<f32> },
+    /// Vector deleted
+    Delete { id: String },
+}
+
+/// State read back when a store is opened
+pub(super) struct Recovered {
+    /// Serialized graph from the latest snapshot
+    pub snapshot: Option<Vec<u8>>,
+    /// Changes logged after the snapshot, oldest first
+    pub ops: Vec<VectorOp>,
+}
+
+/// Snapshot and change log of one vector index
+pub(super) struct VectorStore {
+    dir: PathBuf,
+    log: File,
+    cipher: Option<Arc<DataKeys>>,
+    /// fsync every logged change
+    sync: bool,
+    /// Sequence number of the next change
+    next_seq: u64,
+    /// Changes logged since the last snapshot
+    pending: u64,
+}
+
+impl VectorStore {
+    /// Open (or create) the store in `dir` and read back its contents
+    pub fn open(
+        dir: &Path,
+        cipher: Option<Arc<DataKeys>>,
+        sync: bool,
+    ) -> Result<(Self, Recovered)> {
+        fs::create_dir_all(dir)?;
+
+        let (snapshot_seq, snapshot) = match fs::read(dir.join(SNAPSHOT_FILE)) {
+            Ok(data) => {
+                let (seq, payload) = Self::decode_snapshot(dir, &data, cipher.as_deref())?;
+                (seq, Some(payload))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let log_path = dir.join(LOG_FILE);
+        let data = match fs::read(&log_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut ops = Vec::new();
+        let mut last_seq = snapshot_seq;
+        let mut pos = 0;
+        while let Some((seq, payload, len)) = Self::next_frame(&data[pos..]) {
+            if seq > snapshot_seq {
+                let payload = match cipher.as_deref() {
+                    Some(cipher) => cipher.open_or_plain(&seq.to_le_bytes(), payload)?,
+                    None if encryption::is_sealed(payload) => {
+                        return Err(Self::no_key(&log_path));
+                    }
+                    None => payload.to_vec(),
+                };
+                ops.push(bincode::deserialize(&payload).map_err(|e| {
+                    Error::Storage(StorageError::CorruptData(format!(
+                        "Vector log {}: {e}",
+                        log_path.display()
+                    )))
+                })?);
+                last_seq = last_seq.max(seq);
+            }
+            pos += len;
+        }
+
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        if pos < data.len() {
+            warn!(
+                "Truncating torn tail of vector log {:?} at byte {}",
+                log_path, pos
+            );
+            log.set_len(pos as u64)?;
+            log.seek(SeekFrom::End(0))?;
+        }
+
+        let store = Self {
+            dir: dir.to_path_buf(),
+            log,
+            cipher,
+            sync,
+            next_seq: last_seq + 1,
+            pending: ops.len() as u64,
+        };
+        Ok((store, Recovered { snapshot, ops }))
+    }
+
+    /// Append a change to the log
+    pub fn append(&mut self, op: &VectorOp) -> Result<()> {
+        let seq = self.next_seq;
+        let mut payload =
+            bincode::serialize(op).map_err(|e| Error::Serialization(e.to_string()))?;
+        if let Some(ref cipher) = self.cipher {
+            payload = cipher.seal(&seq.to_le_bytes(), &payload)?;
+        }
+
+        let mut body = Vec::with_capacity(8 + payload.len());
+        body.extend_from_slice(&seq.to_le_bytes());
+        body.extend_from_slice(&payload);
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + body.len());
+        let len = u32::try_from(body.len()).map_err(|_| {
+            Error::Storage(StorageError::WriteFailed(format!(
+                "vector log record of {} bytes is too large",
+                body.len()
+            )))
+        })?;
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        frame.extend_from_slice(&body);
+
+        self.log.write_all(&frame)?;
+        if self.sync {
+            self.log.sync_data()?;
+        }
+
+        self.next_seq += 1;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Atomically replace the snapshot with `graph` (the serialized state
+    /// after every logged change) and truncate the log
+    pub fn write_snapshot(&mut self, graph: &[u8]) -> Result<()> {
+        let seq = self.next_seq - 1;
+        let payload = match self.cipher {
+            Some(ref cipher) => cipher.seal(&seq.to_le_bytes(), graph)?,
+            None => graph.to_vec(),
+        };
+
+        let mut body = Vec::with_capacity(9 + payload.len());
+        body.extend_from_slice(&seq.to_le_bytes());
+        body.push(u8::from(self.cipher.is_some()));
+        body.extend_from_slice(&payload);
+
+        let tmp = self.dir.join(format!("{SNAPSHOT_FILE}.tmp"));
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&crc32fast::hash(&body).to_le_bytes())?;
+            file.write_all(&body)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE))?;
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        // Changes up to `seq` are in the snapshot; a crash before the
+        // truncation only leaves frames that recovery skips
+        self.log.set_len(0)?;
+        self.log.seek(SeekFrom::Start(0))?;
+        self.log.sync_all()?;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Number of changes logged since the last snapshot
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    /// Split the next complete, intact frame off `data`: its sequence
+    /// number, payload and total length
+    fn next_frame(data: &[u8]) -> Option<(u64, &[u8], usize)> {
+        let header = data.get(..FRAME_HEADER_SIZE)?;
+        let len = u32::from_le_bytes(header[0..4].try_into().ok()?) as usize;
+        let crc = u32::from_le_bytes(header[4..8].try_into().ok()?);
+        let body = data.get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len)?;
+        if len < 8 || crc32fast::hash(body) != crc {
+            return None;
+        }
+        let seq = u64::from_le_bytes(body[..8].try_into().ok()?);
+        Some((seq, &body[8..], FRAME_HEADER_SIZE + len))
+    }
+
+    fn decode_snapshot(
+        dir: &Path,
+        data: &[u8],
+        cipher: Option<&DataKeys>,
+    ) -> Result<(u64, Vec<u8>)> {
+        let path = dir.join(SNAPSHOT_FILE);
+        let corrupt = || {
+            Error::Storage(StorageError::CorruptData(format!(
+                "Vector snapshot {} failed its checksum",
+                path.display()
+            )))
+        };
+        if data.len() < 13 {
+            return Err(corrupt());
+        }
+        let crc = u32::from_le_bytes(data[..4].try_into().map_err(|_| corrupt())?);
+        let body = &data[4..];
+        if crc32fast::hash(body) != crc {
+            return Err(corrupt());
+        }
+
+        let seq = u64::from_le_bytes(body[..8].try_into().map_err(|_| corrupt())?);
+        let payload = &body[9..];
+        let payload = match (body[8] != 0, cipher) {
+            (true, Some(cipher)) => cipher.open(&seq.to_le_bytes(), payload)?,
+            (true, None) => return Err(Self::no_key(&path)),
+            (false, _) => payload.to_vec(),
+        };
+        Ok((seq, payload))
+    }
+
+    fn no_key(path: &Path) -> Error {
+        Error::Storage(StorageError::Encryption(format!(
+            "{} is encrypted but no data key is configured",
+            path.display()
+        )))
+    }
+}
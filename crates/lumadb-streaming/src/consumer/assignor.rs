@@ -0,0 +1,344 @@
+//! Built-in partition assignors
+//!
+//! Kafka clients run an assignor in their group leader. Consumers that let
+//! the server assign partitions instead, such as those polling the REST
+//! consume endpoint, pick one of these by its Kafka protocol name. Members
+//! are visited in member id order so every assignor is deterministic.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use lumadb_common::types::PartitionId;
+
+/// A topic and partition
+pub type TopicPartition = (String, PartitionId);
+
+/// Topics a member consumes, sent as its group protocol metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Subscription {
+    pub topics: Vec<String>,
+    /// Partitions assigned to the member in the previous generation
+    #[serde(default)]
+    pub owned: Vec<TopicPartition>,
+}
+
+impl Subscription {
+    fn subscribes(&self, topic: &str) -> bool {
+        self.topics.iter().any(|t| t == topic)
+    }
+}
+
+/// Outcome of an assignment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupAssignment {
+    /// Partitions per member id
+    pub members: BTreeMap<String, Vec<TopicPartition>>,
+    /// Whether partitions were held back until their previous owner gives
+    /// them up, which takes another rebalance
+    pub rebalance_needed: bool,
+}
+
+/// Partition assignment strategies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Assignor {
+    /// Contiguous ranges of each topic's partitions
+    #[serde(rename = "range")]
+    Range,
+    /// Every subscribed partition dealt out in turn
+    #[serde(rename = "roundrobin")]
+    RoundRobin,
+    /// Balanced assignment that keeps partitions with their owners and only
+    /// moves a partition once its owner has released it
+    #[serde(rename = "cooperative-sticky")]
+    CooperativeSticky,
+}
+
+impl Assignor {
+    /// Kafka protocol name
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Range => "range",
+            Self::RoundRobin => "roundrobin",
+            Self::CooperativeSticky => "cooperative-sticky",
+        }
+    }
+
+    /// Assign the partitions of every subscribed topic to `members`
+    ///
+    /// `partitions` holds the partition count of each topic; subscriptions
+    /// to topics missing from it are ignored.
+    #[must_use]
+    pub fn assign(
+        self,
+        members: &BTreeMap<String, Subscription>,
+        partitions: &BTreeMap<String, i32>,
+    ) -> GroupAssignment {
+        match self {
+            Self::Range => GroupAssignment {
+                members: range(members, partitions),
+                rebalance_needed: false,
+            },
+            Self::RoundRobin => GroupAssignment {
+                members: round_robin(members, partitions),
+                rebalance_needed: false,
+            },
+            Self::CooperativeSticky => cooperative_sticky(members, partitions),
+        }
+    }
+}
+
+impl std::str::FromStr for Assignor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "range" => Ok(Self::Range),
+            "roundrobin" => Ok(Self::RoundRobin),
+            "cooperative-sticky" => Ok(Self::CooperativeSticky),
+            _ => Err(format!("unknown assignor '{s}'")),
+        }
+    }
+}
+
+fn empty_assignment(
+    members: &BTreeMap<String, Subscription>,
+) -> BTreeMap<String, Vec<TopicPartition>> {
+    members.keys().map(|id| (id.clone(), Vec::new())).collect()
+}
+
+/// Every partition of the subscribed topics, in topic order
+fn subscribed_partitions(
+    members: &BTreeMap<String, Subscription>,
+    partitions: &BTreeMap<String, i32>,
+) -> BTreeSet<TopicPartition> {
+    partitions
+        .iter()
+        .filter(|(topic, _)| members.values().any(|s| s.subscribes(topic)))
+        .flat_map(|(topic, &count)| (0..count).map(move |p| (topic.clone(), p)))
+        .collect()
+}
+
+/// Per topic, split the partitions into contiguous ranges; the first
+/// members get one extra partition when they do not divide evenly
+fn range(
+    members: &BTreeMap<String, Subscription>,
+    partitions: &BTreeMap<String, i32>,
+) -> BTreeMap<String, Vec<TopicPartition>> {
+    let mut assignment = empty_assignment(members);
+    for (topic, &count) in partitions {
+        let subscribers: Vec<&String> = members
+            .iter()
+            .filter(|(_, s)| s.subscribes(topic))
+            .map(|(id, _)| id)
+            .collect();
+        let Ok(n) = i32::try_from(subscribers.len()) else {
+            continue;
+        };
+        if n == 0 {
+            continue;
+        }
+
+        let mut next = 0;
+        for (i, id) in (0..).zip(subscribers) {
+            let len = count / n + i32::from(i < count % n);
+            if let Some(assigned) = assignment.get_mut(id) {
+                assigned.extend((next..next + len).map(|p| (topic.clone(), p)));
+            }
+            next += len;
+        }
+    }
+    assignment
+}
+
+/// Deal every partition to the next member subscribed to its topic
+fn round_robin(
+    members: &BTreeMap<String, Subscription>,
+    partitions: &BTreeMap<String, i32>,
+) -> BTreeMap<String, Vec<TopicPartition>> {
+    let mut assignment = empty_assignment(members);
+    let ids: Vec<&String> = members.keys().collect();
+    let mut cursor = 0;
+    for tp in subscribed_partitions(members, partitions) {
+        let next = (0..ids.len())
+            .map(|step| (cursor + step) % ids.len())
+            .find(|&i| members[ids[i]].subscribes(&tp.0));
+        if let Some(i) = next {
+            if let Some(assigned) = assignment.get_mut(ids[i]) {
+                assigned.push(tp);
+            }
+            cursor = (i + 1) % ids.len();
+        }
+    }
+    assignment
+}
+
+/// Balanced assignment that keeps owned partitions where possible, then
+/// withholds partitions whose owner has not released them yet
+fn cooperative_sticky(
+    members: &BTreeMap<String, Subscription>,
+    partitions: &BTreeMap<String, i32>,
+) -> GroupAssignment {
+    let target = sticky(members, partitions);
+
+    let owners: BTreeMap<&TopicPartition, &String> = members
+        .iter()
+        .flat_map(|(id, s)| s.owned.iter().map(move |tp| (tp, id)))
+        .collect();
+    let mut rebalance_needed = false;
+    let members = target
+        .into_iter()
+        .map(|(id, assigned)| {
+            let kept = assigned
+                .into_iter()
+                .filter(|tp| {
+                    let moving = owners.get(tp).is_some_and(|owner| **owner != id);
+                    rebalance_needed |= moving;
+                    !moving
+                })
+                .collect();
+            (id, kept)
+        })
+        .collect();
+    GroupAssignment {
+        members,
+        rebalance_needed,
+    }
+}
+
+/// Balanced assignment preferring each member's owned partitions
+fn sticky(
+    members: &BTreeMap<String, Subscription>,
+    partitions: &BTreeMap<String, i32>,
+) -> BTreeMap<String, Vec<TopicPartition>> {
+    let mut assignment = empty_assignment(members);
+    let all = subscribed_partitions(members, partitions);
+    let consumers = members.values().filter(|s| !s.topics.is_empty()).count();
+    if consumers == 0 {
+        return assignment;
+    }
+    let quota = all.len().div_ceil(consumers);
+
+    // Keep owned partitions that are still valid, up to the quota
+    let mut taken = BTreeSet::new();
+    for (id, subscription) in members {
+        let assigned = assignment.entry(id.clone()).or_default();
+        for tp in &subscription.owned {
+            if assigned.len() < quota
+                && subscription.subscribes(&tp.0)
+                && all.contains(tp)
+                && taken.insert(tp.clone())
+            {
+                assigned.push(tp.clone());
+            }
+        }
+    }
+
+    // Hand out the rest to the least loaded subscriber
+    for tp in all.difference(&taken) {
+        let least = members
+            .iter()
+            .filter(|(_, s)| s.subscribes(&tp.0))
+            .map(|(id, _)| id)
+            .min_by_key(|id| assignment.get(*id).map_or(0, Vec::len));
+        if let Some(assigned) = least.and_then(|id| assignment.get_mut(id)) {
+            assigned.push(tp.clone());
+        }
+    }
+
+    // Move partitions from overloaded members while subscriptions allow
+    for _ in 0..all.len() {
+        let moved = members.keys().find_map(|to| {
+            let to_len = assignment[to].len();
+            members.keys().find_map(|from| {
+                if assignment[from].len() <= to_len + 1 {
+                    return None;
+                }
+                let index = assignment[from]
+                    .iter()
+                    .rposition(|tp| members[to].subscribes(&tp.0))?;
+                Some((from.clone(), to.clone(), index))
+            })
+        });
+        let Some((from, to, index)) = moved else {
+            break;
+        };
+        let tp = assignment.get_mut(&from).map(|a| a.remove(index));
+        if let (Some(tp), Some(assigned)) = (tp, assignment.get_mut(&to)) {
+            assigned.push(tp);
+        }
+    }
+
+    for assigned in assignment.values_mut() {
+        assigned.sort();
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (member id, subscribed topics, owned partitions)
+    type MemberSpec<'a> = (&'a str, &'a [&'a str], &'a [(&'a str, i32)]);
+
+    fn members(specs: &[MemberSpec<'_>]) -> BTreeMap<String, Subscription> {
+        specs
+            .iter()
+            .map(|(id, topics, owned)| {
+                (
+                    (*id).to_string(),
+                    Subscription {
+                        topics: topics.iter().map(|t| (*t).to_string()).collect(),
+                        owned: owned.iter().map(|(t, p)| ((*t).to_string(), *p)).collect(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn partitions(specs: &[(&str, i32)]) -> BTreeMap<String, i32> {
+        specs.iter().map(|(t, n)| ((*t).to_string(), *n)).collect()
+    }
+
+    fn tps(specs: &[(&str, i32)]) -> Vec<TopicPartition> {
+        specs.iter().map(|(t, p)| ((*t).to_string(), *p)).collect()
+    }
+
+    #[test]
+    fn range_splits_each_topic() {
+        let members = members(&[("a", &["t", "u"], &[]), ("b", &["t", "u"], &[])]);
+        let result = Assignor::Range.assign(&members, &partitions(&[("t", 3), ("u", 2)]));
+        assert_eq!(result.members["a"], tps(&[("t", 0), ("t", 1), ("u", 0)]));
+        assert_eq!(result.members["b"], tps(&[("t", 2), ("u", 1)]));
+    }
+
+    #[test]
+    fn round_robin_respects_subscriptions() {
+        let members = members(&[("a", &["t"], &[]), ("b", &["t", "u"], &[])]);
+        let result = Assignor::RoundRobin.assign(&members, &partitions(&[("t", 3), ("u", 2)]));
+        assert_eq!(result.members["a"], tps(&[("t", 0), ("t", 2)]));
+        assert_eq!(result.members["b"], tps(&[("t", 1), ("u", 0), ("u", 1)]));
+    }
+
+    #[test]
+    fn cooperative_sticky_moves_partitions_in_two_steps() {
+        let counts = partitions(&[("t", 4)]);
+
+        // A new member joins a group where one member owns everything
+        let first = members(&[
+            ("a", &["t"], &[("t", 0), ("t", 1), ("t", 2), ("t", 3)]),
+            ("b", &["t"], &[]),
+        ]);
+        let result = Assignor::CooperativeSticky.assign(&first, &counts);
+        assert_eq!(result.members["a"], tps(&[("t", 0), ("t", 1)]));
+        assert!(result.members["b"].is_empty());
+        assert!(result.rebalance_needed);
+
+        // Once released, the partitions go to the new member
+        let second = members(&[("a", &["t"], &[("t", 0), ("t", 1)]), ("b", &["t"], &[])]);
+        let result = Assignor::CooperativeSticky.assign(&second, &counts);
+        assert_eq!(result.members["a"], tps(&[("t", 0), ("t", 1)]));
+        assert_eq!(result.members["b"], tps(&[("t", 2), ("t", 3)]));
+        assert!(!result.rebalance_needed);
+    }
+}
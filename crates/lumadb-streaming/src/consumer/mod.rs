@@ -1,44 +1,176 @@
 //! Consumer group management
+//!
+//! Implements the Kafka group membership protocol: members join, one of them
+//! is elected leader and computes assignments, and everyone collects their
+//! assignment through sync. A join parks until every known member has
+//! rejoined or the rebalance timeout expires, at which point stragglers are
+//! dropped from the group. Members that stop heartbeating are evicted once
+//! their session timeout passes (see [`ConsumerGroup::expire_members`]).
+//!
+//! Static members (with a `group.instance.id`) keep their place across
+//! restarts: rejoining under a new member id takes over the old
+//! registration without a rebalance, and the old id is fenced.
+//!
+//! Groups whose members cannot compute assignments themselves use one of
+//! the built-in [`Assignor`]s, run by the server on the leader's behalf.
+//! Offset commits and group metadata are kept in the internal
+//! [`OFFSETS_TOPIC`] so they survive restarts.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
 
 use dashmap::DashMap;
-use parking_lot::RwLock;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
 
 use lumadb_common::types::{Offset, PartitionId};
 
+mod assignor;
+mod offsets;
+
+pub use assignor::{Assignor, GroupAssignment, Subscription, TopicPartition};
+pub use offsets::{
+    load_groups, metadata_record, offset_record, partition_for, OffsetsKey, OFFSETS_TOPIC,
+};
+
 /// Consumer group for coordinating multiple consumers
 pub struct ConsumerGroup {
     /// Group ID
     group_id: String,
     /// Committed offsets: topic -> partition -> offset
     offsets: DashMap<String, DashMap<PartitionId, AtomicI64>>,
-    /// Group members
-    members: RwLock<Vec<Member>>,
-    /// Current generation
-    generation: AtomicI64,
-    /// Group state
-    state: RwLock<GroupState>,
+    /// Membership and rebalance state
+    inner: Mutex<GroupInner>,
 }
 
 /// Group member
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct Member {
     id: String,
+    group_instance_id: Option<String>,
     client_id: String,
-    assigned_partitions: Vec<(String, PartitionId)>,
+    client_host: String,
+    session_timeout_ms: i32,
+    rebalance_timeout_ms: i32,
+    /// Supported protocols in preference order with their metadata
+    protocols: Vec<(String, Vec<u8>)>,
+    /// Assignment from the last completed sync
+    assignment: Vec<u8>,
     last_heartbeat: i64,
 }
 
 /// Group state
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum GroupState {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum GroupState {
+    /// No members
     Empty,
-    Stable,
+    /// Waiting for members to (re)join
     PreparingRebalance,
+    /// Waiting for the leader's assignments
     CompletingRebalance,
-    Dead,
+    /// Members have their assignments
+    Stable,
+}
+
+struct GroupInner {
+    state: GroupState,
+    generation: i32,
+    protocol_type: Option<String>,
+    protocol_name: Option<String>,
+    leader: Option<String>,
+    /// Members in join order
+    members: Vec<Member>,
+    /// Member ids handed out with `MemberIdRequired` and not yet used
+    pending_members: HashSet<String>,
+    pending_joins: HashMap<String, oneshot::Sender<Result<JoinGroupResponse, GroupError>>>,
+    pending_syncs: HashMap<String, oneshot::Sender<Result<Vec<u8>, GroupError>>>,
+    /// When the current rebalance gives up on members that did not rejoin
+    rebalance_deadline: Option<i64>,
+    /// Whether the durable state changed since it was last taken
+    dirty: bool,
+}
+
+/// Durable part of a group's state
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GroupMetadata {
+    state: GroupState,
+    generation: i32,
+    protocol_type: Option<String>,
+    protocol_name: Option<String>,
+    leader: Option<String>,
+    members: Vec<Member>,
+}
+
+/// Group protocol errors, mapped onto Kafka error codes by the wire protocol
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GroupError {
+    #[error("unknown member id")]
+    UnknownMemberId,
+    #[error("illegal generation")]
+    IllegalGeneration,
+    #[error("rebalance in progress")]
+    RebalanceInProgress,
+    #[error("inconsistent group protocol")]
+    InconsistentGroupProtocol,
+    #[error("member id required")]
+    MemberIdRequired(String),
+    #[error("static member was replaced by a newer instance")]
+    FencedInstanceId,
+    #[error("session timeout is outside the allowed range")]
+    InvalidSessionTimeout,
+}
+
+/// A JoinGroup request
+#[derive(Debug, Clone)]
+pub struct JoinGroupRequest {
+    /// Member id, empty for a new member
+    pub member_id: String,
+    /// Static member id
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub session_timeout_ms: i32,
+    pub rebalance_timeout_ms: i32,
+    pub protocol_type: String,
+    /// Supported protocols in preference order with their metadata
+    pub protocols: Vec<(String, Vec<u8>)>,
+    /// Whether a new member must first be given an id and rejoin with it
+    pub require_known_member_id: bool,
+}
+
+/// Result of a completed join
+#[derive(Debug, Clone)]
+pub struct JoinGroupResponse {
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader: String,
+    pub member_id: String,
+    /// All members with their metadata for the chosen protocol; only sent to
+    /// the leader
+    pub members: Vec<JoinedMember>,
+}
+
+/// A member as seen by the group leader
+#[derive(Debug, Clone)]
+pub struct JoinedMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub metadata: Vec<u8>,
+}
+
+/// Summary of a group member
+#[derive(Debug, Clone)]
+pub struct MemberDescription {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub session_timeout_ms: i32,
+    /// Time of the last join or heartbeat in milliseconds
+    pub last_heartbeat: i64,
+    pub assignment: Vec<u8>,
 }
 
 impl ConsumerGroup {
@@ -47,10 +179,69 @@ impl ConsumerGroup {
         Self {
             group_id: group_id.to_string(),
             offsets: DashMap::new(),
-            members: RwLock::new(Vec::new()),
-            generation: AtomicI64::new(0),
-            state: RwLock::new(GroupState::Empty),
+            inner: Mutex::new(GroupInner {
+                state: GroupState::Empty,
+                generation: 0,
+                protocol_type: None,
+                protocol_name: None,
+                leader: None,
+                members: Vec::new(),
+                pending_members: HashSet::new(),
+                pending_joins: HashMap::new(),
+                pending_syncs: HashMap::new(),
+                rebalance_deadline: None,
+                dirty: false,
+            }),
+        }
+    }
+
+    /// Recreate a group from its durable state
+    ///
+    /// Restored members get a fresh session; a group caught mid-rebalance
+    /// waits for its members to rejoin.
+    #[must_use]
+    pub fn restore(group_id: &str, metadata: GroupMetadata) -> Self {
+        let group = Self::new(group_id);
+        {
+            let mut inner = group.inner.lock();
+            let now = chrono::Utc::now().timestamp_millis();
+            inner.generation = metadata.generation;
+            inner.protocol_type = metadata.protocol_type;
+            inner.protocol_name = metadata.protocol_name;
+            inner.leader = metadata.leader;
+            inner.members = metadata.members;
+            for member in &mut inner.members {
+                member.last_heartbeat = now;
+            }
+            match metadata.state {
+                _ if inner.members.is_empty() => inner.reset(),
+                GroupState::Stable => inner.state = GroupState::Stable,
+                _ => inner.prepare_rebalance(),
+            }
         }
+        group
+    }
+
+    /// Durable state of the group if it changed since the last call
+    pub fn take_metadata(&self) -> Option<GroupMetadata> {
+        let mut inner = self.inner.lock();
+        if !inner.dirty {
+            return None;
+        }
+        inner.dirty = false;
+        Some(GroupMetadata {
+            state: inner.state,
+            generation: inner.generation,
+            protocol_type: inner.protocol_type.clone(),
+            protocol_name: inner.protocol_name.clone(),
+            leader: inner.leader.clone(),
+            members: inner.members.clone(),
+        })
+    }
+
+    /// Flag the durable state as changed, e.g. after writing it failed
+    pub fn mark_dirty(&self) {
+        self.inner.lock().dirty = true;
     }
 
     /// Get committed offset for a topic/partition
@@ -64,6 +255,21 @@ impl ConsumerGroup {
             })
     }
 
+    /// All committed offsets
+    pub fn offsets(&self) -> Vec<(String, PartitionId, Offset)> {
+        let mut offsets = Vec::new();
+        for topic in &self.offsets {
+            for partition in topic.value() {
+                offsets.push((
+                    topic.key().clone(),
+                    *partition.key(),
+                    partition.value().load(Ordering::SeqCst),
+                ));
+            }
+        }
+        offsets
+    }
+
     /// Commit an offset
     pub fn commit_offset(&self, topic: &str, partition: PartitionId, offset: Offset) {
         let partitions = self
@@ -77,98 +283,317 @@ impl ConsumerGroup {
             .store(offset, Ordering::SeqCst);
     }
 
-    /// Join the group
-    pub fn join(&self, member_id: &str, client_id: &str) -> JoinResult {
-        let mut members = self.members.write();
-        let mut state = self.state.write();
-
-        // Check if member already exists
-        if let Some(member) = members.iter_mut().find(|m| m.id == member_id) {
-            member.last_heartbeat = chrono::Utc::now().timestamp_millis();
-            return JoinResult {
-                generation: self.generation.load(Ordering::SeqCst),
-                leader_id: members.first().map(|m| m.id.clone()),
-                member_id: member_id.to_string(),
+    /// Check that a member may commit offsets for `generation`
+    ///
+    /// Commits with a negative generation come from consumers that manage
+    /// their own partitions and are only accepted while the group is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member is unknown or `generation` is stale.
+    pub fn validate_commit(
+        &self,
+        member_id: &str,
+        group_instance_id: Option<&str>,
+        generation: i32,
+    ) -> Result<(), GroupError> {
+        let inner = self.inner.lock();
+        if generation < 0 {
+            return if inner.state == GroupState::Empty {
+                Ok(())
+            } else {
+                Err(GroupError::IllegalGeneration)
             };
         }
+        inner.check_instance(member_id, group_instance_id)?;
+        if !inner.members.iter().any(|m| m.id == member_id) {
+            return Err(GroupError::UnknownMemberId);
+        }
+        if generation != inner.generation {
+            return Err(GroupError::IllegalGeneration);
+        }
+        if inner.state == GroupState::PreparingRebalance {
+            return Err(GroupError::RebalanceInProgress);
+        }
+        Ok(())
+    }
 
-        // Add new member
-        members.push(Member {
-            id: member_id.to_string(),
-            client_id: client_id.to_string(),
-            assigned_partitions: Vec::new(),
-            last_heartbeat: chrono::Utc::now().timestamp_millis(),
-        });
+    /// Join the group, waiting for the rebalance to complete
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member's protocols do not match the group's,
+    /// a new member must rejoin with its assigned id, the member is unknown,
+    /// or the rebalance is abandoned.
+    pub async fn join(&self, request: JoinGroupRequest) -> Result<JoinGroupResponse, GroupError> {
+        let (rx, wait) = {
+            let mut inner = self.inner.lock();
 
-        // Trigger rebalance
-        *state = GroupState::PreparingRebalance;
-        let new_gen = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+            inner.check_protocol(&request)?;
 
-        JoinResult {
-            generation: new_gen,
-            leader_id: members.first().map(|m| m.id.clone()),
-            member_id: member_id.to_string(),
-        }
-    }
+            let instance = request.group_instance_id.as_deref();
+            let member_id = if request.member_id.is_empty() {
+                let id = format!("{}-{}", request.client_id, uuid::Uuid::new_v4());
+                if let Some(old_id) = instance.and_then(|i| inner.member_for_instance(i)) {
+                    if let Some(response) = inner.rejoin_static(&old_id, &id, &request.protocols) {
+                        return Ok(response);
+                    }
+                } else if request.require_known_member_id && instance.is_none() {
+                    inner.pending_members.insert(id.clone());
+                    return Err(GroupError::MemberIdRequired(id));
+                }
+                id
+            } else if instance.is_some_and(|i| {
+                inner
+                    .member_for_instance(i)
+                    .is_some_and(|id| id != request.member_id)
+            }) {
+                return Err(GroupError::FencedInstanceId);
+            } else if inner.pending_members.remove(&request.member_id)
+                || inner.members.iter().any(|m| m.id == request.member_id)
+            {
+                request.member_id.clone()
+            } else {
+                return Err(GroupError::UnknownMemberId);
+            };
 
-    /// Leave the group
-    pub fn leave(&self, member_id: &str) {
-        let mut members = self.members.write();
-        members.retain(|m| m.id != member_id);
+            // A stable member rejoining with unchanged metadata gets the
+            // current generation back without a rebalance
+            if inner.state == GroupState::Stable {
+                if let Some(member) = inner.members.iter().find(|m| m.id == member_id) {
+                    if member.protocols == request.protocols {
+                        return Ok(inner.join_response(&member_id));
+                    }
+                }
+            }
 
-        if members.is_empty() {
-            *self.state.write() = GroupState::Empty;
+            let now = chrono::Utc::now().timestamp_millis();
+            let member = Member {
+                id: member_id.clone(),
+                group_instance_id: request.group_instance_id.clone(),
+                client_id: request.client_id.clone(),
+                client_host: request.client_host.clone(),
+                session_timeout_ms: request.session_timeout_ms,
+                rebalance_timeout_ms: request.rebalance_timeout_ms,
+                protocols: request.protocols.clone(),
+                assignment: Vec::new(),
+                last_heartbeat: now,
+            };
+            match inner.members.iter_mut().find(|m| m.id == member_id) {
+                Some(existing) => *existing = member,
+                None => inner.members.push(member),
+            }
+            if inner.members.len() == 1 || inner.protocol_type.is_none() {
+                inner.protocol_type = Some(request.protocol_type.clone());
+            }
+
+            if inner.state != GroupState::PreparingRebalance {
+                inner.prepare_rebalance();
+            }
+
+            let (tx, rx) = oneshot::channel();
+            inner.pending_joins.insert(member_id, tx);
+            if inner
+                .members
+                .iter()
+                .all(|m| inner.pending_joins.contains_key(&m.id))
+            {
+                inner.complete_join();
+            }
+
+            let wait = inner
+                .members
+                .iter()
+                .map(|m| m.rebalance_timeout_ms)
+                .max()
+                .unwrap_or(request.rebalance_timeout_ms);
+            (rx, Duration::from_millis(u64::try_from(wait).unwrap_or(0)))
+        };
+
+        let mut rx = rx;
+        if let Ok(result) = tokio::time::timeout(wait, &mut rx).await {
+            result.unwrap_or(Err(GroupError::RebalanceInProgress))
         } else {
-            *self.state.write() = GroupState::PreparingRebalance;
-            self.generation.fetch_add(1, Ordering::SeqCst);
+            // Members that did not rejoin in time are dropped
+            {
+                let mut inner = self.inner.lock();
+                if inner.state == GroupState::PreparingRebalance {
+                    let joined: HashSet<String> = inner.pending_joins.keys().cloned().collect();
+                    inner.members.retain(|m| joined.contains(&m.id));
+                    inner.complete_join();
+                }
+            }
+            rx.await.unwrap_or(Err(GroupError::RebalanceInProgress))
         }
     }
 
-    /// Heartbeat from a member
-    pub fn heartbeat(&self, member_id: &str) -> HeartbeatResult {
-        let mut members = self.members.write();
-
-        if let Some(member) = members.iter_mut().find(|m| m.id == member_id) {
+    /// Sync group after rebalance
+    ///
+    /// The leader supplies every member's assignment; other members wait
+    /// for it and receive their own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member is unknown, `generation` is stale, or a
+    /// rebalance is still in progress.
+    pub async fn sync(
+        &self,
+        member_id: &str,
+        group_instance_id: Option<&str>,
+        generation: i32,
+        assignments: Vec<(String, Vec<u8>)>,
+    ) -> Result<Vec<u8>, GroupError> {
+        let (rx, wait) = {
+            let mut inner = self.inner.lock();
+            inner.check_instance(member_id, group_instance_id)?;
+            let current = inner.generation;
+            let Some(member) = inner.members.iter_mut().find(|m| m.id == member_id) else {
+                return Err(GroupError::UnknownMemberId);
+            };
+            if generation != current {
+                return Err(GroupError::IllegalGeneration);
+            }
             member.last_heartbeat = chrono::Utc::now().timestamp_millis();
-            HeartbeatResult::Ok
+            let wait = member.rebalance_timeout_ms;
+
+            match inner.state {
+                GroupState::Empty | GroupState::PreparingRebalance => {
+                    return Err(GroupError::RebalanceInProgress)
+                }
+                GroupState::Stable => {
+                    return Ok(inner.assignment_of(member_id));
+                }
+                GroupState::CompletingRebalance => {}
+            }
+
+            if inner.leader.as_deref() == Some(member_id) {
+                let mut assignments: HashMap<String, Vec<u8>> = assignments.into_iter().collect();
+                for member in &mut inner.members {
+                    member.assignment = assignments.remove(&member.id).unwrap_or_default();
+                }
+                inner.state = GroupState::Stable;
+                inner.dirty = true;
+
+                let pending: Vec<_> = inner.pending_syncs.drain().collect();
+                for (id, tx) in pending {
+                    let assignment = inner.assignment_of(&id);
+                    let _ = tx.send(Ok(assignment));
+                }
+                return Ok(inner.assignment_of(member_id));
+            }
+
+            let (tx, rx) = oneshot::channel();
+            inner.pending_syncs.insert(member_id.to_string(), tx);
+            (rx, Duration::from_millis(u64::try_from(wait).unwrap_or(0)))
+        };
+
+        if let Ok(Ok(result)) = tokio::time::timeout(wait, rx).await {
+            result
         } else {
-            HeartbeatResult::UnknownMember
+            self.inner.lock().pending_syncs.remove(member_id);
+            Err(GroupError::RebalanceInProgress)
         }
     }
 
-    /// Sync group after rebalance
-    pub fn sync(
+    /// Heartbeat from a member
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member is unknown, `generation` is stale, or the
+    /// group is rebalancing.
+    pub fn heartbeat(
         &self,
         member_id: &str,
-        generation: i64,
-        assignments: HashMap<String, Vec<(String, PartitionId)>>,
-    ) -> SyncResult {
-        let current_gen = self.generation.load(Ordering::SeqCst);
+        group_instance_id: Option<&str>,
+        generation: i32,
+    ) -> Result<(), GroupError> {
+        let mut inner = self.inner.lock();
+        inner.check_instance(member_id, group_instance_id)?;
+        let state = inner.state;
+        let current = inner.generation;
+        let Some(member) = inner.members.iter_mut().find(|m| m.id == member_id) else {
+            return Err(GroupError::UnknownMemberId);
+        };
+        member.last_heartbeat = chrono::Utc::now().timestamp_millis();
 
-        if generation != current_gen {
-            return SyncResult::StaleGeneration;
+        match state {
+            GroupState::PreparingRebalance => Err(GroupError::RebalanceInProgress),
+            _ if generation != current => Err(GroupError::IllegalGeneration),
+            _ => Ok(()),
         }
+    }
 
-        let mut members = self.members.write();
-        let mut state = self.state.write();
+    /// Leave the group
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member is unknown.
+    pub fn leave(&self, member_id: &str) -> Result<(), GroupError> {
+        let mut inner = self.inner.lock();
+        let before = inner.members.len();
+        inner.members.retain(|m| m.id != member_id);
+        if inner.members.len() == before {
+            return Err(GroupError::UnknownMemberId);
+        }
+        if let Some(tx) = inner.pending_joins.remove(member_id) {
+            let _ = tx.send(Err(GroupError::UnknownMemberId));
+        }
+        inner.pending_syncs.remove(member_id);
+        inner.members_removed();
+        Ok(())
+    }
 
-        // Apply assignments
-        for member in members.iter_mut() {
-            if let Some(partitions) = assignments.get(&member.id) {
-                member.assigned_partitions = partitions.clone();
+    /// Evict members whose session timed out and finish a rebalance whose
+    /// deadline passed, returning the ids of the evicted members
+    ///
+    /// Members parked in a join are not evicted; the rebalance deadline
+    /// covers them.
+    pub fn expire_members(&self, now_ms: i64) -> Vec<String> {
+        let mut inner = self.inner.lock();
+        let expired: Vec<String> = inner
+            .members
+            .iter()
+            .filter(|m| {
+                !inner.pending_joins.contains_key(&m.id)
+                    && m.last_heartbeat
+                        .saturating_add(i64::from(m.session_timeout_ms))
+                        < now_ms
+            })
+            .map(|m| m.id.clone())
+            .collect();
+        if !expired.is_empty() {
+            inner.members.retain(|m| !expired.contains(&m.id));
+            for id in &expired {
+                if let Some(tx) = inner.pending_syncs.remove(id) {
+                    let _ = tx.send(Err(GroupError::UnknownMemberId));
+                }
             }
+            inner.members_removed();
         }
 
-        // Find this member's assignment
-        let assignment = members
-            .iter()
-            .find(|m| m.id == member_id)
-            .map(|m| m.assigned_partitions.clone())
-            .unwrap_or_default();
+        if inner.state == GroupState::PreparingRebalance
+            && inner
+                .rebalance_deadline
+                .is_some_and(|deadline| deadline <= now_ms)
+        {
+            let joined: HashSet<String> = inner.pending_joins.keys().cloned().collect();
+            inner.members.retain(|m| joined.contains(&m.id));
+            inner.complete_join();
+        }
+        expired
+    }
 
-        *state = GroupState::Stable;
+    /// Start a rebalance so every member rejoins
+    pub fn request_rebalance(&self) {
+        let mut inner = self.inner.lock();
+        if inner.state == GroupState::Stable {
+            inner.prepare_rebalance();
+        }
+    }
 
-        SyncResult::Assignment(assignment)
+    /// Find a member by its static instance id
+    pub fn member_for_instance(&self, group_instance_id: &str) -> Option<String> {
+        self.inner.lock().member_for_instance(group_instance_id)
     }
 
     /// Get group ID
@@ -178,30 +603,375 @@ impl ConsumerGroup {
 
     /// Get current generation
     pub fn generation(&self) -> i64 {
-        self.generation.load(Ordering::SeqCst)
+        i64::from(self.inner.lock().generation)
+    }
+
+    /// Get current state
+    pub fn state(&self) -> GroupState {
+        self.inner.lock().state
+    }
+
+    /// Protocol type and chosen protocol of the current generation
+    pub fn protocol(&self) -> (Option<String>, Option<String>) {
+        let inner = self.inner.lock();
+        (inner.protocol_type.clone(), inner.protocol_name.clone())
+    }
+
+    /// Current members
+    pub fn members(&self) -> Vec<MemberDescription> {
+        self.inner
+            .lock()
+            .members
+            .iter()
+            .map(|m| MemberDescription {
+                member_id: m.id.clone(),
+                group_instance_id: m.group_instance_id.clone(),
+                client_id: m.client_id.clone(),
+                client_host: m.client_host.clone(),
+                session_timeout_ms: m.session_timeout_ms,
+                last_heartbeat: m.last_heartbeat,
+                assignment: m.assignment.clone(),
+            })
+            .collect()
     }
 }
 
-/// Result of joining a group
-#[derive(Debug)]
-pub struct JoinResult {
-    pub generation: i64,
-    pub leader_id: Option<String>,
-    pub member_id: String,
+impl Member {
+    fn supports(&self, protocol: &str) -> bool {
+        self.protocols.iter().any(|(name, _)| name == protocol)
+    }
+
+    fn metadata(&self, protocol: &str) -> Vec<u8> {
+        self.protocols
+            .iter()
+            .find(|(name, _)| name == protocol)
+            .map(|(_, metadata)| metadata.clone())
+            .unwrap_or_default()
+    }
 }
 
-/// Result of heartbeat
-#[derive(Debug)]
-pub enum HeartbeatResult {
-    Ok,
-    UnknownMember,
-    RebalanceInProgress,
+impl GroupInner {
+    /// Start a new rebalance; members in sync are told to rejoin
+    fn prepare_rebalance(&mut self) {
+        self.state = GroupState::PreparingRebalance;
+        let timeout = self
+            .members
+            .iter()
+            .map(|m| m.rebalance_timeout_ms)
+            .max()
+            .unwrap_or(0);
+        self.rebalance_deadline = Some(chrono::Utc::now().timestamp_millis() + i64::from(timeout));
+        self.dirty = true;
+        for (_, tx) in self.pending_syncs.drain() {
+            let _ = tx.send(Err(GroupError::RebalanceInProgress));
+        }
+    }
+
+    /// React to members leaving or being evicted
+    fn members_removed(&mut self) {
+        if self.members.is_empty() {
+            self.reset();
+        } else if self.state == GroupState::PreparingRebalance {
+            if self
+                .members
+                .iter()
+                .all(|m| self.pending_joins.contains_key(&m.id))
+            {
+                self.complete_join();
+            }
+        } else {
+            self.prepare_rebalance();
+        }
+    }
+
+    /// Fail a join whose protocols the current members cannot share
+    fn check_protocol(&self, request: &JoinGroupRequest) -> Result<(), GroupError> {
+        let Some(ref protocol_type) = self.protocol_type else {
+            return Ok(());
+        };
+        if self.members.is_empty() {
+            return Ok(());
+        }
+        let shares_protocol = request
+            .protocols
+            .iter()
+            .any(|(name, _)| self.members.iter().all(|m| m.supports(name)));
+        if *protocol_type != request.protocol_type || !shares_protocol {
+            return Err(GroupError::InconsistentGroupProtocol);
+        }
+        Ok(())
+    }
+
+    fn member_for_instance(&self, group_instance_id: &str) -> Option<String> {
+        self.members
+            .iter()
+            .find(|m| m.group_instance_id.as_deref() == Some(group_instance_id))
+            .map(|m| m.id.clone())
+    }
+
+    /// Fail requests from a static member whose id was taken over
+    fn check_instance(
+        &self,
+        member_id: &str,
+        group_instance_id: Option<&str>,
+    ) -> Result<(), GroupError> {
+        match group_instance_id.and_then(|i| self.member_for_instance(i)) {
+            Some(id) if id != member_id => Err(GroupError::FencedInstanceId),
+            _ => Ok(()),
+        }
+    }
+
+    /// Let a restarted static member take over its registration under a
+    /// new id; with unchanged protocols in a stable group it keeps its
+    /// assignment and the returned response skips the rebalance
+    fn rejoin_static(
+        &mut self,
+        old_id: &str,
+        new_id: &str,
+        protocols: &[(String, Vec<u8>)],
+    ) -> Option<JoinGroupResponse> {
+        self.replace_member(old_id, new_id);
+        if self.state != GroupState::Stable {
+            return None;
+        }
+        let member = self.members.iter_mut().find(|m| m.id == new_id)?;
+        if member.protocols != protocols {
+            return None;
+        }
+        member.last_heartbeat = chrono::Utc::now().timestamp_millis();
+        Some(self.join_response(new_id))
+    }
+
+    /// Move a static member's registration to a new member id, fencing
+    /// requests still waiting under the old one
+    fn replace_member(&mut self, old_id: &str, new_id: &str) {
+        if let Some(member) = self.members.iter_mut().find(|m| m.id == old_id) {
+            member.id = new_id.to_string();
+        }
+        if self.leader.as_deref() == Some(old_id) {
+            self.leader = Some(new_id.to_string());
+        }
+        if let Some(tx) = self.pending_joins.remove(old_id) {
+            let _ = tx.send(Err(GroupError::FencedInstanceId));
+        }
+        if let Some(tx) = self.pending_syncs.remove(old_id) {
+            let _ = tx.send(Err(GroupError::FencedInstanceId));
+        }
+        self.dirty = true;
+    }
+
+    /// Finish the join phase: bump the generation, pick a protocol and a
+    /// leader and answer every waiting member
+    fn complete_join(&mut self) {
+        if self.members.is_empty() {
+            for (_, tx) in self.pending_joins.drain() {
+                let _ = tx.send(Err(GroupError::UnknownMemberId));
+            }
+            self.reset();
+            return;
+        }
+
+        self.generation += 1;
+        self.rebalance_deadline = None;
+        self.dirty = true;
+        self.protocol_name = self.select_protocol();
+        if !self
+            .leader
+            .as_ref()
+            .is_some_and(|l| self.members.iter().any(|m| &m.id == l))
+        {
+            self.leader = self.members.first().map(|m| m.id.clone());
+        }
+        self.state = GroupState::CompletingRebalance;
+
+        let pending: Vec<_> = self.pending_joins.drain().collect();
+        for (id, tx) in pending {
+            let response = self.join_response(&id);
+            let _ = tx.send(Ok(response));
+        }
+    }
+
+    /// Protocol supported by every member with the most first-choice votes
+    fn select_protocol(&self) -> Option<String> {
+        let first = self.members.first()?;
+        let candidates: Vec<&String> = first
+            .protocols
+            .iter()
+            .map(|(name, _)| name)
+            .filter(|name| self.members.iter().all(|m| m.supports(name)))
+            .collect();
+
+        let mut votes: HashMap<&String, usize> = HashMap::new();
+        for member in &self.members {
+            if let Some((name, _)) = member
+                .protocols
+                .iter()
+                .find(|(name, _)| candidates.contains(&name))
+            {
+                *votes.entry(name).or_default() += 1;
+            }
+        }
+        // Ties go to the protocol the first member prefers
+        candidates
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, name)| {
+                (
+                    votes.get(*name).copied().unwrap_or(0),
+                    std::cmp::Reverse(*i),
+                )
+            })
+            .map(|(_, name)| (*name).clone())
+    }
+
+    fn join_response(&self, member_id: &str) -> JoinGroupResponse {
+        let leader = self.leader.clone().unwrap_or_default();
+        let protocol = self.protocol_name.clone().unwrap_or_default();
+        let members = if leader == member_id {
+            self.members
+                .iter()
+                .map(|m| JoinedMember {
+                    member_id: m.id.clone(),
+                    group_instance_id: m.group_instance_id.clone(),
+                    metadata: m.metadata(&protocol),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        JoinGroupResponse {
+            generation_id: self.generation,
+            protocol_type: self.protocol_type.clone(),
+            protocol_name: self.protocol_name.clone(),
+            leader,
+            member_id: member_id.to_string(),
+            members,
+        }
+    }
+
+    fn assignment_of(&self, member_id: &str) -> Vec<u8> {
+        self.members
+            .iter()
+            .find(|m| m.id == member_id)
+            .map(|m| m.assignment.clone())
+            .unwrap_or_default()
+    }
+
+    fn reset(&mut self) {
+        self.state = GroupState::Empty;
+        self.protocol_type = None;
+        self.protocol_name = None;
+        self.leader = None;
+        self.rebalance_deadline = None;
+        self.dirty = true;
+    }
 }
 
-/// Result of sync
-#[derive(Debug)]
-pub enum SyncResult {
-    Assignment(Vec<(String, PartitionId)>),
-    StaleGeneration,
-    NotLeader,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(member_id: &str, instance: Option<&str>) -> JoinGroupRequest {
+        JoinGroupRequest {
+            member_id: member_id.to_string(),
+            group_instance_id: instance.map(str::to_string),
+            client_id: "client".to_string(),
+            client_host: String::new(),
+            session_timeout_ms: 1_000,
+            rebalance_timeout_ms: 1_000,
+            protocol_type: "consumer".to_string(),
+            protocols: vec![("range".to_string(), Vec::new())],
+            require_known_member_id: false,
+        }
+    }
+
+    /// Join a single member and finish the rebalance
+    async fn join_alone(group: &ConsumerGroup, instance: Option<&str>) -> JoinGroupResponse {
+        let joined = group.join(request("", instance)).await.unwrap();
+        let assignment = vec![(joined.member_id.clone(), b"assigned".to_vec())];
+        group
+            .sync(
+                &joined.member_id,
+                instance,
+                joined.generation_id,
+                assignment,
+            )
+            .await
+            .unwrap();
+        joined
+    }
+
+    #[tokio::test]
+    async fn test_expired_member_is_evicted() {
+        let group = ConsumerGroup::new("g");
+        let joined = join_alone(&group, None).await;
+        assert_eq!(group.state(), GroupState::Stable);
+
+        let now = chrono::Utc::now().timestamp_millis();
+        assert!(group.expire_members(now).is_empty());
+        assert_eq!(
+            group.expire_members(now + 5_000),
+            vec![joined.member_id.clone()]
+        );
+        assert_eq!(group.state(), GroupState::Empty);
+        assert_eq!(
+            group.heartbeat(&joined.member_id, None, joined.generation_id),
+            Err(GroupError::UnknownMemberId)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_static_member_rejoins_without_rebalance() {
+        let group = ConsumerGroup::new("g");
+        let first = join_alone(&group, Some("instance-1")).await;
+
+        // The restarted instance gets a new id but keeps its generation and
+        // assignment; the old id is fenced
+        let second = group.join(request("", Some("instance-1"))).await.unwrap();
+        assert_ne!(second.member_id, first.member_id);
+        assert_eq!(second.generation_id, first.generation_id);
+        assert_eq!(group.state(), GroupState::Stable);
+        let assignment = group
+            .sync(
+                &second.member_id,
+                Some("instance-1"),
+                second.generation_id,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(assignment, b"assigned");
+        assert_eq!(
+            group.heartbeat(&first.member_id, Some("instance-1"), first.generation_id),
+            Err(GroupError::FencedInstanceId)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_survives_reload() {
+        let group = ConsumerGroup::new("g");
+        let joined = join_alone(&group, Some("instance-1")).await;
+        group.commit_offset("t", 0, 42);
+        let metadata = group.take_metadata().unwrap();
+        assert!(group.take_metadata().is_none());
+
+        let records = vec![
+            offset_record("g", "t", 0, 41).unwrap(),
+            metadata_record("g", &metadata).unwrap(),
+            offset_record("g", "t", 0, 42).unwrap(),
+        ];
+        let groups = load_groups(records);
+        let restored = &groups["g"];
+        assert_eq!(restored.get_offset("t", 0), Some(42));
+        assert_eq!(restored.state(), GroupState::Stable);
+        assert_eq!(restored.generation(), i64::from(joined.generation_id));
+        assert_eq!(
+            restored.member_for_instance("instance-1"),
+            Some(joined.member_id.clone())
+        );
+        assert!(restored
+            .heartbeat(&joined.member_id, Some("instance-1"), joined.generation_id)
+            .is_ok());
+    }
 }
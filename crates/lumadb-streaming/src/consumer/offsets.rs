@@ -0,0 +1,137 @@
+//! Records of the internal offsets topic
+//!
+//! Offset commits and group metadata are written to the compacted
+//! [`OFFSETS_TOPIC`], keyed so that compaction keeps the latest commit per
+//! partition and the latest metadata per group. Replaying the topic on
+//! startup restores every group.
+
+use std::collections::HashMap;
+
+use lumadb_common::error::Result;
+use lumadb_common::types::{Offset, PartitionId, Record};
+use tracing::warn;
+
+use super::{ConsumerGroup, GroupMetadata};
+
+/// Internal topic holding group offsets and metadata
+pub const OFFSETS_TOPIC: &str = "__consumer_offsets";
+
+/// Key of an offsets topic record
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetsKey {
+    /// Committed offset of one partition; the value is the offset
+    Offset {
+        group: String,
+        topic: String,
+        partition: PartitionId,
+    },
+    /// Membership of a group; the value is its [`GroupMetadata`]
+    Group { group: String },
+}
+
+/// Offsets topic partition that holds a group's records
+#[must_use]
+pub fn partition_for(group_id: &str, partitions: u32) -> PartitionId {
+    // Stable across restarts, unlike the std hasher
+    let hash = group_id.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(u32::from(byte))
+    });
+    PartitionId::try_from(hash % partitions.max(1)).unwrap_or(0)
+}
+
+/// Record of an offset commit
+///
+/// # Errors
+///
+/// Returns an error if the key or offset cannot be serialized.
+pub fn offset_record(
+    group: &str,
+    topic: &str,
+    partition: PartitionId,
+    offset: Offset,
+) -> Result<Record> {
+    let key = OffsetsKey::Offset {
+        group: group.to_string(),
+        topic: topic.to_string(),
+        partition,
+    };
+    Ok(Record::new(serde_json::to_vec(&offset)?).with_key(serde_json::to_vec(&key)?))
+}
+
+/// Record of a group's metadata
+///
+/// # Errors
+///
+/// Returns an error if the key or metadata cannot be serialized.
+pub fn metadata_record(group: &str, metadata: &GroupMetadata) -> Result<Record> {
+    let key = OffsetsKey::Group {
+        group: group.to_string(),
+    };
+    Ok(Record::new(serde_json::to_vec(metadata)?).with_key(serde_json::to_vec(&key)?))
+}
+
+/// Rebuild groups from offsets topic records in offset order
+///
+/// Later records replace earlier ones with the same key and tombstones
+/// remove them; unreadable records are skipped.
+pub fn load_groups(records: impl IntoIterator<Item = Record>) -> HashMap<String, ConsumerGroup> {
+    #[derive(Default)]
+    struct Loaded {
+        metadata: Option<GroupMetadata>,
+        offsets: HashMap<(String, PartitionId), Offset>,
+    }
+
+    let mut loaded: HashMap<String, Loaded> = HashMap::new();
+    for record in records {
+        let Some(key) = record
+            .key
+            .as_ref()
+            .and_then(|k| serde_json::from_slice::<OffsetsKey>(k).ok())
+        else {
+            warn!("Skipping offsets topic record with an unreadable key");
+            continue;
+        };
+        let tombstone = record.is_tombstone();
+        match key {
+            OffsetsKey::Offset {
+                group,
+                topic,
+                partition,
+            } => {
+                let offsets = &mut loaded.entry(group).or_default().offsets;
+                match serde_json::from_slice(&record.value) {
+                    _ if tombstone => {
+                        offsets.remove(&(topic, partition));
+                    }
+                    Ok(offset) => {
+                        offsets.insert((topic, partition), offset);
+                    }
+                    Err(e) => warn!("Skipping unreadable offset commit: {}", e),
+                }
+            }
+            OffsetsKey::Group { group } => {
+                let entry = loaded.entry(group).or_default();
+                match serde_json::from_slice(&record.value) {
+                    _ if tombstone => entry.metadata = None,
+                    Ok(metadata) => entry.metadata = Some(metadata),
+                    Err(e) => warn!("Skipping unreadable group metadata: {}", e),
+                }
+            }
+        }
+    }
+
+    loaded
+        .into_iter()
+        .map(|(group_id, loaded)| {
+            let group = match loaded.metadata {
+                Some(metadata) => ConsumerGroup::restore(&group_id, metadata),
+                None => ConsumerGroup::new(&group_id),
+            };
+            for ((topic, partition), offset) in loaded.offsets {
+                group.commit_offset(&topic, partition, offset);
+            }
+            (group_id, group)
+        })
+        .collect()
+}
@@ -1,29 +1,50 @@
 //! Main streaming engine implementation
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
 use parking_lot::RwLock;
+use tokio::sync::Notify;
 use tracing::{info, warn, debug};
 
 use lumadb_common::config::StreamingConfig;
 use lumadb_common::error::{Result, Error};
 use lumadb_common::types::{
-    Offset, PartitionId, Record, RecordMetadata, TopicConfig, TopicMetadata, PartitionMetadata,
+    CleanupPolicy, ControlRecord, Offset, PartitionId, PartitionMetadata, ProducerInfo, Record,
+    RecordMetadata, TopicConfig, TopicMetadata,
 };
 
-use crate::consumer::ConsumerGroup;
-use crate::log::{PartitionLog, Segment};
+use crate::consumer::{
+    self, Assignor, ConsumerGroup, GroupError, JoinGroupRequest, JoinGroupResponse, Subscription,
+    TopicPartition, OFFSETS_TOPIC,
+};
+use crate::log::{LogOptions, PartitionLog};
+use lumadb_storage::encryption::{self, KeyManager};
+use crate::producer::{
+    InitProducer, IsolationLevel, ProducerError, ProducerIdAndEpoch, TransactionCompletion,
+    TransactionCoordinator, TransactionMetadata,
+};
 
 /// High-performance streaming engine
 pub struct StreamingEngine {
     /// Configuration
     config: StreamingConfig,
+    /// Directory holding one subdirectory per topic
+    data_dir: PathBuf,
     /// Topics
     topics: DashMap<String, Topic>,
     /// Consumer groups
     consumer_groups: DashMap<String, Arc<ConsumerGroup>>,
+    /// Producer ids and transactions
+    transactions: TransactionCoordinator,
+    /// Woken whenever records are appended, for long-polling fetches
+    appended: Notify,
+    /// Data keys of the storage engine, when encryption at rest is enabled
+    encryption: Option<Arc<KeyManager>>,
     /// Running state
     running: Arc<RwLock<bool>>,
 }
@@ -37,46 +58,256 @@ struct Topic {
 }
 
 /// A single partition
-struct Partition {
+pub struct Partition {
     /// Partition ID
     id: PartitionId,
     /// Append-only log
     log: PartitionLog,
 }
 
+impl Partition {
+    /// Partition ID
+    pub fn id(&self) -> PartitionId {
+        self.id
+    }
+
+    /// Append-only log
+    pub fn log(&self) -> &PartitionLog {
+        &self.log
+    }
+}
+
+/// Topic metadata file inside each topic directory
+const TOPIC_METADATA_FILE: &str = "topic.json";
+/// Interval between checks for transactions past their timeout
+const TRANSACTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval between group session checks and metadata checkpoints
+const GROUP_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Protocol type of groups whose partitions the server assigns
+const SERVER_ASSIGNED_PROTOCOL: &str = "lumadb";
+
 impl StreamingEngine {
     /// Create a new streaming engine
+    ///
+    /// Topics are stored under `streaming/` in the storage engine's data
+    /// directory and recovered from there on startup. When the storage
+    /// engine encrypts at rest, so does every topic, each under its own
+    /// data key.
     pub async fn new(
         config: &StreamingConfig,
-        _storage: Arc<lumadb_storage::StorageEngine>,
+        storage: Arc<lumadb_storage::StorageEngine>,
         _raft: Arc<crate::RaftStub>,
     ) -> Result<Self> {
         info!("Initializing streaming engine with thread-per-core architecture");
 
-        Ok(Self {
+        let data_dir = storage.data_dir().join("streaming");
+        fs::create_dir_all(&data_dir)?;
+
+        let engine = Self {
             config: config.clone(),
+            transactions: TransactionCoordinator::open(
+                &data_dir,
+                config.transaction_max_timeout_ms,
+            )?,
+            data_dir,
             topics: DashMap::new(),
             consumer_groups: DashMap::new(),
+            appended: Notify::new(),
+            encryption: storage.encryption().cloned(),
             running: Arc::new(RwLock::new(true)),
-        })
+        };
+        engine.recover_topics()?;
+        engine.open_offsets_topic()?;
+
+        // Finish transactions whose markers were being written at shutdown
+        for completion in engine.transactions.pending() {
+            engine.complete_transaction(&completion)?;
+        }
+
+        Ok(engine)
+    }
+
+    /// Reopen every topic found in the data directory
+    fn recover_topics(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.data_dir)? {
+            let dir = entry?.path();
+            let metadata = dir.join(TOPIC_METADATA_FILE);
+            if !metadata.exists() {
+                continue;
+            }
+
+            let config: TopicConfig = serde_json::from_slice(&fs::read(&metadata)?)?;
+            let topic = self.open_topic(&dir, config)?;
+            info!(
+                "Recovered topic {} with {} partitions",
+                topic.config.name,
+                topic.partitions.len()
+            );
+            self.topics.insert(topic.config.name.clone(), topic);
+        }
+        Ok(())
+    }
+
+    /// Create the internal offsets topic if needed and restore the groups
+    /// recorded in it
+    fn open_offsets_topic(&self) -> Result<()> {
+        if !self.topics.contains_key(OFFSETS_TOPIC) {
+            let mut config =
+                TopicConfig::new(OFFSETS_TOPIC, self.config.offsets_topic_partitions, 1);
+            config.cleanup_policy = CleanupPolicy::Compact;
+            self.add_topic(config)?;
+        }
+
+        let mut records = Vec::new();
+        for partition in self.partitions(OFFSETS_TOPIC).unwrap_or_default() {
+            let log = &partition.log;
+            records.extend(
+                log.read(log.low_watermark(), usize::MAX, usize::MAX)?
+                    .into_iter()
+                    .map(|(_, record)| record),
+            );
+        }
+        for (group_id, group) in consumer::load_groups(records) {
+            self.consumer_groups.insert(group_id, Arc::new(group));
+        }
+        if !self.consumer_groups.is_empty() {
+            info!("Recovered {} consumer groups", self.consumer_groups.len());
+        }
+        Ok(())
+    }
+
+    /// Open the partition logs of a topic stored in `dir`
+    fn open_topic(&self, dir: &Path, config: TopicConfig) -> Result<Topic> {
+        let mut options = LogOptions::for_topic(&self.config, &config)?;
+        if let Some(ref keys) = self.encryption {
+            options.cipher = Some(keys.data_keys(&encryption::topic_scope(&config.name))?);
+        }
+        let mut partitions = Vec::new();
+        for id in 0..config.partitions as i32 {
+            partitions.push(Arc::new(Partition {
+                id,
+                log: PartitionLog::open(id, dir.join(id.to_string()), options.clone())?,
+            }));
+        }
+
+        Ok(Topic { config, partitions })
     }
 
     /// Run the streaming engine
     pub async fn run(&self) -> Result<()> {
         info!("Starting streaming engine...");
 
+        let retention_interval = Duration::from_millis(self.config.retention_check_interval_ms);
+        let cleaner_interval = Duration::from_millis(self.config.cleaner_interval_ms);
+        let mut last_retention_check = Instant::now();
+        let mut last_cleaner_run = Instant::now();
+        let mut last_transaction_check = Instant::now();
+        let mut last_group_check = Instant::now();
+
         while *self.running.read() {
             // Main event loop - in production this would use io_uring or epoll
             tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            if last_cleaner_run.elapsed() >= cleaner_interval {
+                self.compact_topics();
+                last_cleaner_run = Instant::now();
+            }
+            if last_retention_check.elapsed() >= retention_interval {
+                self.enforce_retention();
+                last_retention_check = Instant::now();
+            }
+            if last_transaction_check.elapsed() >= TRANSACTION_CHECK_INTERVAL {
+                if let Err(e) = self.abort_expired_transactions() {
+                    warn!("Aborting expired transactions failed: {}", e);
+                }
+                last_transaction_check = Instant::now();
+            }
+            if last_group_check.elapsed() >= GROUP_CHECK_INTERVAL {
+                self.expire_group_members();
+                self.persist_groups();
+                last_group_check = Instant::now();
+            }
         }
 
         Ok(())
     }
 
+    /// Apply retention to every partition and expire idle producer state
+    pub fn enforce_retention(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        for entry in &self.topics {
+            for partition in &entry.value().partitions {
+                partition.log.expire_producers(now);
+                if let Err(e) = partition.log.enforce_retention(now) {
+                    warn!(
+                        "Retention failed for {}/{}: {}",
+                        entry.key(),
+                        partition.id,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Run the log cleaner over partitions of compacted topics, and
+    /// re-encrypt segments sealed with rotated data keys
+    pub fn compact_topics(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        for entry in &self.topics {
+            for partition in &entry.value().partitions {
+                if let Err(e) = partition.log.compact(now) {
+                    warn!(
+                        "Compaction failed for {}/{}: {}",
+                        entry.key(),
+                        partition.id,
+                        e
+                    );
+                }
+                if let Err(e) = partition.log.reencrypt() {
+                    warn!(
+                        "Re-encryption failed for {}/{}: {}",
+                        entry.key(),
+                        partition.id,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Rotate a topic's data key
+    ///
+    /// Records are sealed with the new version at once; the log cleaner
+    /// re-encrypts existing segments on its next pass. Returns the new key
+    /// version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if encryption at rest is off, the topic does not exist, or the
+    /// keyring cannot be saved.
+    pub fn rotate_topic_key(&self, topic: &str) -> Result<u32> {
+        let keys = self
+            .encryption
+            .as_ref()
+            .ok_or_else(|| Error::Config("encryption at rest is not enabled".to_string()))?;
+        if !self.topics.contains_key(topic) {
+            return Err(Error::Internal(format!("Topic {topic} not found")));
+        }
+        keys.rotate(&encryption::topic_scope(topic))
+    }
+
     /// Shutdown the streaming engine
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down streaming engine...");
         *self.running.write() = false;
+
+        self.persist_groups();
+        for entry in &self.topics {
+            for partition in &entry.value().partitions {
+                partition.log.flush()?;
+            }
+        }
         Ok(())
     }
 
@@ -88,24 +319,38 @@ impl StreamingEngine {
     pub async fn create_topic(&self, config: TopicConfig) -> Result<()> {
         info!("Creating topic: {} with {} partitions", config.name, config.partitions);
 
+        if config.name == OFFSETS_TOPIC {
+            return Err(Error::Internal(format!(
+                "Topic {} is internal",
+                config.name
+            )));
+        }
+        self.add_topic(config)
+    }
+
+    /// Create a topic's directory, logs and metadata
+    fn add_topic(&self, config: TopicConfig) -> Result<()> {
+        validate_topic_name(&config.name)?;
         if self.topics.contains_key(&config.name) {
             return Err(Error::Internal(format!("Topic {} already exists", config.name)));
         }
 
-        let mut partitions = Vec::new();
-        for id in 0..config.partitions as i32 {
-            partitions.push(Arc::new(Partition {
-                id,
-                log: PartitionLog::new(id, self.config.segment_size_bytes),
-            }));
-        }
+        // Reject invalid topic settings before anything reaches disk
+        LogOptions::for_topic(&self.config, &config)?;
 
-        let topic = Topic {
-            config: config.clone(),
-            partitions,
-        };
+        let name = config.name.clone();
+        let data = serde_json::to_vec_pretty(&config)?;
+        let dir = self.data_dir.join(&name);
+        fs::create_dir_all(&dir)?;
+        let topic = self.open_topic(&dir, config)?;
+
+        // Write metadata last so a half-created topic is not recovered
+        let metadata = dir.join(TOPIC_METADATA_FILE);
+        let tmp = dir.join(format!("{TOPIC_METADATA_FILE}.tmp"));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &metadata)?;
 
-        self.topics.insert(config.name.clone(), topic);
+        self.topics.insert(name, topic);
 
         Ok(())
     }
@@ -132,7 +377,7 @@ impl StreamingEngine {
             topics.push(TopicMetadata {
                 name: topic.config.name.clone(),
                 partitions,
-                is_internal: false,
+                is_internal: topic.config.name == OFFSETS_TOPIC,
             });
         }
 
@@ -159,21 +404,60 @@ impl StreamingEngine {
             Ok(Some(TopicMetadata {
                 name: topic.config.name.clone(),
                 partitions,
-                is_internal: false,
+                is_internal: topic.config.name == OFFSETS_TOPIC,
             }))
         } else {
             Ok(None)
         }
     }
 
+    /// Whether a topic exists
+    pub fn topic_exists(&self, name: &str) -> bool {
+        self.topics.contains_key(name)
+    }
+
+    /// Configuration a topic was created with
+    pub fn topic_config(&self, name: &str) -> Option<TopicConfig> {
+        self.topics.get(name).map(|t| t.config.clone())
+    }
+
+    /// Partitions of a topic
+    pub fn partitions(&self, topic: &str) -> Option<Vec<Arc<Partition>>> {
+        self.topics.get(topic).map(|t| t.partitions.clone())
+    }
+
+    /// A single partition of a topic
+    pub fn partition(&self, topic: &str, partition: PartitionId) -> Option<Arc<Partition>> {
+        let index = usize::try_from(partition).ok()?;
+        self.topics.get(topic)?.partitions.get(index).cloned()
+    }
+
+    /// Engine configuration
+    pub fn config(&self) -> &StreamingConfig {
+        &self.config
+    }
+
     /// Delete a topic
     pub async fn delete_topic(&self, name: &str) -> Result<()> {
         info!("Deleting topic: {}", name);
 
-        self.topics
+        if name == OFFSETS_TOPIC {
+            return Err(Error::Internal(format!("Topic {name} is internal")));
+        }
+
+        let (_, topic) = self
+            .topics
             .remove(name)
             .ok_or_else(|| Error::Internal(format!("Topic {} not found", name)))?;
 
+        for partition in &topic.partitions {
+            partition.log.delete()?;
+        }
+        let dir = self.data_dir.join(name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
         Ok(())
     }
 
@@ -212,19 +496,11 @@ impl StreamingEngine {
                 .get(partition as usize)
                 .ok_or_else(|| Error::Internal(format!("Partition {} not found", partition)))?;
 
-            // Create record bytes
-            let value_bytes = serde_json::to_vec(&record.value)?;
-            let record_bytes = Record {
-                key: record.key.as_ref().map(|k| bytes::Bytes::from(k.clone())),
-                value: bytes::Bytes::from(value_bytes),
-                headers: record.headers.clone().unwrap_or_default(),
-                timestamp: chrono::Utc::now().timestamp_millis(),
-                partition: Some(partition),
-                offset: None,
-            };
+            let record_bytes = record.to_record(partition)?;
 
             // Append to log
             let offset = partition_ref.log.append(&record_bytes)?;
+            self.appended.notify_waiters();
 
             results.push(RecordMetadata {
                 topic: topic.to_string(),
@@ -245,6 +521,299 @@ impl StreamingEngine {
         Ok(results)
     }
 
+    /// Produce records to one partition as an idempotent or transactional
+    /// producer
+    ///
+    /// `producer` carries the sequence number of the first record. A retried
+    /// request returns the offsets of the original write.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the partition does not exist, the batch fails the
+    /// producer or transaction checks, or the write fails.
+    pub fn produce_to_partition(
+        &self,
+        topic: &str,
+        partition: PartitionId,
+        records: &[ProduceRecord],
+        producer: ProducerInfo,
+    ) -> std::result::Result<Vec<RecordMetadata>, ProducerError> {
+        let records = records
+            .iter()
+            .map(|r| r.to_record(partition))
+            .collect::<Result<Vec<_>>>()?;
+        let base_offset = self.append_producer_records(topic, partition, &records, producer)?;
+
+        Ok(records
+            .iter()
+            .zip(base_offset..)
+            .map(|(record, offset)| RecordMetadata {
+                topic: topic.to_string(),
+                partition,
+                offset,
+                timestamp: record.timestamp,
+            })
+            .collect())
+    }
+
+    /// Append records to one partition at consecutive offsets, returning the
+    /// first offset
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the topic or partition does not exist or the write
+    /// fails.
+    pub fn append_records(
+        &self,
+        topic: &str,
+        partition: PartitionId,
+        records: &[Record],
+    ) -> Result<Offset> {
+        let partition = self
+            .partition(topic, partition)
+            .ok_or_else(|| Error::Internal(format!("Partition {topic}/{partition} not found")))?;
+        let base_offset = partition.log.append_batch(records)?;
+        self.appended.notify_waiters();
+
+        let bytes: usize = records.iter().map(|r| r.value.len()).sum();
+        lumadb_common::metrics::record_messages_produced(topic, records.len() as u64, bytes as u64);
+        Ok(base_offset)
+    }
+
+    /// Append a batch from an idempotent or transactional producer to one
+    /// partition, returning the first offset
+    ///
+    /// Transactional batches are only accepted for partitions added to the
+    /// producer's ongoing transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the partition does not exist, the batch fails the
+    /// producer or transaction checks, or the write fails.
+    pub fn append_producer_records(
+        &self,
+        topic: &str,
+        partition: PartitionId,
+        records: &[Record],
+        producer: ProducerInfo,
+    ) -> std::result::Result<Offset, ProducerError> {
+        let log = self
+            .partition(topic, partition)
+            .ok_or(ProducerError::UnknownTopicOrPartition)?;
+        if producer.transactional {
+            self.transactions.verify_partition(
+                producer_id_and_epoch(&producer),
+                topic,
+                partition,
+            )?;
+        }
+        let base_offset = log.log.append_producer_batch(records, producer)?;
+        self.appended.notify_waiters();
+
+        let bytes: usize = records.iter().map(|r| r.value.len()).sum();
+        lumadb_common::metrics::record_messages_produced(topic, records.len() as u64, bytes as u64);
+        Ok(base_offset)
+    }
+
+    /// Future completing on the next append to any partition
+    ///
+    /// Create it before checking for data so an append in between is not
+    /// missed.
+    pub fn appended(&self) -> tokio::sync::futures::Notified<'_> {
+        self.appended.notified()
+    }
+
+    // ========================================================================
+    // Producer and Transaction Operations
+    // ========================================================================
+
+    /// Obtain a producer id and epoch
+    ///
+    /// Without a transactional id this allocates a new idempotent producer,
+    /// or bumps the epoch of `current` so it can reset its sequence numbers.
+    /// With one, the producer registered under the id is fenced by an epoch
+    /// bump and any transaction it left open is aborted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator rejects the producer or an open
+    /// transaction cannot be aborted.
+    pub fn init_producer_id(
+        &self,
+        transactional_id: Option<&str>,
+        transaction_timeout_ms: i32,
+        current: Option<ProducerIdAndEpoch>,
+    ) -> std::result::Result<ProducerIdAndEpoch, ProducerError> {
+        let Some(transactional_id) = transactional_id else {
+            return match current {
+                Some(current) => self.transactions.bump_epoch(current),
+                None => self.transactions.allocate_producer_id(),
+            };
+        };
+
+        loop {
+            match self.transactions.init_producer_id(
+                transactional_id,
+                transaction_timeout_ms,
+                current,
+                chrono::Utc::now().timestamp_millis(),
+            )? {
+                InitProducer::Ready(producer) => return Ok(producer),
+                InitProducer::Abort(completion) => self.complete_transaction(&completion)?,
+            }
+        }
+    }
+
+    /// Add partitions to a producer's transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a partition does not exist or the coordinator
+    /// rejects the producer.
+    pub fn add_partitions_to_txn(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        partitions: &[(String, PartitionId)],
+    ) -> std::result::Result<(), ProducerError> {
+        if partitions
+            .iter()
+            .any(|(topic, partition)| self.partition(topic, *partition).is_none())
+        {
+            return Err(ProducerError::UnknownTopicOrPartition);
+        }
+        self.transactions.add_partitions(
+            transactional_id,
+            producer,
+            partitions,
+            chrono::Utc::now().timestamp_millis(),
+        )
+    }
+
+    /// Make a consumer group's offset commits part of a producer's
+    /// transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator rejects the producer.
+    pub fn add_offsets_to_txn(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        group_id: &str,
+    ) -> std::result::Result<(), ProducerError> {
+        self.transactions.add_offsets(
+            transactional_id,
+            producer,
+            group_id,
+            chrono::Utc::now().timestamp_millis(),
+        )
+    }
+
+    /// Commit consumer group offsets when the producer's transaction commits
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator rejects the producer or the group is
+    /// not in its transaction.
+    pub fn txn_offset_commit(
+        &self,
+        transactional_id: &str,
+        group_id: &str,
+        producer: ProducerIdAndEpoch,
+        offsets: &[(String, PartitionId, Offset)],
+    ) -> std::result::Result<(), ProducerError> {
+        self.transactions
+            .stage_offsets(transactional_id, producer, group_id, offsets)
+    }
+
+    /// Commit or abort a producer's transaction
+    ///
+    /// Returns once the markers are in every partition of the transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator rejects the request or a marker
+    /// cannot be written.
+    pub fn end_txn(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        commit: bool,
+    ) -> std::result::Result<(), ProducerError> {
+        if let Some(completion) =
+            self.transactions
+                .end_transaction(transactional_id, producer, commit)?
+        {
+            self.complete_transaction(&completion)?;
+        }
+        Ok(())
+    }
+
+    /// State of a transactional id
+    pub fn transaction(&self, transactional_id: &str) -> Option<TransactionMetadata> {
+        self.transactions.describe(transactional_id)
+    }
+
+    /// Abort transactions open for longer than their timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transaction cannot be aborted.
+    pub fn abort_expired_transactions(&self) -> Result<()> {
+        for completion in self
+            .transactions
+            .expire(chrono::Utc::now().timestamp_millis())?
+        {
+            self.complete_transaction(&completion)?;
+        }
+        Ok(())
+    }
+
+    /// Write the markers of a transaction and apply its offset commits
+    fn complete_transaction(&self, completion: &TransactionCompletion) -> Result<()> {
+        let control = if completion.commit {
+            ControlRecord::Commit
+        } else {
+            ControlRecord::Abort
+        };
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        for (topic, partition) in &completion.partitions {
+            let Some(partition) = self.partition(topic, *partition) else {
+                warn!(
+                    "Skipping transaction marker for missing partition {}/{}",
+                    topic, partition
+                );
+                continue;
+            };
+            partition.log.append_marker(
+                completion.producer.producer_id,
+                completion.producer.producer_epoch,
+                control,
+                timestamp,
+            )?;
+        }
+        if completion.commit {
+            for (group_id, offsets) in &completion.offsets {
+                self.store_offsets(group_id, offsets)?;
+            }
+        }
+        self.transactions.complete(completion)?;
+        self.appended.notify_waiters();
+
+        debug!(
+            "Transaction {} {} across {} partitions",
+            completion.transactional_id,
+            if completion.commit {
+                "committed"
+            } else {
+                "aborted"
+            },
+            completion.partitions.len()
+        );
+        Ok(())
+    }
+
     // ========================================================================
     // Consume Operations
     // ========================================================================
@@ -258,14 +827,110 @@ impl StreamingEngine {
         max_records: usize,
         timeout_ms: u64,
     ) -> Result<Vec<ConsumeRecord>> {
-        let topic_entry = self.topics.get(topic)
+        self.consume_with_isolation(
+            topic,
+            group_id,
+            offset,
+            max_records,
+            timeout_ms,
+            IsolationLevel::ReadUncommitted,
+        )
+        .await
+    }
+
+    /// Consume records from a topic at an isolation level
+    ///
+    /// Transaction markers are never returned; with `read_committed`,
+    /// records of open and aborted transactions are skipped as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the topic does not exist, `offset` is invalid, or a
+    /// partition cannot be read.
+    pub async fn consume_with_isolation(
+        &self,
+        topic: &str,
+        group_id: Option<&str>,
+        offset: Option<&str>,
+        max_records: usize,
+        _timeout_ms: u64,
+        isolation: IsolationLevel,
+    ) -> Result<Vec<ConsumeRecord>> {
+        let partitions = self
+            .partitions(topic)
             .ok_or_else(|| Error::Internal(format!("Topic {} not found", topic)))?;
 
+        self.consume_partitions(topic, &partitions, group_id, offset, max_records, isolation)
+            .await
+    }
+
+    /// Consume from the partitions of `topic` assigned to a member of a
+    /// server-assigned group
+    ///
+    /// Each partition is read from the group's committed offset, or from
+    /// `offset` when nothing was committed yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the topic or the member does not exist, `offset` is
+    /// invalid, or a partition cannot be read.
+    pub async fn consume_assigned(
+        &self,
+        topic: &str,
+        group_id: &str,
+        member_id: &str,
+        offset: Option<&str>,
+        max_records: usize,
+        isolation: IsolationLevel,
+    ) -> Result<Vec<ConsumeRecord>> {
+        let partitions = self
+            .partitions(topic)
+            .ok_or_else(|| Error::Internal(format!("Topic {topic} not found")))?;
+        let member = self
+            .find_consumer_group(group_id)
+            .and_then(|group| {
+                group
+                    .members()
+                    .into_iter()
+                    .find(|m| m.member_id == member_id)
+            })
+            .ok_or_else(|| {
+                Error::Internal(format!("Member {member_id} of group {group_id} not found"))
+            })?;
+        let assignment: Vec<TopicPartition> =
+            serde_json::from_slice(&member.assignment).unwrap_or_default();
+
+        let assigned: Vec<Arc<Partition>> = partitions
+            .into_iter()
+            .filter(|p| assignment.iter().any(|(t, id)| t == topic && *id == p.id))
+            .collect();
+        self.consume_partitions(
+            topic,
+            &assigned,
+            Some(group_id),
+            offset,
+            max_records,
+            isolation,
+        )
+        .await
+    }
+
+    /// Read records from `partitions` of a topic, starting from the group's
+    /// committed offsets when a group is given
+    async fn consume_partitions(
+        &self,
+        topic: &str,
+        partitions: &[Arc<Partition>],
+        group_id: Option<&str>,
+        offset: Option<&str>,
+        max_records: usize,
+        isolation: IsolationLevel,
+    ) -> Result<Vec<ConsumeRecord>> {
         let mut results = Vec::new();
         let start_offset = Self::parse_offset(offset)?;
 
         // Consume from each partition
-        for partition in &topic_entry.partitions {
+        for partition in partitions {
             let partition_offset = if let Some(gid) = group_id {
                 // Get committed offset for consumer group
                 self.get_committed_offset(gid, topic, partition.id)
@@ -275,19 +940,14 @@ impl StreamingEngine {
                 start_offset
             };
 
-            let records = partition.log.fetch(partition_offset, max_records)?;
-
-            for (offset, record) in records {
-                results.push(ConsumeRecord {
-                    topic: topic.to_string(),
-                    partition: partition.id,
-                    offset,
-                    timestamp: record.timestamp,
-                    key: record.key.map(|b| String::from_utf8_lossy(&b).to_string()),
-                    value: serde_json::from_slice(&record.value)?,
-                    headers: record.headers,
-                });
-            }
+            let (records, _) = Self::partition_records(
+                topic,
+                partition,
+                partition_offset,
+                max_records,
+                isolation,
+            )?;
+            results.extend(records);
 
             if results.len() >= max_records {
                 break;
@@ -307,6 +967,63 @@ impl StreamingEngine {
         Ok(results)
     }
 
+    /// Read up to `max_records` records of one partition starting at
+    /// `offset`, returning them with the offset to continue from
+    ///
+    /// Control records, and aborted ones under `read_committed`, are
+    /// skipped but still advance the returned offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the partition does not exist or cannot be read.
+    pub fn read_partition(
+        &self,
+        topic: &str,
+        partition: PartitionId,
+        offset: Offset,
+        max_records: usize,
+        isolation: IsolationLevel,
+    ) -> Result<(Vec<ConsumeRecord>, Offset)> {
+        let partition = self
+            .partition(topic, partition)
+            .ok_or_else(|| Error::Internal(format!("Partition {topic}-{partition} not found")))?;
+        Self::partition_records(topic, &partition, offset, max_records, isolation)
+    }
+
+    fn partition_records(
+        topic: &str,
+        partition: &Partition,
+        offset: Offset,
+        max_records: usize,
+        isolation: IsolationLevel,
+    ) -> Result<(Vec<ConsumeRecord>, Offset)> {
+        let records = partition
+            .log
+            .read_isolated(offset, max_records, usize::MAX, isolation)?;
+
+        let mut next = offset;
+        let mut results = Vec::with_capacity(records.len());
+        for (offset, record) in records {
+            next = offset + 1;
+            if record.is_control()
+                || (isolation == IsolationLevel::ReadCommitted
+                    && partition.log.is_aborted(offset, &record))
+            {
+                continue;
+            }
+            results.push(ConsumeRecord {
+                topic: topic.to_string(),
+                partition: partition.id,
+                offset,
+                timestamp: record.timestamp,
+                key: record.key.map(|b| String::from_utf8_lossy(&b).to_string()),
+                value: decode_value(&record.value),
+                headers: record.headers,
+            });
+        }
+        Ok((results, next))
+    }
+
     /// Parse offset string
     fn parse_offset(offset: Option<&str>) -> Result<Offset> {
         match offset {
@@ -330,23 +1047,303 @@ impl StreamingEngine {
         }
     }
 
+    /// Get or create a consumer group
+    pub fn consumer_group(&self, group_id: &str) -> Arc<ConsumerGroup> {
+        self.consumer_groups
+            .entry(group_id.to_string())
+            .or_insert_with(|| Arc::new(ConsumerGroup::new(group_id)))
+            .clone()
+    }
+
+    /// Get a consumer group if it exists
+    pub fn find_consumer_group(&self, group_id: &str) -> Option<Arc<ConsumerGroup>> {
+        self.consumer_groups.get(group_id).map(|g| g.clone())
+    }
+
     /// Commit offsets for a consumer group
     pub async fn commit_offsets(
         &self,
         group_id: &str,
         offsets: &[(String, PartitionId, Offset)],
     ) -> Result<()> {
-        let group = self
-            .consumer_groups
-            .entry(group_id.to_string())
-            .or_insert_with(|| Arc::new(ConsumerGroup::new(group_id)));
+        self.store_offsets(group_id, offsets)
+    }
 
+    /// Commit offsets for a consumer group, writing them to the offsets
+    /// topic first so they survive restarts
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the offsets topic cannot be written.
+    pub fn store_offsets(
+        &self,
+        group_id: &str,
+        offsets: &[(String, PartitionId, Offset)],
+    ) -> Result<()> {
+        if offsets.is_empty() {
+            return Ok(());
+        }
+        let records = offsets
+            .iter()
+            .map(|(topic, partition, offset)| {
+                consumer::offset_record(group_id, topic, *partition, *offset)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.offsets_partition(group_id)?
+            .log
+            .append_batch(&records)?;
+
+        let group = self.consumer_group(group_id);
         for (topic, partition, offset) in offsets {
             group.commit_offset(topic, *partition, *offset);
         }
-
         Ok(())
     }
+
+    /// Offsets topic partition holding a group's records
+    fn offsets_partition(&self, group_id: &str) -> Result<Arc<Partition>> {
+        let partitions = self
+            .partitions(OFFSETS_TOPIC)
+            .ok_or_else(|| Error::Internal(format!("Topic {OFFSETS_TOPIC} not found")))?;
+        let count = u32::try_from(partitions.len()).unwrap_or(u32::MAX);
+        let index = usize::try_from(consumer::partition_for(group_id, count)).unwrap_or(0);
+        partitions
+            .get(index)
+            .cloned()
+            .ok_or_else(|| Error::Internal(format!("Topic {OFFSETS_TOPIC} has no partitions")))
+    }
+
+    /// Write the metadata of groups whose membership changed to the offsets
+    /// topic
+    pub fn persist_groups(&self) {
+        for entry in &self.consumer_groups {
+            let group = entry.value();
+            let Some(metadata) = group.take_metadata() else {
+                continue;
+            };
+            let result = consumer::metadata_record(entry.key(), &metadata)
+                .and_then(|record| self.offsets_partition(entry.key())?.log.append(&record));
+            if let Err(e) = result {
+                warn!("Persisting consumer group {} failed: {}", entry.key(), e);
+                group.mark_dirty();
+            }
+        }
+    }
+
+    /// Evict group members whose session expired and finish rebalances past
+    /// their deadline
+    pub fn expire_group_members(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        for entry in &self.consumer_groups {
+            for member_id in entry.value().expire_members(now) {
+                info!(
+                    "Evicted member {} from group {} after its session expired",
+                    member_id,
+                    entry.key()
+                );
+            }
+        }
+    }
+
+    /// Join a consumer group, checking the requested session timeout
+    /// against the configured bounds
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session timeout is out of bounds or the group
+    /// rejects the member.
+    pub async fn join_group(
+        &self,
+        group_id: &str,
+        request: JoinGroupRequest,
+    ) -> std::result::Result<JoinGroupResponse, GroupError> {
+        let timeout = u64::try_from(request.session_timeout_ms).unwrap_or(0);
+        if timeout < self.config.group_min_session_timeout_ms
+            || timeout > self.config.group_max_session_timeout_ms
+        {
+            return Err(GroupError::InvalidSessionTimeout);
+        }
+        self.consumer_group(group_id).join(request).await
+    }
+
+    /// Join a group whose partitions the server assigns
+    ///
+    /// Parks through the rebalance like a Kafka join followed by a sync;
+    /// when this member leads the group, the assignment is computed here
+    /// with the group's assignor. A cooperative assignment that held
+    /// partitions back starts a follow-up rebalance to hand them over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session timeout is out of bounds, the group
+    /// rejects the member, or the assignment cannot be computed.
+    pub async fn join_assigned_group(
+        &self,
+        group_id: &str,
+        request: AssignedJoinRequest,
+    ) -> std::result::Result<GroupMembership, GroupError> {
+        let group = self.consumer_group(group_id);
+        let owned = group
+            .members()
+            .into_iter()
+            .find(|m| {
+                m.member_id == request.member_id
+                    || (m.group_instance_id.is_some()
+                        && m.group_instance_id == request.group_instance_id)
+            })
+            .and_then(|m| serde_json::from_slice(&m.assignment).ok())
+            .unwrap_or_default();
+        let subscription = Subscription {
+            topics: request.topics.clone(),
+            owned,
+        };
+
+        let joined = self
+            .join_group(
+                group_id,
+                JoinGroupRequest {
+                    member_id: request.member_id.clone(),
+                    group_instance_id: request.group_instance_id.clone(),
+                    client_id: request.client_id.clone(),
+                    client_host: String::new(),
+                    session_timeout_ms: request.session_timeout_ms,
+                    rebalance_timeout_ms: request.rebalance_timeout_ms,
+                    protocol_type: SERVER_ASSIGNED_PROTOCOL.to_string(),
+                    protocols: vec![(
+                        request.assignor.name().to_string(),
+                        serde_json::to_vec(&subscription).unwrap_or_default(),
+                    )],
+                    require_known_member_id: false,
+                },
+            )
+            .await?;
+
+        let mut rebalance_needed = false;
+        let assignments = if joined.leader == joined.member_id {
+            let assignor = joined
+                .protocol_name
+                .as_deref()
+                .and_then(|name| name.parse::<Assignor>().ok())
+                .unwrap_or(request.assignor);
+            let subscriptions: BTreeMap<String, Subscription> = joined
+                .members
+                .iter()
+                .map(|m| {
+                    (
+                        m.member_id.clone(),
+                        serde_json::from_slice(&m.metadata).unwrap_or_default(),
+                    )
+                })
+                .collect();
+            let partitions: BTreeMap<String, i32> = subscriptions
+                .values()
+                .flat_map(|s| s.topics.iter())
+                .filter_map(|topic| {
+                    let count = i32::try_from(self.partitions(topic)?.len()).ok()?;
+                    Some((topic.clone(), count))
+                })
+                .collect();
+
+            let result = assignor.assign(&subscriptions, &partitions);
+            rebalance_needed = result.rebalance_needed;
+            result
+                .members
+                .into_iter()
+                .map(|(id, assigned)| (id, serde_json::to_vec(&assigned).unwrap_or_default()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let assignment = group
+            .sync(
+                &joined.member_id,
+                request.group_instance_id.as_deref(),
+                joined.generation_id,
+                assignments,
+            )
+            .await?;
+        if rebalance_needed {
+            group.request_rebalance();
+        }
+
+        Ok(GroupMembership {
+            member_id: joined.member_id,
+            generation_id: joined.generation_id,
+            assignment: serde_json::from_slice(&assignment).unwrap_or_default(),
+        })
+    }
+}
+
+/// Request to join a group whose partitions the server assigns
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AssignedJoinRequest {
+    /// Member id from an earlier join, empty for a new member
+    #[serde(default)]
+    pub member_id: String,
+    /// Static member id kept across restarts
+    #[serde(default)]
+    pub group_instance_id: Option<String>,
+    #[serde(default)]
+    pub client_id: String,
+    pub topics: Vec<String>,
+    #[serde(default = "default_assignor")]
+    pub assignor: Assignor,
+    #[serde(default = "default_session_timeout_ms")]
+    pub session_timeout_ms: i32,
+    #[serde(default = "default_rebalance_timeout_ms")]
+    pub rebalance_timeout_ms: i32,
+}
+
+fn default_assignor() -> Assignor {
+    Assignor::Range
+}
+
+fn default_session_timeout_ms() -> i32 {
+    45_000
+}
+
+fn default_rebalance_timeout_ms() -> i32 {
+    300_000
+}
+
+/// A member's place in a server-assigned group
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupMembership {
+    pub member_id: String,
+    pub generation_id: i32,
+    pub assignment: Vec<TopicPartition>,
+}
+
+/// Decode a stored value for REST consumers: JSON as produced over REST,
+/// tombstones as null, and anything else (e.g. Kafka clients) as a string
+fn decode_value(value: &[u8]) -> serde_json::Value {
+    if value.is_empty() {
+        return serde_json::Value::Null;
+    }
+    serde_json::from_slice(value)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(value).into_owned()))
+}
+
+/// Check that a topic name is legal in Kafka and safe to use as a directory
+///
+/// # Errors
+///
+/// Returns an error if the name is empty, too long, `.` or `..`, or has
+/// characters other than ASCII alphanumerics, `.`, `_` and `-`.
+pub fn validate_topic_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > 249 || name == "." || name == ".." {
+        return Err(Error::Internal(format!("Invalid topic name '{name}'")));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+    {
+        return Err(Error::Internal(format!(
+            "Invalid character '{c}' in topic name '{name}'"
+        )));
+    }
+    Ok(())
 }
 
 /// Record to produce (from REST API)
@@ -358,6 +1355,29 @@ pub struct ProduceRecord {
     pub partition: Option<i32>,
 }
 
+impl ProduceRecord {
+    /// Stored form of the record; a null value is a tombstone
+    fn to_record(&self, partition: PartitionId) -> Result<Record> {
+        let value = if self.value.is_null() {
+            Vec::new()
+        } else {
+            serde_json::to_vec(&self.value)?
+        };
+        let mut record = Record::new(value);
+        record.key = self.key.as_ref().map(|k| bytes::Bytes::from(k.clone()));
+        record.headers = self.headers.clone().unwrap_or_default();
+        record.partition = Some(partition);
+        Ok(record)
+    }
+}
+
+fn producer_id_and_epoch(producer: &ProducerInfo) -> ProducerIdAndEpoch {
+    ProducerIdAndEpoch {
+        producer_id: producer.producer_id,
+        producer_epoch: producer.producer_epoch,
+    }
+}
+
 /// Record from consume (for REST API)
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ConsumeRecord {
@@ -14,9 +14,13 @@ pub mod batch;
 pub mod consumer;
 pub mod log;
 pub mod network;
+pub mod producer;
 pub mod reactor;
 
 mod engine;
 
-pub use engine::{StreamingEngine, ProduceRecord, ConsumeRecord, RaftStub};
+pub use engine::{
+    validate_topic_name, AssignedJoinRequest, ConsumeRecord, GroupMembership, Partition,
+    ProduceRecord, RaftStub, StreamingEngine,
+};
 pub use lumadb_common::config::StreamingConfig;
@@ -1,73 +1,423 @@
 //! Append-only log storage for streaming
+//!
+//! Each partition lives in its own directory as a sequence of [`Segment`]s
+//! ordered by base offset; the last one is active and receives appends.
+//! Segments roll when they reach `segment_bytes` or span more than
+//! `segment_ms` of record time, and whole segments are removed from the head
+//! of the log by time- and size-based retention. Topics with a compacting
+//! cleanup policy are also cleaned: closed segments are rewritten keeping
+//! only the latest record per key, with every record keeping its offset.
+//! Each log also keeps the state of idempotent and transactional producers
+//! (see [`ProducerStateManager`]), which validates their sequence numbers and
+//! bounds what `read_committed` readers may see.
+//!
+//! Logs of encrypted topics seal each record under the topic's data key,
+//! with the record offset as associated data. After a key rotation the
+//! cleaner rewrites closed segments holding records sealed with older key
+//! versions (see [`PartitionLog::reencrypt`]).
 
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
 
 use bytes::Bytes;
-use crossbeam::queue::SegQueue;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use tracing::{debug, info};
 
+use lumadb_common::config::StreamingConfig;
 use lumadb_common::error::{Result, Error};
-use lumadb_common::types::{Offset, PartitionId, Record};
+use lumadb_common::types::{
+    CleanupPolicy, ControlRecord, Offset, PartitionId, ProducerInfo, Record, TopicConfig,
+};
+use lumadb_storage::encryption::{self, DataKeys};
 
+use crate::producer::{IsolationLevel, ProducerError};
+
+mod producer;
 mod segment;
 
+pub use producer::{AbortedTransaction, ProducerStateManager};
 pub use segment::Segment;
 
+/// Subdirectory of a partition where the cleaner writes rewritten segments
+const CLEANER_DIR: &str = "cleaner";
+
+/// Per-partition log settings
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    /// Maximum segment size in bytes
+    pub segment_bytes: usize,
+    /// Maximum record time span of a segment in milliseconds
+    pub segment_ms: u64,
+    /// Retention time in milliseconds (unlimited when unset)
+    pub retention_ms: Option<u64>,
+    /// Retention size in bytes (unlimited when unset)
+    pub retention_bytes: Option<u64>,
+    /// Bytes of log data between sparse index entries
+    pub index_interval_bytes: usize,
+    /// How old records are cleaned up
+    pub cleanup_policy: CleanupPolicy,
+    /// How long tombstones survive compaction, in milliseconds
+    pub delete_retention_ms: u64,
+    /// How long state is kept for producers that stopped writing, in
+    /// milliseconds
+    pub producer_id_expiration_ms: u64,
+    /// Data keys of the topic, if its records are encrypted
+    pub cipher: Option<Arc<DataKeys>>,
+}
+
+impl LogOptions {
+    /// Resolve options for a topic from engine defaults and topic overrides
+    ///
+    /// Kafka-style keys in `TopicConfig::config` (`segment.bytes`,
+    /// `segment.ms`, `retention.ms`, `retention.bytes`, `cleanup.policy`,
+    /// `delete.retention.ms`) take precedence over the typed fields; a
+    /// negative retention disables that limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a topic config value is invalid.
+    pub fn for_topic(config: &StreamingConfig, topic: &TopicConfig) -> Result<Self> {
+        let setting = |key: &str| -> Result<Option<i64>> {
+            topic
+                .config
+                .get(key)
+                .map(|v| {
+                    v.trim().parse::<i64>().map_err(|_| {
+                        Error::Config(format!("invalid value '{v}' for topic config {key}"))
+                    })
+                })
+                .transpose()
+        };
+        let limit = |value: Option<i64>, fallback: Option<u64>| match value {
+            Some(v) => u64::try_from(v).ok(),
+            None => fallback,
+        };
+
+        let segment_bytes = match setting("segment.bytes")? {
+            Some(v) => usize::try_from(v).ok(),
+            None => topic.segment_bytes.and_then(|v| usize::try_from(v).ok()),
+        }
+        .unwrap_or(config.segment_size_bytes);
+        let segment_ms =
+            limit(setting("segment.ms")?, Some(config.segment_ms)).unwrap_or(config.segment_ms);
+        let cleanup_policy = match topic.config.get("cleanup.policy") {
+            Some(v) => v.parse().map_err(Error::Config)?,
+            None => topic.cleanup_policy,
+        };
+        let delete_retention_ms = limit(
+            setting("delete.retention.ms")?,
+            Some(
+                topic
+                    .delete_retention_ms
+                    .unwrap_or(config.delete_retention_ms),
+            ),
+        )
+        .unwrap_or(u64::MAX);
+
+        Ok(Self {
+            segment_bytes,
+            segment_ms,
+            retention_ms: limit(
+                setting("retention.ms")?,
+                Some(topic.retention_ms.unwrap_or(config.retention_ms)),
+            ),
+            retention_bytes: limit(
+                setting("retention.bytes")?,
+                topic.retention_bytes.or(config.retention_bytes),
+            ),
+            index_interval_bytes: config.index_interval_bytes,
+            cleanup_policy,
+            delete_retention_ms,
+            producer_id_expiration_ms: config.producer_id_expiration_ms,
+            cipher: None,
+        })
+    }
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        let config = StreamingConfig::default();
+        Self {
+            segment_bytes: config.segment_size_bytes,
+            segment_ms: config.segment_ms,
+            retention_ms: Some(config.retention_ms),
+            retention_bytes: config.retention_bytes,
+            index_interval_bytes: config.index_interval_bytes,
+            cleanup_policy: CleanupPolicy::default(),
+            delete_retention_ms: config.delete_retention_ms,
+            producer_id_expiration_ms: config.producer_id_expiration_ms,
+            cipher: None,
+        }
+    }
+}
+
 /// Partition log - append-only storage for a single partition
 pub struct PartitionLog {
     /// Partition ID
     partition_id: PartitionId,
-    /// Active segment
-    active_segment: RwLock<Segment>,
-    /// Immutable segments
-    segments: RwLock<Vec<Segment>>,
+    /// Directory holding the partition's segments
+    dir: PathBuf,
+    /// Log settings
+    options: LogOptions,
+    /// Segments ordered by base offset; the last one is active
+    segments: RwLock<Vec<Arc<Segment>>>,
+    /// Serializes appends, rolls and retention
+    append_lock: Mutex<()>,
+    /// Serializes cleaning and retention, which both replace closed segments
+    cleaner_lock: Mutex<()>,
+    /// Highest offset covered by the last compaction
+    cleaned_through: AtomicI64,
+    /// Earliest time a retained tombstone becomes removable
+    next_tombstone_expiry: AtomicI64,
+    /// Data key version every closed segment was last found sealed with
+    reencrypted_version: AtomicI64,
+    /// Idempotent and transactional producer state, updated under the
+    /// append lock
+    producers: Mutex<ProducerStateManager>,
     /// High watermark (latest committed offset)
     high_watermark: AtomicI64,
     /// Low watermark (earliest available offset)
     low_watermark: AtomicI64,
-    /// Maximum segment size
-    max_segment_size: usize,
     /// Next offset to assign
     next_offset: AtomicI64,
 }
 
 impl PartitionLog {
-    /// Create a new partition log
-    pub fn new(partition_id: PartitionId, max_segment_size: usize) -> Self {
-        Self {
-            partition_id,
-            active_segment: RwLock::new(Segment::new(0)),
-            segments: RwLock::new(Vec::new()),
-            high_watermark: AtomicI64::new(-1),
-            low_watermark: AtomicI64::new(0),
-            max_segment_size,
-            next_offset: AtomicI64::new(0),
+    /// Open the partition log in `dir`, recovering existing segments
+    ///
+    /// Watermarks are restored from the recovered segments; an empty
+    /// directory starts a new log at offset 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a segment cannot be opened.
+    pub fn open(
+        partition_id: PartitionId,
+        dir: impl Into<PathBuf>,
+        options: LogOptions,
+    ) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        // Discard output of a cleaner pass interrupted before its swap
+        let cleaner_dir = dir.join(CLEANER_DIR);
+        if cleaner_dir.exists() {
+            fs::remove_dir_all(&cleaner_dir)?;
         }
+
+        let mut base_offsets = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("log") {
+                continue;
+            }
+            if let Some(base) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<Offset>().ok())
+            {
+                base_offsets.push(base);
+            }
+        }
+        base_offsets.sort_unstable();
+
+        let mut segments = Vec::with_capacity(base_offsets.len().max(1));
+        for base in base_offsets {
+            segments.push(Arc::new(Segment::open(
+                &dir,
+                base,
+                options.index_interval_bytes,
+            )?));
+        }
+        if segments.is_empty() {
+            segments.push(Arc::new(Segment::create(
+                &dir,
+                0,
+                options.index_interval_bytes,
+            )?));
+        }
+
+        let next_offset = segments
+            .last()
+            .map_or(0, |s| s.highest_offset().map_or(s.base_offset(), |o| o + 1));
+        let low_watermark = segments.first().map_or(0, |s| s.base_offset());
+        if segments.len() > 1 || next_offset > 0 {
+            info!(
+                "Recovered partition {} from {:?}: {} segments, offsets {}..{}",
+                partition_id,
+                dir,
+                segments.len(),
+                low_watermark,
+                next_offset
+            );
+        }
+
+        let producers = recover_producers(
+            &dir,
+            &segments,
+            low_watermark,
+            next_offset,
+            options.cipher.as_deref(),
+        )?;
+
+        Ok(Self {
+            partition_id,
+            dir,
+            options,
+            segments: RwLock::new(segments),
+            append_lock: Mutex::new(()),
+            cleaner_lock: Mutex::new(()),
+            cleaned_through: AtomicI64::new(-1),
+            next_tombstone_expiry: AtomicI64::new(i64::MIN),
+            reencrypted_version: AtomicI64::new(-1),
+            producers: Mutex::new(producers),
+            high_watermark: AtomicI64::new(next_offset - 1),
+            low_watermark: AtomicI64::new(low_watermark),
+            next_offset: AtomicI64::new(next_offset),
+        })
     }
 
     /// Append a record to the log
     pub fn append(&self, record: &Record) -> Result<Offset> {
-        let offset = self.next_offset.fetch_add(1, Ordering::SeqCst);
+        self.append_batch(std::slice::from_ref(record))
+    }
+
+    /// Append records at consecutive offsets, returning the first offset
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the partition is compacted and a record has no
+    /// key, or a segment cannot be rolled or written.
+    pub fn append_batch(&self, records: &[Record]) -> Result<Offset> {
+        self.check_keys(records)?;
+
+        let _guard = self.append_lock.lock();
+        let base_offset = self.next_offset.load(Ordering::SeqCst);
+        for record in records {
+            self.append_locked(record)?;
+        }
+        Ok(base_offset)
+    }
+
+    /// Append a batch from an idempotent producer, returning the first offset
+    ///
+    /// `producer` carries the sequence number of the first record; the
+    /// records are stamped with consecutive sequence numbers. A retry of a
+    /// batch that is already in the log is not appended again and returns
+    /// the offset of the original.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch fails the producer checks, the partition
+    /// is compacted and a record has no key, or the write fails.
+    pub fn append_producer_batch(
+        &self,
+        records: &[Record],
+        producer: ProducerInfo,
+    ) -> std::result::Result<Offset, ProducerError> {
+        self.check_keys(records)?;
+
+        let _guard = self.append_lock.lock();
+        if let Some(offset) = self
+            .producers
+            .lock()
+            .check_batch(&producer, records.len())?
+        {
+            debug!(
+                "Partition {} ignored duplicate batch from producer {} at sequence {}",
+                self.partition_id, producer.producer_id, producer.sequence
+            );
+            return Ok(offset);
+        }
+
+        let base_offset = self.next_offset.load(Ordering::SeqCst);
+        let mut sequence = producer.sequence;
+        for record in records {
+            let mut record = record.clone();
+            record.producer = Some(ProducerInfo {
+                sequence,
+                ..producer
+            });
+            self.append_locked(&record)?;
+            sequence = sequence.checked_add(1).unwrap_or(0);
+        }
+        Ok(base_offset)
+    }
+
+    /// Append a commit or abort marker ending a producer's transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer has been fenced or the write fails.
+    pub fn append_marker(
+        &self,
+        producer_id: i64,
+        producer_epoch: i16,
+        control: ControlRecord,
+        timestamp: i64,
+    ) -> std::result::Result<Offset, ProducerError> {
+        let _guard = self.append_lock.lock();
+        self.producers
+            .lock()
+            .check_marker(producer_id, producer_epoch)?;
+
+        let mut record = Record::new(Vec::new()).with_timestamp(timestamp);
+        record.producer = Some(ProducerInfo {
+            producer_id,
+            producer_epoch,
+            sequence: -1,
+            transactional: true,
+        });
+        record.control = Some(control);
+        Ok(self.append_locked(&record)?)
+    }
+
+    fn check_keys(&self, records: &[Record]) -> Result<()> {
+        if self.options.cleanup_policy.compacts() && records.iter().any(|r| r.key.is_none()) {
+            return Err(Error::Internal(format!(
+                "Partition {} is compacted and requires record keys",
+                self.partition_id
+            )));
+        }
+        Ok(())
+    }
+
+    fn append_locked(&self, record: &Record) -> Result<Offset> {
+        let offset = self.next_offset.load(Ordering::SeqCst);
 
         // Create record with offset
         let mut record = record.clone();
         record.offset = Some(offset);
 
         // Serialize record
-        let data = bincode::serialize(&record)
+        let mut data = bincode::serialize(&record)
             .map_err(|e| Error::Internal(format!("Serialization failed: {}", e)))?;
+        if let Some(ref cipher) = self.options.cipher {
+            data = cipher.seal(&offset.to_le_bytes(), &data)?;
+        }
 
         // Check if we need to roll the segment
-        {
-            let segment = self.active_segment.read();
-            if segment.size() + data.len() > self.max_segment_size {
-                drop(segment);
-                self.roll_segment()?;
-            }
-        }
+        let active = self.active_segment();
+        let full = active.size() + data.len() > self.options.segment_bytes;
+        let aged = active.first_timestamp().is_some_and(|first| {
+            record.timestamp.saturating_sub(first)
+                >= i64::try_from(self.options.segment_ms).unwrap_or(i64::MAX)
+        });
+        let active = if !active.is_empty() && (full || aged) {
+            self.roll_segment(offset)?
+        } else {
+            active
+        };
 
         // Append to active segment
-        self.active_segment.write().append(offset, &data)?;
+        active.append(offset, record.timestamp, &data)?;
+        self.next_offset.store(offset + 1, Ordering::SeqCst);
+        if record.producer.is_some() {
+            self.producers.lock().record(offset, &record);
+        }
 
         // Update high watermark
         self.high_watermark.store(offset, Ordering::SeqCst);
@@ -76,62 +426,497 @@ impl PartitionLog {
     }
 
     /// Fetch records starting from an offset
+    ///
+    /// A negative offset starts at the high watermark; offsets removed by
+    /// retention start at the low watermark.
     pub fn fetch(&self, start_offset: Offset, max_records: usize) -> Result<Vec<(Offset, Record)>> {
+        self.read(start_offset, max_records, usize::MAX)
+    }
+
+    /// Fetch records up to a record count and an approximate size limit
+    ///
+    /// At least one record is returned when one is available, even if it is
+    /// larger than `max_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment cannot be read.
+    pub fn read(
+        &self,
+        start_offset: Offset,
+        max_records: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<(Offset, Record)>> {
         let mut results = Vec::new();
+        let mut bytes = 0usize;
         let start = if start_offset < 0 {
             // -1 means latest
             self.high_watermark.load(Ordering::SeqCst)
         } else {
-            start_offset
+            start_offset.max(self.low_watermark.load(Ordering::SeqCst))
         };
 
-        // Check segments
-        for segment in self.segments.read().iter() {
+        let segments = self.segments.read().clone();
+        let first = segments
+            .partition_point(|s| s.base_offset() <= start)
+            .saturating_sub(1);
+
+        'segments: for segment in &segments[first..] {
             if results.len() >= max_records {
                 break;
             }
 
-            for (offset, data) in segment.read_from(start)? {
-                if results.len() >= max_records {
-                    break;
+            let remaining_bytes = max_bytes.saturating_sub(bytes);
+            if !results.is_empty() && remaining_bytes == 0 {
+                break;
+            }
+            for (offset, data) in
+                segment.read_from(start, max_records - results.len(), remaining_bytes)?
+            {
+                if !results.is_empty() && bytes + data.len() > max_bytes {
+                    break 'segments;
                 }
+                bytes += data.len();
+                results.push((offset, self.decode(offset, &data)?));
+            }
+        }
 
-                let record: Record = bincode::deserialize(&data)
-                    .map_err(|e| Error::Internal(format!("Deserialization failed: {}", e)))?;
-                results.push((offset, record));
+        Ok(results)
+    }
+
+    /// Fetch records visible at an isolation level
+    ///
+    /// `read_committed` reads stop at the last stable offset. Records of
+    /// aborted transactions and control records are still returned; callers
+    /// filter them with [`Self::aborted_transactions`] or
+    /// [`Self::is_aborted`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment cannot be read.
+    pub fn read_isolated(
+        &self,
+        start_offset: Offset,
+        max_records: usize,
+        max_bytes: usize,
+        isolation: IsolationLevel,
+    ) -> Result<Vec<(Offset, Record)>> {
+        let mut records = self.read(start_offset, max_records, max_bytes)?;
+        if isolation == IsolationLevel::ReadCommitted {
+            let stable = self.last_stable_offset();
+            records.retain(|(offset, _)| *offset < stable);
+        }
+        Ok(records)
+    }
+
+    /// Earliest offset whose record timestamp is at or after `timestamp`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment cannot be read.
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> Result<Option<Offset>> {
+        let segments = self.segments.read().clone();
+        for segment in segments {
+            if segment.max_timestamp() >= timestamp {
+                if let Some(offset) = segment.offset_for_timestamp(timestamp)? {
+                    return Ok(Some(offset));
+                }
             }
         }
+        Ok(None)
+    }
+
+    /// Roll to a new active segment starting at `next_offset`
+    ///
+    /// Must be called with the append lock held.
+    fn roll_segment(&self, next_offset: Offset) -> Result<Arc<Segment>> {
+        let old = self.active_segment();
+        old.flush()?;
+        self.producers.lock().save(&self.dir, next_offset)?;
+
+        let segment = Arc::new(Segment::create(
+            &self.dir,
+            next_offset,
+            self.options.index_interval_bytes,
+        )?);
+        self.segments.write().push(segment.clone());
+        debug!(
+            "Partition {} rolled segment {} at offset {}",
+            self.partition_id,
+            old.base_offset(),
+            next_offset
+        );
+
+        Ok(segment)
+    }
+
+    /// Delete segments past the retention time or size limits
+    ///
+    /// Only whole segments are removed, oldest first. An active segment whose
+    /// records have all expired is rolled so it can be deleted too. Returns
+    /// the number of segments removed; nothing is removed unless the cleanup
+    /// policy includes `delete`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the active segment cannot be rolled or a segment
+    /// cannot be deleted.
+    pub fn enforce_retention(&self, now_ms: i64) -> Result<usize> {
+        if !self.options.cleanup_policy.deletes() {
+            return Ok(0);
+        }
+        let _cleaner = self.cleaner_lock.lock();
+        let _guard = self.append_lock.lock();
+
+        let cutoff = self
+            .options
+            .retention_ms
+            .and_then(|ms| i64::try_from(ms).ok())
+            .map(|ms| now_ms.saturating_sub(ms));
+
+        let active = self.active_segment();
+        if !active.is_empty() && cutoff.is_some_and(|c| active.max_timestamp() < c) {
+            self.roll_segment(self.next_offset.load(Ordering::SeqCst))?;
+        }
+
+        let expired = {
+            let mut segments = self.segments.write();
+            let mut excess = self.options.retention_bytes.map_or(0, |limit| {
+                let total: u64 = segments.iter().map(|s| s.size() as u64).sum();
+                total.saturating_sub(limit)
+            });
 
-        // Check active segment
-        if results.len() < max_records {
-            for (offset, data) in self.active_segment.read().read_from(start)? {
-                if results.len() >= max_records {
+            let mut count = 0;
+            for segment in &segments[..segments.len() - 1] {
+                let by_time = cutoff.is_some_and(|c| segment.max_timestamp() < c);
+                let by_size = excess > 0 && segment.size() as u64 <= excess;
+                if !(by_time || by_size) {
                     break;
                 }
+                excess = excess.saturating_sub(segment.size() as u64);
+                count += 1;
+            }
+            segments.drain(..count).collect::<Vec<_>>()
+        };
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let low_watermark = self.segments.read()[0].base_offset();
+        self.low_watermark.store(low_watermark, Ordering::SeqCst);
+        for segment in &expired {
+            segment.delete()?;
+        }
+        info!(
+            "Partition {} deleted {} segments by retention, log start offset now {}",
+            self.partition_id,
+            expired.len(),
+            low_watermark
+        );
+
+        Ok(expired.len())
+    }
+
+    /// Forget producers that have not written for `producer_id_expiration_ms`
+    /// and aborted transactions below the log start offset, returning the
+    /// number of producers removed
+    pub fn expire_producers(&self, now_ms: i64) -> usize {
+        let expiration = i64::try_from(self.options.producer_id_expiration_ms).unwrap_or(i64::MAX);
+        let _guard = self.append_lock.lock();
+        self.producers
+            .lock()
+            .expire(now_ms.saturating_sub(expiration), self.low_watermark())
+    }
+
+    /// Compact closed segments, keeping the latest record for each key
+    ///
+    /// Superseded records are dropped and tombstones are kept for
+    /// `delete_retention_ms` after their timestamp so consumers can observe
+    /// the deletion. Surviving records keep their offsets, so committed
+    /// consumer offsets stay valid; a fetch from a removed offset continues
+    /// at the next surviving one. Records of aborted transactions are
+    /// dropped, and segments reaching into an open transaction are left for
+    /// a later pass. Returns the number of records removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment cannot be read, rewritten or replaced.
+    pub fn compact(&self, now_ms: i64) -> Result<usize> {
+        if !self.options.cleanup_policy.compacts() {
+            return Ok(0);
+        }
+        let _cleaner = self.cleaner_lock.lock();
+
+        let segments = self.segments.read().clone();
+        let stable = self.last_stable_offset();
+        let cleanable = segments[..segments.len() - 1]
+            .iter()
+            .take_while(|s| s.highest_offset().map_or(true, |o| o < stable))
+            .count();
+        let closed = &segments[..cleanable];
+        let Some(dirty_end) = closed.iter().rev().find_map(|s| s.highest_offset()) else {
+            return Ok(0);
+        };
+        let dirty = dirty_end > self.cleaned_through.load(Ordering::SeqCst);
+        let tombstones_due = now_ms >= self.next_tombstone_expiry.load(Ordering::SeqCst);
+        if !dirty && !tombstones_due {
+            return Ok(0);
+        }
+
+        // Latest offset of every key in the closed segments, by key hash
+        let aborted = self.aborted_transactions(Offset::MIN, stable);
+        let is_aborted =
+            |offset: Offset, record: &Record| aborted.iter().any(|a| a.covers(offset, record));
+        let mut latest: HashMap<u128, Offset> = HashMap::new();
+        for segment in closed {
+            segment.scan(|offset, _, payload| {
+                let record = self.decode(offset, payload)?;
+                if let Some(ref key) = record.key {
+                    if !is_aborted(offset, &record) {
+                        latest.insert(xxhash_rust::xxh3::xxh3_128(key), offset);
+                    }
+                }
+                Ok(())
+            })?;
+        }
 
-                let record: Record = bincode::deserialize(&data)
-                    .map_err(|e| Error::Internal(format!("Deserialization failed: {}", e)))?;
-                results.push((offset, record));
+        let delete_retention = i64::try_from(self.options.delete_retention_ms).unwrap_or(i64::MAX);
+        let mut next_tombstone_expiry = i64::MAX;
+        let mut keep = |offset: Offset, timestamp: i64, payload: &[u8]| -> Result<bool> {
+            let record = self.decode(offset, payload)?;
+            let Some(ref key) = record.key else {
+                return Ok(true);
+            };
+            if is_aborted(offset, &record) {
+                return Ok(false);
             }
+            if latest.get(&xxhash_rust::xxh3::xxh3_128(key)) != Some(&offset) {
+                return Ok(false);
+            }
+            if record.is_tombstone() {
+                let expiry = timestamp.saturating_add(delete_retention);
+                if expiry <= now_ms {
+                    return Ok(false);
+                }
+                next_tombstone_expiry = next_tombstone_expiry.min(expiry);
+            }
+            Ok(true)
+        };
+
+        let mut removed = 0;
+        for (i, segment) in closed.iter().enumerate() {
+            let mut kept = Vec::new();
+            let mut total = 0;
+            segment.scan(|offset, timestamp, payload| {
+                total += 1;
+                if keep(offset, timestamp, payload)? {
+                    kept.push(offset);
+                }
+                Ok(())
+            })?;
+            if kept.len() == total {
+                continue;
+            }
+            removed += total - kept.len();
+
+            // Empty segments other than the first are dropped outright; the
+            // first stays so the log start offset does not move
+            if kept.is_empty() && i > 0 {
+                self.segments.write().retain(|s| !Arc::ptr_eq(s, segment));
+                segment.delete()?;
+                continue;
+            }
+
+            let mut kept = kept.into_iter().peekable();
+            self.rewrite_segment(segment, |offset| {
+                let keep = kept.peek() == Some(&offset);
+                if keep {
+                    kept.next();
+                }
+                keep
+            })?;
         }
 
-        Ok(results)
+        self.cleaned_through.store(dirty_end, Ordering::SeqCst);
+        self.next_tombstone_expiry
+            .store(next_tombstone_expiry, Ordering::SeqCst);
+        if removed > 0 {
+            debug!(
+                "Partition {} compacted {} superseded records through offset {}",
+                self.partition_id, removed, dirty_end
+            );
+        }
+
+        Ok(removed)
     }
 
-    /// Roll to a new segment
-    fn roll_segment(&self) -> Result<()> {
-        let mut active = self.active_segment.write();
-        let old_segment = std::mem::replace(
-            &mut *active,
-            Segment::new(self.next_offset.load(Ordering::SeqCst)),
-        );
+    /// Rewrite closed segments holding records not sealed with the topic's
+    /// active data key
+    ///
+    /// Brings existing records onto a rotated key, or encrypts those written
+    /// before encryption was enabled. An active segment holding such records
+    /// is rolled first. Does nothing once a pass has completed for the
+    /// active key version; returns the number of segments rewritten.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a segment cannot be read, resealed or swapped into place.
+    pub fn reencrypt(&self) -> Result<usize> {
+        let Some(cipher) = self.options.cipher.clone() else {
+            return Ok(0);
+        };
+        let active = cipher.active_version();
+        if self.reencrypted_version.load(Ordering::SeqCst) == i64::from(active) {
+            return Ok(0);
+        }
+        let _cleaner = self.cleaner_lock.lock();
+        let stale = |payload: &[u8]| encryption::sealed_version(payload) != Some(active);
 
-        // Make old segment immutable and add to list
-        self.segments.write().push(old_segment);
+        // Records are appended under the active key, so the first one is the
+        // oldest in the segment
+        {
+            let _guard = self.append_lock.lock();
+            let segment = self.active_segment();
+            let first = segment.read_from(segment.base_offset(), 1, 0)?;
+            if first.first().is_some_and(|(_, payload)| stale(payload)) {
+                self.roll_segment(self.next_offset.load(Ordering::SeqCst))?;
+            }
+        }
+
+        let segments = self.segments.read().clone();
+        let mut rewritten = 0;
+        for segment in &segments[..segments.len() - 1] {
+            let mut sealed_with_active = true;
+            segment.scan(|_, _, payload| {
+                sealed_with_active &= !stale(payload);
+                Ok(())
+            })?;
+            if !sealed_with_active {
+                self.rewrite_segment(segment, |_| true)?;
+                rewritten += 1;
+            }
+        }
 
+        self.reencrypted_version
+            .store(i64::from(active), Ordering::SeqCst);
+        if rewritten > 0 {
+            info!(
+                "Partition {} re-encrypted {} segment(s) with data key version {}",
+                self.partition_id, rewritten, active
+            );
+        }
+        Ok(rewritten)
+    }
+
+    /// Replace a closed segment with a copy holding the records `keep`
+    /// accepts, sealed with the active data key
+    ///
+    /// Must be called with the cleaner lock held.
+    fn rewrite_segment(
+        &self,
+        segment: &Arc<Segment>,
+        mut keep: impl FnMut(Offset) -> bool,
+    ) -> Result<()> {
+        let cleaner_dir = self.dir.join(CLEANER_DIR);
+        fs::create_dir_all(&cleaner_dir)?;
+        let cleaned = Segment::create(
+            &cleaner_dir,
+            segment.base_offset(),
+            self.options.index_interval_bytes,
+        )?;
+        segment.scan(|offset, timestamp, payload| {
+            if keep(offset) {
+                cleaned.append(offset, timestamp, &self.reseal(offset, payload)?)?;
+            }
+            Ok(())
+        })?;
+        cleaned.flush()?;
+        drop(cleaned);
+
+        Segment::replace_files(&cleaner_dir, &self.dir, segment.base_offset())?;
+        let replacement = Arc::new(Segment::open(
+            &self.dir,
+            segment.base_offset(),
+            self.options.index_interval_bytes,
+        )?);
+        let mut segments = self.segments.write();
+        if let Some(slot) = segments.iter_mut().find(|s| Arc::ptr_eq(s, segment)) {
+            *slot = replacement;
+        }
         Ok(())
     }
 
+    /// A stored payload as it should be rewritten: sealed with the active
+    /// data key when the topic is encrypted
+    fn reseal(&self, offset: Offset, payload: &[u8]) -> Result<Vec<u8>> {
+        let Some(ref cipher) = self.options.cipher else {
+            return Ok(payload.to_vec());
+        };
+        let aad = offset.to_le_bytes();
+        if encryption::sealed_version(payload) == Some(cipher.active_version()) {
+            return Ok(payload.to_vec());
+        }
+        cipher.seal(&aad, &cipher.open_or_plain(&aad, payload)?)
+    }
+
+    fn decode(&self, offset: Offset, payload: &[u8]) -> Result<Record> {
+        decode_record(offset, payload, self.options.cipher.as_deref())
+    }
+
+    /// Flush the active segment to disk and snapshot producer state
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the segment cannot be synced or the snapshot
+    /// cannot be written.
+    pub fn flush(&self) -> Result<()> {
+        let _guard = self.append_lock.lock();
+        self.active_segment().flush()?;
+        self.producers
+            .lock()
+            .save(&self.dir, self.next_offset.load(Ordering::SeqCst))
+    }
+
+    /// Remove the partition's files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment's files cannot be removed.
+    pub fn delete(&self) -> Result<()> {
+        let _guard = self.append_lock.lock();
+        for segment in self.segments.write().drain(..) {
+            segment.delete()?;
+        }
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn active_segment(&self) -> Arc<Segment> {
+        self.segments
+            .read()
+            .last()
+            .cloned()
+            .expect("partition log always has an active segment")
+    }
+
+    /// Get partition ID
+    pub fn partition_id(&self) -> PartitionId {
+        self.partition_id
+    }
+
+    /// Effective log settings
+    pub fn options(&self) -> &LogOptions {
+        &self.options
+    }
+
+    /// Directory holding the partition's segments
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
     /// Get high watermark
     pub fn high_watermark(&self) -> Offset {
         self.high_watermark.load(Ordering::SeqCst)
@@ -142,14 +927,87 @@ impl PartitionLog {
         self.low_watermark.load(Ordering::SeqCst)
     }
 
+    /// Offset the next appended record will receive
+    pub fn log_end_offset(&self) -> Offset {
+        self.next_offset.load(Ordering::SeqCst)
+    }
+
+    /// Offset below which every transaction has completed
+    ///
+    /// Equal to the log end offset when no transaction is open.
+    pub fn last_stable_offset(&self) -> Offset {
+        self.producers
+            .lock()
+            .last_stable_offset(self.log_end_offset())
+    }
+
+    /// Aborted transactions with records in `from..to`
+    pub fn aborted_transactions(&self, from: Offset, to: Offset) -> Vec<AbortedTransaction> {
+        self.producers.lock().aborted_transactions(from, to)
+    }
+
+    /// Whether the record at `offset` belongs to an aborted transaction
+    pub fn is_aborted(&self, offset: Offset, record: &Record) -> bool {
+        self.producers.lock().is_aborted(offset, record)
+    }
+
+    /// Number of segments, including the active one
+    pub fn segment_count(&self) -> usize {
+        self.segments.read().len()
+    }
+
     /// Get log size in bytes
     pub fn size_bytes(&self) -> usize {
-        let mut size = self.active_segment.read().size();
-        for segment in self.segments.read().iter() {
-            size += segment.size();
+        self.segments.read().iter().map(|s| s.size()).sum()
+    }
+}
+
+/// Load the producer state snapshot and replay the records written after it
+///
+/// A snapshot from beyond the recovered log end (a truncated tail) is
+/// discarded and the state rebuilt from the start of the log.
+fn recover_producers(
+    dir: &Path,
+    segments: &[Arc<Segment>],
+    log_start_offset: Offset,
+    log_end_offset: Offset,
+    cipher: Option<&DataKeys>,
+) -> Result<ProducerStateManager> {
+    let (replay_from, mut producers) = match ProducerStateManager::load(dir)? {
+        Some((offset, state)) if offset <= log_end_offset => (offset, state),
+        _ => (log_start_offset, ProducerStateManager::default()),
+    };
+
+    for segment in segments {
+        if segment.highest_offset().map_or(true, |o| o < replay_from) {
+            continue;
         }
-        size
+        segment.scan(|offset, _, payload| {
+            if offset >= replay_from {
+                producers.record(offset, &decode_record(offset, payload, cipher)?);
+            }
+            Ok(())
+        })?;
     }
+    Ok(producers)
+}
+
+/// Deserialize a stored record, opening it first if it is sealed
+fn decode_record(offset: Offset, payload: &[u8], cipher: Option<&DataKeys>) -> Result<Record> {
+    let opened;
+    let payload = if encryption::is_sealed(payload) {
+        let cipher = cipher.ok_or_else(|| {
+            Error::Internal(format!(
+                "record {offset} is encrypted but no data key is configured"
+            ))
+        })?;
+        opened = cipher.open(&offset.to_le_bytes(), payload)?;
+        opened.as_slice()
+    } else {
+        payload
+    };
+    bincode::deserialize(payload)
+        .map_err(|e| Error::Internal(format!("Deserialization failed: {e}")))
 }
 
 /// Log entry with offset
@@ -159,3 +1017,435 @@ pub struct LogEntry {
     pub data: Bytes,
     pub timestamp: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(segment_bytes: usize) -> LogOptions {
+        LogOptions {
+            segment_bytes,
+            segment_ms: u64::MAX,
+            retention_ms: None,
+            retention_bytes: None,
+            index_interval_bytes: 256,
+            cleanup_policy: CleanupPolicy::Delete,
+            delete_retention_ms: 1_000,
+            producer_id_expiration_ms: 1_000,
+            cipher: None,
+        }
+    }
+
+    fn keyed(key: &str, value: &str, timestamp: i64) -> Record {
+        record(value, timestamp).with_key(key.as_bytes().to_vec())
+    }
+
+    fn record(value: &str, timestamp: i64) -> Record {
+        let mut record = Record::new(value.as_bytes().to_vec());
+        record.timestamp = timestamp;
+        record
+    }
+
+    #[test]
+    fn test_recover_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let log = PartitionLog::open(0, dir.path(), options(1024)).unwrap();
+            for i in 0..100 {
+                log.append(&record(&format!("value-{i}"), i)).unwrap();
+            }
+            assert!(log.segment_count() > 1);
+            log.flush().unwrap();
+        }
+
+        let log = PartitionLog::open(0, dir.path(), options(1024)).unwrap();
+        assert_eq!(log.high_watermark(), 99);
+        assert_eq!(log.low_watermark(), 0);
+
+        // Reads span segment boundaries and start mid-segment via the index
+        let records = log.fetch(37, 20).unwrap();
+        assert_eq!(records.len(), 20);
+        assert_eq!(records[0].0, 37);
+        assert_eq!(records[0].1.value.as_ref(), b"value-37");
+        assert_eq!(records[19].0, 56);
+
+        assert_eq!(log.append(&record("next", 100)).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_torn_tail_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let log = PartitionLog::open(0, dir.path(), options(1 << 20)).unwrap();
+            for i in 0..10 {
+                log.append(&record(&format!("value-{i}"), i)).unwrap();
+            }
+        }
+
+        // Simulate a crash halfway through the last frame
+        let path = dir.path().join(format!("{:020}.log", 0));
+        let len = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let log = PartitionLog::open(0, dir.path(), options(1 << 20)).unwrap();
+        assert_eq!(log.high_watermark(), 8);
+        assert_eq!(log.append(&record("again", 10)).unwrap(), 9);
+        let records = log.fetch(0, 100).unwrap();
+        assert_eq!(records.len(), 10);
+        assert_eq!(records[9].1.value.as_ref(), b"again");
+    }
+
+    #[test]
+    fn test_encrypted_segments_and_reencryption() {
+        use lumadb_storage::encryption::{KeyManager, LocalKeyProvider};
+
+        let dir = tempfile::tempdir().unwrap();
+        let provider = LocalKeyProvider::new(vec![("1".to_string(), [9u8; 32])]).unwrap();
+        let keys = KeyManager::open(&dir.path().join("keys"), Arc::new(provider)).unwrap();
+        let log_dir = dir.path().join("log");
+        let encrypted = || LogOptions {
+            cipher: Some(keys.data_keys("topic:secrets").unwrap()),
+            ..options(1024)
+        };
+        let leaks = || {
+            fs::read_dir(&log_dir).unwrap().any(|entry| {
+                let data = fs::read(entry.unwrap().path()).unwrap_or_default();
+                data.windows(16).any(|w| w == b"plaintext-marker")
+            })
+        };
+
+        // Records written before encryption was enabled stay readable
+        {
+            let log = PartitionLog::open(0, &log_dir, options(1024)).unwrap();
+            for i in 0..20 {
+                log.append(&record(&format!("plaintext-marker-{i}"), i))
+                    .unwrap();
+            }
+            log.flush().unwrap();
+        }
+        assert!(leaks());
+
+        let log = PartitionLog::open(0, &log_dir, encrypted()).unwrap();
+        for i in 20..40 {
+            log.append(&record(&format!("plaintext-marker-{i}"), i))
+                .unwrap();
+        }
+        assert_eq!(
+            log.fetch(5, 1).unwrap()[0].1.value.as_ref(),
+            b"plaintext-marker-5"
+        );
+        assert!(log.reencrypt().unwrap() > 0);
+        assert_eq!(log.reencrypt().unwrap(), 0);
+        log.flush().unwrap();
+        assert!(!leaks());
+
+        // Rotation re-encrypts every segment, including the active one
+        assert_eq!(keys.rotate("topic:secrets").unwrap(), 2);
+        assert!(log.reencrypt().unwrap() > 0);
+        let segments: Vec<_> = log.segments.read().clone();
+        for segment in &segments[..segments.len() - 1] {
+            segment
+                .scan(|_, _, payload| {
+                    assert_eq!(encryption::sealed_version(payload), Some(2));
+                    Ok(())
+                })
+                .unwrap();
+        }
+        log.flush().unwrap();
+        drop(log);
+
+        let log = PartitionLog::open(0, &log_dir, encrypted()).unwrap();
+        let records = log.fetch(0, 100).unwrap();
+        assert_eq!(records.len(), 40);
+        assert_eq!(records[39].1.value.as_ref(), b"plaintext-marker-39");
+        drop(log);
+
+        // Sealed records cannot be read without the key
+        let log = PartitionLog::open(0, &log_dir, options(1024)).unwrap();
+        assert!(log.fetch(0, 1).is_err());
+    }
+
+    #[test]
+    fn test_retention_by_time_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = options(512);
+        opts.retention_ms = Some(1_000);
+        let log = PartitionLog::open(0, dir.path(), opts).unwrap();
+        for i in 0..50 {
+            log.append(&record(&format!("value-{i}"), i * 100)).unwrap();
+        }
+
+        // Records older than now - retention are dropped a segment at a time
+        assert!(log.enforce_retention(3_000).unwrap() > 0);
+        let low = log.low_watermark();
+        assert!(low > 0 && low <= 20);
+        assert_eq!(log.fetch(0, 1).unwrap()[0].0, low);
+
+        // Everything expired: the active segment is rolled and removed
+        log.enforce_retention(100_000).unwrap();
+        assert_eq!(log.low_watermark(), 50);
+        assert!(log.fetch(0, 10).unwrap().is_empty());
+        assert_eq!(log.append(&record("fresh", 100_000)).unwrap(), 50);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = options(512);
+        opts.retention_bytes = Some(1024);
+        let log = PartitionLog::open(0, dir.path(), opts).unwrap();
+        for i in 0..100 {
+            log.append(&record(&format!("value-{i}"), i)).unwrap();
+        }
+        log.enforce_retention(0).unwrap();
+        assert!(log.size_bytes() <= 1024 + 512);
+        assert!(log.low_watermark() > 0);
+    }
+
+    #[test]
+    fn test_offset_for_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = PartitionLog::open(0, dir.path(), options(1024)).unwrap();
+        for i in 0..100 {
+            log.append(&record(&format!("value-{i}"), 1_000 + i * 10))
+                .unwrap();
+        }
+
+        assert_eq!(log.offset_for_timestamp(0).unwrap(), Some(0));
+        assert_eq!(log.offset_for_timestamp(1_505).unwrap(), Some(51));
+        assert_eq!(log.offset_for_timestamp(1_990).unwrap(), Some(99));
+        assert_eq!(log.offset_for_timestamp(5_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_compaction_keeps_latest_per_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = options(512);
+        opts.cleanup_policy = CleanupPolicy::Compact;
+        opts.retention_ms = Some(1);
+        let log = PartitionLog::open(0, dir.path(), opts.clone()).unwrap();
+        assert!(log.append(&record("unkeyed", 0)).is_err());
+
+        for i in 0..60 {
+            log.append(&keyed(&format!("k{}", i % 5), &format!("v{i}"), i))
+                .unwrap();
+        }
+        let closed_end = log
+            .segments
+            .read()
+            .iter()
+            .rev()
+            .nth(1)
+            .unwrap()
+            .highest_offset()
+            .unwrap();
+
+        // Retention does not apply to compact-only topics
+        assert_eq!(log.enforce_retention(i64::MAX).unwrap(), 0);
+        assert!(log.compact(100).unwrap() > 0);
+        assert_eq!(log.compact(100).unwrap(), 0);
+
+        // Only the latest closed record per key survives, at its original
+        // offset; the active segment is untouched
+        let records = log.fetch(0, 100).unwrap();
+        let closed: Vec<_> = records.iter().filter(|(o, _)| *o <= closed_end).collect();
+        assert!(closed.len() <= 5);
+        for (offset, record) in &closed {
+            assert_eq!(record.offset, Some(*offset));
+            assert_eq!(record.value.as_ref(), format!("v{offset}").as_bytes());
+        }
+        assert_eq!(records.last().unwrap().0, 59);
+        assert_eq!(log.low_watermark(), 0);
+
+        // Fetching from a compacted-away offset resumes at the next survivor
+        assert!(closed[0].0 > 0);
+        assert_eq!(log.fetch(0, 1).unwrap()[0].0, closed[0].0);
+
+        drop(log);
+        let log = PartitionLog::open(0, dir.path(), opts).unwrap();
+        assert_eq!(log.high_watermark(), 59);
+        assert_eq!(log.fetch(0, 100).unwrap().len(), records.len());
+    }
+
+    #[test]
+    fn test_tombstones_expire_after_delete_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = options(256);
+        opts.cleanup_policy = CleanupPolicy::CompactDelete;
+        let log = PartitionLog::open(0, dir.path(), opts).unwrap();
+
+        log.append(&keyed("gone", "v", 0)).unwrap();
+        log.append(&keyed("kept", "v", 0)).unwrap();
+        log.append(&keyed("gone", "", 10)).unwrap();
+        for i in 0..20 {
+            log.append(&keyed(&format!("filler{i}"), "v", 20)).unwrap();
+        }
+        let keys = |log: &PartitionLog| -> Vec<Vec<u8>> {
+            log.fetch(0, 100)
+                .unwrap()
+                .into_iter()
+                .filter_map(|(_, r)| r.key.map(|k| k.to_vec()))
+                .collect()
+        };
+
+        // Within the delete retention window the tombstone is still visible
+        log.compact(500).unwrap();
+        let visible = keys(&log);
+        assert_eq!(
+            visible.iter().filter(|k| k.as_slice() == b"gone").count(),
+            1
+        );
+        assert!(visible.contains(&b"kept".to_vec()));
+
+        // Past the window a later pass removes it even without new segments
+        log.compact(2_000).unwrap();
+        let visible = keys(&log);
+        assert!(!visible.contains(&b"gone".to_vec()));
+        assert!(visible.contains(&b"kept".to_vec()));
+    }
+
+    fn producer(
+        producer_id: i64,
+        producer_epoch: i16,
+        sequence: i32,
+        transactional: bool,
+    ) -> ProducerInfo {
+        ProducerInfo {
+            producer_id,
+            producer_epoch,
+            sequence,
+            transactional,
+        }
+    }
+
+    #[test]
+    fn test_idempotent_producer_sequences() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = PartitionLog::open(0, dir.path(), options(1 << 20)).unwrap();
+        let batch = [record("a", 0), record("b", 0)];
+
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 0, 0, false))
+                .unwrap(),
+            0
+        );
+        // A retry is answered with the original offsets
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 0, 0, false))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            log.append_producer_batch(&batch[1..], producer(7, 0, 1, false))
+                .unwrap(),
+            1
+        );
+        assert_eq!(log.log_end_offset(), 2);
+
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 0, 5, false)),
+            Err(ProducerError::OutOfOrderSequence)
+        );
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(8, 0, 3, false)),
+            Err(ProducerError::UnknownProducerId)
+        );
+
+        // A new epoch restarts the sequence and fences the old one
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 1, 0, false))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 0, 2, false)),
+            Err(ProducerError::InvalidProducerEpoch)
+        );
+        assert_eq!(
+            log.append_producer_batch(&batch, producer(7, 1, 2, false))
+                .unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_read_committed_skips_open_and_aborted_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = PartitionLog::open(0, dir.path(), options(1 << 20)).unwrap();
+
+        log.append(&record("plain", 0)).unwrap();
+        log.append_producer_batch(&[record("aborted", 0)], producer(1, 0, 0, true))
+            .unwrap();
+        log.append_producer_batch(&[record("committed", 0)], producer(2, 0, 0, true))
+            .unwrap();
+        assert_eq!(log.last_stable_offset(), 1);
+
+        log.append_marker(1, 0, ControlRecord::Abort, 0).unwrap();
+        assert_eq!(log.last_stable_offset(), 2);
+        log.append_marker(2, 0, ControlRecord::Commit, 0).unwrap();
+        assert_eq!(log.last_stable_offset(), log.log_end_offset());
+
+        let aborted = log.aborted_transactions(0, log.log_end_offset());
+        assert_eq!(
+            aborted,
+            vec![AbortedTransaction {
+                producer_id: 1,
+                first_offset: 1,
+                last_offset: 3,
+            }]
+        );
+        let visible: Vec<_> = log
+            .read_isolated(0, 100, usize::MAX, IsolationLevel::ReadCommitted)
+            .unwrap()
+            .into_iter()
+            .filter(|(offset, record)| !record.is_control() && !log.is_aborted(*offset, record))
+            .map(|(_, record)| record.value)
+            .collect();
+        assert_eq!(
+            visible,
+            vec![Bytes::from("plain"), Bytes::from("committed")]
+        );
+
+        // A fenced producer cannot write a marker
+        log.append_producer_batch(&[record("next", 0)], producer(1, 1, 0, true))
+            .unwrap();
+        assert_eq!(
+            log.append_marker(1, 0, ControlRecord::Commit, 0),
+            Err(ProducerError::InvalidProducerEpoch)
+        );
+    }
+
+    #[test]
+    fn test_producer_state_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let log = PartitionLog::open(0, dir.path(), options(256)).unwrap();
+            for i in 0..20 {
+                let sequence = i32::try_from(i).unwrap();
+                log.append_producer_batch(
+                    &[record(&format!("value-{i}"), i)],
+                    producer(3, 0, sequence, false),
+                )
+                .unwrap();
+            }
+            log.append_producer_batch(&[record("open", 20)], producer(4, 0, 0, true))
+                .unwrap();
+            assert!(log.segment_count() > 1);
+        }
+
+        // Recovered from the last snapshot plus the records written after it
+        let log = PartitionLog::open(0, dir.path(), options(256)).unwrap();
+        assert_eq!(
+            log.append_producer_batch(&[record("dup", 19)], producer(3, 0, 19, false))
+                .unwrap(),
+            19
+        );
+        assert_eq!(
+            log.append_producer_batch(&[record("gap", 21)], producer(3, 0, 25, false)),
+            Err(ProducerError::OutOfOrderSequence)
+        );
+        assert_eq!(log.last_stable_offset(), 20);
+    }
+}
@@ -0,0 +1,312 @@
+//! Per-partition producer state
+//!
+//! For every producer that wrote to the partition this tracks its current
+//! epoch, the sequence ranges of its most recent writes and the first offset
+//! of its open transaction. Sequence ranges let a retried batch be answered
+//! with its original offset instead of being appended twice, and reject
+//! batches that skip ahead. Open transactions bound the last stable offset;
+//! aborted ones are indexed so `read_committed` readers can skip their
+//! records.
+//!
+//! The state is snapshotted to `producer.snapshot` when a segment rolls and
+//! on flush, and rebuilt on open by replaying records written after the
+//! snapshot offset.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use lumadb_common::error::Result;
+use lumadb_common::types::{ControlRecord, Offset, ProducerInfo, Record};
+
+use crate::producer::ProducerError;
+
+/// Producer state snapshot file inside each partition directory
+const SNAPSHOT_FILE: &str = "producer.snapshot";
+
+/// Sequence ranges remembered per producer for duplicate detection, matching
+/// the in-flight request limit of idempotent Kafka producers
+const MAX_BATCHES_PER_PRODUCER: usize = 5;
+
+/// A run of records with consecutive sequence numbers and offsets
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct BatchMetadata {
+    first_sequence: i32,
+    last_sequence: i32,
+    first_offset: Offset,
+}
+
+impl BatchMetadata {
+    fn last_offset(&self) -> Offset {
+        self.first_offset + Offset::from(self.last_sequence - self.first_sequence)
+    }
+}
+
+/// State of one producer in this partition
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ProducerEntry {
+    epoch: i16,
+    /// Most recent writes, oldest first
+    batches: VecDeque<BatchMetadata>,
+    /// First offset of the producer's open transaction
+    txn_first_offset: Option<Offset>,
+    /// Timestamp of the producer's last write
+    last_timestamp: i64,
+}
+
+impl ProducerEntry {
+    fn new(epoch: i16) -> Self {
+        Self {
+            epoch,
+            batches: VecDeque::new(),
+            txn_first_offset: None,
+            last_timestamp: i64::MIN,
+        }
+    }
+
+    /// Move to a newer epoch, which restarts sequence numbering
+    fn advance_epoch(&mut self, epoch: i16) {
+        if epoch > self.epoch {
+            self.epoch = epoch;
+            self.batches.clear();
+        }
+    }
+
+    fn next_sequence(&self) -> i32 {
+        self.batches.back().map_or(0, |b| {
+            if b.last_sequence == i32::MAX {
+                0
+            } else {
+                b.last_sequence + 1
+            }
+        })
+    }
+}
+
+/// A transaction that was aborted, as reported to `read_committed` readers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: Offset,
+    /// Offset of the abort marker
+    pub last_offset: Offset,
+}
+
+impl AbortedTransaction {
+    /// Whether the record at `offset` was written by this transaction
+    pub fn covers(&self, offset: Offset, record: &Record) -> bool {
+        record
+            .producer
+            .is_some_and(|p| p.transactional && p.producer_id == self.producer_id)
+            && (self.first_offset..=self.last_offset).contains(&offset)
+    }
+}
+
+/// Producer state of a partition
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ProducerStateManager {
+    producers: HashMap<i64, ProducerEntry>,
+    /// Aborted transactions in marker order
+    aborted: Vec<AbortedTransaction>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    /// Log end offset the state covers
+    offset: Offset,
+    state: ProducerStateManager,
+}
+
+impl ProducerStateManager {
+    /// Validate a batch from an idempotent producer before it is appended
+    ///
+    /// Returns the offset of the original write when the batch is a retry
+    /// of records already in the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is unknown or fenced, the sequence
+    /// number does not follow the producer's last batch, or a
+    /// non-transactional batch arrives while the producer has a transaction
+    /// open.
+    pub fn check_batch(
+        &self,
+        producer: &ProducerInfo,
+        count: usize,
+    ) -> std::result::Result<Option<Offset>, ProducerError> {
+        let Some(entry) = self.producers.get(&producer.producer_id) else {
+            return if producer.sequence == 0 {
+                Ok(None)
+            } else {
+                Err(ProducerError::UnknownProducerId)
+            };
+        };
+        if producer.producer_epoch < entry.epoch {
+            return Err(ProducerError::InvalidProducerEpoch);
+        }
+        if producer.producer_epoch > entry.epoch {
+            return if producer.sequence == 0 {
+                Ok(None)
+            } else {
+                Err(ProducerError::OutOfOrderSequence)
+            };
+        }
+        if !producer.transactional && entry.txn_first_offset.is_some() {
+            return Err(ProducerError::InvalidTxnState);
+        }
+
+        let last_sequence =
+            i64::from(producer.sequence) + i64::try_from(count).unwrap_or(i64::MAX) - 1;
+        for batch in &entry.batches {
+            if producer.sequence >= batch.first_sequence
+                && last_sequence <= i64::from(batch.last_sequence)
+            {
+                let delta = Offset::from(producer.sequence - batch.first_sequence);
+                return Ok(Some(batch.first_offset + delta));
+            }
+        }
+        if producer.sequence != entry.next_sequence() {
+            return Err(ProducerError::OutOfOrderSequence);
+        }
+        Ok(None)
+    }
+
+    /// Validate a transaction marker before it is appended
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer has been fenced by a newer epoch.
+    pub fn check_marker(
+        &self,
+        producer_id: i64,
+        producer_epoch: i16,
+    ) -> std::result::Result<(), ProducerError> {
+        match self.producers.get(&producer_id) {
+            Some(entry) if producer_epoch < entry.epoch => Err(ProducerError::InvalidProducerEpoch),
+            _ => Ok(()),
+        }
+    }
+
+    /// Update the state with a record appended (or replayed) at `offset`
+    pub fn record(&mut self, offset: Offset, record: &Record) {
+        let Some(producer) = record.producer else {
+            return;
+        };
+        let entry = self
+            .producers
+            .entry(producer.producer_id)
+            .or_insert_with(|| ProducerEntry::new(producer.producer_epoch));
+        entry.advance_epoch(producer.producer_epoch);
+        entry.last_timestamp = entry.last_timestamp.max(record.timestamp);
+
+        if let Some(control) = record.control {
+            if let Some(first_offset) = entry.txn_first_offset.take() {
+                if control == ControlRecord::Abort {
+                    self.aborted.push(AbortedTransaction {
+                        producer_id: producer.producer_id,
+                        first_offset,
+                        last_offset: offset,
+                    });
+                }
+            }
+            return;
+        }
+
+        if producer.transactional && entry.txn_first_offset.is_none() {
+            entry.txn_first_offset = Some(offset);
+        }
+        match entry.batches.back_mut() {
+            Some(last)
+                if last.last_offset() + 1 == offset
+                    && last.last_sequence.checked_add(1) == Some(producer.sequence) =>
+            {
+                last.last_sequence = producer.sequence;
+            }
+            _ => {
+                entry.batches.push_back(BatchMetadata {
+                    first_sequence: producer.sequence,
+                    last_sequence: producer.sequence,
+                    first_offset: offset,
+                });
+                if entry.batches.len() > MAX_BATCHES_PER_PRODUCER {
+                    entry.batches.pop_front();
+                }
+            }
+        }
+    }
+
+    /// First offset that may still belong to an open transaction, or
+    /// `log_end_offset` when none is open
+    #[must_use]
+    pub fn last_stable_offset(&self, log_end_offset: Offset) -> Offset {
+        self.producers
+            .values()
+            .filter_map(|e| e.txn_first_offset)
+            .min()
+            .unwrap_or(log_end_offset)
+    }
+
+    /// Aborted transactions with records in `from..to`
+    #[must_use]
+    pub fn aborted_transactions(&self, from: Offset, to: Offset) -> Vec<AbortedTransaction> {
+        self.aborted
+            .iter()
+            .filter(|a| a.last_offset >= from && a.first_offset < to)
+            .copied()
+            .collect()
+    }
+
+    /// Whether the record at `offset` belongs to an aborted transaction
+    pub fn is_aborted(&self, offset: Offset, record: &Record) -> bool {
+        record.producer.is_some_and(|p| p.transactional)
+            && self.aborted.iter().any(|a| a.covers(offset, record))
+    }
+
+    /// Forget producers idle since before `cutoff` and aborted transactions
+    /// that retention has removed, returning the number of producers removed
+    pub fn expire(&mut self, cutoff: i64, log_start_offset: Offset) -> usize {
+        let before = self.producers.len();
+        self.producers
+            .retain(|_, e| e.txn_first_offset.is_some() || e.last_timestamp >= cutoff);
+        self.aborted.retain(|a| a.last_offset >= log_start_offset);
+        before - self.producers.len()
+    }
+
+    /// Load the snapshot in `dir`, returning the offset it covers
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot cannot be read or parsed.
+    pub fn load(dir: &Path) -> Result<Option<(Offset, Self)>> {
+        let path = dir.join(SNAPSHOT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let snapshot: Snapshot = serde_json::from_slice(&fs::read(&path)?)?;
+        Ok(Some((snapshot.offset, snapshot.state)))
+    }
+
+    /// Write a snapshot of the state as of log end offset `offset`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot cannot be written.
+    pub fn save(&self, dir: &Path, offset: Offset) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct SnapshotRef<'a> {
+            offset: Offset,
+            state: &'a ProducerStateManager,
+        }
+
+        let tmp = dir.join(format!("{SNAPSHOT_FILE}.tmp"));
+        fs::write(
+            &tmp,
+            serde_json::to_vec(&SnapshotRef {
+                offset,
+                state: self,
+            })?,
+        )?;
+        fs::rename(&tmp, dir.join(SNAPSHOT_FILE))?;
+        Ok(())
+    }
+}
@@ -1,89 +1,552 @@
 //! Log segment implementation
+//!
+//! A segment covers a contiguous offset range starting at its base offset
+//! and is stored as three files named after the zero-padded base offset:
+//! - `.log`: record frames `[offset i64][timestamp i64][len u32][crc32 u32][payload]`
+//! - `.index`: sparse offset index entries `[relative offset u32][position u32]`
+//! - `.timeindex`: sparse time index entries `[max timestamp i64][relative offset u32]`
+//!
+//! Index entries are written every `index_interval_bytes` of log data, so a
+//! lookup lands on a nearby frame and scans forward. Reads go through a
+//! read-only memory map that is refreshed when the file has grown.
 
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
 
-use parking_lot::RwLock;
+use memmap2::Mmap;
+use parking_lot::{Mutex, RwLock};
+use tracing::warn;
 
-use lumadb_common::error::Result;
+use lumadb_common::error::{Error, Result};
 use lumadb_common::types::Offset;
 
+/// Size of a record frame header
+const FRAME_HEADER_SIZE: usize = 24;
+/// Size of an offset index entry
+const INDEX_ENTRY_SIZE: usize = 8;
+/// Size of a time index entry
+const TIME_INDEX_ENTRY_SIZE: usize = 12;
+
+/// Offset index entry
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry {
+    relative_offset: u32,
+    position: u32,
+}
+
+/// Time index entry: the largest timestamp seen up to `relative_offset`
+#[derive(Debug, Clone, Copy)]
+struct TimeIndexEntry {
+    timestamp: i64,
+    relative_offset: u32,
+}
+
+/// Append handles, held only by the writer
+struct SegmentWriter {
+    log: File,
+    index: File,
+    time_index: File,
+    /// Log bytes written since the last offset index entry
+    bytes_since_index: usize,
+    /// Timestamp of the last time index entry
+    last_time_index_ts: i64,
+}
+
+/// A decoded frame borrowed from the mapped log
+struct Frame<'a> {
+    offset: Offset,
+    timestamp: i64,
+    payload: &'a [u8],
+    /// Total frame length including the header
+    len: usize,
+}
+
 /// A segment of the partition log
 pub struct Segment {
     /// Base offset of this segment
     base_offset: Offset,
-    /// In-memory index: offset -> position in data
-    index: RwLock<BTreeMap<Offset, usize>>,
-    /// Data storage (in production this would be memory-mapped files)
-    data: RwLock<Vec<u8>>,
-    /// Current size
-    size: AtomicUsize,
+    log_path: PathBuf,
+    index_path: PathBuf,
+    time_index_path: PathBuf,
+    index_interval_bytes: usize,
+    writer: Mutex<SegmentWriter>,
+    index: RwLock<Vec<IndexEntry>>,
+    time_index: RwLock<Vec<TimeIndexEntry>>,
+    /// Read-only mapping of the log, replaced when the file outgrows it
+    mmap: RwLock<Option<Arc<Mmap>>>,
+    /// Bytes of complete frames in the log
+    size: AtomicU64,
+    /// Offset of the last record (`base_offset - 1` when empty)
+    last_offset: AtomicI64,
+    /// Timestamp of the first record (`i64::MIN` when empty)
+    first_timestamp: AtomicI64,
+    /// Largest record timestamp (`i64::MIN` when empty)
+    max_timestamp: AtomicI64,
 }
 
 impl Segment {
-    /// Create a new segment
-    pub fn new(base_offset: Offset) -> Self {
-        Self {
+    /// Create a new, empty segment in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the segment files cannot be created.
+    pub fn create(dir: &Path, base_offset: Offset, index_interval_bytes: usize) -> Result<Self> {
+        let segment = Self::open_files(dir, base_offset, index_interval_bytes, true)?;
+        segment.writer.lock().log.sync_all()?;
+        Ok(segment)
+    }
+
+    /// Open an existing segment, validating frames after the last index
+    /// entry and truncating a torn or corrupt tail
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the segment files cannot be opened or repaired.
+    pub fn open(dir: &Path, base_offset: Offset, index_interval_bytes: usize) -> Result<Self> {
+        let segment = Self::open_files(dir, base_offset, index_interval_bytes, false)?;
+        segment.recover()?;
+        Ok(segment)
+    }
+
+    fn open_files(
+        dir: &Path,
+        base_offset: Offset,
+        index_interval_bytes: usize,
+        truncate: bool,
+    ) -> Result<Self> {
+        let log_path = segment_file(dir, base_offset, "log");
+        let index_path = segment_file(dir, base_offset, "index");
+        let time_index_path = segment_file(dir, base_offset, "timeindex");
+
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(path)
+                .and_then(|f| {
+                    if truncate {
+                        f.set_len(0)?;
+                    }
+                    Ok(f)
+                })
+        };
+
+        Ok(Self {
             base_offset,
-            index: RwLock::new(BTreeMap::new()),
-            data: RwLock::new(Vec::new()),
-            size: AtomicUsize::new(0),
+            writer: Mutex::new(SegmentWriter {
+                log: open(&log_path)?,
+                index: open(&index_path)?,
+                time_index: open(&time_index_path)?,
+                bytes_since_index: 0,
+                last_time_index_ts: i64::MIN,
+            }),
+            log_path,
+            index_path,
+            time_index_path,
+            index_interval_bytes,
+            index: RwLock::new(Vec::new()),
+            time_index: RwLock::new(Vec::new()),
+            mmap: RwLock::new(None),
+            size: AtomicU64::new(0),
+            last_offset: AtomicI64::new(base_offset - 1),
+            first_timestamp: AtomicI64::new(i64::MIN),
+            max_timestamp: AtomicI64::new(i64::MIN),
+        })
+    }
+
+    /// Rebuild in-memory state from the files on disk
+    fn recover(&self) -> Result<()> {
+        let file_len = fs::metadata(&self.log_path)?.len();
+        let data = if file_len > 0 {
+            let file = File::open(&self.log_path)?;
+            // SAFETY: the mapping is dropped before the log is truncated and
+            // no other handle writes to the file during recovery
+            Some(unsafe { Mmap::map(&file)? })
+        } else {
+            None
+        };
+        let bytes: &[u8] = data.as_deref().unwrap_or(&[]);
+
+        // Keep the strictly increasing prefix of the index whose last entry
+        // still lands on a valid frame; the tail is rebuilt by the scan below
+        let index_raw = fs::read(&self.index_path)?;
+        let mut index: Vec<IndexEntry> = index_raw
+            .chunks_exact(INDEX_ENTRY_SIZE)
+            .map(decode_index_entry)
+            .collect();
+        let increasing = index
+            .windows(2)
+            .position(|w| {
+                w[1].relative_offset <= w[0].relative_offset || w[1].position <= w[0].position
+            })
+            .map_or(index.len(), |i| i + 1);
+        index.truncate(increasing);
+        while let Some(last) = index.last() {
+            let expected = self.base_offset + i64::from(last.relative_offset);
+            if decode_frame(bytes, last.position as usize).is_some_and(|f| f.offset == expected) {
+                break;
+            }
+            index.pop();
+        }
+
+        let time_index_raw = fs::read(&self.time_index_path)?;
+        let mut time_index: Vec<TimeIndexEntry> = time_index_raw
+            .chunks_exact(TIME_INDEX_ENTRY_SIZE)
+            .map(decode_time_index_entry)
+            .collect();
+        let last_indexed = index.last().map(|e| e.relative_offset);
+        time_index.retain(|e| last_indexed.is_some_and(|last| e.relative_offset <= last));
+
+        // Scan forward from the last indexed frame. Time index entries hold
+        // the running maximum, so the last one covers every frame before it.
+        let mut position = index.last().map_or(0, |e| e.position as usize);
+        let mut max_timestamp = time_index.last().map_or(i64::MIN, |e| e.timestamp);
+        let mut last_offset = self.base_offset - 1;
+        let mut bytes_since_index = 0usize;
+        while let Some(frame) = decode_frame(bytes, position) {
+            if frame.offset <= last_offset {
+                break;
+            }
+            let relative_offset = self.relative(frame.offset)?;
+            let at_index_point = index.is_empty()
+                || (index
+                    .last()
+                    .is_some_and(|e| e.relative_offset < relative_offset)
+                    && bytes_since_index >= self.index_interval_bytes);
+            if at_index_point {
+                index.push(IndexEntry {
+                    relative_offset,
+                    position: to_position(position)?,
+                });
+                bytes_since_index = 0;
+            }
+            max_timestamp = max_timestamp.max(frame.timestamp);
+            if at_index_point
+                && time_index
+                    .last()
+                    .map_or(true, |e| e.timestamp < max_timestamp)
+            {
+                time_index.push(TimeIndexEntry {
+                    timestamp: max_timestamp,
+                    relative_offset,
+                });
+            }
+            bytes_since_index += frame.len;
+            last_offset = frame.offset;
+            position += frame.len;
         }
+        let first_timestamp = decode_frame(bytes, 0).map_or(i64::MIN, |f| f.timestamp);
+        drop(data);
+
+        let mut writer = self.writer.lock();
+        if (position as u64) < file_len {
+            warn!(
+                "Truncating {:?} from {} to {} bytes after an incomplete write",
+                self.log_path, file_len, position
+            );
+            writer.log.set_len(position as u64)?;
+            writer.log.sync_all()?;
+        }
+
+        // Persist the repaired indexes
+        let index_bytes: Vec<u8> = index.iter().flat_map(|e| encode_index_entry(*e)).collect();
+        rewrite(&mut writer.index, &index_raw, &index_bytes)?;
+        let time_index_bytes: Vec<u8> = time_index
+            .iter()
+            .flat_map(|e| encode_time_index_entry(*e))
+            .collect();
+        rewrite(&mut writer.time_index, &time_index_raw, &time_index_bytes)?;
+        writer.bytes_since_index = bytes_since_index;
+        writer.last_time_index_ts = time_index.last().map_or(i64::MIN, |e| e.timestamp);
+
+        *self.index.write() = index;
+        *self.time_index.write() = time_index;
+        self.size.store(position as u64, Ordering::SeqCst);
+        self.last_offset.store(last_offset, Ordering::SeqCst);
+        self.first_timestamp
+            .store(first_timestamp, Ordering::SeqCst);
+        self.max_timestamp.store(max_timestamp, Ordering::SeqCst);
+        Ok(())
     }
 
     /// Append data at offset
-    pub fn append(&self, offset: Offset, data: &[u8]) -> Result<()> {
-        let mut storage = self.data.write();
-        let position = storage.len();
+    ///
+    /// Offsets must be strictly increasing within a segment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `offset` does not follow the last record or the
+    /// write fails.
+    pub fn append(&self, offset: Offset, timestamp: i64, data: &[u8]) -> Result<()> {
+        let relative_offset = self.relative(offset)?;
+        let len = u32::try_from(data.len())
+            .map_err(|_| Error::Internal("record exceeds 4GB".to_string()))?;
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + data.len());
+        frame.extend_from_slice(&offset.to_le_bytes());
+        frame.extend_from_slice(&timestamp.to_le_bytes());
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(&frame_crc(offset, timestamp, data).to_le_bytes());
+        frame.extend_from_slice(data);
+
+        let mut writer = self.writer.lock();
+        if offset <= self.last_offset.load(Ordering::SeqCst) {
+            return Err(Error::Internal(format!(
+                "offset {offset} is not past the end of segment at base {}",
+                self.base_offset
+            )));
+        }
+        let position = self.size.load(Ordering::SeqCst);
+        let entry_position = to_position(usize::try_from(position).unwrap_or(usize::MAX))?;
+        writer.log.write_all(&frame)?;
 
-        // Write length prefix + data
-        let len = data.len() as u32;
-        storage.extend_from_slice(&len.to_le_bytes());
-        storage.extend_from_slice(data);
+        let is_first = position == 0;
+        let max_timestamp = self.max_timestamp.load(Ordering::SeqCst).max(timestamp);
+        if is_first || writer.bytes_since_index >= self.index_interval_bytes {
+            let entry = IndexEntry {
+                relative_offset,
+                position: entry_position,
+            };
+            writer.index.write_all(&encode_index_entry(entry))?;
+            self.index.write().push(entry);
 
-        // Update index
-        self.index.write().insert(offset, position);
+            if max_timestamp > writer.last_time_index_ts {
+                let entry = TimeIndexEntry {
+                    timestamp: max_timestamp,
+                    relative_offset,
+                };
+                writer
+                    .time_index
+                    .write_all(&encode_time_index_entry(entry))?;
+                self.time_index.write().push(entry);
+                writer.last_time_index_ts = max_timestamp;
+            }
+            writer.bytes_since_index = 0;
+        }
+        writer.bytes_since_index += frame.len();
 
-        // Update size
-        self.size.fetch_add(4 + data.len(), Ordering::SeqCst);
+        if is_first {
+            self.first_timestamp.store(timestamp, Ordering::SeqCst);
+        }
+        self.max_timestamp.store(max_timestamp, Ordering::SeqCst);
+        self.last_offset.store(offset, Ordering::SeqCst);
+        self.size
+            .store(position + frame.len() as u64, Ordering::SeqCst);
 
         Ok(())
     }
 
-    /// Read entries starting from offset
-    pub fn read_from(&self, start_offset: Offset) -> Result<Vec<(Offset, Vec<u8>)>> {
+    /// Read entries starting from offset, up to a record count and a payload
+    /// size limit (at least one entry is returned when available)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be read.
+    pub fn read_from(
+        &self,
+        start_offset: Offset,
+        max_records: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<(Offset, Vec<u8>)>> {
         let mut results = Vec::new();
-        let index = self.index.read();
-        let data = self.data.read();
+        let mut read_bytes = 0usize;
+        if max_records == 0 || start_offset > self.last_offset.load(Ordering::SeqCst) {
+            return Ok(results);
+        }
+        let size = self.size();
+        let Some(map) = self.mapped(size)? else {
+            return Ok(results);
+        };
+        let bytes = &map[..size];
 
-        for (&offset, &position) in index.range(start_offset..) {
-            if position + 4 > data.len() {
-                break;
+        let mut position = self.position_for(start_offset.max(self.base_offset));
+        while let Some(frame) = decode_frame(bytes, position) {
+            if frame.offset >= start_offset {
+                if !results.is_empty() && read_bytes + frame.payload.len() > max_bytes {
+                    break;
+                }
+                read_bytes += frame.payload.len();
+                results.push((frame.offset, frame.payload.to_vec()));
+                if results.len() >= max_records {
+                    break;
+                }
             }
+            position += frame.len;
+        }
 
-            // Read length prefix
-            let len = u32::from_le_bytes([
-                data[position],
-                data[position + 1],
-                data[position + 2],
-                data[position + 3],
-            ]) as usize;
+        Ok(results)
+    }
 
-            if position + 4 + len > data.len() {
-                break;
+    /// First offset whose record timestamp is at or after `timestamp`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be read.
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> Result<Option<Offset>> {
+        if self.is_empty() || self.max_timestamp() < timestamp {
+            return Ok(None);
+        }
+        let size = self.size();
+        let Some(map) = self.mapped(size)? else {
+            return Ok(None);
+        };
+        let bytes = &map[..size];
+
+        // Start at the last indexed point known to be before the target
+        let start = self
+            .time_index
+            .read()
+            .iter()
+            .take_while(|e| e.timestamp < timestamp)
+            .last()
+            .map_or(self.base_offset, |e| {
+                self.base_offset + i64::from(e.relative_offset)
+            });
+
+        let mut position = self.position_for(start);
+        while let Some(frame) = decode_frame(bytes, position) {
+            if frame.timestamp >= timestamp {
+                return Ok(Some(frame.offset));
             }
+            position += frame.len;
+        }
+        Ok(None)
+    }
+
+    /// Visit every record in offset order with its timestamp and payload
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be read or `f` fails.
+    pub fn scan(&self, mut f: impl FnMut(Offset, i64, &[u8]) -> Result<()>) -> Result<()> {
+        let size = self.size();
+        let Some(map) = self.mapped(size)? else {
+            return Ok(());
+        };
+        let bytes = &map[..size];
 
-            // Read data
-            let entry_data = data[position + 4..position + 4 + len].to_vec();
-            results.push((offset, entry_data));
+        let mut position = 0;
+        while let Some(frame) = decode_frame(bytes, position) {
+            f(frame.offset, frame.timestamp, frame.payload)?;
+            position += frame.len;
         }
+        Ok(())
+    }
 
-        Ok(results)
+    /// Replace the files of the segment at `base_offset` in `dir` with those
+    /// of a segment written to `from`
+    ///
+    /// The old indexes are removed before the log is renamed into place, so
+    /// a crash part way leaves either log with indexes that recovery rebuilds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be removed or renamed.
+    pub fn replace_files(from: &Path, dir: &Path, base_offset: Offset) -> Result<()> {
+        for ext in ["index", "timeindex"] {
+            match fs::remove_file(segment_file(dir, base_offset, ext)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        for ext in ["log", "index", "timeindex"] {
+            fs::rename(
+                segment_file(from, base_offset, ext),
+                segment_file(dir, base_offset, ext),
+            )?;
+        }
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Byte position of the indexed frame at or before `offset`
+    fn position_for(&self, offset: Offset) -> usize {
+        let Ok(relative) = self.relative(offset) else {
+            return 0;
+        };
+        let index = self.index.read();
+        let slot = index.partition_point(|e| e.relative_offset <= relative);
+        slot.checked_sub(1)
+            .map_or(0, |i| index[i].position as usize)
+    }
+
+    /// Mapping covering at least `size` bytes
+    fn mapped(&self, size: usize) -> Result<Option<Arc<Mmap>>> {
+        if size == 0 {
+            return Ok(None);
+        }
+        if let Some(ref map) = *self.mmap.read() {
+            if map.len() >= size {
+                return Ok(Some(map.clone()));
+            }
+        }
+
+        let mut slot = self.mmap.write();
+        if let Some(ref map) = *slot {
+            if map.len() >= size {
+                return Ok(Some(map.clone()));
+            }
+        }
+        let file = File::open(&self.log_path)?;
+        // SAFETY: frames below `size` are complete and never rewritten; the
+        // file is only truncated during recovery, before any reader exists
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        *slot = Some(map.clone());
+        Ok(Some(map))
+    }
+
+    fn relative(&self, offset: Offset) -> Result<u32> {
+        u32::try_from(offset - self.base_offset).map_err(|_| {
+            Error::Internal(format!(
+                "offset {offset} outside segment at base {}",
+                self.base_offset
+            ))
+        })
+    }
+
+    /// Flush written data to disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files cannot be synced.
+    pub fn flush(&self) -> Result<()> {
+        let writer = self.writer.lock();
+        writer.log.sync_data()?;
+        writer.index.sync_data()?;
+        writer.time_index.sync_data()?;
+        Ok(())
+    }
+
+    /// Remove the segment's files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files cannot be removed.
+    pub fn delete(&self) -> Result<()> {
+        *self.mmap.write() = None;
+        for path in [&self.log_path, &self.index_path, &self.time_index_path] {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
     }
 
     /// Get segment size
     pub fn size(&self) -> usize {
-        self.size.load(Ordering::SeqCst)
+        usize::try_from(self.size.load(Ordering::SeqCst)).unwrap_or(usize::MAX)
+    }
+
+    /// Whether the segment holds no records
+    pub fn is_empty(&self) -> bool {
+        self.size.load(Ordering::SeqCst) == 0
     }
 
     /// Get base offset
@@ -93,6 +556,95 @@ impl Segment {
 
     /// Get highest offset in segment
     pub fn highest_offset(&self) -> Option<Offset> {
-        self.index.read().keys().last().copied()
+        let last = self.last_offset.load(Ordering::SeqCst);
+        (last >= self.base_offset).then_some(last)
+    }
+
+    /// Timestamp of the first record, if any
+    pub fn first_timestamp(&self) -> Option<i64> {
+        let ts = self.first_timestamp.load(Ordering::SeqCst);
+        (ts != i64::MIN).then_some(ts)
+    }
+
+    /// Largest record timestamp (`i64::MIN` when empty)
+    pub fn max_timestamp(&self) -> i64 {
+        self.max_timestamp.load(Ordering::SeqCst)
+    }
+}
+
+/// Path of one of a segment's files
+fn segment_file(dir: &Path, base_offset: Offset, ext: &str) -> PathBuf {
+    dir.join(format!("{base_offset:020}.{ext}"))
+}
+
+/// Decode the frame at `position`, or `None` at the end of valid data
+fn decode_frame(bytes: &[u8], position: usize) -> Option<Frame<'_>> {
+    let header = bytes.get(position..position + FRAME_HEADER_SIZE)?;
+    let offset = i64::from_le_bytes(header[0..8].try_into().ok()?);
+    let timestamp = i64::from_le_bytes(header[8..16].try_into().ok()?);
+    let len = u32::from_le_bytes(header[16..20].try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(header[20..24].try_into().ok()?);
+
+    let start = position + FRAME_HEADER_SIZE;
+    let payload = bytes.get(start..start.checked_add(len)?)?;
+    if frame_crc(offset, timestamp, payload) != crc {
+        return None;
+    }
+    Some(Frame {
+        offset,
+        timestamp,
+        payload,
+        len: FRAME_HEADER_SIZE + len,
+    })
+}
+
+fn frame_crc(offset: Offset, timestamp: i64, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&offset.to_le_bytes());
+    hasher.update(&timestamp.to_le_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+fn to_position(position: usize) -> Result<u32> {
+    u32::try_from(position).map_err(|_| Error::Internal("segment exceeds 4GB".to_string()))
+}
+
+fn encode_index_entry(entry: IndexEntry) -> [u8; INDEX_ENTRY_SIZE] {
+    let mut buf = [0u8; INDEX_ENTRY_SIZE];
+    buf[..4].copy_from_slice(&entry.relative_offset.to_le_bytes());
+    buf[4..].copy_from_slice(&entry.position.to_le_bytes());
+    buf
+}
+
+fn decode_index_entry(c: &[u8]) -> IndexEntry {
+    IndexEntry {
+        relative_offset: u32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+        position: u32::from_le_bytes([c[4], c[5], c[6], c[7]]),
+    }
+}
+
+fn encode_time_index_entry(entry: TimeIndexEntry) -> [u8; TIME_INDEX_ENTRY_SIZE] {
+    let mut buf = [0u8; TIME_INDEX_ENTRY_SIZE];
+    buf[..8].copy_from_slice(&entry.timestamp.to_le_bytes());
+    buf[8..].copy_from_slice(&entry.relative_offset.to_le_bytes());
+    buf
+}
+
+fn decode_time_index_entry(c: &[u8]) -> TimeIndexEntry {
+    TimeIndexEntry {
+        timestamp: i64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]),
+        relative_offset: u32::from_le_bytes([c[8], c[9], c[10], c[11]]),
+    }
+}
+
+/// Replace an append-mode file's contents if they changed
+fn rewrite(file: &mut File, original: &[u8], bytes: &[u8]) -> Result<()> {
+    if original == bytes {
+        return Ok(());
     }
+    file.set_len(0)?;
+    file.write_all(bytes)?;
+    file.sync_data()?;
+    Ok(())
 }
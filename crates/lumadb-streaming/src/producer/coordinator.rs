@@ -0,0 +1,540 @@
+//! Transaction coordinator
+//!
+//! Owns producer id allocation and the state of every transactional id. The
+//! state is small and rewritten to `transactions.json` on each change, so a
+//! restart resumes with the same producer ids and epochs, and finishes any
+//! transaction that was between `PrepareCommit`/`PrepareAbort` and
+//! completion when the process stopped.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use tracing::info;
+
+use lumadb_common::error::Result;
+use lumadb_common::types::{Offset, PartitionId};
+
+use super::{ProducerError, ProducerIdAndEpoch};
+
+/// Coordinator state file inside the streaming data directory
+const STATE_FILE: &str = "transactions.json";
+
+/// Offsets a consumer group commits within a transaction: (topic, partition,
+/// offset)
+pub type GroupOffsets = Vec<(String, PartitionId, Offset)>;
+
+/// Lifecycle of a transactional id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TransactionState {
+    /// No transaction has started since the last one completed
+    Empty,
+    /// Partitions or offsets have been added
+    Ongoing,
+    /// Commit markers are being written
+    PrepareCommit,
+    /// Abort markers are being written
+    PrepareAbort,
+    /// The last transaction committed
+    CompleteCommit,
+    /// The last transaction aborted
+    CompleteAbort,
+}
+
+/// State of one transactional id
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TransactionMetadata {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub timeout_ms: i32,
+    pub state: TransactionState,
+    /// Partitions written in the current transaction
+    pub partitions: BTreeSet<(String, PartitionId)>,
+    /// Consumer groups whose offsets are part of the transaction
+    pub groups: BTreeSet<String>,
+    /// Offsets to commit per group when the transaction commits
+    pub offsets: BTreeMap<String, GroupOffsets>,
+    /// Start of the current transaction in milliseconds
+    pub started_ms: i64,
+}
+
+/// A transaction whose markers must be written
+#[derive(Debug, Clone)]
+pub struct TransactionCompletion {
+    pub transactional_id: String,
+    /// Producer id and epoch to write the markers with
+    pub producer: ProducerIdAndEpoch,
+    pub commit: bool,
+    pub partitions: Vec<(String, PartitionId)>,
+    /// Group offsets to commit along with the transaction
+    pub offsets: Vec<(String, GroupOffsets)>,
+}
+
+/// Outcome of `init_producer_id` for a transactional id
+#[derive(Debug, Clone)]
+pub enum InitProducer {
+    /// The producer may start using this id and epoch
+    Ready(ProducerIdAndEpoch),
+    /// An open transaction must be aborted first; the call is then retried
+    Abort(TransactionCompletion),
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct CoordinatorState {
+    next_producer_id: i64,
+    transactions: HashMap<String, TransactionMetadata>,
+}
+
+/// Allocates producer ids and tracks transactions
+pub struct TransactionCoordinator {
+    path: PathBuf,
+    /// Largest transaction timeout a producer may request
+    max_timeout_ms: u64,
+    state: Mutex<CoordinatorState>,
+}
+
+impl TransactionCoordinator {
+    /// Open the coordinator state stored in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file cannot be read or parsed.
+    pub fn open(dir: &Path, max_timeout_ms: u64) -> Result<Self> {
+        let path = dir.join(STATE_FILE);
+        let state = if path.exists() {
+            let state: CoordinatorState = serde_json::from_slice(&fs::read(&path)?)?;
+            info!(
+                "Recovered {} transactional ids, next producer id {}",
+                state.transactions.len(),
+                state.next_producer_id
+            );
+            state
+        } else {
+            CoordinatorState::default()
+        };
+
+        Ok(Self {
+            path,
+            max_timeout_ms,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Allocate a producer id for an idempotent producer without a
+    /// transactional id
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator state cannot be persisted.
+    pub fn allocate_producer_id(&self) -> std::result::Result<ProducerIdAndEpoch, ProducerError> {
+        let mut state = self.state.lock();
+        let producer_id = next_producer_id(&mut state.next_producer_id);
+        self.persist(&state)?;
+        Ok(ProducerIdAndEpoch {
+            producer_id,
+            producer_epoch: 0,
+        })
+    }
+
+    /// Bump the epoch of an idempotent producer, or give it a new id once
+    /// the epoch is exhausted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a new producer id is needed and the coordinator
+    /// state cannot be persisted.
+    pub fn bump_epoch(
+        &self,
+        producer: ProducerIdAndEpoch,
+    ) -> std::result::Result<ProducerIdAndEpoch, ProducerError> {
+        if producer.producer_epoch < i16::MAX - 1 {
+            Ok(ProducerIdAndEpoch {
+                producer_id: producer.producer_id,
+                producer_epoch: producer.producer_epoch + 1,
+            })
+        } else {
+            self.allocate_producer_id()
+        }
+    }
+
+    /// Register a producer under a transactional id
+    ///
+    /// A new id starts at epoch 0; an existing one has its epoch bumped,
+    /// fencing any older producer instance. An open transaction is handed
+    /// back for abort first. `current`, when given, must match the
+    /// registered producer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the timeout is out of range, `current` is not the
+    /// registered producer, a previous transaction is still completing, or the
+    /// coordinator state cannot be persisted.
+    pub fn init_producer_id(
+        &self,
+        transactional_id: &str,
+        timeout_ms: i32,
+        current: Option<ProducerIdAndEpoch>,
+        now_ms: i64,
+    ) -> std::result::Result<InitProducer, ProducerError> {
+        if timeout_ms <= 0 || u64::try_from(timeout_ms).unwrap_or(u64::MAX) > self.max_timeout_ms {
+            return Err(ProducerError::InvalidTransactionTimeout);
+        }
+
+        let mut guard = self.state.lock();
+        let state = &mut *guard;
+        let producer = if let Some(txn) = state.transactions.get_mut(transactional_id) {
+            if current.is_some_and(|c| {
+                c.producer_id != txn.producer_id || c.producer_epoch != txn.producer_epoch
+            }) {
+                return Err(ProducerError::InvalidProducerEpoch);
+            }
+            match txn.state {
+                TransactionState::PrepareCommit | TransactionState::PrepareAbort => {
+                    return Err(ProducerError::ConcurrentTransactions);
+                }
+                TransactionState::Ongoing => {
+                    let completion = fence(transactional_id, txn);
+                    self.persist(state)?;
+                    return Ok(InitProducer::Abort(completion));
+                }
+                _ => {}
+            }
+
+            if txn.producer_epoch < i16::MAX - 1 {
+                txn.producer_epoch += 1;
+            } else {
+                txn.producer_id = next_producer_id(&mut state.next_producer_id);
+                txn.producer_epoch = 0;
+            }
+            txn.timeout_ms = timeout_ms;
+            txn.state = TransactionState::Empty;
+            ProducerIdAndEpoch {
+                producer_id: txn.producer_id,
+                producer_epoch: txn.producer_epoch,
+            }
+        } else {
+            let producer_id = next_producer_id(&mut state.next_producer_id);
+            state.transactions.insert(
+                transactional_id.to_string(),
+                TransactionMetadata {
+                    producer_id,
+                    producer_epoch: 0,
+                    timeout_ms,
+                    state: TransactionState::Empty,
+                    partitions: BTreeSet::new(),
+                    groups: BTreeSet::new(),
+                    offsets: BTreeMap::new(),
+                    started_ms: now_ms,
+                },
+            );
+            ProducerIdAndEpoch {
+                producer_id,
+                producer_epoch: 0,
+            }
+        };
+
+        self.persist(state)?;
+        Ok(InitProducer::Ready(producer))
+    }
+
+    /// Add partitions to the producer's transaction, starting one if needed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is not the current one for the
+    /// transactional id, its previous transaction is still completing, or the
+    /// coordinator state cannot be persisted.
+    pub fn add_partitions(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        partitions: &[(String, PartitionId)],
+        now_ms: i64,
+    ) -> std::result::Result<(), ProducerError> {
+        let mut state = self.state.lock();
+        let txn = begin(&mut state, transactional_id, producer, now_ms)?;
+        let added = partitions.iter().any(|p| txn.partitions.insert(p.clone()));
+        if added {
+            self.persist(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Make a consumer group's offsets part of the producer's transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is not the current one for the
+    /// transactional id, its previous transaction is still completing, or the
+    /// coordinator state cannot be persisted.
+    pub fn add_offsets(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        group_id: &str,
+        now_ms: i64,
+    ) -> std::result::Result<(), ProducerError> {
+        let mut state = self.state.lock();
+        let txn = begin(&mut state, transactional_id, producer, now_ms)?;
+        if txn.groups.insert(group_id.to_string()) {
+            self.persist(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Stage group offsets to be committed when the transaction commits
+    ///
+    /// The group must have been added with [`Self::add_offsets`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is not the current one for the
+    /// transactional id, the group is not in its transaction, or the
+    /// coordinator state cannot be persisted.
+    pub fn stage_offsets(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        group_id: &str,
+        offsets: &[(String, PartitionId, Offset)],
+    ) -> std::result::Result<(), ProducerError> {
+        let mut state = self.state.lock();
+        let txn = verify(&mut state, transactional_id, producer)?;
+        if txn.state != TransactionState::Ongoing || !txn.groups.contains(group_id) {
+            return Err(ProducerError::InvalidTxnState);
+        }
+
+        let staged = txn.offsets.entry(group_id.to_string()).or_default();
+        for (topic, partition, offset) in offsets {
+            staged.retain(|(t, p, _)| !(t == topic && p == partition));
+            staged.push((topic.clone(), *partition, *offset));
+        }
+        self.persist(&state)?;
+        Ok(())
+    }
+
+    /// Start committing or aborting the producer's transaction
+    ///
+    /// Returns the markers to write, or `None` when the same outcome was
+    /// already reached (a retried request).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is not the current one for the
+    /// transactional id, the transaction is in another state, or the
+    /// coordinator state cannot be persisted.
+    pub fn end_transaction(
+        &self,
+        transactional_id: &str,
+        producer: ProducerIdAndEpoch,
+        commit: bool,
+    ) -> std::result::Result<Option<TransactionCompletion>, ProducerError> {
+        let mut state = self.state.lock();
+        let txn = verify(&mut state, transactional_id, producer)?;
+        match (txn.state, commit) {
+            (TransactionState::Ongoing, _) => {
+                txn.state = if commit {
+                    TransactionState::PrepareCommit
+                } else {
+                    TransactionState::PrepareAbort
+                };
+                let completion = completion(transactional_id, txn);
+                self.persist(&state)?;
+                Ok(Some(completion))
+            }
+            (TransactionState::CompleteCommit, true) | (TransactionState::CompleteAbort, false) => {
+                Ok(None)
+            }
+            (TransactionState::PrepareCommit, true) | (TransactionState::PrepareAbort, false) => {
+                Err(ProducerError::ConcurrentTransactions)
+            }
+            _ => Err(ProducerError::InvalidTxnState),
+        }
+    }
+
+    /// Record that every marker of a transaction has been written
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator state cannot be persisted.
+    pub fn complete(&self, completion: &TransactionCompletion) -> Result<()> {
+        let mut state = self.state.lock();
+        let Some(txn) = state.transactions.get_mut(&completion.transactional_id) else {
+            return Ok(());
+        };
+        txn.state = match txn.state {
+            TransactionState::PrepareCommit => TransactionState::CompleteCommit,
+            TransactionState::PrepareAbort => TransactionState::CompleteAbort,
+            other => other,
+        };
+        txn.partitions.clear();
+        txn.groups.clear();
+        txn.offsets.clear();
+        self.persist(&state)
+    }
+
+    /// Check that a transactional batch belongs to an ongoing transaction
+    /// that includes the partition
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the producer is not the current one for the
+    /// transactional id or the partition is not in an ongoing transaction.
+    pub fn verify_partition(
+        &self,
+        producer: ProducerIdAndEpoch,
+        topic: &str,
+        partition: PartitionId,
+    ) -> std::result::Result<(), ProducerError> {
+        let state = self.state.lock();
+        let txn = state
+            .transactions
+            .values()
+            .find(|t| t.producer_id == producer.producer_id)
+            .ok_or(ProducerError::InvalidProducerIdMapping)?;
+        if producer.producer_epoch < txn.producer_epoch {
+            return Err(ProducerError::InvalidProducerEpoch);
+        }
+        if txn.state != TransactionState::Ongoing
+            || !txn.partitions.contains(&(topic.to_string(), partition))
+        {
+            return Err(ProducerError::InvalidTxnState);
+        }
+        Ok(())
+    }
+
+    /// Abort transactions open for longer than their timeout
+    ///
+    /// The producer's epoch is bumped so that it cannot keep writing to the
+    /// aborted transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the coordinator state cannot be persisted.
+    pub fn expire(&self, now_ms: i64) -> Result<Vec<TransactionCompletion>> {
+        let mut state = self.state.lock();
+        let mut expired = Vec::new();
+        for (id, txn) in &mut state.transactions {
+            if txn.state == TransactionState::Ongoing
+                && now_ms.saturating_sub(txn.started_ms) > i64::from(txn.timeout_ms)
+            {
+                info!(
+                    "Aborting transaction {} after its {} ms timeout",
+                    id, txn.timeout_ms
+                );
+                expired.push(fence(id, txn));
+            }
+        }
+        if !expired.is_empty() {
+            self.persist(&state)?;
+        }
+        Ok(expired)
+    }
+
+    /// Transactions that were preparing when the coordinator last stopped
+    pub fn pending(&self) -> Vec<TransactionCompletion> {
+        self.state
+            .lock()
+            .transactions
+            .iter()
+            .filter(|(_, t)| {
+                matches!(
+                    t.state,
+                    TransactionState::PrepareCommit | TransactionState::PrepareAbort
+                )
+            })
+            .map(|(id, t)| completion(id, t))
+            .collect()
+    }
+
+    /// State of a transactional id
+    pub fn describe(&self, transactional_id: &str) -> Option<TransactionMetadata> {
+        self.state
+            .lock()
+            .transactions
+            .get(transactional_id)
+            .cloned()
+    }
+
+    fn persist(&self, state: &CoordinatorState) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn next_producer_id(next: &mut i64) -> i64 {
+    let producer_id = *next;
+    *next += 1;
+    producer_id
+}
+
+/// Look up a transactional id and check the caller is its current producer
+fn verify<'a>(
+    state: &'a mut CoordinatorState,
+    transactional_id: &str,
+    producer: ProducerIdAndEpoch,
+) -> std::result::Result<&'a mut TransactionMetadata, ProducerError> {
+    let txn = state
+        .transactions
+        .get_mut(transactional_id)
+        .ok_or(ProducerError::InvalidProducerIdMapping)?;
+    if txn.producer_id != producer.producer_id {
+        return Err(ProducerError::InvalidProducerIdMapping);
+    }
+    if txn.producer_epoch != producer.producer_epoch {
+        return Err(ProducerError::InvalidProducerEpoch);
+    }
+    Ok(txn)
+}
+
+/// Verify the producer and move its transaction to `Ongoing`
+fn begin<'a>(
+    state: &'a mut CoordinatorState,
+    transactional_id: &str,
+    producer: ProducerIdAndEpoch,
+    now_ms: i64,
+) -> std::result::Result<&'a mut TransactionMetadata, ProducerError> {
+    let txn = verify(state, transactional_id, producer)?;
+    match txn.state {
+        TransactionState::Ongoing => {}
+        TransactionState::PrepareCommit | TransactionState::PrepareAbort => {
+            return Err(ProducerError::ConcurrentTransactions);
+        }
+        _ => {
+            txn.state = TransactionState::Ongoing;
+            txn.started_ms = now_ms;
+            txn.partitions.clear();
+            txn.groups.clear();
+            txn.offsets.clear();
+        }
+    }
+    Ok(txn)
+}
+
+/// Bump the epoch of an ongoing transaction's producer and prepare to abort
+/// it with the new epoch
+fn fence(transactional_id: &str, txn: &mut TransactionMetadata) -> TransactionCompletion {
+    txn.producer_epoch = txn.producer_epoch.saturating_add(1);
+    txn.state = TransactionState::PrepareAbort;
+    completion(transactional_id, txn)
+}
+
+fn completion(transactional_id: &str, txn: &TransactionMetadata) -> TransactionCompletion {
+    TransactionCompletion {
+        transactional_id: transactional_id.to_string(),
+        producer: ProducerIdAndEpoch {
+            producer_id: txn.producer_id,
+            producer_epoch: txn.producer_epoch,
+        },
+        commit: txn.state == TransactionState::PrepareCommit,
+        partitions: txn.partitions.iter().cloned().collect(),
+        offsets: txn
+            .offsets
+            .iter()
+            .map(|(group, offsets)| (group.clone(), offsets.clone()))
+            .collect(),
+    }
+}
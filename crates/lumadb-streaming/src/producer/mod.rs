@@ -0,0 +1,100 @@
+//! Idempotent and transactional producers
+//!
+//! Producers obtain a producer id and epoch from `init_producer_id` and stamp
+//! every batch with them and a per-partition sequence number, which lets each
+//! partition log drop retried batches and reject gaps (see
+//! [`crate::log::ProducerStateManager`]). Producers with a transactional id
+//! additionally register the partitions and consumer group offsets they
+//! write with the [`TransactionCoordinator`]; ending the transaction writes a
+//! commit or abort marker to every registered partition. Readers using
+//! [`IsolationLevel::ReadCommitted`] stop at the last stable offset and skip
+//! records of aborted transactions.
+
+mod coordinator;
+
+pub use coordinator::{
+    GroupOffsets, InitProducer, TransactionCompletion, TransactionCoordinator, TransactionMetadata,
+    TransactionState,
+};
+
+use lumadb_common::error::Error;
+
+/// Producer id and epoch handed out by `init_producer_id`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ProducerIdAndEpoch {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+/// Which records a consumer may read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    /// Everything up to the high watermark, including records of open and
+    /// aborted transactions
+    #[default]
+    ReadUncommitted,
+    /// Only records below the last stable offset, skipping aborted
+    /// transactions
+    ReadCommitted,
+}
+
+impl IsolationLevel {
+    /// Kafka wire value (0 or 1)
+    #[must_use]
+    pub fn from_kafka(value: i8) -> Self {
+        if value == 1 {
+            Self::ReadCommitted
+        } else {
+            Self::ReadUncommitted
+        }
+    }
+}
+
+impl std::str::FromStr for IsolationLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "read_uncommitted" => Ok(Self::ReadUncommitted),
+            "read_committed" => Ok(Self::ReadCommitted),
+            _ => Err(format!("invalid isolation level '{s}'")),
+        }
+    }
+}
+
+/// Producer and transaction errors, mapped onto Kafka error codes by the wire
+/// protocol
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProducerError {
+    #[error("out of order sequence number")]
+    OutOfOrderSequence,
+    #[error("producer has been fenced by a newer epoch")]
+    InvalidProducerEpoch,
+    #[error("unknown producer id")]
+    UnknownProducerId,
+    #[error("invalid transaction state")]
+    InvalidTxnState,
+    #[error("producer id does not match the transactional id")]
+    InvalidProducerIdMapping,
+    #[error("invalid transaction timeout")]
+    InvalidTransactionTimeout,
+    #[error("a previous transaction is still completing")]
+    ConcurrentTransactions,
+    #[error("unknown topic or partition")]
+    UnknownTopicOrPartition,
+    #[error("storage error: {0}")]
+    Storage(String),
+}
+
+impl From<Error> for ProducerError {
+    fn from(error: Error) -> Self {
+        Self::Storage(error.to_string())
+    }
+}
+
+impl From<ProducerError> for Error {
+    fn from(error: ProducerError) -> Self {
+        Error::Internal(error.to_string())
+    }
+}
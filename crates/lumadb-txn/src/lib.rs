@@ -1,156 +1,453 @@
 //! Distributed transaction support
 //!
 //! Provides:
-//! - MVCC (Multi-Version Concurrency Control)
-//! - 2PC (Two-Phase Commit)
-//! - Lock management
+//! - MVCC snapshot isolation over the storage engine's document versions
+//! - First-committer-wins write-conflict detection
+//! - Serializable isolation through read-set validation
 //! - Timestamp Oracle
+//! - Rollback of transactions left idle past a timeout
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::iter::Peekable;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
-use parking_lot::RwLock;
-use tracing::{info, debug};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info};
 
 use lumadb_common::error::{Result, Error, TransactionError};
-use lumadb_common::types::Timestamp;
+use lumadb_common::types::{Document, Timestamp};
+use lumadb_storage::{DocumentScan, DocumentWrite, ReadKey, StorageEngine};
+
+/// How long a transaction may go unused before it is rolled back
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+type ActiveTransactions = DashMap<u64, Arc<Transaction>>;
 
 /// Transaction coordinator
+///
+/// A transaction left idle for longer than the idle timeout is rolled back
+/// by a background reaper, so an abandoned transaction does not hold its
+/// snapshot, and with it the versions garbage collection may reclaim,
+/// forever.
 pub struct TransactionCoordinator {
-    /// Timestamp oracle
+    /// Storage the transactions read from and commit to
+    storage: Arc<StorageEngine>,
+    /// Transaction IDs
     tso: TimestampOracle,
     /// Active transactions
-    active: DashMap<u64, Transaction>,
-    /// Lock manager
-    locks: LockManager,
+    active: Arc<ActiveTransactions>,
+    idle_timeout: Duration,
+    /// Task rolling back idle transactions, when started in a runtime
+    reaper: Option<JoinHandle<()>>,
 }
 
 impl TransactionCoordinator {
     /// Create a new transaction coordinator
-    pub fn new() -> Self {
+    pub fn new(storage: Arc<StorageEngine>) -> Self {
+        Self::with_idle_timeout(storage, DEFAULT_IDLE_TIMEOUT)
+    }
+
+    /// Create a transaction coordinator rolling back transactions left
+    /// idle for longer than `idle_timeout`
+    ///
+    /// The reaper only runs when this is called within a Tokio runtime;
+    /// [`expire_idle`](Self::expire_idle) rolls idle transactions back on
+    /// demand.
+    pub fn with_idle_timeout(storage: Arc<StorageEngine>, idle_timeout: Duration) -> Self {
+        let active = Arc::new(ActiveTransactions::new());
+        let reaper = tokio::runtime::Handle::try_current().ok().map(|runtime| {
+            runtime.spawn(Self::reap(active.clone(), storage.clone(), idle_timeout))
+        });
         Self {
+            storage,
             tso: TimestampOracle::new(),
-            active: DashMap::new(),
-            locks: LockManager::new(),
+            active,
+            idle_timeout,
+            reaper,
+        }
+    }
+
+    /// Roll back idle transactions every half timeout
+    async fn reap(
+        active: Arc<ActiveTransactions>,
+        storage: Arc<StorageEngine>,
+        idle_timeout: Duration,
+    ) {
+        let mut ticks = tokio::time::interval((idle_timeout / 2).max(Duration::from_millis(10)));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            expire(&active, &storage, idle_timeout);
         }
     }
 
-    /// Begin a new transaction
-    pub fn begin(&self) -> Transaction {
-        let txn_id = self.tso.next();
-        let start_ts = self.tso.get_timestamp();
+    /// Roll back the transactions idle for longer than the idle timeout,
+    /// returning how many there were
+    pub fn expire_idle(&self) -> usize {
+        expire(&self.active, &self.storage, self.idle_timeout)
+    }
 
-        let txn = Transaction {
-            id: txn_id,
-            start_ts,
-            commit_ts: None,
-            state: RwLock::new(TxnState::Active),
-            writes: RwLock::new(Vec::new()),
-            reads: RwLock::new(Vec::new()),
-        };
+    /// Begin a new transaction reading a snapshot of everything committed
+    /// so far
+    pub fn begin(&self, isolation: IsolationLevel) -> Arc<Transaction> {
+        self.begin_for(isolation, None)
+    }
 
-        self.active.insert(txn_id, txn.clone());
+    /// Begin a new transaction on behalf of `owner`
+    pub fn begin_for(&self, isolation: IsolationLevel, owner: Option<String>) -> Arc<Transaction> {
+        let txn = Arc::new(Transaction {
+            id: self.tso.next(),
+            start_ts: self.storage.begin_snapshot(),
+            isolation,
+            owner,
+            storage: self.storage.clone(),
+            state: RwLock::new(TxnState::Active),
+            last_used: Mutex::new(Instant::now()),
+            commit_ts: RwLock::new(None),
+            writes: RwLock::new(BTreeMap::new()),
+            reads: RwLock::new(HashSet::new()),
+        });
+
+        debug!("Began transaction {} at {}", txn.id, txn.start_ts);
+        self.active.insert(txn.id, txn.clone());
         txn
     }
 
-    /// Commit a transaction
-    pub async fn commit(&self, txn_id: u64) -> Result<()> {
-        let txn = self
-            .active
+    /// Look up an active transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no active transaction has that ID.
+    pub fn get(&self, txn_id: u64) -> Result<Arc<Transaction>> {
+        self.active
             .get(&txn_id)
-            .ok_or(Error::Transaction(TransactionError::Aborted(
-                "Transaction not found".to_string(),
-            )))?;
-
-        // Check for conflicts
-        for write in txn.writes.read().iter() {
-            if !self.locks.try_lock(txn_id, &write.key, LockType::Write)? {
-                return Err(Error::Transaction(TransactionError::Conflict(
-                    "Write conflict".to_string(),
-                )));
-            }
-        }
+            .map(|txn| txn.clone())
+            .ok_or_else(|| not_found(txn_id))
+    }
 
-        // Get commit timestamp
-        let commit_ts = self.tso.get_timestamp();
+    /// Number of active transactions
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
 
-        // Update transaction state
-        *txn.state.write() = TxnState::Committed;
+    /// Commit a transaction, returning its commit timestamp
+    ///
+    /// Fails, rolling the transaction back, if a transaction that
+    /// committed after it began wrote a document it writes or, under
+    /// serializable isolation, anything it read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is unknown, conflicts as described
+    /// above, or cannot be written.
+    pub async fn commit(&self, txn_id: u64) -> Result<u64> {
+        let (_, txn) = self
+            .active
+            .remove(&txn_id)
+            .ok_or_else(|| not_found(txn_id))?;
+
+        let writes: Vec<DocumentWrite> = std::mem::take(&mut *txn.writes.write())
+            .into_iter()
+            .map(|((collection, id), document)| DocumentWrite {
+                collection,
+                id,
+                document,
+            })
+            .collect();
+        let reads: Vec<ReadKey> = match txn.isolation {
+            IsolationLevel::Snapshot => Vec::new(),
+            IsolationLevel::Serializable => txn.reads.write().drain().collect(),
+        };
 
-        // Release locks
-        for write in txn.writes.read().iter() {
-            self.locks.unlock(txn_id, &write.key);
+        // A read-only transaction saw a consistent snapshot and has
+        // nothing to validate
+        let result = if writes.is_empty() {
+            Ok(txn.start_ts)
+        } else {
+            self.storage.commit(txn.start_ts, writes, &reads).await
+        };
+        self.storage.release_snapshot(txn.start_ts);
+
+        match result {
+            Ok(commit_ts) => {
+                *txn.commit_ts.write() = Some(commit_ts);
+                *txn.state.write() = TxnState::Committed;
+                debug!("Committed transaction {} at {}", txn_id, commit_ts);
+                Ok(commit_ts)
+            }
+            Err(e) => {
+                *txn.state.write() = TxnState::RolledBack;
+                debug!("Transaction {} aborted: {}", txn_id, e);
+                Err(e)
+            }
         }
-
-        self.active.remove(&txn_id);
-
-        Ok(())
     }
 
-    /// Rollback a transaction
+    /// Rollback a transaction, discarding its writes
+    #[allow(clippy::unused_async)] // async like begin and commit
     pub async fn rollback(&self, txn_id: u64) -> Result<()> {
-        if let Some((_, txn)) = self.active.remove(&txn_id) {
-            *txn.state.write() = TxnState::RolledBack;
+        let (_, txn) = self
+            .active
+            .remove(&txn_id)
+            .ok_or_else(|| not_found(txn_id))?;
+        txn.discard(&self.storage);
+        debug!("Rolled back transaction {}", txn_id);
+        Ok(())
+    }
+}
 
-            // Release all locks
-            for write in txn.writes.read().iter() {
-                self.locks.unlock(txn_id, &write.key);
-            }
+impl Drop for TransactionCoordinator {
+    fn drop(&mut self) {
+        if let Some(reaper) = self.reaper.take() {
+            reaper.abort();
         }
+    }
+}
 
-        Ok(())
+/// Roll back the transactions idle for longer than `idle_timeout`
+fn expire(active: &ActiveTransactions, storage: &StorageEngine, idle_timeout: Duration) -> usize {
+    let idle: Vec<u64> = active
+        .iter()
+        .filter(|txn| txn.idle_for() > idle_timeout)
+        .map(|txn| txn.id)
+        .collect();
+    let mut expired = 0;
+    for txn_id in idle {
+        // Skip a transaction used again since it was found idle
+        if let Some((_, txn)) = active.remove_if(&txn_id, |_, txn| txn.idle_for() > idle_timeout) {
+            txn.discard(storage);
+            info!(
+                "Rolled back transaction {} after {:?} idle",
+                txn_id, idle_timeout
+            );
+            expired += 1;
+        }
     }
+    expired
 }
 
-impl Default for TransactionCoordinator {
-    fn default() -> Self {
-        Self::new()
+fn not_found(txn_id: u64) -> Error {
+    Error::Transaction(TransactionError::Aborted(format!(
+        "Transaction {txn_id} not found"
+    )))
+}
+
+/// Isolation level of a transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    /// Reads see the snapshot the transaction began with; concurrent
+    /// writes to the same document conflict
+    #[default]
+    Snapshot,
+    /// Snapshot isolation that also fails the commit if anything the
+    /// transaction read was changed by a concurrent commit
+    Serializable,
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsolationLevel::Snapshot => write!(f, "snapshot"),
+            IsolationLevel::Serializable => write!(f, "serializable"),
+        }
     }
 }
 
 /// Transaction
-#[derive(Debug)]
+///
+/// Writes are buffered until commit; reads see the transaction's own
+/// writes over the snapshot it began with.
 pub struct Transaction {
     pub id: u64,
-    pub start_ts: Timestamp,
-    pub commit_ts: Option<Timestamp>,
+    /// Snapshot timestamp the transaction reads at
+    pub start_ts: u64,
+    pub isolation: IsolationLevel,
+    /// Principal that began the transaction, if any
+    pub owner: Option<String>,
+    storage: Arc<StorageEngine>,
     state: RwLock<TxnState>,
-    writes: RwLock<Vec<WriteIntent>>,
-    reads: RwLock<Vec<ReadSet>>,
+    /// When the transaction was last used
+    last_used: Mutex<Instant>,
+    commit_ts: RwLock<Option<u64>>,
+    /// Buffered writes by collection and document ID
+    writes: RwLock<BTreeMap<(String, String), Option<Document>>>,
+    /// What the transaction read, validated under serializable isolation
+    reads: RwLock<HashSet<ReadKey>>,
 }
 
-impl Clone for Transaction {
-    fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            start_ts: self.start_ts,
-            commit_ts: self.commit_ts,
-            state: RwLock::new(*self.state.read()),
-            writes: RwLock::new(self.writes.read().clone()),
-            reads: RwLock::new(self.reads.read().clone()),
-        }
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("id", &self.id)
+            .field("start_ts", &self.start_ts)
+            .field("isolation", &self.isolation)
+            .field("state", &*self.state.read())
+            .finish_non_exhaustive()
     }
 }
 
 impl Transaction {
-    /// Add a write intent
-    pub fn write(&self, key: Vec<u8>, value: Vec<u8>) {
-        self.writes.write().push(WriteIntent { key, value });
+    /// Read a document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is no longer active or the document
+    /// cannot be read.
+    pub async fn get(&self, collection: &str, id: &str) -> Result<Option<Document>> {
+        self.check_active()?;
+        let key = (collection.to_string(), id.to_string());
+        if let Some(write) = self.writes.read().get(&key) {
+            return Ok(write.clone());
+        }
+
+        self.record_read(ReadKey::Document {
+            collection: key.0,
+            id: key.1,
+        });
+        self.storage
+            .get_document_at(collection, id, self.start_ts)
+            .await
     }
 
-    /// Add to read set
-    pub fn read(&self, key: Vec<u8>, version: Timestamp) {
-        self.reads.write().push(ReadSet { key, version });
+    /// Iterate the documents of a collection in ID order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is no longer active or the
+    /// collection cannot be read.
+    pub fn scan(&self, collection: &str) -> Result<TransactionScan> {
+        self.check_active()?;
+        self.record_read(ReadKey::Collection(collection.to_string()));
+
+        let from = (collection.to_string(), String::new());
+        let pending: Vec<(String, Option<Document>)> = self
+            .writes
+            .read()
+            .range(from..)
+            .take_while(|((c, _), _)| c == collection)
+            .map(|((_, id), doc)| (id.clone(), doc.clone()))
+            .collect();
+
+        Ok(TransactionScan {
+            committed: self
+                .storage
+                .document_scan_at(collection, None, None, self.start_ts)?
+                .peekable(),
+            pending: pending.into_iter().peekable(),
+        })
+    }
+
+    /// Insert or replace a document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is no longer active.
+    pub fn put(&self, collection: &str, doc: Document) -> Result<()> {
+        self.check_active()?;
+        self.writes
+            .write()
+            .insert((collection.to_string(), doc.id.clone()), Some(doc));
+        Ok(())
+    }
+
+    /// Delete a document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is no longer active.
+    pub fn delete(&self, collection: &str, id: &str) -> Result<()> {
+        self.check_active()?;
+        self.writes
+            .write()
+            .insert((collection.to_string(), id.to_string()), None);
+        Ok(())
+    }
+
+    /// Commit timestamp, once committed
+    pub fn commit_ts(&self) -> Option<u64> {
+        *self.commit_ts.read()
     }
 
     /// Check if transaction is active
     pub fn is_active(&self) -> bool {
         *self.state.read() == TxnState::Active
     }
+
+    /// How long since the transaction was last used
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().elapsed()
+    }
+
+    /// Mark the transaction rolled back and release its snapshot
+    fn discard(&self, storage: &StorageEngine) {
+        *self.state.write() = TxnState::RolledBack;
+        self.writes.write().clear();
+        storage.release_snapshot(self.start_ts);
+    }
+
+    /// Check that the transaction can still be used, and note that it was
+    fn check_active(&self) -> Result<()> {
+        *self.last_used.lock() = Instant::now();
+        match *self.state.read() {
+            TxnState::Active => Ok(()),
+            TxnState::Committed => Err(Error::Transaction(TransactionError::AlreadyCommitted)),
+            TxnState::RolledBack => Err(Error::Transaction(TransactionError::AlreadyRolledBack)),
+        }
+    }
+
+    fn record_read(&self, key: ReadKey) {
+        if self.isolation == IsolationLevel::Serializable {
+            self.reads.write().insert(key);
+        }
+    }
+}
+
+/// Documents of a collection as a transaction sees them, in ID order
+pub struct TransactionScan {
+    committed: Peekable<DocumentScan>,
+    /// The transaction's own writes to the collection
+    pending: Peekable<std::vec::IntoIter<(String, Option<Document>)>>,
+}
+
+impl Iterator for TransactionScan {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let committed_first = match (self.committed.peek(), self.pending.peek()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) | (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(Ok(doc)), Some((id, _))) => {
+                    if doc.id == *id {
+                        // The transaction's write replaces the committed version
+                        self.committed.next();
+                        false
+                    } else {
+                        doc.id < *id
+                    }
+                }
+            };
+
+            if committed_first {
+                return self.committed.next();
+            }
+            if let Some((_, Some(doc))) = self.pending.next() {
+                return Some(Ok(doc));
+            }
+        }
+    }
 }
 
 /// Transaction state
@@ -161,20 +458,6 @@ enum TxnState {
     RolledBack,
 }
 
-/// Write intent
-#[derive(Debug, Clone)]
-struct WriteIntent {
-    key: Vec<u8>,
-    value: Vec<u8>,
-}
-
-/// Read set entry
-#[derive(Debug, Clone)]
-struct ReadSet {
-    key: Vec<u8>,
-    version: Timestamp,
-}
-
 /// Timestamp Oracle for global ordering
 pub struct TimestampOracle {
     counter: AtomicU64,
@@ -204,54 +487,145 @@ impl Default for TimestampOracle {
     }
 }
 
-/// Lock manager
-struct LockManager {
-    locks: DashMap<Vec<u8>, Lock>,
-}
-
-struct Lock {
-    holder: u64,
-    lock_type: LockType,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumadb_common::config::StorageConfig;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum LockType {
-    Read,
-    Write,
-}
+    async fn storage(dir: &std::path::Path) -> Arc<StorageEngine> {
+        let config = StorageConfig {
+            path: dir.to_string_lossy().to_string(),
+            ..StorageConfig::default()
+        };
+        let storage = Arc::new(StorageEngine::new(&config).await.unwrap());
+        storage.create_collection("accounts").await.unwrap();
+        storage
+    }
 
-impl LockManager {
-    fn new() -> Self {
-        Self {
-            locks: DashMap::new(),
-        }
+    fn account(id: &str, balance: i64) -> Document {
+        Document::with_id(id, serde_json::json!({ "balance": balance }))
     }
 
-    fn try_lock(&self, txn_id: u64, key: &[u8], lock_type: LockType) -> Result<bool> {
-        let key = key.to_vec();
+    fn balance(doc: Option<Document>) -> i64 {
+        doc.unwrap().data["balance"].as_i64().unwrap()
+    }
 
-        if let Some(existing) = self.locks.get(&key) {
-            if existing.holder == txn_id {
-                return Ok(true);
-            }
+    #[tokio::test]
+    async fn snapshot_reads_and_first_committer_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        storage
+            .insert_document("accounts", &account("a", 100))
+            .await
+            .unwrap();
+        let coordinator = TransactionCoordinator::new(storage.clone());
+
+        let first = coordinator.begin(IsolationLevel::Snapshot);
+        let second = coordinator.begin(IsolationLevel::Snapshot);
+
+        first.put("accounts", account("a", 150)).unwrap();
+        first.put("accounts", account("b", 10)).unwrap();
+        // A transaction reads its own writes, others keep their snapshot
+        assert_eq!(balance(first.get("accounts", "a").await.unwrap()), 150);
+        let ids: Vec<String> = first
+            .scan("accounts")
+            .unwrap()
+            .map(|d| d.unwrap().id)
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        coordinator.commit(first.id).await.unwrap();
+
+        assert_eq!(balance(second.get("accounts", "a").await.unwrap()), 100);
+        assert_eq!(second.scan("accounts").unwrap().count(), 1);
+        second.put("accounts", account("a", 50)).unwrap();
+        let err = coordinator.commit(second.id).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Transaction(TransactionError::Conflict(_))
+        ));
+        assert!(!second.is_active());
+
+        let doc = storage.get_document("accounts", "a").await.unwrap();
+        assert_eq!(balance(doc), 150);
+        assert_eq!(coordinator.active_count(), 0);
+    }
 
-            // Check for conflicts
-            if lock_type == LockType::Write || existing.lock_type == LockType::Write {
-                return Ok(false);
-            }
+    #[tokio::test]
+    async fn serializable_transactions_validate_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        storage
+            .insert_document("accounts", &account("a", 100))
+            .await
+            .unwrap();
+        storage
+            .insert_document("accounts", &account("b", 100))
+            .await
+            .unwrap();
+        let coordinator = TransactionCoordinator::new(storage.clone());
+
+        // Write skew: each reads both balances and withdraws from one
+        let first = coordinator.begin(IsolationLevel::Serializable);
+        let second = coordinator.begin(IsolationLevel::Serializable);
+        for txn in [&first, &second] {
+            let total: i64 = txn
+                .scan("accounts")
+                .unwrap()
+                .map(|d| d.unwrap().data["balance"].as_i64().unwrap())
+                .sum();
+            assert_eq!(total, 200);
         }
-
-        self.locks.insert(key, Lock { holder: txn_id, lock_type });
-        Ok(true)
+        first.put("accounts", account("a", -50)).unwrap();
+        second.put("accounts", account("b", -50)).unwrap();
+
+        coordinator.commit(first.id).await.unwrap();
+        let err = coordinator.commit(second.id).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Transaction(TransactionError::SerializationFailure(_))
+        ));
+
+        // Old versions go once no snapshot can read them
+        let reader = coordinator.begin(IsolationLevel::Snapshot);
+        storage
+            .insert_document("accounts", &account("a", 0))
+            .await
+            .unwrap();
+        assert_eq!(storage.collect_garbage().await.unwrap(), 1);
+        assert_eq!(balance(reader.get("accounts", "a").await.unwrap()), -50);
+        coordinator.rollback(reader.id).await.unwrap();
+        assert_eq!(storage.collect_garbage().await.unwrap(), 1);
     }
 
-    fn unlock(&self, txn_id: u64, key: &[u8]) {
-        let key = key.to_vec();
-        if let Some(lock) = self.locks.get(&key) {
-            if lock.holder == txn_id {
-                drop(lock);
-                self.locks.remove(&key);
-            }
-        }
+    #[tokio::test]
+    async fn abandoned_transactions_stop_blocking_garbage_collection() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        storage
+            .insert_document("accounts", &account("a", 100))
+            .await
+            .unwrap();
+        let coordinator =
+            TransactionCoordinator::with_idle_timeout(storage.clone(), Duration::from_millis(100));
+
+        let abandoned = coordinator.begin(IsolationLevel::Snapshot);
+        assert_eq!(balance(abandoned.get("accounts", "a").await.unwrap()), 100);
+        storage
+            .insert_document("accounts", &account("a", 200))
+            .await
+            .unwrap();
+        // The open snapshot can still read the old version
+        assert_eq!(storage.collect_garbage().await.unwrap(), 0);
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(coordinator.active_count(), 0);
+        assert!(!abandoned.is_active());
+        let err = abandoned.get("accounts", "a").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Transaction(TransactionError::AlreadyRolledBack)
+        ));
+        assert!(coordinator.commit(abandoned.id).await.is_err());
+        assert_eq!(storage.collect_garbage().await.unwrap(), 1);
     }
 }
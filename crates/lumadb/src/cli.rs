@@ -1,5 +1,7 @@
 //! CLI utilities
 
+use std::path::Path;
+
 use anyhow::Result;
 
 /// Handle topic commands
@@ -19,3 +21,16 @@ pub async fn run_benchmark(test: &str) -> Result<()> {
     println!("Running benchmark: {}", test);
     Ok(())
 }
+
+/// Verify the hash chain of an audit trail
+pub fn verify_audit_trail(path: &str) -> Result<()> {
+    let verification = lumadb_security::audit::verify(Path::new(path))?;
+    match (verification.first_seq, verification.last_seq) {
+        (Some(first), Some(last)) => println!(
+            "Audit trail intact: {} records ({} to {}) in {} files",
+            verification.records, first, last, verification.files
+        ),
+        _ => println!("Audit trail is empty"),
+    }
+    Ok(())
+}
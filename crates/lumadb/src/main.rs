@@ -56,10 +56,26 @@ enum Commands {
         format: String,
     },
 
+    /// Inspect the audit trail
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+
     /// Show version information
     Version,
 }
 
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Check that no record of the audit trail was altered or removed
+    Verify {
+        /// Audit trail directory
+        #[arg(short, long, default_value = "/var/lib/lumadb/audit")]
+        path: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
@@ -100,6 +116,12 @@ async fn main() -> Result<()> {
             // Would connect to server and execute query
         }
 
+        Commands::Audit {
+            command: AuditCommands::Verify { path },
+        } => {
+            cli::verify_audit_trail(&path)?;
+        }
+
         Commands::Version => {
             println!("LumaDB version {}", env!("CARGO_PKG_VERSION"));
             println!("Build: Pure Rust, 100x faster streaming");
@@ -2,18 +2,21 @@
 
 use std::sync::Arc;
 
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::RwLock;
-use tracing::{info, error};
+use tracing::{info, error, warn};
 use anyhow::Result;
 
 use lumadb_common::config::Config;
-use lumadb_api::{RestServer, GraphQLServer, GrpcServer};
+use lumadb_common::types::TopicConfig;
+use lumadb_api::{RestServer, GraphQLServer, GrpcServer, WebSocketServer};
 use lumadb_protocol::kafka::KafkaServer;
-use lumadb_streaming::StreamingEngine;
+use lumadb_streaming::{ProduceRecord, StreamingEngine};
 use lumadb_storage::StorageEngine;
 use lumadb_query::QueryEngine;
 use lumadb_raft::RaftEngine;
 use lumadb_cluster::ClusterManager;
+use lumadb_security::audit::AuditRecord;
 use lumadb_security::SecurityManager;
 
 /// Main LumaDB server orchestrating all components
@@ -33,6 +36,7 @@ pub struct LumaServer {
     rest_server: Arc<RestServer>,
     graphql_server: GraphQLServer,
     grpc_server: GrpcServer,
+    websocket_server: WebSocketServer,
     kafka_server: Arc<KafkaServer>,
 
     // State
@@ -43,14 +47,20 @@ impl LumaServer {
     pub async fn new(config: Config) -> Result<Self> {
         info!("Initializing LumaDB components...");
 
-        // Initialize security first
-        let security = Arc::new(SecurityManager::new(&config.security).await?);
-
         // Initialize storage engine
         let storage = Arc::new(StorageEngine::new(&config.storage).await?);
 
+        // Initialize security, with roles and ACLs kept in the metadata store
+        let security = Arc::new(
+            SecurityManager::new(&config.security)
+                .await?
+                .with_storage(storage.clone())?,
+        );
+
         // Initialize Raft consensus
-        let raft = Arc::new(RaftEngine::new(&config.raft, storage.clone()).await?);
+        let raft =
+            Arc::new(RaftEngine::new(config.server.node_id, &config.raft, storage.clone()).await?);
+        raft.start_listener();
 
         // Initialize cluster manager
         let cluster = Arc::new(ClusterManager::new(&config.cluster, raft.clone()).await?);
@@ -82,6 +92,7 @@ impl LumaServer {
             query.clone(),
             streaming.clone(),
             security.clone(),
+            storage.changes().clone(),
         ).await?;
 
         let grpc_server = GrpcServer::new(
@@ -89,6 +100,16 @@ impl LumaServer {
             query.clone(),
             streaming.clone(),
             security.clone(),
+        )
+        .await?;
+
+        let websocket_server = WebSocketServer::new(
+            &config.api.websocket,
+            &graphql_server,
+            query.clone(),
+            streaming.clone(),
+            security.clone(),
+            storage.changes().clone(),
         ).await?;
 
         let kafka_server = Arc::new(KafkaServer::new(
@@ -110,6 +131,7 @@ impl LumaServer {
             rest_server,
             graphql_server,
             grpc_server,
+            websocket_server,
             kafka_server,
             running: Arc::new(RwLock::new(false)),
         })
@@ -148,6 +170,14 @@ impl LumaServer {
             }
         });
 
+        // WebSocket server (live queries, change feeds, GraphQL subscriptions)
+        let websocket_server = self.websocket_server.clone();
+        let websocket_handle = tokio::spawn(async move {
+            if let Err(e) = websocket_server.run().await {
+                error!("WebSocket server error: {}", e);
+            }
+        });
+
         // Kafka protocol server
         let kafka_server = self.kafka_server.clone();
         let kafka_handle = tokio::spawn(async move {
@@ -164,12 +194,27 @@ impl LumaServer {
             }
         });
 
+        // Audit trail mirror
+        if let (Some(log), Some(topic)) = (
+            self.security.audit_log(),
+            self.config.security.audit.mirror_topic.clone(),
+        ) {
+            let records = log.subscribe();
+            let streaming = self.streaming.clone();
+            tokio::spawn(async move {
+                if let Err(e) = mirror_audit(&streaming, &topic, records).await {
+                    error!("Audit mirror error: {}", e);
+                }
+            });
+        }
+
         info!("╔══════════════════════════════════════════════════════════╗");
         info!("║              LumaDB Server Started                        ║");
         info!("╠══════════════════════════════════════════════════════════╣");
         info!("║  REST API:    http://0.0.0.0:{}                       ║", self.config.api.rest.port);
         info!("║  GraphQL:     http://0.0.0.0:{}                       ║", self.config.api.graphql.port);
         info!("║  gRPC:        http://0.0.0.0:{}                       ║", self.config.api.grpc.port);
+        info!("║  WebSocket:   ws://0.0.0.0:{}                         ║", self.config.api.websocket.port);
         info!("║  Kafka:       0.0.0.0:{}                              ║", self.config.kafka.port);
         info!("║  Metrics:     http://0.0.0.0:{}/metrics              ║", self.config.api.rest.port);
         info!("╚══════════════════════════════════════════════════════════╝");
@@ -178,6 +223,7 @@ impl LumaServer {
         tokio::try_join!(
             graphql_handle,
             grpc_handle,
+            websocket_handle,
             kafka_handle,
             streaming_handle,
         )?;
@@ -192,6 +238,7 @@ impl LumaServer {
 
         // Shutdown in reverse order
         self.kafka_server.as_ref().shutdown().await?;
+        self.websocket_server.shutdown().await?;
         self.grpc_server.shutdown().await?;
         self.graphql_server.shutdown().await?;
         self.rest_server.as_ref().shutdown().await?;
@@ -203,3 +250,37 @@ impl LumaServer {
         Ok(())
     }
 }
+
+/// Produce every audit record to `topic`, creating it if needed
+async fn mirror_audit(
+    streaming: &StreamingEngine,
+    topic: &str,
+    mut records: tokio::sync::broadcast::Receiver<AuditRecord>,
+) -> Result<()> {
+    if !streaming.topic_exists(topic) {
+        streaming
+            .create_topic(TopicConfig::new(topic, 1, 1))
+            .await?;
+    }
+    info!("Mirroring the audit trail to topic {}", topic);
+
+    loop {
+        let record = match records.recv().await {
+            Ok(record) => record,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Audit mirror fell behind and skipped {} records", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        let produced = ProduceRecord {
+            key: Some(record.category.as_str().to_string()),
+            value: serde_json::to_value(&record)?,
+            headers: None,
+            partition: None,
+        };
+        if let Err(e) = streaming.produce(topic, &[produced], 1).await {
+            warn!("Failed to mirror audit record {}: {}", record.seq, e);
+        }
+    }
+}
@@ -260,6 +260,234 @@ async fn test_security_manager_initialization() {
     assert!(session.is_ok() || session.is_err());
 }
 
+#[tokio::test]
+async fn test_security_roles_and_acls_persist() {
+    use lumadb_security::authz::{
+        AclBinding, AclBindingFilter, Operation, PatternType, Permission, PermissionType,
+        ResourceType, Role,
+    };
+    use lumadb_security::{AuthMechanism, SecurityManager, Session};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let config = SecurityConfig {
+        auth_enabled: true,
+        jwt_secret: "test-secret-key-12345".to_string(),
+        ..Default::default()
+    };
+
+    let manager = SecurityManager::new(&config)
+        .await
+        .expect("Failed to create security manager")
+        .with_storage(storage.clone())
+        .expect("Failed to load roles and ACLs");
+    let admin = Session::anonymous();
+    manager
+        .create_acls(
+            &admin,
+            &[AclBinding {
+                resource_type: ResourceType::Topic,
+                resource_name: "orders-".to_string(),
+                pattern_type: PatternType::Prefixed,
+                principal: "User:alice".to_string(),
+                host: "*".to_string(),
+                operation: Operation::Read,
+                permission: PermissionType::Allow,
+            }],
+        )
+        .expect("Failed to create ACL");
+    manager
+        .put_role(
+            &admin,
+            Role {
+                name: "auditor".to_string(),
+                permissions: vec![Permission {
+                    resource: "collection:*".to_string(),
+                    action: "describe".to_string(),
+                }],
+            },
+        )
+        .expect("Failed to define role");
+
+    // A manager started later on the same metadata store enforces them
+    let restarted = SecurityManager::new(&config)
+        .await
+        .expect("Failed to create security manager")
+        .with_storage(storage)
+        .expect("Failed to load roles and ACLs");
+    assert!(restarted.roles().iter().any(|role| role.name == "auditor"));
+    assert_eq!(
+        restarted.describe_acls(&AclBindingFilter::default()).len(),
+        1
+    );
+
+    let alice = Session {
+        user_id: "alice".to_string(),
+        username: "alice".to_string(),
+        roles: Vec::new(),
+        mechanism: AuthMechanism::Jwt,
+        host: None,
+        created_at: 0,
+        expires_at: i64::MAX,
+    };
+    assert!(restarted.is_authorized(&alice, "topic:orders-eu", "read"));
+    assert!(restarted.is_authorized(&alice, "topic:orders-eu", "describe"));
+    assert!(!restarted.is_authorized(&alice, "topic:orders-eu", "write"));
+    assert!(!restarted.is_authorized(&alice, "topic:payments", "read"));
+
+    let auditor = Session {
+        roles: vec!["auditor".to_string()],
+        ..alice.clone()
+    };
+    assert!(restarted.is_authorized(&auditor, "collection:users", "describe"));
+    assert!(!restarted.is_authorized(&auditor, "collection:users", "read"));
+    assert!(!restarted.is_authorized(&auditor, "cluster", "alter"));
+}
+
+#[tokio::test]
+async fn test_security_scram_credentials_and_oauthbearer() {
+    use lumadb_security::auth::{
+        JwtAuth, SaslStep, ScramCredential, ScramCredentialInfo, ScramMechanism,
+    };
+    use lumadb_security::{AuthenticatedUser, SecurityManager, Session};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let config = SecurityConfig {
+        auth_enabled: true,
+        jwt_secret: "test-secret-key-12345".to_string(),
+        ..Default::default()
+    };
+
+    let manager = SecurityManager::new(&config)
+        .await
+        .expect("Failed to create security manager")
+        .with_storage(storage.clone())
+        .expect("Failed to load security metadata");
+    let credential = ScramCredential::new(ScramMechanism::Sha512, "s3cret", 8192)
+        .expect("Failed to derive credential");
+    manager
+        .upsert_scram_credential(
+            &Session::anonymous(),
+            "alice",
+            ScramMechanism::Sha512,
+            credential,
+        )
+        .expect("Failed to store credential");
+
+    // A manager started later on the same metadata store knows the user
+    let restarted = SecurityManager::new(&config)
+        .await
+        .expect("Failed to create security manager")
+        .with_storage(storage)
+        .expect("Failed to load security metadata");
+    assert_eq!(
+        restarted.scram_credentials(),
+        vec![ScramCredentialInfo {
+            username: "alice".to_string(),
+            mechanism: ScramMechanism::Sha512,
+            iterations: 8192,
+        }]
+    );
+    let mechanisms: Vec<&str> = restarted
+        .sasl_mechanisms()
+        .iter()
+        .map(|m| m.name())
+        .collect();
+    assert_eq!(
+        mechanisms,
+        ["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512", "OAUTHBEARER"]
+    );
+
+    // SCRAM answers client-first with the stored salt and iterations
+    let mut exchange = restarted
+        .sasl_exchange("SCRAM-SHA-512")
+        .expect("SCRAM is offered");
+    match restarted
+        .sasl_step(&mut exchange, b"n,,n=alice,r=client-nonce")
+        .await
+    {
+        Ok(SaslStep::Challenge(challenge)) => {
+            let challenge = String::from_utf8(challenge).unwrap();
+            assert!(challenge.starts_with("r=client-nonce"));
+            assert!(challenge.ends_with(",i=8192"));
+        }
+        _ => panic!("Expected a server-first challenge"),
+    }
+
+    // OAUTHBEARER takes tokens signed with the JWT secret
+    let token = JwtAuth::new(&config.jwt_secret)
+        .generate_token(&AuthenticatedUser {
+            id: "42".to_string(),
+            username: "bob".to_string(),
+            roles: vec!["reader".to_string()],
+        })
+        .expect("Failed to sign token");
+    let mut exchange = restarted
+        .sasl_exchange("OAUTHBEARER")
+        .expect("OAUTHBEARER is offered");
+    let message = format!("n,,\u{1}auth=Bearer {token}\u{1}\u{1}");
+    match restarted.sasl_step(&mut exchange, message.as_bytes()).await {
+        Ok(SaslStep::Complete(session, _)) => {
+            assert_eq!(session.principal(), "User:bob");
+            assert_eq!(session.roles, vec!["reader".to_string()]);
+        }
+        _ => panic!("Expected the token to authenticate"),
+    }
+
+    let mut exchange = restarted
+        .sasl_exchange("OAUTHBEARER")
+        .expect("OAUTHBEARER is offered");
+    let forged = b"n,,\x01auth=Bearer forged\x01\x01";
+    assert!(restarted.sasl_step(&mut exchange, forged).await.is_err());
+}
+
+#[tokio::test]
+async fn test_security_audit_trail() {
+    use lumadb_security::audit::{self, AuditOutcome, AuditRecord};
+    use lumadb_security::AuditCategory;
+    use lumadb_security::{Credentials, SecurityManager, Session};
+
+    let audit_dir = TempDir::new().expect("Failed to create temp dir");
+    let mut config = SecurityConfig {
+        auth_enabled: true,
+        audit_enabled: true,
+        auth_method: "jwt".to_string(),
+        jwt_secret: "test-secret-key-12345".to_string(),
+        ..Default::default()
+    };
+    config.audit.path = audit_dir.path().to_string_lossy().into_owned();
+
+    let manager = SecurityManager::new(&config)
+        .await
+        .expect("Failed to create security manager");
+    let mut mirror = manager
+        .audit_log()
+        .expect("auditing is enabled")
+        .subscribe();
+
+    let failed = manager
+        .authenticate(&Credentials::Token("forged".to_string()))
+        .await;
+    assert!(failed.is_err());
+    let anonymous = Session::anonymous();
+    let mut mallory = anonymous.clone();
+    mallory.username = "mallory".to_string();
+    mallory.roles.clear();
+    assert!(manager
+        .authorize(&mallory, "collection:users", "read")
+        .is_err());
+    manager.delete_role(&anonymous, "auditor").ok();
+
+    let verified = audit::verify(audit_dir.path()).expect("Audit trail is intact");
+    assert_eq!(verified.records, 3);
+
+    let first: AuditRecord = mirror.try_recv().expect("Records are mirrored");
+    assert_eq!(first.category, AuditCategory::Authentication);
+    assert_eq!(first.outcome, AuditOutcome::Failure);
+    let second: AuditRecord = mirror.try_recv().expect("Records are mirrored");
+    assert_eq!(second.principal.as_deref(), Some("User:mallory"));
+    assert_eq!(second.prev_hash, first.hash);
+}
+
 // ============================================================================
 // End-to-End Tests
 // ============================================================================
@@ -354,6 +582,772 @@ async fn test_e2e_streaming_workflow() {
     assert!(consumed.len() >= 5);
 }
 
+// ============================================================================
+// REST API Tests
+// ============================================================================
+
+/// Test helper to start a REST server with authentication on a free port,
+/// returning the port it listens on
+async fn start_test_rest(
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+    security: Arc<lumadb_security::SecurityManager>,
+) -> u16 {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .expect("Failed to find a free port")
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = lumadb_common::config::RestApiConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        workers: 1,
+        ..Default::default()
+    };
+
+    let server = lumadb_api::RestServer::new(&config, query, streaming, security)
+        .await
+        .expect("Failed to create REST server");
+    // The actix server is not `Send`, so it runs on a thread of its own
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to build the REST runtime");
+        tokio::task::LocalSet::new().block_on(&runtime, server.run())
+    });
+
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    port
+}
+
+/// POST `body` to `path` with a bearer token, returning the status and the
+/// JSON response, if any
+async fn rest_post(
+    port: u16,
+    path: &str,
+    token: &str,
+    body: serde_json::Value,
+) -> (u16, serde_json::Value) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let body = body.to_string();
+    let request = format!(
+        "POST {path} HTTP/1.1\r\n\
+         Host: 127.0.0.1\r\n\
+         Authorization: Bearer {token}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .expect("Failed to connect to the REST server");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").expect("Malformed response");
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .expect("Malformed status line");
+    (
+        status,
+        serde_json::from_str(body).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn test_rest_transactions_belong_to_their_caller() {
+    use lumadb_security::auth::JwtAuth;
+    use lumadb_security::{AuthenticatedUser, SecurityManager};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage).await;
+    let config = SecurityConfig {
+        auth_enabled: true,
+        auth_method: "jwt".to_string(),
+        jwt_secret: "test-secret-key-12345".to_string(),
+        ..Default::default()
+    };
+    let security = Arc::new(
+        SecurityManager::new(&config)
+            .await
+            .expect("Failed to create security manager"),
+    );
+    let port = start_test_rest(query, streaming, security).await;
+
+    let jwt = JwtAuth::new(&config.jwt_secret);
+    let token = |id: &str, username: &str| {
+        jwt.generate_token(&AuthenticatedUser {
+            id: id.to_string(),
+            username: username.to_string(),
+            roles: vec!["admin".to_string()],
+        })
+        .expect("Failed to sign token")
+    };
+    let alice = token("1", "alice");
+    let bob = token("2", "bob");
+
+    let (status, begun) = rest_post(
+        port,
+        "/api/v1/query/transactions",
+        &alice,
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let txn_id = begun["transaction_id"]
+        .as_u64()
+        .expect("Expected a transaction ID");
+    let txn = format!("/api/v1/query/transactions/{txn_id}");
+
+    // Another caller cannot see the transaction, let alone use or end it
+    let statement = serde_json::json!({ "query": "SELECT * FROM docs" });
+    let (status, _) = rest_post(port, &txn, &bob, statement.clone()).await;
+    assert_eq!(status, 404);
+    let (status, _) = rest_post(port, &format!("{txn}/commit"), &bob, serde_json::json!({})).await;
+    assert_eq!(status, 404);
+    let (status, _) = rest_post(
+        port,
+        &format!("{txn}/rollback"),
+        &bob,
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    // The caller that began it still can
+    let (status, _) = rest_post(
+        port,
+        &format!("{txn}/commit"),
+        &alice,
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, 200);
+}
+
+// ============================================================================
+// gRPC API Tests
+// ============================================================================
+
+/// Test helper to start a gRPC server on a free port, returning the server
+/// and the endpoint clients connect to
+async fn start_test_grpc(
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+) -> (lumadb_api::GrpcServer, String) {
+    start_test_grpc_with(
+        lumadb_common::config::GrpcApiConfig::default(),
+        query,
+        streaming,
+    )
+    .await
+}
+
+/// Test helper to start a gRPC server with `config` on a free port
+async fn start_test_grpc_with(
+    mut config: lumadb_common::config::GrpcApiConfig,
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+) -> (lumadb_api::GrpcServer, String) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .expect("Failed to find a free port")
+        .local_addr()
+        .unwrap()
+        .port();
+    config.port = port;
+
+    let security = Arc::new(
+        lumadb_security::SecurityManager::new(&SecurityConfig::default())
+            .await
+            .expect("Failed to create security manager"),
+    );
+    let server = lumadb_api::GrpcServer::new(&config, query, streaming, security)
+        .await
+        .expect("Failed to create gRPC server");
+    let runner = server.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    (server, endpoint)
+}
+
+#[tokio::test]
+async fn test_grpc_query_and_documents() {
+    use lumadb_api::grpc::proto::document_service_client::DocumentServiceClient;
+    use lumadb_api::grpc::proto::query_service_client::QueryServiceClient;
+    use lumadb_api::grpc::proto::{FindRequest, InsertRequest, QueryRequest};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage).await;
+    let (server, endpoint) = start_test_grpc(query, streaming).await;
+
+    let mut documents = DocumentServiceClient::connect(endpoint.clone())
+        .await
+        .expect("Failed to connect");
+    let inserted = documents
+        .insert(InsertRequest {
+            collection: "users".to_string(),
+            documents_json: (0..3)
+                .map(|i| {
+                    serde_json::json!({"name": format!("user-{}", i), "age": 20 + i}).to_string()
+                })
+                .collect(),
+        })
+        .await
+        .expect("Failed to insert")
+        .into_inner();
+    assert_eq!(inserted.inserted_count, 3);
+
+    let found = documents
+        .find(FindRequest {
+            collection: "users".to_string(),
+            filter_json: r#"{"name": "user-1"}"#.to_string(),
+            limit: 10,
+        })
+        .await
+        .expect("Failed to find")
+        .into_inner();
+    assert_eq!(found.documents_json.len(), 1);
+
+    let invalid = documents
+        .find(FindRequest {
+            collection: "users".to_string(),
+            filter_json: "{not json".to_string(),
+            limit: 0,
+        })
+        .await
+        .expect_err("Invalid filter should be rejected");
+    assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+
+    let mut queries = QueryServiceClient::connect(endpoint)
+        .await
+        .expect("Failed to connect");
+    let mut rows = queries
+        .execute_stream(QueryRequest {
+            query: "SELECT * FROM users".to_string(),
+            page_size: 2,
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to execute")
+        .into_inner();
+    let mut count = 0;
+    while let Some(row) = rows.message().await.expect("Failed to stream rows") {
+        serde_json::from_str::<serde_json::Value>(&row.json).expect("Row is not JSON");
+        count += 1;
+    }
+    assert_eq!(count, 3);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_grpc_produce_and_consume() {
+    use lumadb_api::grpc::proto::stream_service_client::StreamServiceClient;
+    use lumadb_api::grpc::proto::{ConsumeRequest, ProduceRecord, ProduceRequest};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage).await;
+    streaming
+        .create_topic(lumadb_common::types::TopicConfig::new("grpc-events", 2, 1))
+        .await
+        .expect("Failed to create topic");
+    let (server, endpoint) = start_test_grpc(query, streaming.clone()).await;
+
+    let mut client = StreamServiceClient::connect(endpoint)
+        .await
+        .expect("Failed to connect");
+    let batches: Vec<ProduceRequest> = (0..2)
+        .map(|batch| ProduceRequest {
+            topic: "grpc-events".to_string(),
+            acks: 1,
+            records: (0..3)
+                .map(|i| ProduceRecord {
+                    key: Some(format!("key-{}-{}", batch, i)),
+                    value_json: serde_json::json!({"seq": batch * 3 + i}).to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+        .collect();
+    let produced = client
+        .produce(tokio_stream::iter(batches))
+        .await
+        .expect("Failed to produce")
+        .into_inner();
+    assert_eq!(produced.records.len(), 6);
+
+    let mut stream = client
+        .consume(ConsumeRequest {
+            topic: "grpc-events".to_string(),
+            group_id: "grpc-group".to_string(),
+            offset: "earliest".to_string(),
+            auto_commit: true,
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to consume")
+        .into_inner();
+    let mut consumed = 0;
+    while consumed < 6 {
+        let batch = timeout(Duration::from_secs(5), stream.message())
+            .await
+            .expect("Timed out waiting for records")
+            .expect("Failed to read records")
+            .expect("Stream ended early");
+        consumed += batch.records.len();
+    }
+    assert_eq!(consumed, 6);
+    drop(stream);
+
+    // Offsets are committed right after each batch is sent, so wait for the
+    // group to catch up with everything that was consumed
+    let mut committed = 0;
+    for _ in 0..50 {
+        committed = streaming
+            .find_consumer_group("grpc-group")
+            .map(|group| {
+                (0..2)
+                    .filter_map(|partition| group.get_offset("grpc-events", partition))
+                    .sum()
+            })
+            .unwrap_or(0);
+        if committed == 6 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(committed, 6);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_grpc_streams_outlive_request_timeout() {
+    use lumadb_api::grpc::proto::stream_service_client::StreamServiceClient;
+    use lumadb_api::grpc::proto::{ProduceRecord, ProduceRequest};
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage).await;
+    streaming
+        .create_topic(lumadb_common::types::TopicConfig::new("slow-events", 1, 1))
+        .await
+        .expect("Failed to create topic");
+    let config = lumadb_common::config::GrpcApiConfig {
+        request_timeout_ms: 200,
+        ..Default::default()
+    };
+    let (server, endpoint) = start_test_grpc_with(config, query, streaming).await;
+
+    // The client stream stays open well past the unary request timeout
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        for seq in 0..3 {
+            let batch = ProduceRequest {
+                topic: "slow-events".to_string(),
+                acks: 1,
+                records: vec![ProduceRecord {
+                    value_json: serde_json::json!({"seq": seq}).to_string(),
+                    ..Default::default()
+                }],
+            };
+            tx.send(batch).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    });
+
+    let mut client = StreamServiceClient::connect(endpoint)
+        .await
+        .expect("Failed to connect");
+    let produced = client
+        .produce(tokio_stream::wrappers::ReceiverStream::new(rx))
+        .await
+        .expect("Failed to produce")
+        .into_inner();
+    assert_eq!(produced.records.len(), 3);
+
+    server.shutdown().await.unwrap();
+}
+
+// ============================================================================
+// WebSocket API Tests
+// ============================================================================
+
+type TestSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Test helper to start a WebSocket server on a free port, returning the
+/// server and its address
+async fn start_test_websocket(
+    storage: Arc<StorageEngine>,
+    query: Arc<QueryEngine>,
+    streaming: Arc<StreamingEngine>,
+    max_unacked: usize,
+) -> (lumadb_api::WebSocketServer, String) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .expect("Failed to find a free port")
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut config = lumadb_common::config::WebSocketApiConfig::default();
+    config.port = port;
+    config.max_unacked = max_unacked;
+
+    let security = Arc::new(
+        lumadb_security::SecurityManager::new(&SecurityConfig::default())
+            .await
+            .expect("Failed to create security manager"),
+    );
+    let graphql = lumadb_api::GraphQLServer::new(
+        &lumadb_common::config::GraphQLApiConfig::default(),
+        query.clone(),
+        streaming.clone(),
+        security.clone(),
+        storage.changes().clone(),
+    )
+    .await
+    .expect("Failed to create GraphQL server");
+    let server = lumadb_api::WebSocketServer::new(
+        &config,
+        &graphql,
+        query,
+        streaming,
+        security,
+        storage.changes().clone(),
+    )
+    .await
+    .expect("Failed to create WebSocket server");
+    let runner = server.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    (server, format!("127.0.0.1:{}", port))
+}
+
+async fn ws_send(socket: &mut TestSocket, message: serde_json::Value) {
+    use futures::SinkExt;
+    socket
+        .send(tokio_tungstenite::tungstenite::Message::Text(
+            message.to_string(),
+        ))
+        .await
+        .expect("Failed to send");
+}
+
+/// Next JSON message, or `None` if nothing arrives within `wait`
+async fn ws_recv_within(socket: &mut TestSocket, wait: Duration) -> Option<serde_json::Value> {
+    use futures::StreamExt;
+    loop {
+        let message = timeout(wait, socket.next()).await.ok()?;
+        match message
+            .expect("Connection closed")
+            .expect("Failed to receive")
+        {
+            tokio_tungstenite::tungstenite::Message::Text(text) => {
+                return Some(serde_json::from_str(&text).expect("Message is not JSON"));
+            }
+            _ => continue,
+        }
+    }
+}
+
+async fn ws_recv(socket: &mut TestSocket) -> serde_json::Value {
+    ws_recv_within(socket, Duration::from_secs(5))
+        .await
+        .expect("Timed out waiting for a message")
+}
+
+#[tokio::test]
+async fn test_websocket_document_changes_resume() {
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage.clone()).await;
+    query
+        .create_collection("users", None, None)
+        .await
+        .expect("Failed to create collection");
+    let (server, addr) = start_test_websocket(storage.clone(), query.clone(), streaming, 100).await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/", addr))
+        .await
+        .expect("Failed to connect");
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "subscribe",
+            "id": "admins",
+            "collection": "users",
+            "filter": {"role": "admin"}
+        }),
+    )
+    .await;
+    assert_eq!(ws_recv(&mut socket).await["type"], "subscribed");
+
+    let inserted = query
+        .insert(
+            "users",
+            &[
+                serde_json::json!({"name": "u1", "role": "admin"}),
+                serde_json::json!({"name": "u2", "role": "member"}),
+            ],
+        )
+        .await
+        .expect("Failed to insert");
+
+    // Only the document matching the filter is reported
+    let change = ws_recv(&mut socket).await;
+    assert_eq!(change["type"], "change");
+    assert_eq!(change["operation"], "insert");
+    assert_eq!(change["document_id"], inserted.ids[0].as_str());
+    assert_eq!(change["document"]["name"], "u1");
+    let token = change["resume_token"].as_str().unwrap().to_string();
+    ws_send(
+        &mut socket,
+        serde_json::json!({"type": "ack", "id": "admins", "resume_token": token}),
+    )
+    .await;
+    drop(socket);
+
+    // Changes made while disconnected are delivered after resuming
+    storage
+        .delete_document("users", &inserted.ids[0])
+        .await
+        .expect("Failed to delete");
+    query
+        .insert(
+            "users",
+            &[serde_json::json!({"name": "u3", "role": "admin"})],
+        )
+        .await
+        .expect("Failed to insert");
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/", addr))
+        .await
+        .expect("Failed to reconnect");
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "subscribe",
+            "id": "admins",
+            "collection": "users",
+            "filter": {"role": "admin"},
+            "resume_token": token
+        }),
+    )
+    .await;
+    assert_eq!(ws_recv(&mut socket).await["type"], "subscribed");
+    let deleted = ws_recv(&mut socket).await;
+    assert_eq!(deleted["operation"], "delete");
+    assert_eq!(deleted["document_id"], inserted.ids[0].as_str());
+    let added = ws_recv(&mut socket).await;
+    assert_eq!(added["operation"], "insert");
+    assert_eq!(added["document"]["name"], "u3");
+
+    // A malformed token is rejected
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "subscribe",
+            "id": "bad",
+            "collection": "users",
+            "resume_token": "not-a-token"
+        }),
+    )
+    .await;
+    let error = ws_recv(&mut socket).await;
+    assert_eq!(
+        (error["type"].as_str(), error["id"].as_str()),
+        (Some("error"), Some("bad"))
+    );
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_websocket_topic_backpressure() {
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage.clone()).await;
+    streaming
+        .create_topic(lumadb_common::types::TopicConfig::new("ws-events", 1, 1))
+        .await
+        .expect("Failed to create topic");
+    let records: Vec<lumadb_streaming::ProduceRecord> = (0..5)
+        .map(|i| lumadb_streaming::ProduceRecord {
+            key: Some(format!("key-{}", i)),
+            value: serde_json::json!({"seq": i}),
+            headers: None,
+            partition: None,
+        })
+        .collect();
+    streaming
+        .produce("ws-events", &records, 1)
+        .await
+        .expect("Failed to produce");
+    let (server, addr) = start_test_websocket(storage, query, streaming, 2).await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/", addr))
+        .await
+        .expect("Failed to connect");
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "subscribe",
+            "id": "events",
+            "topic": "ws-events",
+            "offset": "earliest"
+        }),
+    )
+    .await;
+    assert_eq!(ws_recv(&mut socket).await["type"], "subscribed");
+
+    // Delivery pauses once two records are unacknowledged
+    let first = ws_recv(&mut socket).await;
+    let second = ws_recv(&mut socket).await;
+    assert_eq!(
+        (first["offset"].as_i64(), second["offset"].as_i64()),
+        (Some(0), Some(1))
+    );
+    assert!(ws_recv_within(&mut socket, Duration::from_millis(300))
+        .await
+        .is_none());
+
+    // Acknowledging the second record acknowledges both
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "ack",
+            "id": "events",
+            "resume_token": second["resume_token"]
+        }),
+    )
+    .await;
+    let third = ws_recv(&mut socket).await;
+    assert_eq!(third["offset"].as_i64(), Some(2));
+    assert_eq!(third["value"]["seq"], 2);
+
+    // Resuming from a token continues after its record
+    let resumed = third["resume_token"].clone();
+    ws_send(
+        &mut socket,
+        serde_json::json!({"type": "unsubscribe", "id": "events"}),
+    )
+    .await;
+    loop {
+        if ws_recv(&mut socket).await["type"] == "unsubscribed" {
+            break;
+        }
+    }
+    ws_send(
+        &mut socket,
+        serde_json::json!({
+            "type": "subscribe",
+            "id": "events",
+            "topic": "ws-events",
+            "resume_token": resumed
+        }),
+    )
+    .await;
+    assert_eq!(ws_recv(&mut socket).await["type"], "subscribed");
+    assert_eq!(ws_recv(&mut socket).await["offset"].as_i64(), Some(3));
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_websocket_graphql_subscription() {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let (storage, _temp_dir) = create_test_storage().await;
+    let streaming = create_test_streaming(storage.clone()).await;
+    let query = create_test_query(storage.clone()).await;
+    query
+        .create_collection("orders", None, None)
+        .await
+        .expect("Failed to create collection");
+    let (server, addr) = start_test_websocket(storage, query.clone(), streaming, 100).await;
+
+    let mut request = format!("ws://{}/graphql", addr)
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        "graphql-transport-ws".parse().unwrap(),
+    );
+    let (mut socket, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(
+        response.headers().get("sec-websocket-protocol").unwrap(),
+        "graphql-transport-ws"
+    );
+
+    ws_send(
+        &mut socket,
+        serde_json::json!({"type": "connection_init", "payload": {}}),
+    )
+    .await;
+    assert_eq!(ws_recv(&mut socket).await["type"], "connection_ack");
+    ws_send(&mut socket, serde_json::json!({
+        "id": "1",
+        "type": "subscribe",
+        "payload": {
+            "query": "subscription { documentChanges(collection: \"orders\") { operation id document resumeToken } }"
+        }
+    })).await;
+    // Give the subscription time to start before writing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let inserted = query
+        .insert("orders", &[serde_json::json!({"total": 42})])
+        .await
+        .expect("Failed to insert");
+    let next = ws_recv(&mut socket).await;
+    assert_eq!(next["type"], "next");
+    let change = &next["payload"]["data"]["documentChanges"];
+    assert_eq!(change["operation"], "insert");
+    assert_eq!(change["id"], inserted.ids[0].as_str());
+    assert_eq!(change["document"]["total"], 42);
+    assert!(change["resumeToken"].is_string());
+
+    server.shutdown().await.unwrap();
+}
+
 // ============================================================================
 // Performance Tests (basic benchmarks)
 // ============================================================================
@@ -54,6 +54,12 @@ pub enum LumaError {
     #[error("Deadlock detected")]
     Deadlock,
 
+    #[error("Write conflict: {0}")]
+    WriteConflict(String),
+
+    #[error("Key locked by another transaction: {0}")]
+    KeyLocked(String),
+
     // Capacity errors
     #[error("Memory limit exceeded: {used} / {limit} bytes")]
     MemoryLimitExceeded { used: usize, limit: usize },
@@ -145,6 +151,7 @@ impl LumaError {
             self,
             LumaError::Io(_)
                 | LumaError::TransactionTimeout
+                | LumaError::KeyLocked(_)
                 | LumaError::WriteBufferFull
                 | LumaError::ShardUnavailable(_)
         )
@@ -175,6 +182,8 @@ impl LumaError {
             LumaError::TransactionAborted(_) => 11,
             LumaError::TransactionTimeout => 12,
             LumaError::Deadlock => 13,
+            LumaError::WriteConflict(_) => 25,
+            LumaError::KeyLocked(_) => 26,
             LumaError::MemoryLimitExceeded { .. } => 14,
             LumaError::WriteBufferFull => 15,
             LumaError::TooManyOpenFiles => 16,
@@ -9,23 +9,56 @@ pub use coordinator::ShardCoordinator;
 pub use core_shard::CoreShard;
 pub use router::ShardRouter;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-/// Stub shard handle for backward compatibility
-pub struct ShardHandle;
+use parking_lot::RwLock;
+
+/// In-memory ordered key space owned by one shard
+pub struct ShardHandle {
+    id: usize,
+    data: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+    latch: tokio::sync::Mutex<()>,
+}
 
 impl ShardHandle {
-    pub async fn put(&self, _key: &[u8], _value: &[u8]) -> crate::Result<()> {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            data: RwLock::new(BTreeMap::new()),
+            latch: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Latch serializing read-modify-write sequences, such as transactional
+    /// prewrites, against this shard
+    pub fn latch(&self) -> &tokio::sync::Mutex<()> {
+        &self.latch
+    }
+
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        self.data.write().insert(key.to_vec(), value.to_vec());
         Ok(())
     }
-    pub async fn get(&self, _key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
-        Ok(None)
+    pub async fn get(&self, key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        Ok(self.data.read().get(key).cloned())
     }
-    pub async fn delete(&self, _key: &[u8]) -> crate::Result<()> {
+    pub async fn delete(&self, key: &[u8]) -> crate::Result<()> {
+        self.data.write().remove(key);
         Ok(())
     }
-    pub async fn scan_prefix(&self, _prefix: &[u8]) -> crate::Result<Vec<(Vec<u8>, Vec<u8>)>> {
-        Ok(vec![])
+    pub async fn scan_prefix(&self, prefix: &[u8]) -> crate::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .data
+            .read()
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
     }
     pub async fn index_vector(&self, _key: &[u8], _vector: &[f32]) -> crate::Result<()> {
         Ok(())
@@ -40,23 +73,57 @@ impl ShardHandle {
 
 // Legacy ShardManager for backward compat with existing code
 pub struct ShardManager {
-    num_shards: usize,
+    router: ShardRouter,
+    shards: Vec<Arc<ShardHandle>>,
 }
 
 impl ShardManager {
-    pub async fn new(_config: &crate::config::Config) -> crate::Result<Self> {
-        Ok(Self { num_shards: num_cpus::get() })
+    pub async fn new(config: &crate::config::Config) -> crate::Result<Self> {
+        let num_shards = match config.sharding.num_shards {
+            0 => num_cpus::get(),
+            n => n,
+        };
+        Ok(Self::with_shards(num_shards))
+    }
+
+    /// Manager over `num_shards` in-process shards
+    pub fn with_shards(num_shards: usize) -> Self {
+        let num_shards = num_shards.max(1);
+        Self {
+            router: ShardRouter::new(num_shards),
+            shards: (0..num_shards)
+                .map(|id| Arc::new(ShardHandle::new(id)))
+                .collect(),
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Index of the shard owning `key`
+    pub fn shard_id(&self, key: &[u8]) -> usize {
+        self.router.route_key(key)
+    }
+
+    pub fn shard(&self, id: usize) -> Option<Arc<ShardHandle>> {
+        self.shards.get(id).cloned()
     }
 
-    pub fn get_shard(&self, _key: &[u8]) -> Arc<ShardHandle> {
-        Arc::new(ShardHandle)
+    pub fn get_shard(&self, key: &[u8]) -> Arc<ShardHandle> {
+        self.shards[self.shard_id(key)].clone()
     }
 
     pub fn all_shards(&self) -> Vec<Arc<ShardHandle>> {
-        (0..self.num_shards).map(|_| Arc::new(ShardHandle)).collect()
+        self.shards.clone()
     }
 
-    pub async fn delete_prefix(&self, _prefix: &[u8]) -> crate::Result<()> {
+    pub async fn delete_prefix(&self, prefix: &[u8]) -> crate::Result<()> {
+        for shard in &self.shards {
+            for (key, _) in shard.scan_prefix(prefix).await? {
+                shard.delete(&key).await?;
+            }
+        }
         Ok(())
     }
 
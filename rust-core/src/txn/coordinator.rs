@@ -1,67 +1,278 @@
-use std::sync::atomic::{AtomicU64, AtomicU32, Ordering};
+//! Percolator-style transaction coordinator
+//!
+//! Writes are buffered in a [`Transaction`] and committed in two phases over
+//! the shards of a [`ShardManager`]:
+//!
+//! 1. Prewrite locks every key, the primary key's shard first. Each shard
+//!    prewrites its keys atomically.
+//! 2. Commit takes a commit timestamp and commits the primary key. That
+//!    single write is the commit point. Secondary keys are then committed
+//!    best-effort; any lock left behind is rolled forward by the
+//!    [`LockResolver`] of whoever runs into it.
+
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
-/// Transaction coordinator using Percolator model
-pub struct TransactionCoordinator {
-    tso: Arc<TimestampOracle>,
-    // storage: Arc<dyn TransactionalStorage>, // To be implemented
-    // lock_manager: Arc<LockManager>, // To be implemented
-}
+use super::oracle::{Timestamp, TimestampOracle};
+use super::resolver::LockResolver;
+use super::store::{LockCheck, Mutation, TxnShard};
+use crate::error::{LumaError, Result};
+use crate::shard::ShardManager;
 
-/// Timestamp Oracle for global ordering
-pub struct TimestampOracle {
-    current: AtomicU64,
-    logical: AtomicU32,
-    // physical_shift: u64,
-}
+/// Default lifetime of prewrite locks in milliseconds
+pub const DEFAULT_LOCK_TTL_MS: u64 = 3_000;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub struct Timestamp {
-    pub physical: u64,
-    pub logical: u32,
+/// Buffered writes of a transaction reading at `start_ts`
+pub struct Transaction {
+    start_ts: Timestamp,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
 }
 
-impl TimestampOracle {
-    pub fn new() -> Self {
-        Self {
-            current: AtomicU64::new(0),
-            logical: AtomicU32::new(0),
-        }
+impl Transaction {
+    pub fn start_ts(&self) -> Timestamp {
+        self.start_ts
     }
 
-    /// Get a new unique timestamp
-    pub fn get_timestamp(&self) -> Timestamp {
-        let physical = self.current.fetch_add(1, Ordering::SeqCst);
-        let logical = self.logical.fetch_add(1, Ordering::SeqCst);
-        
-        Timestamp {
-            physical,
-            logical,
-        }
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.writes.insert(key.into(), Some(value.into()));
+    }
+
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) {
+        self.writes.insert(key.into(), None);
+    }
+
+    /// Key whose commit decides the whole transaction
+    pub fn primary(&self) -> Option<&[u8]> {
+        self.writes.keys().next().map(Vec::as_slice)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.writes.is_empty()
     }
 }
 
+/// Transaction coordinator using Percolator model
+pub struct TransactionCoordinator {
+    tso: Arc<TimestampOracle>,
+    shards: Arc<ShardManager>,
+    resolver: LockResolver,
+    lock_ttl: u64,
+}
+
 impl TransactionCoordinator {
-    pub fn new() -> Self {
+    pub fn new(shards: Arc<ShardManager>) -> Self {
+        Self::with_oracle(shards, Arc::new(TimestampOracle::new()))
+    }
+
+    pub fn with_oracle(shards: Arc<ShardManager>, tso: Arc<TimestampOracle>) -> Self {
         Self {
-            tso: Arc::new(TimestampOracle::new()),
+            resolver: LockResolver::new(shards.clone(), tso.clone()),
+            tso,
+            shards,
+            lock_ttl: DEFAULT_LOCK_TTL_MS,
         }
     }
 
+    /// Lifetime of the locks this coordinator takes, in milliseconds
+    pub fn with_lock_ttl(mut self, ttl_ms: u64) -> Self {
+        self.lock_ttl = ttl_ms;
+        self
+    }
+
     pub fn get_tso(&self) -> Arc<TimestampOracle> {
         self.tso.clone()
     }
-}
 
-// Placeholder for Lock and Write types
-pub struct Lock {
-    pub ts: Timestamp,
-    pub primary_key: Vec<u8>,
-    pub ttl: u64,
-}
+    pub fn resolver(&self) -> &LockResolver {
+        &self.resolver
+    }
+
+    pub fn begin(&self) -> Transaction {
+        Transaction {
+            start_ts: self.tso.get_timestamp(),
+            writes: BTreeMap::new(),
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> TxnShard {
+        TxnShard::new(self.shards.get_shard(key))
+    }
 
-pub struct Write {
-    pub start_ts: Timestamp,
-    pub commit_ts: Timestamp,
+    /// Read `key` as of the transaction's snapshot, seeing its own writes
+    pub async fn get(&self, txn: &Transaction, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = txn.writes.get(key) {
+            return Ok(value.clone());
+        }
+        self.read(key, txn.start_ts).await
+    }
+
+    /// Read the newest version of `key` committed at or before `ts`,
+    /// resolving locks of finished or abandoned transactions on the way
+    pub async fn read(&self, key: &[u8], ts: Timestamp) -> Result<Option<Vec<u8>>> {
+        let shard = self.shard(key);
+        loop {
+            match shard.get(key, ts).await? {
+                LockCheck::Ready(value) => return Ok(value),
+                LockCheck::Locked { key, lock } => {
+                    if !self.resolver.resolve(&key, &lock).await? {
+                        return Err(LumaError::KeyLocked(format!(
+                            "{key:?} by transaction {}",
+                            lock.start_ts
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Commit `txn`, returning its commit timestamp
+    pub async fn commit(&self, txn: Transaction) -> Result<Timestamp> {
+        if txn.is_read_only() {
+            return Ok(txn.start_ts);
+        }
+        self.prewrite(&txn).await?;
+        let commit_ts = self.commit_primary(&txn).await?;
+        self.commit_secondaries(&txn, commit_ts).await;
+        Ok(commit_ts)
+    }
+
+    /// Mutations grouped by owning shard, the primary key's shard first
+    fn group_by_shard<'a>(
+        &self,
+        keys: impl Iterator<Item = (&'a Vec<u8>, &'a Option<Vec<u8>>)>,
+        primary: &[u8],
+    ) -> Vec<(usize, Vec<Mutation>)> {
+        let primary_shard = self.shards.shard_id(primary);
+        let mut groups: BTreeMap<usize, Vec<Mutation>> = BTreeMap::new();
+        for (key, value) in keys {
+            groups
+                .entry(self.shards.shard_id(key))
+                .or_default()
+                .push((key.clone(), value.clone()));
+        }
+
+        let mut ordered: Vec<_> = groups.into_iter().collect();
+        ordered.sort_by_key(|(shard_id, _)| *shard_id != primary_shard);
+        ordered
+    }
+
+    /// First phase: lock every key of `txn`. On failure whatever was
+    /// prewritten is rolled back.
+    pub async fn prewrite(&self, txn: &Transaction) -> Result<()> {
+        let Some(primary) = txn.primary() else {
+            return Ok(());
+        };
+
+        for (shard_id, mutations) in self.group_by_shard(txn.writes.iter(), primary) {
+            if let Err(e) = self
+                .prewrite_shard(shard_id, &mutations, primary, txn.start_ts)
+                .await
+            {
+                if let Err(rollback) = self.rollback(txn).await {
+                    tracing::warn!("rollback of {} failed: {}", txn.start_ts, rollback);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn prewrite_shard(
+        &self,
+        shard_id: usize,
+        mutations: &[Mutation],
+        primary: &[u8],
+        start_ts: Timestamp,
+    ) -> Result<()> {
+        let shard = self
+            .shards
+            .shard(shard_id)
+            .ok_or(LumaError::ShardNotFound(shard_id as u32))?;
+        let shard = TxnShard::new(shard);
+
+        loop {
+            match shard
+                .prewrite(mutations, primary, start_ts, self.lock_ttl)
+                .await?
+            {
+                LockCheck::Ready(()) => return Ok(()),
+                LockCheck::Locked { key, lock } => {
+                    if !self.resolver.resolve(&key, &lock).await? {
+                        return Err(LumaError::KeyLocked(format!(
+                            "{key:?} by transaction {}",
+                            lock.start_ts
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Second phase, commit point: commit the primary key and return the
+    /// commit timestamp. Fails if the primary lock was resolved away.
+    pub async fn commit_primary(&self, txn: &Transaction) -> Result<Timestamp> {
+        let Some(primary) = txn.primary() else {
+            return Ok(txn.start_ts);
+        };
+
+        let commit_ts = self.tso.get_timestamp();
+        if let Err(e) = self
+            .shard(primary)
+            .commit(&[primary.to_vec()], txn.start_ts, commit_ts)
+            .await
+        {
+            if let Err(rollback) = self.rollback(txn).await {
+                tracing::warn!("rollback of {} failed: {}", txn.start_ts, rollback);
+            }
+            return Err(e);
+        }
+        Ok(commit_ts)
+    }
+
+    /// Commit the remaining keys after the commit point. Failures only
+    /// leave locks that readers roll forward, so they are logged.
+    pub async fn commit_secondaries(&self, txn: &Transaction, commit_ts: Timestamp) {
+        let Some(primary) = txn.primary() else {
+            return;
+        };
+
+        let secondaries = txn
+            .writes
+            .iter()
+            .filter(|(key, _)| key.as_slice() != primary);
+        for (shard_id, mutations) in self.group_by_shard(secondaries, primary) {
+            let Some(shard) = self.shards.shard(shard_id) else {
+                continue;
+            };
+            let keys: Vec<_> = mutations.into_iter().map(|(key, _)| key).collect();
+            if let Err(e) = TxnShard::new(shard)
+                .commit(&keys, txn.start_ts, commit_ts)
+                .await
+            {
+                tracing::warn!(
+                    "commit of {} on shard {} failed: {}",
+                    txn.start_ts,
+                    shard_id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Roll back every key of `txn` that may have been prewritten
+    pub async fn rollback(&self, txn: &Transaction) -> Result<()> {
+        let Some(primary) = txn.primary() else {
+            return Ok(());
+        };
+
+        for (shard_id, mutations) in self.group_by_shard(txn.writes.iter(), primary) {
+            let shard = self
+                .shards
+                .shard(shard_id)
+                .ok_or(LumaError::ShardNotFound(shard_id as u32))?;
+            let keys: Vec<_> = mutations.into_iter().map(|(key, _)| key).collect();
+            TxnShard::new(shard).rollback(&keys, txn.start_ts).await?;
+        }
+        Ok(())
+    }
 }
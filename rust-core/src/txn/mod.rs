@@ -1,3 +1,9 @@
 pub mod coordinator;
+pub mod oracle;
+pub mod resolver;
+pub mod store;
 
-pub use coordinator::{TransactionCoordinator, TimestampOracle};
+pub use coordinator::{Transaction, TransactionCoordinator};
+pub use oracle::{Timestamp, TimestampOracle};
+pub use resolver::LockResolver;
+pub use store::{Lock, TxnStatus, Write, WriteKind};
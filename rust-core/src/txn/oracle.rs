@@ -0,0 +1,199 @@
+//! Hybrid logical clock timestamp oracle
+//!
+//! Timestamps pair a wall-clock millisecond reading with a logical counter.
+//! The oracle never goes backwards: when the wall clock stalls or steps back,
+//! the logical counter advances instead, and timestamps observed from other
+//! nodes push the clock forward through [`TimestampOracle::update`].
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Source of wall-clock milliseconds
+pub type PhysicalClock = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+/// Hybrid logical timestamp, ordered by physical then logical component
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize,
+)]
+pub struct Timestamp {
+    pub physical: u64,
+    pub logical: u32,
+}
+
+impl Timestamp {
+    pub const ZERO: Timestamp = Timestamp {
+        physical: 0,
+        logical: 0,
+    };
+    pub const MAX: Timestamp = Timestamp {
+        physical: u64::MAX,
+        logical: u32::MAX,
+    };
+
+    pub const ENCODED_LEN: usize = 12;
+
+    /// Big-endian encoding that sorts like the timestamp
+    pub fn to_bytes(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[..8].copy_from_slice(&self.physical.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.logical.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return None;
+        }
+        Some(Self {
+            physical: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+            logical: u32::from_be_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.physical, self.logical)
+    }
+}
+
+/// Timestamp Oracle for global ordering
+pub struct TimestampOracle {
+    clock: PhysicalClock,
+    last: Mutex<Timestamp>,
+}
+
+impl TimestampOracle {
+    /// Oracle driven by the system clock
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default()
+        }))
+    }
+
+    /// Oracle driven by a custom physical clock, e.g. a manual clock in tests
+    pub fn with_clock(clock: PhysicalClock) -> Self {
+        Self {
+            clock,
+            last: Mutex::new(Timestamp::ZERO),
+        }
+    }
+
+    /// Current wall-clock reading in milliseconds
+    pub fn physical_now(&self) -> u64 {
+        (self.clock)()
+    }
+
+    /// Get a new unique timestamp
+    pub fn get_timestamp(&self) -> Timestamp {
+        let now = self.physical_now();
+        let mut last = self.last.lock();
+        *last = if now > last.physical {
+            Timestamp {
+                physical: now,
+                logical: 0,
+            }
+        } else {
+            Timestamp {
+                physical: last.physical,
+                logical: last.logical + 1,
+            }
+        };
+        *last
+    }
+
+    /// Merge a timestamp observed from another node so later timestamps
+    /// issued here order after it
+    pub fn update(&self, observed: Timestamp) {
+        let mut last = self.last.lock();
+        if observed > *last {
+            *last = observed;
+        }
+    }
+
+    /// Latest timestamp issued or observed
+    pub fn last(&self) -> Timestamp {
+        *self.last.lock()
+    }
+}
+
+impl Default for TimestampOracle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_hlc_is_monotonic_when_clock_stalls_or_steps_back() {
+        let wall = Arc::new(AtomicU64::new(100));
+        let clock = wall.clone();
+        let oracle = TimestampOracle::with_clock(Arc::new(move || clock.load(Ordering::SeqCst)));
+
+        let a = oracle.get_timestamp();
+        let b = oracle.get_timestamp();
+        assert_eq!(
+            a,
+            Timestamp {
+                physical: 100,
+                logical: 0
+            }
+        );
+        assert_eq!(
+            b,
+            Timestamp {
+                physical: 100,
+                logical: 1
+            }
+        );
+
+        wall.store(90, Ordering::SeqCst);
+        let c = oracle.get_timestamp();
+        assert!(c > b);
+
+        wall.store(200, Ordering::SeqCst);
+        assert_eq!(
+            oracle.get_timestamp(),
+            Timestamp {
+                physical: 200,
+                logical: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_hlc_update_orders_after_observed() {
+        let oracle = TimestampOracle::with_clock(Arc::new(|| 10));
+        let remote = Timestamp {
+            physical: 50,
+            logical: 7,
+        };
+        oracle.update(remote);
+        assert!(oracle.get_timestamp() > remote);
+    }
+
+    #[test]
+    fn test_timestamp_encoding_sorts() {
+        let a = Timestamp {
+            physical: 1,
+            logical: u32::MAX,
+        };
+        let b = Timestamp {
+            physical: 2,
+            logical: 0,
+        };
+        assert!(a.to_bytes() < b.to_bytes());
+        assert_eq!(Timestamp::from_bytes(&b.to_bytes()), Some(b));
+    }
+}
@@ -0,0 +1,60 @@
+//! Resolution of locks left behind by other transactions
+//!
+//! A lock only says which primary key decides its transaction. The resolver
+//! asks the primary's shard for the transaction status and then rolls the
+//! lock forward (primary committed) or back (primary rolled back or its lock
+//! expired). Locks whose primary is still alive are left alone.
+
+use std::sync::Arc;
+
+use super::oracle::TimestampOracle;
+use super::store::{Lock, TxnShard, TxnStatus};
+use crate::error::Result;
+use crate::shard::ShardManager;
+
+pub struct LockResolver {
+    shards: Arc<ShardManager>,
+    tso: Arc<TimestampOracle>,
+}
+
+impl LockResolver {
+    pub fn new(shards: Arc<ShardManager>, tso: Arc<TimestampOracle>) -> Self {
+        Self { shards, tso }
+    }
+
+    /// Resolve `lock` found on `key`. Returns `false` when the owning
+    /// transaction is still running and the lock must be waited out.
+    pub async fn resolve(&self, key: &[u8], lock: &Lock) -> Result<bool> {
+        let primary = TxnShard::new(self.shards.get_shard(&lock.primary_key));
+        let status = primary
+            .check_txn_status(&lock.primary_key, lock.start_ts, self.tso.physical_now())
+            .await?;
+
+        let shard = TxnShard::new(self.shards.get_shard(key));
+        match status {
+            TxnStatus::Committed(commit_ts) => {
+                self.tso.update(commit_ts);
+                shard
+                    .commit(&[key.to_vec()], lock.start_ts, commit_ts)
+                    .await?;
+            }
+            TxnStatus::RolledBack => shard.rollback(&[key.to_vec()], lock.start_ts).await?,
+            TxnStatus::Locked { .. } => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Sweep every shard for orphaned locks, e.g. after a coordinator crash.
+    /// Returns how many locks were rolled forward or back.
+    pub async fn resolve_orphans(&self) -> Result<usize> {
+        let mut resolved = 0;
+        for shard in self.shards.all_shards() {
+            for (key, lock) in TxnShard::new(shard).locks().await? {
+                if self.resolve(&key, &lock).await? {
+                    resolved += 1;
+                }
+            }
+        }
+        Ok(resolved)
+    }
+}
@@ -0,0 +1,353 @@
+//! Per-shard Percolator columns
+//!
+//! Every shard keeps three column families inside its key space:
+//!
+//! - data:  `key @ start_ts -> value` written at prewrite
+//! - lock:  `key -> Lock` held between prewrite and commit
+//! - write: `key @ commit_ts -> Write` making a version visible
+//!
+//! Rollbacks leave a `WriteKind::Rollback` record at the transaction's start
+//! timestamp so a delayed prewrite of the same transaction cannot succeed
+//! after its locks were cleaned up. All operations on a shard run under the
+//! shard latch, which makes each of them atomic.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::oracle::Timestamp;
+use crate::error::{LumaError, Result};
+use crate::shard::ShardHandle;
+
+const DATA_CF: &[u8] = b"\0txn\0d";
+const LOCK_CF: &[u8] = b"\0txn\0l";
+const WRITE_CF: &[u8] = b"\0txn\0w";
+
+/// Kind of a committed or pending mutation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteKind {
+    Put,
+    Delete,
+    Rollback,
+}
+
+/// Lock left on a key by a prewritten transaction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    pub start_ts: Timestamp,
+    pub primary_key: Vec<u8>,
+    /// Lifetime in milliseconds, counted from the physical part of `start_ts`
+    pub ttl: u64,
+    pub kind: WriteKind,
+}
+
+impl Lock {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.start_ts.physical.saturating_add(self.ttl) <= now
+    }
+}
+
+/// Record in the write column pointing at the data written at `start_ts`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Write {
+    pub start_ts: Timestamp,
+    pub kind: WriteKind,
+}
+
+/// Result of an operation that may run into another transaction's lock
+#[derive(Debug)]
+pub enum LockCheck<T> {
+    Ready(T),
+    Locked { key: Vec<u8>, lock: Lock },
+}
+
+/// State of a transaction as recorded on its primary key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxnStatus {
+    Committed(Timestamp),
+    RolledBack,
+    /// The primary lock is still alive for this many milliseconds
+    Locked {
+        ttl_remaining: u64,
+    },
+}
+
+/// A user key with the buffered mutation to apply to it; `None` deletes
+pub type Mutation = (Vec<u8>, Option<Vec<u8>>);
+
+fn encode(cf: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(cf.len() + 4 + key.len() + Timestamp::ENCODED_LEN);
+    encoded.extend_from_slice(cf);
+    encoded.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(key);
+    encoded
+}
+
+fn data_key(key: &[u8], start_ts: Timestamp) -> Vec<u8> {
+    let mut encoded = encode(DATA_CF, key);
+    encoded.extend_from_slice(&start_ts.to_bytes());
+    encoded
+}
+
+/// Write records sort newest first by storing the complemented timestamp
+fn write_key(key: &[u8], commit_ts: Timestamp) -> Vec<u8> {
+    let mut encoded = encode(WRITE_CF, key);
+    encoded.extend(commit_ts.to_bytes().iter().map(|b| !b));
+    encoded
+}
+
+fn decode_user_key(cf: &[u8], encoded: &[u8]) -> Option<Vec<u8>> {
+    let rest = encoded.strip_prefix(cf)?;
+    let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    rest.get(4..4 + len).map(<[u8]>::to_vec)
+}
+
+/// Percolator operations over one shard
+pub struct TxnShard {
+    shard: Arc<ShardHandle>,
+}
+
+impl TxnShard {
+    pub fn new(shard: Arc<ShardHandle>) -> Self {
+        Self { shard }
+    }
+
+    async fn lock(&self, key: &[u8]) -> Result<Option<Lock>> {
+        match self.shard.get(&encode(LOCK_CF, key)).await? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write records of `key`, newest commit first
+    async fn writes(&self, key: &[u8]) -> Result<Vec<(Timestamp, Write)>> {
+        let prefix = encode(WRITE_CF, key);
+        let mut writes = Vec::new();
+        for (encoded, value) in self.shard.scan_prefix(&prefix).await? {
+            let inverted: Vec<u8> = encoded[prefix.len()..].iter().map(|b| !b).collect();
+            let commit_ts = Timestamp::from_bytes(&inverted).ok_or_else(|| {
+                LumaError::Corruption(format!("bad write record key {encoded:?}"))
+            })?;
+            writes.push((commit_ts, bincode::deserialize(&value)?));
+        }
+        Ok(writes)
+    }
+
+    /// Write record left by the transaction that started at `start_ts`
+    async fn write_of(
+        &self,
+        key: &[u8],
+        start_ts: Timestamp,
+    ) -> Result<Option<(Timestamp, Write)>> {
+        Ok(self
+            .writes(key)
+            .await?
+            .into_iter()
+            .find(|(_, write)| write.start_ts == start_ts))
+    }
+
+    async fn put_write(&self, key: &[u8], commit_ts: Timestamp, write: Write) -> Result<()> {
+        self.shard
+            .put(&write_key(key, commit_ts), &bincode::serialize(&write)?)
+            .await
+    }
+
+    /// Read the newest version of `key` committed at or before `ts`
+    pub async fn get(&self, key: &[u8], ts: Timestamp) -> Result<LockCheck<Option<Vec<u8>>>> {
+        let _latch = self.shard.latch().lock().await;
+
+        if let Some(lock) = self.lock(key).await? {
+            if lock.start_ts <= ts {
+                return Ok(LockCheck::Locked {
+                    key: key.to_vec(),
+                    lock,
+                });
+            }
+        }
+
+        for (commit_ts, write) in self.writes(key).await? {
+            if commit_ts > ts {
+                continue;
+            }
+            match write.kind {
+                WriteKind::Rollback => continue,
+                WriteKind::Delete => return Ok(LockCheck::Ready(None)),
+                WriteKind::Put => {
+                    let value = self.shard.get(&data_key(key, write.start_ts)).await?;
+                    return Ok(LockCheck::Ready(value));
+                }
+            }
+        }
+        Ok(LockCheck::Ready(None))
+    }
+
+    /// Lock every key of `mutations` and stage their values.
+    ///
+    /// Either all keys are locked or none are: a conflicting write fails the
+    /// prewrite, and a lock held by another transaction is reported back so
+    /// the caller can resolve it and retry.
+    pub async fn prewrite(
+        &self,
+        mutations: &[Mutation],
+        primary: &[u8],
+        start_ts: Timestamp,
+        ttl: u64,
+    ) -> Result<LockCheck<()>> {
+        let _latch = self.shard.latch().lock().await;
+
+        let mut pending = Vec::with_capacity(mutations.len());
+        for (key, value) in mutations {
+            if let Some((commit_ts, write)) = self.writes(key).await?.into_iter().next() {
+                if write.kind == WriteKind::Rollback && write.start_ts == start_ts {
+                    return Err(LumaError::TransactionAborted(format!(
+                        "transaction {start_ts} was rolled back"
+                    )));
+                }
+                if commit_ts >= start_ts {
+                    return Err(LumaError::WriteConflict(format!(
+                        "key {key:?} was written at {commit_ts} after transaction start {start_ts}"
+                    )));
+                }
+            }
+            match self.lock(key).await? {
+                Some(lock) if lock.start_ts == start_ts => {}
+                Some(lock) => {
+                    return Ok(LockCheck::Locked {
+                        key: key.clone(),
+                        lock,
+                    })
+                }
+                None => pending.push((key, value)),
+            }
+        }
+
+        for (key, value) in pending {
+            let kind = match value {
+                Some(value) => {
+                    self.shard.put(&data_key(key, start_ts), value).await?;
+                    WriteKind::Put
+                }
+                None => WriteKind::Delete,
+            };
+            let lock = Lock {
+                start_ts,
+                primary_key: primary.to_vec(),
+                ttl,
+                kind,
+            };
+            self.shard
+                .put(&encode(LOCK_CF, key), &bincode::serialize(&lock)?)
+                .await?;
+        }
+        Ok(LockCheck::Ready(()))
+    }
+
+    /// Replace the locks of `start_ts` on `keys` with write records at
+    /// `commit_ts`. Keys already committed by the same transaction are skipped.
+    pub async fn commit(
+        &self,
+        keys: &[Vec<u8>],
+        start_ts: Timestamp,
+        commit_ts: Timestamp,
+    ) -> Result<()> {
+        let _latch = self.shard.latch().lock().await;
+
+        for key in keys {
+            match self.lock(key).await? {
+                Some(lock) if lock.start_ts == start_ts => {
+                    let write = Write {
+                        start_ts,
+                        kind: lock.kind,
+                    };
+                    self.put_write(key, commit_ts, write).await?;
+                    self.shard.delete(&encode(LOCK_CF, key)).await?;
+                }
+                _ => match self.write_of(key, start_ts).await? {
+                    Some((_, write)) if write.kind != WriteKind::Rollback => {}
+                    _ => {
+                        return Err(LumaError::TransactionAborted(format!(
+                            "transaction {start_ts} lost its lock on {key:?}"
+                        )))
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Roll back `start_ts` on `keys`, leaving rollback records behind
+    pub async fn rollback(&self, keys: &[Vec<u8>], start_ts: Timestamp) -> Result<()> {
+        let _latch = self.shard.latch().lock().await;
+        for key in keys {
+            self.rollback_key(key, start_ts).await?;
+        }
+        Ok(())
+    }
+
+    async fn rollback_key(&self, key: &[u8], start_ts: Timestamp) -> Result<()> {
+        match self.write_of(key, start_ts).await? {
+            Some((_, write)) if write.kind == WriteKind::Rollback => return Ok(()),
+            Some((commit_ts, _)) => {
+                return Err(LumaError::Internal(format!(
+                    "transaction {start_ts} already committed at {commit_ts}"
+                )))
+            }
+            None => {}
+        }
+
+        if let Some(lock) = self.lock(key).await? {
+            if lock.start_ts == start_ts {
+                self.shard.delete(&encode(LOCK_CF, key)).await?;
+                self.shard.delete(&data_key(key, start_ts)).await?;
+            }
+        }
+        let write = Write {
+            start_ts,
+            kind: WriteKind::Rollback,
+        };
+        self.put_write(key, start_ts, write).await
+    }
+
+    /// Decide the fate of the transaction that started at `start_ts` from
+    /// its primary key. An expired or missing primary lock is rolled back
+    /// here, so a crashed coordinator can no longer commit it.
+    pub async fn check_txn_status(
+        &self,
+        primary: &[u8],
+        start_ts: Timestamp,
+        now: u64,
+    ) -> Result<TxnStatus> {
+        let _latch = self.shard.latch().lock().await;
+
+        if let Some((commit_ts, write)) = self.write_of(primary, start_ts).await? {
+            return Ok(match write.kind {
+                WriteKind::Rollback => TxnStatus::RolledBack,
+                _ => TxnStatus::Committed(commit_ts),
+            });
+        }
+
+        if let Some(lock) = self.lock(primary).await? {
+            if lock.start_ts == start_ts && !lock.is_expired(now) {
+                let expires = lock.start_ts.physical.saturating_add(lock.ttl);
+                return Ok(TxnStatus::Locked {
+                    ttl_remaining: expires - now,
+                });
+            }
+        }
+
+        self.rollback_key(primary, start_ts).await?;
+        Ok(TxnStatus::RolledBack)
+    }
+
+    /// Every lock currently held on this shard
+    pub async fn locks(&self) -> Result<Vec<(Vec<u8>, Lock)>> {
+        let _latch = self.shard.latch().lock().await;
+        let mut locks = Vec::new();
+        for (encoded, value) in self.shard.scan_prefix(LOCK_CF).await? {
+            let key = decode_user_key(LOCK_CF, &encoded)
+                .ok_or_else(|| LumaError::Corruption(format!("bad lock key {encoded:?}")))?;
+            locks.push((key, bincode::deserialize(&value)?));
+        }
+        Ok(locks)
+    }
+}
@@ -7,6 +7,7 @@
 //! - Lock-free HNSW index for concurrent insert/search
 //! - Sharded architecture for linear scaling with cores
 //! - Batch operations for maximum throughput
+//! - Optional int8 scalar quantization of stored vectors
 
 pub mod ultra_engine;
 
@@ -16,6 +17,7 @@ pub use ultra_engine::{
     HNSWIndex,
     HNSWConfig,
     DistanceMetric,
+    Quantization,
     l2_distance_simd,
     inner_product_simd,
     normalize,
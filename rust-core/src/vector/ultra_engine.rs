@@ -165,6 +165,110 @@ impl DistanceMetric {
     }
 }
 
+// ============================================================================
+// Quantization
+// ============================================================================
+
+/// How an index stores the vectors of its nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quantization {
+    /// Full `f32` vectors
+    #[default]
+    None,
+    /// One byte per dimension, scaled to the vector's own min/max
+    ///
+    /// Vectors are inserted one at a time with no training set, so each is
+    /// scaled to its own range rather than per-dimension bounds. Queries
+    /// stay `f32` and are compared against the codes directly.
+    Scalar,
+}
+
+/// A node's vector, in the form its index stores it
+pub enum StoredVector {
+    Full(Vec<f32>),
+    Scalar {
+        codes: Vec<u8>,
+        /// Value of code 0
+        min: f32,
+        /// Value of one code step
+        scale: f32,
+    },
+}
+
+impl StoredVector {
+    /// Store `vector` as `quantization` says
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // codes are 0..=255
+    pub fn encode(vector: Vec<f32>, quantization: Quantization) -> Self {
+        match quantization {
+            Quantization::None => StoredVector::Full(vector),
+            Quantization::Scalar => {
+                let min = vector.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = vector.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                let scale = if max > min { (max - min) / 255.0 } else { 0.0 };
+                let codes = vector
+                    .iter()
+                    .map(|x| {
+                        if scale > 0.0 {
+                            ((x - min) / scale).round() as u8
+                        } else {
+                            0
+                        }
+                    })
+                    .collect();
+                StoredVector::Scalar { codes, min, scale }
+            }
+        }
+    }
+
+    /// The vector, reconstructed from its codes when quantized
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<f32> {
+        match self {
+            StoredVector::Full(vector) => vector.clone(),
+            StoredVector::Scalar { codes, min, scale } => {
+                codes.iter().map(|&c| min + scale * f32::from(c)).collect()
+            }
+        }
+    }
+
+    /// Distance from a full-precision `query` to this vector
+    #[inline]
+    #[must_use]
+    pub fn distance(&self, metric: DistanceMetric, query: &[f32]) -> f32 {
+        match self {
+            StoredVector::Full(vector) => metric.distance(query, vector),
+            StoredVector::Scalar { codes, min, scale } => match metric {
+                DistanceMetric::L2 => query
+                    .iter()
+                    .zip(codes)
+                    .map(|(q, &c)| {
+                        let diff = q - (min + scale * f32::from(c));
+                        diff * diff
+                    })
+                    .sum(),
+                DistanceMetric::InnerProduct | DistanceMetric::Cosine => {
+                    let dot: f32 = query
+                        .iter()
+                        .zip(codes)
+                        .map(|(q, &c)| q * f32::from(c))
+                        .sum();
+                    let sum: f32 = query.iter().sum();
+                    1.0 - (min * sum + scale * dot)
+                }
+            },
+        }
+    }
+
+    /// Bytes the vector takes
+    #[must_use]
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            StoredVector::Full(vector) => vector.len() * std::mem::size_of::<f32>(),
+            StoredVector::Scalar { codes, .. } => codes.len() + 2 * std::mem::size_of::<f32>(),
+        }
+    }
+}
+
 // ============================================================================
 // HNSW Configuration
 // ============================================================================
@@ -184,6 +288,8 @@ pub struct HNSWConfig {
     pub max_level: usize,
     /// Level generation multiplier (1/ln(M))
     pub level_mult: f64,
+    /// How node vectors are stored
+    pub quantization: Quantization,
 }
 
 impl Default for HNSWConfig {
@@ -196,6 +302,7 @@ impl Default for HNSWConfig {
             ef_search: 50,
             max_level: 16,
             level_mult: 1.0 / (m as f64).ln(),
+            quantization: Quantization::None,
         }
     }
 }
@@ -210,6 +317,7 @@ impl HNSWConfig {
             ef_search: 200,
             max_level: 20,
             level_mult: 1.0 / 32.0_f64.ln(),
+            quantization: Quantization::None,
         }
     }
     
@@ -222,8 +330,16 @@ impl HNSWConfig {
             ef_search: 32,
             max_level: 12,
             level_mult: 1.0 / 12.0_f64.ln(),
+            quantization: Quantization::None,
         }
     }
+
+    /// Store node vectors as `quantization` says
+    #[must_use]
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
 }
 
 // ============================================================================
@@ -233,14 +349,15 @@ impl HNSWConfig {
 /// A node in the HNSW graph
 pub struct HNSWNode {
     pub id: u64,
-    pub vector: Vec<f32>,
+    pub vector: StoredVector,
     pub level: usize,
     /// Neighbors at each level: level -> [neighbor_ids]
     pub neighbors: Vec<RwLock<Vec<u64>>>,
 }
 
 impl HNSWNode {
-    pub fn new(id: u64, vector: Vec<f32>, level: usize) -> Self {
+    #[must_use]
+    pub fn new(id: u64, vector: StoredVector, level: usize) -> Self {
         let neighbors = (0..=level)
             .map(|_| RwLock::new(Vec::with_capacity(32)))
             .collect();
@@ -341,7 +458,8 @@ impl HNSWIndex {
         assert_eq!(vector.len(), self.dim);
         
         let level = self.random_level();
-        let node = Arc::new(HNSWNode::new(id, vector, level));
+        let encoded = StoredVector::encode(vector.clone(), self.config.quantization);
+        let node = Arc::new(HNSWNode::new(id, encoded, level));
         self.nodes.insert(id, node.clone());
         
         let entry = *self.entry_point.read();
@@ -359,7 +477,7 @@ impl HNSWIndex {
         // Navigate from top to insertion level
         let mut current = entry_id;
         for lv in (level + 1..=current_max).rev() {
-            let result = self.search_layer(&node.vector, current, 1, lv);
+            let result = self.search_layer(&vector, current, 1, lv);
             if !result.is_empty() {
                 current = result[0].0;
             }
@@ -367,10 +485,10 @@ impl HNSWIndex {
         
         // Insert at each layer from 0 to min(level, current_max)
         for lv in (0..=level.min(current_max)).rev() {
-            let candidates = self.search_layer(&node.vector, current, self.config.ef_construction, lv);
+            let candidates = self.search_layer(&vector, current, self.config.ef_construction, lv);
             
             let max_conn = if lv == 0 { self.config.m0 } else { self.config.m };
-            let neighbors = self.select_neighbors(&node.vector, &candidates, max_conn);
+            let neighbors = self.select_neighbors(&vector, &candidates, max_conn);
             
             // Set this node's neighbors
             node.set_neighbors(lv, neighbors.iter().map(|(id, _)| *id).collect());
@@ -383,14 +501,15 @@ impl HNSWIndex {
                     
                     // Prune if too many
                     if n_neighbors.len() > max_conn {
+                        let neighbor_vector = neighbor.vector.to_vec();
                         let scored: Vec<_> = n_neighbors.iter()
                             .filter_map(|&nid| {
                                 self.nodes.get(&nid).map(|n| {
-                                    (nid, self.metric.distance(&neighbor.vector, &n.vector))
+                                    (nid, n.vector.distance(self.metric, &neighbor_vector))
                                 })
                             })
                             .collect();
-                        let pruned = self.select_neighbors(&neighbor.vector, &scored, max_conn);
+                        let pruned = self.select_neighbors(&neighbor_vector, &scored, max_conn);
                         neighbor.set_neighbors(lv, pruned.iter().map(|(id, _)| *id).collect());
                     } else {
                         neighbor.set_neighbors(lv, n_neighbors);
@@ -446,7 +565,7 @@ impl HNSWIndex {
         let mut results: BinaryHeap<(OrderedFloat, u64)> = BinaryHeap::new();
         
         if let Some(entry_node) = self.nodes.get(&entry) {
-            let dist = self.metric.distance(query, &entry_node.vector);
+            let dist = entry_node.vector.distance(self.metric, query);
             candidates.push(Reverse((OrderedFloat(dist), entry)));
             results.push((OrderedFloat(dist), entry));
             visited.insert(entry);
@@ -463,7 +582,7 @@ impl HNSWIndex {
                 for neighbor_id in node.get_neighbors(level) {
                     if visited.insert(neighbor_id) {
                         if let Some(neighbor) = self.nodes.get(&neighbor_id) {
-                            let dist = self.metric.distance(query, &neighbor.vector);
+                            let dist = neighbor.vector.distance(self.metric, query);
                             
                             if results.len() < ef || dist < worst {
                                 candidates.push(Reverse((OrderedFloat(dist), neighbor_id)));
@@ -493,6 +612,14 @@ impl HNSWIndex {
     pub fn len(&self) -> usize {
         self.node_count.load(Ordering::Relaxed) as usize
     }
+
+    /// Bytes the node vectors take, as stored
+    pub fn vector_bytes(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| node.vector.memory_bytes())
+            .sum()
+    }
     
     pub fn is_empty(&self) -> bool {
         self.len() == 0
@@ -751,4 +878,37 @@ mod tests {
         let results = engine.batch_search(&queries, 10);
         assert_eq!(results.len(), 10);
     }
+
+    #[test]
+    fn test_scalar_quantization() {
+        let config = HNSWConfig::default().with_quantization(Quantization::Scalar);
+        let quantized = HNSWIndex::new(32, config, DistanceMetric::L2);
+        let full = HNSWIndex::new(32, HNSWConfig::default(), DistanceMetric::L2);
+
+        let vectors: Vec<Vec<f32>> = (0..500)
+            .map(|_| (0..32).map(|_| rand::random::<f32>()).collect())
+            .collect();
+        for (id, vector) in vectors.iter().enumerate() {
+            quantized.insert(id as u64, vector.clone());
+            full.insert(id as u64, vector.clone());
+        }
+
+        // A byte per dimension plus the range, against four bytes
+        assert_eq!(quantized.vector_bytes(), 500 * (32 + 8));
+        assert_eq!(full.vector_bytes(), 500 * 32 * 4);
+
+        // Every vector still finds itself
+        let found = vectors
+            .iter()
+            .enumerate()
+            .filter(|(id, vector)| quantized.search(vector, 1)[0].0 == *id as u64)
+            .count();
+        assert!(found >= 490, "only {found} of 500 vectors found themselves");
+
+        let stored = StoredVector::encode(vectors[0].clone(), Quantization::Scalar);
+        for metric in [DistanceMetric::L2, DistanceMetric::InnerProduct] {
+            let exact = metric.distance(&vectors[1], &vectors[0]);
+            assert!((stored.distance(metric, &vectors[1]) - exact).abs() < 0.05);
+        }
+    }
 }
@@ -0,0 +1,204 @@
+//! Percolator transactions over an in-process multi-shard cluster
+//!
+//! Tests commit atomicity, snapshot reads, write conflicts and lock
+//! resolution after a coordinator crashes midway through a commit.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use luma_core::shard::ShardManager;
+use luma_core::txn::{TimestampOracle, TransactionCoordinator};
+use luma_core::LumaError;
+
+const TTL_MS: u64 = 1_000;
+
+/// Shards plus a manually driven clock shared by every coordinator
+struct Cluster {
+    shards: Arc<ShardManager>,
+    wall: Arc<AtomicU64>,
+    tso: Arc<TimestampOracle>,
+}
+
+impl Cluster {
+    fn new(num_shards: usize) -> Self {
+        let wall = Arc::new(AtomicU64::new(1_000));
+        let clock = wall.clone();
+        Self {
+            shards: Arc::new(ShardManager::with_shards(num_shards)),
+            tso: Arc::new(TimestampOracle::with_clock(Arc::new(move || {
+                clock.load(Ordering::SeqCst)
+            }))),
+            wall,
+        }
+    }
+
+    /// A fresh coordinator, as if started on another node
+    fn coordinator(&self) -> TransactionCoordinator {
+        TransactionCoordinator::with_oracle(self.shards.clone(), self.tso.clone())
+            .with_lock_ttl(TTL_MS)
+    }
+
+    fn advance(&self, ms: u64) {
+        self.wall.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+fn keys(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("account:{i}")).collect()
+}
+
+#[tokio::test]
+async fn test_commit_spans_shards_atomically() {
+    let cluster = Cluster::new(4);
+    let coordinator = cluster.coordinator();
+    let keys = keys(16);
+
+    let shards: HashSet<_> = keys
+        .iter()
+        .map(|k| cluster.shards.shard_id(k.as_bytes()))
+        .collect();
+    assert!(shards.len() > 1);
+
+    let before = coordinator.begin();
+    let mut txn = coordinator.begin();
+    for key in &keys {
+        txn.put(key.as_bytes(), b"100".to_vec());
+    }
+    let commit_ts = coordinator.commit(txn).await.unwrap();
+    assert!(commit_ts > before.start_ts());
+
+    let after = coordinator.begin();
+    for key in &keys {
+        assert_eq!(
+            coordinator.get(&before, key.as_bytes()).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            coordinator.get(&after, key.as_bytes()).await.unwrap(),
+            Some(b"100".to_vec())
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_reads_and_deletes() {
+    let cluster = Cluster::new(2);
+    let coordinator = cluster.coordinator();
+
+    let mut txn = coordinator.begin();
+    txn.put("k", "v1");
+    coordinator.commit(txn).await.unwrap();
+
+    let reader = coordinator.begin();
+
+    let mut txn = coordinator.begin();
+    txn.delete("k");
+    assert_eq!(coordinator.get(&txn, b"k").await.unwrap(), None);
+    coordinator.commit(txn).await.unwrap();
+
+    assert_eq!(
+        coordinator.get(&reader, b"k").await.unwrap(),
+        Some(b"v1".to_vec())
+    );
+    assert_eq!(
+        coordinator.get(&coordinator.begin(), b"k").await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_first_committer_wins() {
+    let cluster = Cluster::new(2);
+    let coordinator = cluster.coordinator();
+
+    let mut first = coordinator.begin();
+    let mut second = coordinator.begin();
+    first.put("k", "first");
+    second.put("k", "second");
+    second.put("other", "second");
+
+    coordinator.commit(first).await.unwrap();
+    let err = coordinator.commit(second).await.unwrap_err();
+    assert!(matches!(err, LumaError::WriteConflict(_)), "{err:?}");
+
+    let reader = coordinator.begin();
+    assert_eq!(
+        coordinator.get(&reader, b"k").await.unwrap(),
+        Some(b"first".to_vec())
+    );
+    assert_eq!(coordinator.get(&reader, b"other").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_crash_after_prewrite_rolls_back_once_locks_expire() {
+    let cluster = Cluster::new(4);
+    let keys = keys(8);
+
+    let crashed = cluster.coordinator();
+    let mut txn = crashed.begin();
+    for key in &keys {
+        txn.put(key.as_bytes(), b"lost".to_vec());
+    }
+    crashed.prewrite(&txn).await.unwrap();
+
+    let survivor = cluster.coordinator();
+    let reader = survivor.begin();
+    let err = survivor.get(&reader, keys[3].as_bytes()).await.unwrap_err();
+    assert!(matches!(err, LumaError::KeyLocked(_)), "{err:?}");
+    assert!(err.is_retryable());
+    assert_eq!(survivor.resolver().resolve_orphans().await.unwrap(), 0);
+
+    cluster.advance(TTL_MS);
+    assert!(survivor.resolver().resolve_orphans().await.unwrap() > 0);
+    assert_eq!(survivor.resolver().resolve_orphans().await.unwrap(), 0);
+    for key in &keys {
+        assert_eq!(survivor.get(&reader, key.as_bytes()).await.unwrap(), None);
+    }
+
+    // The original coordinator can no longer reach its commit point
+    let err = crashed.commit_primary(&txn).await.unwrap_err();
+    assert!(matches!(err, LumaError::TransactionAborted(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn test_crash_after_primary_commit_rolls_forward() {
+    let cluster = Cluster::new(4);
+    let keys = keys(8);
+
+    let crashed = cluster.coordinator();
+    let mut txn = crashed.begin();
+    for key in &keys {
+        txn.put(key.as_bytes(), b"kept".to_vec());
+    }
+    crashed.prewrite(&txn).await.unwrap();
+    crashed.commit_primary(&txn).await.unwrap();
+
+    // Readers roll secondaries forward without waiting for the TTL
+    let survivor = cluster.coordinator();
+    let reader = survivor.begin();
+    let secondary = keys
+        .iter()
+        .find(|k| k.as_bytes() != txn.primary().unwrap())
+        .unwrap();
+    assert_eq!(
+        survivor.get(&reader, secondary.as_bytes()).await.unwrap(),
+        Some(b"kept".to_vec())
+    );
+
+    assert_eq!(
+        survivor.resolver().resolve_orphans().await.unwrap(),
+        keys.len() - 2
+    );
+    for key in &keys {
+        assert_eq!(
+            survivor.get(&reader, key.as_bytes()).await.unwrap(),
+            Some(b"kept".to_vec())
+        );
+    }
+
+    // A later writer is not blocked by leftover locks
+    let mut txn = survivor.begin();
+    txn.put(secondary.as_bytes(), b"next".to_vec());
+    survivor.commit(txn).await.unwrap();
+}